@@ -1,22 +1,47 @@
-use axum::{extract::State, routing::{get, post}, Json, Router};
+use axum::{extract::{MatchedPath, Query, Request, State}, http::{header, StatusCode}, middleware::{self, Next}, response::{IntoResponse, Response}, routing::{get, post}, Json, Router};
 use serde::{Deserialize, Serialize};
-use std::{net::SocketAddr, sync::Arc};
+use std::{collections::BTreeMap, fmt::Write, net::SocketAddr, sync::{Arc, Mutex}};
 use tracing::{info, error};
 use chromiumoxide::browser::{Browser, BrowserConfig};
-use chromiumoxide::cdp::browser_protocol::page::NavigateParams;
+use base64::Engine as _;
+use chromiumoxide::cdp::browser_protocol::page::{CaptureScreenshotFormat, NavigateParams};
+use chromiumoxide::page::ScreenshotParams;
 use chromiumoxide::Page;
 
 #[derive(Clone)]
 struct AppState {
     browser: Arc<Browser>,
     page: Arc<Page>,
+    metrics: Arc<Metrics>,
+}
+
+/// Request counters keyed by (route, status), rendered for Prometheus at /metrics
+#[derive(Default)]
+struct Metrics {
+    requests: Mutex<BTreeMap<(String, u16), u64>>,
+}
+
+impl Metrics {
+    fn record(&self, route: &str, status: u16) {
+        *self.requests.lock().unwrap().entry((route.to_string(), status)).or_insert(0) += 1;
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# HELP titan_agent_http_requests_total HTTP requests handled by the agent");
+        let _ = writeln!(out, "# TYPE titan_agent_http_requests_total counter");
+        for ((route, status), count) in self.requests.lock().unwrap().iter() {
+            let _ = writeln!(out, "titan_agent_http_requests_total{{route=\"{}\",status=\"{}\"}} {}", route, status, count);
+        }
+        out
+    }
 }
 
 #[derive(Serialize)]
 struct Health { status: &'static str }
 
 #[derive(Deserialize)]
-struct NavigateReq { url: String }
+struct NavigateReq { url: String, #[serde(default)] wait_for_load: bool }
 
 #[derive(Deserialize)]
 struct ClickReq { selector: String }
@@ -24,6 +49,43 @@ struct ClickReq { selector: String }
 #[derive(Deserialize)]
 struct ExtractReq { selector: String, attribute: Option<String> }
 
+#[derive(Deserialize)]
+struct FillReq { selector: String, value: String }
+
+#[derive(Deserialize)]
+struct SubmitReq { selector: String }
+
+#[derive(Deserialize)]
+struct WaitReq { selector: String, timeout_ms: Option<u64> }
+
+/// How long `/wait` polls when the request doesn't say
+const DEFAULT_WAIT_TIMEOUT_MS: u64 = 5_000;
+
+/// Delay between `/wait` polls
+const WAIT_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Query parameters for `GET /screenshot`
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ScreenshotReq {
+    /// `png` (default) or `jpeg`
+    format: Option<String>,
+    /// JPEG quality, 0-100
+    quality: Option<u8>,
+    /// Capture the whole scrollable page instead of the viewport
+    full_page: bool,
+    /// Return the image bytes directly instead of base64 in JSON
+    raw: bool,
+}
+
+/// Outcome of a page action; `error` explains why `ok` is false
+#[derive(Serialize, Debug, PartialEq)]
+struct ActionResult {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt()
@@ -39,7 +101,7 @@ async fn main() -> anyhow::Result<()> {
     tokio::spawn(async move { while let Some(evt) = handler.next().await { if let Err(e) = evt { error!(?e, "browser event error"); } } });
 
     let page = browser.new_page("about:blank").await?;
-    let state = AppState { browser: Arc::new(browser), page: Arc::new(page) };
+    let state = AppState { browser: Arc::new(browser), page: Arc::new(page), metrics: Arc::new(Metrics::default()) };
 
     let app = Router::new()
         .route("/health", get(health))
@@ -47,6 +109,12 @@ async fn main() -> anyhow::Result<()> {
         .route("/click", post(click))
         .route("/content", get(content))
         .route("/extract", post(extract))
+        .route("/fill", post(fill))
+        .route("/submit", post(submit))
+        .route("/screenshot", get(screenshot))
+        .route("/wait", post(wait))
+        .route("/metrics", get(metrics))
+        .route_layer(middleware::from_fn_with_state(state.clone(), track_requests))
         .with_state(state);
 
     let addr: SocketAddr = "127.0.0.1:9224".parse()?;
@@ -57,13 +125,63 @@ async fn main() -> anyhow::Result<()> {
 
 async fn health() -> Json<Health> { Json(Health { status: "ok" }) }
 
-async fn navigate(State(state): State<AppState>, Json(req): Json<NavigateReq>) -> Json<serde_json::Value> {
-    let _ = state.page.navigate(NavigateParams::builder().url(req.url.clone()).build()).await;
-    Json(serde_json::json!({"ok": true}))
+async fn track_requests(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let route = req.extensions().get::<MatchedPath>().map(|p| p.as_str().to_string()).unwrap_or_else(|| "unmatched".to_string());
+    let res = next.run(req).await;
+    state.metrics.record(&route, res.status().as_u16());
+    res
+}
+
+async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], state.metrics.render())
+}
+
+async fn navigate(State(state): State<AppState>, Json(req): Json<NavigateReq>) -> Json<ActionResult> {
+    let params = match NavigateParams::builder().url(req.url).build() {
+        Ok(params) => params,
+        Err(e) => return Json(ActionResult { ok: false, error: Some(e) }),
+    };
+    if let Err(e) = state.page.execute(params).await {
+        return Json(ActionResult { ok: false, error: Some(e.to_string()) });
+    }
+
+    // Optionally hold the response until the load event so follow-up calls see the new page
+    if req.wait_for_load {
+        if let Err(e) = state.page.wait_for_navigation().await {
+            return Json(ActionResult { ok: false, error: Some(e.to_string()) });
+        }
+    }
+    Json(ActionResult { ok: true, error: None })
+}
+
+/// Poll until `selector` matches an element or the timeout passes
+async fn wait(State(state): State<AppState>, Json(req): Json<WaitReq>) -> Json<serde_json::Value> {
+    let timeout = std::time::Duration::from_millis(req.timeout_ms.unwrap_or(DEFAULT_WAIT_TIMEOUT_MS));
+    let found = tokio::time::timeout(timeout, async {
+        while state.page.find_element(req.selector.as_str()).await.is_err() {
+            tokio::time::sleep(WAIT_POLL_INTERVAL).await;
+        }
+    }).await.is_ok();
+    Json(serde_json::json!({"found": found}))
+}
+
+/// Encode a string as a JavaScript string literal; JSON string syntax is valid JS
+fn js_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_else(|_| "\"\"".to_string())
+}
+
+/// Run a script that returns 'ok' on success or a short error code otherwise
+async fn run_action(page: &Page, js: String) -> ActionResult {
+    match page.evaluate(js).await.map(|v| v.into_value::<String>()) {
+        Ok(Ok(result)) if result == "ok" => ActionResult { ok: true, error: None },
+        Ok(Ok(result)) => ActionResult { ok: false, error: Some(result) },
+        Ok(Err(e)) => ActionResult { ok: false, error: Some(e.to_string()) },
+        Err(e) => ActionResult { ok: false, error: Some(e.to_string()) },
+    }
 }
 
 async fn click(State(state): State<AppState>, Json(req): Json<ClickReq>) -> Json<serde_json::Value> {
-    let js = format!("(() => {{ const el = document.querySelector('{}'); if (el) {{ el.click(); return 'ok'; }} return 'not_found'; }})()", req.selector.replace("'", "\\'"));
+    let js = format!("(() => {{ const el = document.querySelector({}); if (el) {{ el.click(); return 'ok'; }} return 'not_found'; }})()", js_string(&req.selector));
     let _ = state.page.evaluate(js).await;
     Json(serde_json::json!({"ok": true}))
 }
@@ -75,7 +193,194 @@ async fn content(State(state): State<AppState>) -> Json<serde_json::Value> {
 
 async fn extract(State(state): State<AppState>, Json(req): Json<ExtractReq>) -> Json<serde_json::Value> {
     let attr = req.attribute.unwrap_or_else(|| "textContent".to_string());
-    let js = format!("(() => {{ const el = document.querySelector('{}'); if (!el) return ''; const v = el['{}']; return (v || '').toString(); }})()", req.selector.replace("'", "\\'"), attr);
+    let js = format!("(() => {{ const el = document.querySelector({}); if (!el) return ''; const v = el[{}]; return (v || '').toString(); }})()", js_string(&req.selector), js_string(&attr));
     let val = state.page.evaluate(js).await.ok().and_then(|v| v.into_value()).unwrap_or(serde_json::Value::Null);
     Json(serde_json::json!({"value": val}))
 }
+
+async fn fill(State(state): State<AppState>, Json(req): Json<FillReq>) -> Json<ActionResult> {
+    let js = format!(
+        "(() => {{ const el = document.querySelector({}); if (!el) return 'not_found'; if (!('value' in el)) return 'not_an_input'; el.value = {}; el.dispatchEvent(new Event('input', {{ bubbles: true }})); return 'ok'; }})()",
+        js_string(&req.selector),
+        js_string(&req.value),
+    );
+    Json(run_action(&state.page, js).await)
+}
+
+async fn submit(State(state): State<AppState>, Json(req): Json<SubmitReq>) -> Json<ActionResult> {
+    // The selector may name the form itself or any control inside it
+    let js = format!(
+        "(() => {{ const el = document.querySelector({}); if (!el) return 'not_found'; const form = el instanceof HTMLFormElement ? el : el.closest('form'); if (!form) return 'not_a_form'; if (form.requestSubmit) form.requestSubmit(); else form.submit(); return 'ok'; }})()",
+        js_string(&req.selector),
+    );
+    Json(run_action(&state.page, js).await)
+}
+
+async fn screenshot(State(state): State<AppState>, Query(req): Query<ScreenshotReq>) -> Response {
+    let (format, content_type) = match req.format.as_deref().unwrap_or("png") {
+        "png" => (CaptureScreenshotFormat::Png, "image/png"),
+        "jpeg" | "jpg" => (CaptureScreenshotFormat::Jpeg, "image/jpeg"),
+        other => {
+            let error = ActionResult { ok: false, error: Some(format!("unsupported format: {}", other)) };
+            return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+        }
+    };
+
+    let mut params = ScreenshotParams::builder().format(format).full_page(req.full_page);
+    if let Some(quality) = req.quality {
+        params = params.quality(quality.min(100) as i64);
+    }
+
+    // A capture taken mid-navigation can come back blank, so let any pending load finish first
+    if let Err(e) = state.page.wait_for_navigation().await {
+        error!(?e, "waiting for page load before screenshot");
+    }
+
+    match state.page.screenshot(params.build()).await {
+        Ok(bytes) if req.raw => ([(header::CONTENT_TYPE, content_type)], bytes).into_response(),
+        Ok(bytes) => Json(serde_json::json!({
+            "content_type": content_type,
+            "data": base64::engine::general_purpose::STANDARD.encode(bytes),
+        })).into_response(),
+        Err(e) => {
+            let error = ActionResult { ok: false, error: Some(e.to_string()) };
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const FORM_PAGE: &str = r#"<!doctype html>
+<html><body>
+<form id="signup">
+  <input id="name" name="name">
+  <button id="send" type="submit">Send</button>
+</form>
+<script>
+  window.inputEvents = 0;
+  document.getElementById('name').addEventListener('input', () => window.inputEvents++);
+  document.getElementById('signup').addEventListener('submit', (e) => {
+    e.preventDefault();
+    window.submitted = new FormData(e.target).get('name');
+  });
+  setTimeout(() => {
+    const late = document.createElement('div');
+    late.id = 'late';
+    document.body.appendChild(late);
+  }, 300);
+</script>
+</body></html>"#;
+
+    /// Serve the form page to every connection
+    async fn serve_form_page() -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0u8; 4096];
+                let _ = socket.read(&mut buf).await;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    FORM_PAGE.len(),
+                    FORM_PAGE,
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn test_js_string_escapes_quotes_and_script_breakers() {
+        assert_eq!(js_string(r#"a'b"c\d"#), r#""a'b\"c\\d""#);
+        assert_eq!(js_string("line\nbreak"), r#""line\nbreak""#);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local Chromium"]
+    async fn test_fill_and_submit_form() {
+        let addr = serve_form_page().await;
+        let (browser, mut handler) = Browser::launch(BrowserConfig::builder().build().unwrap()).await.unwrap();
+        tokio::spawn(async move { while handler.next().await.is_some() {} });
+
+        let page = browser.new_page(format!("http://{}/", addr)).await.unwrap();
+        page.wait_for_navigation().await.unwrap();
+        let state = AppState { browser: Arc::new(browser), page: Arc::new(page), metrics: Arc::new(Metrics::default()) };
+
+        // Quotes and backslashes must reach the page verbatim rather than breaking the script
+        let value = r#"O'Brien "the \ quoted""#;
+        let Json(filled) = fill(State(state.clone()), Json(FillReq { selector: "#name".to_string(), value: value.to_string() })).await;
+        assert_eq!(filled, ActionResult { ok: true, error: None });
+
+        let input_events: u32 = state.page.evaluate("window.inputEvents").await.unwrap().into_value().unwrap();
+        assert_eq!(input_events, 1);
+
+        // Submitting through a control inside the form submits the form
+        let Json(submitted) = submit(State(state.clone()), Json(SubmitReq { selector: "#send".to_string() })).await;
+        assert_eq!(submitted, ActionResult { ok: true, error: None });
+        let submitted: String = state.page.evaluate("window.submitted").await.unwrap().into_value().unwrap();
+        assert_eq!(submitted, value);
+
+        let Json(missing) = fill(State(state.clone()), Json(FillReq { selector: "#missing".to_string(), value: String::new() })).await;
+        assert_eq!(missing, ActionResult { ok: false, error: Some("not_found".to_string()) });
+
+        let Json(not_form) = submit(State(state), Json(SubmitReq { selector: "body".to_string() })).await;
+        assert_eq!(not_form, ActionResult { ok: false, error: Some("not_a_form".to_string()) });
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local Chromium"]
+    async fn test_screenshot_about_blank() {
+        let (browser, mut handler) = Browser::launch(BrowserConfig::builder().build().unwrap()).await.unwrap();
+        tokio::spawn(async move { while handler.next().await.is_some() {} });
+
+        let page = browser.new_page("about:blank").await.unwrap();
+        let state = AppState { browser: Arc::new(browser), page: Arc::new(page), metrics: Arc::new(Metrics::default()) };
+
+        let response = screenshot(State(state.clone()), Query(ScreenshotReq { raw: true, ..Default::default() })).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(bytes.starts_with(b"\x89PNG"));
+
+        let response = screenshot(State(state.clone()), Query(ScreenshotReq { format: Some("jpeg".to_string()), quality: Some(50), ..Default::default() })).await;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["content_type"], "image/jpeg");
+        let data = base64::engine::general_purpose::STANDARD.decode(json["data"].as_str().unwrap()).unwrap();
+        assert!(!data.is_empty());
+
+        let response = screenshot(State(state), Query(ScreenshotReq { format: Some("gif".to_string()), ..Default::default() })).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local Chromium"]
+    async fn test_navigate_and_wait_for_selector() {
+        let addr = serve_form_page().await;
+        let (browser, mut handler) = Browser::launch(BrowserConfig::builder().build().unwrap()).await.unwrap();
+        tokio::spawn(async move { while handler.next().await.is_some() {} });
+
+        let page = browser.new_page("about:blank").await.unwrap();
+        let state = AppState { browser: Arc::new(browser), page: Arc::new(page), metrics: Arc::new(Metrics::default()) };
+
+        let Json(navigated) = navigate(State(state.clone()), Json(NavigateReq { url: format!("http://{}/", addr), wait_for_load: true })).await;
+        assert_eq!(navigated, ActionResult { ok: true, error: None });
+
+        // Present once loaded, so no polling is needed
+        let name: String = state.page.evaluate("document.getElementById('name') ? 'present' : 'missing'").await.unwrap().into_value().unwrap();
+        assert_eq!(name, "present");
+
+        // Added by a timer after load
+        let Json(late) = wait(State(state.clone()), Json(WaitReq { selector: "#late".to_string(), timeout_ms: Some(5_000) })).await;
+        assert_eq!(late["found"], true);
+
+        let Json(missing) = wait(State(state), Json(WaitReq { selector: "#never".to_string(), timeout_ms: Some(300) })).await;
+        assert_eq!(missing["found"], false);
+    }
+}
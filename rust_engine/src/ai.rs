@@ -1,12 +1,13 @@
 //! AI engine for intelligent web browsing features
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use serde::{Serialize, Deserialize};
 use crate::core::{ElementId, Result, EngineError};
-use crate::html::Document;
-use crate::networking::NetworkResponse;
+use crate::html::{Document, Element};
+use crate::networking::{NetworkResponse, NetworkStack, RequestOptions};
+use crate::storage::StorageEngine;
 
 /// AI engine for intelligent browsing features
 pub struct AIEngine {
@@ -18,39 +19,78 @@ pub struct AIEngine {
     content_analyzer: ContentAnalyzer,
     interaction_predictor: InteractionPredictor,
     
-    /// Configuration
-    config: AIConfig,
+    /// Translation provider used by `translate_content`
+    translation_backend: RwLock<Option<Arc<dyn TranslationBackend>>>,
+    
+    /// Model behind sentiment, topics, and summaries
+    inference: Arc<dyn InferenceBackend>,
+    
+    /// Document frequencies over pages analyzed this session, for keyword ranking
+    keyword_corpus: RwLock<KeywordCorpus>,
+    
+    /// Configuration, read afresh by each operation so updates apply to the shared engine
+    config: RwLock<AIConfig>,
 }
 
 impl AIEngine {
-    /// Create a new AI engine
+    /// Create a new AI engine using the built-in heuristics for inference
     pub async fn new() -> Result<Self> {
+        Self::with_backend(Arc::new(HeuristicBackend::default())).await
+    }
+    
+    /// Create an AI engine whose sentiment, topics, and summaries come from `inference`
+    pub async fn with_backend(inference: Arc<dyn InferenceBackend>) -> Result<Self> {
         Ok(Self {
             analysis_cache: Arc::new(RwLock::new(HashMap::new())),
             text_processor: TextProcessor::new(),
             content_analyzer: ContentAnalyzer::new(),
             interaction_predictor: InteractionPredictor::new(),
-            config: AIConfig::default(),
+            translation_backend: RwLock::new(None),
+            inference,
+            keyword_corpus: RwLock::new(KeywordCorpus::default()),
+            config: RwLock::new(AIConfig::default()),
         })
     }
     
     /// Analyze a web page for AI insights
     pub async fn analyze_page(&self, document: &Document, response: &NetworkResponse) -> Result<PageContext> {
-        // Check cache first
+        let text_blocks = extract_text_blocks(document);
+        
+        // Check cache first; a reload with different content is analyzed afresh
         {
             let cache = self.analysis_cache.read().await;
             if let Some(cached_context) = cache.get(&document.url) {
-                if !cached_context.is_expired() {
+                let ttl = analysis_ttl(&*self.config.read().await, response);
+                if !cached_context.is_expired(ttl) && cached_context.text_blocks == text_blocks {
                     return Ok(cached_context.clone());
                 }
             }
         }
         
         let mut context = PageContext::new(document.url.clone());
+        context.text_blocks = text_blocks;
+        context.structure = PageStructure {
+            form_count: document.get_elements_by_tag_name("form").len(),
+            link_count: document.get_elements_by_tag_name("a").len(),
+            image_count: document.get_elements_by_tag_name("img").len(),
+            heading_count: ["h1", "h2", "h3", "h4", "h5", "h6"].iter()
+                .map(|tag| document.get_elements_by_tag_name(tag).len())
+                .sum(),
+        };
         
         // Extract and analyze text content
         let text_content = self.extract_text_content(document).await?;
-        let text_analysis = self.text_processor.analyze(&text_content).await?;
+        let mut text_analysis = self.text_processor.analyze(&text_content).await?;
+        text_analysis.sentiment_score = self.inference.sentiment(&text_content).await?.clamp(-1.0, 1.0);
+        text_analysis.topics = self.inference.classify(&text_content, &TOPIC_LABELS).await?
+            .into_iter()
+            .filter(|(_, score)| *score >= TOPIC_THRESHOLD)
+            .map(|(label, _)| label)
+            .collect();
+        {
+            let mut corpus = self.keyword_corpus.write().await;
+            text_analysis.keywords = corpus.add_document(&text_content);
+        }
         context.text_analysis = Some(text_analysis);
         
         // Analyze page structure and content
@@ -82,6 +122,83 @@ impl AIEngine {
         Ok(context)
     }
     
+    /// Compare two analyses of a page, e.g. successive loads of a watched URL.
+    /// Blocks are matched by the hash of their normalized text, so a block that only
+    /// changed position is reported as moved rather than removed and re-added.
+    pub fn diff_pages(old: &PageContext, new: &PageContext) -> PageDiff {
+        let old_hashes: Vec<u64> = old.text_blocks.iter().map(|block| hash_block(block)).collect();
+        let new_hashes: Vec<u64> = new.text_blocks.iter().map(|block| hash_block(block)).collect();
+        
+        // Runs of unmatched blocks between the anchors of the longest common subsequence
+        let mut gaps: Vec<(Vec<usize>, Vec<usize>)> = Vec::new();
+        let (mut removed, mut added) = (Vec::new(), Vec::new());
+        let (mut i, mut j) = (0, 0);
+        for (anchor_old, anchor_new) in longest_common_subsequence(&old_hashes, &new_hashes)
+            .into_iter()
+            .chain(std::iter::once((old_hashes.len(), new_hashes.len())))
+        {
+            removed.extend(i..anchor_old);
+            added.extend(j..anchor_new);
+            if !removed.is_empty() || !added.is_empty() {
+                gaps.push((std::mem::take(&mut removed), std::mem::take(&mut added)));
+            }
+            i = anchor_old + 1;
+            j = anchor_new + 1;
+        }
+        
+        // Pair up identical blocks across gaps as moves
+        let mut moved_old = std::collections::HashSet::new();
+        let mut moved_new = std::collections::HashSet::new();
+        let mut block_changes = Vec::new();
+        for old_index in gaps.iter().flat_map(|(removed, _)| removed.iter().copied()) {
+            let target = gaps.iter()
+                .flat_map(|(_, added)| added.iter().copied())
+                .find(|&new_index| !moved_new.contains(&new_index) && new_hashes[new_index] == old_hashes[old_index]);
+            if let Some(new_index) = target {
+                moved_old.insert(old_index);
+                moved_new.insert(new_index);
+                block_changes.push(BlockChange::Moved { old_index, new_index, text: new.text_blocks[new_index].clone() });
+            }
+        }
+        
+        // What is left in a gap was edited in place, inserted, or deleted
+        for (removed, added) in &gaps {
+            let removed: Vec<usize> = removed.iter().copied().filter(|index| !moved_old.contains(index)).collect();
+            let added: Vec<usize> = added.iter().copied().filter(|index| !moved_new.contains(index)).collect();
+            for k in 0..removed.len().max(added.len()) {
+                block_changes.push(match (removed.get(k), added.get(k)) {
+                    (Some(&old_index), Some(&new_index)) => BlockChange::Changed {
+                        old_index,
+                        new_index,
+                        old_text: old.text_blocks[old_index].clone(),
+                        new_text: new.text_blocks[new_index].clone(),
+                    },
+                    (Some(&index), None) => BlockChange::Removed { index, text: old.text_blocks[index].clone() },
+                    (None, Some(&index)) => BlockChange::Added { index, text: new.text_blocks[index].clone() },
+                    (None, None) => unreachable!(),
+                });
+            }
+        }
+        
+        let counts = |structure: &PageStructure| [
+            ("form", structure.form_count),
+            ("a", structure.link_count),
+            ("img", structure.image_count),
+            ("heading", structure.heading_count),
+        ];
+        let structural_changes = counts(&old.structure).into_iter()
+            .zip(counts(&new.structure))
+            .filter(|((_, old_count), (_, new_count))| old_count != new_count)
+            .map(|((element, old_count), (_, new_count))| StructuralChange {
+                element: element.to_string(),
+                old_count,
+                new_count,
+            })
+            .collect();
+        
+        PageDiff { block_changes, structural_changes }
+    }
+    
     /// Extract meaningful text content from document
     async fn extract_text_content(&self, document: &Document) -> Result<String> {
         let mut text_content = String::new();
@@ -90,30 +207,14 @@ impl AIEngine {
         text_content.push_str(&document.title);
         text_content.push('\n');
         
-        // Extract text from body elements
+        // Extract the visible body text, block by block
         if let Some(body) = &document.body {
-            self.extract_element_text(body, document, &mut text_content);
+            text_content.push_str(&visible_text(document, body));
         }
         
         Ok(text_content)
     }
     
-    /// Recursively extract text from elements
-    fn extract_element_text(&self, element: &crate::html::Element, document: &Document, text_content: &mut String) {
-        // Add element's text content
-        if !element.text_content.is_empty() {
-            text_content.push_str(&element.text_content);
-            text_content.push(' ');
-        }
-        
-        // Process children
-        for child_id in &element.children {
-            if let Some(child_element) = document.elements.get(child_id) {
-                self.extract_element_text(child_element, document, text_content);
-            }
-        }
-    }
-    
     /// Generate AI insights from analysis
     async fn generate_insights(&self, context: &PageContext) -> Result<Vec<AIInsight>> {
         let mut insights = Vec::new();
@@ -127,7 +228,11 @@ impl AIEngine {
                     description: "This page contains predominantly negative content".to_string(),
                     confidence: 0.8,
                     actionable: false,
-                    metadata: HashMap::new(),
+                    metadata: {
+                        let mut meta = HashMap::new();
+                        meta.insert("score".to_string(), format!("{:.2}", text_analysis.sentiment_score));
+                        meta
+                    },
                 });
             }
             
@@ -164,6 +269,27 @@ impl AIEngine {
                 });
             }
             
+            if !content_analysis.mixed_content_urls.is_empty() {
+                insights.push(AIInsight {
+                    insight_type: "security".to_string(),
+                    title: "Mixed Content Detected".to_string(),
+                    description: format!(
+                        "This HTTPS page loads {} resource(s) over insecure HTTP",
+                        content_analysis.mixed_content_urls.len()
+                    ),
+                    confidence: 0.95,
+                    actionable: true,
+                    metadata: {
+                        let mut meta = HashMap::new();
+                        meta.insert("risk".to_string(), "medium".to_string());
+                        meta.insert("count".to_string(), content_analysis.mixed_content_urls.len().to_string());
+                        meta.insert("resources".to_string(), content_analysis.mixed_content_urls.join("\n"));
+                        meta.insert("suggestion".to_string(), "Serve these resources over HTTPS".to_string());
+                        meta
+                    },
+                });
+            }
+            
             if content_analysis.ad_density > 0.3 {
                 insights.push(AIInsight {
                     insight_type: "user_experience".to_string(),
@@ -203,71 +329,104 @@ impl AIEngine {
         Ok(insights)
     }
     
-    /// Smart form filling suggestions
-    pub async fn suggest_form_fill(&self, form_data: &HashMap<String, String>) -> Result<HashMap<String, String>> {
+    /// Smart form filling suggestions for `form_data` (field name to input type).
+    /// Values come from the autofill profile in `storage` when `use_autofill_profile` is
+    /// enabled; placeholders are only offered when there is no profile to draw from.
+    pub async fn suggest_form_fill(&self, form_data: &HashMap<String, String>, storage: &StorageEngine) -> Result<HashMap<String, String>> {
         let mut suggestions = HashMap::new();
+        let config = self.config.read().await.clone();
+        if !config.enable_form_suggestions {
+            return Ok(suggestions);
+        }
+        
+        let profile = if config.use_autofill_profile {
+            storage.get_autofill_profile().await?
+        } else {
+            HashMap::new()
+        };
         
         for (field_name, field_type) in form_data {
+            // Credentials belong to the password manager, never to autofill
             let field_lower = field_name.to_lowercase();
-            
-            // Email field detection
-            if field_lower.contains("email") || field_lower.contains("e-mail") {
-                suggestions.insert(field_name.clone(), "user@example.com".to_string());
-            }
-            
-            // Name field detection
-            else if field_lower.contains("name") {
-                if field_lower.contains("first") {
-                    suggestions.insert(field_name.clone(), "John".to_string());
-                } else if field_lower.contains("last") {
-                    suggestions.insert(field_name.clone(), "Doe".to_string());
-                } else {
-                    suggestions.insert(field_name.clone(), "John Doe".to_string());
-                }
-            }
-            
-            // Phone field detection
-            else if field_lower.contains("phone") || field_lower.contains("tel") {
-                suggestions.insert(field_name.clone(), "+1-555-123-4567".to_string());
+            if field_type.eq_ignore_ascii_case("password") || field_lower.contains("password") || field_lower.contains("passwd") {
+                continue;
             }
             
-            // Address field detection
-            else if field_lower.contains("address") {
-                suggestions.insert(field_name.clone(), "123 Main St".to_string());
-            }
-            
-            // City field detection
-            else if field_lower.contains("city") {
-                suggestions.insert(field_name.clone(), "New York".to_string());
-            }
+            let Some(category) = autofill_category(&field_lower, &field_type.to_lowercase()) else {
+                continue;
+            };
             
-            // ZIP/Postal code detection
-            else if field_lower.contains("zip") || field_lower.contains("postal") {
-                suggestions.insert(field_name.clone(), "10001".to_string());
+            let value = if profile.is_empty() {
+                autofill_placeholder(category).map(str::to_string)
+            } else {
+                profile_value(&profile, category)
+            };
+            if let Some(value) = value {
+                suggestions.insert(field_name.clone(), value);
             }
         }
         
         Ok(suggestions)
     }
     
+    /// Extract the main article of a page for reader mode
+    pub async fn extract_article(&self, document: &Document) -> Result<ReaderArticle> {
+        self.content_analyzer.extract_article(document)
+    }
+    
     /// Content summarization
     pub async fn summarize_content(&self, content: &str, max_length: usize) -> Result<String> {
-        self.text_processor.summarize(content, max_length).await
+        self.inference.summarize(content, max_length).await
+    }
+    
+    /// Embedding of `text` from the inference backend, e.g. for similarity search
+    pub async fn embed_text(&self, text: &str) -> Result<Vec<f32>> {
+        self.inference.embed(text).await
     }
     
-    /// Language detection
+    /// Language detection, returning an ISO 639-1 code or "und" when undetermined
     pub async fn detect_language(&self, content: &str) -> Result<String> {
+        Ok(self.text_processor.detect_language(content).await?.language)
+    }
+    
+    /// Language detection with the confidence of the chosen language
+    pub async fn detect_language_with_confidence(&self, content: &str) -> Result<LanguageDetection> {
         self.text_processor.detect_language(content).await
     }
     
     /// Content translation
     pub async fn translate_content(&self, content: &str, target_language: &str) -> Result<String> {
-        self.text_processor.translate(content, target_language).await
+        if !self.config.read().await.enable_translation {
+            return Err(EngineError::AIError("Translation is disabled".to_string()));
+        }
+        
+        let backend = self.translation_backend.read().await.clone()
+            .ok_or_else(|| EngineError::AIError("No translation backend configured".to_string()))?;
+        
+        let source_language = self.text_processor.detect_language(content).await?.language;
+        
+        let chunks = chunk_paragraphs(content, backend.max_chunk_chars());
+        let mut translated = Vec::with_capacity(chunks.len());
+        for chunk in &chunks {
+            translated.push(backend.translate(&chunk.text, &source_language, target_language).await?);
+        }
+        
+        Ok(join_chunks(&chunks, translated))
+    }
+    
+    /// Register the backend used for content translation
+    pub async fn set_translation_backend(&self, backend: Arc<dyn TranslationBackend>) {
+        *self.translation_backend.write().await = Some(backend);
     }
     
     /// Update AI configuration
-    pub fn update_config(&mut self, config: AIConfig) {
-        self.config = config;
+    pub async fn update_config(&self, config: AIConfig) {
+        *self.config.write().await = config;
+    }
+    
+    /// The current AI configuration
+    pub async fn config(&self) -> AIConfig {
+        self.config.read().await.clone()
     }
     
     /// Shutdown AI engine
@@ -288,6 +447,52 @@ pub struct PageContext {
     pub content_analysis: Option<ContentAnalysis>,
     pub interaction_predictions: Vec<InteractionPrediction>,
     pub insights: Vec<AIInsight>,
+    /// Normalized text of each element, in document order, for diffing between loads
+    #[serde(default)]
+    pub text_blocks: Vec<String>,
+    #[serde(default)]
+    pub structure: PageStructure,
+}
+
+/// Element counts that describe a page's shape
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PageStructure {
+    pub form_count: usize,
+    pub link_count: usize,
+    pub image_count: usize,
+    pub heading_count: usize,
+}
+
+/// How one block of text differs between two loads of a page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BlockChange {
+    Added { index: usize, text: String },
+    Removed { index: usize, text: String },
+    /// Edited in place, e.g. a price that went from "$10" to "$12"
+    Changed { old_index: usize, new_index: usize, old_text: String, new_text: String },
+    /// Same text at a different position
+    Moved { old_index: usize, new_index: usize, text: String },
+}
+
+/// An element count that differs between two loads
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StructuralChange {
+    pub element: String,
+    pub old_count: usize,
+    pub new_count: usize,
+}
+
+/// Differences between two analyses of a page, from `AIEngine::diff_pages`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PageDiff {
+    pub block_changes: Vec<BlockChange>,
+    pub structural_changes: Vec<StructuralChange>,
+}
+
+impl PageDiff {
+    pub fn is_empty(&self) -> bool {
+        self.block_changes.is_empty() && self.structural_changes.is_empty()
+    }
 }
 
 impl PageContext {
@@ -299,13 +504,15 @@ impl PageContext {
             content_analysis: None,
             interaction_predictions: Vec::new(),
             insights: Vec::new(),
+            text_blocks: Vec::new(),
+            structure: PageStructure::default(),
         }
     }
     
-    fn is_expired(&self) -> bool {
-        let now = chrono::Utc::now();
-        let age = now.signed_duration_since(self.analyzed_at);
-        age.num_minutes() > 30 // Expire after 30 minutes
+    /// Whether the analysis is older than `ttl`
+    fn is_expired(&self, ttl: std::time::Duration) -> bool {
+        let age = chrono::Utc::now().signed_duration_since(self.analyzed_at);
+        age.to_std().is_ok_and(|age| age >= ttl)
     }
 }
 
@@ -320,10 +527,18 @@ pub struct TextAnalysis {
     pub reading_difficulty: f64, // 0.0 = easy, 1.0 = very difficult
     pub sentiment_score: f64, // -1.0 = very negative, 1.0 = very positive
     pub language: String,
+    pub language_confidence: f64, // 0.0 = undetermined, 1.0 = certain
     pub keywords: Vec<String>,
     pub topics: Vec<String>,
 }
 
+/// Detected language with its confidence
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LanguageDetection {
+    pub language: String, // ISO 639-1 code, or "und" when undetermined
+    pub confidence: f64,
+}
+
 /// Content analysis results
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContentAnalysis {
@@ -339,6 +554,31 @@ pub struct ContentAnalysis {
     pub mobile_friendly: bool,
     pub accessibility_score: f64, // 0.0 = poor, 1.0 = excellent
     pub performance_score: f64, // 0.0 = poor, 1.0 = excellent
+    pub schema_types: Vec<String>, // schema.org types declared via JSON-LD
+    pub mixed_content_urls: Vec<String>, // http:// subresources of an https:// page
+    #[serde(default)]
+    pub noindex: bool, // opted out of indexing by `<meta name="robots">` or `X-Robots-Tag`
+    #[serde(default)]
+    pub nofollow: bool, // asks crawlers not to follow its links, by the same signals
+    #[serde(default)]
+    pub canonical_url: Option<String>, // absolute URL from `<link rel="canonical">`
+}
+
+/// Main content of a page with navigation, ads, and sidebars stripped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReaderArticle {
+    pub title: String,
+    pub byline: Option<String>,
+    pub paragraphs: Vec<String>,
+    pub content_element: ElementId, // root of the region the paragraphs were taken from
+    pub word_count: usize,
+}
+
+impl ReaderArticle {
+    /// Paragraphs joined as plain text, suitable for summarization
+    pub fn text(&self) -> String {
+        self.paragraphs.join("\n\n")
+    }
 }
 
 /// Interaction prediction
@@ -362,6 +602,7 @@ pub struct AIInsight {
 }
 
 /// Text processing engine
+#[derive(Default)]
 struct TextProcessor;
 
 impl TextProcessor {
@@ -373,6 +614,7 @@ impl TextProcessor {
         let words: Vec<&str> = text.split_whitespace().collect();
         let sentences: Vec<&str> = text.split(&['.', '!', '?'][..]).collect();
         let paragraphs: Vec<&str> = text.split("\n\n").collect();
+        let language = self.detect_language_simple(text);
         
         Ok(TextAnalysis {
             word_count: words.len(),
@@ -382,7 +624,8 @@ impl TextProcessor {
             reading_time_minutes: words.len() as f64 / 200.0, // Average reading speed
             reading_difficulty: self.calculate_reading_difficulty(&words, &sentences),
             sentiment_score: self.analyze_sentiment(text),
-            language: self.detect_language_simple(text),
+            language: language.language,
+            language_confidence: language.confidence,
             keywords: self.extract_keywords(&words),
             topics: self.extract_topics(&words),
         })
@@ -447,231 +690,1354 @@ impl TextProcessor {
         (positive_count as f64 - negative_count as f64) / total as f64
     }
     
-    fn detect_language_simple(&self, text: &str) -> String {
-        let english_words = ["the", "and", "or", "but", "in", "on", "at", "to", "for", "of"];
-        let spanish_words = ["el", "la", "y", "o", "pero", "en", "de", "para", "con", "por"];
-        let french_words = ["le", "la", "et", "ou", "mais", "dans", "de", "pour", "avec", "par"];
+    fn detect_language_simple(&self, text: &str) -> LanguageDetection {
+        // Stopword unigrams carry most of the signal; distinctive character n-grams break ties
+        const PROFILES: &[(&str, &[&str], &[&str])] = &[
+            ("en", &["the", "and", "is", "are", "of", "to", "in", "that", "it", "with", "was", "for", "on", "this", "be", "have", "you", "not", "from", "they"],
+                &["th", "wh", "ing", "ght"]),
+            ("es", &["el", "la", "los", "las", "de", "que", "y", "en", "un", "una", "es", "por", "con", "para", "no", "se", "del", "al", "como", "está"],
+                &["ñ", "ción", "ll"]),
+            ("fr", &["le", "la", "les", "des", "de", "et", "est", "un", "une", "que", "qui", "dans", "pour", "pas", "sur", "avec", "ce", "il", "au", "du"],
+                &["ç", "eau", "aux", "è", "ê"]),
+            ("de", &["der", "die", "das", "und", "ist", "nicht", "ein", "eine", "zu", "mit", "den", "von", "auf", "für", "sich", "dem", "des", "auch", "es", "ich"],
+                &["sch", "ß", "ä", "ö", "ü", "ei"]),
+            ("it", &["il", "lo", "la", "gli", "le", "di", "che", "e", "è", "un", "una", "per", "non", "con", "del", "della", "sono", "nel", "nella", "come"],
+                &["gli", "zz", "cch", "ò", "ù"]),
+            ("pt", &["o", "a", "os", "as", "de", "que", "e", "é", "um", "uma", "não", "para", "com", "do", "da", "em", "no", "na", "por", "são"],
+                &["ão", "ões", "ç", "lh", "nh"]),
+        ];
+        const MIN_WORDS: usize = 3;
+        const MIN_CONFIDENCE: f64 = 0.35;
+        const NGRAM_WEIGHT: f64 = 0.25;
         
+        let undetermined = LanguageDetection {
+            language: "und".to_string(),
+            confidence: 0.0,
+        };
+        
+        // Split on anything that isn't a letter so "the" never matches inside "there"
         let text_lower = text.to_lowercase();
-        let mut english_score = 0;
-        let mut spanish_score = 0;
-        let mut french_score = 0;
+        let words: Vec<&str> = text_lower
+            .split(|c: char| !c.is_alphabetic())
+            .filter(|w| !w.is_empty())
+            .collect();
         
-        for word in &english_words {
-            english_score += text_lower.matches(word).count();
+        if words.len() < MIN_WORDS {
+            return undetermined;
         }
         
-        for word in &spanish_words {
-            spanish_score += text_lower.matches(word).count();
+        let mut scores: Vec<(&str, usize, f64)> = PROFILES.iter()
+            .map(|(code, stopwords, ngrams)| {
+                let stopword_hits = words.iter().filter(|w| stopwords.contains(w)).count();
+                let ngram_hits: usize = words.iter()
+                    .map(|w| ngrams.iter().filter(|g| w.contains(*g)).count())
+                    .sum();
+                (*code, stopword_hits, stopword_hits as f64 + ngram_hits as f64 * NGRAM_WEIGHT)
+            })
+            .collect();
+        
+        scores.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+        
+        let (top_code, top_stopwords, top_score) = scores[0];
+        let total: f64 = scores.iter().map(|(_, _, score)| score).sum();
+        if top_stopwords == 0 || total == 0.0 {
+            return undetermined;
         }
         
-        for word in &french_words {
-            french_score += text_lower.matches(word).count();
+        let confidence = top_score / total;
+        if confidence < MIN_CONFIDENCE {
+            return LanguageDetection { confidence, ..undetermined };
         }
         
-        if english_score >= spanish_score && english_score >= french_score {
-            "en".to_string()
-        } else if spanish_score >= french_score {
-            "es".to_string()
-        } else {
-            "fr".to_string()
+        LanguageDetection {
+            language: top_code.to_string(),
+            confidence,
         }
     }
     
+    /// Most frequent stemmed terms, by raw count within `words`
     fn extract_keywords(&self, words: &[&str]) -> Vec<String> {
-        let stop_words = ["the", "and", "or", "but", "in", "on", "at", "to", "for", "of", "with", "by"];
-        let mut word_counts = HashMap::new();
+        let mut sorted_words: Vec<_> = term_counts(words.iter().copied()).into_iter().collect();
+        sorted_words.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
         
-        for word in words {
-            let word_lower = word.to_lowercase();
-            if word_lower.len() > 3 && !stop_words.contains(&word_lower.as_str()) {
-                *word_counts.entry(word_lower).or_insert(0) += 1;
-            }
-        }
-        
-        let mut sorted_words: Vec<_> = word_counts.into_iter().collect();
-        sorted_words.sort_by(|a, b| b.1.cmp(&a.1));
-        
-        sorted_words.into_iter().take(10).map(|(word, _)| word).collect()
+        sorted_words.into_iter().take(MAX_KEYWORDS).map(|(word, _)| word).collect()
     }
     
     fn extract_topics(&self, words: &[&str]) -> Vec<String> {
         // Simplified topic extraction based on keyword clustering
-        let tech_words = ["technology", "computer", "software", "internet", "digital"];
-        let business_words = ["business", "company", "market", "finance", "economy"];
-        let health_words = ["health", "medical", "doctor", "hospital", "medicine"];
-        
         let text = words.join(" ").to_lowercase();
-        let mut topics = Vec::new();
-        
-        if tech_words.iter().any(|word| text.contains(word)) {
-            topics.push("Technology".to_string());
-        }
-        
-        if business_words.iter().any(|word| text.contains(word)) {
-            topics.push("Business".to_string());
-        }
-        
-        if health_words.iter().any(|word| text.contains(word)) {
-            topics.push("Health".to_string());
-        }
-        
-        topics
+        TOPIC_KEYWORDS.iter()
+            .filter(|(_, keywords)| keywords.iter().any(|word| text.contains(word)))
+            .map(|(topic, _)| topic.to_string())
+            .collect()
     }
     
     async fn summarize(&self, content: &str, max_length: usize) -> Result<String> {
         let sentences: Vec<&str> = content.split(&['.', '!', '?'][..])
-            .filter(|s| !s.trim().is_empty())
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
             .collect();
         
         if sentences.is_empty() {
             return Ok(String::new());
         }
         
-        // Simple extractive summarization - take first few sentences
+        // Very short text gains nothing from ranking
+        if sentences.len() <= 2 || content.len() <= max_length {
+            return Ok(self.summarize_leading(&sentences, max_length));
+        }
+        
+        // Extractive summarization - pick the highest scoring sentences that fit
+        let scores = self.score_sentences(&sentences);
+        let mut ranked: Vec<usize> = (0..sentences.len()).collect();
+        ranked.sort_by(|a, b| {
+            scores[*b].partial_cmp(&scores[*a])
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.cmp(b))
+        });
+        
+        let mut selected = Vec::new();
+        let mut current_length = 0;
+        for index in ranked {
+            let separator = if selected.is_empty() { 0 } else { 2 };
+            let added = sentences[index].len() + separator;
+            if current_length + added > max_length {
+                continue;
+            }
+            selected.push(index);
+            current_length += added;
+        }
+        
+        // Preserve the original sentence order
+        selected.sort_unstable();
+        
+        Ok(selected.iter()
+            .map(|&index| sentences[index])
+            .collect::<Vec<_>>()
+            .join(". "))
+    }
+    
+    /// Positional summarization - take leading sentences until the length cap
+    fn summarize_leading(&self, sentences: &[&str], max_length: usize) -> String {
         let mut summary = String::new();
         let mut current_length = 0;
         
         for sentence in sentences {
-            let sentence_trimmed = sentence.trim();
-            if current_length + sentence_trimmed.len() > max_length {
+            if current_length + sentence.len() > max_length {
                 break;
             }
             
             if !summary.is_empty() {
                 summary.push_str(". ");
             }
-            summary.push_str(sentence_trimmed);
-            current_length += sentence_trimmed.len() + 2;
+            summary.push_str(sentence);
+            current_length += sentence.len() + 2;
         }
         
-        Ok(summary)
+        summary
     }
     
-    async fn detect_language(&self, content: &str) -> Result<String> {
-        Ok(self.detect_language_simple(content))
+    /// Score sentences by term frequency, keyword overlap, and position
+    fn score_sentences(&self, sentences: &[&str]) -> Vec<f64> {
+        let tokenized: Vec<Vec<String>> = sentences.iter()
+            .map(|sentence| {
+                sentence.split_whitespace()
+                    .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase())
+                    .filter(|w| !w.is_empty())
+                    .collect()
+            })
+            .collect();
+        
+        let all_words: Vec<&str> = tokenized.iter().flatten().map(|w| w.as_str()).collect();
+        let keywords = self.extract_keywords(&all_words);
+        
+        let mut frequencies: HashMap<&str, usize> = HashMap::new();
+        for word in &all_words {
+            if word.len() > 3 {
+                *frequencies.entry(word).or_insert(0) += 1;
+            }
+        }
+        let max_frequency = frequencies.values().copied().max().unwrap_or(1) as f64;
+        let sentence_count = sentences.len() as f64;
+        
+        tokenized.iter().enumerate().map(|(index, words)| {
+            if words.is_empty() {
+                return 0.0;
+            }
+            
+            let term_score = words.iter()
+                .filter_map(|w| frequencies.get(w.as_str()))
+                .map(|&f| f as f64 / max_frequency)
+                .sum::<f64>() / words.len() as f64;
+            let keyword_overlap = words.iter()
+                .filter(|w| keywords.contains(&stem(w)))
+                .count() as f64 / words.len() as f64;
+            let position_score = 1.0 - index as f64 / sentence_count;
+            
+            0.4 * term_score + 0.4 * keyword_overlap + 0.2 * position_score
+        }).collect()
     }
     
-    async fn translate(&self, _content: &str, _target_language: &str) -> Result<String> {
-        // Placeholder for translation functionality
-        Ok("Translation not implemented".to_string())
+    async fn detect_language(&self, content: &str) -> Result<LanguageDetection> {
+        Ok(self.detect_language_simple(content))
     }
 }
 
-/// Content analysis engine
-struct ContentAnalyzer;
+/// Each element's own text with whitespace collapsed, in document order, skipping
+/// elements whose text is never rendered
+fn extract_text_blocks(document: &Document) -> Vec<String> {
+    let Some(body) = &document.body else {
+        return Vec::new();
+    };
+    
+    let mut blocks = Vec::new();
+    collect_text_blocks(body, document, &mut blocks);
+    blocks
+}
 
-impl ContentAnalyzer {
-    fn new() -> Self {
-        Self
+fn collect_text_blocks(element: &crate::html::Element, document: &Document, blocks: &mut Vec<String>) {
+    if is_hidden(element) || matches!(element.tag_name.to_ascii_lowercase().as_str(), "script" | "style" | "noscript" | "template") {
+        return;
     }
     
-    async fn analyze(&self, document: &Document, response: &NetworkResponse) -> Result<ContentAnalysis> {
-        Ok(ContentAnalysis {
-            has_forms: self.has_forms(document),
-            has_https: response.url.starts_with("https://"),
-            has_images: self.has_images(document),
-            has_videos: self.has_videos(document),
-            has_audio: self.has_audio(document),
-            has_scripts: self.has_scripts(document),
-            has_external_links: self.has_external_links(document),
-            ad_density: self.calculate_ad_density(document),
-            content_quality_score: self.calculate_content_quality(document),
-            mobile_friendly: self.is_mobile_friendly(document),
-            accessibility_score: self.calculate_accessibility_score(document),
-            performance_score: self.calculate_performance_score(response),
-        })
+    let text = element.text_content.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !text.is_empty() {
+        blocks.push(text);
     }
-    
-    fn has_forms(&self, document: &Document) -> bool {
-        !document.get_elements_by_tag_name("form").is_empty()
+    for child_id in &element.children {
+        if let Some(child) = document.elements.get(child_id) {
+            collect_text_blocks(child, document, blocks);
+        }
     }
-    
-    fn has_images(&self, document: &Document) -> bool {
-        !document.get_elements_by_tag_name("img").is_empty()
+}
+
+/// How long an analysis of the page behind `response` stays fresh: the configured TTL,
+/// or with `ttl_from_cache_headers` the page's own `Cache-Control` lifetime when it has one
+fn analysis_ttl(config: &AIConfig, response: &NetworkResponse) -> std::time::Duration {
+    if !config.ttl_from_cache_headers {
+        return config.analysis_ttl;
     }
-    
-    fn has_videos(&self, document: &Document) -> bool {
-        !document.get_elements_by_tag_name("video").is_empty()
+    let cache_control = response.headers.iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("cache-control"))
+        .map(|(_, value)| value.as_str());
+    cache_control.and_then(cache_control_lifetime).unwrap_or(config.analysis_ttl)
+}
+
+/// Freshness lifetime from a `Cache-Control` header's `max-age`, or zero when the
+/// response may not be reused without revalidation
+fn cache_control_lifetime(header: &str) -> Option<std::time::Duration> {
+    let mut max_age = None;
+    for directive in header.split(',') {
+        let (name, value) = match directive.split_once('=') {
+            Some((name, value)) => (name.trim().to_ascii_lowercase(), Some(value.trim().trim_matches('"'))),
+            None => (directive.trim().to_ascii_lowercase(), None),
+        };
+        match name.as_str() {
+            "no-store" | "no-cache" => return Some(std::time::Duration::ZERO),
+            "max-age" => max_age = value.and_then(|value| value.parse().ok()),
+            _ => {}
+        }
+    }
+    max_age.map(std::time::Duration::from_secs)
+}
+
+/// Elements whose text is never shown as page content
+const NON_TEXT_ELEMENTS: &[&str] = &["script", "style", "noscript", "template"];
+
+/// Elements that start and end a line of text
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "caption", "dd", "details", "div", "dl", "dt",
+    "fieldset", "figcaption", "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6",
+    "header", "hr", "li", "main", "nav", "ol", "p", "pre", "section", "summary", "table", "tr", "ul",
+];
+
+/// Text a reader would see in `element`: each block on its own line, whitespace collapsed
+/// outside `<pre>`, and script, style, and hidden content skipped. Text is stored per element,
+/// so an element's own text comes before its children's even where it followed them.
+pub fn visible_text(document: &Document, element: &Element) -> String {
+    let mut lines = TextLines::default();
+    collect_visible_text(document, element, false, &mut lines);
+    lines.finish()
+}
+
+fn collect_visible_text(document: &Document, element: &Element, preformatted: bool, lines: &mut TextLines) {
+    let tag = element.tag_name.to_ascii_lowercase();
+    if NON_TEXT_ELEMENTS.contains(&tag.as_str()) || is_hidden(element) {
+        return;
     }
     
-    fn has_audio(&self, document: &Document) -> bool {
-        !document.get_elements_by_tag_name("audio").is_empty()
+    let block = BLOCK_ELEMENTS.contains(&tag.as_str());
+    if block || tag == "br" {
+        lines.break_line();
     }
     
-    fn has_scripts(&self, document: &Document) -> bool {
-        !document.get_elements_by_tag_name("script").is_empty()
+    let preformatted = preformatted || tag == "pre";
+    if preformatted {
+        lines.push_preformatted(&element.text_content);
+    } else {
+        lines.push_words(&element.text_content);
+    }
+    for child in element.children.iter().filter_map(|id| document.elements.get(id)) {
+        collect_visible_text(document, child, preformatted, lines);
     }
     
-    fn has_external_links(&self, document: &Document) -> bool {
-        let links = document.get_elements_by_tag_name("a");
-        links.iter().any(|link| {
-            if let Some(href) = link.get_attribute("href") {
-                href.starts_with("http://") || href.starts_with("https://")
-            } else {
-                false
+    if block {
+        lines.break_line();
+    }
+}
+
+/// Lines of extracted text, built up a word or preformatted line at a time
+#[derive(Default)]
+struct TextLines {
+    lines: Vec<String>,
+    current: String,
+}
+
+impl TextLines {
+    fn push_words(&mut self, text: &str) {
+        for word in text.split_whitespace() {
+            if !self.current.is_empty() {
+                self.current.push(' ');
             }
-        })
+            self.current.push_str(word);
+        }
     }
     
-    fn calculate_ad_density(&self, _document: &Document) -> f64 {
-        // Simplified ad detection
-        0.1 // Placeholder
+    fn push_preformatted(&mut self, text: &str) {
+        for (index, line) in text.split('\n').enumerate() {
+            if index > 0 {
+                self.break_line();
+            }
+            self.current.push_str(line.trim_end());
+        }
     }
     
-    fn calculate_content_quality(&self, _document: &Document) -> f64 {
-        // Simplified content quality assessment
-        0.7 // Placeholder
+    /// End the current line; blank lines are dropped
+    fn break_line(&mut self) {
+        if !self.current.trim().is_empty() {
+            self.lines.push(std::mem::take(&mut self.current));
+        }
+        self.current.clear();
     }
     
-    fn is_mobile_friendly(&self, _document: &Document) -> bool {
-        // Simplified mobile-friendliness check
-        true // Placeholder
+    fn finish(mut self) -> String {
+        self.break_line();
+        self.lines.join("\n")
+    }
+}
+
+/// Whether an element is hidden via the `hidden` attribute or an inline `display: none`
+fn is_hidden(element: &crate::html::Element) -> bool {
+    if element.attributes.contains_key("hidden") {
+        return true;
     }
+    element.attributes.get("style").is_some_and(|style| {
+        let style: String = style.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_ascii_lowercase();
+        style.split(';').any(|declaration| declaration.starts_with("display:none"))
+    })
+}
+
+fn hash_block(block: &str) -> u64 {
+    use std::hash::{Hash, Hasher};
     
-    fn calculate_accessibility_score(&self, _document: &Document) -> f64 {
-        // Simplified accessibility assessment
-        0.8 // Placeholder
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    block.to_lowercase().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Index pairs of the longest common subsequence of `old` and `new`, in order
+fn longest_common_subsequence(old: &[u64], new: &[u64]) -> Vec<(usize, usize)> {
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
     }
     
-    fn calculate_performance_score(&self, response: &NetworkResponse) -> f64 {
-        // Simple performance score based on load time
-        let load_time_ms = response.load_time.as_millis() as f64;
-        if load_time_ms < 1000.0 {
-            1.0
-        } else if load_time_ms < 3000.0 {
-            0.8
-        } else if load_time_ms < 5000.0 {
-            0.6
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
         } else {
-            0.4
+            j += 1;
         }
     }
+    pairs
 }
 
-/// Interaction prediction engine
-struct InteractionPredictor;
+/// Autofill profile key for a form field, from its lowercased name and input type
+fn autofill_category(field_name: &str, field_type: &str) -> Option<&'static str> {
+    if field_type == "email" || field_name.contains("email") || field_name.contains("e-mail") {
+        Some("email")
+    } else if field_name.contains("name") {
+        if field_name.contains("first") || field_name.contains("given") {
+            Some("given_name")
+        } else if field_name.contains("last") || field_name.contains("family") || field_name.contains("surname") {
+            Some("family_name")
+        } else {
+            Some("name")
+        }
+    } else if field_type == "tel" || field_name.contains("phone") || field_name.contains("tel") {
+        Some("phone")
+    } else if field_name.contains("address") {
+        Some("street_address")
+    } else if field_name.contains("city") {
+        Some("city")
+    } else if field_name.contains("zip") || field_name.contains("postal") {
+        Some("postal_code")
+    } else {
+        None
+    }
+}
 
-impl InteractionPredictor {
-    fn new() -> Self {
-        Self
+/// Profile value for `category`, composing a full name from its parts when needed
+fn profile_value(profile: &HashMap<String, String>, category: &str) -> Option<String> {
+    if let Some(value) = profile.get(category) {
+        return Some(value.clone());
+    }
+    if category == "name" {
+        let parts: Vec<&str> = ["given_name", "family_name"].iter()
+            .filter_map(|key| profile.get(*key).map(String::as_str))
+            .collect();
+        if !parts.is_empty() {
+            return Some(parts.join(" "));
+        }
+    }
+    None
+}
+
+/// Example value shown for `category` when the user has no stored profile
+fn autofill_placeholder(category: &str) -> Option<&'static str> {
+    match category {
+        "email" => Some("user@example.com"),
+        "given_name" => Some("John"),
+        "family_name" => Some("Doe"),
+        "name" => Some("John Doe"),
+        "phone" => Some("+1-555-123-4567"),
+        "street_address" => Some("123 Main St"),
+        "city" => Some("New York"),
+        "postal_code" => Some("10001"),
+        _ => None,
     }
+}
+
+/// Number of keywords reported per page
+const MAX_KEYWORDS: usize = 10;
+
+/// Common words that never make useful keywords
+const STOP_WORDS: &[&str] = &[
+    "a", "about", "above", "after", "again", "against", "all", "also", "am", "an", "and", "any",
+    "are", "as", "at", "be", "because", "been", "before", "being", "below", "between", "both",
+    "but", "by", "can", "could", "did", "do", "does", "doing", "down", "during", "each", "even",
+    "every", "few", "for", "from", "further", "had", "has", "have", "having", "he", "her",
+    "here", "hers", "herself", "him", "himself", "his", "how", "however", "i", "if", "in",
+    "into", "is", "it", "its", "itself", "just", "like", "made", "make", "many", "may", "me",
+    "might", "more", "most", "much", "must", "my", "myself", "new", "no", "nor", "not", "now",
+    "of", "off", "on", "once", "one", "only", "or", "other", "our", "ours", "ourselves", "out",
+    "over", "own", "said", "same", "say", "says", "she", "should", "since", "so", "some",
+    "still", "such", "than", "that", "the", "their", "theirs", "them", "themselves", "then",
+    "there", "these", "they", "this", "those", "through", "to", "too", "two", "under", "until",
+    "up", "upon", "us", "use", "used", "using", "very", "was", "way", "we", "well", "were",
+    "what", "when", "where", "whether", "which", "while", "who", "whom", "why", "will", "with",
+    "within", "without", "would", "yet", "you", "your", "yours", "yourself", "yourselves",
+];
+
+/// Reduce a lowercase word to a crude stem so inflections share a term,
+/// e.g. "browsers" becomes "browser" and "computing" becomes "comput"
+fn stem(word: &str) -> String {
+    const SUFFIXES: [(&str, &str); 9] = [
+        ("ational", "ate"),
+        ("ies", "y"),
+        ("sses", "ss"),
+        ("ing", ""),
+        ("edly", ""),
+        ("ed", ""),
+        ("ly", ""),
+        ("es", ""),
+        ("s", ""),
+    ];
     
-    async fn predict(&self, document: &Document) -> Result<Vec<InteractionPrediction>> {
-        let mut predictions = Vec::new();
-        
-        // Predict form interactions
-        let forms = document.get_elements_by_tag_name("form");
-        for form in forms {
-            predictions.push(InteractionPrediction {
-                element_id: Some(form.id),
-                action_type: "form_fill".to_string(),
-                confidence: 0.7,
-                reasoning: "Form detected on page".to_string(),
-            });
+    for (suffix, replacement) in SUFFIXES {
+        if let Some(base) = word.strip_suffix(suffix) {
+            // Keep short words whole, and don't strip the "s" from "ss" endings
+            if base.chars().count() < 3 || (suffix == "s" && base.ends_with('s')) {
+                continue;
+            }
+            return format!("{}{}", base, replacement);
         }
-        
-        // Predict button clicks
+    }
+    word.to_string()
+}
+
+/// Stemmed keyword candidates in `words` with their counts, ignoring stop words and short words
+fn term_counts<'a>(words: impl Iterator<Item = &'a str>) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for word in words {
+        let word = word.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+        if word.chars().count() <= 3 || STOP_WORDS.contains(&word.as_str()) {
+            continue;
+        }
+        *counts.entry(stem(&word)).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Document frequencies of terms across the pages analyzed in a session
+#[derive(Debug, Default)]
+struct KeywordCorpus {
+    document_count: usize,
+    document_frequency: HashMap<String, usize>,
+}
+
+impl KeywordCorpus {
+    /// Add a page to the corpus and return its keywords, ranked by TF-IDF once there are
+    /// other pages to compare against and by raw frequency before that
+    fn add_document(&mut self, text: &str) -> Vec<String> {
+        let counts = term_counts(text.split_whitespace());
+        self.document_count += 1;
+        for term in counts.keys() {
+            *self.document_frequency.entry(term.clone()).or_insert(0) += 1;
+        }
+        
+        let total_terms = counts.values().sum::<usize>().max(1) as f64;
+        let mut scored: Vec<(String, f64)> = counts.into_iter().map(|(term, count)| {
+            let score = if self.document_count > 1 {
+                count as f64 / total_terms * self.inverse_document_frequency(&term)
+            } else {
+                count as f64
+            };
+            (term, score)
+        }).collect();
+        scored.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal).then_with(|| a.0.cmp(&b.0))
+        });
+        
+        scored.into_iter().take(MAX_KEYWORDS).map(|(term, _)| term).collect()
+    }
+    
+    /// Smoothed IDF, so a term on every page still scores above zero
+    fn inverse_document_frequency(&self, term: &str) -> f64 {
+        let frequency = self.document_frequency.get(term).copied().unwrap_or(0) as f64;
+        ((1.0 + self.document_count as f64) / (1.0 + frequency)).ln() + 1.0
+    }
+}
+
+/// Topics page text is classified into
+const TOPIC_LABELS: [&str; 3] = ["Technology", "Business", "Health"];
+
+/// Minimum classifier score for a topic to be reported
+const TOPIC_THRESHOLD: f64 = 0.5;
+
+/// Keywords the heuristic classifier associates with each topic
+const TOPIC_KEYWORDS: &[(&str, &[&str])] = &[
+    ("Technology", &["technology", "computer", "software", "internet", "digital"]),
+    ("Business", &["business", "company", "market", "finance", "economy"]),
+    ("Health", &["health", "medical", "doctor", "hospital", "medicine"]),
+];
+
+/// Pluggable model for the text tasks behind page analysis, e.g. a local ONNX model or a
+/// remote service
+#[async_trait::async_trait]
+pub trait InferenceBackend: Send + Sync {
+    /// Fixed-length vector representation of `text`
+    async fn embed(&self, text: &str) -> Result<Vec<f32>>;
+    
+    /// Score `text` against each label from 0.0 to 1.0, highest first
+    async fn classify(&self, text: &str, labels: &[&str]) -> Result<Vec<(String, f64)>>;
+    
+    /// Summary of `text` no longer than `max_length` characters
+    async fn summarize(&self, text: &str, max_length: usize) -> Result<String>;
+    
+    /// Sentiment from -1.0 (very negative) to 1.0 (very positive)
+    async fn sentiment(&self, text: &str) -> Result<f64>;
+}
+
+/// Word-list heuristics, used when no model is configured
+#[derive(Default)]
+pub struct HeuristicBackend {
+    processor: TextProcessor,
+}
+
+impl HeuristicBackend {
+    /// Dimensions of the hashed bag-of-words embedding
+    const EMBEDDING_DIMENSIONS: usize = 64;
+}
+
+#[async_trait::async_trait]
+impl InferenceBackend for HeuristicBackend {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        use std::hash::{Hash, Hasher};
+        
+        let mut embedding = vec![0.0f32; Self::EMBEDDING_DIMENSIONS];
+        for word in text.split_whitespace() {
+            let word = word.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+            if word.is_empty() {
+                continue;
+            }
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            word.hash(&mut hasher);
+            embedding[hasher.finish() as usize % Self::EMBEDDING_DIMENSIONS] += 1.0;
+        }
+        
+        let norm = embedding.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            embedding.iter_mut().for_each(|v| *v /= norm);
+        }
+        Ok(embedding)
+    }
+    
+    async fn classify(&self, text: &str, labels: &[&str]) -> Result<Vec<(String, f64)>> {
+        let text = text.to_lowercase();
+        let mut scores: Vec<(String, f64)> = labels.iter().map(|label| {
+            // Known topics match on their keywords, anything else on the label itself
+            let matched = match TOPIC_KEYWORDS.iter().find(|(topic, _)| topic.eq_ignore_ascii_case(label)) {
+                Some((_, keywords)) => keywords.iter().any(|word| text.contains(word)),
+                None => text.contains(&label.to_lowercase()),
+            };
+            (label.to_string(), if matched { 1.0 } else { 0.0 })
+        }).collect();
+        scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(scores)
+    }
+    
+    async fn summarize(&self, text: &str, max_length: usize) -> Result<String> {
+        self.processor.summarize(text, max_length).await
+    }
+    
+    async fn sentiment(&self, text: &str) -> Result<f64> {
+        Ok(self.processor.analyze_sentiment(text))
+    }
+}
+
+/// Pluggable machine translation provider
+#[async_trait::async_trait]
+pub trait TranslationBackend: Send + Sync {
+    /// Translate `text` from `source_lang` into `target_lang`
+    async fn translate(&self, text: &str, source_lang: &str, target_lang: &str) -> Result<String>;
+    
+    /// Maximum number of characters the backend accepts per request
+    fn max_chunk_chars(&self) -> usize {
+        4000
+    }
+}
+
+/// Translation backend speaking the LibreTranslate-style JSON API over `NetworkStack`
+pub struct HttpTranslationBackend {
+    network: Arc<NetworkStack>,
+    endpoint: String,
+    api_key: Option<String>,
+}
+
+impl HttpTranslationBackend {
+    pub fn new(network: Arc<NetworkStack>, endpoint: String, api_key: Option<String>) -> Self {
+        Self {
+            network,
+            endpoint,
+            api_key,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl TranslationBackend for HttpTranslationBackend {
+    async fn translate(&self, text: &str, source_lang: &str, target_lang: &str) -> Result<String> {
+        let mut payload = serde_json::json!({
+            "q": text,
+            "source": source_lang,
+            "target": target_lang,
+            "format": "text",
+        });
+        if let Some(api_key) = &self.api_key {
+            payload["api_key"] = serde_json::Value::String(api_key.clone());
+        }
+        
+        let mut options = RequestOptions::default();
+        options.method = "POST".to_string();
+        options.headers.insert("Content-Type".to_string(), "application/json".to_string());
+        options.body = Some(payload.to_string().into_bytes());
+        
+        let response = self.network.fetch_with_options(&self.endpoint, options).await?;
+        if response.status >= 400 {
+            return Err(EngineError::AIError(format!("Translation service returned status {}", response.status)));
+        }
+        
+        let body: serde_json::Value = serde_json::from_str(&response.body)
+            .map_err(|e| EngineError::AIError(format!("Invalid translation response: {}", e)))?;
+        
+        body.get("translatedText")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+            .ok_or_else(|| EngineError::AIError("Translation response missing translatedText".to_string()))
+    }
+}
+
+/// Text sent to the translation backend in one request
+#[derive(Debug, Clone, PartialEq)]
+struct TextChunk {
+    text: String,
+    /// Index of the paragraph the chunk starts in
+    first_paragraph: usize,
+    /// Index of the paragraph the chunk ends in
+    last_paragraph: usize,
+}
+
+impl TextChunk {
+    /// What goes between this chunk and text from `paragraph` that follows it:
+    /// a space within one paragraph, a blank line between paragraphs
+    fn separator_before(&self, paragraph: usize) -> &'static str {
+        if self.last_paragraph == paragraph { " " } else { "\n\n" }
+    }
+}
+
+/// Split text into chunks of at most `max_chars`, keeping paragraphs intact where possible
+fn chunk_paragraphs(text: &str, max_chars: usize) -> Vec<TextChunk> {
+    let max_chars = max_chars.max(1);
+    let mut chunks: Vec<TextChunk> = Vec::new();
+    
+    let paragraphs = text.split("\n\n").map(|p| p.trim()).filter(|p| !p.is_empty());
+    for (index, paragraph) in paragraphs.enumerate() {
+        // Oversized paragraphs are split on sentence boundaries, then hard-wrapped
+        let pieces = if paragraph.chars().count() > max_chars {
+            split_long_paragraph(paragraph, max_chars)
+        } else {
+            vec![paragraph.to_string()]
+        };
+        
+        for piece in pieces {
+            match chunks.last_mut() {
+                Some(chunk) if chunk.text.chars().count() + chunk.separator_before(index).len() + piece.chars().count() <= max_chars => {
+                    chunk.text.push_str(chunk.separator_before(index));
+                    chunk.text.push_str(&piece);
+                    chunk.last_paragraph = index;
+                }
+                _ => chunks.push(TextChunk { text: piece, first_paragraph: index, last_paragraph: index }),
+            }
+        }
+    }
+    
+    chunks
+}
+
+/// Reassemble translated chunks, rejoining the pieces of a split paragraph into one
+fn join_chunks(chunks: &[TextChunk], translated: Vec<String>) -> String {
+    let mut text = String::new();
+    for (index, (chunk, translation)) in chunks.iter().zip(translated).enumerate() {
+        if index > 0 {
+            text.push_str(chunks[index - 1].separator_before(chunk.first_paragraph));
+        }
+        text.push_str(&translation);
+    }
+    text
+}
+
+fn split_long_paragraph(paragraph: &str, max_chars: usize) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    
+    for sentence in paragraph.split_inclusive(&['.', '!', '?'][..]) {
+        if !current.is_empty() && current.chars().count() + sentence.chars().count() > max_chars {
+            pieces.push(current.trim().to_string());
+            current.clear();
+        }
+        if current.is_empty() {
+            current.push_str(sentence.trim_start());
+        } else {
+            current.push_str(sentence);
+        }
+        
+        // A single sentence longer than the limit is cut at character boundaries
+        while current.chars().count() > max_chars {
+            let split_at = current.char_indices().nth(max_chars).map(|(i, _)| i).unwrap_or(current.len());
+            pieces.push(current[..split_at].trim().to_string());
+            current = current[split_at..].to_string();
+        }
+    }
+    
+    if !current.trim().is_empty() {
+        pieces.push(current.trim().to_string());
+    }
+    
+    pieces
+}
+
+/// Content analysis engine
+struct ContentAnalyzer;
+
+impl ContentAnalyzer {
+    fn new() -> Self {
+        Self
+    }
+    
+    async fn analyze(&self, document: &Document, response: &NetworkResponse) -> Result<ContentAnalysis> {
+        let robots = self.robots_directives(document, response);
+        Ok(ContentAnalysis {
+            has_forms: self.has_forms(document),
+            has_https: response.url.starts_with("https://"),
+            has_images: self.has_images(document),
+            has_videos: self.has_videos(document),
+            has_audio: self.has_audio(document),
+            has_scripts: self.has_scripts(document),
+            has_external_links: self.has_external_links(document),
+            ad_density: self.calculate_ad_density(document),
+            content_quality_score: self.calculate_content_quality(document),
+            mobile_friendly: self.is_mobile_friendly(document),
+            accessibility_score: self.calculate_accessibility_score(document),
+            performance_score: self.calculate_performance_score(response),
+            schema_types: document.structured_data_types(),
+            mixed_content_urls: self.find_mixed_content(document, &response.url),
+            noindex: robots.contains("noindex"),
+            nofollow: robots.contains("nofollow"),
+            canonical_url: self.find_canonical_url(document, &response.url),
+        })
+    }
+    
+    /// Crawler directives from `<meta name="robots">` and unscoped `X-Robots-Tag` headers,
+    /// lowercased; `none` expands to `noindex` and `nofollow`
+    fn robots_directives(&self, document: &Document, response: &NetworkResponse) -> HashSet<String> {
+        let metas = document.get_elements_by_tag_name("meta");
+        let meta = metas.iter()
+            .filter(|m| m.get_attribute("name").is_some_and(|n| n.trim().eq_ignore_ascii_case("robots")))
+            .filter_map(|m| m.get_attribute("content"))
+            .flat_map(|content| content.split(','));
+        let header = response.headers.get("x-robots-tag")
+            .map_or_else(Vec::new, |value| unscoped_robots_directives(value));
+        
+        let mut directives = HashSet::new();
+        for directive in meta.chain(header) {
+            match directive.trim().to_ascii_lowercase().as_str() {
+                "" => {}
+                "none" => directives.extend(["noindex".to_string(), "nofollow".to_string()]),
+                directive => {
+                    directives.insert(directive.to_string());
+                }
+            }
+        }
+        directives
+    }
+    
+    /// The first `<link rel="canonical">` target, resolved against the page URL
+    fn find_canonical_url(&self, document: &Document, page_url: &str) -> Option<String> {
+        let href = document.descendants(&document.root).into_iter()
+            .filter(|element| element.tag_name.eq_ignore_ascii_case("link"))
+            .filter(|link| link.get_attribute("rel")
+                .is_some_and(|rel| rel.split_whitespace().any(|r| r.eq_ignore_ascii_case("canonical"))))
+            .find_map(|link| link.get_attribute("href").map(|href| href.trim().to_string()))
+            .filter(|href| !href.is_empty())?;
+        
+        match url::Url::parse(page_url) {
+            Ok(base) => base.join(&href).ok().map(String::from),
+            Err(_) => url::Url::parse(&href).ok().map(String::from),
+        }
+    }
+    
+    fn has_forms(&self, document: &Document) -> bool {
+        !document.get_elements_by_tag_name("form").is_empty()
+    }
+    
+    fn has_images(&self, document: &Document) -> bool {
+        !document.get_elements_by_tag_name("img").is_empty()
+    }
+    
+    fn has_videos(&self, document: &Document) -> bool {
+        !document.get_elements_by_tag_name("video").is_empty()
+    }
+    
+    fn has_audio(&self, document: &Document) -> bool {
+        !document.get_elements_by_tag_name("audio").is_empty()
+    }
+    
+    fn has_scripts(&self, document: &Document) -> bool {
+        !document.get_elements_by_tag_name("script").is_empty()
+    }
+    
+    fn has_external_links(&self, document: &Document) -> bool {
+        let links = document.get_elements_by_tag_name("a");
+        links.iter().any(|link| {
+            if let Some(href) = link.get_attribute("href") {
+                href.starts_with("http://") || href.starts_with("https://")
+            } else {
+                false
+            }
+        })
+    }
+    
+    /// Subresources (`<img>`, `<script>`, `<link>`) fetched over http:// from an https:// page
+    fn find_mixed_content(&self, document: &Document, page_url: &str) -> Vec<String> {
+        if !page_url.starts_with("https://") {
+            return Vec::new();
+        }
+        
+        let mut urls: Vec<String> = Vec::new();
+        for element in document.descendants(&document.root) {
+            let resource = match element.tag_name.to_ascii_lowercase().as_str() {
+                "img" | "script" => element.get_attribute("src"),
+                // Only links the browser actually fetches; canonical/alternate links are not loaded
+                "link" if link_fetches_resource(&element) => element.get_attribute("href"),
+                _ => None,
+            };
+            
+            if let Some(url) = resource.map(str::trim) {
+                if url.to_ascii_lowercase().starts_with("http://") && !urls.iter().any(|u| u == url) {
+                    urls.push(url.to_string());
+                }
+            }
+        }
+        
+        urls
+    }
+    
+    /// Fraction of body elements that look like ads (ad class/id words or iframes to ad networks)
+    fn calculate_ad_density(&self, document: &Document) -> f64 {
+        let Some(body) = &document.body else {
+            return 0.0;
+        };
+        
+        let elements = document.descendants(body);
+        if elements.is_empty() {
+            return 0.0;
+        }
+        
+        let ad_count = elements.iter().filter(|e| is_ad_element(e)).count();
+        ad_count as f64 / elements.len() as f64
+    }
+    
+    /// Readability-style extraction: score paragraph containers by text density, link density,
+    /// and tag type, then keep the paragraphs of the single best-scoring region
+    fn extract_article(&self, document: &Document) -> Result<ReaderArticle> {
+        let body = document.body.as_ref()
+            .ok_or_else(|| EngineError::AIError("Document has no body".to_string()))?;
+        
+        // Each paragraph credits its parent fully and its grandparent by half
+        let mut scores: HashMap<ElementId, f64> = HashMap::new();
+        for element in readable_descendants(document, body) {
+            if !is_paragraph_tag(&element.tag_name) {
+                continue;
+            }
+            
+            let text = inner_text(document, &element);
+            if text.len() < MIN_PARAGRAPH_CHARS {
+                continue;
+            }
+            
+            let paragraph_score = 1.0
+                + text.matches(',').count() as f64
+                + (text.len() as f64 / 100.0).min(3.0);
+            
+            let parent = element.parent.and_then(|id| document.elements.get(&id));
+            if let Some(parent) = parent {
+                *scores.entry(parent.id).or_insert_with(|| candidate_base_score(parent)) += paragraph_score;
+                
+                if let Some(grandparent) = parent.parent.and_then(|id| document.elements.get(&id)) {
+                    *scores.entry(grandparent.id).or_insert_with(|| candidate_base_score(grandparent)) += paragraph_score / 2.0;
+                }
+            }
+        }
+        
+        // Link-heavy regions are navigation, however much text they hold
+        let best = scores.into_iter()
+            .filter_map(|(id, score)| {
+                let element = document.elements.get(&id)?;
+                Some((element.clone(), score * (1.0 - link_density(document, element))))
+            })
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        
+        let (content, score) = best
+            .ok_or_else(|| EngineError::AIError("No readable content found".to_string()))?;
+        if score < MIN_ARTICLE_SCORE {
+            return Err(EngineError::AIError("No readable content found".to_string()));
+        }
+        
+        let mut paragraphs = Vec::new();
+        let mut heading = None;
+        for element in readable_descendants(document, &content) {
+            let tag = element.tag_name.to_ascii_lowercase();
+            if tag == "h1" && heading.is_none() {
+                heading = Some(normalize_whitespace(&inner_text(document, &element)));
+                continue;
+            }
+            
+            if !is_paragraph_tag(&tag) || link_density(document, &element) > 0.5 {
+                continue;
+            }
+            
+            let text = normalize_whitespace(&inner_text(document, &element));
+            if !text.is_empty() {
+                paragraphs.push(text);
+            }
+        }
+        
+        let title = heading
+            .filter(|h| !h.is_empty())
+            .unwrap_or_else(|| document.title.clone());
+        let word_count = paragraphs.iter().map(|p| p.split_whitespace().count()).sum();
+        
+        Ok(ReaderArticle {
+            title,
+            byline: find_byline(document),
+            paragraphs,
+            content_element: content.id,
+            word_count,
+        })
+    }
+    
+    /// Quality of the main article: its length (saturating at 600 words) and paragraph structure,
+    /// discounted by ad density; 0.0 when no article can be found
+    fn calculate_content_quality(&self, document: &Document) -> f64 {
+        let Ok(article) = self.extract_article(document) else {
+            return 0.0;
+        };
+        
+        let length_score = (article.word_count as f64 / 600.0).min(1.0);
+        let structure_score = (article.paragraphs.len() as f64 / 5.0).min(1.0);
+        let score = 0.7 * length_score + 0.3 * structure_score;
+        
+        score * (1.0 - self.calculate_ad_density(document))
+    }
+    
+    /// Mobile friendly when a viewport meta tag sizes the page to the device
+    fn is_mobile_friendly(&self, document: &Document) -> bool {
+        document.get_elements_by_tag_name("meta").iter()
+            .filter(|m| m.get_attribute("name").map(|n| n.eq_ignore_ascii_case("viewport")).unwrap_or(false))
+            .filter_map(|m| m.get_attribute("content"))
+            .any(|content| {
+                let content = content.to_ascii_lowercase().replace(' ', "");
+                content.contains("width=device-width") || content.contains("initial-scale=")
+            })
+    }
+    
+    /// Mean of the applicable checks: images with alt text, labelled form controls, and a
+    /// heading hierarchy without skipped levels; 1.0 when nothing applies
+    fn calculate_accessibility_score(&self, document: &Document) -> f64 {
+        let Some(body) = &document.body else {
+            return 1.0;
+        };
+        let elements = document.descendants(body);
+        let mut checks = Vec::new();
+        
+        // alt="" is valid for decorative images, so presence is what counts
+        let images: Vec<_> = elements.iter().filter(|e| e.tag_name.eq_ignore_ascii_case("img")).collect();
+        if !images.is_empty() {
+            let with_alt = images.iter().filter(|img| img.has_attribute("alt")).count();
+            checks.push(with_alt as f64 / images.len() as f64);
+        }
+        
+        let controls: Vec<_> = elements.iter().filter(|e| is_labelable_control(e)).collect();
+        if !controls.is_empty() {
+            let label_targets: Vec<&str> = elements.iter()
+                .filter(|e| e.tag_name.eq_ignore_ascii_case("label"))
+                .filter_map(|label| label.get_attribute("for"))
+                .collect();
+            let labelled = controls.iter()
+                .filter(|control| is_labelled(document, control, &label_targets))
+                .count();
+            checks.push(labelled as f64 / controls.len() as f64);
+        }
+        
+        let levels: Vec<usize> = elements.iter().filter_map(|e| heading_level(e)).collect();
+        if !levels.is_empty() {
+            // The first heading should be an h1, and each heading may go at most one level deeper
+            let mut violations = usize::from(levels[0] != 1);
+            violations += levels.windows(2).filter(|pair| pair[1] > pair[0] + 1).count();
+            checks.push(1.0 - violations as f64 / levels.len() as f64);
+        }
+        
+        if checks.is_empty() {
+            1.0
+        } else {
+            checks.iter().sum::<f64>() / checks.len() as f64
+        }
+    }
+    
+    fn calculate_performance_score(&self, response: &NetworkResponse) -> f64 {
+        // Simple performance score based on load time
+        let load_time_ms = response.load_time.as_millis() as f64;
+        if load_time_ms < 1000.0 {
+            1.0
+        } else if load_time_ms < 3000.0 {
+            0.8
+        } else if load_time_ms < 5000.0 {
+            0.6
+        } else {
+            0.4
+        }
+    }
+}
+
+/// Paragraphs shorter than this carry too little text to vote for their container
+const MIN_PARAGRAPH_CHARS: usize = 25;
+
+/// Candidates scoring below this are treated as "no article" (e.g. navigation-only pages)
+const MIN_ARTICLE_SCORE: f64 = 5.0;
+
+/// Class/id words that mark advertising
+const AD_PATTERNS: &[&str] = &["ad", "ads", "advert", "advertisement", "banner", "sponsor", "sponsored", "promo"];
+
+/// Class/id words that mark page chrome rather than content
+const BOILERPLATE_PATTERNS: &[&str] = &[
+    "nav", "navbar", "menu", "sidebar", "footer", "header", "comment", "comments",
+    "share", "social", "related", "subscribe", "newsletter", "cookie", "breadcrumb",
+];
+
+/// Class/id words that suggest the main content
+const CONTENT_PATTERNS: &[&str] = &["article", "content", "entry", "main", "post", "story", "body", "text"];
+
+/// Split an element's class and id into lowercase words ("sidebar-ad" -> ["sidebar", "ad"])
+fn class_and_id_words(element: &Element) -> Vec<String> {
+    let mut words = Vec::new();
+    for attr in ["class", "id"] {
+        if let Some(value) = element.get_attribute(attr) {
+            words.extend(
+                value.split(|c: char| c.is_whitespace() || c == '-' || c == '_')
+                    .filter(|w| !w.is_empty())
+                    .map(|w| w.to_ascii_lowercase()),
+            );
+        }
+    }
+    words
+}
+
+/// Hosts of common ad networks; subdomains match too
+const AD_DOMAINS: &[&str] = &[
+    "doubleclick.net", "googlesyndication.com", "googleadservices.com", "adnxs.com",
+    "amazon-adsystem.com", "taboola.com", "outbrain.com", "criteo.com", "adsrvr.org",
+];
+
+/// Whether an element looks like an advertisement by its class or id, or is an iframe to an ad network
+fn is_ad_element(element: &Element) -> bool {
+    if element.tag_name.eq_ignore_ascii_case("iframe") {
+        let host = element.get_attribute("src")
+            .and_then(|src| url::Url::parse(src).ok())
+            .and_then(|url| url.host_str().map(|h| h.to_ascii_lowercase()));
+        if let Some(host) = host {
+            let is_ad_host = AD_DOMAINS.iter()
+                .any(|domain| host == *domain || host.ends_with(&format!(".{}", domain)));
+            if is_ad_host {
+                return true;
+            }
+        }
+    }
+    
+    class_and_id_words(element).iter().any(|w| AD_PATTERNS.contains(&w.as_str()))
+}
+
+/// Whether a `<link>`'s rel makes the browser fetch its href
+fn link_fetches_resource(element: &Element) -> bool {
+    element.get_attribute("rel")
+        .map(|rel| {
+            rel.split_whitespace().any(|r| matches!(
+                r.to_ascii_lowercase().as_str(),
+                "stylesheet" | "icon" | "preload" | "modulepreload" | "prefetch" | "manifest"
+            ))
+        })
+        .unwrap_or(false)
+}
+
+/// Directives with a `name: value` form, as opposed to a `crawler:` scope
+const VALUED_ROBOTS_DIRECTIVES: [&str; 4] = ["unavailable_after", "max-snippet", "max-image-preview", "max-video-preview"];
+
+/// Directives in an `X-Robots-Tag` value that apply to every crawler. After a
+/// `crawler:` prefix, directives apply only to that crawler up to the next prefix.
+fn unscoped_robots_directives(value: &str) -> Vec<&str> {
+    let mut scoped = false;
+    let mut directives = Vec::new();
+    for token in value.split(',') {
+        let directive = match token.split_once(':') {
+            Some((name, _)) if VALUED_ROBOTS_DIRECTIVES.contains(&name.trim().to_ascii_lowercase().as_str()) => token,
+            Some((_, directive)) => {
+                scoped = true;
+                directive
+            }
+            None => token,
+        };
+        if !scoped {
+            directives.push(directive);
+        }
+    }
+    directives
+}
+
+/// Form controls that need an accessible label
+fn is_labelable_control(element: &Element) -> bool {
+    match element.tag_name.to_ascii_lowercase().as_str() {
+        "select" | "textarea" => true,
+        "input" => !matches!(
+            element.get_attribute("type").map(|t| t.to_ascii_lowercase()).as_deref(),
+            Some("hidden" | "submit" | "button" | "reset" | "image")
+        ),
+        _ => false,
+    }
+}
+
+/// Labelled by `<label for>`, an enclosing `<label>`, or ARIA/title attributes
+fn is_labelled(document: &Document, control: &Element, label_targets: &[&str]) -> bool {
+    if ["aria-label", "aria-labelledby", "title"].iter().any(|attr| control.has_attribute(attr)) {
+        return true;
+    }
+    
+    if let Some(id) = control.get_attribute("id") {
+        if label_targets.contains(&id) {
+            return true;
+        }
+    }
+    
+    let mut parent = control.parent.and_then(|id| document.elements.get(&id));
+    while let Some(ancestor) = parent {
+        if ancestor.tag_name.eq_ignore_ascii_case("label") {
+            return true;
+        }
+        parent = ancestor.parent.and_then(|id| document.elements.get(&id));
+    }
+    
+    false
+}
+
+/// Level of an `<h1>`..`<h6>` element
+fn heading_level(element: &Element) -> Option<usize> {
+    let tag = element.tag_name.to_ascii_lowercase();
+    let level = tag.strip_prefix('h')?.parse::<usize>().ok()?;
+    (1..=6).contains(&level).then_some(level)
+}
+
+/// Whether an element (and its subtree) should be dropped from reader output
+fn is_boilerplate(element: &Element) -> bool {
+    let tag = element.tag_name.to_ascii_lowercase();
+    if matches!(
+        tag.as_str(),
+        "nav" | "aside" | "footer" | "header" | "form" | "script" | "style" | "noscript" | "iframe" | "button"
+    ) {
+        return true;
+    }
+    
+    if element.get_attribute("role").map(|r| r == "navigation" || r == "complementary").unwrap_or(false) {
+        return true;
+    }
+    
+    is_ad_element(element)
+        || class_and_id_words(element).iter().any(|w| BOILERPLATE_PATTERNS.contains(&w.as_str()))
+}
+
+fn is_paragraph_tag(tag: &str) -> bool {
+    matches!(tag.to_ascii_lowercase().as_str(), "p" | "pre" | "blockquote")
+}
+
+/// Initial score of a content candidate from its tag and class/id
+fn candidate_base_score(element: &Element) -> f64 {
+    let tag_score = match element.tag_name.to_ascii_lowercase().as_str() {
+        "article" | "main" => 10.0,
+        "div" | "section" => 5.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "ol" | "ul" | "dl" | "li" | "form" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+        _ => 0.0,
+    };
+    
+    let words = class_and_id_words(element);
+    let class_score = if words.iter().any(|w| CONTENT_PATTERNS.contains(&w.as_str())) {
+        25.0
+    } else {
+        0.0
+    };
+    
+    tag_score + class_score
+}
+
+/// Descendants in document order, pruning boilerplate subtrees
+fn readable_descendants(document: &Document, element: &Element) -> Vec<Arc<Element>> {
+    let mut result = Vec::new();
+    for child_id in &element.children {
+        if let Some(child) = document.elements.get(child_id) {
+            if is_boilerplate(child) {
+                continue;
+            }
+            result.push(child.clone());
+            result.extend(readable_descendants(document, child));
+        }
+    }
+    result
+}
+
+/// Text of an element and all its descendants
+fn inner_text(document: &Document, element: &Element) -> String {
+    let mut text = element.text_content.clone();
+    for child in document.descendants(element) {
+        if !child.text_content.is_empty() {
+            text.push(' ');
+            text.push_str(&child.text_content);
+        }
+    }
+    text
+}
+
+/// Fraction of an element's text that sits inside links
+fn link_density(document: &Document, element: &Element) -> f64 {
+    let total = inner_text(document, element).trim().len();
+    if total == 0 {
+        return 0.0;
+    }
+    
+    let link_chars: usize = document.descendants(element).iter()
+        .filter(|e| e.tag_name.eq_ignore_ascii_case("a"))
+        .map(|a| inner_text(document, a).trim().len())
+        .sum();
+    
+    (link_chars as f64 / total as f64).min(1.0)
+}
+
+fn normalize_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Author from `<meta name="author">`, `rel="author"`, or a byline/author class
+fn find_byline(document: &Document) -> Option<String> {
+    let meta_author = document.get_elements_by_tag_name("meta").into_iter()
+        .find(|m| m.get_attribute("name").map(|n| n.eq_ignore_ascii_case("author")).unwrap_or(false))
+        .and_then(|m| m.get_attribute("content").map(normalize_whitespace));
+    if let Some(author) = meta_author.filter(|a| !a.is_empty()) {
+        return Some(author);
+    }
+    
+    let body = document.body.as_ref()?;
+    document.descendants(body).into_iter()
+        .filter(|e| {
+            e.get_attribute("rel") == Some("author")
+                || class_and_id_words(e).iter().any(|w| w == "byline" || w == "author")
+        })
+        .map(|e| normalize_whitespace(&inner_text(document, &e)))
+        .find(|text| !text.is_empty() && text.len() < 100)
+}
+
+/// Interaction prediction engine
+struct InteractionPredictor;
+
+impl InteractionPredictor {
+    fn new() -> Self {
+        Self
+    }
+    
+    async fn predict(&self, document: &Document) -> Result<Vec<InteractionPrediction>> {
+        let mut predictions = Vec::new();
+        
+        // Predict form interactions
+        let forms = document.get_elements_by_tag_name("form");
+        for form in forms {
+            predictions.push(InteractionPrediction {
+                element_id: Some(form.id),
+                action_type: "form_fill".to_string(),
+                confidence: 0.7,
+                reasoning: "Form detected on page".to_string(),
+            });
+        }
+        
+        // Predict button clicks
         let buttons = document.get_elements_by_tag_name("button");
         for button in buttons {
             predictions.push(InteractionPrediction {
@@ -706,9 +2072,15 @@ pub struct AIConfig {
     pub enable_content_analysis: bool,
     pub enable_interaction_prediction: bool,
     pub enable_form_suggestions: bool,
+    /// Fill forms from the user's stored autofill profile; off unless the user opts in
+    pub use_autofill_profile: bool,
     pub enable_translation: bool,
     pub cache_analysis_results: bool,
     pub max_cache_size: usize,
+    /// How long a page's analysis is reused before the page is analyzed again
+    pub analysis_ttl: std::time::Duration,
+    /// Prefer the page's `Cache-Control` max-age (zero for no-cache/no-store) to `analysis_ttl`
+    pub ttl_from_cache_headers: bool,
 }
 
 impl Default for AIConfig {
@@ -718,9 +2090,12 @@ impl Default for AIConfig {
             enable_content_analysis: true,
             enable_interaction_prediction: true,
             enable_form_suggestions: true,
+            use_autofill_profile: false,
             enable_translation: false,
             cache_analysis_results: true,
             max_cache_size: 100,
+            analysis_ttl: std::time::Duration::from_secs(30 * 60),
+            ttl_from_cache_headers: false,
         }
     }
 }
@@ -770,6 +2145,289 @@ mod tests {
         assert_eq!(processor.analyze_sentiment(neutral_text), 0.0);
     }
     
+    #[test]
+    fn test_language_detection() {
+        let processor = TextProcessor::new();
+        
+        let samples = [
+            ("en", "The dog is in the house with the children and they are happy"),
+            ("es", "El perro come la comida en la casa con los niños"),
+            ("fr", "Le chat est dans la maison avec les enfants"),
+            ("de", "Der Hund ist nicht in dem Haus und die Katze auch"),
+            ("it", "Il gatto è nella casa con i bambini e la mamma"),
+            ("pt", "O gato não está na casa com as crianças e a mãe"),
+        ];
+        
+        for (expected, sample) in &samples {
+            let detection = processor.detect_language_simple(sample);
+            assert_eq!(detection.language, *expected, "sample: {}", sample);
+            assert!(detection.confidence > 0.0);
+        }
+        
+        // Substrings of stopwords must not count
+        assert_eq!(processor.detect_language_simple("there theory thesis").language, "und");
+        
+        let gibberish = processor.detect_language_simple("xyzzy plugh qwrtk zzgrp vbnm");
+        assert_eq!(gibberish.language, "und");
+        assert_eq!(gibberish.confidence, 0.0);
+    }
+    
+    #[tokio::test]
+    async fn test_reader_mode_extraction() {
+        let parser = crate::html::HTMLParser::new();
+        let html = r#"
+            <html>
+                <head>
+                    <title>Rust Browsers | Example News</title>
+                    <meta name="author" content="Ada Lovelace">
+                </head>
+                <body>
+                    <nav><a href="/">Home</a> <a href="/world">World</a> <a href="/tech">Tech</a></nav>
+                    <div class="sidebar-ad">Buy now, limited offer, click here for a great deal on widgets</div>
+                    <article>
+                        <h1>Why engines are written in Rust</h1>
+                        <p>Memory safety, without a garbage collector, makes Rust attractive for browser engines.</p>
+                        <p>Parsing, layout, and rendering all benefit from fearless concurrency and strong typing.</p>
+                        <div class="share">Share on <a href="https://social.example">social</a></div>
+                        <p>Several projects, large and small, now ship Rust components in production browsers.</p>
+                    </article>
+                    <footer><p>Copyright Example News, all rights reserved, since the dawn of time itself.</p></footer>
+                </body>
+            </html>
+        "#;
+        let document = parser.parse(html).unwrap();
+        
+        let engine = AIEngine::new().await.unwrap();
+        let article = engine.extract_article(&document).await.unwrap();
+        
+        assert_eq!(article.title, "Why engines are written in Rust");
+        assert_eq!(article.byline.as_deref(), Some("Ada Lovelace"));
+        assert_eq!(article.paragraphs.len(), 3);
+        assert!(article.paragraphs[0].starts_with("Memory safety"));
+        assert!(!article.text().contains("Copyright"));
+        assert!(!article.text().contains("widgets"));
+        
+        // A page that is all navigation has no article
+        let nav_only = parser.parse(r#"
+            <html><body>
+                <ul><li><a href="/a">Section A</a></li><li><a href="/b">Section B</a></li></ul>
+            </body></html>
+        "#).unwrap();
+        assert!(engine.extract_article(&nav_only).await.is_err());
+    }
+    
+    #[test]
+    fn test_content_analysis_heuristics() {
+        let parser = crate::html::HTMLParser::new();
+        let analyzer = ContentAnalyzer::new();
+        
+        let accessible = parser.parse(r#"
+            <html>
+                <head><meta name="viewport" content="width=device-width, initial-scale=1"></head>
+                <body>
+                    <h1>Title</h1>
+                    <h2>Section</h2>
+                    <img src="a.png" alt="A chart">
+                    <img src="divider.png" alt="">
+                    <label for="email">Email</label><input id="email" type="email">
+                    <label>Name <input type="text"></label>
+                    <input type="submit">
+                </body>
+            </html>
+        "#).unwrap();
+        assert!(analyzer.is_mobile_friendly(&accessible));
+        assert_eq!(analyzer.calculate_accessibility_score(&accessible), 1.0);
+        assert_eq!(analyzer.calculate_ad_density(&accessible), 0.0);
+        
+        let inaccessible = parser.parse(r#"
+            <html>
+                <body>
+                    <h3>Skipped to h3</h3>
+                    <img src="a.png">
+                    <input type="text">
+                    <iframe src="https://ad.doubleclick.net/slot"></iframe>
+                    <div class="sponsored-links">Sponsored</div>
+                    <p>Content</p>
+                </body>
+            </html>
+        "#).unwrap();
+        assert!(!analyzer.is_mobile_friendly(&inaccessible));
+        assert_eq!(analyzer.calculate_accessibility_score(&inaccessible), 0.0);
+        
+        // 2 of the 6 body elements are ads: the ad-network iframe and the sponsored div
+        let density = analyzer.calculate_ad_density(&inaccessible);
+        assert!((density - 2.0 / 6.0).abs() < 1e-9);
+    }
+    
+    #[tokio::test]
+    async fn test_crawler_signals() {
+        let parser = crate::html::HTMLParser::new();
+        let analyzer = ContentAnalyzer::new();
+        let mut response = NetworkResponse {
+            url: "https://news.example/story?utm_source=feed".to_string(),
+            status: 200,
+            headers: HashMap::new(),
+            body: String::new(),
+            body_bytes: Vec::new(),
+            load_time: std::time::Duration::from_millis(100),
+            from_cache: false,
+            security_info: crate::networking::SecurityInfo {
+                https: true,
+                hsts: false,
+                csp: None,
+                x_frame_options: None,
+                x_content_type_options: None,
+                tls: None,
+            },
+        };
+        
+        let opted_out = parser.parse(r#"<html><head>
+            <meta name="Robots" content="NoIndex, nofollow">
+            <link rel="canonical" href="/story">
+        </head><body></body></html>"#).unwrap();
+        let analysis = analyzer.analyze(&opted_out, &response).await.unwrap();
+        assert!(analysis.noindex);
+        assert!(analysis.nofollow);
+        assert_eq!(analysis.canonical_url.as_deref(), Some("https://news.example/story"));
+        
+        // Headers count too, except directives scoped to another crawler
+        let plain = parser.parse("<html><head></head><body></body></html>").unwrap();
+        let analysis = analyzer.analyze(&plain, &response).await.unwrap();
+        assert!(!analysis.noindex && !analysis.nofollow);
+        assert_eq!(analysis.canonical_url, None);
+        
+        response.headers.insert("x-robots-tag".to_string(), "nofollow, max-snippet: 20, otherbot: noindex, noarchive".to_string());
+        let analysis = analyzer.analyze(&plain, &response).await.unwrap();
+        assert!(!analysis.noindex);
+        assert!(analysis.nofollow);
+        
+        response.headers.insert("x-robots-tag".to_string(), "none".to_string());
+        let analysis = analyzer.analyze(&plain, &response).await.unwrap();
+        assert!(analysis.noindex && analysis.nofollow);
+    }
+    
+    #[tokio::test]
+    async fn test_mixed_content_insight() {
+        let parser = crate::html::HTMLParser::new();
+        let document = parser.parse(r#"
+            <html>
+                <head>
+                    <link rel="stylesheet" href="https://cdn.example.com/site.css">
+                    <link rel="canonical" href="http://example.com/page">
+                    <script src="http://cdn.example.com/tracker.js"></script>
+                </head>
+                <body><img src="/logo.png"><img src="http://images.example.com/hero.jpg"></body>
+            </html>
+        "#).unwrap();
+        
+        let response = NetworkResponse {
+            url: "https://example.com/page".to_string(),
+            status: 200,
+            headers: HashMap::new(),
+            body: String::new(),
+            body_bytes: Vec::new(),
+            load_time: std::time::Duration::from_millis(100),
+            from_cache: false,
+            security_info: crate::networking::SecurityInfo {
+                https: true,
+                hsts: false,
+                csp: None,
+                x_frame_options: None,
+                x_content_type_options: None,
+                tls: None,
+            },
+        };
+        
+        let engine = AIEngine::new().await.unwrap();
+        let context = engine.analyze_page(&document, &response).await.unwrap();
+        
+        let insight = context.insights.iter()
+            .find(|i| i.title == "Mixed Content Detected")
+            .expect("mixed content insight");
+        assert_eq!(insight.insight_type, "security");
+        assert!(insight.actionable);
+        assert_eq!(insight.metadata["count"], "2");
+        
+        let resources: Vec<&str> = insight.metadata["resources"].lines().collect();
+        assert_eq!(resources.len(), 2);
+        assert!(resources.contains(&"http://cdn.example.com/tracker.js"));
+        assert!(resources.contains(&"http://images.example.com/hero.jpg"));
+    }
+    
+    struct UppercaseBackend;
+    
+    #[async_trait::async_trait]
+    impl TranslationBackend for UppercaseBackend {
+        async fn translate(&self, text: &str, _source_lang: &str, _target_lang: &str) -> Result<String> {
+            Ok(text.to_uppercase())
+        }
+        
+        fn max_chunk_chars(&self) -> usize {
+            20
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_translation_backend() {
+        let engine = AIEngine::new().await.unwrap();
+        
+        // Disabled by default
+        assert!(engine.translate_content("hello", "fr").await.is_err());
+        
+        engine.update_config(AIConfig {
+            enable_translation: true,
+            ..AIConfig::default()
+        }).await;
+        
+        // Enabled but no backend registered
+        assert!(engine.translate_content("hello", "fr").await.is_err());
+        
+        engine.set_translation_backend(Arc::new(UppercaseBackend)).await;
+        let translated = engine.translate_content("hello there\n\ngeneral kenobi", "fr").await.unwrap();
+        assert_eq!(translated, "HELLO THERE\n\nGENERAL KENOBI");
+        
+        // A paragraph too long for one request comes back as one paragraph
+        let translated = engine.translate_content("Hello there. General Kenobi. You are a bold one.\n\nKill him!", "fr").await.unwrap();
+        assert_eq!(translated, "HELLO THERE. GENERAL KENOBI. YOU ARE A BOLD ONE.\n\nKILL HIM!");
+    }
+    
+    #[tokio::test]
+    async fn test_update_config_through_shared_engine() {
+        let engine = Arc::new(AIEngine::new().await.unwrap());
+        let handle = engine.clone();
+        
+        let updater = tokio::spawn(async move {
+            handle.update_config(AIConfig {
+                enable_translation: true,
+                ..AIConfig::default()
+            }).await;
+        });
+        updater.await.unwrap();
+        
+        // The update is visible through every clone of the Arc
+        assert!(engine.config().await.enable_translation);
+        engine.set_translation_backend(Arc::new(UppercaseBackend)).await;
+        assert_eq!(engine.translate_content("hi", "fr").await.unwrap(), "HI");
+    }
+    
+    #[test]
+    fn test_chunk_paragraphs() {
+        let chunks = chunk_paragraphs("one\n\ntwo\n\nthree", 10);
+        assert_eq!(chunks, vec![
+            TextChunk { text: "one\n\ntwo".to_string(), first_paragraph: 0, last_paragraph: 1 },
+            TextChunk { text: "three".to_string(), first_paragraph: 2, last_paragraph: 2 },
+        ]);
+        
+        // Both halves of a split paragraph point back at it
+        let chunks = chunk_paragraphs("First sentence. Second sentence.", 16);
+        assert_eq!(chunks, vec![
+            TextChunk { text: "First sentence.".to_string(), first_paragraph: 0, last_paragraph: 0 },
+            TextChunk { text: "Second sentence.".to_string(), first_paragraph: 0, last_paragraph: 0 },
+        ]);
+        let translated = chunks.iter().map(|chunk| chunk.text.clone()).collect();
+        assert_eq!(join_chunks(&chunks, translated), "First sentence. Second sentence.");
+    }
+    
     #[tokio::test]
     async fn test_content_summarization() {
         let processor = TextProcessor::new();
@@ -779,4 +2437,303 @@ mod tests {
         assert!(summary.len() <= 50);
         assert!(!summary.is_empty());
     }
-}
\ No newline at end of file
+    
+    #[tokio::test]
+    async fn test_summarization_prefers_key_sentences() {
+        let processor = TextProcessor::new();
+        let text = "Welcome to our site. Thanks for visiting today. Please enjoy your stay. \
+            Rust engine performance: the Rust engine renders Rust pages with engine speed. Have a nice day.";
+        
+        let summary = processor.summarize(text, 80).await.unwrap();
+        assert_eq!(summary, "Rust engine performance: the Rust engine renders Rust pages with engine speed");
+    }
+
+    struct FixedBackend;
+    
+    #[async_trait::async_trait]
+    impl InferenceBackend for FixedBackend {
+        async fn embed(&self, _text: &str) -> Result<Vec<f32>> {
+            Ok(vec![1.0, 0.0])
+        }
+        
+        async fn classify(&self, _text: &str, labels: &[&str]) -> Result<Vec<(String, f64)>> {
+            Ok(vec![(labels[2].to_string(), 0.9), (labels[0].to_string(), 0.1)])
+        }
+        
+        async fn summarize(&self, _text: &str, _max_length: usize) -> Result<String> {
+            Ok("model summary".to_string())
+        }
+        
+        async fn sentiment(&self, _text: &str) -> Result<f64> {
+            Ok(-0.9)
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_inference_backend_feeds_analysis() {
+        let parser = crate::html::HTMLParser::new();
+        let mut document = parser.parse("<html><body><p>A neutral page about the weather.</p></body></html>").unwrap();
+        document.url = "https://example.com/weather".to_string();
+        let response = NetworkResponse {
+            url: document.url.clone(),
+            status: 200,
+            headers: HashMap::new(),
+            body: String::new(),
+            body_bytes: Vec::new(),
+            load_time: std::time::Duration::from_millis(100),
+            from_cache: false,
+            security_info: crate::networking::SecurityInfo {
+                https: true,
+                hsts: false,
+                csp: None,
+                x_frame_options: None,
+                x_content_type_options: None,
+                tls: None,
+            },
+        };
+        
+        let engine = AIEngine::with_backend(Arc::new(FixedBackend)).await.unwrap();
+        let context = engine.analyze_page(&document, &response).await.unwrap();
+        
+        let text_analysis = context.text_analysis.as_ref().unwrap();
+        assert_eq!(text_analysis.sentiment_score, -0.9);
+        assert_eq!(text_analysis.topics, vec!["Health".to_string()]);
+        let insight = context.insights.iter()
+            .find(|i| i.insight_type == "sentiment")
+            .expect("sentiment insight");
+        assert_eq!(insight.metadata["score"], "-0.90");
+        
+        assert_eq!(engine.summarize_content("Anything at all.", 100).await.unwrap(), "model summary");
+        assert_eq!(engine.embed_text("Anything").await.unwrap(), vec![1.0, 0.0]);
+    }
+
+    #[tokio::test]
+    async fn test_keywords_rank_distinctive_terms_by_tf_idf() {
+        async fn keywords(engine: &AIEngine, url: &str, text: &str) -> Vec<String> {
+            let parser = crate::html::HTMLParser::new();
+            let mut document = parser.parse(&format!("<html><body><p>{}</p></body></html>", text)).unwrap();
+            document.url = url.to_string();
+            let response = NetworkResponse {
+                url: url.to_string(),
+                status: 200,
+                headers: HashMap::new(),
+                body: String::new(),
+                body_bytes: Vec::new(),
+                load_time: std::time::Duration::from_millis(100),
+                from_cache: false,
+                security_info: crate::networking::SecurityInfo {
+                    https: true,
+                    hsts: false,
+                    csp: None,
+                    x_frame_options: None,
+                    x_content_type_options: None,
+                    tls: None,
+                },
+            };
+            engine.analyze_page(&document, &response).await.unwrap().text_analysis.unwrap().keywords
+        }
+        
+        let engine = AIEngine::new().await.unwrap();
+        
+        // A lone page has nothing to compare against, so raw counts decide
+        let first = keywords(&engine, "https://a.example/", "Browser browsers browser guide about gardens").await;
+        assert_eq!(first[0], "browser");
+        
+        keywords(&engine, "https://b.example/", "The browser renders pages quickly").await;
+        keywords(&engine, "https://c.example/", "Every browser supports tabs").await;
+        
+        let last = keywords(
+            &engine,
+            "https://d.example/",
+            "Browser browser browser news: quantum computing and quantum sensors",
+        ).await;
+        let rank = |term: &str| last.iter().position(|k| k == term).unwrap();
+        assert!(rank("quantum") < rank("browser"), "{:?}", last);
+    }
+
+    #[tokio::test]
+    async fn test_form_fill_uses_stored_profile() {
+        let mut storage = StorageEngine::open(":memory:").await.unwrap();
+        let engine = AIEngine::new().await.unwrap();
+        let form: HashMap<String, String> = [
+            ("email", "text"),
+            ("full_name", "text"),
+            ("city", "text"),
+            ("phone", "tel"),
+            ("password", "password"),
+            ("pin", "password"),
+        ].iter().map(|(name, kind)| (name.to_string(), kind.to_string())).collect();
+        
+        // No profile yet: placeholders, but never for passwords
+        let suggestions = engine.suggest_form_fill(&form, &storage).await.unwrap();
+        assert_eq!(suggestions["email"], "user@example.com");
+        assert!(!suggestions.contains_key("password"));
+        assert!(!suggestions.contains_key("pin"));
+        
+        storage.set_autofill_value("email", "ada@lovelace.org").await.unwrap();
+        storage.set_autofill_value("given_name", "Ada").await.unwrap();
+        storage.set_autofill_value("family_name", "Lovelace").await.unwrap();
+        storage.set_autofill_value("city", "London").await.unwrap();
+        
+        // The profile is only read once the user opts in
+        let suggestions = engine.suggest_form_fill(&form, &storage).await.unwrap();
+        assert_eq!(suggestions["email"], "user@example.com");
+        
+        engine.update_config(AIConfig {
+            use_autofill_profile: true,
+            ..AIConfig::default()
+        }).await;
+        let suggestions = engine.suggest_form_fill(&form, &storage).await.unwrap();
+        assert_eq!(suggestions["email"], "ada@lovelace.org");
+        assert_eq!(suggestions["full_name"], "Ada Lovelace");
+        assert_eq!(suggestions["city"], "London");
+        // Missing categories stay empty rather than mixing in fake data
+        assert!(!suggestions.contains_key("phone"));
+        assert!(!suggestions.contains_key("password"));
+    }
+
+    #[tokio::test]
+    async fn test_diff_pages_pinpoints_changed_paragraph() {
+        let engine = AIEngine::new().await.unwrap();
+        let parser = crate::html::HTMLParser::new();
+        let response = NetworkResponse {
+            url: "https://shop.example/item".to_string(),
+            status: 200,
+            headers: HashMap::new(),
+            body: String::new(),
+            body_bytes: Vec::new(),
+            load_time: std::time::Duration::from_millis(100),
+            from_cache: false,
+            security_info: crate::networking::SecurityInfo {
+                https: true,
+                hsts: false,
+                csp: None,
+                x_frame_options: None,
+                x_content_type_options: None,
+                tls: None,
+            },
+        };
+        let page = |price: &str, extra_form: &str| {
+            let mut document = parser.parse(&format!(
+                "<html><body><h1>Walnut desk</h1><p>Solid walnut, hand finished.</p>\
+                 <p>Price: {}</p><p>Ships in two weeks.</p>{}</body></html>",
+                price, extra_form,
+            )).unwrap();
+            document.url = "https://shop.example/item".to_string();
+            document
+        };
+        
+        let old = engine.analyze_page(&page("$450", ""), &response).await.unwrap();
+        let unchanged = engine.analyze_page(&page("$450", ""), &response).await.unwrap();
+        assert!(AIEngine::diff_pages(&old, &unchanged).is_empty());
+        
+        let new = engine.analyze_page(&page("$399", "<form></form>"), &response).await.unwrap();
+        let diff = AIEngine::diff_pages(&old, &new);
+        
+        assert_eq!(diff.block_changes, vec![BlockChange::Changed {
+            old_index: 2,
+            new_index: 2,
+            old_text: "Price: $450".to_string(),
+            new_text: "Price: $399".to_string(),
+        }]);
+        assert_eq!(diff.structural_changes, vec![StructuralChange {
+            element: "form".to_string(),
+            old_count: 0,
+            new_count: 1,
+        }]);
+    }
+    
+    #[test]
+    fn test_diff_pages_reports_moves() {
+        let mut old = PageContext::new("https://example.com/".to_string());
+        old.text_blocks = vec!["intro".to_string(), "alpha".to_string(), "beta".to_string(), "outro".to_string()];
+        let mut new = old.clone();
+        new.text_blocks = vec!["beta".to_string(), "intro".to_string(), "alpha".to_string(), "outro".to_string()];
+        
+        let diff = AIEngine::diff_pages(&old, &new);
+        assert_eq!(diff.block_changes, vec![BlockChange::Moved {
+            old_index: 2,
+            new_index: 0,
+            text: "beta".to_string(),
+        }]);
+    }
+
+    #[tokio::test]
+    async fn test_text_extraction_skips_hidden_and_script_text() {
+        let document = crate::html::HTMLParser::new().parse(r#"
+            <html><head><title>Extraction</title><style>p { color: red }</style></head>
+            <body>
+                <h1>Heading</h1>
+                <p>First   paragraph
+                   wraps.</p>
+                <script>var secret = "do not read";</script>
+                <div hidden>Hidden by attribute</div>
+                <div style="display: none">Hidden by style</div>
+                <noscript>Enable JavaScript</noscript>
+                <ul><li>One</li><li>Two</li></ul>
+                <pre>keep   this
+  layout</pre>
+            </body></html>
+        "#).unwrap();
+        
+        let text = AIEngine::new().await.unwrap().extract_text_content(&document).await.unwrap();
+        assert_eq!(text, "Extraction\nHeading\nFirst paragraph wraps.\nOne\nTwo\nkeep   this\n  layout");
+    }
+
+    #[tokio::test]
+    async fn test_analysis_cache_ttl() {
+        let parser = crate::html::HTMLParser::new();
+        let mut document = parser.parse("<html><body><p>Live scores, updated every minute.</p></body></html>").unwrap();
+        document.url = "https://scores.example/".to_string();
+        let mut response = NetworkResponse {
+            url: document.url.clone(),
+            status: 200,
+            headers: HashMap::new(),
+            body: String::new(),
+            body_bytes: Vec::new(),
+            load_time: std::time::Duration::from_millis(10),
+            from_cache: false,
+            security_info: crate::networking::SecurityInfo {
+                https: true,
+                hsts: false,
+                csp: None,
+                x_frame_options: None,
+                x_content_type_options: None,
+                tls: None,
+            },
+        };
+        let engine_with = |config: AIConfig| async move {
+            let engine = AIEngine::new().await.unwrap();
+            engine.update_config(config).await;
+            engine
+        };
+        
+        // A short TTL re-analyzes once it has passed
+        let engine = engine_with(AIConfig { analysis_ttl: std::time::Duration::from_millis(50), ..AIConfig::default() }).await;
+        let first = engine.analyze_page(&document, &response).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let second = engine.analyze_page(&document, &response).await.unwrap();
+        assert!(second.analyzed_at > first.analyzed_at);
+        
+        // A long one keeps serving the cached analysis
+        let engine = engine_with(AIConfig { analysis_ttl: std::time::Duration::from_secs(3600), ..AIConfig::default() }).await;
+        let first = engine.analyze_page(&document, &response).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let second = engine.analyze_page(&document, &response).await.unwrap();
+        assert_eq!(second.analyzed_at, first.analyzed_at);
+        
+        // The page's own headers can override the configured TTL
+        let engine = engine_with(AIConfig {
+            analysis_ttl: std::time::Duration::from_secs(3600),
+            ttl_from_cache_headers: true,
+            ..AIConfig::default()
+        }).await;
+        response.headers.insert("Cache-Control".to_string(), "public, no-cache".to_string());
+        let first = engine.analyze_page(&document, &response).await.unwrap();
+        let second = engine.analyze_page(&document, &response).await.unwrap();
+        assert!(second.analyzed_at > first.analyzed_at);
+        
+        assert_eq!(cache_control_lifetime("public, max-age=\"60\""), Some(std::time::Duration::from_secs(60)));
+        assert_eq!(cache_control_lifetime("public"), None);
+    }
+}
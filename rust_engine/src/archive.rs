@@ -0,0 +1,238 @@
+//! Single-file HTML archives of pages, with subresources inlined for offline reading
+
+use base64::Engine;
+use url::Url;
+use crate::core::{ElementId, EngineError, Result};
+use crate::html::Document;
+use crate::networking::NetworkResponse;
+use crate::subresources::{Subresource, SubresourceKind};
+
+/// Default cap on an archive's size
+pub const MAX_ARCHIVE_BYTES: usize = 32 * 1024 * 1024;
+
+/// What goes into an archive
+#[derive(Debug, Clone)]
+pub struct ArchiveOptions {
+    /// Inline external scripts and keep inline ones; otherwise every script is left out
+    pub include_scripts: bool,
+    /// Largest archive to produce, in bytes. Resources that would go over stay as links.
+    pub max_bytes: usize,
+}
+
+impl Default for ArchiveOptions {
+    fn default() -> Self {
+        Self {
+            include_scripts: false,
+            max_bytes: MAX_ARCHIVE_BYTES,
+        }
+    }
+}
+
+/// Serialize `document` with its fetched subresources inlined: stylesheets become `<style>`
+/// elements, images `data:` URIs, and scripts (when included) inline `<script>`s.
+/// A `<base>` keeps links and anything left un-inlined pointing at the original page.
+pub fn build_archive(
+    document: &Document,
+    fetched: Vec<(Subresource, Result<NetworkResponse>)>,
+    options: &ArchiveOptions,
+) -> Result<String> {
+    let mut archive = document.clone();
+    if !options.include_scripts {
+        for script in archive.get_elements_by_tag_name("script") {
+            if let Some(parent) = script.parent {
+                archive.remove_child(parent, script.id)?;
+            }
+        }
+    }
+    add_base(&mut archive)?;
+    
+    let mut size = archive.to_html().len();
+    if size > options.max_bytes {
+        return Err(EngineError::InternalError(format!(
+            "Page is larger than the {} byte archive limit",
+            options.max_bytes
+        )));
+    }
+    
+    for (resource, result) in fetched {
+        if resource.kind == SubresourceKind::Script && !options.include_scripts {
+            continue;
+        }
+        let response = match result {
+            Ok(response) if (200..300).contains(&response.status) => response,
+            Ok(response) => {
+                log::warn!("Not archiving {}: HTTP {}", resource.url, response.status);
+                continue;
+            }
+            Err(e) => {
+                log::warn!("Not archiving {}: {:?}", resource.url, e);
+                continue;
+            }
+        };
+        
+        let inlined = match resource.kind {
+            SubresourceKind::Stylesheet => escape_end_tag(&absolutize_css_urls(&response.body, &resource.url), "style"),
+            SubresourceKind::Script => escape_end_tag(&response.body, "script"),
+            SubresourceKind::Image | SubresourceKind::Poster => data_url(&response),
+        };
+        if size + inlined.len() > options.max_bytes {
+            log::info!("Archive size limit reached, linking {} instead", resource.url);
+            continue;
+        }
+        size += inlined.len();
+        
+        let element_id = resource.element_id;
+        match resource.kind {
+            SubresourceKind::Stylesheet => inline_stylesheet(&mut archive, element_id, &inlined)?,
+            SubresourceKind::Script => {
+                archive.remove_attribute(element_id, "src")?;
+                archive.set_text_content(element_id, &inlined)?;
+            }
+            SubresourceKind::Image => {
+                archive.set_attribute(element_id, "src", &inlined)?;
+                archive.remove_attribute(element_id, "srcset")?;
+            }
+            SubresourceKind::Poster => archive.set_attribute(element_id, "poster", &inlined)?,
+        }
+    }
+    
+    Ok(archive.to_html())
+}
+
+/// Point relative URLs at the page's own address, unless it already has a `<base>`
+fn add_base(document: &mut Document) -> Result<()> {
+    let has_base = document.descendants(&document.root).iter().any(|element| element.tag_name.eq_ignore_ascii_case("base"));
+    if has_base || document.url.is_empty() {
+        return Ok(());
+    }
+    
+    let parent = document.head.as_ref().map_or(document.root.id, |head| head.id);
+    let first_child = document.elements.get(&parent).and_then(|parent| parent.children.first().copied());
+    let base = document.create_element("base");
+    let url = document.url.clone();
+    document.set_attribute(base, "href", &url)?;
+    document.insert_before(parent, base, first_child)
+}
+
+/// Swap a `<link rel="stylesheet">` for a `<style>` holding the stylesheet's text
+fn inline_stylesheet(document: &mut Document, link_id: ElementId, css: &str) -> Result<()> {
+    let Some(link) = document.elements.get(&link_id).cloned() else {
+        return Ok(());
+    };
+    let Some(parent) = link.parent else {
+        return Ok(());
+    };
+    
+    let style = document.create_element("style");
+    document.set_text_content(style, css)?;
+    if let Some(media) = link.get_attribute("media") {
+        document.set_attribute(style, "media", media)?;
+    }
+    document.insert_before(parent, style, Some(link_id))?;
+    document.remove_child(parent, link_id)
+}
+
+/// Resolve `url()` and `@import` references against the stylesheet's own URL, which the
+/// page's `<base>` no longer matches once the stylesheet is inlined
+fn absolutize_css_urls(css: &str, base: &Url) -> String {
+    let lower = css.to_ascii_lowercase();
+    let mut rewritten = String::with_capacity(css.len());
+    let mut last = 0;
+    let mut position = 0;
+    
+    while position < css.len() {
+        let (start, open) = match (lower[position..].find("url("), lower[position..].find("@import")) {
+            (Some(url), Some(import)) if import < url => (position + import, "@import".len()),
+            (Some(url), _) => (position + url, "url(".len()),
+            (None, Some(import)) => (position + import, "@import".len()),
+            (None, None) => break,
+        };
+        let value_start = start + open;
+        let leading = css[value_start..].len() - css[value_start..].trim_start().len();
+        let value_start = value_start + leading;
+        let rest = &css[value_start..];
+        
+        // `@import url(...)` is handled when the scan reaches its `url(`
+        let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'');
+        let value_end = match quote {
+            Some(quote) => rest[1..].find(quote).map(|end| value_start + 1 + end + 1),
+            None if open == "url(".len() => rest.find(')').map(|end| value_start + rest[..end].trim_end().len()),
+            None => None,
+        };
+        let Some(value_end) = value_end else {
+            position = value_start;
+            continue;
+        };
+        
+        let reference = css[value_start..value_end].trim_matches(|c| c == '"' || c == '\'').trim();
+        let resolved = (!reference.is_empty() && !reference.starts_with('#') && !reference.to_ascii_lowercase().starts_with("data:"))
+            .then(|| base.join(reference).ok())
+            .flatten();
+        if let Some(resolved) = resolved {
+            rewritten.push_str(&css[last..value_start]);
+            rewritten.push('"');
+            rewritten.push_str(&resolved.as_str().replace('"', "%22"));
+            rewritten.push('"');
+            last = value_end;
+        }
+        position = value_end;
+    }
+    
+    rewritten.push_str(&css[last..]);
+    rewritten
+}
+
+/// A `data:` URL carrying the response body, typed by its `Content-Type`
+fn data_url(response: &NetworkResponse) -> String {
+    let mime_type = response.content_type()
+        .and_then(|content_type| content_type.split(';').next())
+        .map(str::trim)
+        .filter(|mime_type| !mime_type.is_empty())
+        .unwrap_or("application/octet-stream");
+    format!(
+        "data:{};base64,{}",
+        mime_type,
+        base64::engine::general_purpose::STANDARD.encode(&response.body_bytes)
+    )
+}
+
+/// Escape `</tag` in raw text so it can't close the element early; `<\/` means the same
+/// thing inside CSS and JavaScript strings
+fn escape_end_tag(text: &str, tag: &str) -> String {
+    let closing = format!("</{}", tag);
+    let lower = text.to_ascii_lowercase();
+    let mut escaped = String::with_capacity(text.len());
+    let mut last = 0;
+    for (index, _) in lower.match_indices(&closing) {
+        escaped.push_str(&text[last..=index]);
+        escaped.push('\\');
+        last = index + 1;
+    }
+    escaped.push_str(&text[last..]);
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_inlined_stylesheet_urls_resolve_against_stylesheet() {
+        let base = Url::parse("https://cdn.example.com/css/site.css").unwrap();
+        let css = "@import 'print.css';\n@import url(\"/reset.css\");\n\
+                   body { background: URL( ../img/bg.png ) }\n\
+                   .icon { mask: url(data:image/svg+xml;base64,AAAA), url(#clip) }";
+        assert_eq!(
+            absolutize_css_urls(css, &base),
+            "@import \"https://cdn.example.com/css/print.css\";\n@import url(\"https://cdn.example.com/reset.css\");\n\
+             body { background: URL( \"https://cdn.example.com/img/bg.png\" ) }\n\
+             .icon { mask: url(data:image/svg+xml;base64,AAAA), url(#clip) }",
+        );
+    }
+    
+    #[test]
+    fn test_escape_end_tag() {
+        assert_eq!(escape_end_tag("a { content: '</STYLE>' }", "style"), "a { content: '<\\/STYLE>' }");
+        assert_eq!(escape_end_tag("if (a </b) {}", "script"), "if (a </b) {}");
+    }
+}
@@ -1,7 +1,11 @@
 //! Core types and structures for the Titan Engine
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
 use std::sync::Arc;
+use futures::FutureExt;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -16,7 +20,7 @@ impl ElementId {
 }
 
 /// Represents a loaded web page with all its components
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PageHandle {
     pub id: Uuid,
     pub url: String,
@@ -25,6 +29,7 @@ pub struct PageHandle {
     pub render_tree: Arc<crate::rendering::RenderTree>,
     pub ai_context: Arc<crate::ai::PageContext>,
     pub metadata: PageMetadata,
+    pub frames: Vec<FrameHandle>,
 }
 
 impl PageHandle {
@@ -34,6 +39,12 @@ impl PageHandle {
         render_tree: crate::rendering::RenderTree,
         ai_context: crate::ai::PageContext,
     ) -> Self {
+        let metadata = PageMetadata {
+            title: document.title.clone(),
+            structured_data: document.structured_data.clone(),
+            ..PageMetadata::default()
+        };
+        
         Self {
             id: Uuid::new_v4(),
             url: document.url.clone(),
@@ -41,11 +52,28 @@ impl PageHandle {
             layout_tree: Arc::new(layout_tree),
             render_tree: Arc::new(render_tree),
             ai_context: Arc::new(ai_context),
-            metadata: PageMetadata::default(),
+            metadata,
+            frames: Vec::new(),
         }
     }
 }
 
+/// A nested browsing context loaded from an `<iframe>`
+#[derive(Debug, Clone)]
+pub struct FrameHandle {
+    /// The `<iframe>` element in the parent document
+    pub element_id: ElementId,
+    pub url: String,
+    /// Serialized origin, or "null" for an opaque (sandboxed) origin
+    pub origin: String,
+    pub sandbox: Option<crate::security::SandboxFlags>,
+    pub document: Arc<crate::html::Document>,
+    pub layout_tree: Arc<crate::layout::LayoutTree>,
+    /// Where the frame's content is drawn in the parent
+    pub viewport: Rect,
+    pub scripts_executed: bool,
+}
+
 /// Metadata about a loaded page
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct PageMetadata {
@@ -57,6 +85,7 @@ pub struct PageMetadata {
     pub resource_count: u32,
     pub security_score: f32,
     pub ai_insights: Vec<String>,
+    pub structured_data: Vec<serde_json::Value>,
 }
 
 /// Represents a point in 2D space
@@ -115,6 +144,11 @@ impl Rect {
         }
     }
     
+    /// The same rect moved by `dx`, `dy`
+    pub fn translate(&self, dx: f32, dy: f32) -> Self {
+        Self::new(self.origin.x + dx, self.origin.y + dy, self.size.width, self.size.height)
+    }
+    
     pub fn contains_point(&self, point: Point) -> bool {
         point.x >= self.origin.x
             && point.x <= self.origin.x + self.size.width
@@ -198,6 +232,30 @@ pub enum BrowserEvent {
         element_id: Option<ElementId>,
         position: Point,
     },
+    
+    /// Navigation hit its budget; remaining subresource fetches were skipped
+    NavigationBudgetExceeded {
+        url: String,
+        limit: BudgetLimit,
+        resources_loaded: usize,
+        bytes_loaded: u64,
+    },
+    
+    /// Download made progress or changed state
+    DownloadProgress {
+        id: String,
+        status: crate::storage::DownloadStatus,
+        downloaded_bytes: u64,
+        total_bytes: Option<u64>,
+    },
+}
+
+/// Which part of a navigation budget ran out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BudgetLimit {
+    Bytes,
+    Time,
+    Resources,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -237,6 +295,28 @@ pub enum InteractionType {
     Touch,
 }
 
+impl InteractionType {
+    /// DOM event type dispatched for this interaction
+    pub fn dom_event_type(&self) -> &'static str {
+        match self {
+            InteractionType::Click => "click",
+            InteractionType::DoubleClick => "dblclick",
+            InteractionType::RightClick => "contextmenu",
+            InteractionType::Hover => "mouseover",
+            InteractionType::KeyPress => "keydown",
+            InteractionType::Scroll => "scroll",
+            InteractionType::Touch => "touchstart",
+        }
+    }
+}
+
+/// Where an interaction happened: a point for pointer input, or the key pressed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum InteractionInput {
+    Pointer(Point),
+    Key(String),
+}
+
 /// Performance metrics for monitoring
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct PerformanceMetrics {
@@ -251,6 +331,21 @@ pub struct PerformanceMetrics {
     pub paint_time_ms: u64,
 }
 
+impl PerformanceMetrics {
+    /// Fold in another reporter's numbers; reporters own disjoint fields, so this sums them
+    pub fn merge(&mut self, other: &PerformanceMetrics) {
+        self.memory_usage_mb += other.memory_usage_mb;
+        self.cpu_usage_percent += other.cpu_usage_percent;
+        self.gpu_usage_percent += other.gpu_usage_percent;
+        self.network_bytes_sent += other.network_bytes_sent;
+        self.network_bytes_received += other.network_bytes_received;
+        self.render_fps += other.render_fps;
+        self.javascript_execution_time_ms += other.javascript_execution_time_ms;
+        self.layout_time_ms += other.layout_time_ms;
+        self.paint_time_ms += other.paint_time_ms;
+    }
+}
+
 /// Configuration for engine behavior
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EngineSettings {
@@ -303,14 +398,22 @@ pub enum EngineError {
     #[error("HTML parsing error: {0}")]
     HtmlParseError(String),
     
+    #[error("DOM error: {0}")]
+    DomError(String),
+    
     #[error("CSS parsing error: {0}")]
     CssParseError(String),
     
     #[error("JavaScript execution error: {0}")]
     JavaScriptError(String),
     
-    #[error("Network error: {0}")]
-    NetworkError(String),
+    #[error("Network error: {message}")]
+    NetworkError {
+        kind: NetworkErrorKind,
+        /// The URL being fetched, when the failure concerns one
+        url: Option<String>,
+        message: String,
+    },
     
     #[error("Rendering error: {0}")]
     RenderingError(String),
@@ -332,10 +435,214 @@ pub enum EngineError {
     
     #[error("Internal error: {0}")]
     InternalError(String),
+    
+    #[error("Load cancelled: {0}")]
+    Cancelled(String),
+    
+    #[error("Disallowed by robots.txt: {0}")]
+    RobotsDisallowed(String),
+    
+    #[error("Certificate for {host} does not match its pins")]
+    CertificatePinMismatch {
+        host: String,
+        url: String,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, EngineError>;
 
+impl EngineError {
+    /// Network error of the given kind, for `url` when known
+    pub fn network(kind: NetworkErrorKind, url: Option<&str>, message: impl Into<String>) -> Self {
+        EngineError::NetworkError {
+            kind,
+            url: url.map(str::to_string),
+            message: message.into(),
+        }
+    }
+    
+    /// The kind of network failure, if this is a network error
+    pub fn network_kind(&self) -> Option<NetworkErrorKind> {
+        match self {
+            EngineError::NetworkError { kind, .. } => Some(*kind),
+            _ => None,
+        }
+    }
+    
+    /// The URL the error concerns, when known
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            EngineError::NetworkError { url, .. } => url.as_deref(),
+            EngineError::RobotsDisallowed(url) => Some(url),
+            EngineError::CertificatePinMismatch { url, .. } => Some(url),
+            _ => None,
+        }
+    }
+    
+    /// Something the user can do about the error, suitable for showing next to it
+    pub fn recovery_hint(&self) -> Option<&'static str> {
+        match self {
+            EngineError::NetworkError { kind, .. } => kind.recovery_hint(),
+            EngineError::SecurityError(_) => Some("The request was blocked by the browser's security settings"),
+            EngineError::StorageError(_) => Some("Check that the profile directory is writable and the disk is not full"),
+            EngineError::RobotsDisallowed(_) => Some("The site asks automated clients not to fetch this page"),
+            EngineError::CertificatePinMismatch { .. } => Some("The site presented an unexpected certificate; the connection may be intercepted, or the pins need updating"),
+            _ => None,
+        }
+    }
+}
+
+/// What went wrong with a network request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NetworkErrorKind {
+    /// The hostname could not be resolved
+    Dns,
+    /// The TLS handshake failed, e.g. an untrusted or expired certificate
+    Tls,
+    /// No response in time
+    Timeout,
+    /// The connection was refused, reset, or could not be established
+    Connect,
+    /// The server answered with this unsuccessful HTTP status
+    Status(u16),
+    /// The URL could not be parsed or is not usable for this request
+    InvalidUrl,
+    /// Offline mode with no cached copy
+    Offline,
+    /// Anything else, such as a malformed or oversized body
+    Other,
+}
+
+impl NetworkErrorKind {
+    /// Short machine-readable name, e.g. for FFI payloads
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NetworkErrorKind::Dns => "dns",
+            NetworkErrorKind::Tls => "tls",
+            NetworkErrorKind::Timeout => "timeout",
+            NetworkErrorKind::Connect => "connect",
+            NetworkErrorKind::Status(_) => "status",
+            NetworkErrorKind::InvalidUrl => "invalid_url",
+            NetworkErrorKind::Offline => "offline",
+            NetworkErrorKind::Other => "other",
+        }
+    }
+    
+    pub fn recovery_hint(&self) -> Option<&'static str> {
+        match self {
+            NetworkErrorKind::Dns => Some("Check the address for typos and that you are connected to the internet"),
+            NetworkErrorKind::Tls => Some("The site's certificate could not be verified; check the system clock or contact the site owner"),
+            NetworkErrorKind::Timeout => Some("The server took too long to respond; try again later"),
+            NetworkErrorKind::Connect => Some("The server could not be reached; it may be down or blocked by a firewall"),
+            NetworkErrorKind::Status(404 | 410) => Some("The page may have moved or been removed"),
+            NetworkErrorKind::Status(401 | 403) => Some("You may need to sign in to view this page"),
+            NetworkErrorKind::Status(429) => Some("The site is limiting requests; wait a moment and try again"),
+            NetworkErrorKind::Status(status) if *status >= 500 => Some("The site is having problems; try again later"),
+            NetworkErrorKind::Status(_) => None,
+            NetworkErrorKind::InvalidUrl => Some("Check that the address is a complete http or https URL"),
+            NetworkErrorKind::Offline => Some("Reconnect to load this page; only pages saved while online are available offline"),
+            NetworkErrorKind::Other => None,
+        }
+    }
+}
+
+/// Engine subsystems that can be isolated from each other's panics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Subsystem {
+    Html,
+    Css,
+    Layout,
+    JavaScript,
+    Rendering,
+    Ai,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 6] = [
+        Subsystem::Html,
+        Subsystem::Css,
+        Subsystem::Layout,
+        Subsystem::JavaScript,
+        Subsystem::Rendering,
+        Subsystem::Ai,
+    ];
+    
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Subsystem::Html => "html",
+            Subsystem::Css => "css",
+            Subsystem::Layout => "layout",
+            Subsystem::JavaScript => "javascript",
+            Subsystem::Rendering => "rendering",
+            Subsystem::Ai => "ai",
+        }
+    }
+    
+    /// Whether panics here can be caught. Script execution calls back into Rust from V8
+    /// frames, which a panic must never unwind through, so JavaScript is never isolated.
+    pub fn can_isolate(&self) -> bool {
+        !matches!(self, Subsystem::JavaScript)
+    }
+}
+
+impl fmt::Display for Subsystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Which subsystems have panics caught and reported as `InternalError` rather than unwinding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanicIsolation {
+    isolated: HashSet<Subsystem>,
+}
+
+impl PanicIsolation {
+    /// Isolate every subsystem that can be isolated
+    pub fn all() -> Self {
+        Self { isolated: Subsystem::ALL.into_iter().filter(Subsystem::can_isolate).collect() }
+    }
+    
+    /// Let panics propagate everywhere, e.g. to get backtraces while debugging
+    pub fn none() -> Self {
+        Self { isolated: HashSet::new() }
+    }
+    
+    /// Isolate or stop isolating a subsystem; asking to isolate JavaScript has no effect
+    pub fn set(&mut self, subsystem: Subsystem, isolated: bool) {
+        if isolated && subsystem.can_isolate() {
+            self.isolated.insert(subsystem);
+        } else {
+            self.isolated.remove(&subsystem);
+        }
+    }
+    
+    pub fn is_isolated(&self, subsystem: Subsystem) -> bool {
+        self.isolated.contains(&subsystem)
+    }
+}
+
+impl Default for PanicIsolation {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+/// Run a subsystem call, turning a panic into `EngineError::InternalError`.
+/// Needs `panic = "unwind"`, which the release profile keeps for this reason.
+pub async fn catch_panic<T>(subsystem: Subsystem, future: impl Future<Output = Result<T>>) -> Result<T> {
+    match AssertUnwindSafe(future).catch_unwind().await {
+        Ok(result) => result,
+        Err(payload) => {
+            let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            log::error!("{} subsystem panicked: {}", subsystem, message);
+            Err(EngineError::InternalError(format!("{} subsystem panicked: {}", subsystem, message)))
+        }
+    }
+}
+
 /// Trait for components that can be shut down gracefully
 #[async_trait::async_trait]
 pub trait Shutdown {
@@ -343,8 +650,10 @@ pub trait Shutdown {
 }
 
 /// Trait for components that can report performance metrics
+/// Each subsystem fills in only the fields it owns; `PerformanceMetrics::merge` combines them.
+#[async_trait::async_trait]
 pub trait MetricsReporter {
-    fn get_metrics(&self) -> PerformanceMetrics;
+    async fn get_metrics(&self) -> PerformanceMetrics;
 }
 
 /// Trait for components that can handle events
@@ -353,6 +662,9 @@ pub trait EventHandler {
     async fn handle_event(&self, event: BrowserEvent) -> Result<()>;
 }
 
+/// Handlers shared between the engine and the components that emit events on its behalf
+pub type EventHandlers = Arc<tokio::sync::RwLock<Vec<Arc<dyn EventHandler + Send + Sync>>>>;
+
 #[cfg(test)]
 mod tests {
     use super::*;
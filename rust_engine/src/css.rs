@@ -1,12 +1,14 @@
 //! CSS parsing and styling engine using cssparser and selectors
 
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::{Duration, Instant};
 use cssparser::{Parser, ParserInput, ParseError, Token, Color as CSSColor};
 use selectors::parser::{SelectorList, ParseRelative};
 use selectors::{Element as SelectorElement, OpaqueElement};
-use crate::core::{ElementId, Result, EngineError, Color};
-use crate::html::{Document, Element};
+use crate::core::{ElementId, Result, EngineError, Color, Shutdown};
+use crate::html::{Document, Element, ElementState};
 
 /// CSS engine for parsing stylesheets and computing styles
 pub struct CSSEngine {
@@ -15,14 +17,28 @@ pub struct CSSEngine {
     
     /// Cached parsed stylesheets
     stylesheet_cache: HashMap<String, Arc<Stylesheet>>,
+    
+    /// Parsed form of every selector seen, keyed by selector text
+    selector_cache: RwLock<HashMap<String, ParsedSelector>>,
+    
+    /// Number of selector parses, i.e. cache misses
+    selector_parses: AtomicUsize,
+    
+    /// Lowercased families loaded from the current page's `@font-face` rules
+    web_fonts: RwLock<HashSet<String>>,
 }
 
 impl CSSEngine {
     pub fn new() -> Self {
-        Self {
+        let engine = Self {
             user_agent_stylesheet: Stylesheet::default_user_agent(),
             stylesheet_cache: HashMap::new(),
-        }
+            selector_cache: RwLock::new(HashMap::new()),
+            selector_parses: AtomicUsize::new(0),
+            web_fonts: RwLock::new(HashSet::new()),
+        };
+        engine.cache_selectors(&engine.user_agent_stylesheet);
+        engine
     }
     
     /// Parse CSS string into a stylesheet
@@ -48,83 +64,349 @@ impl CSSEngine {
             }
         }
         
-        Ok(Stylesheet {
+        let stylesheet = Stylesheet {
             rules,
             origin,
             media_queries: Vec::new(),
-        })
+            url: None,
+        };
+        self.cache_selectors(&stylesheet);
+        
+        Ok(stylesheet)
+    }
+    
+    /// Parse a stylesheet's selectors up front so `compute_style` only looks them up
+    fn cache_selectors(&self, stylesheet: &Stylesheet) {
+        let mut cache = self.selector_cache.write().unwrap_or_else(PoisonError::into_inner);
+        for rule in &stylesheet.rules {
+            if let CSSRule::StyleRule(style_rule) = rule {
+                for selector in &style_rule.selectors {
+                    if !cache.contains_key(selector) {
+                        self.selector_parses.fetch_add(1, Ordering::Relaxed);
+                        cache.insert(selector.clone(), ParsedSelector::parse(selector));
+                    }
+                }
+            }
+        }
+    }
+    
+    /// Set the `@font-face` families the current page has loaded, so `font-family` lists
+    /// prefer them; families from the previous page no longer apply
+    pub fn set_web_fonts(&self, families: &[String]) {
+        *self.web_fonts.write().unwrap_or_else(PoisonError::into_inner) = families.iter()
+            .map(|family| normalize_family(family))
+            .collect();
+    }
+    
+    /// The first loaded web font in a `font-family` list, keeping the list as
+    /// written when none of its families has loaded
+    fn resolve_font_family(&self, font_family: &str) -> String {
+        let web_fonts = self.web_fonts.read().unwrap_or_else(PoisonError::into_inner);
+        font_family.split(',')
+            .map(|family| family.trim().trim_matches(|c| c == '"' || c == '\''))
+            .find(|family| web_fonts.contains(&normalize_family(family)))
+            .map_or_else(|| font_family.to_string(), str::to_string)
     }
     
     /// Parse stylesheets from a document
     pub async fn parse_stylesheets(&self, document: &Document) -> Result<Vec<Arc<Stylesheet>>> {
+        self.parse_stylesheets_with(document, &HashMap::new()).await
+    }
+    
+    /// Parse the document's stylesheets, using `external` for the text of `<link>` stylesheets
+    /// already fetched. Author sheets apply in document order; unfetched links are skipped.
+    pub async fn parse_stylesheets_with(
+        &self,
+        document: &Document,
+        external: &HashMap<ElementId, String>,
+    ) -> Result<Vec<Arc<Stylesheet>>> {
         let mut stylesheets = Vec::new();
         
         // Add user agent stylesheet
         stylesheets.push(Arc::new(self.user_agent_stylesheet.clone()));
         
-        // Parse <style> elements
-        let style_elements = document.get_elements_by_tag_name("style");
-        for style_element in style_elements {
-            let css_text = style_element.text_content();
+        let base_url = url::Url::parse(&document.url).ok();
+        for element in document.descendants(&document.root) {
+            let mut stylesheet_url = None;
+            let css_text = match element.tag_name.as_str() {
+                "style" => element.text_content(),
+                "link" => match external.get(&element.id) {
+                    Some(css_text) => {
+                        stylesheet_url = element.get_attribute("href")
+                            .and_then(|href| match &base_url {
+                                Some(base_url) => base_url.join(href).ok(),
+                                None => url::Url::parse(href).ok(),
+                            })
+                            .map(String::from);
+                        css_text.clone()
+                    }
+                    None => {
+                        if let Some(href) = element.get_attribute("href").filter(|_| is_stylesheet_link(&element)) {
+                            log::info!("External stylesheet not loaded: {}", href);
+                        }
+                        continue;
+                    }
+                },
+                _ => continue,
+            };
+            
             if !css_text.is_empty() {
                 match self.parse_stylesheet(&css_text, StylesheetOrigin::Author) {
-                    Ok(stylesheet) => stylesheets.push(Arc::new(stylesheet)),
-                    Err(e) => log::warn!("Failed to parse inline stylesheet: {:?}", e),
+                    Ok(stylesheet) => stylesheets.push(Arc::new(Stylesheet { url: stylesheet_url, ..stylesheet })),
+                    Err(e) => log::warn!("Failed to parse stylesheet: {:?}", e),
                 }
             }
         }
         
-        // Parse <link> elements for external stylesheets
-        let link_elements = document.get_elements_by_tag_name("link");
-        for link_element in link_elements {
-            if let Some(rel) = link_element.get_attribute("rel") {
-                if rel == "stylesheet" {
-                    if let Some(href) = link_element.get_attribute("href") {
-                        // TODO: Fetch external stylesheet
-                        // For now, we'll skip external stylesheets
-                        log::info!("External stylesheet found: {}", href);
+        Ok(stylesheets)
+    }
+    
+    /// Compute the final style for an element
+    pub fn compute_style(&self, element: &Element, stylesheets: &[Arc<Stylesheet>]) -> ComputedStyle {
+        self.compute_style_with_parent(element, stylesheets, None)
+    }
+    
+    /// Compute styles for every element in tree order, so each element inherits from its
+    /// parent's computed style. Class selectors are only tried on the elements the
+    /// document's class index lists for them.
+    pub fn compute_document_styles(&self, document: &Document, stylesheets: &[Arc<Stylesheet>]) -> HashMap<ElementId, ComputedStyle> {
+        let candidates = self.selector_candidates(document, stylesheets);
+        let mut styles = HashMap::new();
+        let mut pending = vec![(document.root.clone(), None::<ElementId>)];
+        while let Some((element, parent)) = pending.pop() {
+            let parent_style = parent.and_then(|id| styles.get(&id));
+            let style = self.cascade(&element, Some(document), Some(&candidates), stylesheets, parent_style);
+            styles.insert(element.id, style);
+            for child_id in element.children.iter().rev() {
+                if let Some(child) = document.elements.get(child_id) {
+                    pending.push((child.clone(), Some(element.id)));
+                }
+            }
+        }
+        styles
+    }
+    
+    /// Compute the final style for an element. Inherited properties and custom properties
+    /// (`--*`) it doesn't set come from `parent_style`, as does any value of `inherit`.
+    /// Without a document, structural and interaction pseudo-classes never match.
+    pub fn compute_style_with_parent(
+        &self,
+        element: &Element,
+        stylesheets: &[Arc<Stylesheet>],
+        parent_style: Option<&ComputedStyle>,
+    ) -> ComputedStyle {
+        self.cascade(element, None, None, stylesheets, parent_style)
+    }
+    
+    /// Compute an element's style as `compute_style_with_parent` does, matching pseudo-classes
+    /// such as `:nth-child` and `:hover` against its position and state in `document`
+    pub fn compute_style_in(
+        &self,
+        element: &Element,
+        document: &Document,
+        stylesheets: &[Arc<Stylesheet>],
+        parent_style: Option<&ComputedStyle>,
+    ) -> ComputedStyle {
+        self.cascade(element, Some(document), None, stylesheets, parent_style)
+    }
+    
+    /// The elements each class-keyed selector in the stylesheets can match, from the
+    /// document's class index. Ids aren't used: the id index keeps one element per id,
+    /// while a selector matches every element sharing it.
+    fn selector_candidates(&self, document: &Document, stylesheets: &[Arc<Stylesheet>]) -> HashMap<String, HashSet<ElementId>> {
+        let mut by_class: HashMap<String, HashSet<ElementId>> = HashMap::new();
+        let mut candidates = HashMap::new();
+        for stylesheet in stylesheets {
+            for rule in &stylesheet.rules {
+                let CSSRule::StyleRule(style_rule) = rule else { continue };
+                for selector in &style_rule.selectors {
+                    if candidates.contains_key(selector) {
+                        continue;
                     }
+                    let class = self.parsed_selector(selector).class_key().map(str::to_string);
+                    let Some(class) = class else { continue };
+                    let elements = by_class.entry(class.clone())
+                        .or_insert_with(|| document.get_elements_by_class_name(&class).iter().map(|e| e.id).collect());
+                    candidates.insert(selector.clone(), elements.clone());
                 }
             }
         }
-        
-        Ok(stylesheets)
+        candidates
     }
     
-    /// Compute the final style for an element
-    pub fn compute_style(&self, element: &Element, stylesheets: &[Arc<Stylesheet>]) -> ComputedStyle {
+    fn cascade(
+        &self,
+        element: &Element,
+        document: Option<&Document>,
+        candidates: Option<&HashMap<String, HashSet<ElementId>>>,
+        stylesheets: &[Arc<Stylesheet>],
+        parent_style: Option<&ComputedStyle>,
+    ) -> ComputedStyle {
         let mut computed_style = ComputedStyle::default();
+        if let Some(parent) = parent_style {
+            for property in INHERITED_PROPERTIES {
+                computed_style.copy_property(property, parent);
+            }
+            computed_style.root_font_size = parent.root_font_size;
+        }
         
-        // Apply styles from all stylesheets in cascade order
+        // Collect matching declarations in cascade order
+        let mut declarations: Vec<Declaration> = Vec::new();
         for stylesheet in stylesheets {
             for rule in &stylesheet.rules {
                 if let CSSRule::StyleRule(style_rule) = rule {
-                    // Check if any selector matches the element
+                    // Check if any selector matches the element, skipping ones it can't be a candidate for
                     for selector in &style_rule.selectors {
-                        if self.selector_matches(selector, element) {
-                            // Apply declarations
-                            for declaration in &style_rule.declarations {
-                                self.apply_declaration(&mut computed_style, declaration);
-                            }
+                        let excluded = candidates.and_then(|candidates| candidates.get(selector))
+                            .is_some_and(|elements| !elements.contains(&element.id));
+                        if !excluded && self.selector_matches(selector, element, document) {
+                            declarations.extend(style_rule.declarations.iter().cloned());
                         }
                     }
                 }
             }
         }
         
-        // Apply inline styles (highest specificity)
+        // Inline styles come last (highest specificity)
         if let Some(style_attr) = element.get_attribute("style") {
-            if let Ok(declarations) = self.parse_declaration_list(style_attr) {
-                for declaration in declarations {
-                    self.apply_declaration(&mut computed_style, &declaration);
-                }
+            if let Ok(inline) = self.parse_declaration_list(style_attr) {
+                declarations.extend(inline);
             }
         }
         
+        // Custom properties cascade like any other property and inherit by default
+        let mut variables: HashMap<String, String> = parent_style
+            .map(|parent| parent.custom_properties.iter()
+                .filter(|(name, _)| name.starts_with("--"))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect())
+            .unwrap_or_default();
+        for declaration in declarations.iter().filter(|d| d.property.starts_with("--")) {
+            variables.insert(declaration.property.clone(), declaration.value.clone());
+        }
+        
+        // Variables may refer to each other; one that can't be resolved is dropped
+        let resolved: HashMap<String, String> = variables.iter()
+            .filter_map(|(name, value)| Some((name.clone(), substitute_var_references(value, &variables, 0)?)))
+            .collect();
+        
+        // The root has nothing to inherit from, so it inherits initial values
+        let initial = ComputedStyle::default();
+        let parent = parent_style.unwrap_or(&initial);
+        
+        // font-size goes first since `em` in every other property depends on it
+        let (font_sizes, others): (Vec<&Declaration>, Vec<&Declaration>) = declarations.iter()
+            .filter(|d| !d.property.starts_with("--"))
+            .partition(|d| d.property == "font-size");
+        for declaration in font_sizes {
+            self.apply_cascaded(&mut computed_style, declaration, parent, &resolved);
+        }
+        if parent_style.is_none() {
+            computed_style.root_font_size = computed_style.font_size;
+        }
+        for declaration in others {
+            self.apply_cascaded(&mut computed_style, declaration, parent, &resolved);
+        }
+        computed_style.custom_properties.extend(resolved);
+        
         computed_style
     }
     
+    /// Recompute an element's style after a class or style mutation.
+    /// Watched properties that changed start transitioning instead of snapping.
+    pub fn restyle_element(
+        &self,
+        element: &Element,
+        stylesheets: &[Arc<Stylesheet>],
+        previous: &ComputedStyle,
+        driver: &mut AnimationDriver,
+        now: Instant,
+    ) -> ComputedStyle {
+        let new_style = self.compute_style(element, stylesheets);
+        driver.style_changed(element.id, previous, &new_style, now)
+    }
+    
+    /// Start, keep, or stop an element's keyframe animations to match its `animation-*`
+    /// properties. Returns the style to display at `now`.
+    pub fn animate_element(
+        &self,
+        element_id: ElementId,
+        style: &ComputedStyle,
+        stylesheets: &[Arc<Stylesheet>],
+        driver: &mut AnimationDriver,
+        now: Instant,
+    ) -> ComputedStyle {
+        let animations = style.animation.animations().into_iter()
+            .filter_map(|animation| {
+                let keyframes = find_keyframes(stylesheets, &animation.name)?;
+                Some((animation, self.resolve_keyframes(keyframes, style)))
+            })
+            .collect();
+        driver.set_animations(element_id, style, animations, now)
+    }
+    
+    /// Turn a `@keyframes` rule into per-property tracks. Offsets 0% and 100%
+    /// default to the element's own value when the rule leaves them out.
+    fn resolve_keyframes(&self, rule: &KeyframesRule, base: &ComputedStyle) -> Vec<KeyframeTrack> {
+        let mut tracks: Vec<KeyframeTrack> = Vec::new();
+        
+        for keyframe in &rule.keyframes {
+            // Selectors like `150%` or `50` name no valid offset and are ignored
+            let offsets = keyframe.offsets();
+            if offsets.is_empty() {
+                continue;
+            }
+            let mut frame_style = base.clone();
+            for declaration in &keyframe.declarations {
+                self.apply_declaration(&mut frame_style, declaration, base);
+            }
+            
+            for declaration in &keyframe.declarations {
+                let property = declaration.property.as_str();
+                let Some(value) = frame_style.animatable_value(property) else {
+                    continue;
+                };
+                let track = match tracks.iter().position(|track| track.property == property) {
+                    Some(index) => &mut tracks[index],
+                    None => {
+                        tracks.push(KeyframeTrack { property: property.to_string(), keyframes: Vec::new() });
+                        tracks.last_mut().unwrap()
+                    }
+                };
+                track.keyframes.extend(offsets.iter().map(|offset| (*offset, value)));
+            }
+        }
+        
+        tracks.retain(|track| !track.keyframes.is_empty());
+        for track in &mut tracks {
+            track.keyframes.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let (first, last) = (track.keyframes[0], track.keyframes[track.keyframes.len() - 1]);
+            let underlying = base.animatable_value(&track.property);
+            if first.0 > 0.0 {
+                track.keyframes.insert(0, (0.0, underlying.unwrap_or(first.1)));
+            }
+            if last.0 < 1.0 {
+                track.keyframes.push((1.0, underlying.unwrap_or(last.1)));
+            }
+        }
+        tracks
+    }
+    
     fn parse_rule(&self, parser: &mut Parser) -> Result<CSSRule> {
+        let state = parser.state();
+        if matches!(parser.next(), Ok(Token::AtKeyword(name)) if name.eq_ignore_ascii_case("font-face")) {
+            parser.expect_curly_bracket_block()?;
+            let declarations = parser.parse_nested_block(|parser| {
+                self.parse_declaration_list_from_parser(parser)
+            })?;
+            return Ok(CSSRule::FontFaceRule(FontFaceRule { declarations }));
+        }
+        parser.reset(&state);
+        if matches!(parser.next(), Ok(Token::AtKeyword(name)) if name.eq_ignore_ascii_case("keyframes")) {
+            return self.parse_keyframes(parser);
+        }
+        parser.reset(&state);
+        
         // Simplified rule parsing - in reality this would be much more complex
         let selector_text = self.parse_selector_list(parser)?;
         
@@ -139,21 +421,44 @@ impl CSSEngine {
         }))
     }
     
-    fn parse_selector_list(&self, parser: &mut Parser) -> Result<String> {
-        // Simplified selector parsing
-        let mut selector = String::new();
+    /// Parse the rest of a `@keyframes` rule, after the at-keyword
+    fn parse_keyframes(&self, parser: &mut Parser) -> Result<CSSRule> {
+        let name = match parser.next()? {
+            Token::Ident(name) | Token::QuotedString(name) => name.to_string(),
+            token => return Err(EngineError::CssParseError(format!("Expected keyframes name, found {:?}", token))),
+        };
+        
+        parser.expect_curly_bracket_block()?;
+        let keyframes = parser.parse_nested_block(|parser| {
+            let mut keyframes = Vec::new();
+            while !parser.is_exhausted() {
+                // The selector list stops after consuming the keyframe's block
+                let selector = self.parse_selector_list(parser)?;
+                let declarations = parser.parse_nested_block(|parser| {
+                    self.parse_declaration_list_from_parser(parser)
+                })?;
+                keyframes.push(Keyframe { selector, declarations });
+            }
+            Ok::<_, EngineError>(keyframes)
+        })?;
         
+        Ok(CSSRule::KeyframesRule(KeyframesRule { name, keyframes }))
+    }
+    
+    fn parse_selector_list(&self, parser: &mut Parser) -> Result<String> {
+        // Simplified selector parsing: keep the source text, including pseudo-class
+        // arguments such as `:nth-child(2n + 1)`
+        let start = parser.position();
+        let mut end = start;
         while !parser.is_exhausted() {
             match parser.next() {
                 Ok(Token::CurlyBracketBlock) => break,
-                Ok(token) => {
-                    selector.push_str(&token.to_css_string());
-                }
+                Ok(_) => end = parser.position(),
                 Err(_) => break,
             }
         }
         
-        Ok(selector.trim().to_string())
+        Ok(parser.slice(start..end).trim().to_string())
     }
     
     fn parse_declaration_list_from_parser(&self, parser: &mut Parser) -> Result<Vec<Declaration>> {
@@ -191,33 +496,79 @@ impl CSSEngine {
         
         parser.expect_colon()?;
         
-        let mut value = String::new();
-        while !parser.is_exhausted() {
+        // Keep the value's source text, including function arguments such as `var(--a, 1px)`;
+        // the terminating semicolon is left for the caller
+        let start = parser.position();
+        let mut end = start;
+        loop {
+            let state = parser.state();
             match parser.next() {
-                Ok(Token::Semicolon) | Ok(Token::EOF) => break,
-                Ok(token) => {
-                    if !value.is_empty() {
-                        value.push(' ');
-                    }
-                    value.push_str(&token.to_css_string());
+                Ok(Token::Semicolon) => {
+                    parser.reset(&state);
+                    break;
                 }
+                Ok(_) => end = parser.position(),
                 Err(_) => break,
             }
         }
+        let value = parser.slice(start..end).trim();
+        
+        let (value, important) = match value.rfind('!') {
+            Some(bang) if value[bang + 1..].trim().eq_ignore_ascii_case("important") => (value[..bang].trim_end(), true),
+            _ => (value, false),
+        };
         
         Ok(Declaration {
             property,
-            value: value.trim().to_string(),
-            important: false, // TODO: Parse !important
+            value: value.to_string(),
+            important,
         })
     }
     
-    fn selector_matches(&self, selector: &str, element: &Element) -> bool {
-        // Simplified selector matching
-        element.matches_selector(selector)
+    fn selector_matches(&self, selector: &str, element: &Element, document: Option<&Document>) -> bool {
+        if let Some(parsed) = self.selector_cache.read().unwrap_or_else(PoisonError::into_inner).get(selector) {
+            return parsed.matches(element, document);
+        }
+        self.parsed_selector(selector).matches(element, document)
+    }
+    
+    /// The cached parse of a selector, parsing it for rules built without going through `parse_stylesheet`
+    fn parsed_selector(&self, selector: &str) -> ParsedSelector {
+        if let Some(parsed) = self.selector_cache.read().unwrap_or_else(PoisonError::into_inner).get(selector) {
+            return parsed.clone();
+        }
+        self.selector_parses.fetch_add(1, Ordering::Relaxed);
+        let parsed = ParsedSelector::parse(selector);
+        self.selector_cache.write().unwrap_or_else(PoisonError::into_inner).insert(selector.to_string(), parsed.clone());
+        parsed
+    }
+    
+    /// Apply a declaration after handling `inherit` and substituting `var()` references
+    fn apply_cascaded(
+        &self,
+        computed_style: &mut ComputedStyle,
+        declaration: &Declaration,
+        parent: &ComputedStyle,
+        variables: &HashMap<String, String>,
+    ) {
+        if declaration.value.eq_ignore_ascii_case("inherit") {
+            computed_style.copy_property(&declaration.property, parent);
+        } else if !declaration.value.contains("var(") {
+            self.apply_declaration(computed_style, declaration, parent);
+        } else if let Some(value) = substitute_var_references(&declaration.value, variables, 0) {
+            self.apply_declaration(computed_style, &Declaration { value, ..declaration.clone() }, parent);
+        }
+        // A value whose references can't be resolved is invalid and ignored
     }
     
-    fn apply_declaration(&self, computed_style: &mut ComputedStyle, declaration: &Declaration) {
+    /// Apply one declaration; relative lengths resolve against the element's font size,
+    /// the root's font size, and `parent`'s dimensions
+    fn apply_declaration(&self, computed_style: &mut ComputedStyle, declaration: &Declaration, parent: &ComputedStyle) {
+        let context = LengthContext {
+            font_size: computed_style.font_size,
+            root_font_size: computed_style.root_font_size,
+            percent_of: None,
+        };
         match declaration.property.as_str() {
             "color" => {
                 computed_style.color = self.parse_color(&declaration.value)
@@ -228,11 +579,18 @@ impl CSSEngine {
                     .unwrap_or(Color::transparent());
             }
             "font-size" => {
-                computed_style.font_size = self.parse_length(&declaration.value)
-                    .unwrap_or(16.0);
+                // `em` and `%` in font-size itself are relative to the parent's font size
+                let context = LengthContext {
+                    font_size: parent.font_size,
+                    percent_of: Some(parent.font_size),
+                    ..context
+                };
+                if let Some(font_size) = self.parse_length(&declaration.value, &context) {
+                    computed_style.font_size = font_size;
+                }
             }
             "font-family" => {
-                computed_style.font_family = declaration.value.clone();
+                computed_style.font_family = self.resolve_font_family(&declaration.value);
             }
             "font-weight" => {
                 computed_style.font_weight = self.parse_font_weight(&declaration.value)
@@ -246,20 +604,92 @@ impl CSSEngine {
                 computed_style.position = self.parse_position(&declaration.value)
                     .unwrap_or(PositionType::Static);
             }
+            "overflow" => {
+                computed_style.overflow = self.parse_overflow(&declaration.value)
+                    .unwrap_or(OverflowType::Visible);
+            }
+            // Percentages are left for layout, which knows the containing block's size
             "width" => {
-                computed_style.width = self.parse_length(&declaration.value);
+                (computed_style.width, computed_style.width_percent) = self.parse_size(&declaration.value, &context);
             }
             "height" => {
-                computed_style.height = self.parse_length(&declaration.value);
+                (computed_style.height, computed_style.height_percent) = self.parse_size(&declaration.value, &context);
             }
             "margin" => {
-                computed_style.margin = self.parse_box_values(&declaration.value);
+                (computed_style.margin, computed_style.margin_percent) = self.parse_box_sides(&declaration.value, &context);
             }
             "padding" => {
-                computed_style.padding = self.parse_box_values(&declaration.value);
+                (computed_style.padding, computed_style.padding_percent) = self.parse_box_sides(&declaration.value, &context);
             }
             "border-width" => {
-                computed_style.border_width = self.parse_box_values(&declaration.value);
+                computed_style.border_width = self.parse_box_values(&declaration.value, &context);
+            }
+            "opacity" => {
+                computed_style.opacity = declaration.value.parse::<f32>()
+                    .map(|opacity| opacity.clamp(0.0, 1.0))
+                    .unwrap_or(1.0);
+            }
+            "transition" => {
+                computed_style.transitions = self.parse_transitions(&declaration.value);
+            }
+            "transition-property" => {
+                let properties: Vec<String> = split_top_level_commas(&declaration.value)
+                    .into_iter()
+                    .map(str::to_ascii_lowercase)
+                    .collect();
+                // `none` stays as an entry that matches no property
+                let existing = std::mem::take(&mut computed_style.transitions);
+                computed_style.transitions = properties.into_iter()
+                    .enumerate()
+                    .map(|(index, property)| Transition {
+                        property,
+                        ..existing.get(index % existing.len().max(1)).cloned().unwrap_or_default()
+                    })
+                    .collect();
+            }
+            "transition-duration" => {
+                let durations = split_top_level_commas(&declaration.value).into_iter().filter_map(parse_time).collect();
+                set_transition_longhand(&mut computed_style.transitions, durations, |transition, duration| transition.duration = duration);
+            }
+            "transition-timing-function" => {
+                let timing_functions = split_top_level_commas(&declaration.value).into_iter().filter_map(TimingFunction::parse).collect();
+                set_transition_longhand(&mut computed_style.transitions, timing_functions, |transition, timing_function| {
+                    transition.timing_function = timing_function;
+                });
+            }
+            "transition-delay" => {
+                let delays = split_top_level_commas(&declaration.value).into_iter().filter_map(parse_time).collect();
+                set_transition_longhand(&mut computed_style.transitions, delays, |transition, delay| transition.delay = delay);
+            }
+            "animation" => {
+                computed_style.animation = AnimationProperties::parse_shorthand(&declaration.value);
+            }
+            "animation-name" => {
+                computed_style.animation.names = split_top_level_commas(&declaration.value)
+                    .into_iter()
+                    .map(|name| unquote(name).to_string())
+                    .collect();
+            }
+            "animation-duration" => {
+                computed_style.animation.durations = split_top_level_commas(&declaration.value)
+                    .into_iter()
+                    .filter_map(parse_time)
+                    .collect();
+            }
+            "animation-timing-function" => {
+                computed_style.animation.timing_functions = split_top_level_commas(&declaration.value)
+                    .into_iter()
+                    .filter_map(TimingFunction::parse)
+                    .collect();
+            }
+            "animation-iteration-count" => {
+                computed_style.animation.iteration_counts = split_top_level_commas(&declaration.value)
+                    .into_iter()
+                    .filter_map(IterationCount::parse)
+                    .collect();
+            }
+            "transform" => {
+                computed_style.transform = self.parse_transform(&declaration.value, &context);
             }
             _ => {
                 // Unknown property, store as custom property
@@ -299,18 +729,21 @@ impl CSSEngine {
         }
     }
     
-    fn parse_length(&self, value: &str) -> Option<f32> {
-        // Simple length parsing - supports px, em, rem, %
-        if let Some(px_pos) = value.find("px") {
-            value[..px_pos].parse().ok()
-        } else if let Some(em_pos) = value.find("em") {
-            value[..em_pos].parse::<f32>().ok().map(|v| v * 16.0) // Assume 16px base
-        } else if let Some(rem_pos) = value.find("rem") {
-            value[..rem_pos].parse::<f32>().ok().map(|v| v * 16.0) // Assume 16px base
-        } else if let Some(percent_pos) = value.find('%') {
-            value[..percent_pos].parse().ok()
+    /// Resolve a length in px, em, rem, or % to pixels; `None` if it's invalid or a
+    /// percentage of something unknown
+    fn parse_length(&self, value: &str, context: &LengthContext) -> Option<f32> {
+        let value = value.trim();
+        let number = |digits: &str| digits.trim().parse::<f32>().ok();
+        if let Some(px) = value.strip_suffix("px") {
+            number(px)
+        } else if let Some(rem) = value.strip_suffix("rem") {
+            number(rem).map(|v| v * context.root_font_size)
+        } else if let Some(em) = value.strip_suffix("em") {
+            number(em).map(|v| v * context.font_size)
+        } else if let Some(percent) = value.strip_suffix('%') {
+            number(percent).zip(context.percent_of).map(|(v, base)| v / 100.0 * base)
         } else {
-            value.parse().ok()
+            number(value)
         }
     }
     
@@ -347,10 +780,129 @@ impl CSSEngine {
         }
     }
     
-    fn parse_box_values(&self, value: &str) -> BoxValues {
+    /// Parse `overflow`; with separate x and y keywords the box clips if either axis does
+    fn parse_overflow(&self, value: &str) -> Option<OverflowType> {
+        let keywords = value.split_whitespace()
+            .map(|keyword| match keyword {
+                "visible" => Some(OverflowType::Visible),
+                "hidden" | "clip" => Some(OverflowType::Hidden),
+                "scroll" => Some(OverflowType::Scroll),
+                "auto" => Some(OverflowType::Auto),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        keywords.iter().find(|overflow| **overflow != OverflowType::Visible)
+            .or_else(|| keywords.first())
+            .cloned()
+    }
+    
+    /// Parse a `transform` list such as `translate(10px, 5px) rotate(45deg)`.
+    /// Unsupported functions are skipped; `none` yields an empty list.
+    fn parse_transform(&self, value: &str, context: &LengthContext) -> Vec<TransformFunction> {
+        let mut functions = Vec::new();
+        for part in value.split(')') {
+            let Some((name, args)) = part.split_once('(') else {
+                continue;
+            };
+            let args: Vec<&str> = args.split(',').map(str::trim).filter(|arg| !arg.is_empty()).collect();
+            let length = |index: usize| args.get(index).and_then(|arg| self.parse_length(arg, context));
+            let number = |index: usize| args.get(index).and_then(|arg| arg.parse::<f32>().ok());
+            let function = match name.trim() {
+                "translate" => length(0).map(|x| TransformFunction::Translate(x, length(1).unwrap_or(0.0))),
+                "translateX" => length(0).map(|x| TransformFunction::Translate(x, 0.0)),
+                "translateY" => length(0).map(|y| TransformFunction::Translate(0.0, y)),
+                "scale" => number(0).map(|x| TransformFunction::Scale(x, number(1).unwrap_or(x))),
+                "scaleX" => number(0).map(|x| TransformFunction::Scale(x, 1.0)),
+                "scaleY" => number(0).map(|y| TransformFunction::Scale(1.0, y)),
+                "rotate" => args.first().and_then(|arg| Self::parse_angle(arg)).map(TransformFunction::Rotate),
+                _ => None,
+            };
+            functions.extend(function);
+        }
+        functions
+    }
+    
+    /// Angle in degrees from a `deg`, `rad` or `turn` value
+    fn parse_angle(value: &str) -> Option<f32> {
+        if let Some(deg) = value.strip_suffix("deg") {
+            deg.trim().parse().ok()
+        } else if let Some(rad) = value.strip_suffix("rad") {
+            rad.trim().parse::<f32>().ok().map(f32::to_degrees)
+        } else if let Some(turn) = value.strip_suffix("turn") {
+            turn.trim().parse::<f32>().ok().map(|turns| turns * 360.0)
+        } else {
+            value.parse().ok()
+        }
+    }
+    
+    /// Parse the `transition` shorthand: `property duration timing-function delay`, comma-separated.
+    /// `none` is kept as a transition of property `none`, so longhands applied later don't
+    /// fall back to the initial `all`
+    fn parse_transitions(&self, value: &str) -> Vec<Transition> {
+        split_top_level_commas(value)
+            .into_iter()
+            .map(|part| {
+                let mut transition = Transition::default();
+                let mut seen_duration = false;
+                
+                for component in split_components(part) {
+                    if let Some(time) = parse_time(component) {
+                        // The first time is the duration, the second the delay
+                        if seen_duration {
+                            transition.delay = time;
+                        } else {
+                            transition.duration = time;
+                            seen_duration = true;
+                        }
+                    } else if let Some(timing_function) = TimingFunction::parse(component) {
+                        transition.timing_function = timing_function;
+                    } else {
+                        transition.property = component.to_ascii_lowercase();
+                    }
+                }
+                
+                transition
+            })
+            .collect()
+    }
+    
+    /// A `width`/`height` value as pixels, or as a fraction of the containing block
+    fn parse_size(&self, value: &str, context: &LengthContext) -> (Option<f32>, Option<f32>) {
+        match value.trim().strip_suffix('%') {
+            Some(percent) => (None, percent.trim().parse::<f32>().ok().map(|v| v / 100.0)),
+            None => (self.parse_length(value, context), None),
+        }
+    }
+    
+    /// Margin or padding sides in pixels, with percentage sides kept as fractions of the
+    /// containing block's width
+    fn parse_box_sides(&self, value: &str, context: &LengthContext) -> (BoxValues, BoxPercentages) {
+        let sides: Vec<(f32, Option<f32>)> = value
+            .split_whitespace()
+            .filter_map(|v| match self.parse_size(v, context) {
+                (Some(px), _) => Some((px, None)),
+                (None, Some(fraction)) => Some((0.0, Some(fraction))),
+                (None, None) => None,
+            })
+            .collect();
+        
+        let [top, right, bottom, left] = match sides.len() {
+            1 => [sides[0]; 4],
+            2 => [sides[0], sides[1], sides[0], sides[1]],
+            3 => [sides[0], sides[1], sides[2], sides[1]],
+            4 => [sides[0], sides[1], sides[2], sides[3]],
+            _ => [(0.0, None); 4],
+        };
+        (
+            BoxValues::new(top.0, right.0, bottom.0, left.0),
+            BoxPercentages { top: top.1, right: right.1, bottom: bottom.1, left: left.1 },
+        )
+    }
+    
+    fn parse_box_values(&self, value: &str, context: &LengthContext) -> BoxValues {
         let values: Vec<f32> = value
             .split_whitespace()
-            .filter_map(|v| self.parse_length(v))
+            .filter_map(|v| self.parse_length(v, context))
             .collect();
         
         match values.len() {
@@ -363,12 +915,29 @@ impl CSSEngine {
     }
 }
 
+/// Whether a `<link>` element's `rel` includes `stylesheet`
+pub(crate) fn is_stylesheet_link(element: &Element) -> bool {
+    element.get_attribute("rel")
+        .is_some_and(|rel| rel.split_ascii_whitespace().any(|token| token.eq_ignore_ascii_case("stylesheet")))
+}
+
+/// Stylesheets are parsed on demand, so there is nothing to release
+#[async_trait::async_trait]
+impl Shutdown for CSSEngine {
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
 /// Represents a CSS stylesheet
 #[derive(Debug, Clone)]
 pub struct Stylesheet {
     pub rules: Vec<CSSRule>,
     pub origin: StylesheetOrigin,
     pub media_queries: Vec<MediaQuery>,
+    /// Address of an external stylesheet, which its relative URLs resolve against;
+    /// `None` for `<style>` elements and the user agent stylesheet
+    pub url: Option<String>,
 }
 
 impl Stylesheet {
@@ -434,6 +1003,7 @@ impl Stylesheet {
             rules,
             origin: StylesheetOrigin::UserAgent,
             media_queries: Vec::new(),
+            url: None,
         }
     }
 }
@@ -448,6 +1018,178 @@ pub enum CSSRule {
     KeyframesRule(KeyframesRule),
 }
 
+/// A selector parsed once from its text (simplified: a single compound selector such as
+/// `li.item:nth-child(odd)`; selectors with combinators never match)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParsedSelector {
+    Id(String),
+    Class(String),
+    Type(String),
+    /// `*`
+    Universal,
+    PseudoClass(PseudoClass),
+    /// Simple selectors that must all match
+    Compound(Vec<ParsedSelector>),
+}
+
+impl ParsedSelector {
+    pub fn parse(selector: &str) -> Self {
+        let selector = selector.trim();
+        let Some(parts) = split_compound(selector) else {
+            return Self::Type(selector.to_ascii_lowercase());
+        };
+        if parts.len() == 1 {
+            return Self::parse_simple(parts[0]);
+        }
+        Self::Compound(parts.into_iter().map(Self::parse_simple).collect())
+    }
+    
+    fn parse_simple(selector: &str) -> Self {
+        if let Some(id) = selector.strip_prefix('#') {
+            Self::Id(id.to_string())
+        } else if let Some(class) = selector.strip_prefix('.') {
+            Self::Class(class.to_string())
+        } else if let Some(pseudo_class) = selector.strip_prefix(':') {
+            Self::PseudoClass(PseudoClass::parse(pseudo_class))
+        } else if selector == "*" {
+            Self::Universal
+        } else {
+            Self::Type(selector.to_ascii_lowercase())
+        }
+    }
+    
+    /// Whether `element` matches; structural and interaction pseudo-classes need its `document`
+    /// A class every matching element must have, for looking up candidates in a class index
+    pub fn class_key(&self) -> Option<&str> {
+        match self {
+            Self::Class(class) => Some(class),
+            Self::Compound(parts) => parts.iter().find_map(Self::class_key),
+            _ => None,
+        }
+    }
+    
+    pub fn matches(&self, element: &Element, document: Option<&Document>) -> bool {
+        match self {
+            Self::Id(id) => element.get_attribute("id") == Some(id.as_str()),
+            Self::Class(class) => element.get_attribute("class")
+                .is_some_and(|classes| classes.split_whitespace().any(|c| c == class)),
+            Self::Type(tag_name) => element.tag_name.eq_ignore_ascii_case(tag_name),
+            Self::Universal => true,
+            Self::PseudoClass(pseudo_class) => pseudo_class.matches(element, document),
+            Self::Compound(parts) => parts.iter().all(|part| part.matches(element, document)),
+        }
+    }
+}
+
+/// Pseudo-classes understood by `ParsedSelector`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PseudoClass {
+    Hover,
+    Active,
+    Focus,
+    FirstChild,
+    LastChild,
+    /// `:nth-child(an+b)`
+    NthChild { a: i32, b: i32 },
+    Not(Box<ParsedSelector>),
+    /// Any other pseudo-class or pseudo-element, which never matches
+    Unsupported(String),
+}
+
+impl PseudoClass {
+    /// Parse the text after the colon
+    fn parse(text: &str) -> Self {
+        let lower = text.trim().to_ascii_lowercase();
+        let function = lower.split_once('(')
+            .and_then(|(name, rest)| Some((name, rest.strip_suffix(')')?)));
+        let parsed = match (lower.as_str(), function) {
+            ("hover", _) => Some(Self::Hover),
+            ("active", _) => Some(Self::Active),
+            ("focus", _) => Some(Self::Focus),
+            ("first-child", _) => Some(Self::FirstChild),
+            ("last-child", _) => Some(Self::LastChild),
+            (_, Some(("nth-child", arguments))) => parse_nth(arguments).map(|(a, b)| Self::NthChild { a, b }),
+            // Keep the argument's case, which matters for ids and classes
+            (_, Some(("not", _))) => text.trim().split_once('(')
+                .and_then(|(_, rest)| rest.strip_suffix(')'))
+                .map(|inner| Self::Not(Box::new(ParsedSelector::parse(inner)))),
+            _ => None,
+        };
+        parsed.unwrap_or_else(|| Self::Unsupported(text.to_string()))
+    }
+    
+    fn matches(&self, element: &Element, document: Option<&Document>) -> bool {
+        let position = || document.and_then(|document| document.child_position(element));
+        let has_state = |state| document.is_some_and(|document| document.has_element_state(element.id, state));
+        match self {
+            Self::Hover => has_state(ElementState::Hover),
+            Self::Active => has_state(ElementState::Active),
+            Self::Focus => has_state(ElementState::Focus),
+            Self::FirstChild => position().is_some_and(|(index, _)| index == 1),
+            Self::LastChild => position().is_some_and(|(index, count)| index == count),
+            Self::NthChild { a, b } => position().is_some_and(|(index, _)| nth_matches(*a, *b, index as i32)),
+            Self::Not(selector) => !selector.matches(element, document),
+            Self::Unsupported(_) => false,
+        }
+    }
+}
+
+/// Split compound selector text into simple selectors, e.g. `a.link:hover` into `a`, `.link`
+/// and `:hover`. `None` if it contains combinators or a selector list.
+fn split_compound(selector: &str) -> Option<Vec<&str>> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    
+    for (i, c) in selector.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            c if depth == 0 && (c.is_whitespace() || matches!(c, '>' | '+' | '~' | ',')) => return None,
+            // `::` pseudo-elements stay in one piece
+            ':' if depth == 0 && selector[..i].ends_with(':') => {}
+            '#' | '.' | ':' if depth == 0 && i > start => {
+                parts.push(&selector[start..i]);
+                start = i;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&selector[start..]);
+    
+    (!selector.is_empty()).then_some(parts)
+}
+
+/// Parse the `an+b` argument of `:nth-child`, including `odd` and `even`
+fn parse_nth(arguments: &str) -> Option<(i32, i32)> {
+    let arguments: String = arguments.chars().filter(|c| !c.is_whitespace()).collect();
+    match arguments.as_str() {
+        "odd" => return Some((2, 1)),
+        "even" => return Some((2, 0)),
+        _ => {}
+    }
+    
+    let Some((a, b)) = arguments.split_once('n') else {
+        return Some((0, arguments.parse().ok()?));
+    };
+    let a = match a {
+        "" | "+" => 1,
+        "-" => -1,
+        a => a.parse().ok()?,
+    };
+    let b = if b.is_empty() { 0 } else { b.parse().ok()? };
+    Some((a, b))
+}
+
+/// Whether the 1-based `index` is `a*n + b` for some n >= 0
+fn nth_matches(a: i32, b: i32, index: i32) -> bool {
+    if a == 0 {
+        return index == b;
+    }
+    let offset = index - b;
+    offset % a == 0 && offset / a >= 0
+}
+
 /// Style rule with selectors and declarations
 #[derive(Debug, Clone)]
 pub struct StyleRule {
@@ -475,6 +1217,92 @@ pub struct FontFaceRule {
     pub declarations: Vec<Declaration>,
 }
 
+impl FontFaceRule {
+    fn value_of(&self, property: &str) -> Option<&str> {
+        self.declarations.iter().rev()
+            .find(|declaration| declaration.property.eq_ignore_ascii_case(property))
+            .map(|declaration| declaration.value.as_str())
+    }
+    
+    /// The family and sources this rule declares; `None` unless it has both
+    pub fn font_face(&self) -> Option<FontFace> {
+        let family = unquote(self.value_of("font-family")?).to_string();
+        let sources: Vec<FontFaceSource> = split_top_level_commas(self.value_of("src")?).into_iter()
+            .filter_map(FontFaceSource::parse)
+            .collect();
+        (!family.is_empty() && !sources.is_empty()).then_some(FontFace { family, sources, base_url: None })
+    }
+}
+
+/// A web font declared by `@font-face`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontFace {
+    pub family: String,
+    /// Candidates in preference order; the first one that loads is used
+    pub sources: Vec<FontFaceSource>,
+    /// URL of the stylesheet declaring the rule, which relative sources resolve against;
+    /// `None` for rules in `<style>` elements, which resolve against the page
+    pub base_url: Option<String>,
+}
+
+/// One entry of a `@font-face` `src` list
+#[derive(Debug, Clone, PartialEq)]
+pub enum FontFaceSource {
+    /// `url(...)`, with the `format(...)` hint if given
+    Url { url: String, format: Option<String> },
+    /// `local(...)`, a font already available under that name
+    Local(String),
+}
+
+impl FontFaceSource {
+    /// Formats the renderer can load
+    const SUPPORTED_FORMATS: [&'static str; 4] = ["truetype", "opentype", "woff", "collection"];
+    
+    fn parse(source: &str) -> Option<Self> {
+        let (function, rest) = source.split_once('(')?;
+        let (argument, rest) = rest.split_once(')')?;
+        let argument = unquote(argument.trim()).to_string();
+        match function.trim().to_ascii_lowercase().as_str() {
+            "url" => {
+                let format = rest.trim().strip_prefix("format(")
+                    .and_then(|hint| hint.split_once(')'))
+                    .map(|(hint, _)| unquote(hint.trim()).to_ascii_lowercase());
+                Some(Self::Url { url: argument, format })
+            }
+            "local" => Some(Self::Local(argument)),
+            _ => None,
+        }
+    }
+    
+    /// Whether this source is worth trying; a `url()` with an unknown format hint is skipped
+    pub fn is_supported(&self) -> bool {
+        match self {
+            Self::Url { format: Some(format), .. } => Self::SUPPORTED_FORMATS.contains(&format.as_str()),
+            _ => true,
+        }
+    }
+}
+
+/// Every `@font-face` rule in the stylesheets, including ones nested in `@media`
+pub fn collect_font_faces(stylesheets: &[Arc<Stylesheet>]) -> Vec<FontFace> {
+    fn collect(rules: &[CSSRule], base_url: &Option<String>, faces: &mut Vec<FontFace>) {
+        for rule in rules {
+            match rule {
+                CSSRule::FontFaceRule(font_face_rule) => faces.extend(font_face_rule.font_face()
+                    .map(|face| FontFace { base_url: base_url.clone(), ..face })),
+                CSSRule::MediaRule(media_rule) => collect(&media_rule.rules, base_url, faces),
+                _ => {}
+            }
+        }
+    }
+    
+    let mut faces = Vec::new();
+    for stylesheet in stylesheets {
+        collect(&stylesheet.rules, &stylesheet.url, &mut faces);
+    }
+    faces
+}
+
 /// Keyframes rule for animations
 #[derive(Debug, Clone)]
 pub struct KeyframesRule {
@@ -489,6 +1317,33 @@ pub struct Keyframe {
     pub declarations: Vec<Declaration>,
 }
 
+impl Keyframe {
+    /// Offsets in [0, 1] named by the selector; `from` and `to` are 0% and 100%
+    pub fn offsets(&self) -> Vec<f32> {
+        self.selector.split(',')
+            .filter_map(|offset| match offset.trim().to_ascii_lowercase().as_str() {
+                "from" => Some(0.0),
+                "to" => Some(1.0),
+                percent => percent.strip_suffix('%')?.trim().parse::<f32>().ok()
+                    .filter(|percent| (0.0..=100.0).contains(percent))
+                    .map(|percent| percent / 100.0),
+            })
+            .collect()
+    }
+}
+
+/// The last `@keyframes` rule with this name, including ones nested in `@media`
+fn find_keyframes<'a>(stylesheets: &'a [Arc<Stylesheet>], name: &str) -> Option<&'a KeyframesRule> {
+    fn find<'a>(rules: &'a [CSSRule], name: &str) -> Option<&'a KeyframesRule> {
+        rules.iter().rev().find_map(|rule| match rule {
+            CSSRule::KeyframesRule(keyframes) if keyframes.name == name => Some(keyframes),
+            CSSRule::MediaRule(media_rule) => find(&media_rule.rules, name),
+            _ => None,
+        })
+    }
+    stylesheets.iter().rev().find_map(|stylesheet| find(&stylesheet.rules, name))
+}
+
 /// CSS declaration (property: value)
 #[derive(Debug, Clone)]
 pub struct Declaration {
@@ -529,12 +1384,118 @@ pub struct ComputedStyle {
     pub font_weight: u16,
     pub display: DisplayType,
     pub position: PositionType,
+    pub overflow: OverflowType,
     pub width: Option<f32>,
     pub height: Option<f32>,
+    /// Percentage `width`/`height` as a fraction of the containing block, resolved by layout
+    pub width_percent: Option<f32>,
+    pub height_percent: Option<f32>,
     pub margin: BoxValues,
     pub padding: BoxValues,
+    /// Percentage margin and padding sides, which replace the pixel value of that side
+    pub margin_percent: BoxPercentages,
+    pub padding_percent: BoxPercentages,
     pub border_width: BoxValues,
+    pub opacity: f32,
+    pub transform: Vec<TransformFunction>,
+    pub transitions: Vec<Transition>,
+    pub animation: AnimationProperties,
     pub custom_properties: HashMap<String, String>,
+    /// Font size of the root element, which `rem` is relative to
+    pub root_font_size: f32,
+}
+
+impl ComputedStyle {
+    /// Current value of a property that can be interpolated
+    pub fn animatable_value(&self, property: &str) -> Option<AnimatableValue> {
+        match property {
+            "opacity" => Some(AnimatableValue::Number(self.opacity)),
+            "font-size" => Some(AnimatableValue::Number(self.font_size)),
+            "width" => self.width.map(AnimatableValue::Number),
+            "height" => self.height.map(AnimatableValue::Number),
+            "color" => Some(AnimatableValue::Color(self.color)),
+            "background-color" => Some(AnimatableValue::Color(self.background_color)),
+            _ => None,
+        }
+    }
+    
+    /// Overwrite an animatable property with an interpolated value
+    pub fn set_animatable_value(&mut self, property: &str, value: AnimatableValue) {
+        match (property, value) {
+            ("opacity", AnimatableValue::Number(n)) => self.opacity = n,
+            ("font-size", AnimatableValue::Number(n)) => self.font_size = n,
+            ("width", AnimatableValue::Number(n)) => self.width = Some(n),
+            ("height", AnimatableValue::Number(n)) => self.height = Some(n),
+            ("color", AnimatableValue::Color(c)) => self.color = c,
+            ("background-color", AnimatableValue::Color(c)) => self.background_color = c,
+            _ => {}
+        }
+    }
+    
+    /// The transition that applies to a property; later entries win
+    pub fn transition_for(&self, property: &str) -> Option<&Transition> {
+        self.transitions.iter()
+            .rev()
+            .find(|t| t.property == property || t.property == "all")
+            .filter(|t| t.duration > Duration::ZERO)
+    }
+}
+
+/// What relative lengths resolve against while applying one declaration
+#[derive(Debug, Clone, Copy)]
+struct LengthContext {
+    /// Font size `em` is relative to
+    font_size: f32,
+    /// Root element's font size, for `rem`
+    root_font_size: f32,
+    /// Size `%` is relative to; percentages don't resolve while it's unknown (auto)
+    percent_of: Option<f32>,
+}
+
+impl Default for LengthContext {
+    fn default() -> Self {
+        Self {
+            font_size: 16.0,
+            root_font_size: 16.0,
+            percent_of: None,
+        }
+    }
+}
+
+/// Properties a child takes from its parent unless it sets them itself
+const INHERITED_PROPERTIES: [&str; 4] = ["color", "font-size", "font-family", "font-weight"];
+
+impl ComputedStyle {
+    /// Take `property`'s computed value from `source`
+    fn copy_property(&mut self, property: &str, source: &ComputedStyle) {
+        match property {
+            "color" => self.color = source.color,
+            "background-color" => self.background_color = source.background_color,
+            "font-size" => self.font_size = source.font_size,
+            "font-family" => self.font_family = source.font_family.clone(),
+            "font-weight" => self.font_weight = source.font_weight,
+            "display" => self.display = source.display.clone(),
+            "position" => self.position = source.position.clone(),
+            "overflow" => self.overflow = source.overflow.clone(),
+            "width" => (self.width, self.width_percent) = (source.width, source.width_percent),
+            "height" => (self.height, self.height_percent) = (source.height, source.height_percent),
+            "margin" => (self.margin, self.margin_percent) = (source.margin.clone(), source.margin_percent),
+            "padding" => (self.padding, self.padding_percent) = (source.padding.clone(), source.padding_percent),
+            "border-width" => self.border_width = source.border_width.clone(),
+            "opacity" => self.opacity = source.opacity,
+            "transform" => self.transform = source.transform.clone(),
+            "transition" => self.transitions = source.transitions.clone(),
+            "animation" => self.animation = source.animation.clone(),
+            _ => match source.custom_properties.get(property) {
+                Some(value) => {
+                    self.custom_properties.insert(property.to_string(), value.clone());
+                }
+                None => {
+                    self.custom_properties.remove(property);
+                }
+            },
+        }
+    }
 }
 
 impl Default for ComputedStyle {
@@ -547,16 +1508,35 @@ impl Default for ComputedStyle {
             font_weight: 400,
             display: DisplayType::Block,
             position: PositionType::Static,
+            overflow: OverflowType::Visible,
             width: None,
             height: None,
+            width_percent: None,
+            height_percent: None,
             margin: BoxValues::zero(),
             padding: BoxValues::zero(),
+            margin_percent: BoxPercentages::default(),
+            padding_percent: BoxPercentages::default(),
             border_width: BoxValues::zero(),
+            opacity: 1.0,
+            transform: Vec::new(),
+            transitions: Vec::new(),
+            animation: AnimationProperties::default(),
             custom_properties: HashMap::new(),
+            root_font_size: 16.0,
         }
     }
 }
 
+/// A single function from a `transform` list
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransformFunction {
+    Translate(f32, f32),
+    Scale(f32, f32),
+    /// Clockwise rotation in degrees
+    Rotate(f32),
+}
+
 /// CSS display types
 #[derive(Debug, Clone, PartialEq)]
 pub enum DisplayType {
@@ -568,6 +1548,27 @@ pub enum DisplayType {
     None,
 }
 
+/// CSS overflow behaviour
+#[derive(Debug, Clone, PartialEq)]
+pub enum OverflowType {
+    Visible,
+    Hidden,
+    Scroll,
+    Auto,
+}
+
+impl OverflowType {
+    /// Whether content is clipped to the padding box
+    pub fn clips(&self) -> bool {
+        *self != OverflowType::Visible
+    }
+    
+    /// Whether the box is a scroll container the user can scroll
+    pub fn scrolls(&self) -> bool {
+        matches!(self, OverflowType::Scroll | OverflowType::Auto)
+    }
+}
+
 /// CSS position types
 #[derive(Debug, Clone, PartialEq)]
 pub enum PositionType {
@@ -601,6 +1602,632 @@ impl BoxValues {
     }
 }
 
+/// Box sides given as a fraction of the containing block's width (top, right, bottom, left)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BoxPercentages {
+    pub top: Option<f32>,
+    pub right: Option<f32>,
+    pub bottom: Option<f32>,
+    pub left: Option<f32>,
+}
+
+/// Properties the animation driver knows how to interpolate
+pub const ANIMATABLE_PROPERTIES: &[&str] = &[
+    "opacity",
+    "font-size",
+    "width",
+    "height",
+    "color",
+    "background-color",
+];
+
+/// One entry of the `transition` property
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    /// Property name, `all`, or `none`
+    pub property: String,
+    pub duration: Duration,
+    pub timing_function: TimingFunction,
+    pub delay: Duration,
+}
+
+/// Initial values of the `transition-*` longhands
+impl Default for Transition {
+    fn default() -> Self {
+        Self {
+            property: "all".to_string(),
+            duration: Duration::ZERO,
+            timing_function: TimingFunction::Ease,
+            delay: Duration::ZERO,
+        }
+    }
+}
+
+/// Apply a `transition-*` longhand list to each transition, repeating the list as needed.
+/// With no transitions yet, the initial `transition-property: all` is assumed.
+fn set_transition_longhand<T: Copy>(transitions: &mut Vec<Transition>, values: Vec<T>, set: impl Fn(&mut Transition, T)) {
+    if values.is_empty() {
+        return;
+    }
+    if transitions.is_empty() {
+        transitions.push(Transition::default());
+    }
+    for (index, transition) in transitions.iter_mut().enumerate() {
+        set(transition, values[index % values.len()]);
+    }
+}
+
+/// The `animation-*` longhands, one list each. Entries pair up by index, and a
+/// shorter list repeats to match `animation-name`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AnimationProperties {
+    pub names: Vec<String>,
+    pub durations: Vec<Duration>,
+    pub timing_functions: Vec<TimingFunction>,
+    pub iteration_counts: Vec<IterationCount>,
+}
+
+impl AnimationProperties {
+    /// Parse the `animation` shorthand: `name duration timing-function iteration-count`, comma-separated
+    fn parse_shorthand(value: &str) -> Self {
+        let mut properties = Self::default();
+        if value.trim() == "none" {
+            return properties;
+        }
+        
+        for part in split_top_level_commas(value) {
+            let mut name = "none".to_string();
+            let mut duration = None;
+            let mut timing_function = TimingFunction::Ease;
+            let mut iteration_count = IterationCount::Count(1.0);
+            
+            for component in split_components(part) {
+                if let Some(time) = parse_time(component) {
+                    // A second time is the delay, which isn't supported
+                    duration.get_or_insert(time);
+                } else if let Some(parsed) = TimingFunction::parse(component) {
+                    timing_function = parsed;
+                } else if let Some(parsed) = IterationCount::parse(component) {
+                    iteration_count = parsed;
+                } else {
+                    name = unquote(component).to_string();
+                }
+            }
+            
+            properties.names.push(name);
+            properties.durations.push(duration.unwrap_or(Duration::ZERO));
+            properties.timing_functions.push(timing_function);
+            properties.iteration_counts.push(iteration_count);
+        }
+        properties
+    }
+    
+    /// One animation per name other than `none`
+    pub fn animations(&self) -> Vec<Animation> {
+        fn nth<T: Copy>(list: &[T], index: usize, default: T) -> T {
+            if list.is_empty() { default } else { list[index % list.len()] }
+        }
+        
+        self.names.iter()
+            .enumerate()
+            .filter(|(_, name)| *name != "none")
+            .map(|(index, name)| Animation {
+                name: name.clone(),
+                duration: nth(&self.durations, index, Duration::ZERO),
+                timing_function: nth(&self.timing_functions, index, TimingFunction::Ease),
+                iteration_count: nth(&self.iteration_counts, index, IterationCount::Count(1.0)),
+            })
+            .collect()
+    }
+}
+
+/// A `@keyframes` animation applied to an element
+#[derive(Debug, Clone, PartialEq)]
+pub struct Animation {
+    pub name: String,
+    pub duration: Duration,
+    pub timing_function: TimingFunction,
+    pub iteration_count: IterationCount,
+}
+
+/// How many times an animation runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IterationCount {
+    Count(f32),
+    Infinite,
+}
+
+impl IterationCount {
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if value.eq_ignore_ascii_case("infinite") {
+            return Some(Self::Infinite);
+        }
+        value.parse::<f32>().ok()
+            .filter(|count| *count >= 0.0)
+            .map(Self::Count)
+    }
+}
+
+/// Easing curve for transitions and animations
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimingFunction {
+    Linear,
+    Ease,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    CubicBezier(f32, f32, f32, f32),
+}
+
+impl TimingFunction {
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim().to_ascii_lowercase();
+        match value.as_str() {
+            "linear" => Some(Self::Linear),
+            "ease" => Some(Self::Ease),
+            "ease-in" => Some(Self::EaseIn),
+            "ease-out" => Some(Self::EaseOut),
+            "ease-in-out" => Some(Self::EaseInOut),
+            _ => {
+                let args = value.strip_prefix("cubic-bezier(")?.strip_suffix(')')?;
+                let points: Vec<f32> = args.split(',')
+                    .filter_map(|arg| arg.trim().parse().ok())
+                    .collect();
+                match points[..] {
+                    [x1, y1, x2, y2] if (0.0..=1.0).contains(&x1) && (0.0..=1.0).contains(&x2) => {
+                        Some(Self::CubicBezier(x1, y1, x2, y2))
+                    }
+                    _ => None,
+                }
+            }
+        }
+    }
+    
+    /// Map linear progress in [0, 1] to eased progress
+    pub fn apply(&self, progress: f32) -> f32 {
+        let progress = progress.clamp(0.0, 1.0);
+        let (x1, y1, x2, y2) = match *self {
+            Self::Linear => return progress,
+            Self::Ease => (0.25, 0.1, 0.25, 1.0),
+            Self::EaseIn => (0.42, 0.0, 1.0, 1.0),
+            Self::EaseOut => (0.0, 0.0, 0.58, 1.0),
+            Self::EaseInOut => (0.42, 0.0, 0.58, 1.0),
+            Self::CubicBezier(x1, y1, x2, y2) => (x1, y1, x2, y2),
+        };
+        
+        // Find the curve parameter whose x matches progress, then evaluate y there
+        let bezier = |a: f32, b: f32, t: f32| {
+            let u = 1.0 - t;
+            3.0 * u * u * t * a + 3.0 * u * t * t * b + t * t * t
+        };
+        let (mut low, mut high) = (0.0f32, 1.0f32);
+        let mut t = progress;
+        for _ in 0..32 {
+            let x = bezier(x1, x2, t);
+            if (x - progress).abs() < 1e-5 {
+                break;
+            }
+            if x < progress {
+                low = t;
+            } else {
+                high = t;
+            }
+            t = (low + high) / 2.0;
+        }
+        bezier(y1, y2, t)
+    }
+}
+
+/// A property value that can be interpolated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnimatableValue {
+    Number(f32),
+    Color(Color),
+}
+
+impl AnimatableValue {
+    /// Interpolate towards `to`; mismatched kinds jump at the halfway point
+    pub fn interpolate(&self, to: &AnimatableValue, progress: f32) -> AnimatableValue {
+        let lerp = |a: f32, b: f32| a + (b - a) * progress;
+        match (self, to) {
+            (Self::Number(a), Self::Number(b)) => Self::Number(lerp(*a, *b)),
+            (Self::Color(a), Self::Color(b)) => Self::Color(Color::new(
+                lerp(a.r, b.r),
+                lerp(a.g, b.g),
+                lerp(a.b, b.b),
+                lerp(a.a, b.a),
+            )),
+            _ if progress < 0.5 => *self,
+            _ => *to,
+        }
+    }
+}
+
+/// A property moving from one value to another
+#[derive(Debug, Clone)]
+struct RunningTransition {
+    property: String,
+    from: AnimatableValue,
+    to: AnimatableValue,
+    start: Instant,
+    duration: Duration,
+    timing_function: TimingFunction,
+}
+
+impl RunningTransition {
+    fn progress(&self, now: Instant) -> f32 {
+        let elapsed = now.saturating_duration_since(self.start);
+        (elapsed.as_secs_f32() / self.duration.as_secs_f32()).min(1.0)
+    }
+    
+    fn value_at(&self, now: Instant) -> AnimatableValue {
+        let eased = self.timing_function.apply(self.progress(now));
+        self.from.interpolate(&self.to, eased)
+    }
+}
+
+/// Keyframed values of one property, sorted by offset and covering 0 to 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyframeTrack {
+    pub property: String,
+    pub keyframes: Vec<(f32, AnimatableValue)>,
+}
+
+impl KeyframeTrack {
+    /// Value at `progress` through one iteration; easing applies between each pair of keyframes
+    fn value_at(&self, progress: f32, timing_function: TimingFunction) -> AnimatableValue {
+        let next = self.keyframes.iter()
+            .position(|(offset, _)| *offset >= progress)
+            .unwrap_or(self.keyframes.len() - 1)
+            .max(1);
+        let (from_offset, from) = self.keyframes[next - 1];
+        let (to_offset, to) = self.keyframes[next];
+        let local = if to_offset > from_offset { (progress - from_offset) / (to_offset - from_offset) } else { 1.0 };
+        from.interpolate(&to, timing_function.apply(local))
+    }
+}
+
+/// A keyframe animation started on an element
+#[derive(Debug, Clone)]
+struct RunningAnimation {
+    animation: Animation,
+    tracks: Vec<KeyframeTrack>,
+    /// The element's own values, shown again once the animation ends
+    underlying: Vec<(String, AnimatableValue)>,
+    start: Instant,
+    /// Kept after finishing so a restyle doesn't start it over
+    finished: bool,
+}
+
+impl RunningAnimation {
+    /// Iterations elapsed, including the fraction of the current one
+    fn iterations(&self, now: Instant) -> f32 {
+        now.saturating_duration_since(self.start).as_secs_f32() / self.animation.duration.as_secs_f32()
+    }
+    
+    fn is_done(&self, now: Instant) -> bool {
+        match self.animation.iteration_count {
+            IterationCount::Count(count) => self.iterations(now) >= count,
+            IterationCount::Infinite => false,
+        }
+    }
+    
+    /// Write the animated values at `now` into `style`, or the underlying ones once it's done
+    fn apply(&self, style: &mut ComputedStyle, now: Instant) {
+        if self.is_done(now) {
+            for (property, value) in &self.underlying {
+                style.set_animatable_value(property, *value);
+            }
+            return;
+        }
+        
+        let progress = self.iterations(now).fract();
+        for track in &self.tracks {
+            style.set_animatable_value(&track.property, track.value_at(progress, self.animation.timing_function));
+        }
+    }
+}
+
+/// Drives style interpolation over time, reporting which elements need repainting each frame
+#[derive(Debug, Default)]
+pub struct AnimationDriver {
+    running: HashMap<ElementId, Vec<RunningTransition>>,
+    animations: HashMap<ElementId, Vec<RunningAnimation>>,
+}
+
+impl AnimationDriver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Start transitions for watched properties that differ between `previous` and `new_style`.
+    /// Returns the style to display at `now`.
+    pub fn style_changed(
+        &mut self,
+        element_id: ElementId,
+        previous: &ComputedStyle,
+        new_style: &ComputedStyle,
+        now: Instant,
+    ) -> ComputedStyle {
+        let mut displayed = new_style.clone();
+        let mut running = self.running.remove(&element_id).unwrap_or_default();
+        
+        for property in ANIMATABLE_PROPERTIES {
+            let existing = running.iter().position(|t| t.property == *property);
+            
+            // A restyle that leaves the target alone doesn't disturb the transition towards it
+            if let Some(index) = existing.filter(|_| previous.animatable_value(property) == new_style.animatable_value(property)) {
+                displayed.set_animatable_value(property, running[index].value_at(now));
+                continue;
+            }
+            
+            // An interrupted transition restarts from wherever it had got to
+            let from = match existing {
+                Some(index) => Some(running.remove(index).value_at(now)),
+                None => previous.animatable_value(property),
+            };
+            
+            let (from, to) = match (from, new_style.animatable_value(property)) {
+                (Some(from), Some(to)) if from != to => (from, to),
+                _ => continue,
+            };
+            
+            if let Some(transition) = new_style.transition_for(property) {
+                running.push(RunningTransition {
+                    property: property.to_string(),
+                    from,
+                    to,
+                    start: now + transition.delay,
+                    duration: transition.duration,
+                    timing_function: transition.timing_function,
+                });
+                displayed.set_animatable_value(property, from);
+            }
+        }
+        
+        if !running.is_empty() {
+            self.running.insert(element_id, running);
+        }
+        displayed
+    }
+    
+    /// Replace an element's keyframe animations with `animations`. One already running
+    /// under the same name carries on rather than restarting. Returns the style to display at `now`.
+    pub fn set_animations(
+        &mut self,
+        element_id: ElementId,
+        style: &ComputedStyle,
+        animations: Vec<(Animation, Vec<KeyframeTrack>)>,
+        now: Instant,
+    ) -> ComputedStyle {
+        let mut previous = self.animations.remove(&element_id).unwrap_or_default();
+        let mut displayed = style.clone();
+        let mut running = Vec::new();
+        
+        for (animation, tracks) in animations {
+            if animation.duration.is_zero() || tracks.is_empty() {
+                continue;
+            }
+            let (start, finished) = match previous.iter().position(|existing| existing.animation.name == animation.name) {
+                Some(index) => {
+                    let existing = previous.remove(index);
+                    (existing.start, existing.finished)
+                }
+                None => (now, false),
+            };
+            let underlying = tracks.iter()
+                .filter_map(|track| Some((track.property.clone(), style.animatable_value(&track.property)?)))
+                .collect();
+            let animation = RunningAnimation { animation, tracks, underlying, start, finished };
+            if !animation.finished {
+                animation.apply(&mut displayed, now);
+            }
+            running.push(animation);
+        }
+        
+        if !running.is_empty() {
+            self.animations.insert(element_id, running);
+        }
+        displayed
+    }
+    
+    /// Stop tracking an element that has left the document
+    pub fn remove_element(&mut self, element_id: ElementId) {
+        self.running.remove(&element_id);
+        self.animations.remove(&element_id);
+    }
+    
+    /// Advance running transitions and animations, writing current values into `styles`.
+    /// Returns the elements whose style changed this frame.
+    pub fn tick(&mut self, now: Instant, styles: &mut HashMap<ElementId, ComputedStyle>) -> Vec<ElementId> {
+        let mut dirty = Vec::new();
+        
+        self.running.retain(|element_id, transitions| {
+            if let Some(style) = styles.get_mut(element_id) {
+                for transition in transitions.iter() {
+                    style.set_animatable_value(&transition.property, transition.value_at(now));
+                }
+                dirty.push(*element_id);
+            }
+            
+            transitions.retain(|transition| transition.progress(now) < 1.0);
+            !transitions.is_empty()
+        });
+        
+        for (element_id, animations) in &mut self.animations {
+            let Some(style) = styles.get_mut(element_id) else {
+                continue;
+            };
+            let mut changed = false;
+            for animation in animations.iter_mut().filter(|animation| !animation.finished) {
+                animation.apply(style, now);
+                animation.finished = animation.is_done(now);
+                changed = true;
+            }
+            if changed && !dirty.contains(element_id) {
+                dirty.push(*element_id);
+            }
+        }
+        
+        dirty
+    }
+    
+    /// Whether an element has transitions or animations in progress
+    pub fn is_animating(&self, element_id: ElementId) -> bool {
+        self.running.contains_key(&element_id)
+            || self.animations.get(&element_id)
+                .is_some_and(|animations| animations.iter().any(|animation| !animation.finished))
+    }
+    
+    /// Whether any transitions or animations are in progress
+    pub fn has_running_animations(&self) -> bool {
+        !self.running.is_empty()
+            || self.animations.values().flatten().any(|animation| !animation.finished)
+    }
+}
+
+/// Strip one pair of matching quotes
+fn unquote(value: &str) -> &str {
+    ['"', '\''].iter()
+        .find_map(|&quote| value.strip_prefix(quote)?.strip_suffix(quote))
+        .unwrap_or(value)
+}
+
+/// Key for comparing font family names
+fn normalize_family(family: &str) -> String {
+    unquote(family.trim()).to_lowercase()
+}
+
+/// Split on commas that aren't inside parentheses
+fn split_top_level_commas(value: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    
+    for (i, c) in value.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(value[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(value[start..].trim());
+    
+    parts.into_iter().filter(|part| !part.is_empty()).collect()
+}
+
+/// Nesting limit for `var()` substitution, which also stops reference cycles
+const MAX_VAR_DEPTH: usize = 32;
+
+/// Replace each `var(--name, fallback)` in `value` with the variable's value, or the
+/// fallback when it's undefined. `None` if a reference can't be resolved.
+fn substitute_var_references(value: &str, variables: &HashMap<String, String>, depth: usize) -> Option<String> {
+    if depth > MAX_VAR_DEPTH {
+        return None;
+    }
+    
+    let mut result = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("var(") {
+        result.push_str(&rest[..start]);
+        let arguments_start = start + "var(".len();
+        
+        // Find the matching close paren, allowing nested functions in the fallback
+        let mut depth_in_call = 1;
+        let mut arguments_end = None;
+        for (i, c) in rest[arguments_start..].char_indices() {
+            match c {
+                '(' => depth_in_call += 1,
+                ')' => {
+                    depth_in_call -= 1;
+                    if depth_in_call == 0 {
+                        arguments_end = Some(arguments_start + i);
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        let arguments_end = arguments_end?;
+        let arguments = &rest[arguments_start..arguments_end];
+        
+        let (name, fallback) = match split_top_level_comma_once(arguments) {
+            Some((name, fallback)) => (name.trim(), Some(fallback.trim())),
+            None => (arguments.trim(), None),
+        };
+        if !name.starts_with("--") {
+            return None;
+        }
+        let replacement = match (variables.get(name), fallback) {
+            (Some(defined), _) => substitute_var_references(defined, variables, depth + 1)?,
+            (None, Some(fallback)) => substitute_var_references(fallback, variables, depth + 1)?,
+            (None, None) => return None,
+        };
+        result.push_str(&replacement);
+        rest = &rest[arguments_end + 1..];
+    }
+    result.push_str(rest);
+    
+    Some(result.trim().to_string())
+}
+
+/// Split at the first comma outside parentheses; a fallback may itself contain commas
+fn split_top_level_comma_once(value: &str) -> Option<(&str, &str)> {
+    let mut depth = 0;
+    for (i, c) in value.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => return Some((&value[..i], &value[i + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split on whitespace that isn't inside parentheses
+fn split_components(value: &str) -> Vec<&str> {
+    let mut components = Vec::new();
+    let mut depth = 0;
+    let mut start = None;
+    
+    for (i, c) in value.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            c if c.is_whitespace() && depth == 0 => {
+                if let Some(s) = start.take() {
+                    components.push(&value[s..i]);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        start.get_or_insert(i);
+    }
+    if let Some(s) = start {
+        components.push(&value[s..]);
+    }
+    
+    components
+}
+
+/// Parse a CSS `<time>` such as `200ms` or `1.5s`
+fn parse_time(value: &str) -> Option<Duration> {
+    let value = value.trim().to_ascii_lowercase();
+    let seconds = if let Some(ms) = value.strip_suffix("ms") {
+        ms.parse::<f32>().ok()? / 1000.0
+    } else {
+        value.strip_suffix('s')?.parse::<f32>().ok()?
+    };
+    (seconds >= 0.0).then(|| Duration::from_secs_f32(seconds))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -648,19 +2275,467 @@ mod tests {
     fn test_length_parsing() {
         let engine = CSSEngine::new();
         
-        assert_eq!(engine.parse_length("16px"), Some(16.0));
-        assert_eq!(engine.parse_length("1em"), Some(16.0)); // Assuming 16px base
-        assert_eq!(engine.parse_length("50%"), Some(50.0));
+        let context = LengthContext::default();
+        
+        assert_eq!(engine.parse_length("16px", &context), Some(16.0));
+        assert_eq!(engine.parse_length("1em", &context), Some(16.0));
+        assert_eq!(engine.parse_length("1.5rem", &context), Some(24.0));
+        // Percentages need something to be relative to
+        assert_eq!(engine.parse_length("50%", &context), None);
+        assert_eq!(engine.parse_length("50%", &LengthContext { percent_of: Some(300.0), ..context }), Some(150.0));
     }
     
     #[test]
     fn test_box_values_parsing() {
         let engine = CSSEngine::new();
         
-        let single = engine.parse_box_values("10px");
+        let single = engine.parse_box_values("10px", &LengthContext::default());
         assert_eq!(single, BoxValues::all(10.0));
         
-        let quad = engine.parse_box_values("10px 20px 30px 40px");
+        let quad = engine.parse_box_values("10px 20px 30px 40px", &LengthContext::default());
         assert_eq!(quad, BoxValues::new(10.0, 20.0, 30.0, 40.0));
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_transition_shorthand_parsing() {
+        let engine = CSSEngine::new();
+        
+        let transitions = engine.parse_transitions("opacity 200ms ease-in 50ms, width 1s");
+        assert_eq!(transitions.len(), 2);
+        assert_eq!(transitions[0].property, "opacity");
+        assert_eq!(transitions[0].duration, Duration::from_millis(200));
+        assert_eq!(transitions[0].timing_function, TimingFunction::EaseIn);
+        assert_eq!(transitions[0].delay, Duration::from_millis(50));
+        assert_eq!(transitions[1].property, "width");
+        assert_eq!(transitions[1].timing_function, TimingFunction::Ease);
+        
+        let none = engine.parse_transitions("none");
+        assert_eq!(none.len(), 1);
+        assert_eq!(none[0].property, "none");
+        assert_eq!(
+            TimingFunction::parse("cubic-bezier(0.1, 0.7, 1.0, 0.1)"),
+            Some(TimingFunction::CubicBezier(0.1, 0.7, 1.0, 0.1))
+        );
+    }
+    
+    #[test]
+    fn test_transition_property_none_survives_later_longhands() {
+        let engine = CSSEngine::new();
+        let stylesheet = engine.parse_stylesheet(
+            ".a { transition-property: none; transition-duration: 1s; } \
+             .b { transition: none; transition-duration: 1s; } \
+             .c { transition-duration: 1s; }",
+            StylesheetOrigin::Author,
+        ).unwrap();
+        let stylesheets = vec![Arc::new(stylesheet)];
+        
+        let mut element = Element::new("div".to_string(), ElementId::new());
+        for class in ["a", "b"] {
+            element.set_attribute("class".to_string(), class.to_string());
+            let style = engine.compute_style(&element, &stylesheets);
+            assert_eq!(style.transitions.len(), 1);
+            assert_eq!(style.transitions[0].duration, Duration::from_secs(1));
+            assert!(style.transition_for("opacity").is_none());
+        }
+        
+        // Without `none`, a duration alone applies to the initial `all`
+        element.set_attribute("class".to_string(), "c".to_string());
+        let style = engine.compute_style(&element, &stylesheets);
+        assert!(style.transition_for("opacity").is_some());
+    }
+    
+    #[test]
+    fn test_opacity_transition_interpolates() {
+        let engine = CSSEngine::new();
+        let stylesheet = engine.parse_stylesheet(
+            ".box { opacity: 0; transition: opacity 200ms linear; } .visible { opacity: 1; }",
+            StylesheetOrigin::Author,
+        ).unwrap();
+        let stylesheets = vec![Arc::new(stylesheet)];
+        
+        let mut element = Element::new("div".to_string(), ElementId::new());
+        element.set_attribute("class".to_string(), "box".to_string());
+        let hidden = engine.compute_style(&element, &stylesheets);
+        assert_eq!(hidden.opacity, 0.0);
+        
+        // Toggling the class starts from the old value rather than snapping
+        let mut driver = AnimationDriver::new();
+        let start = Instant::now();
+        element.set_attribute("class".to_string(), "box visible".to_string());
+        let shown = engine.restyle_element(&element, &stylesheets, &hidden, &mut driver, start);
+        assert_eq!(shown.opacity, 0.0);
+        assert!(driver.is_animating(element.id));
+        
+        let mut styles = HashMap::new();
+        styles.insert(element.id, shown);
+        
+        let dirty = driver.tick(start + Duration::from_millis(100), &mut styles);
+        assert_eq!(dirty, vec![element.id]);
+        assert!((styles[&element.id].opacity - 0.5).abs() < 1e-3);
+        
+        // Once complete the final value sticks and the node stops being marked dirty
+        driver.tick(start + Duration::from_millis(250), &mut styles);
+        assert_eq!(styles[&element.id].opacity, 1.0);
+        assert!(!driver.has_running_animations());
+        assert!(driver.tick(start + Duration::from_millis(300), &mut styles).is_empty());
+    }
+    
+    #[test]
+    fn test_keyframe_animation_interpolates_opacity() {
+        let engine = CSSEngine::new();
+        let stylesheet = engine.parse_stylesheet(
+            "@keyframes fade { from { opacity: 0; } to { opacity: 1; } } \
+             .box { opacity: 0.2; animation: fade 1s linear 2; }",
+            StylesheetOrigin::Author,
+        ).unwrap();
+        let keyframes = match &stylesheet.rules[0] {
+            CSSRule::KeyframesRule(keyframes) => keyframes,
+            rule => panic!("expected @keyframes, got {:?}", rule),
+        };
+        assert_eq!(keyframes.name, "fade");
+        let offsets: Vec<Vec<f32>> = keyframes.keyframes.iter().map(Keyframe::offsets).collect();
+        assert_eq!(offsets, vec![vec![0.0], vec![1.0]]);
+        let stylesheets = vec![Arc::new(stylesheet)];
+        
+        let mut element = Element::new("div".to_string(), ElementId::new());
+        element.set_attribute("class".to_string(), "box".to_string());
+        let style = engine.compute_style(&element, &stylesheets);
+        assert_eq!(style.animation.animations(), vec![Animation {
+            name: "fade".to_string(),
+            duration: Duration::from_secs(1),
+            timing_function: TimingFunction::Linear,
+            iteration_count: IterationCount::Count(2.0),
+        }]);
+        
+        let mut driver = AnimationDriver::new();
+        let start = Instant::now();
+        let displayed = engine.animate_element(element.id, &style, &stylesheets, &mut driver, start);
+        assert_eq!(displayed.opacity, 0.0);
+        
+        let mut styles = HashMap::new();
+        styles.insert(element.id, displayed);
+        let dirty = driver.tick(start + Duration::from_millis(500), &mut styles);
+        assert_eq!(dirty, vec![element.id]);
+        assert!((styles[&element.id].opacity - 0.5).abs() < 1e-3);
+        
+        // A restyle mid-animation carries on instead of restarting
+        let restyled = engine.animate_element(element.id, &style, &stylesheets, &mut driver, start + Duration::from_millis(1250));
+        assert!((restyled.opacity - 0.25).abs() < 1e-3);
+        
+        // After the last iteration the element's own value comes back
+        driver.tick(start + Duration::from_millis(2100), &mut styles);
+        assert_eq!(styles[&element.id].opacity, 0.2);
+        assert!(!driver.has_running_animations());
+        assert!(driver.tick(start + Duration::from_millis(2200), &mut styles).is_empty());
+    }
+    
+    #[test]
+    fn test_out_of_range_keyframe_selectors_are_ignored() {
+        let engine = CSSEngine::new();
+        let stylesheet = engine.parse_stylesheet(
+            "@keyframes broken { 150% { opacity: 0; } 50 { opacity: 0.3; } } \
+             @keyframes partly { 150% { width: 10px; } 50% { opacity: 0.5; } } \
+             .broken { opacity: 0.2; animation: broken 1s linear; } \
+             .partly { opacity: 0.2; animation: partly 1s linear; }",
+            StylesheetOrigin::Author,
+        ).unwrap();
+        let stylesheets = vec![Arc::new(stylesheet)];
+        let mut driver = AnimationDriver::new();
+        let start = Instant::now();
+        
+        // No valid keyframe at all: nothing to animate, and no panic
+        let mut broken = Element::new("div".to_string(), ElementId::new());
+        broken.set_attribute("class".to_string(), "broken".to_string());
+        let style = engine.compute_style(&broken, &stylesheets);
+        let displayed = engine.animate_element(broken.id, &style, &stylesheets, &mut driver, start);
+        assert_eq!(displayed.opacity, 0.2);
+        
+        // Valid keyframes still run; the out-of-range one adds no track
+        let mut partly = Element::new("div".to_string(), ElementId::new());
+        partly.set_attribute("class".to_string(), "partly".to_string());
+        let style = engine.compute_style(&partly, &stylesheets);
+        engine.animate_element(partly.id, &style, &stylesheets, &mut driver, start);
+        let mut styles = HashMap::new();
+        styles.insert(partly.id, style.clone());
+        driver.tick(start + Duration::from_millis(500), &mut styles);
+        assert!((styles[&partly.id].opacity - 0.5).abs() < 1e-3);
+        assert_eq!(styles[&partly.id].width, style.width);
+    }
+    
+    #[test]
+    fn test_selectors_parsed_once() {
+        let engine = CSSEngine::new();
+        let ua_parses = engine.selector_parses.load(Ordering::Relaxed);
+        
+        let css: String = (0..200).map(|i| format!(".item-{} {{ width: {}px; }}\n", i, i)).collect();
+        let stylesheet = Arc::new(engine.parse_stylesheet(&format!("{}#main {{ height: 10px; }}", css), StylesheetOrigin::Author).unwrap());
+        assert_eq!(engine.selector_parses.load(Ordering::Relaxed), ua_parses + 201);
+        
+        let mut element = Element::new("div".to_string(), ElementId::new());
+        element.attributes.insert("class".to_string(), "item-7".to_string());
+        
+        let started = Instant::now();
+        for _ in 0..1000 {
+            let style = engine.compute_style(&element, &[stylesheet.clone()]);
+            assert_eq!(style.width, Some(7.0));
+        }
+        log::debug!("1000 x 201 selector matches took {:?}", started.elapsed());
+        
+        // Matching 1000 times against 201 rules parsed nothing new
+        assert_eq!(engine.selector_parses.load(Ordering::Relaxed), ua_parses + 201);
+    }
+
+    #[test]
+    fn test_parse_transform() {
+        let engine = CSSEngine::new();
+        
+        let functions = engine.parse_transform("translate(10px, 5px) scale(2) rotate(0.25turn)", &LengthContext::default());
+        assert_eq!(functions, vec![
+            TransformFunction::Translate(10.0, 5.0),
+            TransformFunction::Scale(2.0, 2.0),
+            TransformFunction::Rotate(90.0),
+        ]);
+        assert!(engine.parse_transform("none", &LengthContext::default()).is_empty());
+    }
+
+    #[test]
+    fn test_custom_property_resolution() {
+        let engine = CSSEngine::new();
+        let stylesheet = Arc::new(engine.parse_stylesheet(
+            ".card { --c: rgb(0, 0, 255); --pad: var(--unit, 4px); padding: var(--pad); } \
+             .box { color: var(--c); width: var(--missing, var(--fallback-width, 120px)); } \
+             .broken { height: var(--missing); }",
+            StylesheetOrigin::Author,
+        ).unwrap());
+        let stylesheets = vec![stylesheet];
+        
+        let mut element = Element::new("div".to_string(), ElementId::new());
+        element.set_attribute("style".to_string(), "--c: red; color: var(--c)".to_string());
+        let style = engine.compute_style(&element, &[]);
+        assert_eq!(style.color, Color::rgb(1.0, 0.0, 0.0));
+        assert_eq!(style.custom_properties["--c"], "red");
+        
+        // Undefined variables use the (nested) fallback
+        element.set_attribute("style".to_string(), String::new());
+        element.set_attribute("class".to_string(), "box".to_string());
+        let style = engine.compute_style(&element, &stylesheets);
+        assert_eq!(style.width, Some(120.0));
+        
+        // Children inherit their parent's variables; the nearest declaration wins
+        let mut parent = Element::new("section".to_string(), ElementId::new());
+        parent.set_attribute("class".to_string(), "card".to_string());
+        let parent_style = engine.compute_style(&parent, &stylesheets);
+        assert_eq!(parent_style.padding.top, 4.0);
+        let style = engine.compute_style_with_parent(&element, &stylesheets, Some(&parent_style));
+        assert_eq!(style.color, Color::rgb(0.0, 0.0, 1.0));
+        
+        // With no value and no fallback the declaration is ignored
+        element.set_attribute("class".to_string(), "broken".to_string());
+        assert_eq!(engine.compute_style(&element, &stylesheets).height, None);
+    }
+
+    #[test]
+    fn test_inherited_properties_propagate() {
+        let engine = CSSEngine::new();
+        let parser = crate::html::HTMLParser::new();
+        let document = parser.parse(
+            "<html><body><div id=\"parent\" style=\"color: red; font-size: 20px; width: 300px\">\
+             <span id=\"child\">text</span><p id=\"sized\" style=\"width: inherit\"></p></div></body></html>",
+        ).unwrap();
+        
+        let styles = engine.compute_document_styles(&document, &[]);
+        let style_of = |id: &str| &styles[&document.get_element_by_id(id).unwrap().id];
+        
+        let child = style_of("child");
+        assert_eq!(child.color, Color::rgb(1.0, 0.0, 0.0));
+        assert_eq!(child.font_size, 20.0);
+        // Non-inherited properties stay at their initial value unless asked for
+        assert_eq!(child.width, None);
+        assert_eq!(style_of("sized").width, Some(300.0));
+        assert_eq!(style_of("sized").color, Color::rgb(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_class_selectors_use_document_class_index() {
+        let engine = CSSEngine::new();
+        let stylesheet = Arc::new(engine.parse_stylesheet(
+            ".wide { width: 300px } p.wide:first-child { height: 20px } .missing { opacity: 0.5 }",
+            StylesheetOrigin::Author,
+        ).unwrap());
+        let mut document = crate::html::HTMLParser::new().parse(
+            "<html><body><p id=\"a\" class=\"wide\"></p><p id=\"b\"></p></body></html>",
+        ).unwrap();
+        let a = document.get_element_by_id("a").unwrap().id;
+        let b = document.get_element_by_id("b").unwrap().id;
+        
+        let styles = engine.compute_document_styles(&document, &[stylesheet.clone()]);
+        assert_eq!((styles[&a].width, styles[&a].height), (Some(300.0), Some(20.0)));
+        assert_eq!(styles[&b].width, None);
+        assert_eq!(styles[&a].opacity, 1.0);
+        
+        // Class changes made through the document reach the index the cascade consults
+        document.set_attribute(b, "class", "wide").unwrap();
+        document.remove_attribute(a, "class").unwrap();
+        let styles = engine.compute_document_styles(&document, &[stylesheet]);
+        assert_eq!(styles[&a].width, None);
+        assert_eq!((styles[&b].width, styles[&b].height), (Some(300.0), None));
+    }
+    
+    #[test]
+    fn test_relative_lengths_use_font_context() {
+        let engine = CSSEngine::new();
+        let parser = crate::html::HTMLParser::new();
+        let document = parser.parse(
+            "<html style=\"font-size: 10px\"><body><div id=\"parent\" style=\"font-size: 20px; width: 400px\">\
+             <p id=\"em\" style=\"font-size: 2em; margin: 1em\"></p>\
+             <p id=\"rem\" style=\"width: 1.5rem; height: 50%\"></p>\
+             <p id=\"percent\" style=\"width: 25%; font-size: 150%\"></p></div></body></html>",
+        ).unwrap();
+        
+        let styles = engine.compute_document_styles(&document, &[]);
+        let style_of = |id: &str| &styles[&document.get_element_by_id(id).unwrap().id];
+        
+        // font-size in em is relative to the parent; other em lengths to the element's own size
+        assert_eq!(style_of("em").font_size, 40.0);
+        assert_eq!(style_of("em").margin.top, 40.0);
+        assert_eq!(style_of("rem").width, Some(15.0));
+        // Percentage sizes are left for layout to resolve against the containing block
+        assert_eq!(style_of("rem").height, None);
+        assert_eq!(style_of("rem").height_percent, Some(0.5));
+        assert_eq!(style_of("percent").width, None);
+        assert_eq!(style_of("percent").width_percent, Some(0.25));
+        assert_eq!(style_of("percent").font_size, 30.0);
+    }
+    
+    #[test]
+    fn test_font_face_rules_are_collected() {
+        let engine = CSSEngine::new();
+        let stylesheet = engine.parse_stylesheet(
+            "@font-face { font-family: \"Brand Sans\"; src: local('Brand Sans Regular'), \
+             url(brand.woff2) format('woff2'), url(\"/fonts/brand.ttf\") format(\"truetype\"), url(brand.otf) } \
+             @font-face { src: url(orphan.ttf) } \
+             p { font-family: 'Brand Sans', serif }",
+            StylesheetOrigin::Author,
+        ).unwrap();
+        assert_eq!(stylesheet.rules.len(), 3);
+        
+        let faces = collect_font_faces(&[Arc::new(stylesheet)]);
+        assert_eq!(faces.len(), 1, "a rule without font-family is ignored");
+        assert_eq!(faces[0].family, "Brand Sans");
+        assert_eq!(faces[0].base_url, None);
+        assert_eq!(faces[0].sources, vec![
+            FontFaceSource::Local("Brand Sans Regular".to_string()),
+            FontFaceSource::Url { url: "brand.woff2".to_string(), format: Some("woff2".to_string()) },
+            FontFaceSource::Url { url: "/fonts/brand.ttf".to_string(), format: Some("truetype".to_string()) },
+            FontFaceSource::Url { url: "brand.otf".to_string(), format: None },
+        ]);
+        let supported: Vec<bool> = faces[0].sources.iter().map(FontFaceSource::is_supported).collect();
+        assert_eq!(supported, vec![true, false, true, true]);
+    }
+    
+    #[tokio::test]
+    async fn test_font_faces_carry_their_stylesheet_url() {
+        let engine = CSSEngine::new();
+        let mut document = crate::html::HTMLParser::new().parse(
+            "<html><head><link id=\"sheet\" rel=\"stylesheet\" href=\"/static/css/site.css\">\
+             <style>@font-face { font-family: Inline; src: url(inline.ttf) }</style></head><body></body></html>",
+        ).unwrap();
+        document.url = "https://example.com/blog/post.html".to_string();
+        let link_id = document.get_element_by_id("sheet").unwrap().id;
+        let external = HashMap::from([(link_id, "@font-face { font-family: Linked; src: url(../fonts/linked.ttf) }".to_string())]);
+        
+        let stylesheets = engine.parse_stylesheets_with(&document, &external).await.unwrap();
+        let faces = collect_font_faces(&stylesheets);
+        let base_urls: Vec<(&str, Option<&str>)> = faces.iter()
+            .map(|face| (face.family.as_str(), face.base_url.as_deref()))
+            .collect();
+        assert_eq!(base_urls, vec![
+            ("Linked", Some("https://example.com/static/css/site.css")),
+            ("Inline", None),
+        ]);
+    }
+    
+    #[test]
+    fn test_font_family_prefers_loaded_web_font() {
+        let engine = CSSEngine::new();
+        let stylesheet = Arc::new(engine.parse_stylesheet(
+            "p { font-family: Missing, \"Brand Sans\", serif }",
+            StylesheetOrigin::Author,
+        ).unwrap());
+        let element = Element::new("p".to_string(), ElementId::new());
+        
+        // Until the font loads the list is kept as written
+        let style = engine.compute_style(&element, &[stylesheet.clone()]);
+        assert_eq!(style.font_family, "Missing, \"Brand Sans\", serif");
+        
+        engine.set_web_fonts(&["brand sans".to_string()]);
+        let style = engine.compute_style(&element, &[stylesheet.clone()]);
+        assert_eq!(style.font_family, "Brand Sans");
+        
+        // The next page's fonts replace it
+        engine.set_web_fonts(&[]);
+        let style = engine.compute_style(&element, &[stylesheet]);
+        assert_eq!(style.font_family, "Missing, \"Brand Sans\", serif");
+    }
+    
+    #[test]
+    fn test_structural_pseudo_classes() {
+        let engine = CSSEngine::new();
+        let stylesheet = Arc::new(engine.parse_stylesheet(
+            "li:nth-child(odd) { width: 10px; } li:last-child { height: 5px; } \
+             li:not(.skip):nth-child(-n + 2) { opacity: 0.5; }",
+            StylesheetOrigin::Author,
+        ).unwrap());
+        let document = crate::html::HTMLParser::new().parse(
+            "<html><body><ul><li id=\"a\"></li><li id=\"b\" class=\"skip\"></li><li id=\"c\"></li><li id=\"d\"></li></ul></body></html>",
+        ).unwrap();
+        
+        let styles = engine.compute_document_styles(&document, &[stylesheet]);
+        let style_of = |id: &str| &styles[&document.get_element_by_id(id).unwrap().id];
+        let widths: Vec<Option<f32>> = ["a", "b", "c", "d"].iter().map(|id| style_of(id).width).collect();
+        assert_eq!(widths, vec![Some(10.0), None, Some(10.0), None]);
+        assert_eq!(style_of("d").height, Some(5.0));
+        assert_eq!(style_of("c").height, None);
+        assert_eq!(style_of("a").opacity, 0.5);
+        assert_eq!(style_of("b").opacity, 1.0);
+        assert_eq!(style_of("c").opacity, 1.0);
+        
+        assert_eq!(
+            ParsedSelector::parse("li:nth-child(2n+1)"),
+            ParsedSelector::Compound(vec![
+                ParsedSelector::Type("li".to_string()),
+                ParsedSelector::PseudoClass(PseudoClass::NthChild { a: 2, b: 1 }),
+            ])
+        );
+    }
+    
+    #[test]
+    fn test_hover_applies_from_element_state() {
+        let engine = CSSEngine::new();
+        let stylesheet = Arc::new(engine.parse_stylesheet(
+            "a { color: blue; } a:hover { color: red; }",
+            StylesheetOrigin::Author,
+        ).unwrap());
+        let mut document = crate::html::HTMLParser::new().parse(
+            "<html><body><p id=\"p\"><a id=\"link\" href=\"/\">link</a></p></body></html>",
+        ).unwrap();
+        let link = document.get_element_by_id("link").unwrap().id;
+        let paragraph = document.get_element_by_id("p").unwrap().id;
+        
+        let styles = engine.compute_document_styles(&document, &[stylesheet.clone()]);
+        assert_eq!(styles[&link].color, Color::new(0.0, 0.0, 1.0, 1.0));
+        
+        // Hovering the link also hovers its ancestors, and marks them for restyle
+        document.take_dirty_elements();
+        document.set_element_state(ElementState::Hover, Some(link));
+        assert!(document.has_element_state(paragraph, ElementState::Hover));
+        assert!(document.take_dirty_elements().contains(&link));
+        let styles = engine.compute_document_styles(&document, &[stylesheet.clone()]);
+        assert_eq!(styles[&link].color, Color::new(1.0, 0.0, 0.0, 1.0));
+        
+        document.set_element_state(ElementState::Hover, None);
+        assert!(document.take_dirty_elements().contains(&link));
+        let styles = engine.compute_document_styles(&document, &[stylesheet]);
+        assert_eq!(styles[&link].color, Color::new(0.0, 0.0, 1.0, 1.0));
+    }
+}
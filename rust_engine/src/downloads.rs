@@ -0,0 +1,685 @@
+//! Background downloads with pause, resume, and cancel
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use hyper::body::HttpBody;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{broadcast, Mutex, Notify};
+use tokio::task::JoinHandle;
+use crate::core::{Result, EngineError, BrowserEvent, EventHandlers, NetworkErrorKind};
+use crate::networking::NetworkStack;
+use crate::storage::{DownloadRecord, DownloadStatus, StorageEngine};
+
+type DownloadMap = Arc<Mutex<HashMap<String, ActiveDownload>>>;
+type DownloadStore = Option<Arc<Mutex<StorageEngine>>>;
+
+/// How many bytes may arrive between progress writes to the downloads table
+const PERSIST_INTERVAL: u64 = 1024 * 1024;
+
+/// Runs downloads in the background and tracks their progress
+pub struct DownloadManager {
+    /// Network stack used for transfers
+    network: Arc<NetworkStack>,
+
+    /// Downloads started this session, keyed by id
+    downloads: DownloadMap,
+
+    /// Where progress events are delivered
+    events: ProgressEvents,
+
+    /// Downloads table that records survive in across sessions
+    store: DownloadStore,
+
+    /// Directory that named downloads are saved into
+    download_dir: PathBuf,
+}
+
+/// Progress event broadcaster and the engine's registered handlers
+#[derive(Clone)]
+struct ProgressEvents {
+    sender: broadcast::Sender<BrowserEvent>,
+    handlers: EventHandlers,
+}
+
+/// A download and its running transfer, if any
+struct ActiveDownload {
+    record: DownloadRecord,
+    transfer: Option<Transfer>,
+}
+
+/// Handle to a transfer task that stops at the next chunk boundary when signalled
+struct Transfer {
+    stop: Arc<Notify>,
+    task: JoinHandle<()>,
+}
+
+impl DownloadManager {
+    pub fn new(network: Arc<NetworkStack>, event_sender: broadcast::Sender<BrowserEvent>) -> Self {
+        Self {
+            network,
+            downloads: Arc::new(Mutex::new(HashMap::new())),
+            events: ProgressEvents {
+                sender: event_sender,
+                handlers: Arc::new(tokio::sync::RwLock::new(Vec::new())),
+            },
+            store: None,
+            download_dir: default_download_dir(),
+        }
+    }
+
+    /// Persist download records and progress through `store`
+    pub fn set_store(&mut self, store: Arc<Mutex<StorageEngine>>) {
+        self.store = Some(store);
+    }
+
+    /// Also deliver progress events to `handlers`
+    pub fn set_event_handlers(&mut self, handlers: EventHandlers) {
+        self.events.handlers = handlers;
+    }
+
+    /// Save named downloads into `dir` instead of the user's downloads folder
+    pub fn set_download_dir(&mut self, dir: impl Into<PathBuf>) {
+        self.download_dir = dir.into();
+    }
+
+    pub fn download_dir(&self) -> &Path {
+        &self.download_dir
+    }
+
+    /// Start downloading `url` into the download directory, naming the file after
+    /// `suggested_filename` or the URL and never overwriting an existing file
+    pub async fn start_named(&self, url: &str, suggested_filename: Option<&str>) -> Result<String> {
+        let filename = suggested_filename
+            .and_then(sanitize_filename)
+            .or_else(|| filename_from_url(url))
+            .unwrap_or_else(|| "download".to_string());
+
+        tokio::fs::create_dir_all(&self.download_dir).await
+            .map_err(|e| EngineError::StorageError(format!("Failed to create {}: {}", self.download_dir.display(), e)))?;
+
+        let path = {
+            let downloads = self.downloads.lock().await;
+            unique_path(&self.download_dir, &filename, |candidate| {
+                candidate.exists()
+                    || downloads.values().any(|d| Path::new(&d.record.path) == candidate)
+            })
+        };
+
+        self.start(url, path).await
+    }
+
+    /// Start downloading `url` to `path`, returning the download's id
+    pub async fn start(&self, url: &str, path: impl AsRef<Path>) -> Result<String> {
+        let path = path.as_ref();
+        let record = DownloadRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            url: url.to_string(),
+            filename: path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "download".to_string()),
+            path: path.to_string_lossy().into_owned(),
+            status: DownloadStatus::InProgress,
+            total_bytes: None,
+            downloaded_bytes: 0,
+            created_at: chrono::Utc::now().timestamp(),
+            completed_at: None,
+        };
+        let id = record.id.clone();
+
+        if let Some(store) = &self.store {
+            store.lock().await.save_download(&record).await?;
+        }
+
+        // Announce the download before its transfer can report any progress
+        self.events.emit(&record).await;
+
+        let mut downloads = self.downloads.lock().await;
+        let transfer = self.spawn_transfer(&record, 0);
+        downloads.insert(id.clone(), ActiveDownload {
+            record,
+            transfer: Some(transfer),
+        });
+
+        Ok(id)
+    }
+
+    /// All known downloads, including ones recorded by earlier sessions, newest first
+    pub async fn list(&self) -> Vec<DownloadRecord> {
+        let mut records: Vec<_> = {
+            let downloads = self.downloads.lock().await;
+            downloads.values().map(|d| d.record.clone()).collect()
+        };
+
+        if let Some(store) = &self.store {
+            match store.lock().await.list_downloads().await {
+                Ok(persisted) => {
+                    for record in persisted {
+                        if !records.iter().any(|r| r.id == record.id) {
+                            records.push(record);
+                        }
+                    }
+                }
+                Err(e) => log::warn!("Failed to list persisted downloads: {:?}", e),
+            }
+        }
+
+        records.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        records
+    }
+
+    /// Current state of a download
+    pub async fn status(&self, id: &str) -> Option<DownloadRecord> {
+        if let Some(download) = self.downloads.lock().await.get(id) {
+            return Some(download.record.clone());
+        }
+        let store = self.store.as_ref()?;
+        let record = store.lock().await.download_status(id).await.ok()?;
+        record
+    }
+
+    /// Stop a transfer, keeping what has been written so it can be resumed
+    pub async fn pause(&self, id: &str) -> Result<DownloadRecord> {
+        self.stop_transfer(id, DownloadStatus::Paused).await
+    }
+
+    /// Continue a paused or failed download from where it stopped, including ones
+    /// interrupted in an earlier session
+    pub async fn resume(&self, id: &str) -> Result<DownloadRecord> {
+        let mut downloads = self.downloads.lock().await;
+        if !downloads.contains_key(id) {
+            let record = self.load_interrupted(id).await?;
+            downloads.insert(id.to_string(), ActiveDownload { record, transfer: None });
+        }
+        let download = downloads.get_mut(id)
+            .ok_or_else(|| unknown_download(id))?;
+
+        if !matches!(download.record.status, DownloadStatus::Paused | DownloadStatus::Failed) {
+            return Err(EngineError::network(NetworkErrorKind::Other, None, format!("Download {} is not paused", id)));
+        }
+
+        download.record.status = DownloadStatus::InProgress;
+        download.transfer = Some(self.spawn_transfer(&download.record, download.record.downloaded_bytes));
+        let record = download.record.clone();
+        drop(downloads);
+
+        // Handlers may query the manager, so they run without the lock
+        self.events.emit(&record).await;
+        persist(&self.store, &record).await;
+
+        Ok(record)
+    }
+
+    /// Load a download recorded by an earlier session; one left in progress was interrupted
+    /// by shutdown and is treated as failed
+    async fn load_interrupted(&self, id: &str) -> Result<DownloadRecord> {
+        let store = self.store.as_ref().ok_or_else(|| unknown_download(id))?;
+        let mut record = store.lock().await.download_status(id).await?
+            .ok_or_else(|| unknown_download(id))?;
+
+        if record.status == DownloadStatus::InProgress {
+            record.status = DownloadStatus::Failed;
+        }
+
+        // Trust the file over the record, which is only written every PERSIST_INTERVAL bytes
+        record.downloaded_bytes = tokio::fs::metadata(&record.path).await
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+
+        Ok(record)
+    }
+
+    /// Stop a download and delete the partial file
+    pub async fn cancel(&self, id: &str) -> Result<DownloadRecord> {
+        let record = self.stop_transfer(id, DownloadStatus::Cancelled).await?;
+        if record.status == DownloadStatus::Cancelled {
+            let _ = tokio::fs::remove_file(&record.path).await;
+        }
+        Ok(record)
+    }
+
+    /// Signal the transfer to stop, wait for it, then record the new status
+    async fn stop_transfer(&self, id: &str, status: DownloadStatus) -> Result<DownloadRecord> {
+        let transfer = {
+            let mut downloads = self.downloads.lock().await;
+            let download = downloads.get_mut(id)
+                .ok_or_else(|| unknown_download(id))?;
+            if download.record.status.is_finished() {
+                return Err(EngineError::network(NetworkErrorKind::Other, None, format!("Download {} has already finished", id)));
+            }
+            download.transfer.take()
+        };
+
+        // The task takes the lock after every chunk, so it must be awaited without holding it
+        if let Some(transfer) = transfer {
+            transfer.stop.notify_one();
+            let _ = transfer.task.await;
+        }
+
+        let mut downloads = self.downloads.lock().await;
+        let download = downloads.get_mut(id)
+            .ok_or_else(|| unknown_download(id))?;
+
+        // A transfer that completed before it saw the request stays complete
+        if download.record.status == DownloadStatus::Completed {
+            return Ok(download.record.clone());
+        }
+        download.record.status = status;
+        let record = download.record.clone();
+        drop(downloads);
+
+        self.events.emit(&record).await;
+        persist(&self.store, &record).await;
+
+        Ok(record)
+    }
+
+    fn spawn_transfer(&self, record: &DownloadRecord, offset: u64) -> Transfer {
+        let stop = Arc::new(Notify::new());
+        let network = self.network.clone();
+        let downloads = self.downloads.clone();
+        let events = self.events.clone();
+        let store = self.store.clone();
+        let id = record.id.clone();
+        let url = record.url.clone();
+        let path = PathBuf::from(&record.path);
+        let stop_signal = stop.clone();
+
+        let task = tokio::spawn(async move {
+            let outcome = transfer(&network, &downloads, &events, &store, &id, &url, &path, offset, &stop_signal).await;
+
+            let status = match outcome {
+                Ok(true) => DownloadStatus::Completed,
+                Ok(false) => return, // Stopped; the caller records the new status
+                Err(e) => {
+                    log::warn!("Download {} failed: {:?}", url, e);
+                    DownloadStatus::Failed
+                }
+            };
+
+            let record = update_download(&downloads, &events, &id, |record| {
+                record.status = status;
+                if status == DownloadStatus::Completed {
+                    record.completed_at = Some(chrono::Utc::now().timestamp());
+                }
+            }).await;
+            if let Some(record) = record {
+                persist(&store, &record).await;
+            }
+        });
+
+        Transfer { stop, task }
+    }
+}
+
+/// Stream `url` into `path` from `offset`; returns false if stopped before finishing
+#[allow(clippy::too_many_arguments)]
+async fn transfer(
+    network: &NetworkStack,
+    downloads: &DownloadMap,
+    events: &ProgressEvents,
+    store: &DownloadStore,
+    id: &str,
+    url: &str,
+    path: &Path,
+    offset: u64,
+    stop: &Notify,
+) -> Result<bool> {
+    let response = tokio::select! {
+        response = network.fetch_stream(url, offset) => response?,
+        _ = stop.notified() => return Ok(false),
+    };
+
+    // Servers that ignore the Range header send the whole file again
+    let resumed = offset > 0 && response.status() == hyper::StatusCode::PARTIAL_CONTENT;
+    let start = if resumed { offset } else { 0 };
+    let total_bytes = response.headers().get(hyper::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .map(|length| length + start);
+
+    let file = if resumed {
+        tokio::fs::OpenOptions::new().append(true).open(path).await
+    } else {
+        tokio::fs::File::create(path).await
+    };
+    let mut file = file.map_err(|e| EngineError::StorageError(format!("Failed to open {}: {}", path.display(), e)))?;
+
+    if let Some(record) = update_download(downloads, events, id, |record| {
+        record.total_bytes = total_bytes.or(record.total_bytes);
+        record.downloaded_bytes = start;
+    }).await {
+        persist(store, &record).await;
+    }
+
+    let mut body = response.into_body();
+    let mut downloaded = start;
+    let mut persisted = start;
+    loop {
+        let chunk = tokio::select! {
+            chunk = body.data() => chunk,
+            _ = stop.notified() => return Ok(false),
+        };
+        let chunk = match chunk {
+            Some(chunk) => chunk.map_err(|e| EngineError::network(NetworkErrorKind::Other, Some(url), format!("Failed to read download: {}", e)))?,
+            None => break,
+        };
+
+        // Flush before reporting so the recorded offset always matches the file on disk
+        file.write_all(&chunk).await
+            .and(file.flush().await)
+            .map_err(|e| EngineError::StorageError(format!("Failed to write {}: {}", path.display(), e)))?;
+        downloaded += chunk.len() as u64;
+
+        let record = update_download(downloads, events, id, |record| record.downloaded_bytes = downloaded).await;
+        if downloaded - persisted >= PERSIST_INTERVAL {
+            if let Some(record) = record {
+                persist(store, &record).await;
+            }
+            persisted = downloaded;
+        }
+    }
+
+    Ok(true)
+}
+
+async fn update_download(
+    downloads: &DownloadMap,
+    events: &ProgressEvents,
+    id: &str,
+    update: impl FnOnce(&mut DownloadRecord),
+) -> Option<DownloadRecord> {
+    let record = {
+        let mut downloads = downloads.lock().await;
+        let download = downloads.get_mut(id)?;
+        update(&mut download.record);
+        download.record.clone()
+    };
+    events.emit(&record).await;
+    Some(record)
+}
+
+/// Write a download's progress and status to the store, if there is one
+async fn persist(store: &DownloadStore, record: &DownloadRecord) {
+    let Some(store) = store else { return };
+    let result = store.lock().await.update_download_progress(
+        &record.id,
+        record.downloaded_bytes,
+        record.total_bytes,
+        record.status,
+    ).await;
+    if let Err(e) = result {
+        log::warn!("Failed to persist download {}: {:?}", record.id, e);
+    }
+}
+
+/// `$HOME/Downloads`, or the temp directory when there is no home
+fn default_download_dir() -> PathBuf {
+    std::env::var_os("HOME")
+        .map(|home| PathBuf::from(home).join("Downloads"))
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// Reduce a suggested name to a single safe path component
+fn sanitize_filename(name: &str) -> Option<String> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    let sanitized: String = name.chars()
+        .map(|c| if c.is_control() || matches!(c, ':' | '*' | '?' | '"' | '<' | '>' | '|') { '_' } else { c })
+        .collect();
+    let sanitized = sanitized.trim().trim_matches('.');
+    (!sanitized.is_empty()).then(|| sanitized.to_string())
+}
+
+/// Name a download after the last segment of its URL path
+fn filename_from_url(url: &str) -> Option<String> {
+    let url = url::Url::parse(url).ok()?;
+    let segment = url.path_segments()?.filter(|segment| !segment.is_empty()).last()?;
+    let decoded = percent_encoding::percent_decode_str(segment).decode_utf8_lossy();
+    sanitize_filename(&decoded)
+}
+
+/// `dir/filename`, or `dir/name (n).ext` for the first `n` that isn't taken
+fn unique_path(dir: &Path, filename: &str, taken: impl Fn(&Path) -> bool) -> PathBuf {
+    let candidate = dir.join(filename);
+    if !taken(&candidate) {
+        return candidate;
+    }
+
+    let (stem, extension) = match filename.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, Some(extension)),
+        _ => (filename, None),
+    };
+    (1..)
+        .map(|n| match extension {
+            Some(extension) => dir.join(format!("{} ({}).{}", stem, n, extension)),
+            None => dir.join(format!("{} ({})", stem, n)),
+        })
+        .find(|candidate| !taken(candidate))
+        .expect("unbounded range always yields a free name")
+}
+
+impl ProgressEvents {
+    /// Broadcast a download's progress and pass it to each handler
+    async fn emit(&self, record: &DownloadRecord) {
+        let event = BrowserEvent::DownloadProgress {
+            id: record.id.clone(),
+            status: record.status,
+            downloaded_bytes: record.downloaded_bytes,
+            total_bytes: record.total_bytes,
+        };
+        let _ = self.sender.send(event.clone());
+
+        let handlers = self.handlers.read().await.clone();
+        for handler in handlers {
+            if let Err(e) = handler.handle_event(event.clone()).await {
+                log::warn!("Event handler failed: {}", e);
+            }
+        }
+    }
+}
+
+fn unknown_download(id: &str) -> EngineError {
+    EngineError::network(NetworkErrorKind::Other, None, format!("Unknown download {}", id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server};
+    use crate::networking::SecurityConfig;
+
+    /// Serve `payload` in small, slow chunks, honouring `Range: bytes=N-` and recording each offset
+    fn serve_with_ranges(payload: Arc<Vec<u8>>, offsets: Arc<std::sync::Mutex<Vec<u64>>>) -> SocketAddr {
+        let make_service = make_service_fn(move |_| {
+            let payload = payload.clone();
+            let offsets = offsets.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let offset = request.headers().get("range")
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| value.strip_prefix("bytes="))
+                        .and_then(|value| value.trim_end_matches('-').parse::<usize>().ok())
+                        .unwrap_or(0);
+                    offsets.lock().unwrap().push(offset as u64);
+
+                    let remaining = payload[offset..].to_vec();
+                    let length = remaining.len();
+                    let (mut sender, body) = Body::channel();
+                    tokio::spawn(async move {
+                        for chunk in remaining.chunks(1024) {
+                            if sender.send_data(bytes::Bytes::copy_from_slice(chunk)).await.is_err() {
+                                break;
+                            }
+                            tokio::time::sleep(Duration::from_millis(5)).await;
+                        }
+                    });
+
+                    let status = if offset > 0 { 206 } else { 200 };
+                    async move {
+                        Ok::<_, Infallible>(Response::builder()
+                            .status(status)
+                            .header("content-length", length)
+                            .body(body)
+                            .unwrap())
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    /// Wait for the first progress event matching `predicate`
+    async fn wait_for_progress(
+        events: &mut broadcast::Receiver<BrowserEvent>,
+        predicate: impl Fn(DownloadStatus, u64) -> bool,
+    ) {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                match events.recv().await {
+                    Ok(BrowserEvent::DownloadProgress { status, downloaded_bytes, .. })
+                        if predicate(status, downloaded_bytes) => return,
+                    Err(broadcast::error::RecvError::Closed) => panic!("event channel closed"),
+                    _ => {}
+                }
+            }
+        }).await.expect("timed out waiting for download progress");
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume_download() {
+        let payload: Arc<Vec<u8>> = Arc::new((0..64 * 1024).map(|i| (i % 251) as u8).collect());
+        let offsets = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let addr = serve_with_ranges(payload.clone(), offsets.clone());
+
+        let mut network = NetworkStack::new().await.unwrap();
+        network.update_security_config(SecurityConfig {
+            require_https: false,
+            ..SecurityConfig::default()
+        });
+        let (event_sender, mut events) = broadcast::channel(1024);
+        let manager = DownloadManager::new(Arc::new(network), event_sender);
+
+        let path = std::env::temp_dir().join(format!("titan-download-{}.bin", uuid::Uuid::new_v4()));
+        let id = manager.start(&format!("http://{}/file.bin", addr), &path).await.unwrap();
+
+        // Pause once part of the file has arrived
+        wait_for_progress(&mut events, |_, downloaded| downloaded > 0).await;
+        let paused = manager.pause(&id).await.unwrap();
+        assert_eq!(paused.status, DownloadStatus::Paused);
+        assert!(paused.downloaded_bytes > 0 && paused.downloaded_bytes < payload.len() as u64);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), paused.downloaded_bytes);
+        assert_eq!(paused.total_bytes, Some(payload.len() as u64));
+
+        // Resuming asks for exactly the bytes not yet written
+        manager.resume(&id).await.unwrap();
+        wait_for_progress(&mut events, |status, _| status == DownloadStatus::Completed).await;
+
+        assert_eq!(*offsets.lock().unwrap(), vec![0, paused.downloaded_bytes]);
+        let completed = manager.status(&id).await.unwrap();
+        assert_eq!(completed.downloaded_bytes, payload.len() as u64);
+        assert!(completed.completed_at.is_some());
+        assert_eq!(std::fs::read(&path).unwrap(), *payload);
+
+        // Finished downloads can't be paused or cancelled
+        assert!(manager.pause(&id).await.is_err());
+        assert!(manager.cancel(&id).await.is_err());
+        assert_eq!(manager.list().await.len(), 1);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_named_download_streams_to_disk_and_persists() {
+        let payload: Arc<Vec<u8>> = Arc::new((0..16 * 1024).map(|i| (i % 241) as u8).collect());
+        let addr = serve_with_ranges(payload.clone(), Arc::new(std::sync::Mutex::new(Vec::new())));
+
+        let mut network = NetworkStack::new().await.unwrap();
+        network.update_security_config(SecurityConfig {
+            require_https: false,
+            ..SecurityConfig::default()
+        });
+        let (event_sender, mut events) = broadcast::channel(1024);
+        let store = Arc::new(Mutex::new(StorageEngine::open(":memory:").await.unwrap()));
+        let dir = std::env::temp_dir().join(format!("titan-downloads-{}", uuid::Uuid::new_v4()));
+        let mut manager = DownloadManager::new(Arc::new(network), event_sender);
+        manager.set_store(store.clone());
+        manager.set_download_dir(&dir);
+
+        // A file already holding the name gets a numbered sibling instead of being overwritten
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("report.pdf"), b"existing").unwrap();
+
+        let url = format!("http://{}/files/report.pdf", addr);
+        let id = manager.start_named(&url, Some("../report.pdf")).await.unwrap();
+        let record = store.lock().await.download_status(&id).await.unwrap().unwrap();
+        assert_eq!(record.filename, "report (1).pdf");
+        assert_eq!(record.status, DownloadStatus::InProgress);
+
+        wait_for_progress(&mut events, |status, _| status == DownloadStatus::Completed).await;
+
+        let record = store.lock().await.download_status(&id).await.unwrap().unwrap();
+        assert_eq!(record.status, DownloadStatus::Completed);
+        assert_eq!(record.downloaded_bytes, payload.len() as u64);
+        assert!(record.completed_at.is_some());
+        assert_eq!(std::fs::read(dir.join("report (1).pdf")).unwrap(), *payload);
+        assert_eq!(std::fs::read(dir.join("report.pdf")).unwrap(), b"existing");
+
+        assert_eq!(filename_from_url("https://example.com/a/My%20File.zip?x=1").as_deref(), Some("My File.zip"));
+        assert_eq!(filename_from_url("https://example.com/"), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_progress_reaches_event_handlers() {
+        struct Recorder(std::sync::Mutex<Vec<DownloadStatus>>);
+
+        #[async_trait::async_trait]
+        impl crate::core::EventHandler for Recorder {
+            async fn handle_event(&self, event: BrowserEvent) -> Result<()> {
+                if let BrowserEvent::DownloadProgress { status, .. } = event {
+                    self.0.lock().unwrap().push(status);
+                }
+                Ok(())
+            }
+        }
+
+        let payload: Arc<Vec<u8>> = Arc::new(vec![7; 4096]);
+        let addr = serve_with_ranges(payload, Arc::new(std::sync::Mutex::new(Vec::new())));
+
+        let mut network = NetworkStack::new().await.unwrap();
+        network.update_security_config(SecurityConfig {
+            require_https: false,
+            ..SecurityConfig::default()
+        });
+        let (event_sender, mut events) = broadcast::channel(1024);
+        let recorder = Arc::new(Recorder(std::sync::Mutex::new(Vec::new())));
+        let handlers: EventHandlers = Arc::new(tokio::sync::RwLock::new(vec![recorder.clone() as _]));
+        let mut manager = DownloadManager::new(Arc::new(network), event_sender);
+        manager.set_event_handlers(handlers);
+
+        let path = std::env::temp_dir().join(format!("titan-download-{}.bin", uuid::Uuid::new_v4()));
+        manager.start(&format!("http://{}/file.bin", addr), &path).await.unwrap();
+        wait_for_progress(&mut events, |status, _| status == DownloadStatus::Completed).await;
+
+        // Handlers are called just after each broadcast, so the last one may still be running
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while recorder.0.lock().unwrap().last() != Some(&DownloadStatus::Completed) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.expect("handler never saw the download complete");
+        let statuses = recorder.0.lock().unwrap().clone();
+        assert_eq!(statuses.first(), Some(&DownloadStatus::InProgress));
+        assert_eq!(statuses.last(), Some(&DownloadStatus::Completed));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
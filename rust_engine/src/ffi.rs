@@ -2,244 +2,450 @@
 
 use std::ffi::{CStr, CString};
 use std::os::raw::{c_char, c_int, c_double};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::ptr;
-use std::sync::Once;
+use std::rc::Rc;
+use std::sync::{mpsc, Mutex, Once, PoisonError, RwLock};
+use std::thread::JoinHandle;
 use tokio::runtime::Runtime;
-use crate::{TitanEngine, EngineConfig};
+use tokio::sync::broadcast;
+use crate::{TitanEngine, EngineConfig, JSRuntime, PageHandle};
+use crate::core::{BrowserEvent, EngineError, NetworkErrorKind};
 
-static INIT: Once = Once::new();
-static mut RUNTIME: Option<Runtime> = None;
-static mut ENGINE: Option<TitanEngine> = None;
+static LOGGING: Once = Once::new();
+
+/// Thread that owns the engine; `None` until init and after shutdown. Only its command
+/// channel is shared: the engine and its V8 isolates never leave that thread.
+static ENGINE_THREAD: Mutex<Option<EngineThread>> = Mutex::new(None);
+
+struct EngineThread {
+    commands: mpsc::Sender<Command>,
+    thread: JoinHandle<()>,
+}
+
+/// A call run on the engine thread
+type Command = Box<dyn FnOnce(&EngineContext) + Send>;
+
+/// The engine together with the runtime that drives it, living on the engine thread
+struct EngineContext {
+    runtime: Runtime,
+    engine: TitanEngine,
+    pages: PageRegistry,
+}
+
+/// Pages opened over FFI, keyed by the handle returned from `titan_engine_load_page`.
+/// Only the engine thread touches it, so it needs no locking.
+#[derive(Default)]
+struct PageRegistry {
+    last_handle: Cell<u64>,
+    pages: RefCell<HashMap<u64, Rc<FfiPage>>>,
+}
+
+/// A loaded page with its own script context
+struct FfiPage {
+    page: PageHandle,
+    js_runtime: RefCell<JSRuntime>,
+}
+
+impl PageRegistry {
+    /// Store a page and return its handle; 0 is never issued
+    fn insert(&self, page: FfiPage) -> u64 {
+        let handle = self.last_handle.get() + 1;
+        self.last_handle.set(handle);
+        self.pages.borrow_mut().insert(handle, Rc::new(page));
+        handle
+    }
+    
+    fn get(&self, handle: u64) -> Option<Rc<FfiPage>> {
+        self.pages.borrow().get(&handle).cloned()
+    }
+    
+    fn remove(&self, handle: u64) -> bool {
+        self.pages.borrow_mut().remove(&handle).is_some()
+    }
+}
+
+impl EngineContext {
+    fn start() -> Option<Self> {
+        let runtime = match Runtime::new() {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                log::error!("Failed to create Tokio runtime: {}", e);
+                return None;
+            }
+        };
+        
+        let engine = match runtime.block_on(TitanEngine::new()) {
+            Ok(engine) => engine,
+            Err(e) => {
+                log::error!("Failed to create Titan Engine: {}", e);
+                return None;
+            }
+        };
+        
+        forward_download_progress(&engine, &runtime);
+        Some(Self {
+            runtime,
+            engine,
+            pages: PageRegistry::default(),
+        })
+    }
+}
+
+/// Clone the engine thread's command channel so the lock isn't held across engine calls
+fn engine_commands() -> Option<mpsc::Sender<Command>> {
+    ENGINE_THREAD.lock().unwrap_or_else(PoisonError::into_inner)
+        .as_ref()
+        .map(|engine_thread| engine_thread.commands.clone())
+}
+
+/// Run `f` on the engine thread and wait for its result, or return `None` if the engine
+/// isn't initialized. Calls from several threads queue up and run one at a time.
+fn with_context<T: Send + 'static>(f: impl FnOnce(&EngineContext) -> T + Send + 'static) -> Option<T> {
+    let (reply, result) = mpsc::channel();
+    let command: Command = Box::new(move |context| {
+        let _ = reply.send(f(context));
+    });
+    engine_commands()?.send(command).ok()?;
+    result.recv().ok()
+}
+
+/// Run `f` against the engine on its thread, or return `None` if it isn't initialized
+fn with_engine<T: Send + 'static>(f: impl FnOnce(&TitanEngine, &Runtime) -> T + Send + 'static) -> Option<T> {
+    with_context(move |context| f(&context.engine, &context.runtime))
+}
+
+/// Borrow a C string argument, rejecting null and invalid UTF-8.
+/// The caller must pass a NUL-terminated string that outlives the call.
+fn str_arg<'a>(ptr: *const c_char) -> Option<&'a str> {
+    if ptr.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(ptr) }.to_str().ok()
+}
+
+/// Hand a string to the caller, who frees it with `titan_engine_free_string`
+fn into_c_string(value: impl Into<Vec<u8>>) -> *mut c_char {
+    match CString::new(value) {
+        Ok(c_string) => c_string.into_raw(),
+        Err(_) => ptr::null_mut(),
+    }
+}
 
 /// Initialize the Titan Engine
 #[no_mangle]
 pub extern "C" fn titan_engine_init() -> c_int {
-    INIT.call_once(|| {
-        // Initialize logging
-        env_logger::init();
-        
-        // Create Tokio runtime
-        let rt = Runtime::new().expect("Failed to create Tokio runtime");
-        
-        // Create engine
-        let engine = rt.block_on(async {
-            TitanEngine::new().await.expect("Failed to create Titan Engine")
+    LOGGING.call_once(|| {
+        let _ = env_logger::try_init();
+    });
+    
+    // Hold the lock while starting so concurrent callers create one engine
+    let mut engine_thread = ENGINE_THREAD.lock().unwrap_or_else(PoisonError::into_inner);
+    if engine_thread.is_some() {
+        return 1;
+    }
+    
+    let (commands, receiver) = mpsc::channel::<Command>();
+    let (started, startup) = mpsc::channel();
+    let spawned = std::thread::Builder::new()
+        .name("titan-engine".to_string())
+        .spawn(move || {
+            let Some(context) = EngineContext::start() else {
+                let _ = started.send(false);
+                return;
+            };
+            let _ = started.send(true);
+            
+            // Runs until shutdown drops the last sender
+            for command in receiver {
+                command(&context);
+            }
+            let _ = context.runtime.block_on(context.engine.shutdown());
+            let EngineContext { runtime, engine, pages } = context;
+            drop(pages);
+            drop(engine);
+            runtime.shutdown_background();
         });
-        
-        unsafe {
-            RUNTIME = Some(rt);
-            ENGINE = Some(engine);
+    let thread = match spawned {
+        Ok(thread) => thread,
+        Err(e) => {
+            log::error!("Failed to start engine thread: {}", e);
+            return 0;
         }
-    });
+    };
     
+    if startup.recv() != Ok(true) {
+        let _ = thread.join();
+        return 0;
+    }
+    *engine_thread = Some(EngineThread { commands, thread });
     1 // Success
 }
 
+/// Report download progress to the host through the progress and event callbacks
+fn forward_download_progress(engine: &TitanEngine, runtime: &Runtime) {
+    let mut events = engine.subscribe_events();
+    runtime.spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(BrowserEvent::DownloadProgress { id, status, downloaded_bytes, total_bytes }) => {
+                    if let Some(total) = total_bytes.filter(|total| *total > 0) {
+                        notify_progress((downloaded_bytes as f64 / total as f64).min(1.0));
+                    }
+                    let data = serde_json::json!({
+                        "id": id,
+                        "status": status.as_str(),
+                        "downloaded_bytes": downloaded_bytes,
+                        "total_bytes": total_bytes,
+                    });
+                    notify_event("download_progress", &data.to_string());
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
 /// Shutdown the Titan Engine
 #[no_mangle]
 pub extern "C" fn titan_engine_shutdown() -> c_int {
-    unsafe {
-        if let Some(engine) = ENGINE.take() {
-            if let Some(rt) = &RUNTIME {
-                rt.block_on(async {
-                    let _ = engine.shutdown().await;
-                });
-            }
-        }
-        
-        if let Some(rt) = RUNTIME.take() {
-            rt.shutdown_background();
+    let engine_thread = ENGINE_THREAD.lock().unwrap_or_else(PoisonError::into_inner).take();
+    
+    if let Some(EngineThread { commands, thread }) = engine_thread {
+        // Calls already queued still run; the thread shuts the engine down once they're done.
+        // A host callback running on the engine thread can't wait for itself.
+        drop(commands);
+        if thread.thread().id() != std::thread::current().id() {
+            let _ = thread.join();
         }
     }
     
     1 // Success
 }
 
-/// Load a web page
+/// Load a web page into a new page handle; release it with `titan_engine_close_page`
 #[no_mangle]
 pub extern "C" fn titan_engine_load_page(url: *const c_char) -> *mut c_char {
-    if url.is_null() {
-        return ptr::null_mut();
-    }
-    
-    let url_str = unsafe {
-        match CStr::from_ptr(url).to_str() {
-            Ok(s) => s,
-            Err(_) => return ptr::null_mut(),
-        }
+    let url_str = match str_arg(url) {
+        Some(s) => s.to_string(),
+        None => return ptr::null_mut(),
     };
     
-    unsafe {
-        if let (Some(engine), Some(rt)) = (&ENGINE, &RUNTIME) {
-            let result = rt.block_on(async {
-                engine.load_page(url_str).await
-            });
-            
-            match result {
-                Ok(page_handle) => {
-                    let response = format!("{{\"success\": true, \"page_id\": \"{}\"}}", page_handle.id);
-                    match CString::new(response) {
-                        Ok(c_string) => c_string.into_raw(),
-                        Err(_) => ptr::null_mut(),
-                    }
-                }
-                Err(e) => {
-                    let error_response = format!("{{\"success\": false, \"error\": \"{}\"}}", e);
-                    match CString::new(error_response) {
-                        Ok(c_string) => c_string.into_raw(),
-                        Err(_) => ptr::null_mut(),
-                    }
-                }
+    let response = with_context(move |context| {
+        let result = context.runtime.block_on(async {
+            // Each page runs its scripts in its own runtime so pages can't see each other's globals
+            let mut js_runtime = context.engine.create_js_runtime().await?;
+            let page = context.engine.load_page_in(&url_str, &mut js_runtime).await?;
+            Ok::<_, anyhow::Error>((page, js_runtime))
+        });
+        
+        match result {
+            Ok((page, js_runtime)) => {
+                let page_id = page.id;
+                let handle = context.pages.insert(FfiPage {
+                    page,
+                    js_runtime: RefCell::new(js_runtime),
+                });
+                serde_json::json!({ "success": true, "handle": handle, "page_id": page_id.to_string() })
             }
-        } else {
-            ptr::null_mut()
+            Err(e) => engine_error_json("load_failed", &e),
         }
-    }
+    })
+    .unwrap_or_else(|| error_json("not_initialized", "Engine is not initialized"));
+    
+    into_c_string(response.to_string())
 }
 
-/// Execute JavaScript code
+/// Release a page handle; returns 0 if the handle is unknown
 #[no_mangle]
-pub extern "C" fn titan_engine_execute_javascript(code: *const c_char) -> *mut c_char {
-    if code.is_null() {
-        return ptr::null_mut();
+pub extern "C" fn titan_engine_close_page(handle: u64) -> c_int {
+    match with_context(|context| context.pages.remove(handle)) {
+        Some(true) => 1,
+        _ => 0,
     }
+}
+
+/// Execute JavaScript in the script context of a loaded page
+#[no_mangle]
+pub extern "C" fn titan_engine_page_execute_javascript(handle: u64, code: *const c_char) -> *mut c_char {
+    let code_str = match str_arg(code) {
+        Some(s) => s.to_string(),
+        None => return ptr::null_mut(),
+    };
     
-    let code_str = unsafe {
-        match CStr::from_ptr(code).to_str() {
-            Ok(s) => s,
-            Err(_) => return ptr::null_mut(),
+    let response = with_context(move |context| {
+        let page = match context.pages.get(handle) {
+            Some(page) => page,
+            None => return invalid_handle_json(handle),
+        };
+        
+        let result = {
+            let mut js_runtime = page.js_runtime.borrow_mut();
+            context.runtime.block_on(js_runtime.execute_script(&code_str, "ffi-script"))
+        };
+        
+        match result {
+            Ok(js_value) => serde_json::json!({ "success": true, "result": js_value.to_string() }),
+            Err(e) => engine_error_json("script_failed", &anyhow::Error::from(e)),
         }
+    })
+    .unwrap_or_else(|| error_json("not_initialized", "Engine is not initialized"));
+    
+    into_c_string(response.to_string())
+}
+
+/// Get the AI analysis computed when a page was loaded
+#[no_mangle]
+pub extern "C" fn titan_engine_page_get_ai_analysis(handle: u64) -> *mut c_char {
+    let response = with_context(|context| match context.pages.get(handle) {
+        Some(page) => serde_json::json!({ "success": true, "analysis": page.page.ai_context.as_ref() }),
+        None => invalid_handle_json(handle),
+    })
+    .unwrap_or_else(|| error_json("not_initialized", "Engine is not initialized"));
+    
+    into_c_string(response.to_string())
+}
+
+/// Execute JavaScript code
+#[no_mangle]
+pub extern "C" fn titan_engine_execute_javascript(code: *const c_char) -> *mut c_char {
+    let code_str = match str_arg(code) {
+        Some(s) => s.to_string(),
+        None => return ptr::null_mut(),
     };
     
-    unsafe {
-        if let (Some(engine), Some(rt)) = (&ENGINE, &RUNTIME) {
-            let result = rt.block_on(async {
-                let mut js_runtime = engine.js_runtime.write().await;
-                js_runtime.execute_script(code_str, "ffi-script").await
-            });
-            
-            match result {
-                Ok(js_value) => {
-                    let response = format!("{{\"success\": true, \"result\": \"{}\"}}", js_value.to_string());
-                    match CString::new(response) {
-                        Ok(c_string) => c_string.into_raw(),
-                        Err(_) => ptr::null_mut(),
-                    }
-                }
-                Err(e) => {
-                    let error_response = format!("{{\"success\": false, \"error\": \"{}\"}}", e);
-                    match CString::new(error_response) {
-                        Ok(c_string) => c_string.into_raw(),
-                        Err(_) => ptr::null_mut(),
-                    }
-                }
-            }
-        } else {
-            ptr::null_mut()
+    let response = with_engine(move |engine, rt| {
+        let result = rt.block_on(async {
+            let mut js_runtime = engine.js_runtime.write().await;
+            js_runtime.execute_script(&code_str, "ffi-script").await
+        });
+        
+        match result {
+            Ok(js_value) => format!("{{\"success\": true, \"result\": \"{}\"}}", js_value.to_string()),
+            Err(e) => format!("{{\"success\": false, \"error\": \"{}\"}}", e),
         }
-    }
+    });
+    
+    response.map_or(ptr::null_mut(), into_c_string)
 }
 
-/// Get AI analysis for a page
+/// Get AI analysis for the current page as serialized `PageContext` JSON.
+/// `url` may be null; when given it must match the loaded page.
 #[no_mangle]
 pub extern "C" fn titan_engine_get_ai_analysis(url: *const c_char) -> *mut c_char {
-    if url.is_null() {
-        return ptr::null_mut();
-    }
+    let requested_url = if url.is_null() {
+        None
+    } else {
+        match str_arg(url) {
+            Some(s) if !s.is_empty() => Some(s.to_string()),
+            Some(_) => None,
+            None => return ptr::null_mut(),
+        }
+    };
     
-    let url_str = unsafe {
-        match CStr::from_ptr(url).to_str() {
-            Ok(s) => s,
-            Err(_) => return ptr::null_mut(),
+    let response = with_engine(move |engine, rt| rt.block_on(async {
+        let current_page = match engine.current_page().await {
+            Some(page) => page,
+            None => return error_json("no_page_loaded", "No page is loaded"),
+        };
+        
+        if let Some(requested_url) = requested_url {
+            if requested_url != current_page.url {
+                return error_json(
+                    "page_not_loaded",
+                    &format!("{} is not the current page", requested_url),
+                );
+            }
         }
+        
+        match engine.analyze_current_page().await {
+            Ok(context) => serde_json::json!({ "success": true, "analysis": context }),
+            Err(e) => engine_error_json("analysis_failed", &e),
+        }
+    }))
+    .unwrap_or_else(|| error_json("not_initialized", "Engine is not initialized"));
+    
+    into_c_string(response.to_string())
+}
+
+/// Structured error payload for FFI callers
+fn error_json(code: &str, message: &str) -> serde_json::Value {
+    serde_json::json!({
+        "success": false,
+        "error": { "code": code, "message": message },
+    })
+}
+
+/// Error payload for a failed engine call, adding the URL, network failure kind,
+/// and a recovery hint when the error carries them
+fn engine_error_json(code: &str, error: &anyhow::Error) -> serde_json::Value {
+    let mut response = error_json(code, &error.to_string());
+    let Some(engine_error) = error.downcast_ref::<EngineError>() else {
+        return response;
     };
     
-    // Placeholder implementation
-    let analysis = format!(
-        r#"{{
-            "url": "{}",
-            "insights": [
-                {{
-                    "type": "readability",
-                    "title": "Content Analysis",
-                    "description": "Page content analyzed successfully",
-                    "confidence": 0.8
-                }}
-            ],
-            "sentiment": 0.1,
-            "language": "en"
-        }}"#,
-        url_str
-    );
-    
-    match CString::new(analysis) {
-        Ok(c_string) => c_string.into_raw(),
-        Err(_) => ptr::null_mut(),
+    let details = &mut response["error"];
+    if let Some(kind) = engine_error.network_kind() {
+        details["network_error"] = kind.as_str().into();
+        if let NetworkErrorKind::Status(status) = kind {
+            details["status"] = status.into();
+        }
     }
+    if let Some(url) = engine_error.url() {
+        details["url"] = url.into();
+    }
+    if let Some(hint) = engine_error.recovery_hint() {
+        details["hint"] = hint.into();
+    }
+    response
+}
+
+fn invalid_handle_json(handle: u64) -> serde_json::Value {
+    error_json("invalid_handle", &format!("No page is open for handle {}", handle))
 }
 
 /// Validate URL security
 #[no_mangle]
 pub extern "C" fn titan_engine_validate_url_security(url: *const c_char) -> c_int {
-    if url.is_null() {
-        return 0; // Invalid
-    }
-    
-    let url_str = unsafe {
-        match CStr::from_ptr(url).to_str() {
-            Ok(s) => s,
-            Err(_) => return 0,
-        }
+    let url_str = match str_arg(url) {
+        Some(s) => s.to_string(),
+        None => return 0, // Invalid
     };
     
-    unsafe {
-        if let (Some(engine), Some(rt)) = (&ENGINE, &RUNTIME) {
-            let result = rt.block_on(async {
-                engine.security.validate_url(url_str).await
-            });
-            
-            match result {
-                Ok(_) => 1, // Safe
-                Err(_) => 0, // Unsafe
-            }
-        } else {
-            0 // Engine not initialized
+    with_engine(move |engine, rt| {
+        match rt.block_on(engine.security.validate_url(&url_str)) {
+            Ok(_) => 1, // Safe
+            Err(_) => 0, // Unsafe
         }
-    }
+    })
+    .unwrap_or(0) // Engine not initialized
 }
 
 /// Get network metrics
 #[no_mangle]
 pub extern "C" fn titan_engine_get_network_metrics() -> *mut c_char {
-    unsafe {
-        if let (Some(engine), Some(rt)) = (&ENGINE, &RUNTIME) {
-            let metrics = rt.block_on(async {
-                engine.network_stack.get_metrics().await
-            });
-            
-            let metrics_json = format!(
-                r#"{{
-                    "total_requests": {},
-                    "failed_requests": {},
-                    "total_bytes_received": {},
-                    "cache_hit_ratio": {},
-                    "average_load_time_ms": {}
-                }}"#,
-                metrics.total_requests,
-                metrics.failed_requests,
-                metrics.total_bytes_received,
-                metrics.cache_hit_ratio(),
-                metrics.average_load_time.as_millis()
-            );
-            
-            match CString::new(metrics_json) {
-                Ok(c_string) => c_string.into_raw(),
-                Err(_) => ptr::null_mut(),
-            }
-        } else {
-            ptr::null_mut()
-        }
-    }
+    let metrics_json = with_engine(|engine, rt| {
+        let metrics = rt.block_on(engine.network_stack.get_metrics());
+        
+        format!(
+            r#"{{
+                "total_requests": {},
+                "failed_requests": {},
+                "total_bytes_received": {},
+                "cache_hit_ratio": {},
+                "average_load_time_ms": {}
+            }}"#,
+            metrics.total_requests,
+            metrics.failed_requests,
+            metrics.total_bytes_received,
+            metrics.cache_hit_ratio(),
+            metrics.average_load_time.as_millis()
+        )
+    });
+    
+    metrics_json.map_or(ptr::null_mut(), into_c_string)
 }
 
 /// Set engine configuration
@@ -268,23 +474,15 @@ pub extern "C" fn titan_engine_set_config(
     1
 }
 
-/// Get performance metrics
+/// Get performance metrics as serialized `PerformanceMetrics` JSON
 #[no_mangle]
 pub extern "C" fn titan_engine_get_performance_metrics() -> *mut c_char {
-    let metrics = r#"{
-        "memory_usage_mb": 128.5,
-        "cpu_usage_percent": 15.2,
-        "gpu_usage_percent": 8.7,
-        "render_fps": 60.0,
-        "javascript_execution_time_ms": 45,
-        "layout_time_ms": 12,
-        "paint_time_ms": 8
-    }"#;
-    
-    match CString::new(metrics) {
-        Ok(c_string) => c_string.into_raw(),
-        Err(_) => ptr::null_mut(),
-    }
+    let response = with_engine(|engine, rt| {
+        let metrics = rt.block_on(engine.collect_metrics());
+        serde_json::to_string(&metrics).ok()
+    });
+    
+    response.flatten().map_or(ptr::null_mut(), into_c_string)
 }
 
 /// Free a string allocated by the engine
@@ -300,22 +498,16 @@ pub extern "C" fn titan_engine_free_string(ptr: *mut c_char) {
 /// Get engine version
 #[no_mangle]
 pub extern "C" fn titan_engine_get_version() -> *mut c_char {
-    let version = "1.0.0";
-    match CString::new(version) {
-        Ok(c_string) => c_string.into_raw(),
-        Err(_) => ptr::null_mut(),
-    }
+    into_c_string("1.0.0")
 }
 
 /// Check if engine is initialized
 #[no_mangle]
 pub extern "C" fn titan_engine_is_initialized() -> c_int {
-    unsafe {
-        if ENGINE.is_some() && RUNTIME.is_some() {
-            1
-        } else {
-            0
-        }
+    if engine_commands().is_some() {
+        1
+    } else {
+        0
     }
 }
 
@@ -353,74 +545,77 @@ pub extern "C" fn titan_engine_media_set_volume(element_id: *const c_char, volum
 /// Storage functions
 #[no_mangle]
 pub extern "C" fn titan_engine_storage_set(key: *const c_char, value: *const c_char) -> c_int {
-    if key.is_null() || value.is_null() {
-        return 0;
-    }
-    
-    let key_str = unsafe {
-        match CStr::from_ptr(key).to_str() {
-            Ok(s) => s,
-            Err(_) => return 0,
-        }
+    let (key_str, value_str) = match (str_arg(key), str_arg(value)) {
+        (Some(key), Some(value)) => (key.to_string(), value.to_string()),
+        _ => return 0,
     };
     
-    let value_str = unsafe {
-        match CStr::from_ptr(value).to_str() {
-            Ok(s) => s,
-            Err(_) => return 0,
+    with_engine(move |engine, rt| {
+        let result = rt.block_on(async {
+            engine.storage.set_setting(&key_str, &value_str).await
+        });
+        
+        match result {
+            Ok(_) => 1,
+            Err(_) => 0,
         }
+    })
+    .unwrap_or(0)
+}
+
+#[no_mangle]
+pub extern "C" fn titan_engine_storage_get(key: *const c_char) -> *mut c_char {
+    let key_str = match str_arg(key) {
+        Some(s) => s.to_string(),
+        None => return ptr::null_mut(),
     };
     
-    unsafe {
-        if let (Some(engine), Some(rt)) = (&ENGINE, &RUNTIME) {
-            let result = rt.block_on(async {
-                let mut storage = engine.storage.write().await;
-                storage.set_setting(key_str, value_str).await
-            });
-            
-            match result {
-                Ok(_) => 1,
-                Err(_) => 0,
-            }
-        } else {
-            0
-        }
+    let value = with_engine(move |engine, rt| {
+        rt.block_on(async {
+            engine.storage.get_setting(&key_str).await
+        })
+    });
+    
+    match value {
+        Some(Ok(Some(value))) => into_c_string(value),
+        _ => ptr::null_mut(),
     }
 }
 
+/// Start a download into the downloads folder; `suggested_filename` may be null.
+/// Progress arrives as `download_progress` events.
 #[no_mangle]
-pub extern "C" fn titan_engine_storage_get(key: *const c_char) -> *mut c_char {
-    if key.is_null() {
-        return ptr::null_mut();
-    }
+pub extern "C" fn titan_engine_start_download(url: *const c_char, suggested_filename: *const c_char) -> *mut c_char {
+    let url_str = match str_arg(url) {
+        Some(s) => s.to_string(),
+        None => return ptr::null_mut(),
+    };
+    let suggested_filename = str_arg(suggested_filename).filter(|name| !name.is_empty()).map(str::to_string);
     
-    let key_str = unsafe {
-        match CStr::from_ptr(key).to_str() {
-            Ok(s) => s,
-            Err(_) => return ptr::null_mut(),
+    let response = with_engine(move |engine, rt| {
+        match rt.block_on(engine.start_download_named(&url_str, suggested_filename.as_deref())) {
+            Ok(id) => serde_json::json!({ "success": true, "id": id }),
+            Err(e) => engine_error_json("download_failed", &e),
         }
+    })
+    .unwrap_or_else(|| error_json("not_initialized", "Engine is not initialized"));
+    
+    into_c_string(response.to_string())
+}
+
+/// Cancel a download and delete its partial file; returns 0 if it can't be cancelled
+#[no_mangle]
+pub extern "C" fn titan_engine_cancel_download(id: *const c_char) -> c_int {
+    let id_str = match str_arg(id) {
+        Some(s) => s.to_string(),
+        None => return 0,
     };
     
-    unsafe {
-        if let (Some(engine), Some(rt)) = (&ENGINE, &RUNTIME) {
-            let result = rt.block_on(async {
-                let storage = engine.storage.read().await;
-                storage.get_setting(key_str).await
-            });
-            
-            match result {
-                Ok(Some(value)) => {
-                    match CString::new(value) {
-                        Ok(c_string) => c_string.into_raw(),
-                        Err(_) => ptr::null_mut(),
-                    }
-                }
-                _ => ptr::null_mut(),
-            }
-        } else {
-            ptr::null_mut()
-        }
-    }
+    with_engine(move |engine, rt| match rt.block_on(engine.cancel_download(&id_str)) {
+        Ok(_) => 1,
+        Err(_) => 0,
+    })
+    .unwrap_or(0)
 }
 
 /// Callback function types for Flutter
@@ -428,57 +623,55 @@ pub type ProgressCallback = extern "C" fn(progress: c_double);
 pub type EventCallback = extern "C" fn(event_type: *const c_char, event_data: *const c_char);
 pub type ErrorCallback = extern "C" fn(error_message: *const c_char);
 
-static mut PROGRESS_CALLBACK: Option<ProgressCallback> = None;
-static mut EVENT_CALLBACK: Option<EventCallback> = None;
-static mut ERROR_CALLBACK: Option<ErrorCallback> = None;
+static PROGRESS_CALLBACK: RwLock<Option<ProgressCallback>> = RwLock::new(None);
+static EVENT_CALLBACK: RwLock<Option<EventCallback>> = RwLock::new(None);
+static ERROR_CALLBACK: RwLock<Option<ErrorCallback>> = RwLock::new(None);
+
+/// Replace a registered callback
+fn set_callback<T>(slot: &RwLock<Option<T>>, callback: T) {
+    *slot.write().unwrap_or_else(PoisonError::into_inner) = Some(callback);
+}
+
+/// Copy a registered callback out so it runs without the lock held
+fn get_callback<T: Copy>(slot: &RwLock<Option<T>>) -> Option<T> {
+    *slot.read().unwrap_or_else(PoisonError::into_inner)
+}
 
 /// Set callback functions
 #[no_mangle]
 pub extern "C" fn titan_engine_set_progress_callback(callback: ProgressCallback) {
-    unsafe {
-        PROGRESS_CALLBACK = Some(callback);
-    }
+    set_callback(&PROGRESS_CALLBACK, callback);
 }
 
 #[no_mangle]
 pub extern "C" fn titan_engine_set_event_callback(callback: EventCallback) {
-    unsafe {
-        EVENT_CALLBACK = Some(callback);
-    }
+    set_callback(&EVENT_CALLBACK, callback);
 }
 
 #[no_mangle]
 pub extern "C" fn titan_engine_set_error_callback(callback: ErrorCallback) {
-    unsafe {
-        ERROR_CALLBACK = Some(callback);
-    }
+    set_callback(&ERROR_CALLBACK, callback);
 }
 
 /// Helper functions for calling callbacks
 pub fn notify_progress(progress: f64) {
-    unsafe {
-        if let Some(callback) = PROGRESS_CALLBACK {
-            callback(progress);
-        }
+    if let Some(callback) = get_callback(&PROGRESS_CALLBACK) {
+        callback(progress);
     }
 }
 
 pub fn notify_event(event_type: &str, event_data: &str) {
-    unsafe {
-        if let Some(callback) = EVENT_CALLBACK {
-            if let (Ok(event_type_c), Ok(event_data_c)) = (CString::new(event_type), CString::new(event_data)) {
-                callback(event_type_c.as_ptr(), event_data_c.as_ptr());
-            }
+    if let Some(callback) = get_callback(&EVENT_CALLBACK) {
+        if let (Ok(event_type_c), Ok(event_data_c)) = (CString::new(event_type), CString::new(event_data)) {
+            callback(event_type_c.as_ptr(), event_data_c.as_ptr());
         }
     }
 }
 
 pub fn notify_error(error_message: &str) {
-    unsafe {
-        if let Some(callback) = ERROR_CALLBACK {
-            if let Ok(error_c) = CString::new(error_message) {
-                callback(error_c.as_ptr());
-            }
+    if let Some(callback) = get_callback(&ERROR_CALLBACK) {
+        if let Ok(error_c) = CString::new(error_message) {
+            callback(error_c.as_ptr());
         }
     }
 }
@@ -487,9 +680,18 @@ pub fn notify_error(error_message: &str) {
 mod tests {
     use super::*;
     use std::ffi::CString;
+    use std::sync::Mutex;
+    
+    /// Tests share the global engine, so those that init or shut it down run one at a time
+    static ENGINE_LOCK: Mutex<()> = Mutex::new(());
+    
+    fn lock_engine() -> std::sync::MutexGuard<'static, ()> {
+        ENGINE_LOCK.lock().unwrap_or_else(PoisonError::into_inner)
+    }
     
     #[test]
     fn test_engine_initialization() {
+        let _guard = lock_engine();
         let result = titan_engine_init();
         assert_eq!(result, 1);
         
@@ -522,6 +724,7 @@ mod tests {
     
     #[test]
     fn test_url_validation() {
+        let _guard = lock_engine();
         titan_engine_init();
         
         let safe_url = CString::new("https://example.com").unwrap();
@@ -530,4 +733,178 @@ mod tests {
         
         titan_engine_shutdown();
     }
-}
\ No newline at end of file
+    
+    /// Take ownership of a string returned over FFI and parse it
+    fn take_json(ptr: *mut c_char) -> serde_json::Value {
+        assert!(!ptr.is_null());
+        let text = unsafe { CStr::from_ptr(ptr) }.to_str().unwrap().to_string();
+        titan_engine_free_string(ptr);
+        serde_json::from_str(&text).unwrap()
+    }
+    
+    fn test_response(url: &str, html: &str) -> crate::networking::NetworkResponse {
+        crate::networking::NetworkResponse {
+            url: url.to_string(),
+            status: 200,
+            headers: HashMap::new(),
+            body: html.to_string(),
+            body_bytes: html.as_bytes().to_vec(),
+            load_time: std::time::Duration::from_millis(50),
+            from_cache: false,
+            security_info: crate::networking::SecurityInfo {
+                https: true,
+                hsts: false,
+                csp: None,
+                x_frame_options: None,
+                x_content_type_options: None,
+                tls: None,
+            },
+        }
+    }
+    
+    /// Open a page handle from inline HTML, as `titan_engine_load_page` would after fetching
+    fn open_test_page(url: &str, html: &str) -> u64 {
+        let (url, html) = (url.to_string(), html.to_string());
+        with_context(move |context| {
+            context.runtime.block_on(async {
+                let engine = &context.engine;
+                let mut document = engine.html_parser.parse(&html).unwrap();
+                document.url = url.clone();
+                
+                let mut js_runtime = engine.create_js_runtime().await.unwrap();
+                let page = engine.load_document_in(document, test_response(&url, &html), &mut js_runtime).await.unwrap();
+                context.pages.insert(FfiPage {
+                    page,
+                    js_runtime: RefCell::new(js_runtime),
+                })
+            })
+        })
+        .unwrap()
+    }
+    
+    fn run_on_page(handle: u64, code: &str) -> serde_json::Value {
+        let code = CString::new(code).unwrap();
+        take_json(titan_engine_page_execute_javascript(handle, code.as_ptr()))
+    }
+    
+    #[test]
+    fn test_get_ai_analysis() {
+        let _guard = lock_engine();
+        assert_eq!(titan_engine_init(), 1);
+        
+        let response = take_json(titan_engine_get_ai_analysis(ptr::null()));
+        assert_eq!(response["success"], false);
+        assert_eq!(response["error"]["code"], "no_page_loaded");
+        
+        let html = r#"<html><head><title>Form</title></head>
+            <body><form><input name="email"><button id="go">Go</button></form></body></html>"#;
+        
+        with_engine(move |engine, rt| {
+            let mut document = engine.html_parser.parse(html).unwrap();
+            document.url = "https://example.com/".to_string();
+            rt.block_on(engine.load_document(document, test_response("https://example.com/", html))).unwrap();
+        })
+        .unwrap();
+        
+        let response = take_json(titan_engine_get_ai_analysis(ptr::null()));
+        assert_eq!(response["success"], true);
+        
+        let analysis = &response["analysis"];
+        assert_eq!(analysis["url"], "https://example.com/");
+        assert!(analysis["content_analysis"]["has_forms"].as_bool().unwrap());
+        
+        // Predictions target elements by UUID so the client can act on them
+        let predictions = analysis["interaction_predictions"].as_array().unwrap();
+        let form_prediction = predictions.iter().find(|p| p["action_type"] == "form_fill").unwrap();
+        let element_id = form_prediction["element_id"].as_str().unwrap();
+        assert!(uuid::Uuid::parse_str(element_id).is_ok());
+        
+        let other_url = CString::new("https://other.example/").unwrap();
+        let response = take_json(titan_engine_get_ai_analysis(other_url.as_ptr()));
+        assert_eq!(response["error"]["code"], "page_not_loaded");
+        
+        assert_eq!(titan_engine_shutdown(), 1);
+    }
+    
+    #[test]
+    fn test_concurrent_ffi_calls() {
+        let _guard = lock_engine();
+        
+        extern "C" fn on_progress(_progress: c_double) {}
+        
+        let workers: Vec<_> = (0..2)
+            .map(|worker| {
+                std::thread::spawn(move || {
+                    let key = CString::new(format!("ffi.worker.{}", worker)).unwrap();
+                    let url = CString::new("https://example.com").unwrap();
+                    
+                    for i in 0..20 {
+                        assert_eq!(titan_engine_init(), 1);
+                        assert_eq!(titan_engine_is_initialized(), 1);
+                        
+                        titan_engine_set_progress_callback(on_progress);
+                        notify_progress(i as f64 / 20.0);
+                        titan_engine_validate_url_security(url.as_ptr());
+                        
+                        let value = CString::new(i.to_string()).unwrap();
+                        titan_engine_storage_set(key.as_ptr(), value.as_ptr());
+                        let stored = titan_engine_storage_get(key.as_ptr());
+                        titan_engine_free_string(stored);
+                        
+                        let metrics = titan_engine_get_network_metrics();
+                        assert!(!metrics.is_null());
+                        titan_engine_free_string(metrics);
+                    }
+                })
+            })
+            .collect();
+        
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        
+        assert_eq!(titan_engine_shutdown(), 1);
+        assert_eq!(titan_engine_is_initialized(), 0);
+        assert!(titan_engine_get_network_metrics().is_null());
+    }
+    
+    #[test]
+    fn test_page_handles_are_independent() {
+        let _guard = lock_engine();
+        assert_eq!(titan_engine_init(), 1);
+        
+        let first = open_test_page(
+            "https://a.example/",
+            "<html><body><script>var pageName = 'a';</script><form><input name=\"q\"></form></body></html>",
+        );
+        let second = open_test_page(
+            "https://b.example/",
+            "<html><body><script>var pageName = 'b';</script><p>No forms here</p></body></html>",
+        );
+        assert_ne!(first, second);
+        
+        // Scripts and globals stay within their own page
+        assert_eq!(run_on_page(first, "pageName")["result"], "a");
+        assert_eq!(run_on_page(second, "pageName")["result"], "b");
+        run_on_page(first, "globalThis.onlyOnFirst = true");
+        assert_eq!(run_on_page(second, "typeof onlyOnFirst")["result"], "undefined");
+        
+        let first_analysis = take_json(titan_engine_page_get_ai_analysis(first));
+        let second_analysis = take_json(titan_engine_page_get_ai_analysis(second));
+        assert_eq!(first_analysis["analysis"]["url"], "https://a.example/");
+        assert_eq!(second_analysis["analysis"]["url"], "https://b.example/");
+        assert_eq!(first_analysis["analysis"]["content_analysis"]["has_forms"], true);
+        assert_eq!(second_analysis["analysis"]["content_analysis"]["has_forms"], false);
+        
+        // Closing frees the handle without touching the other page
+        assert_eq!(titan_engine_close_page(first), 1);
+        assert_eq!(titan_engine_close_page(first), 0);
+        assert_eq!(run_on_page(first, "pageName")["error"]["code"], "invalid_handle");
+        assert_eq!(take_json(titan_engine_page_get_ai_analysis(first))["error"]["code"], "invalid_handle");
+        assert_eq!(run_on_page(second, "pageName")["result"], "b");
+        assert_eq!(run_on_page(0, "1")["error"]["code"], "invalid_handle");
+        
+        assert_eq!(titan_engine_close_page(second), 1);
+        assert_eq!(titan_engine_shutdown(), 1);
+    }
+}
@@ -1,12 +1,14 @@
 //! HTML parsing and DOM implementation using html5ever
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use html5ever::parse_document;
+use encoding_rs::Encoding;
+use html5ever::{parse_document, parse_fragment};
 use html5ever::rcdom::{RcDom, NodeData, Handle};
 use html5ever::tendril::TendrilSink;
+use html5ever::tree_builder::QuirksMode;
 use markup5ever::{LocalName, Namespace, QualName};
-use crate::core::{ElementId, Result, EngineError};
+use crate::core::{ElementId, Result, EngineError, Shutdown};
 
 /// HTML parser using html5ever for standards compliance
 pub struct HTMLParser {
@@ -32,15 +34,188 @@ impl HTMLParser {
         Ok(document)
     }
     
-    /// Parse HTML fragment (for innerHTML operations)
+    /// Parse raw response bytes, decoding them with the charset from a BOM, the
+    /// `Content-Type` header, or a `<meta>` declaration, in that order of precedence
+    pub fn parse_bytes(&self, bytes: &[u8], content_type: Option<&str>) -> Result<Document> {
+        self.parse(&decode_html(bytes, content_type))
+    }
+    
+    /// Parse HTML fragment (for innerHTML operations).
+    /// The context element decides how markup is interpreted, e.g. `<td>` only survives in a table row.
     pub fn parse_fragment(&self, html: &str, context_element: &Element) -> Result<DocumentFragment> {
-        // Implementation for fragment parsing
-        let fragment = DocumentFragment::new();
-        // TODO: Implement fragment parsing with html5ever
+        let context = QualName::new(
+            None,
+            Namespace::from("http://www.w3.org/1999/xhtml"),
+            LocalName::from(context_element.tag_name.as_str()),
+        );
+        let dom = parse_fragment(RcDom::default(), self.options.clone(), context, Vec::new())
+            .from_utf8()
+            .read_from(&mut html.as_bytes())
+            .map_err(|e| EngineError::HtmlParseError(format!("Parse error: {:?}", e)))?;
+        
+        // Convert through a scratch document, then keep only what the fragment produced
+        let mut scratch = Document::new("about:blank".to_string());
+        let placeholder_id = scratch.root.id;
+        let mut fragment = DocumentFragment::new();
+        
+        // html5ever puts the fragment's nodes under a synthetic <html> element
+        let document_node = dom.document.borrow();
+        if let Some(root) = document_node.children.first() {
+            for child in &root.borrow().children {
+                if let NodeData::Text { contents } = &child.borrow().data {
+                    fragment.text_content.push_str(&contents.borrow());
+                } else if let Some(child_id) = scratch.traverse_node(child, None)? {
+                    fragment.children.push(child_id);
+                }
+            }
+        }
+        
+        scratch.elements.remove(&placeholder_id);
+        fragment.elements = scratch.elements;
         Ok(fragment)
     }
 }
 
+/// The parser holds no state between documents, so there is nothing to release
+#[async_trait::async_trait]
+impl Shutdown for HTMLParser {
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Bytes of a document inspected by the `<meta>` charset pre-scan
+const META_PRESCAN_BYTES: usize = 1024;
+
+/// Character encoding of an HTML document: a BOM wins, then the `charset` parameter of
+/// `content_type`, then a `<meta>` declaration near the top; UTF-8 when nothing says otherwise
+pub fn detect_encoding(bytes: &[u8], content_type: Option<&str>) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+    
+    let declared = content_type
+        .and_then(charset_from_content_type)
+        .and_then(|label| Encoding::for_label(label.as_bytes()));
+    if let Some(encoding) = declared {
+        return encoding;
+    }
+    
+    match prescan_meta_charset(&bytes[..bytes.len().min(META_PRESCAN_BYTES)]) {
+        // A document that could read its own <meta> is ASCII-compatible, so UTF-16 is a mislabel
+        Some(encoding) if encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE => encoding_rs::UTF_8,
+        Some(encoding) if encoding == encoding_rs::X_USER_DEFINED => encoding_rs::WINDOWS_1252,
+        Some(encoding) => encoding,
+        None => encoding_rs::UTF_8,
+    }
+}
+
+/// Decode an HTML document's bytes to text using `detect_encoding`
+pub fn decode_html(bytes: &[u8], content_type: Option<&str>) -> String {
+    let (text, _, _) = detect_encoding(bytes, content_type).decode(bytes);
+    text.into_owned()
+}
+
+/// The `charset` parameter of a MIME type such as `text/html; charset="Shift_JIS"`
+fn charset_from_content_type(content_type: &str) -> Option<&str> {
+    content_type.split(';').skip(1).find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+        name.trim().eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches(|c| c == '"' || c == '\''))
+    })
+}
+
+/// Look for `<meta charset>` or `<meta http-equiv="Content-Type" content="...; charset=...">`
+/// in the start of a document, skipping comments
+fn prescan_meta_charset(head: &[u8]) -> Option<&'static Encoding> {
+    let text = String::from_utf8_lossy(head).to_ascii_lowercase();
+    let mut rest = text.as_str();
+    
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        
+        let end = rest.find('>').unwrap_or(rest.len());
+        let tag = &rest[1..end];
+        rest = &rest[end..];
+        
+        let Some(attributes) = tag.strip_prefix("meta").filter(|a| a.starts_with(|c: char| c.is_ascii_whitespace() || c == '/')) else {
+            continue;
+        };
+        let attributes = parse_prescan_attributes(attributes);
+        let label = attributes.get("charset").map(String::as_str).or_else(|| {
+            let is_content_type = attributes.get("http-equiv").is_some_and(|v| v == "content-type");
+            attributes.get("content").filter(|_| is_content_type).and_then(|content| {
+                let index = content.find("charset")?;
+                let value = content[index + "charset".len()..].trim_start().strip_prefix('=')?;
+                value.trim_start().split(|c: char| c == ';' || c.is_ascii_whitespace()).next()
+            }).map(|label| label.trim_matches(|c| c == '"' || c == '\''))
+        });
+        if let Some(encoding) = label.and_then(|label| Encoding::for_label(label.as_bytes())) {
+            return Some(encoding);
+        }
+    }
+    None
+}
+
+/// Attributes of a tag during the pre-scan; values may be quoted or bare
+fn parse_prescan_attributes(source: &str) -> HashMap<String, String> {
+    let source = source.trim_end_matches('/');
+    let mut attributes = HashMap::new();
+    let mut chars = source.char_indices().peekable();
+    
+    while let Some((start, c)) = chars.next() {
+        if c.is_ascii_whitespace() || c == '/' {
+            continue;
+        }
+        let mut name_end = source.len();
+        let mut value = String::new();
+        while let Some(&(index, c)) = chars.peek() {
+            if c == '=' || c.is_ascii_whitespace() {
+                name_end = index;
+                break;
+            }
+            chars.next();
+        }
+        let name = source[start..name_end].to_string();
+        
+        while matches!(chars.peek(), Some(&(_, c)) if c.is_ascii_whitespace()) {
+            chars.next();
+        }
+        if matches!(chars.peek(), Some(&(_, '='))) {
+            chars.next();
+            while matches!(chars.peek(), Some(&(_, c)) if c.is_ascii_whitespace()) {
+                chars.next();
+            }
+            match chars.peek().map(|&(_, c)| c) {
+                Some(quote @ ('"' | '\'')) => {
+                    chars.next();
+                    for (_, c) in chars.by_ref() {
+                        if c == quote {
+                            break;
+                        }
+                        value.push(c);
+                    }
+                }
+                _ => {
+                    while let Some(&(_, c)) = chars.peek() {
+                        if c.is_ascii_whitespace() {
+                            break;
+                        }
+                        value.push(c);
+                        chars.next();
+                    }
+                }
+            }
+        }
+        attributes.entry(name).or_insert(value);
+    }
+    attributes
+}
+
 /// Represents a complete HTML document
 #[derive(Debug, Clone)]
 pub struct Document {
@@ -49,8 +224,22 @@ pub struct Document {
     pub head: Option<Arc<Element>>,
     pub body: Option<Arc<Element>>,
     pub doctype: Option<DocumentType>,
+    /// Rendering mode chosen by the parser from the doctype
+    pub compat_mode: CompatMode,
     pub elements: HashMap<ElementId, Arc<Element>>,
     pub root: Arc<Element>,
+    pub structured_data: Vec<serde_json::Value>,
+    /// Elements whose layout and rendering are stale after tree mutations
+    pub dirty_elements: HashSet<ElementId>,
+    /// Element with each `id` attribute value, kept in sync by parsing and the mutation methods.
+    /// Callers that change `elements` directly must call `rebuild_indexes`.
+    id_index: HashMap<String, ElementId>,
+    /// Elements carrying each class name
+    class_index: HashMap<String, Vec<ElementId>>,
+    /// Registered mutation observers and the records they haven't been given yet
+    mutation_observers: MutationObservers,
+    /// Elements currently hovered, active, or focused, for `:hover`/`:active`/`:focus`
+    element_states: HashMap<ElementState, HashSet<ElementId>>,
 }
 
 impl Document {
@@ -65,8 +254,352 @@ impl Document {
             head: None,
             body: None,
             doctype: None,
+            compat_mode: CompatMode::NoQuirks,
             elements,
             root,
+            structured_data: Vec::new(),
+            dirty_elements: HashSet::new(),
+            id_index: HashMap::new(),
+            class_index: HashMap::new(),
+            mutation_observers: MutationObservers::default(),
+            element_states: HashMap::new(),
+        }
+    }
+    
+    /// Create a detached element owned by this document
+    pub fn create_element(&mut self, tag_name: &str) -> ElementId {
+        let element = Element::new(tag_name.to_string(), ElementId::new());
+        let element_id = element.id;
+        self.elements.insert(element_id, Arc::new(element));
+        element_id
+    }
+    
+    /// Append `child` as the last child of `parent`, moving it from any previous parent
+    pub fn append_child(&mut self, parent: ElementId, child: ElementId) -> Result<()> {
+        self.insert_before(parent, child, None)
+    }
+    
+    /// Insert `new_child` under `parent` before `reference`, or last if `reference` is `None`
+    pub fn insert_before(&mut self, parent: ElementId, new_child: ElementId, reference: Option<ElementId>) -> Result<()> {
+        // Validate everything before touching the tree so a failed call changes nothing
+        self.element(parent)?;
+        self.element(new_child)?;
+        if self.is_inclusive_ancestor(new_child, parent) {
+            return Err(EngineError::DomError("HierarchyRequestError: the new child contains the parent".to_string()));
+        }
+        if let Some(reference) = reference {
+            if !self.element(parent)?.children.contains(&reference) {
+                return Err(EngineError::DomError("NotFoundError: the reference node is not a child of the parent".to_string()));
+            }
+            if reference == new_child {
+                return Ok(());
+            }
+        }
+        
+        if let Some(old_parent) = self.element(new_child)?.parent {
+            self.update_element(old_parent, |element| element.children.retain(|id| *id != new_child))?;
+            self.mark_dirty(old_parent);
+            self.queue_mutation(old_parent, MutationKind::ChildList { added: Vec::new(), removed: vec![new_child] });
+        }
+        
+        self.update_element(parent, |element| {
+            let index = reference
+                .and_then(|reference| element.children.iter().position(|id| *id == reference))
+                .unwrap_or(element.children.len());
+            element.children.insert(index, new_child);
+        })?;
+        self.update_element(new_child, |element| element.parent = Some(parent))?;
+        
+        // Only elements in the tree are found by id and class
+        if self.is_connected(parent) {
+            self.index_subtree(new_child);
+        } else {
+            self.unindex_subtree(new_child);
+        }
+        
+        self.mark_dirty(parent);
+        self.queue_mutation(parent, MutationKind::ChildList { added: vec![new_child], removed: Vec::new() });
+        Ok(())
+    }
+    
+    /// Detach `child` from `parent`; it stays owned by the document and can be reinserted
+    pub fn remove_child(&mut self, parent: ElementId, child: ElementId) -> Result<()> {
+        if !self.element(parent)?.children.contains(&child) {
+            return Err(EngineError::DomError("NotFoundError: the node is not a child of the parent".to_string()));
+        }
+        
+        self.update_element(parent, |element| element.children.retain(|id| *id != child))?;
+        self.update_element(child, |element| element.parent = None)?;
+        self.unindex_subtree(child);
+        
+        self.mark_dirty(parent);
+        self.mark_dirty(child);
+        self.queue_mutation(parent, MutationKind::ChildList { added: Vec::new(), removed: vec![child] });
+        Ok(())
+    }
+    
+    /// Move a parsed fragment's elements into the document and insert its top-level elements
+    /// before `reference`. Top-level text in the fragment is not carried over.
+    pub fn insert_fragment(&mut self, parent: ElementId, fragment: DocumentFragment, reference: Option<ElementId>) -> Result<()> {
+        self.element(parent)?;
+        if let Some(reference) = reference {
+            if !self.element(parent)?.children.contains(&reference) {
+                return Err(EngineError::DomError("NotFoundError: the reference node is not a child of the parent".to_string()));
+            }
+        }
+        
+        self.elements.extend(fragment.elements);
+        for child in fragment.children {
+            self.insert_before(parent, child, reference)?;
+        }
+        Ok(())
+    }
+    
+    /// Set an attribute on an element, keeping the id and class indexes current
+    pub fn set_attribute(&mut self, id: ElementId, name: &str, value: &str) -> Result<()> {
+        let old = self.element(id)?.clone();
+        self.update_element(id, |element| element.set_attribute(name.to_string(), value.to_string()))?;
+        self.reindex_element(&old)?;
+        self.mark_dirty(id);
+        self.queue_mutation(id, MutationKind::Attribute {
+            name: name.to_string(),
+            old_value: old.get_attribute(name).map(str::to_string),
+        });
+        Ok(())
+    }
+    
+    /// Replace an element's text
+    pub fn set_text_content(&mut self, id: ElementId, text: &str) -> Result<()> {
+        self.update_element(id, |element| element.set_text_content(text.to_string()))?;
+        self.mark_dirty(id);
+        Ok(())
+    }
+    
+    /// Remove an attribute from an element, keeping the id and class indexes current
+    pub fn remove_attribute(&mut self, id: ElementId, name: &str) -> Result<()> {
+        let old = self.element(id)?.clone();
+        self.update_element(id, |element| element.remove_attribute(name))?;
+        self.reindex_element(&old)?;
+        self.mark_dirty(id);
+        if let Some(old_value) = old.get_attribute(name) {
+            self.queue_mutation(id, MutationKind::Attribute {
+                name: name.to_string(),
+                old_value: Some(old_value.to_string()),
+            });
+        }
+        Ok(())
+    }
+    
+    /// Call `callback` with batches of records describing child list and attribute changes
+    /// made through the mutation methods. Records are queued, not delivered as the change
+    /// happens; the script runtime delivers them after each turn, and hosts mutating the
+    /// document directly call `deliver_mutations` themselves.
+    pub fn observe_mutations(
+        &mut self,
+        callback: impl Fn(&[MutationRecord]) + Send + Sync + 'static,
+    ) -> MutationObserverId {
+        let observers = &mut self.mutation_observers;
+        observers.next_id += 1;
+        let id = MutationObserverId(observers.next_id);
+        observers.callbacks.push((id, Arc::new(callback)));
+        id
+    }
+    
+    /// Stop calling an observer; returns false if it wasn't registered
+    pub fn disconnect_mutation_observer(&mut self, id: MutationObserverId) -> bool {
+        let observers = &mut self.mutation_observers;
+        let before = observers.callbacks.len();
+        observers.callbacks.retain(|(observer, _)| *observer != id);
+        if observers.callbacks.is_empty() {
+            observers.pending.clear();
+        }
+        observers.callbacks.len() != before
+    }
+    
+    /// Give every observer the records queued since the last delivery, in the order the
+    /// changes were made. Returns how many records were delivered.
+    pub fn deliver_mutations(&mut self) -> usize {
+        let records = std::mem::take(&mut self.mutation_observers.pending);
+        if records.is_empty() {
+            return 0;
+        }
+        
+        // Snapshot the callbacks so one that disconnects doesn't skip the others
+        let callbacks: Vec<MutationCallback> = self.mutation_observers.callbacks.iter()
+            .map(|(_, callback)| callback.clone())
+            .collect();
+        for callback in callbacks {
+            callback(&records);
+        }
+        records.len()
+    }
+    
+    /// Queue a record for the observers; nothing is kept while none are registered
+    fn queue_mutation(&mut self, target: ElementId, kind: MutationKind) {
+        if !self.mutation_observers.callbacks.is_empty() {
+            self.mutation_observers.pending.push(MutationRecord { target, kind });
+        }
+    }
+    
+    /// Recompute the id and class indexes from the elements in the tree
+    pub fn rebuild_indexes(&mut self) {
+        self.id_index.clear();
+        self.class_index.clear();
+        let root = self.root.clone();
+        for element in std::iter::once(root.clone()).chain(self.descendants(&root)) {
+            self.index_element(&element);
+        }
+    }
+    
+    /// Whether an element is in the document's tree rather than detached
+    pub fn is_connected(&self, id: ElementId) -> bool {
+        self.is_inclusive_ancestor(self.root.id, id)
+    }
+    
+    /// Move an element's index entries from its `old` attributes to its current ones
+    fn reindex_element(&mut self, old: &Element) -> Result<()> {
+        if !self.is_connected(old.id) {
+            return Ok(());
+        }
+        self.unindex_element(old);
+        let updated = self.element(old.id)?.clone();
+        self.index_element(&updated);
+        Ok(())
+    }
+    
+    fn index_subtree(&mut self, id: ElementId) {
+        let Some(element) = self.elements.get(&id).cloned() else { return };
+        for element in std::iter::once(element.clone()).chain(self.descendants(&element)) {
+            self.index_element(&element);
+        }
+    }
+    
+    fn unindex_subtree(&mut self, id: ElementId) {
+        let Some(element) = self.elements.get(&id).cloned() else { return };
+        for element in std::iter::once(element.clone()).chain(self.descendants(&element)) {
+            self.unindex_element(&element);
+        }
+    }
+    
+    fn index_element(&mut self, element: &Element) {
+        if let Some(id) = element.get_attribute("id") {
+            self.id_index.entry(id.to_string()).or_insert(element.id);
+        }
+        if let Some(classes) = element.get_attribute("class") {
+            for class in classes.split_whitespace() {
+                let ids = self.class_index.entry(class.to_string()).or_default();
+                if !ids.contains(&element.id) {
+                    ids.push(element.id);
+                }
+            }
+        }
+    }
+    
+    fn unindex_element(&mut self, element: &Element) {
+        if let Some(id) = element.get_attribute("id") {
+            if self.id_index.get(id) == Some(&element.id) {
+                self.id_index.remove(id);
+                
+                // Fall back to the first other element in the tree sharing the same id
+                let other = std::iter::once(self.root.clone())
+                    .chain(self.descendants(&self.root))
+                    .find(|other| other.id != element.id && other.get_attribute("id") == Some(id));
+                if let Some(other) = other {
+                    self.id_index.insert(id.to_string(), other.id);
+                }
+            }
+        }
+        if let Some(classes) = element.get_attribute("class") {
+            for class in classes.split_whitespace() {
+                if let Some(ids) = self.class_index.get_mut(class) {
+                    ids.retain(|id| *id != element.id);
+                    if ids.is_empty() {
+                        self.class_index.remove(class);
+                    }
+                }
+            }
+        }
+    }
+    
+    /// Whether an element is in an interaction state
+    pub fn has_element_state(&self, id: ElementId, state: ElementState) -> bool {
+        self.element_states.get(&state).is_some_and(|ids| ids.contains(&id))
+    }
+    
+    /// Move an interaction state to `target`, or clear it. Hover and active also apply to the
+    /// target's ancestors. Elements entering or leaving the state are marked dirty for restyle.
+    pub fn set_element_state(&mut self, state: ElementState, target: Option<ElementId>) {
+        let mut entered = HashSet::new();
+        let mut id = target.filter(|id| self.elements.contains_key(id));
+        while let Some(current) = id {
+            entered.insert(current);
+            id = match state {
+                ElementState::Hover | ElementState::Active => self.elements.get(&current).and_then(|element| element.parent),
+                ElementState::Focus => None,
+            };
+        }
+        
+        let previous = self.element_states.insert(state, entered.clone()).unwrap_or_default();
+        for changed in previous.symmetric_difference(&entered) {
+            self.mark_dirty(*changed);
+        }
+    }
+    
+    /// 1-based position of an element among its parent's children, and the parent's child count
+    pub fn child_position(&self, element: &Element) -> Option<(usize, usize)> {
+        let parent = self.elements.get(&element.parent?)?;
+        let index = parent.children.iter().position(|id| *id == element.id)?;
+        Some((index + 1, parent.children.len()))
+    }
+    
+    /// Take the set of elements whose layout and rendering must be recomputed
+    pub fn take_dirty_elements(&mut self) -> HashSet<ElementId> {
+        std::mem::take(&mut self.dirty_elements)
+    }
+    
+    fn element(&self, id: ElementId) -> Result<&Arc<Element>> {
+        self.elements.get(&id)
+            .ok_or_else(|| EngineError::DomError(format!("NotFoundError: no element {:?} in this document", id)))
+    }
+    
+    /// Modify an element in place, cloning it first if another handle still shares it
+    fn update_element(&mut self, id: ElementId, update: impl FnOnce(&mut Element)) -> Result<()> {
+        let element = self.elements.get_mut(&id)
+            .ok_or_else(|| EngineError::DomError(format!("NotFoundError: no element {:?} in this document", id)))?;
+        update(Arc::make_mut(element));
+        
+        // Keep the shortcut references pointing at the current version
+        let updated = element.clone();
+        if self.root.id == id {
+            self.root = updated.clone();
+        }
+        if self.head.as_ref().is_some_and(|head| head.id == id) {
+            self.head = Some(updated.clone());
+        }
+        if self.body.as_ref().is_some_and(|body| body.id == id) {
+            self.body = Some(updated);
+        }
+        Ok(())
+    }
+    
+    /// Whether `ancestor` is `id` or one of its ancestors
+    fn is_inclusive_ancestor(&self, ancestor: ElementId, mut id: ElementId) -> bool {
+        loop {
+            if id == ancestor {
+                return true;
+            }
+            match self.elements.get(&id).and_then(|element| element.parent) {
+                Some(parent) => id = parent,
+                None => return false,
+            }
+        }
+    }
+    
+    /// Mark an element and its subtree as needing layout and rendering
+    fn mark_dirty(&mut self, id: ElementId) {
+        if let Some(element) = self.elements.get(&id).cloned() {
+            self.dirty_elements.insert(id);
+            self.dirty_elements.extend(self.descendants(&element).iter().map(|e| e.id));
         }
     }
     
@@ -76,18 +609,20 @@ impl Document {
         
         // Traverse the DOM tree and convert nodes
         document.traverse_node(&dom.document, None)?;
+        document.compat_mode = dom.quirks_mode.into();
         
         // Extract title from head
-        if let Some(head) = &document.head {
-            if let Some(title_element) = head.find_child_by_tag("title") {
-                document.title = title_element.text_content();
-            }
+        if let Some(title_element) = document.get_elements_by_tag_name("title").first() {
+            document.title = title_element.text_content().trim().to_string();
         }
         
+        // Extract JSON-LD structured data
+        document.structured_data = document.extract_structured_data();
+        
         Ok(document)
     }
     
-    fn traverse_node(&mut self, handle: &Handle, parent_id: Option<ElementId>) -> Result<()> {
+    fn traverse_node(&mut self, handle: &Handle, parent_id: Option<ElementId>) -> Result<Option<ElementId>> {
         let node = handle.borrow();
         
         match &node.data {
@@ -104,14 +639,8 @@ impl Document {
                     system_id: system_id.to_string(),
                 });
             }
-            NodeData::Text { contents } => {
-                // Handle text nodes
-                if let Some(parent_id) = parent_id {
-                    if let Some(parent) = self.elements.get_mut(&parent_id) {
-                        // Add text content to parent element
-                        // This is a simplified approach - in reality we'd need proper text node handling
-                    }
-                }
+            NodeData::Text { .. } => {
+                // Text is collected by the enclosing element while it walks its children
             }
             NodeData::Comment { .. } => {
                 // Skip comments for now
@@ -121,6 +650,7 @@ impl Document {
                 let element_id = ElementId::new();
                 
                 let mut element = Element::new(tag_name.clone(), element_id);
+                element.parent = parent_id;
                 
                 // Process attributes
                 for attr in attrs.borrow().iter() {
@@ -130,40 +660,159 @@ impl Document {
                     );
                 }
                 
+                // Process children, folding text nodes into this element's text content
+                for child in &node.children {
+                    if let NodeData::Text { contents } = &child.borrow().data {
+                        element.text_content.push_str(&contents.borrow());
+                    } else if let Some(child_id) = self.traverse_node(child, Some(element_id))? {
+                        element.children.push(child_id);
+                    }
+                }
+                
+                let element = Arc::new(element);
+                
                 // Set special references for html, head, body
                 match tag_name.as_str() {
                     "html" => {
-                        self.root = Arc::new(element.clone());
+                        let placeholder_id = self.root.id;
+                        self.elements.remove(&placeholder_id);
+                        self.root = element.clone();
                     }
                     "head" => {
-                        self.head = Some(Arc::new(element.clone()));
+                        self.head = Some(element.clone());
                     }
                     "body" => {
-                        self.body = Some(Arc::new(element.clone()));
+                        self.body = Some(element.clone());
                     }
                     _ => {}
                 }
                 
                 // Add to elements map
-                self.elements.insert(element_id, Arc::new(element));
+                self.index_element(&element);
+                self.elements.insert(element_id, element);
                 
-                // Process children
-                for child in &node.children {
-                    self.traverse_node(child, Some(element_id))?;
-                }
+                return Ok(Some(element_id));
             }
             NodeData::ProcessingInstruction { .. } => {
                 // Skip processing instructions
             }
         }
         
-        Ok(())
+        Ok(None)
+    }
+    
+    /// Collect all descendants of an element in document order
+    pub fn descendants(&self, element: &Element) -> Vec<Arc<Element>> {
+        let mut result = Vec::new();
+        for child_id in &element.children {
+            if let Some(child) = self.elements.get(child_id) {
+                result.push(child.clone());
+                result.extend(self.descendants(child));
+            }
+        }
+        result
+    }
+    
+    /// Serialize the document, including any mutations, back to HTML.
+    /// Text is kept per element rather than as separate nodes, so an element's text is
+    /// written ahead of its children and markup that mixes text and elements doesn't
+    /// survive the trip unchanged.
+    pub fn to_html(&self) -> String {
+        let mut output = String::new();
+        
+        if let Some(doctype) = &self.doctype {
+            output.push_str("<!DOCTYPE ");
+            output.push_str(&doctype.name);
+            if !doctype.public_id.is_empty() {
+                output.push_str(&format!(" PUBLIC \"{}\"", doctype.public_id));
+                if !doctype.system_id.is_empty() {
+                    output.push_str(&format!(" \"{}\"", doctype.system_id));
+                }
+            } else if !doctype.system_id.is_empty() {
+                output.push_str(&format!(" SYSTEM \"{}\"", doctype.system_id));
+            }
+            output.push('>');
+        }
+        
+        self.serialize_element(&self.root, &mut output);
+        output
+    }
+    
+    fn serialize_element(&self, element: &Element, output: &mut String) {
+        output.push('<');
+        output.push_str(&element.tag_name);
+        
+        // Attribute order isn't kept, so sort for stable output
+        let mut attributes: Vec<_> = element.attributes.iter().collect();
+        attributes.sort();
+        for (name, value) in attributes {
+            output.push(' ');
+            output.push_str(name);
+            output.push_str("=\"");
+            escape_html(value, true, output);
+            output.push('"');
+        }
+        output.push('>');
+        
+        if VOID_ELEMENTS.contains(&element.tag_name.as_str()) {
+            return;
+        }
+        
+        if RAW_TEXT_ELEMENTS.contains(&element.tag_name.as_str()) {
+            output.push_str(&element.text_content);
+        } else {
+            escape_html(&element.text_content, false, output);
+        }
+        
+        for child_id in &element.children {
+            if let Some(child) = self.elements.get(child_id) {
+                self.serialize_element(child, output);
+            }
+        }
+        
+        output.push_str("</");
+        output.push_str(&element.tag_name);
+        output.push('>');
+    }
+    
+    /// Parse every `<script type="application/ld+json">` block, skipping malformed ones
+    fn extract_structured_data(&self) -> Vec<serde_json::Value> {
+        let mut blocks = Vec::new();
+        
+        for script in self.descendants(&self.root) {
+            if !script.tag_name.eq_ignore_ascii_case("script") {
+                continue;
+            }
+            
+            let is_json_ld = script.get_attribute("type")
+                .map(|t| t.trim().eq_ignore_ascii_case("application/ld+json"))
+                .unwrap_or(false);
+            if !is_json_ld {
+                continue;
+            }
+            
+            match serde_json::from_str::<serde_json::Value>(script.text_content.trim()) {
+                Ok(value) => blocks.push(value),
+                Err(e) => log::warn!("Skipping malformed JSON-LD block: {}", e),
+            }
+        }
+        
+        blocks
+    }
+    
+    /// Get the schema.org types (Article, Product, ...) declared in JSON-LD blocks
+    pub fn structured_data_types(&self) -> Vec<String> {
+        let mut types = Vec::new();
+        for block in &self.structured_data {
+            collect_schema_types(block, &mut types);
+        }
+        types
     }
     
     /// Find element by ID
     pub fn get_element_by_id(&self, id: &str) -> Option<Arc<Element>> {
-        self.elements.values()
-            .find(|element| element.get_attribute("id") == Some(id))
+        self.id_index.get(id)
+            .and_then(|element_id| self.elements.get(element_id))
             .cloned()
     }
     
@@ -177,16 +826,55 @@ impl Document {
     
     /// Find elements by class name
     pub fn get_elements_by_class_name(&self, class_name: &str) -> Vec<Arc<Element>> {
-        self.elements.values()
-            .filter(|element| {
-                if let Some(class_attr) = element.get_attribute("class") {
-                    class_attr.split_whitespace().any(|c| c == class_name)
-                } else {
-                    false
+        self.class_index.get(class_name)
+            .map(|ids| ids.iter().filter_map(|id| self.elements.get(id)).cloned().collect())
+            .unwrap_or_default()
+    }
+    
+    /// Find elements matching a selector; id and class selectors are answered from the indexes
+    pub fn query_selector_all(&self, selector: &str) -> Vec<Arc<Element>> {
+        match crate::css::ParsedSelector::parse(selector) {
+            crate::css::ParsedSelector::Id(id) => self.get_element_by_id(&id).into_iter().collect(),
+            crate::css::ParsedSelector::Class(class) => self.get_elements_by_class_name(&class),
+            crate::css::ParsedSelector::Type(tag_name) => self.get_elements_by_tag_name(&tag_name),
+            parsed => std::iter::once(self.root.clone())
+                .chain(self.descendants(&self.root))
+                .filter(|element| parsed.matches(element, Some(self)))
+                .collect(),
+        }
+    }
+}
+
+/// Recursively collect `@type` values from a JSON-LD value, following `@graph`
+fn collect_schema_types(value: &serde_json::Value, types: &mut Vec<String>) {
+    match value {
+        serde_json::Value::Array(items) => {
+            for item in items {
+                collect_schema_types(item, types);
+            }
+        }
+        serde_json::Value::Object(map) => {
+            match map.get("@type") {
+                Some(serde_json::Value::String(t)) => {
+                    if !types.contains(t) {
+                        types.push(t.clone());
+                    }
                 }
-            })
-            .cloned()
-            .collect()
+                Some(serde_json::Value::Array(ts)) => {
+                    for t in ts.iter().filter_map(|t| t.as_str()) {
+                        if !types.iter().any(|existing| existing == t) {
+                            types.push(t.to_string());
+                        }
+                    }
+                }
+                _ => {}
+            }
+            
+            if let Some(graph) = map.get("@graph") {
+                collect_schema_types(graph, types);
+            }
+        }
+        _ => {}
     }
 }
 
@@ -250,29 +938,11 @@ impl Element {
         None
     }
     
-    /// Check if element matches a CSS selector (simplified)
+    /// Check if element matches a CSS selector (simplified).
+    /// Parses `selector` on every call; style computation goes through `CSSEngine`'s cache instead.
+    /// Structural and state pseudo-classes need the document and never match here.
     pub fn matches_selector(&self, selector: &str) -> bool {
-        // Simplified selector matching - in reality this would use the selectors crate
-        match selector.chars().next() {
-            Some('#') => {
-                // ID selector
-                let id = &selector[1..];
-                self.get_attribute("id") == Some(id)
-            }
-            Some('.') => {
-                // Class selector
-                let class = &selector[1..];
-                if let Some(class_attr) = self.get_attribute("class") {
-                    class_attr.split_whitespace().any(|c| c == class)
-                } else {
-                    false
-                }
-            }
-            _ => {
-                // Tag selector
-                self.tag_name.eq_ignore_ascii_case(selector)
-            }
-        }
+        crate::css::ParsedSelector::parse(selector).matches(self, None)
     }
     
     /// Get computed style for this element (placeholder)
@@ -281,16 +951,231 @@ impl Element {
     }
 }
 
+/// Interaction states tracked per element for the matching pseudo-classes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ElementState {
+    Hover,
+    Active,
+    Focus,
+}
+
+/// A change made to the document, as given to mutation observers
+#[derive(Debug, Clone, PartialEq)]
+pub struct MutationRecord {
+    /// The parent whose children changed, or the element whose attribute changed
+    pub target: ElementId,
+    pub kind: MutationKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MutationKind {
+    ChildList {
+        added: Vec<ElementId>,
+        removed: Vec<ElementId>,
+    },
+    Attribute {
+        name: String,
+        /// Value before the change, `None` if the attribute was newly added
+        old_value: Option<String>,
+    },
+}
+
+/// Handle for disconnecting an observer registered with `Document::observe_mutations`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MutationObserverId(u64);
+
+type MutationCallback = Arc<dyn Fn(&[MutationRecord]) + Send + Sync>;
+
+/// Observer callbacks and undelivered records; clones of a document share the callbacks
+#[derive(Clone, Default)]
+struct MutationObservers {
+    next_id: u64,
+    callbacks: Vec<(MutationObserverId, MutationCallback)>,
+    pending: Vec<MutationRecord>,
+}
+
+impl std::fmt::Debug for MutationObservers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MutationObservers")
+            .field("observers", &self.callbacks.len())
+            .field("pending", &self.pending)
+            .finish()
+    }
+}
+
 /// Document fragment for partial DOM operations
 #[derive(Debug, Clone)]
 pub struct DocumentFragment {
+    /// Top-level elements, in order; their `parent` is unset until inserted
     pub children: Vec<ElementId>,
+    
+    /// Every element in the fragment, including descendants
+    pub elements: HashMap<ElementId, Arc<Element>>,
+    
+    /// Top-level text between the fragment's elements
+    pub text_content: String,
 }
 
 impl DocumentFragment {
     pub fn new() -> Self {
         Self {
             children: Vec::new(),
+            elements: HashMap::new(),
+            text_content: String::new(),
+        }
+    }
+    
+    /// Top-level elements in order
+    pub fn child_elements(&self) -> Vec<Arc<Element>> {
+        self.children.iter()
+            .filter_map(|id| self.elements.get(id).cloned())
+            .collect()
+    }
+}
+
+/// Document compatibility mode, as in `document.compatMode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompatMode {
+    /// Standards mode
+    NoQuirks,
+    /// Almost-standards mode; only table cell line height differs
+    LimitedQuirks,
+    /// Legacy rendering for documents without a modern doctype
+    Quirks,
+}
+
+impl CompatMode {
+    /// The `document.compatMode` string
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompatMode::Quirks => "BackCompat",
+            CompatMode::NoQuirks | CompatMode::LimitedQuirks => "CSS1Compat",
+        }
+    }
+}
+
+impl From<QuirksMode> for CompatMode {
+    fn from(mode: QuirksMode) -> Self {
+        match mode {
+            QuirksMode::Quirks => CompatMode::Quirks,
+            QuirksMode::LimitedQuirks => CompatMode::LimitedQuirks,
+            QuirksMode::NoQuirks => CompatMode::NoQuirks,
+        }
+    }
+}
+
+/// Tags and attributes that survive `HTMLUtils::sanitize_html_with`
+#[derive(Debug, Clone)]
+pub struct SanitizerConfig {
+    /// Elements kept as-is; other elements are unwrapped, keeping their children
+    pub allowed_tags: HashSet<String>,
+    /// Attributes kept on allowed elements
+    pub allowed_attributes: HashSet<String>,
+    /// Attributes holding URLs, dropped when the URL uses a scriptable scheme
+    pub url_attributes: HashSet<String>,
+    /// Elements removed together with everything inside them
+    pub dropped_tags: HashSet<String>,
+}
+
+impl Default for SanitizerConfig {
+    fn default() -> Self {
+        let set = |items: &[&str]| items.iter().map(|s| s.to_string()).collect();
+        Self {
+            allowed_tags: set(&[
+                "a", "abbr", "b", "blockquote", "br", "caption", "cite", "code", "dd", "div", "dl", "dt",
+                "em", "h1", "h2", "h3", "h4", "h5", "h6", "hr", "i", "img", "li", "ol", "p", "pre", "q",
+                "s", "small", "span", "strong", "sub", "sup", "table", "tbody", "td", "tfoot", "th",
+                "thead", "tr", "u", "ul",
+            ]),
+            allowed_attributes: set(&[
+                "alt", "class", "colspan", "height", "href", "id", "lang", "rowspan", "src", "title", "width",
+            ]),
+            url_attributes: set(&["href", "src"]),
+            dropped_tags: set(&[
+                "script", "style", "iframe", "object", "embed", "template", "noscript", "svg", "math",
+                "title", "textarea", "xmp", "noembed", "noframes",
+            ]),
+        }
+    }
+}
+
+/// Elements with no closing tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr",
+];
+
+/// Elements whose text is written without escaping
+const RAW_TEXT_ELEMENTS: &[&str] = &[
+    "iframe", "noembed", "noframes", "plaintext", "script", "style", "xmp",
+];
+
+/// Serialize one node if the sanitizer config allows it
+fn sanitize_node(handle: &Handle, config: &SanitizerConfig, output: &mut String) {
+    let node = handle.borrow();
+    
+    match &node.data {
+        NodeData::Text { contents } => escape_html(&contents.borrow(), false, output),
+        NodeData::Element { name, attrs, .. } => {
+            let tag_name = name.local.to_string();
+            if config.dropped_tags.contains(&tag_name) {
+                return;
+            }
+            
+            let allowed = config.allowed_tags.contains(&tag_name);
+            if allowed {
+                output.push('<');
+                output.push_str(&tag_name);
+                for attr in attrs.borrow().iter() {
+                    let attr_name = attr.name.local.to_string();
+                    if !config.allowed_attributes.contains(&attr_name) {
+                        continue;
+                    }
+                    if config.url_attributes.contains(&attr_name) && is_scriptable_url(&attr.value) {
+                        continue;
+                    }
+                    output.push(' ');
+                    output.push_str(&attr_name);
+                    output.push_str("=\"");
+                    escape_html(&attr.value, true, output);
+                    output.push('"');
+                }
+                output.push('>');
+            }
+            
+            for child in &node.children {
+                sanitize_node(child, config, output);
+            }
+            
+            if allowed && !VOID_ELEMENTS.contains(&tag_name.as_str()) {
+                output.push_str("</");
+                output.push_str(&tag_name);
+                output.push('>');
+            }
+        }
+        // Comments, doctypes, and processing instructions never survive
+        _ => {}
+    }
+}
+
+/// Whether a URL would run script or inline content when followed
+fn is_scriptable_url(url: &str) -> bool {
+    // Browsers ignore whitespace and control characters when reading the scheme
+    let normalized: String = url.chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .flat_map(char::to_lowercase)
+        .collect();
+    ["javascript:", "vbscript:", "data:"].iter().any(|scheme| normalized.starts_with(scheme))
+}
+
+pub(crate) fn escape_html(text: &str, in_attribute: bool, output: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' if in_attribute => output.push_str("&quot;"),
+            '\u{a0}' => output.push_str("&nbsp;"),
+            _ => output.push(c),
         }
     }
 }
@@ -326,32 +1211,31 @@ pub struct ComputedStyle {
 pub struct HTMLUtils;
 
 impl HTMLUtils {
-    /// Sanitize HTML to prevent XSS attacks
+    /// Sanitize HTML to prevent XSS attacks, using the default allowlist
     pub fn sanitize_html(html: &str) -> String {
-        // Basic HTML sanitization - remove script tags and dangerous attributes
-        let mut sanitized = html.to_string();
-        
-        // Remove script tags
-        sanitized = regex::Regex::new(r"(?i)<script[^>]*>.*?</script>")
-            .unwrap()
-            .replace_all(&sanitized, "")
-            .to_string();
-        
-        // Remove dangerous event handlers
-        let dangerous_attrs = [
-            "onload", "onerror", "onclick", "onmouseover", "onmouseout",
-            "onfocus", "onblur", "onchange", "onsubmit", "onreset",
-        ];
+        Self::sanitize_html_with(html, &SanitizerConfig::default())
+    }
+    
+    /// Parse `html` as a body fragment and re-serialize only allowlisted tags and attributes.
+    /// Parsing the way a browser would is what defeats malformed-tag and casing tricks.
+    pub fn sanitize_html_with(html: &str, config: &SanitizerConfig) -> String {
+        let context = QualName::new(None, Namespace::from("http://www.w3.org/1999/xhtml"), LocalName::from("body"));
+        let dom = match parse_fragment(RcDom::default(), html5ever::ParseOpts::default(), context, Vec::new())
+            .from_utf8()
+            .read_from(&mut html.as_bytes())
+        {
+            Ok(dom) => dom,
+            Err(_) => return String::new(),
+        };
         
-        for attr in &dangerous_attrs {
-            let pattern = format!(r"(?i){}=[^>\s]*", attr);
-            sanitized = regex::Regex::new(&pattern)
-                .unwrap()
-                .replace_all(&sanitized, "")
-                .to_string();
+        let mut output = String::new();
+        let document_node = dom.document.borrow();
+        if let Some(root) = document_node.children.first() {
+            for child in &root.borrow().children {
+                sanitize_node(child, config, &mut output);
+            }
         }
-        
-        sanitized
+        output
     }
     
     /// Extract text content from HTML
@@ -394,6 +1278,52 @@ impl HTMLUtils {
         
         images
     }
+    
+    /// Extract links resolved to absolute URLs against `base_url`, or the document's `<base href>`.
+    /// Fragment-only and non-HTTP(S) links (`mailto:`, `javascript:`...) are kept only if `keep_non_fetchable`.
+    pub fn extract_links_with_base(html: &str, base_url: &str, keep_non_fetchable: bool) -> Vec<String> {
+        resolve_urls(html, base_url, Self::extract_links(html), keep_non_fetchable)
+    }
+    
+    /// Extract image sources resolved to absolute URLs, like `extract_links_with_base`
+    pub fn extract_images_with_base(html: &str, base_url: &str, keep_non_fetchable: bool) -> Vec<String> {
+        resolve_urls(html, base_url, Self::extract_images(html), keep_non_fetchable)
+    }
+}
+
+/// Resolve raw href/src values against the document's base URL
+fn resolve_urls(html: &str, base_url: &str, raw_urls: Vec<String>, keep_non_fetchable: bool) -> Vec<String> {
+    let base = url::Url::parse(base_url).ok();
+    
+    // A <base href> overrides the document URL, and may itself be relative to it
+    let base_regex = regex::Regex::new(r#"(?i)<base[^>]*href=["']([^"']*)["'][^>]*>"#).unwrap();
+    let base = base_regex.captures(html)
+        .and_then(|cap| cap.get(1))
+        .and_then(|href| match &base {
+            Some(base) => base.join(href.as_str().trim()).ok(),
+            None => url::Url::parse(href.as_str().trim()).ok(),
+        })
+        .or(base);
+    
+    raw_urls.iter()
+        .filter_map(|raw| {
+            let raw = raw.trim();
+            if raw.starts_with('#') && !keep_non_fetchable {
+                return None;
+            }
+            
+            // `join` handles protocol-relative `//host/path` by taking the base's scheme
+            let resolved = match &base {
+                Some(base) => base.join(raw),
+                None => url::Url::parse(raw),
+            }.ok()?;
+            
+            if !keep_non_fetchable && !matches!(resolved.scheme(), "http" | "https") {
+                return None;
+            }
+            Some(resolved.to_string())
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -457,6 +1387,37 @@ mod tests {
         assert!(!sanitized.contains("onerror"));
     }
     
+    #[test]
+    fn test_html_sanitization_bypasses() {
+        let blocked = [
+            ("<script >alert(1)</script>", "alert"),
+            ("<SCRIPT/xss>alert(1)</SCRIPT>", "alert"),
+            ("<scr<script>ipt>alert(1)</script>", "<script"),
+            ("<div onpointerdown=\"steal()\">x</div>", "onpointerdown"),
+            ("<a href=\"javascript:alert(1)\">x</a>", "javascript"),
+            ("<a href=\" JaVa&#x09;ScRiPt:alert(1)\">x</a>", "alert"),
+            ("<img src=\"data:text/html;base64,PHNjcmlwdD4=\">", "data:"),
+            ("<svg><script>alert(1)</script></svg>", "alert"),
+            ("<!--<script>alert(1)</script>-->", "alert"),
+        ];
+        for (html, forbidden) in blocked {
+            let sanitized = HTMLUtils::sanitize_html(html);
+            assert!(!sanitized.to_lowercase().contains(forbidden), "{:?} survived as {:?}", html, sanitized);
+        }
+        
+        // Allowed markup round-trips, unknown wrappers are unwrapped, and text stays escaped
+        assert_eq!(
+            HTMLUtils::sanitize_html(r#"<p class="x" style="color: red">Hi <a href="https://example.com/?a=1&b=2">there</a></p><br>"#),
+            r#"<p class="x">Hi <a href="https://example.com/?a=1&amp;b=2">there</a></p><br>"#
+        );
+        assert_eq!(HTMLUtils::sanitize_html("<blink>1 &lt; 2</blink>"), "1 &lt; 2");
+        
+        // The allowlist is configurable
+        let mut config = SanitizerConfig::default();
+        config.allowed_tags.remove("a");
+        assert_eq!(HTMLUtils::sanitize_html_with(r#"<a href="/x">link</a>"#, &config), "link");
+    }
+    
     #[test]
     fn test_text_extraction() {
         let html = "<div><p>Hello <strong>World</strong>!</p></div>";
@@ -464,6 +1425,27 @@ mod tests {
         assert_eq!(text, "Hello World!");
     }
     
+    #[test]
+    fn test_json_ld_extraction() {
+        let parser = HTMLParser::new();
+        let html = r#"
+            <html>
+                <head>
+                    <script type="application/ld+json">
+                        {"@context": "https://schema.org", "@type": "Article", "headline": "Titan"}
+                    </script>
+                    <script type="application/ld+json">{ not valid json </script>
+                </head>
+                <body></body>
+            </html>
+        "#;
+        
+        let document = parser.parse(html).unwrap();
+        assert_eq!(document.structured_data.len(), 1);
+        assert_eq!(document.structured_data[0]["headline"], "Titan");
+        assert_eq!(document.structured_data_types(), vec!["Article".to_string()]);
+    }
+    
     #[test]
     fn test_link_extraction() {
         let html = r#"
@@ -476,4 +1458,296 @@ mod tests {
         assert!(links.contains(&"https://example.com".to_string()));
         assert!(links.contains(&"/relative".to_string()));
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_link_resolution_against_base() {
+        let html = r##"
+            <a href="https://other.example/abs">Absolute</a>
+            <a href="/root">Root-relative</a>
+            <a href="page.html">Relative</a>
+            <a href="//cdn.example.com/lib.js">Protocol-relative</a>
+            <a href="#section">Fragment</a>
+            <a href="mailto:team@example.com">Mail</a>
+            <img src="../img/logo.png">
+        "##;
+        let base = "https://example.com/docs/guide/index.html";
+        
+        assert_eq!(HTMLUtils::extract_links_with_base(html, base, false), vec![
+            "https://other.example/abs",
+            "https://example.com/root",
+            "https://example.com/docs/guide/page.html",
+            "https://cdn.example.com/lib.js",
+        ]);
+        assert_eq!(HTMLUtils::extract_images_with_base(html, base, false), vec![
+            "https://example.com/docs/img/logo.png",
+        ]);
+        
+        let all = HTMLUtils::extract_links_with_base(html, base, true);
+        assert!(all.contains(&"https://example.com/docs/guide/index.html#section".to_string()));
+        assert!(all.contains(&"mailto:team@example.com".to_string()));
+        
+        // <base href> takes precedence over the document URL
+        let with_base = r#"<head><base href="/static/"></head><a href="app.js">App</a>"#;
+        assert_eq!(
+            HTMLUtils::extract_links_with_base(with_base, base, false),
+            vec!["https://example.com/static/app.js"],
+        );
+    }
+    
+    #[test]
+    fn test_fragment_parsing_uses_context() {
+        let parser = HTMLParser::new();
+        
+        let list = Element::new("ul".to_string(), ElementId::new());
+        let fragment = parser.parse_fragment("<li>a</li><li>b <em>bold</em></li>", &list).unwrap();
+        let items = fragment.child_elements();
+        assert_eq!(items.len(), 2);
+        assert!(items.iter().all(|item| item.tag_name == "li" && item.parent.is_none()));
+        assert_eq!(items[0].text_content(), "a");
+        
+        // Descendants are linked to their parents and included in the fragment
+        let em = fragment.elements.get(&items[1].children[0]).unwrap();
+        assert_eq!(em.tag_name, "em");
+        assert_eq!(em.parent, Some(items[1].id));
+        assert_eq!(fragment.elements.len(), 3);
+        
+        // Table cells only parse inside a row context
+        let row = Element::new("tr".to_string(), ElementId::new());
+        let cells = parser.parse_fragment("<td>x</td>", &row).unwrap().child_elements();
+        assert_eq!(cells.len(), 1);
+        assert_eq!(cells[0].tag_name, "td");
+        
+        let div = Element::new("div".to_string(), ElementId::new());
+        let fragment = parser.parse_fragment("<td>x</td>", &div).unwrap();
+        assert!(fragment.children.is_empty());
+        assert_eq!(fragment.text_content, "x");
+    }
+    
+    #[test]
+    fn test_dom_mutation() {
+        let parser = HTMLParser::new();
+        let mut document = parser.parse(r#"<html><body><ul id="list"><li id="a">a</li><li id="c">c</li></ul></body></html>"#).unwrap();
+        let list = document.get_element_by_id("list").unwrap().id;
+        let a = document.get_element_by_id("a").unwrap().id;
+        let c = document.get_element_by_id("c").unwrap().id;
+        document.take_dirty_elements();
+        
+        // Append puts the new element last
+        let d = document.create_element("li");
+        document.append_child(list, d).unwrap();
+        assert_eq!(document.elements[&list].children, vec![a, c, d]);
+        assert_eq!(document.elements[&d].parent, Some(list));
+        
+        // Insert-before places the element ahead of the reference
+        let b = document.create_element("li");
+        document.insert_before(list, b, Some(c)).unwrap();
+        assert_eq!(document.elements[&list].children, vec![a, b, c, d]);
+        
+        // Inserting an attached element moves it
+        document.insert_before(list, d, Some(a)).unwrap();
+        assert_eq!(document.elements[&list].children, vec![d, a, b, c]);
+        
+        // Remove detaches without dropping the element
+        document.remove_child(list, a).unwrap();
+        assert_eq!(document.elements[&list].children, vec![d, b, c]);
+        assert_eq!(document.elements[&a].parent, None);
+        assert!(document.remove_child(list, a).is_err());
+        
+        // The body shortcut sees the mutated tree, and the subtree is marked for relayout
+        let body = document.body.clone().unwrap();
+        assert_eq!(document.descendants(&body).len(), 4);
+        let dirty = document.take_dirty_elements();
+        assert!([list, a, b, c, d].iter().all(|id| dirty.contains(id)));
+        
+        // A node can't be inserted into its own subtree, and failures change nothing
+        assert!(document.append_child(b, list).is_err());
+        assert!(document.insert_before(list, a, Some(a)).is_err());
+        assert_eq!(document.elements[&list].children, vec![d, b, c]);
+        
+        // Parsed fragments can be inserted directly
+        let fragment = parser.parse_fragment("<li>x</li><li>y</li>", &document.elements[&list]).unwrap();
+        let inserted = fragment.children.clone();
+        document.insert_fragment(list, fragment, Some(b)).unwrap();
+        assert_eq!(document.elements[&list].children, vec![d, inserted[0], inserted[1], b, c]);
+        assert_eq!(document.elements[&inserted[0]].parent, Some(list));
+    }
+    
+    #[test]
+    fn test_compat_mode_from_doctype() {
+        let parser = HTMLParser::new();
+        
+        let document = parser.parse("<html><body></body></html>").unwrap();
+        assert_eq!(document.compat_mode, CompatMode::Quirks);
+        assert_eq!(document.compat_mode.as_str(), "BackCompat");
+        
+        let document = parser.parse("<!DOCTYPE html><html><body></body></html>").unwrap();
+        assert_eq!(document.compat_mode, CompatMode::NoQuirks);
+        assert_eq!(document.compat_mode.as_str(), "CSS1Compat");
+        
+        let document = parser.parse(
+            r#"<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Transitional//EN" "http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd"><html></html>"#
+        ).unwrap();
+        assert_eq!(document.compat_mode, CompatMode::LimitedQuirks);
+    }
+    
+    #[test]
+    fn test_id_and_class_indexes() {
+        let parser = HTMLParser::new();
+        let mut document = parser.parse(r#"<html><body>
+            <div id="main" class="card wide"><p class="card">One</p><p class="note">Two</p></div>
+            <span class="card"></span>
+        </body></html>"#).unwrap();
+        
+        let linear_class = |document: &Document, class: &str| -> HashSet<ElementId> {
+            document.elements.values()
+                .filter(|e| document.is_connected(e.id))
+                .filter(|e| e.get_attribute("class").is_some_and(|c| c.split_whitespace().any(|c| c == class)))
+                .map(|e| e.id)
+                .collect()
+        };
+        let indexed_class = |document: &Document, class: &str| -> HashSet<ElementId> {
+            document.get_elements_by_class_name(class).iter().map(|e| e.id).collect()
+        };
+        
+        for class in ["card", "wide", "note", "missing"] {
+            assert_eq!(indexed_class(&document, class), linear_class(&document, class));
+        }
+        let main = document.get_element_by_id("main").unwrap();
+        assert_eq!(main.tag_name, "div");
+        assert_eq!(document.query_selector_all(".card").len(), 3);
+        assert_eq!(document.query_selector_all("#main")[0].id, main.id);
+        
+        // Mutations keep the indexes in step with the elements
+        document.set_attribute(main.id, "class", "note").unwrap();
+        document.set_attribute(main.id, "id", "renamed").unwrap();
+        let created = document.create_element("section");
+        document.set_attribute(created, "class", "card").unwrap();
+        document.append_child(main.id, created).unwrap();
+        
+        for class in ["card", "wide", "note"] {
+            assert_eq!(indexed_class(&document, class), linear_class(&document, class));
+        }
+        assert!(document.get_element_by_id("main").is_none());
+        assert_eq!(document.get_element_by_id("renamed").unwrap().id, main.id);
+        
+        document.remove_attribute(main.id, "id").unwrap();
+        assert!(document.get_element_by_id("renamed").is_none());
+        
+        // Detached elements drop out of the indexes, with their descendants, until reinserted
+        let body = document.body.as_ref().unwrap().id;
+        document.set_attribute(main.id, "id", "main").unwrap();
+        document.remove_child(body, main.id).unwrap();
+        assert!(document.get_element_by_id("main").is_none());
+        for class in ["card", "note"] {
+            assert_eq!(indexed_class(&document, class), linear_class(&document, class));
+        }
+        assert_eq!(indexed_class(&document, "card").len(), 1);
+        
+        let detached = document.create_element("aside");
+        document.set_attribute(detached, "id", "aside").unwrap();
+        assert!(document.get_element_by_id("aside").is_none());
+        
+        document.append_child(body, main.id).unwrap();
+        assert_eq!(document.get_element_by_id("main").unwrap().id, main.id);
+        assert_eq!(indexed_class(&document, "card"), linear_class(&document, "card"));
+    }
+
+    #[test]
+    fn test_decode_windows_1252_document() {
+        // "café – naïve" in windows-1252, declared only by <meta charset>
+        let mut bytes = b"<html><head><meta charset=\"windows-1252\"><title>caf".to_vec();
+        bytes.extend_from_slice(&[0xE9]);
+        bytes.extend_from_slice(b"</title></head><body><p>na");
+        bytes.extend_from_slice(&[0xEF]);
+        bytes.extend_from_slice(b"ve ");
+        bytes.extend_from_slice(&[0x96]);
+        bytes.extend_from_slice(b" ok</p></body></html>");
+        
+        assert_eq!(detect_encoding(&bytes, None), encoding_rs::WINDOWS_1252);
+        let document = HTMLParser::new().parse_bytes(&bytes, Some("text/html")).unwrap();
+        assert_eq!(document.title, "caf\u{e9}");
+        let paragraph = document.get_elements_by_tag_name("p");
+        assert_eq!(paragraph[0].text_content, "na\u{ef}ve \u{2013} ok");
+        
+        // ISO-8859-1 in the header maps to windows-1252 and outranks the <meta>
+        assert_eq!(detect_encoding(b"<meta charset=utf-8>", Some("text/html; charset=ISO-8859-1")), encoding_rs::WINDOWS_1252);
+    }
+    
+    #[test]
+    fn test_decode_shift_jis_document() {
+        let (encoded, _, _) = encoding_rs::SHIFT_JIS.encode("<html><head><meta http-equiv=\"Content-Type\" content=\"text/html; charset=Shift_JIS\"></head><body><p>\u{65e5}\u{672c}\u{8a9e}</p></body></html>");
+        
+        assert_eq!(detect_encoding(&encoded, None), encoding_rs::SHIFT_JIS);
+        let document = HTMLParser::new().parse_bytes(&encoded, None).unwrap();
+        assert_eq!(document.get_elements_by_tag_name("p")[0].text_content, "\u{65e5}\u{672c}\u{8a9e}");
+        
+        // A BOM beats every declaration
+        let mut bom = vec![0xEF, 0xBB, 0xBF];
+        bom.extend_from_slice(b"<meta charset=shift_jis>");
+        assert_eq!(detect_encoding(&bom, Some("text/html; charset=windows-1252")), encoding_rs::UTF_8);
+        assert_eq!(detect_encoding(b"<!-- <meta charset=koi8-r> --><p>", None), encoding_rs::UTF_8);
+    }
+
+    #[test]
+    fn test_document_serializes_to_html() {
+        let html = concat!(
+            "<!DOCTYPE html><html><head><title>Fish &amp; Chips</title>",
+            "<style>p > a { color: red }</style></head>",
+            "<body><p class=\"lead\" data-quote=\"say &quot;hi&quot;\">1 &lt; 2</p>",
+            "<br><img alt=\"\" src=\"a.png\"><script>if (a < b && c) {}</script></body></html>",
+        );
+        let parser = HTMLParser::new();
+        let document = parser.parse(html).unwrap();
+        
+        // No element here has text after a child, so the markup comes back as written
+        let serialized = document.to_html();
+        assert_eq!(serialized, html);
+        assert_eq!(parser.parse(&serialized).unwrap().to_html(), serialized);
+        
+        // Text that follows a child moves ahead of it
+        let mixed = parser.parse("<p>a<b>b</b>c</p>").unwrap();
+        assert!(mixed.to_html().contains("<p>ac<b>b</b></p>"));
+        
+        // Mutations show up in the output
+        let mut document = document;
+        let paragraph = document.get_elements_by_tag_name("p")[0].id;
+        document.set_attribute(paragraph, "title", "<tip>").unwrap();
+        assert!(document.to_html().contains("<p class=\"lead\" data-quote=\"say &quot;hi&quot;\" title=\"&lt;tip&gt;\">"));
+    }
+
+    #[test]
+    fn test_mutation_observer_receives_batched_records() {
+        let mut document = HTMLParser::new().parse(r#"<html><body><div id="box" class="a"></div></body></html>"#).unwrap();
+        let body = document.body.as_ref().unwrap().id;
+        let target = document.get_element_by_id("box").unwrap().id;
+        
+        let batches = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let received = batches.clone();
+        let observer = document.observe_mutations(move |records| received.lock().unwrap().push(records.to_vec()));
+        
+        document.set_attribute(target, "class", "b").unwrap();
+        document.set_attribute(target, "title", "new").unwrap();
+        let child = document.create_element("span");
+        document.append_child(body, child).unwrap();
+        document.remove_child(body, child).unwrap();
+        
+        // Nothing is delivered until the batch is flushed
+        assert!(batches.lock().unwrap().is_empty());
+        assert_eq!(document.deliver_mutations(), 4);
+        assert_eq!(*batches.lock().unwrap(), vec![vec![
+            MutationRecord { target, kind: MutationKind::Attribute { name: "class".to_string(), old_value: Some("a".to_string()) } },
+            MutationRecord { target, kind: MutationKind::Attribute { name: "title".to_string(), old_value: None } },
+            MutationRecord { target: body, kind: MutationKind::ChildList { added: vec![child], removed: Vec::new() } },
+            MutationRecord { target: body, kind: MutationKind::ChildList { added: Vec::new(), removed: vec![child] } },
+        ]]);
+        assert_eq!(document.deliver_mutations(), 0);
+        
+        // Removing an absent attribute changes nothing, and disconnected observers hear nothing
+        document.remove_attribute(target, "missing").unwrap();
+        assert_eq!(document.deliver_mutations(), 0);
+        assert!(document.disconnect_mutation_observer(observer));
+        document.set_attribute(target, "class", "c").unwrap();
+        assert_eq!(document.deliver_mutations(), 0);
+        assert_eq!(batches.lock().unwrap().len(), 1);
+    }
+}
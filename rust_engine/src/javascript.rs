@@ -1,11 +1,21 @@
 //! JavaScript runtime using V8 for script execution
 
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroI32;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex, PoisonError, RwLock};
 use std::time::{Duration, Instant};
+use futures::StreamExt;
 use rusty_v8 as v8;
-use crate::core::{ElementId, Result, EngineError, BrowserEvent, ConsoleLevel};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use url::Url;
+use crate::core::{ElementId, Result, EngineError, BrowserEvent, ConsoleLevel, EngineSettings, MetricsReporter, NetworkErrorKind, PerformanceMetrics};
 use crate::html::{Document, Element};
+use crate::networking::{NetworkResponse, NetworkStack, RequestOptions, WebSocketMessage};
+use crate::security::{PermissionKind, PermissionState, PermissionStore};
 
 /// JavaScript runtime for executing scripts
 pub struct JSRuntime {
@@ -15,29 +25,49 @@ pub struct JSRuntime {
     /// Global context
     context: v8::Global<v8::Context>,
     
-    /// Security settings
-    security_settings: SecuritySettings,
+    /// Security settings, shared with the isolate's bindings so updates reach them too
+    security_settings: Arc<RwLock<SecuritySettings>>,
+    
+    /// Whether `eval` and `Function` have been removed from the global object
+    dangerous_globals_removed: bool,
     
     /// Performance monitoring
     execution_stats: Arc<Mutex<ExecutionStats>>,
-    
-    /// Event handlers
-    event_handlers: HashMap<String, v8::Global<v8::Function>>,
-    
-    /// Console message callback
-    console_callback: Option<Box<dyn Fn(ConsoleLevel, String, String, u32) + Send + Sync>>,
 }
 
 impl JSRuntime {
     /// Create a new JavaScript runtime
     pub async fn new() -> Result<Self> {
+        Self::with_settings(SecuritySettings::default()).await
+    }
+    
+    /// Create a runtime whose heap is capped at `settings.max_memory_mb`
+    pub async fn with_settings(settings: SecuritySettings) -> Result<Self> {
         // Initialize V8
         let platform = v8::new_default_platform(0, false).make_shared();
         v8::V8::initialize_platform(platform);
         v8::V8::initialize();
         
-        // Create isolate
-        let mut isolate = v8::Isolate::new(v8::CreateParams::default());
+        // Create isolate; module state lives in a slot so the resolve callback can reach it
+        let max_heap_bytes = (settings.max_memory_mb as usize).saturating_mul(1024 * 1024);
+        let mut isolate = v8::Isolate::new(v8::CreateParams::default().heap_limits(0, max_heap_bytes));
+        
+        // Running out of heap terminates the script instead of aborting the process
+        let heap_limit = Arc::new(HeapLimitState {
+            isolate: isolate.thread_safe_handle(),
+            exceeded: AtomicBool::new(false),
+            initial_limit: AtomicUsize::new(0),
+        });
+        isolate.add_near_heap_limit_callback(near_heap_limit_callback, Arc::as_ptr(&heap_limit) as *mut c_void);
+        isolate.set_slot(heap_limit);
+        isolate.set_slot(ModuleRegistry::default());
+        isolate.set_slot(BrowsingContextState::default());
+        isolate.set_slot(ConsoleSink::default());
+        isolate.set_slot(FetchState::default());
+        isolate.set_slot(WebSocketState::default());
+        let security_settings = Arc::new(RwLock::new(settings));
+        isolate.set_slot(security_settings.clone());
+        isolate.set_slot(DomState::default());
         
         // Create context
         let context = {
@@ -49,10 +79,9 @@ impl JSRuntime {
         let mut runtime = Self {
             isolate,
             context,
-            security_settings: SecuritySettings::default(),
+            security_settings,
+            dangerous_globals_removed: false,
             execution_stats: Arc::new(Mutex::new(ExecutionStats::default())),
-            event_handlers: HashMap::new(),
-            console_callback: None,
         };
         
         // Set up built-in objects and security
@@ -67,9 +96,11 @@ impl JSRuntime {
         let start_time = Instant::now();
         
         // Security check
-        if !self.security_settings.allow_execution {
+        let settings = self.security_settings();
+        if !settings.allow_execution {
             return Err(EngineError::SecurityError("JavaScript execution disabled".to_string()));
         }
+        self.setup_security().await?;
         
         // Check for dangerous patterns
         if self.contains_dangerous_patterns(code) {
@@ -106,12 +137,17 @@ impl JSRuntime {
         };
         
         // Execute with timeout
-        let result = self.execute_with_timeout(scope, script, Duration::from_millis(5000)).await?;
+        let timeout = Duration::from_millis(settings.max_execution_time_ms);
+        let result = self.execute_with_timeout(scope, script, timeout).await;
+        
+        // Observers see the turn's DOM changes once the script and its microtasks are done
+        deliver_mutation_records(scope);
+        let result = result?;
         
         // Update stats
         let execution_time = start_time.elapsed();
         {
-            let mut stats = self.execution_stats.lock().unwrap();
+            let mut stats = self.execution_stats.lock().unwrap_or_else(PoisonError::into_inner);
             stats.total_executions += 1;
             stats.total_execution_time += execution_time;
             stats.last_execution_time = execution_time;
@@ -127,13 +163,17 @@ impl JSRuntime {
         script: v8::Local<v8::Script>,
         timeout: Duration,
     ) -> Result<JSValue> {
-        // Set up timeout (simplified - in reality we'd need proper async handling)
-        let start = Instant::now();
-        
+        // A watchdog thread terminates the script if it runs past the budget
+        let watchdog = Watchdog::start(scope.thread_safe_handle(), timeout);
         let result = script.run(scope);
+        let timed_out = watchdog.finish();
+        
+        if let Some(error) = self.take_heap_limit_error(scope) {
+            return Err(error);
+        }
         
-        if start.elapsed() > timeout {
-            return Err(EngineError::JavaScriptError("Script execution timeout".to_string()));
+        if let Some(error) = self.take_timeout_error(scope, timed_out, result.is_some()) {
+            return Err(error);
         }
         
         match result {
@@ -146,21 +186,212 @@ impl JSRuntime {
         }
     }
     
+    /// After the watchdog fired, clear the termination so the isolate can run again; a run
+    /// that didn't complete is reported as timed out
+    fn take_timeout_error(&self, scope: &mut v8::HandleScope, timed_out: bool, completed: bool) -> Option<EngineError> {
+        if !timed_out {
+            return None;
+        }
+        
+        scope.cancel_terminate_execution();
+        if completed {
+            return None;
+        }
+        let mut stats = self.execution_stats.lock().unwrap_or_else(PoisonError::into_inner);
+        stats.errors += 1;
+        stats.timeouts += 1;
+        Some(EngineError::JavaScriptError("Script execution timeout".to_string()))
+    }
+    
+    /// If the last run hit the heap limit, clear the termination, put back the heap limit
+    /// the callback raised so the isolate can run again, and report the script as out of memory
+    fn take_heap_limit_error(&self, scope: &mut v8::HandleScope) -> Option<EngineError> {
+        let state = scope.get_slot::<Arc<HeapLimitState>>()?.clone();
+        if !state.exceeded.swap(false, Ordering::SeqCst) {
+            return None;
+        }
+        
+        scope.cancel_terminate_execution();
+        // Removing the callback with a limit restores it; then re-arm for the next run
+        let initial_limit = state.initial_limit.load(Ordering::SeqCst);
+        scope.remove_near_heap_limit_callback(near_heap_limit_callback, initial_limit);
+        scope.add_near_heap_limit_callback(near_heap_limit_callback, Arc::as_ptr(&state) as *mut c_void);
+        let mut stats = self.execution_stats.lock().unwrap_or_else(PoisonError::into_inner);
+        stats.errors += 1;
+        stats.oom_terminations += 1;
+        Some(EngineError::JavaScriptError("out of memory".to_string()))
+    }
+    
+    /// Dispatch an event at an element of the bound document. Capturing listeners on its
+    /// ancestors run first, outermost first; then the target's inline `on<type>` handler and
+    /// listeners; then the event bubbles up through the ancestors' inline handlers and
+    /// non-capturing listeners. `stopPropagation()` ends dispatch after the current element.
+    /// Exceptions thrown by handlers are logged and don't stop dispatch. Returns false if a
+    /// handler called `preventDefault()`.
+    pub async fn dispatch_event(&mut self, target: ElementId, event: &DomEvent) -> Result<bool> {
+        let settings = self.security_settings();
+        if !settings.allow_execution {
+            return Err(EngineError::SecurityError("JavaScript execution disabled".to_string()));
+        }
+        self.setup_security().await?;
+        
+        // Target first, then each ancestor, with its inline handler source
+        let handler_attribute = format!("on{}", event.event_type);
+        let path: Vec<(Arc<Element>, Option<String>)> = {
+            let document = self.isolate.get_slot::<DomState>()
+                .and_then(|state| state.document.as_ref())
+                .ok_or_else(|| EngineError::DomError("InvalidStateError: no document is bound".to_string()))?;
+            std::iter::successors(document.elements.get(&target).cloned(), |element| {
+                element.parent.and_then(|parent| document.elements.get(&parent).cloned())
+            })
+            .map(|element| {
+                let handler = element.get_attribute(&handler_attribute).map(str::to_string);
+                (element, handler)
+            })
+            .collect()
+        };
+        if path.is_empty() {
+            return Err(EngineError::DomError(format!("NotFoundError: element {} is not in the document", target.0)));
+        }
+        if path.iter().filter_map(|(_, handler)| handler.as_deref()).any(|code| self.contains_dangerous_patterns(code)) {
+            return Err(EngineError::SecurityError("Potentially dangerous JavaScript detected".to_string()));
+        }
+        
+        let start_time = Instant::now();
+        let timeout = Duration::from_millis(settings.max_execution_time_ms);
+        let scope = &mut v8::HandleScope::new(&mut self.isolate);
+        let context = v8::Local::new(scope, &self.context);
+        let scope = &mut v8::ContextScope::new(scope, context);
+        
+        // (index into `path`, eventPhase): capturing, at target, bubbling
+        let steps = (1..path.len()).rev().map(|index| (index, EVENT_PHASE_CAPTURING))
+            .chain(std::iter::once((0, EVENT_PHASE_AT_TARGET)))
+            .chain((1..path.len()).map(|index| (index, EVENT_PHASE_BUBBLING)));
+        
+        let event_obj = event_object(scope, event, &path[0].0);
+        let watchdog = Watchdog::start(scope.thread_safe_handle(), timeout);
+        for (index, phase) in steps {
+            let (element, inline_handler) = &path[index];
+            let current_target = element_object(scope, element);
+            let current_target_name = v8::String::new(scope, "currentTarget").unwrap();
+            event_obj.set(scope, current_target_name.into(), current_target.into());
+            let phase_name = v8::String::new(scope, "eventPhase").unwrap();
+            let phase_value = v8::Integer::new(scope, phase);
+            event_obj.set(scope, phase_name.into(), phase_value.into());
+            
+            // Inline handlers are non-capturing, like a listener added first
+            let mut handlers = Vec::new();
+            if let Some(code) = inline_handler.as_ref().filter(|_| phase != EVENT_PHASE_CAPTURING) {
+                match compile_inline_handler(scope, code, &handler_attribute) {
+                    Ok(handler) => handlers.push(handler),
+                    Err(e) => log::warn!("Failed to compile {} handler: {}", handler_attribute, e),
+                }
+            }
+            let listeners: Vec<v8::Global<v8::Function>> = scope.get_slot::<DomState>()
+                .and_then(|state| state.listeners.get(&(element.id, event.event_type.clone())))
+                .into_iter()
+                .flatten()
+                .filter(|listener| match phase {
+                    EVENT_PHASE_CAPTURING => listener.capture,
+                    EVENT_PHASE_BUBBLING => !listener.capture,
+                    _ => true,
+                })
+                .map(|listener| listener.callback.clone())
+                .collect();
+            handlers.extend(listeners.iter().map(|listener| v8::Local::new(scope, listener)));
+            
+            for handler in handlers {
+                let scope = &mut v8::TryCatch::new(scope);
+                if handler.call(scope, current_target.into(), &[event_obj.into()]).is_none() && scope.has_caught() {
+                    log::warn!("Uncaught exception in {} handler: {}", event.event_type, exception_message(scope));
+                }
+            }
+            
+            let stopped_name = v8::String::new(scope, "cancelBubble").unwrap();
+            if event_obj.get(scope, stopped_name.into()).is_some_and(|stopped| stopped.boolean_value(scope)) {
+                break;
+            }
+        }
+        let timed_out = watchdog.finish();
+        deliver_mutation_records(scope);
+        
+        if let Some(error) = self.take_heap_limit_error(scope) {
+            return Err(error);
+        }
+        if let Some(error) = self.take_timeout_error(scope, timed_out, !timed_out) {
+            return Err(error);
+        }
+        
+        {
+            let mut stats = self.execution_stats.lock().unwrap_or_else(PoisonError::into_inner);
+            stats.total_executions += 1;
+            stats.total_execution_time += start_time.elapsed();
+            stats.last_execution_time = start_time.elapsed();
+        }
+        
+        let prevented_name = v8::String::new(scope, "defaultPrevented").unwrap();
+        let prevented = event_obj.get(scope, prevented_name.into()).is_some_and(|prevented| prevented.boolean_value(scope));
+        Ok(!prevented)
+    }
+    
     /// Execute page scripts from document
     pub async fn execute_page_scripts(&mut self, document: &Document) -> Result<()> {
+        self.execute_page_scripts_with(document, &HashMap::new()).await
+    }
+    
+    /// Execute the document's scripts in order, taking the source of `src` scripts from
+    /// `external`; scripts that weren't fetched are skipped
+    pub async fn execute_page_scripts_with(&mut self, document: &Document, external: &HashMap<ElementId, String>) -> Result<()> {
         // Find all script elements
         let script_elements = document.get_elements_by_tag_name("script");
         
-        for script_element in script_elements {
+        for (index, script_element) in script_elements.iter().enumerate() {
+            let script_type = script_element.get_attribute("type")
+                .map(|t| t.trim().to_ascii_lowercase())
+                .unwrap_or_default();
+            
+            // Import maps must be registered before any module that depends on them
+            if script_type == "importmap" {
+                if let Err(e) = self.add_import_map(&script_element.text_content(), &document.url) {
+                    log::warn!("Ignoring invalid import map: {:?}", e);
+                }
+                continue;
+            }
+            
             // Check if it's an inline script or external
             if let Some(src) = script_element.get_attribute("src") {
-                // External script - would need to fetch
-                log::info!("External script found: {}", src);
-                // TODO: Implement external script loading
+                let Some(source) = external.get(&script_element.id) else {
+                    log::info!("External script not loaded: {}", src);
+                    continue;
+                };
+                let script_url = Url::parse(&document.url)
+                    .and_then(|base| base.join(src))
+                    .map(String::from)
+                    .unwrap_or_else(|_| src.to_string());
+                
+                let result = if script_type == "module" {
+                    self.execute_module(source, &script_url).await
+                } else {
+                    self.execute_script(source, &script_url).await
+                };
+                match result {
+                    Ok(_) => log::debug!("External script {} executed successfully", script_url),
+                    Err(e) => log::warn!("External script {} failed: {:?}", script_url, e),
+                }
             } else {
                 // Inline script
                 let script_content = script_element.text_content();
-                if !script_content.trim().is_empty() {
+                if script_content.trim().is_empty() {
+                    continue;
+                }
+                
+                if script_type == "module" {
+                    let module_url = format!("{}#inline-module-{}", document.url, index);
+                    match self.execute_module(&script_content, &module_url).await {
+                        Ok(_) => log::debug!("Inline module executed successfully"),
+                        Err(e) => log::warn!("Inline module execution failed: {:?}", e),
+                    }
+                } else {
                     match self.execute_script(&script_content, "inline-script").await {
                         Ok(_) => log::debug!("Inline script executed successfully"),
                         Err(e) => log::warn!("Inline script execution failed: {:?}", e),
@@ -169,9 +400,105 @@ impl JSRuntime {
             }
         }
         
+        // Settle what the scripts started; later activity is pumped by `run_event_loop`
+        if let Err(e) = self.run_event_loop(Duration::ZERO).await {
+            log::warn!("Script callbacks failed: {:?}", e);
+        }
+        
         Ok(())
     }
     
+    /// Merge an import map (the JSON body of `<script type="importmap">`) into the runtime
+    pub fn add_import_map(&mut self, json: &str, base_url: &str) -> Result<()> {
+        let import_map = ImportMap::parse(json, base_url)?;
+        if let Some(registry) = self.isolate.get_slot_mut::<ModuleRegistry>() {
+            registry.import_map.merge(import_map);
+        }
+        Ok(())
+    }
+    
+    /// Make a module's source available for import at the given URL
+    pub fn register_module_source(&mut self, url: &str, source: &str) -> Result<()> {
+        let url = Url::parse(url)
+            .map_err(|e| EngineError::JavaScriptError(format!("Invalid module URL {}: {}", url, e)))?;
+        if let Some(registry) = self.isolate.get_slot_mut::<ModuleRegistry>() {
+            registry.sources.insert(url.to_string(), source.to_string());
+        }
+        Ok(())
+    }
+    
+    /// Execute an ES module, resolving its imports through the import map
+    pub async fn execute_module(&mut self, code: &str, module_url: &str) -> Result<JSValue> {
+        let start_time = Instant::now();
+        
+        // Security check
+        let settings = self.security_settings();
+        if !settings.allow_execution {
+            return Err(EngineError::SecurityError("JavaScript execution disabled".to_string()));
+        }
+        self.setup_security().await?;
+        
+        // Check for dangerous patterns
+        if self.contains_dangerous_patterns(code) {
+            return Err(EngineError::SecurityError("Potentially dangerous JavaScript detected".to_string()));
+        }
+        
+        let scope = &mut v8::HandleScope::new(&mut self.isolate);
+        let context = v8::Local::new(scope, &self.context);
+        let scope = &mut v8::ContextScope::new(scope, context);
+        let scope = &mut v8::TryCatch::new(scope);
+        
+        let module = match compile_module(scope, module_url, code) {
+            Some(module) => module,
+            None => {
+                let message = exception_message(scope);
+                return Err(EngineError::JavaScriptError(format!("Module compilation error: {}", message)));
+            }
+        };
+        
+        if module.instantiate_module(scope, resolve_module_callback) != Some(true) {
+            let message = exception_message(scope);
+            return Err(EngineError::JavaScriptError(format!("Module resolution error: {}", message)));
+        }
+        
+        let watchdog = Watchdog::start(
+            scope.thread_safe_handle(),
+            Duration::from_millis(settings.max_execution_time_ms),
+        );
+        let result = module.evaluate(scope);
+        let timed_out = watchdog.finish();
+        if let Some(error) = self.take_timeout_error(scope, timed_out, result.is_some()) {
+            return Err(error);
+        }
+        scope.perform_microtask_checkpoint();
+        
+        if let Some(error) = self.take_heap_limit_error(scope) {
+            return Err(error);
+        }
+        
+        if module.get_status() == v8::ModuleStatus::Errored {
+            let exception = module.get_exception();
+            let message = exception.to_rust_string_lossy(scope);
+            return Err(EngineError::JavaScriptError(format!("Runtime error: {}", message)));
+        }
+        
+        let value = match result {
+            Some(value) => self.v8_value_to_js_value(scope, value),
+            None => JSValue::Undefined,
+        };
+        
+        // Update stats
+        let execution_time = start_time.elapsed();
+        {
+            let mut stats = self.execution_stats.lock().unwrap_or_else(PoisonError::into_inner);
+            stats.total_executions += 1;
+            stats.total_execution_time += execution_time;
+            stats.last_execution_time = execution_time;
+        }
+        
+        Ok(value)
+    }
+    
     /// Set up built-in JavaScript objects
     async fn setup_builtin_objects(&mut self) -> Result<()> {
         let scope = &mut v8::HandleScope::new(&mut self.isolate);
@@ -186,6 +513,8 @@ impl JSRuntime {
         
         // Set up Web APIs
         self.setup_web_apis(scope)?;
+        self.setup_navigator(scope)?;
+        self.setup_messaging(scope)?;
         
         // Set up Titan-specific APIs
         self.setup_titan_apis(scope)?;
@@ -193,41 +522,22 @@ impl JSRuntime {
         Ok(())
     }
     
-    /// Set up console object
+    /// Set up console object; every method routes through `console_message`
     fn setup_console(&mut self, scope: &mut v8::ContextScope) -> Result<()> {
         let global = scope.get_current_context().global(scope);
         
         // Create console object
         let console_obj = v8::Object::new(scope);
         
-        // console.log
-        let log_fn = v8::Function::new(
-            scope,
-            |scope: &mut v8::HandleScope,
-             args: v8::FunctionCallbackArguments,
-             _rv: v8::ReturnValue| {
-                let message = args.get(0).to_rust_string_lossy(scope);
-                // TODO: Call console callback
-                println!("[JS Console] {}", message);
-            },
-        ).unwrap();
-        
-        let log_name = v8::String::new(scope, "log").unwrap();
-        console_obj.set(scope, log_name.into(), log_fn.into());
-        
-        // console.error
-        let error_fn = v8::Function::new(
-            scope,
-            |scope: &mut v8::HandleScope,
-             args: v8::FunctionCallbackArguments,
-             _rv: v8::ReturnValue| {
-                let message = args.get(0).to_rust_string_lossy(scope);
-                eprintln!("[JS Error] {}", message);
-            },
-        ).unwrap();
-        
-        let error_name = v8::String::new(scope, "error").unwrap();
-        console_obj.set(scope, error_name.into(), error_fn.into());
+        for (index, (name, _)) in CONSOLE_METHODS.iter().enumerate() {
+            let level = v8::Integer::new(scope, index as i32);
+            let method_fn = v8::Function::builder(console_message)
+                .data(level.into())
+                .build(scope)
+                .unwrap();
+            let method_name = v8::String::new(scope, name).unwrap();
+            console_obj.set(scope, method_name.into(), method_fn.into());
+        }
         
         // Add console to global
         let console_name = v8::String::new(scope, "console").unwrap();
@@ -243,16 +553,23 @@ impl JSRuntime {
         // document object (simplified)
         let document_obj = v8::Object::new(scope);
         
-        // document.getElementById
+        // document.getElementById, answered from the document bound with `bind_document`
         let get_element_by_id_fn = v8::Function::new(
             scope,
             |scope: &mut v8::HandleScope,
              args: v8::FunctionCallbackArguments,
              mut rv: v8::ReturnValue| {
                 let id = args.get(0).to_rust_string_lossy(scope);
-                // TODO: Implement actual DOM lookup
-                let null_value = v8::null(scope);
-                rv.set(null_value.into());
+                let element = scope.get_slot::<DomState>()
+                    .and_then(|state| state.document.as_ref())
+                    .and_then(|document| document.get_element_by_id(&id));
+                match element {
+                    Some(element) => {
+                        let element_obj = element_object(scope, &element);
+                        rv.set(element_obj.into());
+                    }
+                    None => rv.set(v8::null(scope).into()),
+                }
             },
         ).unwrap();
         
@@ -285,21 +602,239 @@ impl JSRuntime {
         let set_timeout_name = v8::String::new(scope, "setTimeout").unwrap();
         global.set(scope, set_timeout_name.into(), set_timeout_fn.into());
         
-        // fetch API (simplified)
-        let fetch_fn = v8::Function::new(
+        // fetch API, backed by the engine's network stack; see `run_pending_fetches`
+        let fetch_fn = v8::Function::new(scope, fetch_binding).unwrap();
+        
+        let fetch_name = v8::String::new(scope, "fetch").unwrap();
+        global.set(scope, fetch_name.into(), fetch_fn.into());
+        
+        // WebSocket over the network stack's client; see `run_websocket_events`
+        let websocket_fn = v8::Function::new(scope, websocket_constructor).unwrap();
+        for (state, name) in WEBSOCKET_READY_STATES.iter().enumerate() {
+            let name = v8::String::new(scope, name).unwrap();
+            let value = v8::Integer::new(scope, state as i32);
+            websocket_fn.set(scope, name.into(), value.into());
+        }
+        
+        let websocket_name = v8::String::new(scope, "WebSocket").unwrap();
+        global.set(scope, websocket_name.into(), websocket_fn.into());
+        
+        Ok(())
+    }
+    
+    /// Set up `navigator`, including the async Clipboard API and the permission-gated
+    /// geolocation, media capture, and notification APIs
+    fn setup_navigator(&mut self, scope: &mut v8::ContextScope) -> Result<()> {
+        let global = scope.get_current_context().global(scope);
+        
+        let navigator_obj = v8::Object::new(scope);
+        let clipboard_obj = v8::Object::new(scope);
+        
+        // navigator.clipboard.writeText
+        let write_text_fn = v8::Function::new(
             scope,
             |scope: &mut v8::HandleScope,
              args: v8::FunctionCallbackArguments,
              mut rv: v8::ReturnValue| {
-                let url = args.get(0).to_rust_string_lossy(scope);
-                // TODO: Implement actual fetch functionality
-                let promise = v8::Promise::resolver(scope).unwrap();
-                rv.set(promise.get_promise(scope).into());
+                let text = args.get(0).to_rust_string_lossy(scope);
+                let outcome = clipboard_access(scope, PermissionKind::ClipboardWrite)
+                    .and_then(|clipboard| clipboard.write_text(&text).map(|_| None));
+                rv.set(settled_promise(scope, outcome).into());
             },
         ).unwrap();
         
-        let fetch_name = v8::String::new(scope, "fetch").unwrap();
-        global.set(scope, fetch_name.into(), fetch_fn.into());
+        let write_text_name = v8::String::new(scope, "writeText").unwrap();
+        clipboard_obj.set(scope, write_text_name.into(), write_text_fn.into());
+        
+        // navigator.clipboard.readText
+        let read_text_fn = v8::Function::new(
+            scope,
+            |scope: &mut v8::HandleScope,
+             _args: v8::FunctionCallbackArguments,
+             mut rv: v8::ReturnValue| {
+                let outcome = clipboard_access(scope, PermissionKind::ClipboardRead)
+                    .and_then(|clipboard| clipboard.read_text().map(Some));
+                rv.set(settled_promise(scope, outcome).into());
+            },
+        ).unwrap();
+        
+        let read_text_name = v8::String::new(scope, "readText").unwrap();
+        clipboard_obj.set(scope, read_text_name.into(), read_text_fn.into());
+        
+        let clipboard_name = v8::String::new(scope, "clipboard").unwrap();
+        navigator_obj.set(scope, clipboard_name.into(), clipboard_obj.into());
+        
+        // navigator.geolocation.getCurrentPosition(success, error)
+        let geolocation_obj = v8::Object::new(scope);
+        let get_position_fn = v8::Function::new(
+            scope,
+            |scope: &mut v8::HandleScope,
+             args: v8::FunctionCallbackArguments,
+             _rv: v8::ReturnValue| {
+                let Ok(success) = v8::Local::<v8::Function>::try_from(args.get(0)) else {
+                    let message = v8::String::new(scope, "getCurrentPosition requires a success callback").unwrap();
+                    let exception = v8::Exception::type_error(scope, message);
+                    scope.throw_exception(exception);
+                    return;
+                };
+                let error = match v8::Local::<v8::Function>::try_from(args.get(1)) {
+                    Ok(error) => error,
+                    Err(_) => v8::Function::new(scope, |_: &mut v8::HandleScope, _: v8::FunctionCallbackArguments, _: v8::ReturnValue| {}).unwrap(),
+                };
+                
+                let resolver = v8::PromiseResolver::new(scope).unwrap();
+                match current_position(scope) {
+                    Ok(position) => {
+                        let position = position_object(scope, position);
+                        resolver.resolve(scope, position.into());
+                    }
+                    Err((code, message)) => {
+                        let error = position_error(scope, code, &message);
+                        resolver.reject(scope, error.into());
+                    }
+                }
+                
+                // Callbacks run after the calling script, as they would once a position arrives
+                resolver.get_promise(scope).then2(scope, success, error);
+            },
+        ).unwrap();
+        
+        let get_position_name = v8::String::new(scope, "getCurrentPosition").unwrap();
+        geolocation_obj.set(scope, get_position_name.into(), get_position_fn.into());
+        
+        let geolocation_name = v8::String::new(scope, "geolocation").unwrap();
+        navigator_obj.set(scope, geolocation_name.into(), geolocation_obj.into());
+        
+        // navigator.mediaDevices.getUserMedia({ audio, video })
+        let media_devices_obj = v8::Object::new(scope);
+        let get_user_media_fn = v8::Function::new(
+            scope,
+            |scope: &mut v8::HandleScope,
+             args: v8::FunctionCallbackArguments,
+             mut rv: v8::ReturnValue| {
+                let constraints = args.get(0);
+                let mut requested = Vec::new();
+                for (name, kind) in [("audio", PermissionKind::Microphone), ("video", PermissionKind::Camera)] {
+                    let key = v8::String::new(scope, name).unwrap();
+                    let wanted = constraints.to_object(scope)
+                        .and_then(|constraints| constraints.get(scope, key.into()))
+                        .is_some_and(|value| value.boolean_value(scope));
+                    if wanted {
+                        requested.push((name, kind));
+                    }
+                }
+                
+                let resolver = v8::PromiseResolver::new(scope).unwrap();
+                let outcome = if requested.is_empty() {
+                    Err(EngineError::SecurityError("TypeError: getUserMedia requires audio or video".to_string()))
+                } else {
+                    requested.iter().try_for_each(|(_, kind)| feature_access(scope, *kind))
+                };
+                match outcome {
+                    Ok(()) => {
+                        let kinds: Vec<&str> = requested.iter().map(|(name, _)| *name).collect();
+                        let stream = media_stream_object(scope, &kinds);
+                        resolver.resolve(scope, stream.into());
+                    }
+                    Err(e) => reject_promise(scope, resolver, e),
+                }
+                rv.set(resolver.get_promise(scope).into());
+            },
+        ).unwrap();
+        
+        let get_user_media_name = v8::String::new(scope, "getUserMedia").unwrap();
+        media_devices_obj.set(scope, get_user_media_name.into(), get_user_media_fn.into());
+        
+        let media_devices_name = v8::String::new(scope, "mediaDevices").unwrap();
+        navigator_obj.set(scope, media_devices_name.into(), media_devices_obj.into());
+        
+        // Add navigator to global
+        let navigator_name = v8::String::new(scope, "navigator").unwrap();
+        global.set(scope, navigator_name.into(), navigator_obj.into());
+        
+        // Notification.permission and Notification.requestPermission(); there is no prompt,
+        // so requesting reports the decision already recorded for the origin
+        let notification_obj = v8::Object::new(scope);
+        let permission_name = v8::String::new(scope, "permission").unwrap();
+        notification_obj.set_accessor(
+            scope,
+            permission_name.into(),
+            |scope: &mut v8::HandleScope,
+             _key: v8::Local<v8::Name>,
+             _args: v8::PropertyCallbackArguments,
+             mut rv: v8::ReturnValue| {
+                let permission = v8::String::new(scope, notification_permission(scope)).unwrap();
+                rv.set(permission.into());
+            },
+        );
+        
+        let request_permission_fn = v8::Function::new(
+            scope,
+            |scope: &mut v8::HandleScope,
+             _args: v8::FunctionCallbackArguments,
+             mut rv: v8::ReturnValue| {
+                let permission = notification_permission(scope).to_string();
+                rv.set(settled_promise(scope, Ok(Some(permission))).into());
+            },
+        ).unwrap();
+        
+        let request_permission_name = v8::String::new(scope, "requestPermission").unwrap();
+        notification_obj.set(scope, request_permission_name.into(), request_permission_fn.into());
+        
+        let notification_name = v8::String::new(scope, "Notification").unwrap();
+        global.set(scope, notification_name.into(), notification_obj.into());
+        
+        Ok(())
+    }
+    
+    /// Set up `structuredClone` and `MessageChannel`
+    fn setup_messaging(&mut self, scope: &mut v8::ContextScope) -> Result<()> {
+        let global = scope.get_current_context().global(scope);
+        
+        // structuredClone
+        let structured_clone_fn = v8::Function::new(
+            scope,
+            |scope: &mut v8::HandleScope,
+             args: v8::FunctionCallbackArguments,
+             mut rv: v8::ReturnValue| {
+                if let Some(clone) = structured_clone(scope, args.get(0)) {
+                    rv.set(clone);
+                }
+            },
+        ).unwrap();
+        
+        let structured_clone_name = v8::String::new(scope, "structuredClone").unwrap();
+        global.set(scope, structured_clone_name.into(), structured_clone_fn.into());
+        
+        // MessageChannel constructor creating two entangled ports
+        let message_channel_fn = v8::Function::new(
+            scope,
+            |scope: &mut v8::HandleScope,
+             args: v8::FunctionCallbackArguments,
+             _rv: v8::ReturnValue| {
+                if args.new_target().is_undefined() {
+                    let message = v8::String::new(scope, "MessageChannel constructor requires 'new'").unwrap();
+                    let exception = v8::Exception::type_error(scope, message);
+                    scope.throw_exception(exception);
+                    return;
+                }
+                
+                let port1 = new_message_port(scope);
+                let port2 = new_message_port(scope);
+                let entangled_key = private_key(scope, ENTANGLED_PORT_KEY);
+                port1.set_private(scope, entangled_key, port2.into());
+                port2.set_private(scope, entangled_key, port1.into());
+                
+                let channel = args.this();
+                let port1_name = v8::String::new(scope, "port1").unwrap();
+                channel.set(scope, port1_name.into(), port1.into());
+                let port2_name = v8::String::new(scope, "port2").unwrap();
+                channel.set(scope, port2_name.into(), port2.into());
+            },
+        ).unwrap();
+        
+        let message_channel_name = v8::String::new(scope, "MessageChannel").unwrap();
+        global.set(scope, message_channel_name.into(), message_channel_fn.into());
         
         Ok(())
     }
@@ -344,52 +879,43 @@ impl JSRuntime {
         Ok(())
     }
     
-    /// Set up security restrictions
+    /// Remove dangerous globals once the security level is high. Checked before each script
+    /// so a level raised through `update_security_settings` applies to the next one.
     async fn setup_security(&mut self) -> Result<()> {
+        if self.dangerous_globals_removed || self.security_settings().security_level < 2 {
+            return Ok(());
+        }
+        self.dangerous_globals_removed = true;
+        
         let scope = &mut v8::HandleScope::new(&mut self.isolate);
         let context = v8::Local::new(scope, &self.context);
         let scope = &mut v8::ContextScope::new(scope, context);
         let global = scope.get_current_context().global(scope);
         
-        // Disable dangerous globals if security level is high
-        if self.security_settings.security_level >= 2 {
-            // Disable eval
-            let eval_name = v8::String::new(scope, "eval").unwrap();
-            let undefined = v8::undefined(scope);
-            global.set(scope, eval_name.into(), undefined.into());
-            
-            // Disable Function constructor
-            let function_name = v8::String::new(scope, "Function").unwrap();
-            global.set(scope, function_name.into(), undefined.into());
-        }
+        // Disable eval
+        let eval_name = v8::String::new(scope, "eval").unwrap();
+        let undefined = v8::undefined(scope);
+        global.set(scope, eval_name.into(), undefined.into());
+        
+        // Disable Function constructor
+        let function_name = v8::String::new(scope, "Function").unwrap();
+        global.set(scope, function_name.into(), undefined.into());
         
         Ok(())
     }
     
-    /// Check for dangerous JavaScript patterns
+    /// Check for dangerous API usage. Code is tokenized first, so names inside strings and
+    /// comments don't count, while computed access like `window['eval']` does.
     fn contains_dangerous_patterns(&self, code: &str) -> bool {
-        let dangerous_patterns = [
-            "eval(",
-            "Function(",
-            "document.write(",
-            "innerHTML",
-            "outerHTML",
-            "document.cookie",
-            "localStorage",
-            "sessionStorage",
-            "XMLHttpRequest",
-            "fetch(",
-            "import(",
-            "require(",
-        ];
-        
-        let code_lower = code.to_lowercase();
-        for pattern in &dangerous_patterns {
-            if code_lower.contains(pattern) {
-                log::warn!("Dangerous JavaScript pattern detected: {}", pattern);
-                if self.security_settings.block_dangerous_patterns {
-                    return true;
-                }
+        let settings = self.security_settings();
+        for pattern in find_dangerous_usages(code) {
+            // fetch is gated by the network permission instead
+            if pattern == "fetch(" && settings.allow_network_access {
+                continue;
+            }
+            log::warn!("Dangerous JavaScript pattern detected: {}", pattern);
+            if settings.block_dangerous_patterns {
+                return true;
             }
         }
         
@@ -419,55 +945,2218 @@ impl JSRuntime {
         }
     }
     
-    /// Set console message callback
+    /// Set console message callback, called with the level, formatted message, script name and line
     pub fn set_console_callback<F>(&mut self, callback: F)
     where
         F: Fn(ConsoleLevel, String, String, u32) + Send + Sync + 'static,
     {
-        self.console_callback = Some(Box::new(callback));
+        if let Some(sink) = self.isolate.get_slot_mut::<ConsoleSink>() {
+            sink.callback = Some(Arc::new(callback));
+        }
     }
     
     /// Get execution statistics
     pub fn get_execution_stats(&self) -> ExecutionStats {
-        self.execution_stats.lock().unwrap().clone()
+        self.execution_stats.lock().unwrap_or_else(PoisonError::into_inner).clone()
     }
     
-    /// Update security settings
-    pub fn update_security_settings(&mut self, settings: SecuritySettings) {
-        self.security_settings = settings;
+    /// Set the origin scripts in this runtime run as, used for permission checks
+    pub fn set_origin(&mut self, origin: &str) {
+        if let Some(state) = self.isolate.get_slot_mut::<BrowsingContextState>() {
+            state.origin = origin.to_string();
+        }
     }
     
-    /// Shutdown the runtime
-    pub async fn shutdown(&mut self) -> Result<()> {
-        // Clean up V8 resources
-        // Note: V8 doesn't provide explicit cleanup methods for isolates
-        log::info!("JavaScript runtime shutting down");
-        Ok(())
+    /// Apply the page's Content-Security-Policy header; its `connect-src` (or `default-src`)
+    /// limits where `fetch` and `WebSocket` may connect
+    pub fn set_content_security_policy(&mut self, policy: Option<&str>) {
+        if let Some(state) = self.isolate.get_slot_mut::<BrowsingContextState>() {
+            state.connect_src = policy.and_then(connect_sources);
+        }
     }
-}
-
-/// JavaScript value types
-#[derive(Debug, Clone)]
-pub enum JSValue {
-    Undefined,
-    Null,
-    Boolean(bool),
-    Number(f64),
-    String(String),
-    Array(Vec<JSValue>),
-    Object(HashMap<String, JSValue>),
-    Function,
-}
-
-impl JSValue {
-    /// Convert to string representation
-    pub fn to_string(&self) -> String {
-        match self {
-            JSValue::Undefined => "undefined".to_string(),
-            JSValue::Null => "null".to_string(),
-            JSValue::Boolean(b) => b.to_string(),
-            JSValue::Number(n) => n.to_string(),
-            JSValue::String(s) => s.clone(),
+    
+    /// Make `document` visible to scripts; mutations they make are applied to this copy
+    pub fn bind_document(&mut self, document: Document) {
+        if let Some(state) = self.isolate.get_slot_mut::<DomState>() {
+            state.document = Some(document);
+        }
+    }
+    
+    /// Unbind the document, returning it with any script mutations applied
+    pub fn take_document(&mut self) -> Option<Document> {
+        self.isolate.get_slot_mut::<DomState>().and_then(|state| state.document.take())
+    }
+    
+    /// Share a permission store with this runtime
+    pub fn set_permission_store(&mut self, permissions: PermissionStore) {
+        if let Some(state) = self.isolate.get_slot_mut::<BrowsingContextState>() {
+            state.permissions = permissions;
+        }
+    }
+    
+    /// Switch off the powerful features the settings disable, whatever an origin was granted
+    pub fn apply_engine_settings(&mut self, settings: &EngineSettings) {
+        if let Some(state) = self.isolate.get_slot_mut::<BrowsingContextState>() {
+            state.disabled_features = [
+                (PermissionKind::Geolocation, settings.geolocation_enabled),
+                (PermissionKind::Notifications, settings.notifications_enabled),
+                (PermissionKind::Camera, settings.camera_enabled),
+                (PermissionKind::Microphone, settings.microphone_enabled),
+            ]
+            .into_iter()
+            .filter(|(_, enabled)| !enabled)
+            .map(|(kind, _)| kind)
+            .collect();
+        }
+    }
+    
+    /// Position reported to granted `navigator.geolocation` calls; unavailable when `None`
+    pub fn set_geolocation(&mut self, position: Option<GeoPosition>) {
+        if let Some(state) = self.isolate.get_slot_mut::<BrowsingContextState>() {
+            state.geolocation = position;
+        }
+    }
+    
+    /// Replace the clipboard backing `navigator.clipboard` (in-memory by default)
+    pub fn set_clipboard(&mut self, clipboard: Arc<dyn ClipboardProvider>) {
+        if let Some(state) = self.isolate.get_slot_mut::<BrowsingContextState>() {
+            state.clipboard = clipboard;
+        }
+    }
+    
+    /// Record whether the page is handling a user gesture (click, key press)
+    pub fn set_user_activation(&mut self, active: bool) {
+        if let Some(state) = self.isolate.get_slot_mut::<BrowsingContextState>() {
+            state.user_activation = active;
+        }
+    }
+    
+    /// Update security settings; they apply from the next script, event or fetch.
+    /// A heap limit change only takes effect for new runtimes.
+    pub fn update_security_settings(&self, settings: SecuritySettings) {
+        *self.security_settings.write().unwrap_or_else(PoisonError::into_inner) = settings;
+    }
+    
+    /// The current security settings
+    pub fn security_settings(&self) -> SecuritySettings {
+        self.security_settings.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+    
+    /// Give `fetch` a network stack to issue requests through
+    pub fn set_network_stack(&mut self, network: Arc<NetworkStack>) {
+        if let Some(state) = self.isolate.get_slot_mut::<FetchState>() {
+            state.network = Some(network);
+        }
+    }
+    
+    /// Wait for in-flight `fetch` requests, settle their promises and run the resulting
+    /// microtasks, repeating until callbacks stop issuing new requests. Callbacks share the
+    /// script execution timeout. Returns how many requests were settled.
+    pub async fn run_pending_fetches(&mut self) -> Result<usize> {
+        let timeout = Duration::from_millis(self.security_settings().max_execution_time_ms);
+        let mut settled = 0;
+        loop {
+            let pending = self.isolate.get_slot_mut::<FetchState>()
+                .map(|state| std::mem::take(&mut state.pending))
+                .unwrap_or_default();
+            if pending.is_empty() {
+                return Ok(settled);
+            }
+            
+            let mut outcomes = Vec::with_capacity(pending.len());
+            for fetch in pending {
+                let outcome = match fetch.request.await {
+                    Ok(outcome) => outcome,
+                    Err(e) => Err(EngineError::network(NetworkErrorKind::Other, None, format!("Fetch task failed: {}", e))),
+                };
+                outcomes.push((fetch.resolver, outcome, fetch.credentials));
+            }
+            
+            let origin = self.isolate.get_slot::<BrowsingContextState>()
+                .map(|state| state.origin.clone())
+                .unwrap_or_default();
+            let scope = &mut v8::HandleScope::new(&mut self.isolate);
+            let context = v8::Local::new(scope, &self.context);
+            let scope = &mut v8::ContextScope::new(scope, context);
+            
+            let watchdog = Watchdog::start(scope.thread_safe_handle(), timeout);
+            for (resolver, outcome, credentials) in outcomes {
+                let resolver = v8::Local::new(scope, resolver);
+                match outcome.and_then(|response| cors_check(&origin, response, credentials)) {
+                    Ok(response) => {
+                        let response_obj = response_object(scope, &response);
+                        resolver.resolve(scope, response_obj.into());
+                    }
+                    Err(e) => reject_promise(scope, resolver, e),
+                }
+                settled += 1;
+            }
+            scope.perform_microtask_checkpoint();
+            let timed_out = watchdog.finish();
+            deliver_mutation_records(scope);
+            
+            if let Some(error) = self.take_heap_limit_error(scope) {
+                return Err(error);
+            }
+            if let Some(error) = self.take_timeout_error(scope, timed_out, !timed_out) {
+                return Err(error);
+            }
+        }
+    }
+    
+    /// Run one turn of the page's event loop: settle finished `fetch` requests, dispatch the
+    /// WebSocket events that arrive within `wait`, then settle any requests their handlers made.
+    /// Returns how many requests and events were handled.
+    pub async fn run_event_loop(&mut self, wait: Duration) -> Result<usize> {
+        let settled = self.run_pending_fetches().await?;
+        let dispatched = self.run_websocket_events(wait).await?;
+        Ok(settled + dispatched + self.run_pending_fetches().await?)
+    }
+    
+    /// Wait up to `wait` for activity on the page's WebSockets, then dispatch every event that
+    /// has arrived (`open`, `message`, `error`, `close`) and run the resulting microtasks.
+    /// Handlers share the script execution timeout.
+    /// Returns how many events were dispatched; 0 straight away when no socket is open.
+    pub async fn run_websocket_events(&mut self, wait: Duration) -> Result<usize> {
+        let events = self.isolate.get_slot_mut::<WebSocketState>()
+            .filter(|state| !state.sockets.is_empty())
+            .and_then(|state| state.events.take());
+        let Some(mut events) = events else {
+            return Ok(0);
+        };
+        
+        let mut arrived = Vec::new();
+        if let Ok(Some(event)) = tokio::time::timeout(wait, events.recv()).await {
+            arrived.push(event);
+            while let Ok(event) = events.try_recv() {
+                arrived.push(event);
+            }
+        }
+        if let Some(state) = self.isolate.get_slot_mut::<WebSocketState>() {
+            state.events = Some(events);
+        }
+        
+        let timeout = Duration::from_millis(self.security_settings().max_execution_time_ms);
+        let scope = &mut v8::HandleScope::new(&mut self.isolate);
+        let context = v8::Local::new(scope, &self.context);
+        let scope = &mut v8::ContextScope::new(scope, context);
+        let dispatched = arrived.len();
+        let watchdog = Watchdog::start(scope.thread_safe_handle(), timeout);
+        for (id, event) in arrived {
+            dispatch_socket_event(scope, id, event);
+        }
+        scope.perform_microtask_checkpoint();
+        let timed_out = watchdog.finish();
+        deliver_mutation_records(scope);
+        
+        if let Some(error) = self.take_heap_limit_error(scope) {
+            return Err(error);
+        }
+        if let Some(error) = self.take_timeout_error(scope, timed_out, !timed_out) {
+            return Err(error);
+        }
+        Ok(dispatched)
+    }
+    
+    /// Shutdown the runtime
+    pub async fn shutdown(&mut self) -> Result<()> {
+        // Clean up V8 resources
+        // Note: V8 doesn't provide explicit cleanup methods for isolates
+        log::info!("JavaScript runtime shutting down");
+        Ok(())
+    }
+}
+
+/// Clipboard used by `navigator.clipboard`, supplied by the embedder
+pub trait ClipboardProvider: Send + Sync {
+    fn read_text(&self) -> Result<String>;
+    fn write_text(&self, text: &str) -> Result<()>;
+}
+
+/// Clipboard kept in memory, used when the embedder provides none
+#[derive(Debug, Default)]
+pub struct InMemoryClipboard {
+    text: Mutex<String>,
+}
+
+impl ClipboardProvider for InMemoryClipboard {
+    fn read_text(&self) -> Result<String> {
+        Ok(self.text.lock().unwrap_or_else(PoisonError::into_inner).clone())
+    }
+    
+    fn write_text(&self, text: &str) -> Result<()> {
+        *self.text.lock().unwrap_or_else(PoisonError::into_inner) = text.to_string();
+        Ok(())
+    }
+}
+
+/// Location reported by `navigator.geolocation`, in degrees and meters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoPosition {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub accuracy: f64,
+}
+
+/// Per-context state that JS bindings read through an isolate slot
+struct BrowsingContextState {
+    origin: String,
+    permissions: PermissionStore,
+    /// Features switched off in the engine settings, denied even when granted
+    disabled_features: HashSet<PermissionKind>,
+    geolocation: Option<GeoPosition>,
+    clipboard: Arc<dyn ClipboardProvider>,
+    user_activation: bool,
+    /// CSP sources scripts may connect to; unrestricted when `None`
+    connect_src: Option<Vec<String>>,
+}
+
+impl Default for BrowsingContextState {
+    fn default() -> Self {
+        Self {
+            origin: String::new(),
+            permissions: PermissionStore::new(),
+            disabled_features: HashSet::new(),
+            geolocation: None,
+            clipboard: Arc::new(InMemoryClipboard::default()),
+            user_activation: false,
+            connect_src: None,
+        }
+    }
+}
+
+/// Tokens the dangerous-pattern scan distinguishes; string contents are kept for computed access
+#[derive(Debug, Clone, PartialEq)]
+enum ScanToken {
+    Ident(String),
+    Str(String),
+    /// Numbers and regular expressions
+    Literal,
+    Punct(char),
+}
+
+/// Globals that are dangerous to call
+const DANGEROUS_CALLS: [(&str, &str); 4] = [
+    ("eval", "eval("),
+    ("Function", "Function("),
+    ("require", "require("),
+    ("fetch", "fetch("),
+];
+
+/// Globals that are dangerous to touch at all
+const DANGEROUS_GLOBALS: [&str; 3] = ["XMLHttpRequest", "localStorage", "sessionStorage"];
+
+/// Properties that are dangerous on any object
+const DANGEROUS_MEMBERS: [&str; 2] = ["innerHTML", "outerHTML"];
+
+/// Names that refer to the global object, so `window.eval` is treated like `eval`
+const GLOBAL_OBJECTS: [&str; 3] = ["window", "self", "globalThis"];
+
+/// Dangerous API usages in `code`, labelled like the patterns they replace (e.g. `eval(`)
+fn find_dangerous_usages(code: &str) -> Vec<&'static str> {
+    let chars: Vec<char> = code.chars().collect();
+    let mut tokens = Vec::new();
+    scan_tokens(&chars, &mut 0, false, &mut tokens);
+    
+    let mut found = Vec::new();
+    for (index, token) in tokens.iter().enumerate() {
+        let previous = index.checked_sub(1).map(|i| &tokens[i]);
+        let next = tokens.get(index + 1);
+        // `a.b` where `a` isn't the global object is some other object's property
+        let on_other_object = previous == Some(&ScanToken::Punct('.'))
+            && !matches!(index.checked_sub(2).map(|i| &tokens[i]), Some(ScanToken::Ident(name)) if GLOBAL_OBJECTS.contains(&name.as_str()));
+        let called = next == Some(&ScanToken::Punct('('));
+        
+        match token {
+            ScanToken::Ident(name) => {
+                if let Some((_, label)) = DANGEROUS_CALLS.iter().find(|(call, _)| *call == name.as_str()) {
+                    if called && !on_other_object {
+                        found.push(*label);
+                    }
+                } else if let Some(global) = DANGEROUS_GLOBALS.iter().find(|global| **global == name.as_str()) {
+                    if !on_other_object {
+                        found.push(*global);
+                    }
+                } else if let Some(member) = DANGEROUS_MEMBERS.iter().find(|member| **member == name.as_str()) {
+                    if previous == Some(&ScanToken::Punct('.')) {
+                        found.push(*member);
+                    }
+                } else if name == "import" && called {
+                    found.push("import(");
+                } else if name == "document" && next == Some(&ScanToken::Punct('.')) {
+                    match tokens.get(index + 2) {
+                        Some(ScanToken::Ident(member)) if member == "write" => found.push("document.write("),
+                        Some(ScanToken::Ident(member)) if member == "cookie" => found.push("document.cookie"),
+                        _ => {}
+                    }
+                }
+            }
+            // Computed access with a literal name, e.g. `window['eval']`
+            ScanToken::Str(name) if previous == Some(&ScanToken::Punct('['))
+                && tokens.get(index + 1) == Some(&ScanToken::Punct(']')) =>
+            {
+                if let Some((_, label)) = DANGEROUS_CALLS.iter().find(|(call, _)| *call == name.as_str()) {
+                    found.push(*label);
+                } else if let Some(known) = DANGEROUS_GLOBALS.iter().chain(&DANGEROUS_MEMBERS).find(|known| **known == name.as_str()) {
+                    found.push(*known);
+                } else if name == "write" || name == "cookie" {
+                    if matches!(index.checked_sub(2).map(|i| &tokens[i]), Some(ScanToken::Ident(object)) if object == "document") {
+                        found.push(if name == "write" { "document.write(" } else { "document.cookie" });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+/// Tokenize JavaScript source, skipping whitespace and comments. Template literal
+/// substitutions are scanned as code. With `in_substitution`, stops after the `}` closing it.
+fn scan_tokens(chars: &[char], pos: &mut usize, in_substitution: bool, tokens: &mut Vec<ScanToken>) {
+    let mut depth = 0usize;
+    while *pos < chars.len() {
+        let c = chars[*pos];
+        let next = chars.get(*pos + 1).copied();
+        match c {
+            _ if c.is_whitespace() => *pos += 1,
+            '/' if next == Some('/') => {
+                while *pos < chars.len() && chars[*pos] != '\n' {
+                    *pos += 1;
+                }
+            }
+            '/' if next == Some('*') => {
+                *pos += 2;
+                while *pos < chars.len() && !(chars[*pos] == '*' && chars.get(*pos + 1) == Some(&'/')) {
+                    *pos += 1;
+                }
+                *pos += 2;
+            }
+            // A slash starts a regex where an operand is expected
+            '/' if regex_allowed(tokens.last()) => {
+                *pos += 1;
+                let mut in_class = false;
+                while *pos < chars.len() {
+                    match chars[*pos] {
+                        '\\' => *pos += 1,
+                        '[' => in_class = true,
+                        ']' => in_class = false,
+                        '/' if !in_class => break,
+                        '\n' => break,
+                        _ => {}
+                    }
+                    *pos += 1;
+                }
+                *pos += 1;
+                while *pos < chars.len() && chars[*pos].is_alphanumeric() {
+                    *pos += 1;
+                }
+                tokens.push(ScanToken::Literal);
+            }
+            '\'' | '"' => {
+                *pos += 1;
+                let mut value = String::new();
+                while *pos < chars.len() && chars[*pos] != c && chars[*pos] != '\n' {
+                    if chars[*pos] == '\\' {
+                        *pos += 1;
+                    }
+                    if let Some(&ch) = chars.get(*pos) {
+                        value.push(ch);
+                    }
+                    *pos += 1;
+                }
+                *pos += 1;
+                tokens.push(ScanToken::Str(value));
+            }
+            '`' => {
+                *pos += 1;
+                let mut value = String::new();
+                while *pos < chars.len() && chars[*pos] != '`' {
+                    if chars[*pos] == '\\' {
+                        *pos += 1;
+                    } else if chars[*pos] == '$' && chars.get(*pos + 1) == Some(&'{') {
+                        *pos += 2;
+                        scan_tokens(chars, pos, true, tokens);
+                        continue;
+                    }
+                    if let Some(&ch) = chars.get(*pos) {
+                        value.push(ch);
+                    }
+                    *pos += 1;
+                }
+                *pos += 1;
+                tokens.push(ScanToken::Str(value));
+            }
+            _ if c.is_ascii_digit() => {
+                while *pos < chars.len() && (chars[*pos].is_alphanumeric() || chars[*pos] == '.' || chars[*pos] == '_') {
+                    *pos += 1;
+                }
+                tokens.push(ScanToken::Literal);
+            }
+            _ if c.is_alphabetic() || c == '_' || c == '$' => {
+                let start = *pos;
+                while *pos < chars.len() && (chars[*pos].is_alphanumeric() || chars[*pos] == '_' || chars[*pos] == '$') {
+                    *pos += 1;
+                }
+                tokens.push(ScanToken::Ident(chars[start..*pos].iter().collect()));
+            }
+            _ => {
+                *pos += 1;
+                match c {
+                    '{' => depth += 1,
+                    '}' if depth == 0 && in_substitution => return,
+                    '}' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                tokens.push(ScanToken::Punct(c));
+            }
+        }
+    }
+}
+
+/// Whether a `/` after this token begins a regular expression rather than a division
+fn regex_allowed(previous: Option<&ScanToken>) -> bool {
+    match previous {
+        None => true,
+        Some(ScanToken::Punct(c)) => !matches!(c, ')' | ']' | '}'),
+        Some(ScanToken::Ident(word)) => matches!(word.as_str(), "return" | "typeof" | "case" | "do" | "else" | "in" | "of" | "new" | "delete" | "void" | "throw"),
+        Some(ScanToken::Str(_)) | Some(ScanToken::Literal) => false,
+    }
+}
+
+/// Terminates the isolate's running script if it outlives its time budget
+struct Watchdog {
+    done: mpsc::Sender<()>,
+    thread: std::thread::JoinHandle<bool>,
+}
+
+impl Watchdog {
+    fn start(isolate: v8::IsolateHandle, timeout: Duration) -> Self {
+        let (done, finished) = mpsc::channel();
+        let thread = std::thread::spawn(move || match finished.recv_timeout(timeout) {
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                isolate.terminate_execution();
+                true
+            }
+            _ => false,
+        });
+        Self { done, thread }
+    }
+    
+    /// Stop the timer, returning whether it fired
+    fn finish(self) -> bool {
+        let _ = self.done.send(());
+        self.thread.join().unwrap_or(false)
+    }
+}
+
+/// Shared with the near-heap-limit callback through its data pointer
+struct HeapLimitState {
+    isolate: v8::IsolateHandle,
+    exceeded: AtomicBool,
+    /// Configured heap limit, restored once the terminated script has unwound
+    initial_limit: AtomicUsize,
+}
+
+/// Called by V8 just before the heap limit is reached. Terminates the running script and
+/// grants extra headroom so V8 can unwind instead of aborting the process; the limit is
+/// restored by `take_heap_limit_error`.
+extern "C" fn near_heap_limit_callback(data: *mut c_void, current_heap_limit: usize, initial_heap_limit: usize) -> usize {
+    // Safety: `data` points at the `HeapLimitState` kept alive in an isolate slot
+    let state = unsafe { &*(data as *const HeapLimitState) };
+    state.initial_limit.store(initial_heap_limit, Ordering::SeqCst);
+    state.exceeded.store(true, Ordering::SeqCst);
+    state.isolate.terminate_execution();
+    current_heap_limit.saturating_mul(2)
+}
+
+/// What `fetch` needs from the engine, kept in an isolate slot for the binding
+#[derive(Default)]
+struct FetchState {
+    network: Option<Arc<NetworkStack>>,
+    pending: Vec<PendingFetch>,
+}
+
+/// A request running on tokio whose promise is settled by `run_pending_fetches`
+struct PendingFetch {
+    resolver: v8::Global<v8::PromiseResolver>,
+    request: tokio::task::JoinHandle<Result<NetworkResponse>>,
+    /// Whether the request carried cookies, which makes CORS stricter
+    credentials: bool,
+}
+
+/// `init.credentials` of a `fetch` call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum CredentialsMode {
+    Omit,
+    #[default]
+    SameOrigin,
+    Include,
+}
+
+impl CredentialsMode {
+    fn from_init(scope: &mut v8::HandleScope, init: v8::Local<v8::Value>) -> Result<Self> {
+        let key = v8::String::new(scope, "credentials").unwrap();
+        let value = if init.is_object() {
+            init.to_object(scope).and_then(|init| init.get(scope, key.into()))
+        } else {
+            None
+        };
+        let value = value.filter(|value| !value.is_undefined());
+        let Some(value) = value else {
+            return Ok(Self::default());
+        };
+        match value.to_rust_string_lossy(scope).as_str() {
+            "omit" => Ok(Self::Omit),
+            "same-origin" => Ok(Self::SameOrigin),
+            "include" => Ok(Self::Include),
+            other => Err(EngineError::SecurityError(format!("TypeError: invalid credentials mode {}", other))),
+        }
+    }
+    
+    /// Whether a request to `url` from a page on `origin` carries cookies
+    fn sends_credentials(self, origin: &str, url: &Url) -> bool {
+        match self {
+            Self::Omit => false,
+            Self::SameOrigin => url.origin().ascii_serialization() == origin,
+            Self::Include => true,
+        }
+    }
+}
+
+/// `fetch(url, init)`: GET only, honoring `init.credentials`; the returned promise settles
+/// once the host runs `run_pending_fetches`
+fn fetch_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let resolver = v8::PromiseResolver::new(scope).unwrap();
+    rv.set(resolver.get_promise(scope).into());
+    
+    let input = args.get(0).to_rust_string_lossy(scope);
+    let started = CredentialsMode::from_init(scope, args.get(1))
+        .and_then(|mode| start_fetch(scope, &input, mode));
+    match started {
+        Ok((request, credentials)) => {
+            let resolver = v8::Global::new(scope, resolver);
+            if let Some(state) = scope.get_slot_mut::<FetchState>() {
+                state.pending.push(PendingFetch { resolver, request, credentials });
+            }
+        }
+        Err(e) => reject_promise(scope, resolver, e),
+    }
+}
+
+/// Check permissions, resolve `input` against the page origin and spawn the request.
+/// Also returns whether the request carries cookies.
+fn start_fetch(
+    scope: &mut v8::HandleScope,
+    input: &str,
+    mode: CredentialsMode,
+) -> Result<(tokio::task::JoinHandle<Result<NetworkResponse>>, bool)> {
+    let allowed = scope.get_slot::<Arc<RwLock<SecuritySettings>>>()
+        .is_some_and(|settings| settings.read().unwrap_or_else(PoisonError::into_inner).allow_network_access);
+    let state = scope.get_slot::<FetchState>()
+        .ok_or_else(|| EngineError::InternalError("Fetch state missing".to_string()))?;
+    if !allowed {
+        return Err(EngineError::SecurityError("TypeError: network access is not allowed".to_string()));
+    }
+    let network = state.network.clone()
+        .ok_or_else(|| EngineError::network(NetworkErrorKind::Other, None, "TypeError: no network stack available"))?;
+    
+    let origin = scope.get_slot::<BrowsingContextState>()
+        .map(|state| state.origin.clone())
+        .unwrap_or_default();
+    let url = Url::parse(input)
+        .or_else(|_| Url::parse(&origin).and_then(|base| base.join(input)))
+        .map_err(|_| EngineError::network(NetworkErrorKind::InvalidUrl, Some(input), format!("TypeError: invalid URL {}", input)))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(EngineError::network(NetworkErrorKind::InvalidUrl, Some(url.as_str()), format!("TypeError: unsupported scheme {}", url.scheme())));
+    }
+    check_connect_src(scope, &url)?;
+    
+    let runtime = tokio::runtime::Handle::try_current()
+        .map_err(|_| EngineError::InternalError("fetch requires a tokio runtime".to_string()))?;
+    // Requests from the page only carry cross-site cookies that opted in with SameSite=None
+    let credentials = mode.sends_credentials(&origin, &url);
+    let options = RequestOptions {
+        site_for_cookies: (!origin.is_empty()).then_some(origin),
+        credentials,
+        ..RequestOptions::default()
+    };
+    let request = runtime.spawn(async move { network.fetch_with_options(url.as_str(), options).await });
+    Ok((request, credentials))
+}
+
+/// CORS-lite: cross-origin responses must opt in with `Access-Control-Allow-Origin`.
+/// A request that carried cookies needs the exact origin, not `*`, plus
+/// `Access-Control-Allow-Credentials: true`.
+fn cors_check(origin: &str, response: NetworkResponse, credentials: bool) -> Result<NetworkResponse> {
+    let same_origin = Url::parse(&response.url)
+        .is_ok_and(|url| url.origin().ascii_serialization() == origin);
+    let header = |wanted: &str| response.headers.iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(wanted))
+        .map(|(_, value)| value.trim());
+    let allowed = same_origin || match header("access-control-allow-origin") {
+        Some(allowed_origin) if credentials => {
+            allowed_origin == origin && header("access-control-allow-credentials") == Some("true")
+        }
+        Some(allowed_origin) => allowed_origin == "*" || allowed_origin == origin,
+        None => false,
+    };
+    
+    if allowed {
+        Ok(response)
+    } else {
+        Err(EngineError::SecurityError(format!(
+            "TypeError: cross-origin response from {} blocked by CORS policy",
+            response.url
+        )))
+    }
+}
+
+/// Response-like object with `status`, `ok`, `url`, `text()` and `json()`
+fn response_object<'s>(scope: &mut v8::HandleScope<'s>, response: &NetworkResponse) -> v8::Local<'s, v8::Object> {
+    let response_obj = v8::Object::new(scope);
+    
+    let status_name = v8::String::new(scope, "status").unwrap();
+    let status = v8::Integer::new(scope, response.status as i32);
+    response_obj.set(scope, status_name.into(), status.into());
+    
+    let ok_name = v8::String::new(scope, "ok").unwrap();
+    let ok = v8::Boolean::new(scope, (200..300).contains(&response.status));
+    response_obj.set(scope, ok_name.into(), ok.into());
+    
+    let url_name = v8::String::new(scope, "url").unwrap();
+    let url = v8::String::new(scope, &response.url).unwrap();
+    response_obj.set(scope, url_name.into(), url.into());
+    
+    let body = v8::String::new(scope, &response.body).unwrap();
+    let text_fn = v8::Function::builder(response_text)
+        .data(body.into())
+        .build(scope)
+        .unwrap();
+    let text_name = v8::String::new(scope, "text").unwrap();
+    response_obj.set(scope, text_name.into(), text_fn.into());
+    
+    let json_fn = v8::Function::builder(response_json)
+        .data(body.into())
+        .build(scope)
+        .unwrap();
+    let json_name = v8::String::new(scope, "json").unwrap();
+    response_obj.set(scope, json_name.into(), json_fn.into());
+    
+    response_obj
+}
+
+/// `response.text()`: the body, which was read in full before the fetch resolved
+fn response_text(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let resolver = v8::PromiseResolver::new(scope).unwrap();
+    resolver.resolve(scope, args.data());
+    rv.set(resolver.get_promise(scope).into());
+}
+
+/// `response.json()`: the body parsed as JSON, rejecting on a syntax error
+fn response_json(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let resolver = v8::PromiseResolver::new(scope).unwrap();
+    let body = v8::Local::<v8::String>::try_from(args.data())
+        .unwrap_or_else(|_| v8::String::empty(scope));
+    
+    let scope = &mut v8::TryCatch::new(scope);
+    match v8::json::parse(scope, body) {
+        Some(value) => {
+            resolver.resolve(scope, value);
+        }
+        None => {
+            let exception = scope.exception().unwrap_or_else(|| v8::undefined(scope).into());
+            resolver.reject(scope, exception);
+        }
+    }
+    rv.set(resolver.get_promise(scope).into());
+}
+
+/// `WebSocket.readyState` values, indexed by their numeric value
+const WEBSOCKET_READY_STATES: [&str; 4] = ["CONNECTING", "OPEN", "CLOSING", "CLOSED"];
+
+/// Private property holding a socket's id in `WebSocketState`
+const SOCKET_ID_KEY: &str = "titan::socketId";
+
+/// Private property holding a socket's `addEventListener` registrations, by event type
+const SOCKET_LISTENERS_KEY: &str = "titan::socketListeners";
+
+/// Sockets opened by scripts, kept in an isolate slot; their tasks report back on one channel
+struct WebSocketState {
+    next_id: u32,
+    sockets: HashMap<u32, ScriptSocket>,
+    sender: UnboundedSender<(u32, SocketEvent)>,
+    /// Taken by `run_websocket_events` while it waits
+    events: Option<UnboundedReceiver<(u32, SocketEvent)>>,
+}
+
+impl Default for WebSocketState {
+    fn default() -> Self {
+        let (sender, events) = unbounded_channel();
+        Self {
+            next_id: 0,
+            sockets: HashMap::new(),
+            sender,
+            events: Some(events),
+        }
+    }
+}
+
+/// A script's `WebSocket` object and the channel to the task driving its connection
+struct ScriptSocket {
+    object: v8::Global<v8::Object>,
+    commands: UnboundedSender<SocketCommand>,
+}
+
+/// What a script asked its socket to do
+enum SocketCommand {
+    Send(WebSocketMessage),
+    Close(u16, String),
+}
+
+/// What happened on a connection, dispatched to the script as an event
+enum SocketEvent {
+    Open,
+    Message(WebSocketMessage),
+    Error(String),
+    Closed { code: u16, reason: String, clean: bool },
+}
+
+/// `new WebSocket(url)`: connects in the background; events fire from `run_websocket_events`
+fn websocket_constructor(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    if args.new_target().is_undefined() {
+        let message = v8::String::new(scope, "WebSocket constructor requires 'new'").unwrap();
+        let exception = v8::Exception::type_error(scope, message);
+        scope.throw_exception(exception);
+        return;
+    }
+    
+    let input = args.get(0).to_rust_string_lossy(scope);
+    let (url, network, runtime) = match prepare_websocket(scope, &input) {
+        Ok(target) => target,
+        Err(e) => {
+            let message = v8::String::new(scope, &script_error_message(e)).unwrap();
+            let exception = v8::Exception::error(scope, message);
+            scope.throw_exception(exception);
+            return;
+        }
+    };
+    
+    let socket = args.this();
+    let url_name = v8::String::new(scope, "url").unwrap();
+    let url_value = v8::String::new(scope, url.as_str()).unwrap();
+    socket.set(scope, url_name.into(), url_value.into());
+    set_ready_state(scope, socket, 0);
+    let binary_type_name = v8::String::new(scope, "binaryType").unwrap();
+    let binary_type = v8::String::new(scope, "arraybuffer").unwrap();
+    socket.set(scope, binary_type_name.into(), binary_type.into());
+    for handler in ["onopen", "onmessage", "onerror", "onclose"] {
+        let handler_name = v8::String::new(scope, handler).unwrap();
+        let null_value = v8::null(scope);
+        socket.set(scope, handler_name.into(), null_value.into());
+    }
+    let listeners_key = private_key(scope, SOCKET_LISTENERS_KEY);
+    let listeners = v8::Object::new(scope);
+    socket.set_private(scope, listeners_key, listeners.into());
+    
+    let send_fn = v8::Function::new(scope, websocket_send).unwrap();
+    let send_name = v8::String::new(scope, "send").unwrap();
+    socket.set(scope, send_name.into(), send_fn.into());
+    let close_fn = v8::Function::new(scope, websocket_close).unwrap();
+    let close_name = v8::String::new(scope, "close").unwrap();
+    socket.set(scope, close_name.into(), close_fn.into());
+    let add_event_listener_fn = v8::Function::new(scope, websocket_add_event_listener).unwrap();
+    let add_event_listener_name = v8::String::new(scope, "addEventListener").unwrap();
+    socket.set(scope, add_event_listener_name.into(), add_event_listener_fn.into());
+    
+    let (commands, receiver) = unbounded_channel();
+    let object = v8::Global::new(scope, socket);
+    let Some(state) = scope.get_slot_mut::<WebSocketState>() else {
+        return;
+    };
+    let id = state.next_id;
+    state.next_id += 1;
+    state.sockets.insert(id, ScriptSocket { object, commands });
+    let events = state.sender.clone();
+    
+    let id_key = private_key(scope, SOCKET_ID_KEY);
+    let id_value = v8::Integer::new_from_unsigned(scope, id);
+    socket.set_private(scope, id_key, id_value.into());
+    runtime.spawn(run_script_socket(network, url.to_string(), id, receiver, events));
+}
+
+/// Check permissions and CSP, and resolve `input` to a ws(s) URL against the page origin
+fn prepare_websocket(scope: &mut v8::HandleScope, input: &str) -> Result<(Url, Arc<NetworkStack>, tokio::runtime::Handle)> {
+    let allowed = scope.get_slot::<Arc<RwLock<SecuritySettings>>>()
+        .is_some_and(|settings| settings.read().unwrap_or_else(PoisonError::into_inner).allow_network_access);
+    if !allowed {
+        return Err(EngineError::SecurityError("SecurityError: network access is not allowed".to_string()));
+    }
+    let network = scope.get_slot::<FetchState>()
+        .and_then(|state| state.network.clone())
+        .ok_or_else(|| EngineError::network(NetworkErrorKind::Other, None, "TypeError: no network stack available"))?;
+    
+    let origin = scope.get_slot::<BrowsingContextState>()
+        .map(|state| state.origin.clone())
+        .unwrap_or_default();
+    let mut url = Url::parse(input)
+        .or_else(|_| Url::parse(&origin).and_then(|base| base.join(input)))
+        .map_err(|_| EngineError::network(NetworkErrorKind::InvalidUrl, Some(input), format!("SyntaxError: invalid URL {}", input)))?;
+    let scheme = match url.scheme() {
+        "http" | "ws" => "ws",
+        "https" | "wss" => "wss",
+        other => return Err(EngineError::network(NetworkErrorKind::InvalidUrl, Some(input), format!("SyntaxError: unsupported scheme {}", other))),
+    };
+    if url.scheme() != scheme && url.set_scheme(scheme).is_err() {
+        return Err(EngineError::network(NetworkErrorKind::InvalidUrl, Some(input), format!("SyntaxError: invalid URL {}", input)));
+    }
+    if url.fragment().is_some() {
+        return Err(EngineError::network(NetworkErrorKind::InvalidUrl, Some(input), "SyntaxError: WebSocket URLs cannot have a fragment"));
+    }
+    check_connect_src(scope, &url)?;
+    
+    let runtime = tokio::runtime::Handle::try_current()
+        .map_err(|_| EngineError::InternalError("WebSocket requires a tokio runtime".to_string()))?;
+    Ok((url, network, runtime))
+}
+
+/// `socket.send(data)`: strings go as text frames, buffers and typed arrays as binary
+fn websocket_send(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let socket = args.this();
+    if ready_state(scope, socket) == 0 {
+        let message = v8::String::new(scope, "InvalidStateError: WebSocket is still connecting").unwrap();
+        let exception = v8::Exception::error(scope, message);
+        scope.throw_exception(exception);
+        return;
+    }
+    
+    let data = args.get(0);
+    let message = if let Ok(view) = v8::Local::<v8::ArrayBufferView>::try_from(data) {
+        let mut bytes = vec![0; view.byte_length()];
+        view.copy_contents(&mut bytes);
+        WebSocketMessage::Binary(bytes)
+    } else if let Ok(buffer) = v8::Local::<v8::ArrayBuffer>::try_from(data) {
+        let view = v8::Uint8Array::new(scope, buffer, 0, buffer.byte_length()).unwrap();
+        let mut bytes = vec![0; view.byte_length()];
+        view.copy_contents(&mut bytes);
+        WebSocketMessage::Binary(bytes)
+    } else {
+        WebSocketMessage::Text(data.to_rust_string_lossy(scope))
+    };
+    // Messages sent while closing are dropped, as in browsers
+    if let Some(socket) = socket_entry(scope, socket) {
+        let _ = socket.commands.send(SocketCommand::Send(message));
+    }
+}
+
+/// `socket.close(code, reason)`: starts the closing handshake
+fn websocket_close(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let socket = args.this();
+    if ready_state(scope, socket) >= 2 {
+        return;
+    }
+    let code = match args.get(0) {
+        code if code.is_undefined() => 1000,
+        code => code.uint32_value(scope).unwrap_or(0),
+    };
+    if code != 1000 && !(3000..=4999).contains(&code) {
+        let message = v8::String::new(scope, "InvalidAccessError: close code must be 1000 or 3000-4999").unwrap();
+        let exception = v8::Exception::error(scope, message);
+        scope.throw_exception(exception);
+        return;
+    }
+    let reason = match args.get(1) {
+        reason if reason.is_undefined() => String::new(),
+        reason => reason.to_rust_string_lossy(scope),
+    };
+    
+    set_ready_state(scope, socket, 2);
+    if let Some(socket) = socket_entry(scope, socket) {
+        let _ = socket.commands.send(SocketCommand::Close(code as u16, reason));
+    }
+}
+
+/// `socket.addEventListener(type, listener)`
+fn websocket_add_event_listener(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let event_type = args.get(0).to_rust_string_lossy(scope);
+    let listener = args.get(1);
+    if !listener.is_function() {
+        return;
+    }
+    
+    let listeners_key = private_key(scope, SOCKET_LISTENERS_KEY);
+    let Some(listeners) = args.this().get_private(scope, listeners_key)
+        .and_then(|value| v8::Local::<v8::Object>::try_from(value).ok())
+    else {
+        return;
+    };
+    let type_key = v8::String::new(scope, &event_type).unwrap();
+    let registered = match listeners.get(scope, type_key.into())
+        .and_then(|value| v8::Local::<v8::Array>::try_from(value).ok())
+    {
+        Some(registered) => registered,
+        None => {
+            let registered = v8::Array::new(scope, 0);
+            listeners.set(scope, type_key.into(), registered.into());
+            registered
+        }
+    };
+    registered.set_index(scope, registered.length(), listener);
+}
+
+/// The registry entry behind a `WebSocket` object, while its connection is live
+fn socket_entry<'a>(scope: &'a mut v8::HandleScope, socket: v8::Local<v8::Object>) -> Option<&'a ScriptSocket> {
+    let id_key = private_key(scope, SOCKET_ID_KEY);
+    let id = socket.get_private(scope, id_key)?.uint32_value(scope)?;
+    scope.get_slot::<WebSocketState>()?.sockets.get(&id)
+}
+
+fn ready_state(scope: &mut v8::HandleScope, socket: v8::Local<v8::Object>) -> u32 {
+    let ready_state_name = v8::String::new(scope, "readyState").unwrap();
+    socket.get(scope, ready_state_name.into())
+        .and_then(|value| value.uint32_value(scope))
+        .unwrap_or(3)
+}
+
+fn set_ready_state(scope: &mut v8::HandleScope, socket: v8::Local<v8::Object>, state: u32) {
+    let ready_state_name = v8::String::new(scope, "readyState").unwrap();
+    let value = v8::Integer::new_from_unsigned(scope, state);
+    socket.set(scope, ready_state_name.into(), value.into());
+}
+
+/// Drive one script socket: connect, then relay commands out and messages in until it closes
+async fn run_script_socket(
+    network: Arc<NetworkStack>,
+    url: String,
+    id: u32,
+    mut commands: UnboundedReceiver<SocketCommand>,
+    events: UnboundedSender<(u32, SocketEvent)>,
+) {
+    let mut connection = match network.connect_websocket(&url).await {
+        Ok(connection) => connection,
+        Err(e) => {
+            let _ = events.send((id, SocketEvent::Error(e.to_string())));
+            let _ = events.send((id, SocketEvent::Closed { code: 1006, reason: String::new(), clean: false }));
+            return;
+        }
+    };
+    let _ = events.send((id, SocketEvent::Open));
+    
+    let closed = loop {
+        tokio::select! {
+            command = commands.recv() => {
+                let outgoing = match command {
+                    Some(SocketCommand::Send(message)) => message,
+                    Some(SocketCommand::Close(code, reason)) => WebSocketMessage::Close(Some(CloseFrame {
+                        code: CloseCode::from(code),
+                        reason: reason.into(),
+                    })),
+                    // The runtime went away; nobody is left to tell
+                    None => {
+                        let _ = connection.send(WebSocketMessage::Close(None)).await;
+                        return;
+                    }
+                };
+                if let Err(e) = connection.send(outgoing).await {
+                    let _ = events.send((id, SocketEvent::Error(e.to_string())));
+                    break SocketEvent::Closed { code: 1006, reason: String::new(), clean: false };
+                }
+            }
+            message = async { connection.incoming().next().await } => match message {
+                Some(Ok(WebSocketMessage::Close(frame))) => {
+                    let (code, reason) = frame.map_or((1005, String::new()), |frame| (frame.code.into(), frame.reason.into_owned()));
+                    break SocketEvent::Closed { code, reason, clean: true };
+                }
+                Some(Ok(message @ (WebSocketMessage::Text(_) | WebSocketMessage::Binary(_)))) => {
+                    let _ = events.send((id, SocketEvent::Message(message)));
+                }
+                // Pings and pongs are answered by the client itself
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    let _ = events.send((id, SocketEvent::Error(e.to_string())));
+                    break SocketEvent::Closed { code: 1006, reason: String::new(), clean: false };
+                }
+                None => break SocketEvent::Closed { code: 1006, reason: String::new(), clean: false },
+            },
+        }
+    };
+    let _ = events.send((id, closed));
+}
+
+/// Fire a socket event at `on<type>` and the registered listeners, updating `readyState` first
+fn dispatch_socket_event(scope: &mut v8::HandleScope, id: u32, event: SocketEvent) {
+    let Some(object) = scope.get_slot::<WebSocketState>()
+        .and_then(|state| state.sockets.get(&id))
+        .map(|socket| socket.object.clone())
+    else {
+        return;
+    };
+    let socket = v8::Local::new(scope, object);
+    
+    let event_obj = v8::Object::new(scope);
+    let event_type = match event {
+        SocketEvent::Open => {
+            set_ready_state(scope, socket, 1);
+            "open"
+        }
+        SocketEvent::Message(message) => {
+            let data: v8::Local<v8::Value> = match message {
+                WebSocketMessage::Text(text) => v8::String::new(scope, &text).unwrap().into(),
+                WebSocketMessage::Binary(bytes) => {
+                    let store = v8::ArrayBuffer::new_backing_store_from_vec(bytes).make_shared();
+                    v8::ArrayBuffer::with_backing_store(scope, &store).into()
+                }
+                _ => return,
+            };
+            let data_name = v8::String::new(scope, "data").unwrap();
+            event_obj.set(scope, data_name.into(), data);
+            "message"
+        }
+        SocketEvent::Error(message) => {
+            log::warn!("WebSocket error: {}", message);
+            "error"
+        }
+        SocketEvent::Closed { code, reason, clean } => {
+            set_ready_state(scope, socket, 3);
+            if let Some(state) = scope.get_slot_mut::<WebSocketState>() {
+                state.sockets.remove(&id);
+            }
+            let code_name = v8::String::new(scope, "code").unwrap();
+            let code_value = v8::Integer::new(scope, code as i32);
+            event_obj.set(scope, code_name.into(), code_value.into());
+            let reason_name = v8::String::new(scope, "reason").unwrap();
+            let reason_value = v8::String::new(scope, &reason).unwrap();
+            event_obj.set(scope, reason_name.into(), reason_value.into());
+            let clean_name = v8::String::new(scope, "wasClean").unwrap();
+            let clean_value = v8::Boolean::new(scope, clean);
+            event_obj.set(scope, clean_name.into(), clean_value.into());
+            "close"
+        }
+    };
+    let type_name = v8::String::new(scope, "type").unwrap();
+    let type_value = v8::String::new(scope, event_type).unwrap();
+    event_obj.set(scope, type_name.into(), type_value.into());
+    let target_name = v8::String::new(scope, "target").unwrap();
+    event_obj.set(scope, target_name.into(), socket.into());
+    
+    let mut handlers = Vec::new();
+    let handler_name = v8::String::new(scope, &format!("on{}", event_type)).unwrap();
+    if let Some(handler) = socket.get(scope, handler_name.into()) {
+        handlers.push(handler);
+    }
+    let listeners_key = private_key(scope, SOCKET_LISTENERS_KEY);
+    if let Some(registered) = socket.get_private(scope, listeners_key)
+        .and_then(|value| v8::Local::<v8::Object>::try_from(value).ok())
+        .and_then(|listeners| listeners.get(scope, type_value.into()))
+        .and_then(|value| v8::Local::<v8::Array>::try_from(value).ok())
+    {
+        for i in 0..registered.length() {
+            if let Some(listener) = registered.get_index(scope, i) {
+                handlers.push(listener);
+            }
+        }
+    }
+    
+    for handler in handlers {
+        if let Ok(handler) = v8::Local::<v8::Function>::try_from(handler) {
+            handler.call(scope, socket.into(), &[event_obj.into()]);
+        }
+    }
+}
+
+/// Refuse a connection the page's CSP `connect-src` doesn't allow
+fn check_connect_src(scope: &mut v8::HandleScope, url: &Url) -> Result<()> {
+    let Some(state) = scope.get_slot::<BrowsingContextState>() else {
+        return Ok(());
+    };
+    match &state.connect_src {
+        Some(sources) if !sources_allow(sources, &state.origin, url) => Err(EngineError::SecurityError(format!(
+            "SecurityError: connecting to {} violates the Content-Security-Policy directive connect-src",
+            url
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Sources of a policy's `connect-src`, falling back to `default-src`; `None` when neither is set
+fn connect_sources(policy: &str) -> Option<Vec<String>> {
+    let directives: Vec<(String, Vec<String>)> = policy.split(';')
+        .filter_map(|directive| {
+            let mut tokens = directive.split_whitespace();
+            let name = tokens.next()?.to_ascii_lowercase();
+            Some((name, tokens.map(str::to_ascii_lowercase).collect()))
+        })
+        .collect();
+    ["connect-src", "default-src"].iter().find_map(|wanted| {
+        directives.iter().find(|(name, _)| name == wanted).map(|(_, sources)| sources.clone())
+    })
+}
+
+/// Whether any source in a CSP source list matches `url` for a page at `origin`
+fn sources_allow(sources: &[String], origin: &str, url: &Url) -> bool {
+    let page = Url::parse(origin).ok();
+    sources.iter().any(|source| match source.as_str() {
+        "'none'" => false,
+        "*" => matches!(url.scheme(), "http" | "https" | "ws" | "wss"),
+        "'self'" => page.as_ref().is_some_and(|page| {
+            page_scheme_covers(page, url.scheme())
+                && page.host_str() == url.host_str()
+                && page.port_or_known_default() == url.port_or_known_default()
+        }),
+        scheme if scheme.ends_with(':') => scheme_covers(&scheme[..scheme.len() - 1], url.scheme()),
+        host_source => host_source_matches(host_source, page.as_ref(), url),
+    })
+}
+
+/// Scheme matching as in CSP: a source scheme also allows its secure upgrade
+fn scheme_covers(source: &str, scheme: &str) -> bool {
+    match source {
+        "http" => matches!(scheme, "http" | "https"),
+        "ws" => matches!(scheme, "ws" | "wss"),
+        other => other == scheme,
+    }
+}
+
+/// Schemes a scheme-less source allows: the page's own, plus the WebSocket equivalent
+fn page_scheme_covers(page: &Url, scheme: &str) -> bool {
+    let websocket = match page.scheme() {
+        "http" => "ws",
+        "https" => "wss",
+        other => other,
+    };
+    scheme_covers(page.scheme(), scheme) || scheme_covers(websocket, scheme)
+}
+
+/// Match a `[scheme://]host[:port][/path]` source; paths are not checked
+fn host_source_matches(source: &str, page: Option<&Url>, url: &Url) -> bool {
+    let (scheme, rest) = match source.split_once("://") {
+        Some((scheme, rest)) => (Some(scheme), rest),
+        None => (None, source),
+    };
+    let scheme_ok = match (scheme, page) {
+        (Some(scheme), _) => scheme_covers(scheme, url.scheme()),
+        (None, Some(page)) => page_scheme_covers(page, url.scheme()),
+        (None, None) => matches!(url.scheme(), "http" | "https" | "ws" | "wss"),
+    };
+    
+    let authority = rest.split('/').next().unwrap_or_default();
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, Some(port)),
+        None => (authority, None),
+    };
+    let Some(url_host) = url.host_str() else {
+        return false;
+    };
+    let host_ok = match host.strip_prefix("*.") {
+        Some(suffix) => url_host.to_ascii_lowercase().ends_with(&format!(".{}", suffix)),
+        None => url_host.eq_ignore_ascii_case(host),
+    };
+    let port_ok = match port {
+        Some("*") => true,
+        Some(port) => port.parse::<u16>().ok() == url.port_or_known_default(),
+        None => url.port().is_none(),
+    };
+    scheme_ok && host_ok && port_ok
+}
+
+/// Host callback for console output, kept in an isolate slot so the console bindings can reach it
+#[derive(Default)]
+struct ConsoleSink {
+    callback: Option<Arc<dyn Fn(ConsoleLevel, String, String, u32) + Send + Sync>>,
+}
+
+/// Console methods in the order their level index is passed as function data
+const CONSOLE_METHODS: [(&str, ConsoleLevel); 5] = [
+    ("log", ConsoleLevel::Log),
+    ("info", ConsoleLevel::Info),
+    ("warn", ConsoleLevel::Warn),
+    ("error", ConsoleLevel::Error),
+    ("debug", ConsoleLevel::Debug),
+];
+
+/// Shared body of the console methods: format every argument and hand the message to the host
+fn console_message(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let level = args.data().int32_value(scope)
+        .and_then(|index| CONSOLE_METHODS.get(index as usize))
+        .map_or(ConsoleLevel::Log, |(_, level)| level.clone());
+    
+    let message = (0..args.length())
+        .map(|index| format_console_arg(scope, args.get(index)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    
+    // Caller's script and line from the top stack frame
+    let (source, line) = v8::StackTrace::current_stack_trace(scope, 1)
+        .and_then(|trace| trace.get_frame(scope, 0))
+        .map(|frame| {
+            let source = frame.get_script_name(scope)
+                .map(|name| name.to_rust_string_lossy(scope))
+                .unwrap_or_default();
+            (source, frame.get_line_number() as u32)
+        })
+        .unwrap_or_default();
+    
+    let callback = scope.get_slot::<ConsoleSink>().and_then(|sink| sink.callback.clone());
+    match callback {
+        Some(callback) => callback(level, message, source, line),
+        None => match level {
+            ConsoleLevel::Warn | ConsoleLevel::Error => eprintln!("[JS {:?}] {}", level, message),
+            _ => println!("[JS Console] {}", message),
+        },
+    }
+}
+
+/// Strings print as-is, objects as JSON where possible, everything else via `toString`
+fn format_console_arg(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> String {
+    if value.is_object() && !value.is_function() {
+        if let Some(json) = v8::json::stringify(scope, value) {
+            return json.to_rust_string_lossy(scope);
+        }
+    }
+    value.to_rust_string_lossy(scope)
+}
+
+/// Check user activation and the origin's permission before handing out the clipboard
+fn clipboard_access(scope: &mut v8::HandleScope, kind: PermissionKind) -> Result<Arc<dyn ClipboardProvider>> {
+    let state = scope.get_slot::<BrowsingContextState>()
+        .ok_or_else(|| EngineError::InternalError("Browsing context state missing".to_string()))?;
+    
+    if !state.user_activation {
+        return Err(EngineError::SecurityError("NotAllowedError: clipboard access requires user activation".to_string()));
+    }
+    
+    if !state.permissions.is_granted(&state.origin, kind) {
+        return Err(EngineError::SecurityError("NotAllowedError: clipboard permission denied".to_string()));
+    }
+    
+    Ok(state.clipboard.clone())
+}
+
+/// Check that a powerful feature is enabled and the origin has been granted it
+fn feature_access(scope: &mut v8::HandleScope, kind: PermissionKind) -> Result<()> {
+    let state = scope.get_slot::<BrowsingContextState>()
+        .ok_or_else(|| EngineError::InternalError("Browsing context state missing".to_string()))?;
+    
+    if state.disabled_features.contains(&kind) {
+        return Err(EngineError::SecurityError(format!("NotAllowedError: {} is disabled", kind.as_str())));
+    }
+    if !state.permissions.is_granted(&state.origin, kind) {
+        return Err(EngineError::SecurityError(format!("NotAllowedError: {} permission denied", kind.as_str())));
+    }
+    Ok(())
+}
+
+/// The position for a geolocation request, or a `GeolocationPositionError` code and message
+fn current_position(scope: &mut v8::HandleScope) -> std::result::Result<GeoPosition, (u32, String)> {
+    const PERMISSION_DENIED: u32 = 1;
+    const POSITION_UNAVAILABLE: u32 = 2;
+    
+    if let Err(e) = feature_access(scope, PermissionKind::Geolocation) {
+        let message = match e {
+            EngineError::SecurityError(message) => message,
+            other => other.to_string(),
+        };
+        return Err((PERMISSION_DENIED, message));
+    }
+    scope.get_slot::<BrowsingContextState>()
+        .and_then(|state| state.geolocation)
+        .ok_or((POSITION_UNAVAILABLE, "Position unavailable".to_string()))
+}
+
+/// `GeolocationPosition`-shaped object
+fn position_object<'s>(scope: &mut v8::HandleScope<'s>, position: GeoPosition) -> v8::Local<'s, v8::Object> {
+    let coords = v8::Object::new(scope);
+    for (name, value) in [("latitude", position.latitude), ("longitude", position.longitude), ("accuracy", position.accuracy)] {
+        let key = v8::String::new(scope, name).unwrap();
+        let value = v8::Number::new(scope, value);
+        coords.set(scope, key.into(), value.into());
+    }
+    
+    let position_obj = v8::Object::new(scope);
+    let coords_name = v8::String::new(scope, "coords").unwrap();
+    position_obj.set(scope, coords_name.into(), coords.into());
+    let timestamp_name = v8::String::new(scope, "timestamp").unwrap();
+    let timestamp = v8::Number::new(scope, chrono::Utc::now().timestamp_millis() as f64);
+    position_obj.set(scope, timestamp_name.into(), timestamp.into());
+    position_obj
+}
+
+/// `GeolocationPositionError`-shaped object
+fn position_error<'s>(scope: &mut v8::HandleScope<'s>, code: u32, message: &str) -> v8::Local<'s, v8::Object> {
+    let error_obj = v8::Object::new(scope);
+    let code_name = v8::String::new(scope, "code").unwrap();
+    let code = v8::Integer::new_from_unsigned(scope, code);
+    error_obj.set(scope, code_name.into(), code.into());
+    let message_name = v8::String::new(scope, "message").unwrap();
+    let message = v8::String::new(scope, message).unwrap();
+    error_obj.set(scope, message_name.into(), message.into());
+    error_obj
+}
+
+/// Private property holding a stub media stream's tracks
+const MEDIA_TRACKS_KEY: &str = "titan::mediaTracks";
+
+/// Stub `MediaStream` with one live track per requested kind ("audio", "video")
+fn media_stream_object<'s>(scope: &mut v8::HandleScope<'s>, kinds: &[&str]) -> v8::Local<'s, v8::Object> {
+    let tracks: Vec<v8::Local<v8::Value>> = kinds.iter()
+        .map(|kind| {
+            let track = v8::Object::new(scope);
+            for (name, value) in [("kind", *kind), ("readyState", "live")] {
+                let key = v8::String::new(scope, name).unwrap();
+                let value = v8::String::new(scope, value).unwrap();
+                track.set(scope, key.into(), value.into());
+            }
+            track.into()
+        })
+        .collect();
+    let tracks = v8::Array::new_with_elements(scope, &tracks);
+    
+    let stream = v8::Object::new(scope);
+    let key = private_key(scope, MEDIA_TRACKS_KEY);
+    stream.set_private(scope, key, tracks.into());
+    
+    let id_name = v8::String::new(scope, "id").unwrap();
+    let id = v8::String::new(scope, &uuid::Uuid::new_v4().to_string()).unwrap();
+    stream.set(scope, id_name.into(), id.into());
+    let active_name = v8::String::new(scope, "active").unwrap();
+    let active = v8::Boolean::new(scope, true);
+    stream.set(scope, active_name.into(), active.into());
+    
+    let get_tracks_fn = v8::Function::new(
+        scope,
+        |scope: &mut v8::HandleScope,
+         args: v8::FunctionCallbackArguments,
+         mut rv: v8::ReturnValue| {
+            let key = private_key(scope, MEDIA_TRACKS_KEY);
+            if let Some(tracks) = args.this().get_private(scope, key) {
+                rv.set(tracks);
+            }
+        },
+    ).unwrap();
+    let get_tracks_name = v8::String::new(scope, "getTracks").unwrap();
+    stream.set(scope, get_tracks_name.into(), get_tracks_fn.into());
+    
+    stream
+}
+
+/// `Notification.permission` for the current origin
+fn notification_permission(scope: &mut v8::HandleScope) -> &'static str {
+    let Some(state) = scope.get_slot::<BrowsingContextState>() else {
+        return "denied";
+    };
+    if state.disabled_features.contains(&PermissionKind::Notifications) {
+        return "denied";
+    }
+    match state.permissions.query(&state.origin, PermissionKind::Notifications) {
+        PermissionState::Granted => "granted",
+        PermissionState::Denied => "denied",
+        PermissionState::Prompt => "default",
+    }
+}
+
+/// Build a promise already resolved with the value (or undefined) or rejected with the error
+fn settled_promise<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    outcome: Result<Option<String>>,
+) -> v8::Local<'s, v8::Promise> {
+    let resolver = v8::PromiseResolver::new(scope).unwrap();
+    
+    match outcome {
+        Ok(value) => {
+            let value: v8::Local<v8::Value> = match value {
+                Some(text) => v8::String::new(scope, &text).unwrap().into(),
+                None => v8::undefined(scope).into(),
+            };
+            resolver.resolve(scope, value);
+        }
+        Err(e) => reject_promise(scope, resolver, e),
+    }
+    
+    resolver.get_promise(scope)
+}
+
+/// Reject a promise with an `Error` carrying the engine error's message
+fn reject_promise(scope: &mut v8::HandleScope, resolver: v8::Local<v8::PromiseResolver>, error: EngineError) {
+    let message = v8::String::new(scope, &script_error_message(error)).unwrap();
+    let exception = v8::Exception::error(scope, message);
+    resolver.reject(scope, exception);
+}
+
+/// Message for an engine error surfaced to script; security errors already read like DOM errors
+fn script_error_message(error: EngineError) -> String {
+    match error {
+        EngineError::SecurityError(message) => message,
+        other => other.to_string(),
+    }
+}
+
+/// Private property holding the engine `ElementId` behind an element wrapper
+const ELEMENT_ID_KEY: &str = "titan::elementId";
+
+/// Document that DOM bindings read and mutate, kept in an isolate slot
+#[derive(Default)]
+struct DomState {
+    document: Option<Document>,
+    /// `addEventListener` listeners by element and event type, in registration order
+    listeners: HashMap<(ElementId, String), Vec<EventListener>>,
+}
+
+/// A listener registered with `addEventListener`
+struct EventListener {
+    callback: v8::Global<v8::Function>,
+    /// Runs during the capturing phase instead of the bubbling phase
+    capture: bool,
+}
+
+/// `Event.eventPhase` values
+const EVENT_PHASE_CAPTURING: i32 = 1;
+const EVENT_PHASE_AT_TARGET: i32 = 2;
+const EVENT_PHASE_BUBBLING: i32 = 3;
+
+/// The `capture` flag from `addEventListener`'s third argument: a boolean or an options object
+fn listener_capture(scope: &mut v8::HandleScope, options: v8::Local<v8::Value>) -> bool {
+    match v8::Local::<v8::Object>::try_from(options) {
+        Ok(options) => {
+            let capture_name = v8::String::new(scope, "capture").unwrap();
+            options.get(scope, capture_name.into()).is_some_and(|capture| capture.boolean_value(scope))
+        }
+        Err(_) => options.boolean_value(scope),
+    }
+}
+
+/// An event dispatched to the DOM, such as a click or key press
+#[derive(Debug, Clone, Default)]
+pub struct DomEvent {
+    /// DOM event type, e.g. `click` or `keydown`
+    pub event_type: String,
+    /// Pointer position in viewport coordinates, for pointer events
+    pub client_x: f32,
+    pub client_y: f32,
+    /// Key value, for keyboard events
+    pub key: Option<String>,
+}
+
+/// Script-facing event object with `type`, `target`, pointer or key fields, and
+/// `preventDefault()`/`stopPropagation()` recorded as `defaultPrevented`/`cancelBubble`
+fn event_object<'s>(scope: &mut v8::HandleScope<'s>, event: &DomEvent, target: &Element) -> v8::Local<'s, v8::Object> {
+    let event_obj = v8::Object::new(scope);
+    
+    let type_name = v8::String::new(scope, "type").unwrap();
+    let type_value = v8::String::new(scope, &event.event_type).unwrap();
+    event_obj.set(scope, type_name.into(), type_value.into());
+    let target_name = v8::String::new(scope, "target").unwrap();
+    let target_obj = element_object(scope, target);
+    event_obj.set(scope, target_name.into(), target_obj.into());
+    
+    for (name, value) in [("clientX", event.client_x), ("clientY", event.client_y)] {
+        let name = v8::String::new(scope, name).unwrap();
+        let value = v8::Number::new(scope, value as f64);
+        event_obj.set(scope, name.into(), value.into());
+    }
+    if let Some(key) = &event.key {
+        let key_name = v8::String::new(scope, "key").unwrap();
+        let key_value = v8::String::new(scope, key).unwrap();
+        event_obj.set(scope, key_name.into(), key_value.into());
+    }
+    
+    for flag in ["defaultPrevented", "cancelBubble"] {
+        let name = v8::String::new(scope, flag).unwrap();
+        let value = v8::Boolean::new(scope, false);
+        event_obj.set(scope, name.into(), value.into());
+    }
+    
+    let prevent_default_fn = v8::Function::new(
+        scope,
+        |scope: &mut v8::HandleScope,
+         args: v8::FunctionCallbackArguments,
+         _rv: v8::ReturnValue| {
+            let name = v8::String::new(scope, "defaultPrevented").unwrap();
+            let value = v8::Boolean::new(scope, true);
+            args.this().set(scope, name.into(), value.into());
+        },
+    ).unwrap();
+    let prevent_default_name = v8::String::new(scope, "preventDefault").unwrap();
+    event_obj.set(scope, prevent_default_name.into(), prevent_default_fn.into());
+    
+    let stop_propagation_fn = v8::Function::new(
+        scope,
+        |scope: &mut v8::HandleScope,
+         args: v8::FunctionCallbackArguments,
+         _rv: v8::ReturnValue| {
+            let name = v8::String::new(scope, "cancelBubble").unwrap();
+            let value = v8::Boolean::new(scope, true);
+            args.this().set(scope, name.into(), value.into());
+        },
+    ).unwrap();
+    let stop_propagation_name = v8::String::new(scope, "stopPropagation").unwrap();
+    event_obj.set(scope, stop_propagation_name.into(), stop_propagation_fn.into());
+    
+    event_obj
+}
+
+/// Compile an inline handler attribute into a function taking `event`
+fn compile_inline_handler<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    code: &str,
+    attribute: &str,
+) -> Result<v8::Local<'s, v8::Function>> {
+    let scope = &mut v8::EscapableHandleScope::new(scope);
+    let scope = &mut v8::TryCatch::new(scope);
+    let source = v8::String::new(scope, &format!("(function (event) {{\n{}\n}})", code)).unwrap();
+    let function = v8::Script::compile(scope, source, None)
+        .and_then(|script| script.run(scope))
+        .and_then(|value| v8::Local::<v8::Function>::try_from(value).ok());
+    match function {
+        Some(function) => Ok(scope.escape(function)),
+        None if scope.has_caught() => Err(EngineError::JavaScriptError(exception_message(scope))),
+        None => Err(EngineError::JavaScriptError(format!("{} is not a function body", attribute))),
+    }
+}
+
+/// Script-facing wrapper for an element: a snapshot of `id`, `tagName`, `className` and
+/// `textContent`, with `getAttribute`/`setAttribute`/`removeAttribute` going to the document
+/// and `addEventListener`/`removeEventListener` registering listeners for `dispatch_event`
+fn element_object<'s>(scope: &mut v8::HandleScope<'s>, element: &Element) -> v8::Local<'s, v8::Object> {
+    let element_obj = v8::Object::new(scope);
+    
+    let key = private_key(scope, ELEMENT_ID_KEY);
+    let element_id = v8::String::new(scope, &element.id.0.to_string()).unwrap();
+    element_obj.set_private(scope, key, element_id.into());
+    
+    let properties = [
+        ("id", element.get_attribute("id").unwrap_or_default().to_string()),
+        ("tagName", element.tag_name.to_ascii_uppercase()),
+        ("className", element.get_attribute("class").unwrap_or_default().to_string()),
+        ("textContent", element.text_content()),
+    ];
+    for (name, value) in properties {
+        let name = v8::String::new(scope, name).unwrap();
+        let value = v8::String::new(scope, &value).unwrap();
+        element_obj.set(scope, name.into(), value.into());
+    }
+    
+    let get_attribute_fn = v8::Function::new(
+        scope,
+        |scope: &mut v8::HandleScope,
+         args: v8::FunctionCallbackArguments,
+         mut rv: v8::ReturnValue| {
+            let name = args.get(0).to_rust_string_lossy(scope);
+            let value = bound_element_id(scope, args.this()).and_then(|element_id| {
+                scope.get_slot::<DomState>()
+                    .and_then(|state| state.document.as_ref())
+                    .and_then(|document| document.elements.get(&element_id))
+                    .and_then(|element| element.get_attribute(&name).map(str::to_string))
+            });
+            match value {
+                Some(value) => rv.set(v8::String::new(scope, &value).unwrap().into()),
+                None => rv.set(v8::null(scope).into()),
+            }
+        },
+    ).unwrap();
+    let get_attribute_name = v8::String::new(scope, "getAttribute").unwrap();
+    element_obj.set(scope, get_attribute_name.into(), get_attribute_fn.into());
+    
+    let set_attribute_fn = v8::Function::new(
+        scope,
+        |scope: &mut v8::HandleScope,
+         args: v8::FunctionCallbackArguments,
+         _rv: v8::ReturnValue| {
+            let name = args.get(0).to_rust_string_lossy(scope);
+            let value = args.get(1).to_rust_string_lossy(scope);
+            mutate_bound_element(scope, args.this(), |document, element_id| {
+                document.set_attribute(element_id, &name, &value)
+            });
+        },
+    ).unwrap();
+    let set_attribute_name = v8::String::new(scope, "setAttribute").unwrap();
+    element_obj.set(scope, set_attribute_name.into(), set_attribute_fn.into());
+    
+    let remove_attribute_fn = v8::Function::new(
+        scope,
+        |scope: &mut v8::HandleScope,
+         args: v8::FunctionCallbackArguments,
+         _rv: v8::ReturnValue| {
+            let name = args.get(0).to_rust_string_lossy(scope);
+            mutate_bound_element(scope, args.this(), |document, element_id| {
+                document.remove_attribute(element_id, &name)
+            });
+        },
+    ).unwrap();
+    let remove_attribute_name = v8::String::new(scope, "removeAttribute").unwrap();
+    element_obj.set(scope, remove_attribute_name.into(), remove_attribute_fn.into());
+    
+    // element.addEventListener(type, listener, capture), kept per element so every wrapper sees it
+    let add_event_listener_fn = v8::Function::new(
+        scope,
+        |scope: &mut v8::HandleScope,
+         args: v8::FunctionCallbackArguments,
+         _rv: v8::ReturnValue| {
+            let event_type = args.get(0).to_rust_string_lossy(scope);
+            let capture = listener_capture(scope, args.get(2));
+            let (Some(element_id), Ok(listener)) = (bound_element_id(scope, args.this()), v8::Local::<v8::Function>::try_from(args.get(1))) else {
+                return;
+            };
+            let callback = v8::Global::new(scope, listener);
+            if let Some(state) = scope.get_slot_mut::<DomState>() {
+                let listeners = state.listeners.entry((element_id, event_type)).or_default();
+                if !listeners.iter().any(|registered| registered.callback == callback && registered.capture == capture) {
+                    listeners.push(EventListener { callback, capture });
+                }
+            }
+        },
+    ).unwrap();
+    let add_event_listener_name = v8::String::new(scope, "addEventListener").unwrap();
+    element_obj.set(scope, add_event_listener_name.into(), add_event_listener_fn.into());
+    
+    let remove_event_listener_fn = v8::Function::new(
+        scope,
+        |scope: &mut v8::HandleScope,
+         args: v8::FunctionCallbackArguments,
+         _rv: v8::ReturnValue| {
+            let event_type = args.get(0).to_rust_string_lossy(scope);
+            let capture = listener_capture(scope, args.get(2));
+            let (Some(element_id), Ok(listener)) = (bound_element_id(scope, args.this()), v8::Local::<v8::Function>::try_from(args.get(1))) else {
+                return;
+            };
+            let callback = v8::Global::new(scope, listener);
+            if let Some(listeners) = scope.get_slot_mut::<DomState>()
+                .and_then(|state| state.listeners.get_mut(&(element_id, event_type)))
+            {
+                listeners.retain(|registered| !(registered.callback == callback && registered.capture == capture));
+            }
+        },
+    ).unwrap();
+    let remove_event_listener_name = v8::String::new(scope, "removeEventListener").unwrap();
+    element_obj.set(scope, remove_event_listener_name.into(), remove_event_listener_fn.into());
+    
+    element_obj
+}
+
+/// The `ElementId` behind an element wrapper
+fn bound_element_id(scope: &mut v8::HandleScope, element_obj: v8::Local<v8::Object>) -> Option<ElementId> {
+    let key = private_key(scope, ELEMENT_ID_KEY);
+    let value = element_obj.get_private(scope, key)?;
+    let uuid = uuid::Uuid::parse_str(&value.to_rust_string_lossy(scope)).ok()?;
+    Some(ElementId(uuid))
+}
+
+/// Hand the mutation records queued during a script turn to the bound document's observers
+fn deliver_mutation_records(isolate: &mut v8::Isolate) {
+    if let Some(document) = isolate.get_slot_mut::<DomState>().and_then(|state| state.document.as_mut()) {
+        document.deliver_mutations();
+    }
+}
+
+/// Apply a mutation to the bound document, throwing if the element is gone
+fn mutate_bound_element(
+    scope: &mut v8::HandleScope,
+    element_obj: v8::Local<v8::Object>,
+    mutation: impl FnOnce(&mut Document, ElementId) -> Result<()>,
+) {
+    let outcome = match bound_element_id(scope, element_obj) {
+        Some(element_id) => match scope.get_slot_mut::<DomState>().and_then(|state| state.document.as_mut()) {
+            Some(document) => mutation(document, element_id),
+            None => Err(EngineError::DomError("InvalidStateError: no document is bound".to_string())),
+        },
+        None => Err(EngineError::DomError("TypeError: not an element".to_string())),
+    };
+    
+    if let Err(e) = outcome {
+        let message = v8::String::new(scope, &e.to_string()).unwrap();
+        let exception = v8::Exception::error(scope, message);
+        scope.throw_exception(exception);
+    }
+}
+
+/// Private property linking a `MessagePort` to the port it delivers to
+const ENTANGLED_PORT_KEY: &str = "titan::entangledPort";
+
+/// Private property holding a port's `addEventListener('message')` listeners
+const PORT_LISTENERS_KEY: &str = "titan::messageListeners";
+
+/// Look up a private property key shared across the isolate
+fn private_key<'s>(scope: &mut v8::HandleScope<'s>, name: &str) -> v8::Local<'s, v8::Private> {
+    let name = v8::String::new(scope, name).unwrap();
+    v8::Private::for_api(scope, Some(name))
+}
+
+/// Throws a `DataCloneError` when V8 meets a value it can't serialize
+struct CloneSerializer;
+
+impl v8::ValueSerializerImpl for CloneSerializer {
+    fn throw_data_clone_error<'s>(
+        &mut self,
+        scope: &mut v8::HandleScope<'s>,
+        message: v8::Local<'s, v8::String>,
+    ) {
+        let exception = v8::Exception::error(scope, message);
+        if let Ok(error) = v8::Local::<v8::Object>::try_from(exception) {
+            let name_key = v8::String::new(scope, "name").unwrap();
+            let name = v8::String::new(scope, "DataCloneError").unwrap();
+            error.set(scope, name_key.into(), name.into());
+        }
+        scope.throw_exception(exception);
+    }
+}
+
+struct CloneDeserializer;
+
+impl v8::ValueDeserializerImpl for CloneDeserializer {}
+
+/// Deep-copy a value through V8's serializer; returns `None` with an exception pending on failure
+fn structured_clone<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    value: v8::Local<'s, v8::Value>,
+) -> Option<v8::Local<'s, v8::Value>> {
+    let context = scope.get_current_context();
+    
+    let serializer = v8::ValueSerializer::new(scope, Box::new(CloneSerializer));
+    serializer.write_header();
+    if serializer.write_value(context, value) != Some(true) {
+        return None;
+    }
+    let bytes = serializer.release();
+    
+    let deserializer = v8::ValueDeserializer::new(scope, Box::new(CloneDeserializer), &bytes);
+    if deserializer.read_header(context) != Some(true) {
+        return None;
+    }
+    deserializer.read_value(context)
+}
+
+/// Create an unentangled `MessagePort` object
+fn new_message_port<'s>(scope: &mut v8::HandleScope<'s>) -> v8::Local<'s, v8::Object> {
+    let port = v8::Object::new(scope);
+    
+    let listeners_key = private_key(scope, PORT_LISTENERS_KEY);
+    let listeners = v8::Array::new(scope, 0);
+    port.set_private(scope, listeners_key, listeners.into());
+    
+    let onmessage_name = v8::String::new(scope, "onmessage").unwrap();
+    let null_value = v8::null(scope);
+    port.set(scope, onmessage_name.into(), null_value.into());
+    
+    // port.postMessage: clone now, deliver to the entangled port from a microtask
+    let post_message_fn = v8::Function::new(
+        scope,
+        |scope: &mut v8::HandleScope,
+         args: v8::FunctionCallbackArguments,
+         _rv: v8::ReturnValue| {
+            let entangled_key = private_key(scope, ENTANGLED_PORT_KEY);
+            let target = match args.this().get_private(scope, entangled_key) {
+                Some(target) if target.is_object() => target,
+                _ => return, // Closed ports drop messages
+            };
+            
+            let message = match structured_clone(scope, args.get(0)) {
+                Some(message) => message,
+                None => return,
+            };
+            
+            let delivery = v8::Array::new_with_elements(scope, &[target, message]);
+            let deliver_fn = v8::Function::builder(deliver_port_message)
+                .data(delivery.into())
+                .build(scope)
+                .unwrap();
+            scope.enqueue_microtask(deliver_fn);
+        },
+    ).unwrap();
+    
+    let post_message_name = v8::String::new(scope, "postMessage").unwrap();
+    port.set(scope, post_message_name.into(), post_message_fn.into());
+    
+    // port.addEventListener('message', listener)
+    let add_event_listener_fn = v8::Function::new(
+        scope,
+        |scope: &mut v8::HandleScope,
+         args: v8::FunctionCallbackArguments,
+         _rv: v8::ReturnValue| {
+            let event_type = args.get(0).to_rust_string_lossy(scope);
+            let listener = args.get(1);
+            if event_type != "message" || !listener.is_function() {
+                return;
+            }
+            
+            let listeners_key = private_key(scope, PORT_LISTENERS_KEY);
+            if let Some(listeners) = args.this().get_private(scope, listeners_key)
+                .and_then(|value| v8::Local::<v8::Array>::try_from(value).ok())
+            {
+                listeners.set_index(scope, listeners.length(), listener);
+            }
+        },
+    ).unwrap();
+    
+    let add_event_listener_name = v8::String::new(scope, "addEventListener").unwrap();
+    port.set(scope, add_event_listener_name.into(), add_event_listener_fn.into());
+    
+    // port.start: delivery is never paused, so this only exists for compatibility
+    let start_fn = v8::Function::new(
+        scope,
+        |_scope: &mut v8::HandleScope,
+         _args: v8::FunctionCallbackArguments,
+         _rv: v8::ReturnValue| {},
+    ).unwrap();
+    
+    let start_name = v8::String::new(scope, "start").unwrap();
+    port.set(scope, start_name.into(), start_fn.into());
+    
+    // port.close: disentangle both ends
+    let close_fn = v8::Function::new(
+        scope,
+        |scope: &mut v8::HandleScope,
+         args: v8::FunctionCallbackArguments,
+         _rv: v8::ReturnValue| {
+            let entangled_key = private_key(scope, ENTANGLED_PORT_KEY);
+            let port = args.this();
+            if let Some(other) = port.get_private(scope, entangled_key)
+                .and_then(|value| v8::Local::<v8::Object>::try_from(value).ok())
+            {
+                other.delete_private(scope, entangled_key);
+            }
+            port.delete_private(scope, entangled_key);
+        },
+    ).unwrap();
+    
+    let close_name = v8::String::new(scope, "close").unwrap();
+    port.set(scope, close_name.into(), close_fn.into());
+    
+    port
+}
+
+/// Microtask dispatching a `message` event to `onmessage` and registered listeners
+fn deliver_port_message(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let delivery = match v8::Local::<v8::Array>::try_from(args.data()) {
+        Ok(delivery) => delivery,
+        Err(_) => return,
+    };
+    let (target, data) = match (delivery.get_index(scope, 0), delivery.get_index(scope, 1)) {
+        (Some(target), Some(data)) => (target, data),
+        _ => return,
+    };
+    let target = match v8::Local::<v8::Object>::try_from(target) {
+        Ok(target) => target,
+        Err(_) => return,
+    };
+    
+    let event = v8::Object::new(scope);
+    let type_name = v8::String::new(scope, "type").unwrap();
+    let type_value = v8::String::new(scope, "message").unwrap();
+    event.set(scope, type_name.into(), type_value.into());
+    let data_name = v8::String::new(scope, "data").unwrap();
+    event.set(scope, data_name.into(), data);
+    let target_name = v8::String::new(scope, "target").unwrap();
+    event.set(scope, target_name.into(), target.into());
+    
+    let mut handlers = Vec::new();
+    let onmessage_name = v8::String::new(scope, "onmessage").unwrap();
+    if let Some(onmessage) = target.get(scope, onmessage_name.into()) {
+        handlers.push(onmessage);
+    }
+    let listeners_key = private_key(scope, PORT_LISTENERS_KEY);
+    if let Some(listeners) = target.get_private(scope, listeners_key)
+        .and_then(|value| v8::Local::<v8::Array>::try_from(value).ok())
+    {
+        for i in 0..listeners.length() {
+            if let Some(listener) = listeners.get_index(scope, i) {
+                handlers.push(listener);
+            }
+        }
+    }
+    
+    for handler in handlers {
+        if let Ok(handler) = v8::Local::<v8::Function>::try_from(handler) {
+            handler.call(scope, target.into(), &[event.into()]);
+        }
+    }
+}
+
+/// Compile a module and record it in the registry under its URL
+fn compile_module<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    url: &str,
+    code: &str,
+) -> Option<v8::Local<'s, v8::Module>> {
+    let source = v8::String::new(scope, code)?;
+    let name = v8::String::new(scope, url)?;
+    let origin = v8::ScriptOrigin::new(
+        scope,
+        name.into(),
+        0,
+        0,
+        false,
+        0,
+        None,
+        false,
+        false,
+        true,
+    );
+    
+    let source = v8::script_compiler::Source::new(source, Some(&origin));
+    let module = v8::script_compiler::compile_module(scope, source)?;
+    
+    let global = v8::Global::new(scope, module);
+    if let Some(registry) = scope.get_slot_mut::<ModuleRegistry>() {
+        registry.urls.insert(module.get_identity_hash(), url.to_string());
+        registry.modules.insert(url.to_string(), global);
+    }
+    
+    Some(module)
+}
+
+/// V8 module resolution callback: import map first, then relative/absolute URL resolution
+fn resolve_module_callback<'a>(
+    context: v8::Local<'a, v8::Context>,
+    specifier: v8::Local<'a, v8::String>,
+    _import_assertions: v8::Local<'a, v8::FixedArray>,
+    referrer: v8::Local<'a, v8::Module>,
+) -> Option<v8::Local<'a, v8::Module>> {
+    let scope = &mut unsafe { v8::CallbackScope::new(context) };
+    let specifier = specifier.to_rust_string_lossy(scope);
+    
+    let lookup = {
+        let registry = scope.get_slot::<ModuleRegistry>()?;
+        let referrer_url = registry.urls.get(&referrer.get_identity_hash())
+            .cloned()
+            .unwrap_or_default();
+        
+        registry.import_map.resolve(&specifier, &referrer_url).map(|url| {
+            let cached = registry.modules.get(&url).cloned();
+            let source = registry.sources.get(&url).cloned();
+            (url, cached, source)
+        })
+    };
+    
+    let error = match lookup {
+        Ok((_, Some(module), _)) => return Some(v8::Local::new(scope, module)),
+        Ok((url, None, Some(source))) => return compile_module(scope, &url, &source),
+        Ok((url, None, None)) => format!("Module not found: {}", url),
+        Err(e) => e.to_string(),
+    };
+    
+    let message = v8::String::new(scope, &error)?;
+    let exception = v8::Exception::type_error(scope, message);
+    scope.throw_exception(exception);
+    None
+}
+
+/// Render the pending exception of a try/catch scope as a string
+fn exception_message(scope: &mut v8::TryCatch<v8::HandleScope>) -> String {
+    match scope.exception() {
+        Some(exception) => exception.to_rust_string_lossy(scope),
+        None => "unknown error".to_string(),
+    }
+}
+
+/// Module sources, compiled modules, and the active import map for an isolate
+#[derive(Default)]
+struct ModuleRegistry {
+    import_map: ImportMap,
+    /// Module source text by resolved URL
+    sources: HashMap<String, String>,
+    /// Compiled modules by resolved URL, so each URL is instantiated once
+    modules: HashMap<String, v8::Global<v8::Module>>,
+    /// Resolved URL of each compiled module, keyed by V8 identity hash
+    urls: HashMap<NonZeroI32, String>,
+}
+
+/// Import map mapping module specifiers to URLs
+#[derive(Debug, Clone, Default)]
+pub struct ImportMap {
+    imports: HashMap<String, Url>,
+    scopes: HashMap<String, HashMap<String, Url>>,
+}
+
+impl ImportMap {
+    /// Parse an import map, resolving its addresses against `base_url`
+    pub fn parse(json: &str, base_url: &str) -> Result<Self> {
+        let base = Url::parse(base_url)
+            .map_err(|e| EngineError::JavaScriptError(format!("Invalid import map base URL: {}", e)))?;
+        let value: serde_json::Value = serde_json::from_str(json)
+            .map_err(|e| EngineError::JavaScriptError(format!("Invalid import map JSON: {}", e)))?;
+        
+        let object = value.as_object()
+            .ok_or_else(|| EngineError::JavaScriptError("Import map must be a JSON object".to_string()))?;
+        
+        let imports = object.get("imports")
+            .map(|imports| parse_specifier_map(imports, &base))
+            .unwrap_or_default();
+        
+        let mut scopes = HashMap::new();
+        if let Some(scope_map) = object.get("scopes").and_then(|s| s.as_object()) {
+            for (prefix, map) in scope_map {
+                match base.join(prefix) {
+                    Ok(prefix_url) => {
+                        scopes.insert(prefix_url.to_string(), parse_specifier_map(map, &base));
+                    }
+                    Err(e) => log::warn!("Ignoring import map scope {}: {}", prefix, e),
+                }
+            }
+        }
+        
+        Ok(Self { imports, scopes })
+    }
+    
+    /// Merge another import map into this one; entries already present take precedence
+    pub fn merge(&mut self, other: ImportMap) {
+        for (specifier, url) in other.imports {
+            self.imports.entry(specifier).or_insert(url);
+        }
+        for (prefix, map) in other.scopes {
+            let scope = self.scopes.entry(prefix).or_default();
+            for (specifier, url) in map {
+                scope.entry(specifier).or_insert(url);
+            }
+        }
+    }
+    
+    /// Resolve a module specifier imported from `referrer` to an absolute URL
+    pub fn resolve(&self, specifier: &str, referrer: &str) -> Result<String> {
+        let referrer_url = Url::parse(referrer).ok();
+        let as_url = referrer_url.as_ref().and_then(|base| resolve_url_like(specifier, base))
+            .or_else(|| Url::parse(specifier).ok());
+        let normalized = as_url.as_ref()
+            .map(|url| url.to_string())
+            .unwrap_or_else(|| specifier.to_string());
+        
+        // Scopes apply to referrers under their prefix, most specific first
+        let mut scopes: Vec<_> = self.scopes.iter()
+            .filter(|(prefix, _)| referrer.starts_with(prefix.as_str()))
+            .collect();
+        scopes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        
+        for (_, map) in scopes {
+            if let Some(url) = resolve_imports_match(&normalized, map) {
+                return Ok(url.to_string());
+            }
+        }
+        
+        if let Some(url) = resolve_imports_match(&normalized, &self.imports) {
+            return Ok(url.to_string());
+        }
+        
+        as_url.map(|url| url.to_string()).ok_or_else(|| {
+            EngineError::JavaScriptError(format!("Bare specifier \"{}\" is not mapped by any import map", specifier))
+        })
+    }
+}
+
+/// Parse an `imports`-style object, normalizing URL-like keys and resolving addresses
+fn parse_specifier_map(value: &serde_json::Value, base: &Url) -> HashMap<String, Url> {
+    let mut map = HashMap::new();
+    
+    let Some(object) = value.as_object() else {
+        log::warn!("Ignoring import map entry that is not an object");
+        return map;
+    };
+    
+    for (key, target) in object {
+        if key.is_empty() {
+            continue;
+        }
+        
+        let key = resolve_url_like(key, base)
+            .map(|url| url.to_string())
+            .unwrap_or_else(|| key.clone());
+        
+        let target = match target.as_str().and_then(|t| resolve_url_like(t, base).or_else(|| Url::parse(t).ok())) {
+            Some(target) => target,
+            None => {
+                log::warn!("Ignoring import map address for {}: {}", key, target);
+                continue;
+            }
+        };
+        
+        // Prefix mappings must map to prefixes
+        if key.ends_with('/') && !target.as_str().ends_with('/') {
+            log::warn!("Ignoring import map prefix {} with non-prefix address {}", key, target);
+            continue;
+        }
+        
+        map.insert(key, target);
+    }
+    
+    map
+}
+
+/// Resolve relative (`./`, `../`, `/`) or absolute specifiers; bare specifiers yield `None`
+fn resolve_url_like(specifier: &str, base: &Url) -> Option<Url> {
+    if specifier.starts_with('/') || specifier.starts_with("./") || specifier.starts_with("../") {
+        base.join(specifier).ok()
+    } else {
+        Url::parse(specifier).ok()
+    }
+}
+
+/// Look up a normalized specifier: exact match, else the longest matching `/`-terminated prefix
+fn resolve_imports_match(normalized: &str, map: &HashMap<String, Url>) -> Option<Url> {
+    if let Some(url) = map.get(normalized) {
+        return Some(url.clone());
+    }
+    
+    map.iter()
+        .filter(|(key, _)| key.ends_with('/') && normalized.starts_with(key.as_str()))
+        .max_by_key(|(key, _)| key.len())
+        .and_then(|(key, target)| target.join(&normalized[key.len()..]).ok())
+}
+
+/// JavaScript value types
+#[derive(Debug, Clone)]
+pub enum JSValue {
+    Undefined,
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JSValue>),
+    Object(HashMap<String, JSValue>),
+    Function,
+}
+
+impl JSValue {
+    /// Convert to string representation
+    pub fn to_string(&self) -> String {
+        match self {
+            JSValue::Undefined => "undefined".to_string(),
+            JSValue::Null => "null".to_string(),
+            JSValue::Boolean(b) => b.to_string(),
+            JSValue::Number(n) => n.to_string(),
+            JSValue::String(s) => s.clone(),
             JSValue::Array(_) => "[object Array]".to_string(),
             JSValue::Object(_) => "[object Object]".to_string(),
             JSValue::Function => "[object Function]".to_string(),
@@ -527,6 +3216,18 @@ pub struct ExecutionStats {
     pub errors: u64,
     pub timeouts: u64,
     pub security_violations: u64,
+    /// Scripts terminated for exceeding the heap limit
+    pub oom_terminations: u64,
+}
+
+#[async_trait::async_trait]
+impl MetricsReporter for JSRuntime {
+    async fn get_metrics(&self) -> PerformanceMetrics {
+        PerformanceMetrics {
+            javascript_execution_time_ms: self.get_execution_stats().total_execution_time.as_millis() as u64,
+            ..PerformanceMetrics::default()
+        }
+    }
 }
 
 #[cfg(test)]
@@ -573,11 +3274,568 @@ mod tests {
     }
     
     #[test]
-    fn test_dangerous_pattern_detection() {
+    fn test_import_map_resolution() {
+        let import_map = ImportMap::parse(
+            r#"{
+                "imports": {
+                    "app": "/js/app.js",
+                    "lib/": "https://cdn.example.com/lib/"
+                },
+                "scopes": {
+                    "/legacy/": { "app": "/js/app-legacy.js" }
+                }
+            }"#,
+            "https://example.com/index.html",
+        ).unwrap();
+        
+        let referrer = "https://example.com/index.html";
+        assert_eq!(import_map.resolve("app", referrer).unwrap(), "https://example.com/js/app.js");
+        assert_eq!(import_map.resolve("lib/util.js", referrer).unwrap(), "https://cdn.example.com/lib/util.js");
+        assert_eq!(import_map.resolve("./local.js", referrer).unwrap(), "https://example.com/local.js");
+        assert_eq!(
+            import_map.resolve("app", "https://example.com/legacy/main.js").unwrap(),
+            "https://example.com/js/app-legacy.js"
+        );
+        assert!(import_map.resolve("unmapped", referrer).is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_module_imports_through_import_map() {
+        let mut runtime = JSRuntime::new().await.unwrap();
+        
+        runtime.add_import_map(r#"{"imports": {"app": "/js/app.js"}}"#, "https://example.com/").unwrap();
+        runtime.register_module_source("https://example.com/js/app.js", "export const answer = 42;").unwrap();
+        
+        runtime.execute_module(
+            "import { answer } from 'app'; globalThis.importedAnswer = answer;",
+            "https://example.com/main.js",
+        ).await.unwrap();
+        
+        match runtime.execute_script("importedAnswer", "test").await.unwrap() {
+            JSValue::Number(n) => assert_eq!(n, 42.0),
+            _ => panic!("Expected the imported module's export"),
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_clipboard_permission_gating() {
+        let mut runtime = JSRuntime::new().await.unwrap();
+        let permissions = PermissionStore::new();
+        runtime.set_origin("https://example.com");
+        runtime.set_permission_store(permissions.clone());
+        runtime.set_user_activation(true);
+        
+        // Without permission the promise rejects
+        runtime.execute_script(
+            "navigator.clipboard.readText().then(() => { globalThis.outcome = 'resolved'; }, \
+             () => { globalThis.outcome = 'rejected'; });",
+            "test",
+        ).await.unwrap();
+        assert_eq!(runtime.execute_script("outcome", "test").await.unwrap().to_string(), "rejected");
+        
+        permissions.grant("https://example.com", PermissionKind::ClipboardRead);
+        permissions.grant("https://example.com", PermissionKind::ClipboardWrite);
+        
+        runtime.execute_script(
+            "navigator.clipboard.writeText('copied text') \
+                .then(() => navigator.clipboard.readText()) \
+                .then(text => { globalThis.pasted = text; });",
+            "test",
+        ).await.unwrap();
+        assert_eq!(runtime.execute_script("pasted", "test").await.unwrap().to_string(), "copied text");
+    }
+    
+    #[tokio::test]
+    async fn test_structured_clone_is_deep() {
+        let mut runtime = JSRuntime::new().await.unwrap();
+        
+        runtime.execute_script(
+            "const original = { nested: { list: [1, 2, { deep: true }] }, when: new Date(0), tags: new Map([['a', 1]]) }; \
+             const copy = structuredClone(original); \
+             copy.nested.list[2].deep = false; \
+             globalThis.cloneResult = [original.nested.list[2].deep, copy.nested !== original.nested, \
+                 copy.when instanceof Date, copy.tags.get('a')].join(',');",
+            "test",
+        ).await.unwrap();
+        assert_eq!(runtime.execute_script("cloneResult", "test").await.unwrap().to_string(), "true,true,true,1");
+        
+        let result = runtime.execute_script(
+            "try { structuredClone({ callback() {} }); 'cloned'; } catch (e) { e.name; }",
+            "test",
+        ).await.unwrap();
+        assert_eq!(result.to_string(), "DataCloneError");
+    }
+    
+    #[tokio::test]
+    async fn test_message_channel_delivery() {
+        let mut runtime = JSRuntime::new().await.unwrap();
+        
+        runtime.execute_script(
+            "const channel = new MessageChannel(); \
+             const payload = { text: 'hello' }; \
+             channel.port2.onmessage = (event) => { globalThis.received = event.data; }; \
+             channel.port1.postMessage(payload); \
+             payload.text = 'mutated'; \
+             globalThis.deliveredSynchronously = globalThis.received !== undefined;",
+            "test",
+        ).await.unwrap();
+        
+        assert!(!runtime.execute_script("deliveredSynchronously", "test").await.unwrap().is_truthy());
+        assert_eq!(runtime.execute_script("received.text", "test").await.unwrap().to_string(), "hello");
+    }
+    
+    #[tokio::test]
+    async fn test_dangerous_pattern_detection() {
         let runtime = JSRuntime::new().await.unwrap();
         
         assert!(runtime.contains_dangerous_patterns("eval('malicious code')"));
         assert!(runtime.contains_dangerous_patterns("document.cookie = 'steal'"));
         assert!(!runtime.contains_dangerous_patterns("console.log('safe')"));
+        
+        // Names in strings and comments are not usages
+        assert!(!runtime.contains_dangerous_patterns("console.log('eval(x)')"));
+        assert!(!runtime.contains_dangerous_patterns("// reads localStorage\nlet note = `innerHTML`;"));
+        assert!(!runtime.contains_dangerous_patterns("let pattern = /eval\\(/; element.evaluate(x);"));
+        assert!(runtime.contains_dangerous_patterns("eval(x)"));
+        
+        // Obfuscated access through the global object is still caught
+        assert!(runtime.contains_dangerous_patterns("window['eval'](x)"));
+        assert!(runtime.contains_dangerous_patterns("el.innerHTML = html"));
+        assert!(runtime.contains_dangerous_patterns("let html = `${node['outerHTML']}`"));
+    }
+
+    #[tokio::test]
+    async fn test_console_callback_receives_formatted_message() {
+        let mut runtime = JSRuntime::new().await.unwrap();
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let sink = messages.clone();
+        runtime.set_console_callback(move |level, message, source, line| {
+            sink.lock().unwrap().push((format!("{:?}", level), message, source, line));
+        });
+        
+        runtime.execute_script("console.log('a', 1);\nconsole.warn({ b: [2] });", "page.js").await.unwrap();
+        
+        let messages = messages.lock().unwrap();
+        assert_eq!(messages[0], ("Log".to_string(), "a 1".to_string(), "page.js".to_string(), 1));
+        assert_eq!(messages[1], ("Warn".to_string(), r#"{"b":[2]}"#.to_string(), "page.js".to_string(), 2));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_resolves_with_response_text() {
+        use hyper::service::{make_service_fn, service_fn};
+        
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, std::convert::Infallible>(service_fn(|_request| async {
+                Ok::<_, std::convert::Infallible>(hyper::Response::new(hyper::Body::from("hello from server")))
+            }))
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        
+        let mut network = NetworkStack::new().await.unwrap();
+        network.update_security_config(crate::networking::SecurityConfig {
+            require_https: false,
+            ..crate::networking::SecurityConfig::default()
+        });
+        let mut runtime = JSRuntime::new().await.unwrap();
+        runtime.set_network_stack(Arc::new(network));
+        runtime.set_origin(&format!("http://{}", addr));
+        
+        runtime.update_security_settings(SecuritySettings {
+            allow_network_access: true,
+            ..SecuritySettings::default()
+        });
+        runtime.execute_script(
+            "let status; let body; fetch('/data').then(r => { status = r.status; return r.text(); }).then(t => { body = t; });",
+            "page.js",
+        ).await.unwrap();
+        assert_eq!(runtime.run_pending_fetches().await.unwrap(), 1);
+        
+        match runtime.execute_script("body", "test").await.unwrap() {
+            JSValue::String(body) => assert_eq!(body, "hello from server"),
+            other => panic!("Expected string body, got {:?}", other),
+        }
+        match runtime.execute_script("status", "test").await.unwrap() {
+            JSValue::Number(status) => assert_eq!(status, 200.0),
+            other => panic!("Expected numeric status, got {:?}", other),
+        }
+        
+        // Callbacks run under the same execution timeout as scripts
+        runtime.update_security_settings(SecuritySettings {
+            allow_network_access: true,
+            max_execution_time_ms: 200,
+            ..SecuritySettings::default()
+        });
+        runtime.execute_script("fetch('/data').then(() => { while (true) {} });", "page.js").await.unwrap();
+        let start = Instant::now();
+        match runtime.run_pending_fetches().await {
+            Err(EngineError::JavaScriptError(message)) => assert!(message.contains("timeout")),
+            other => panic!("Expected timeout error, got {:?}", other),
+        }
+        assert!(start.elapsed() < Duration::from_secs(2));
+        match runtime.execute_script("1 + 1", "after.js").await.unwrap() {
+            JSValue::Number(n) => assert_eq!(n, 2.0),
+            other => panic!("Expected number result, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_credentials_mode() {
+        use hyper::service::{make_service_fn, service_fn};
+        
+        // /login sets a cookie; the rest echo the Cookie header, allowing any origin or,
+        // on /credentialed, only https://app.example with credentials
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, std::convert::Infallible>(service_fn(|request: hyper::Request<hyper::Body>| async move {
+                let cookie = request.headers().get("cookie")
+                    .map(|value| value.to_str().unwrap().to_string())
+                    .unwrap_or_default();
+                let response = match request.uri().path() {
+                    "/login" => hyper::Response::builder()
+                        .header("Set-Cookie", "session=abc123; Path=/"),
+                    "/credentialed" => hyper::Response::builder()
+                        .header("Access-Control-Allow-Origin", "https://app.example")
+                        .header("Access-Control-Allow-Credentials", "true"),
+                    _ => hyper::Response::builder()
+                        .header("Access-Control-Allow-Origin", "*"),
+                };
+                Ok::<_, std::convert::Infallible>(response.body(hyper::Body::from(cookie)).unwrap())
+            }))
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        
+        let mut network = NetworkStack::new().await.unwrap();
+        network.update_security_config(crate::networking::SecurityConfig {
+            require_https: false,
+            ..crate::networking::SecurityConfig::default()
+        });
+        let jar = Arc::new(tokio::sync::Mutex::new(crate::storage::StorageEngine::open(":memory:").await.unwrap()));
+        network.set_cookie_store(jar);
+        network.fetch(&format!("http://{}/login", addr)).await.unwrap();
+        
+        let mut runtime = JSRuntime::new().await.unwrap();
+        runtime.set_network_stack(Arc::new(network));
+        runtime.update_security_settings(SecuritySettings {
+            allow_network_access: true,
+            ..SecuritySettings::default()
+        });
+        runtime.execute_script(
+            "var results = {}; function record(label, request) { request.then(r => r.text()).then(t => { results[label] = 'sent ' + t; }, e => { results[label] = 'blocked'; }); }",
+            "setup.js",
+        ).await.unwrap();
+        
+        // Same-origin requests carry cookies unless told to omit them
+        runtime.set_origin(&format!("http://{}", addr));
+        runtime.execute_script(
+            "record('same', fetch('/echo')); record('omit', fetch('/echo', { credentials: 'omit' })); record('bogus', fetch('/echo', { credentials: 'all' }));",
+            "page.js",
+        ).await.unwrap();
+        runtime.run_pending_fetches().await.unwrap();
+        
+        // Cross-origin requests don't by default; with credentials, `*` is not enough.
+        // The Lax cookie itself stays behind on these cross-site requests either way.
+        runtime.set_origin("https://app.example");
+        runtime.execute_script(&format!(
+            "record('cross', fetch('http://{0}/echo')); record('include', fetch('http://{0}/echo', {{ credentials: 'include' }})); record('allowed', fetch('http://{0}/credentialed', {{ credentials: 'include' }}));",
+            addr,
+        ), "page.js").await.unwrap();
+        runtime.run_pending_fetches().await.unwrap();
+        
+        for (label, expected) in [
+            ("same", "sent session=abc123"),
+            ("omit", "sent "),
+            ("bogus", "blocked"),
+            ("cross", "sent "),
+            ("include", "blocked"),
+            ("allowed", "sent "),
+        ] {
+            match runtime.execute_script(&format!("results.{}", label), "test").await.unwrap() {
+                JSValue::String(result) => assert_eq!(result, expected, "{}", label),
+                other => panic!("Expected string for {}, got {:?}", label, other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_websocket_echo_reaches_onmessage() {
+        use futures::SinkExt;
+        
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut server = tokio_tungstenite::accept_async(socket).await.unwrap();
+            while let Some(Ok(message)) = server.next().await {
+                if message.is_text() || message.is_binary() {
+                    server.send(message).await.unwrap();
+                }
+            }
+        });
+        
+        let mut network = NetworkStack::new().await.unwrap();
+        network.update_security_config(crate::networking::SecurityConfig {
+            require_https: false,
+            ..crate::networking::SecurityConfig::default()
+        });
+        let mut runtime = JSRuntime::new().await.unwrap();
+        runtime.set_network_stack(Arc::new(network));
+        runtime.set_origin(&format!("http://{}", addr));
+        runtime.update_security_settings(SecuritySettings {
+            allow_network_access: true,
+            ..SecuritySettings::default()
+        });
+        
+        // Connections outside the page's connect-src are refused up front
+        runtime.set_content_security_policy(Some("default-src 'self'; connect-src https://api.example.com"));
+        let blocked = runtime.execute_script(
+            "try { new WebSocket('/echo'); 'opened' } catch (e) { e.message }",
+            "page.js",
+        ).await.unwrap();
+        assert!(matches!(blocked, JSValue::String(ref message) if message.contains("connect-src")), "{:?}", blocked);
+        
+        runtime.set_content_security_policy(Some("connect-src 'self'"));
+        runtime.execute_script(
+            "let events = []; let received; let closeCode; \
+             const socket = new WebSocket('/echo'); \
+             events.push(socket.readyState); \
+             socket.onopen = () => { events.push('open'); socket.send('ping'); }; \
+             socket.onmessage = (event) => { received = event.data; socket.close(1000, 'done'); }; \
+             socket.addEventListener('close', (event) => { closeCode = event.code; events.push(socket.readyState); });",
+            "page.js",
+        ).await.unwrap();
+        while runtime.run_websocket_events(Duration::from_secs(5)).await.unwrap() > 0 {}
+        
+        match runtime.execute_script("received", "test").await.unwrap() {
+            JSValue::String(received) => assert_eq!(received, "ping"),
+            other => panic!("Expected echoed message, got {:?}", other),
+        }
+        match runtime.execute_script("closeCode", "test").await.unwrap() {
+            JSValue::Number(code) => assert_eq!(code, 1000.0),
+            other => panic!("Expected close code, got {:?}", other),
+        }
+        match runtime.execute_script("events.join(',')", "test").await.unwrap() {
+            JSValue::String(events) => assert_eq!(events, "0,open,3"),
+            other => panic!("Expected event log, got {:?}", other),
+        }
+    }
+
+    fn heap_size_limit(runtime: &mut JSRuntime) -> usize {
+        let mut stats = v8::HeapStatistics::default();
+        runtime.isolate.get_heap_statistics(&mut stats);
+        stats.heap_size_limit()
+    }
+
+    #[tokio::test]
+    async fn test_heap_limit_terminates_script() {
+        let mut runtime = JSRuntime::with_settings(SecuritySettings {
+            max_memory_mb: 16,
+            ..SecuritySettings::default()
+        }).await.unwrap();
+        
+        let result = runtime.execute_script(
+            "let chunks = []; while (true) { chunks.push(new Array(100000).fill(1.5)); }",
+            "hog.js",
+        ).await;
+        match result {
+            Err(EngineError::JavaScriptError(message)) => assert_eq!(message, "out of memory"),
+            other => panic!("Expected out of memory error, got {:?}", other),
+        }
+        assert_eq!(runtime.get_execution_stats().oom_terminations, 1);
+        
+        // The isolate is still usable afterwards
+        match runtime.execute_script("chunks = null; 1 + 1", "after.js").await.unwrap() {
+            JSValue::Number(n) => assert_eq!(n, 2.0),
+            other => panic!("Expected number result, got {:?}", other),
+        }
+        
+        // The extra headroom was taken back, so the limit holds for later scripts too
+        let limit_before = heap_size_limit(&mut runtime);
+        let result = runtime.execute_script(
+            "let more = []; while (true) { more.push(new Array(100000).fill(1.5)); }",
+            "hog-again.js",
+        ).await;
+        assert!(matches!(result, Err(EngineError::JavaScriptError(message)) if message == "out of memory"));
+        assert_eq!(runtime.get_execution_stats().oom_terminations, 2);
+        assert_eq!(heap_size_limit(&mut runtime), limit_before);
+    }
+
+    #[tokio::test]
+    async fn test_infinite_loop_times_out() {
+        let mut runtime = JSRuntime::with_settings(SecuritySettings {
+            max_execution_time_ms: 200,
+            ..SecuritySettings::default()
+        }).await.unwrap();
+        
+        let start = Instant::now();
+        let result = runtime.execute_script("while (true) {}", "spin.js").await;
+        match result {
+            Err(EngineError::JavaScriptError(message)) => assert!(message.contains("timeout")),
+            other => panic!("Expected timeout error, got {:?}", other),
+        }
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(runtime.get_execution_stats().timeouts, 1);
+        
+        // Termination was reset, so the isolate runs scripts again
+        match runtime.execute_script("1 + 1", "after.js").await.unwrap() {
+            JSValue::Number(n) => assert_eq!(n, 2.0),
+            other => panic!("Expected number result, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_security_settings_tighten_at_runtime() {
+        let runtime = Arc::new(tokio::sync::RwLock::new(JSRuntime::with_settings(SecuritySettings {
+            security_level: 0,
+            block_dangerous_patterns: false,
+            allow_network_access: true,
+            ..SecuritySettings::default()
+        }).await.unwrap()));
+        match runtime.write().await.execute_script("typeof eval", "test").await.unwrap() {
+            JSValue::String(kind) => assert_eq!(kind, "function"),
+            other => panic!("Expected string result, got {:?}", other),
+        }
+        
+        // Tightened through a shared handle, the way the engine holds its runtime
+        runtime.read().await.update_security_settings(SecuritySettings {
+            block_dangerous_patterns: false,
+            ..SecuritySettings::default()
+        });
+        let mut runtime = runtime.write().await;
+        match runtime.execute_script("typeof eval", "test").await.unwrap() {
+            JSValue::String(kind) => assert_eq!(kind, "undefined"),
+            other => panic!("Expected string result, got {:?}", other),
+        }
+        
+        // The fetch binding reads the live settings too
+        runtime.execute_script("let rejected = false; fetch('/data').catch(() => { rejected = true; });", "page.js").await.unwrap();
+        match runtime.execute_script("rejected", "test").await.unwrap() {
+            JSValue::Boolean(rejected) => assert!(rejected),
+            other => panic!("Expected boolean result, got {:?}", other),
+        }
+        
+        runtime.update_security_settings(SecuritySettings::default());
+        assert!(matches!(
+            runtime.execute_script("fetch('/data')", "page.js").await,
+            Err(EngineError::SecurityError(_))
+        ));
+        assert!(!runtime.security_settings().allow_network_access);
+    }
+
+    #[tokio::test]
+    async fn test_event_listeners_capture_then_bubble() {
+        let mut runtime = JSRuntime::new().await.unwrap();
+        let document = crate::html::HTMLParser::new()
+            .parse(r#"<html><body><div id="outer"><button id="inner">Go</button></div></body></html>"#)
+            .unwrap();
+        let inner = document.get_element_by_id("inner").unwrap().id;
+        runtime.bind_document(document);
+        
+        runtime.execute_script(r#"
+            var order = [];
+            var outer = document.getElementById('outer');
+            var inner = document.getElementById('inner');
+            outer.addEventListener('click', function (e) { order.push('outer-capture:' + e.eventPhase); }, { capture: true });
+            outer.addEventListener('click', function (e) { order.push('outer-bubble:' + e.eventPhase); });
+            inner.addEventListener('click', function (e) { order.push('inner:' + e.target.id); });
+            var removed = function () { order.push('removed'); };
+            inner.addEventListener('click', removed);
+            document.getElementById('inner').removeEventListener('click', removed);
+        "#, "listeners.js").await.unwrap();
+        
+        let event = DomEvent { event_type: "click".to_string(), ..DomEvent::default() };
+        assert!(runtime.dispatch_event(inner, &event).await.unwrap());
+        match runtime.execute_script("order.join(',')", "check.js").await.unwrap() {
+            JSValue::String(order) => assert_eq!(order, "outer-capture:1,inner:inner,outer-bubble:3"),
+            other => panic!("Expected a string, got {:?}", other),
+        }
+        
+        // stopPropagation at the target keeps the event from bubbling
+        runtime.execute_script(r#"
+            order = [];
+            inner.addEventListener('click', function (e) { e.stopPropagation(); e.preventDefault(); });
+        "#, "stop.js").await.unwrap();
+        assert!(!runtime.dispatch_event(inner, &event).await.unwrap());
+        match runtime.execute_script("order.join(',')", "check.js").await.unwrap() {
+            JSValue::String(order) => assert_eq!(order, "outer-capture:1,inner:inner"),
+            other => panic!("Expected a string, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_powerful_features_require_permission() {
+        let mut runtime = JSRuntime::new().await.unwrap();
+        let permissions = PermissionStore::new();
+        runtime.set_origin("https://example.com");
+        runtime.set_permission_store(permissions.clone());
+        runtime.set_geolocation(Some(GeoPosition { latitude: 48.85, longitude: 2.35, accuracy: 25.0 }));
+        let attempt = "globalThis.results = []; \
+            navigator.geolocation.getCurrentPosition(p => results.push('position ' + p.coords.longitude), e => results.push('position error ' + e.code)); \
+            navigator.mediaDevices.getUserMedia({ audio: true, video: true }) \
+                .then(s => results.push('media ' + s.getTracks().map(t => t.kind).join('+')), e => results.push('media rejected')); \
+            Notification.requestPermission().then(p => results.push('notifications ' + p));";
+        let results = "results.sort().join(', ')";
+        
+        // Nothing is granted yet
+        runtime.execute_script(attempt, "test").await.unwrap();
+        assert_eq!(
+            runtime.execute_script(results, "test").await.unwrap().to_string(),
+            "media rejected, notifications default, position error 1",
+        );
+        
+        // Camera alone isn't enough for a request that also wants the microphone
+        permissions.grant("https://example.com", PermissionKind::Camera);
+        permissions.grant("https://example.com", PermissionKind::Geolocation);
+        permissions.set("https://example.com", PermissionKind::Notifications, PermissionState::Denied);
+        runtime.execute_script(attempt, "test").await.unwrap();
+        assert_eq!(
+            runtime.execute_script(results, "test").await.unwrap().to_string(),
+            "media rejected, notifications denied, position 2.35",
+        );
+        
+        permissions.grant("https://example.com", PermissionKind::Microphone);
+        permissions.grant("https://example.com", PermissionKind::Notifications);
+        runtime.execute_script(attempt, "test").await.unwrap();
+        assert_eq!(
+            runtime.execute_script(results, "test").await.unwrap().to_string(),
+            "media audio+video, notifications granted, position 2.35",
+        );
+        
+        // Features switched off in the settings stay off even when granted
+        runtime.apply_engine_settings(&EngineSettings::default());
+        runtime.execute_script(attempt, "test").await.unwrap();
+        assert_eq!(
+            runtime.execute_script(results, "test").await.unwrap().to_string(),
+            "media rejected, notifications denied, position error 1",
+        );
+        assert_eq!(runtime.execute_script("Notification.permission", "test").await.unwrap().to_string(), "denied");
+    }
+
+    #[tokio::test]
+    async fn test_mutation_records_delivered_after_script_turn() {
+        use crate::html::{MutationKind, MutationRecord};
+        
+        let mut runtime = JSRuntime::new().await.unwrap();
+        let mut document = crate::html::HTMLParser::new()
+            .parse(r#"<html><body><p id="note" class="old"></p></body></html>"#)
+            .unwrap();
+        let note = document.get_element_by_id("note").unwrap().id;
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let received = batches.clone();
+        document.observe_mutations(move |records| received.lock().unwrap().push(records.to_vec()));
+        runtime.bind_document(document);
+        
+        // Both changes, including the one made in a microtask, arrive together
+        runtime.execute_script("var note = document.getElementById('note'); \
+            note.setAttribute('class', 'new'); \
+            Promise.resolve().then(() => note.removeAttribute('class'));", "test").await.unwrap();
+        let batches = batches.lock().unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0], vec![
+            MutationRecord { target: note, kind: MutationKind::Attribute { name: "class".to_string(), old_value: Some("old".to_string()) } },
+            MutationRecord { target: note, kind: MutationKind::Attribute { name: "class".to_string(), old_value: Some("new".to_string()) } },
+        ]);
     }
-}
\ No newline at end of file
+}
@@ -1,21 +1,27 @@
 //! Layout engine using Taffy for modern CSS layout algorithms
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::time::Instant;
 use taffy::{
     Taffy, Node, Style, Dimension, LengthPercentage, LengthPercentageAuto,
     Display, Position, FlexDirection, FlexWrap, AlignItems, AlignContent,
-    JustifyContent, Size, Rect as TaffyRect, Point as TaffyPoint,
+    JustifyContent, Rect as TaffyRect, Point as TaffyPoint,
 };
-use crate::core::{ElementId, Result, EngineError, Rect, Point, Size as CoreSize};
-use crate::html::{Document, Element};
-use crate::css::{ComputedStyle, DisplayType, PositionType, BoxValues};
+use serde::Serialize;
+use crate::core::{ElementId, Result, EngineError, Rect, Point, Size as CoreSize, MetricsReporter, PerformanceMetrics, Shutdown};
+use crate::html::{CompatMode, Document, Element};
+use crate::css::{AnimationDriver, ComputedStyle, DisplayType, PositionType, BoxValues, BoxPercentages};
 
 /// Layout engine for computing element positions and sizes
 pub struct LayoutEngine {
     /// Taffy layout engine instance
     taffy: Taffy,
     
-    /// Mapping from element IDs to Taffy nodes
+    /// Style resolver, kept so its selector cache lives across elements and layouts
+    css_engine: crate::css::CSSEngine,
+    
+    /// Mapping from element IDs to Taffy nodes, kept across layouts so unchanged nodes are reused
     element_to_node: HashMap<ElementId, Node>,
     
     /// Mapping from Taffy nodes to element IDs
@@ -23,16 +29,132 @@ pub struct LayoutEngine {
     
     /// Computed layout results
     layout_cache: HashMap<ElementId, LayoutBox>,
+    
+    /// Root of the current Taffy tree and the viewport it was laid out in
+    root_node: Option<Node>,
+    viewport: CoreSize,
+    
+    /// Text of inline-level elements, flowed into line fragments after Taffy layout
+    inline_content: HashMap<ElementId, InlineContent>,
+    
+    /// Scaled ascent, descent, and font size of each element's first available font
+    text_metrics: HashMap<ElementId, (f32, f32, f32)>,
+    
+    /// Metrics of loaded fonts, keyed by lowercase family name
+    font_metrics: HashMap<String, FontMetrics>,
+    
+    /// Natural size of replaced content (images, video), keyed by source URL
+    intrinsic_sizes: HashMap<String, CoreSize>,
+    
+    /// Styles resolved in the last pass, handed on to the renderer for paint properties
+    computed_styles: HashMap<ElementId, ComputedStyle>,
+    
+    /// Cascaded styles before transitions and animations, kept across passes so
+    /// a changed value transitions from the old one
+    cascaded_styles: HashMap<ElementId, ComputedStyle>,
+    
+    /// Keyframe animations and transitions, advanced by `tick_animations`
+    animations: AnimationDriver,
+    
+    /// Parent of each laid out box, for walking up to scroll containers
+    box_parents: HashMap<ElementId, ElementId>,
+    
+    /// Scroll position of the root (the viewport) and each `overflow: scroll`/`auto` element
+    scroll_states: HashMap<ElementId, ScrollState>,
+    
+    /// Whether layout results are checked for invalid geometry (on by default in debug builds)
+    validation_enabled: bool,
+    
+    /// Invalid style inputs seen while building the current layout tree
+    style_issues: Vec<LayoutIssue>,
+    
+    /// Result of the last validation pass that found problems
+    last_validation_error: Option<LayoutError>,
+    
+    /// Layout performance metrics
+    metrics: LayoutMetrics,
 }
 
 impl LayoutEngine {
     pub fn new() -> Self {
         Self {
             taffy: Taffy::new(),
+            css_engine: crate::css::CSSEngine::new(),
             element_to_node: HashMap::new(),
             node_to_element: HashMap::new(),
             layout_cache: HashMap::new(),
+            root_node: None,
+            viewport: CoreSize::new(1920.0, 1080.0),
+            inline_content: HashMap::new(),
+            text_metrics: HashMap::new(),
+            font_metrics: HashMap::new(),
+            intrinsic_sizes: HashMap::new(),
+            computed_styles: HashMap::new(),
+            cascaded_styles: HashMap::new(),
+            animations: AnimationDriver::new(),
+            box_parents: HashMap::new(),
+            scroll_states: HashMap::new(),
+            validation_enabled: cfg!(debug_assertions),
+            style_issues: Vec::new(),
+            last_validation_error: None,
+            metrics: LayoutMetrics::default(),
+        }
+    }
+    
+    /// Let `font-family` lists resolve to the `@font-face` families the page has loaded
+    pub fn set_web_fonts(&self, families: &[String]) {
+        self.css_engine.set_web_fonts(families);
+    }
+    
+    /// Use a loaded font's real metrics for elements in that family, instead of the approximation
+    pub fn register_font_metrics(&mut self, family: &str, metrics: FontMetrics) {
+        self.font_metrics.insert(family.trim().to_lowercase(), metrics);
+    }
+    
+    /// Record the decoded size of an image or video so `<img>`/`<video>` elements whose
+    /// source resolves to `url` get it as their natural size
+    pub fn register_intrinsic_size(&mut self, url: &str, size: CoreSize) {
+        self.intrinsic_sizes.insert(url.to_string(), size);
+    }
+    
+    /// Natural size of a replaced element, if its source (resolved against the document URL)
+    /// has been decoded
+    fn intrinsic_size_for(&self, document: &Document, element: &Element) -> Option<CoreSize> {
+        if !matches!(element.tag_name.as_str(), "img" | "video") {
+            return None;
         }
+        let src = element.attributes.get("src")?;
+        let resolved = url::Url::parse(&document.url)
+            .and_then(|base| base.join(src))
+            .map(String::from);
+        let size = match resolved {
+            Ok(url) => self.intrinsic_sizes.get(&url),
+            Err(_) => self.intrinsic_sizes.get(src),
+        }?;
+        (size.width > 0.0 && size.height > 0.0).then_some(*size)
+    }
+    
+    /// Metrics of the first registered family in a `font-family` list
+    fn font_metrics_for(&self, font_family: &str) -> FontMetrics {
+        font_family.split(',')
+            .map(|family| family.trim().trim_matches(|c| c == '"' || c == '\'').to_lowercase())
+            .find_map(|family| self.font_metrics.get(&family).copied())
+            .unwrap_or_default()
+    }
+    
+    /// Get layout performance metrics
+    pub fn get_metrics(&self) -> LayoutMetrics {
+        self.metrics.clone()
+    }
+    
+    /// Enable or disable the validation pass after `compute_layout`
+    pub fn set_validation_enabled(&mut self, enabled: bool) {
+        self.validation_enabled = enabled;
+    }
+    
+    /// Get the structured report from the last failed validation pass
+    pub fn last_validation_error(&self) -> Option<&LayoutError> {
+        self.last_validation_error.as_ref()
     }
     
     /// Compute layout for the entire document
@@ -41,28 +163,119 @@ impl LayoutEngine {
         document: &Document,
         stylesheets: &[std::sync::Arc<crate::css::Stylesheet>],
     ) -> Result<LayoutTree> {
-        // Clear previous layout
+        // Default viewport size
+        self.compute_layout_with_viewport(document, stylesheets, CoreSize::new(1920.0, 1080.0)).await
+    }
+    
+    /// Compute layout for a document within a viewport of the given size (e.g. an iframe's box)
+    pub async fn compute_layout_with_viewport(
+        &mut self,
+        document: &Document,
+        stylesheets: &[std::sync::Arc<crate::css::Stylesheet>],
+        viewport: CoreSize,
+    ) -> Result<LayoutTree> {
+        let start_time = Instant::now();
+        
+        // Clear results of the previous layout; Taffy nodes are reused
         self.clear_layout();
         
-        // Create layout tree from DOM
+        // Sync the layout tree with the DOM, creating nodes only for new elements
         let root_node = self.create_layout_tree(document, stylesheets).await?;
+        self.root_node = Some(root_node);
+        self.viewport = viewport;
         
-        // Compute layout with available space
-        let available_space = Size::new(1920.0, 1080.0); // Default viewport size
-        self.taffy.compute_layout(
-            root_node,
-            taffy::Size {
-                width: taffy::AvailableSpace::Definite(available_space.width),
-                height: taffy::AvailableSpace::Definite(available_space.height),
-            },
-        ).map_err(|e| EngineError::RenderingError(format!("Layout computation failed: {:?}", e)))?;
+        // Compute layout with available space; Taffy skips subtrees that aren't dirty
+        self.compute_from_root(root_node)?;
         
         // Extract layout results
         let layout_tree = self.extract_layout_tree(root_node, document)?;
+        self.update_scroll_states(&layout_tree);
+        
+        self.metrics.layout_time_ms = start_time.elapsed().as_millis() as u64;
+        self.metrics.elements_laid_out = layout_tree.layout_boxes.len() as u32;
+        
+        // Report garbage geometry without failing the page
+        if self.validation_enabled {
+            if let Err(error) = self.validate_layout(&layout_tree) {
+                log::warn!("Layout validation failed: {}", error);
+                self.last_validation_error = Some(error);
+            }
+        }
         
         Ok(layout_tree)
     }
     
+    /// Check a computed layout for invalid style inputs, non-finite or negative geometry, and
+    /// in-flow children placed before their parent's content box. Overflow past the far edges,
+    /// negative margins, and positioned boxes are legitimate and not reported.
+    pub fn validate_layout(&self, layout_tree: &LayoutTree) -> std::result::Result<(), LayoutError> {
+        let mut issues = self.style_issues.clone();
+        
+        // Sort for a deterministic report regardless of map order
+        let mut boxes: Vec<&LayoutBox> = layout_tree.layout_boxes.values().collect();
+        boxes.sort_by_key(|b| b.element_id.0);
+        
+        for layout_box in boxes {
+            let element_id = layout_box.element_id;
+            let rects = [
+                ("content", &layout_box.content_rect),
+                ("padding", &layout_box.padding_rect),
+                ("border", &layout_box.border_rect),
+                ("margin", &layout_box.margin_rect),
+            ];
+            
+            let mut finite = true;
+            for (name, rect) in rects {
+                let values = [rect.origin.x, rect.origin.y, rect.size.width, rect.size.height];
+                if values.iter().any(|v| !v.is_finite()) {
+                    finite = false;
+                    issues.push(LayoutIssue::NonFiniteGeometry { element_id, rect: name });
+                } else if name != "margin" && (rect.size.width < 0.0 || rect.size.height < 0.0) {
+                    // Negative margins can shrink the margin box below zero
+                    issues.push(LayoutIssue::NegativeSize {
+                        element_id,
+                        rect: name,
+                        width: rect.size.width,
+                        height: rect.size.height,
+                    });
+                }
+            }
+            
+            if !finite || !layout_box.fragments.is_empty() {
+                continue;
+            }
+            let positioned = self.computed_styles.get(&element_id)
+                .is_some_and(|style| style.position != PositionType::Static);
+            let Some(parent_box) = layout_tree.parents.get(&element_id).and_then(|parent_id| layout_tree.layout_boxes.get(parent_id)) else {
+                continue;
+            };
+            // Flex and grid alignment may legitimately place items before the start edge
+            let aligned = self.computed_styles.get(&parent_box.element_id)
+                .is_some_and(|style| matches!(style.display, DisplayType::Flex | DisplayType::Grid));
+            if positioned || aligned {
+                continue;
+            }
+            
+            // Positions are relative to the parent, so the content box starts at the parent's
+            // padding and border; a negative margin may pull the child out by its own size
+            let start_x = parent_box.content_rect.origin.x - parent_box.border_rect.origin.x;
+            let start_y = parent_box.content_rect.origin.y - parent_box.border_rect.origin.y;
+            let margin_left = layout_box.border_rect.origin.x - layout_box.margin_rect.origin.x;
+            let margin_top = layout_box.border_rect.origin.y - layout_box.margin_rect.origin.y;
+            let outside = layout_box.content_rect.origin.x - margin_left.min(0.0) < start_x - GEOMETRY_EPSILON
+                || layout_box.content_rect.origin.y - margin_top.min(0.0) < start_y - GEOMETRY_EPSILON;
+            if outside && parent_box.content_rect.size.width.is_finite() && parent_box.content_rect.size.height.is_finite() {
+                issues.push(LayoutIssue::OutsideParent { element_id, parent_id: parent_box.element_id });
+            }
+        }
+        
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(LayoutError { issues })
+        }
+    }
+    
     /// Create Taffy layout tree from DOM
     async fn create_layout_tree(
         &mut self,
@@ -71,32 +284,123 @@ impl LayoutEngine {
     ) -> Result<Node> {
         // Start with the root element (html)
         let root_element = &document.root;
-        self.create_layout_node(root_element, document, stylesheets).await
+        let mut live_elements = HashSet::new();
+        let root_node = self.create_layout_node(root_element, document, stylesheets, &mut live_elements).await?;
+        
+        // Drop nodes for elements that are no longer in the document
+        let stale: Vec<ElementId> = self.element_to_node.keys()
+            .filter(|id| !live_elements.contains(id))
+            .copied()
+            .collect();
+        for element_id in stale {
+            if let Some(node) = self.element_to_node.remove(&element_id) {
+                self.node_to_element.remove(&node);
+                let _ = self.taffy.remove(node);
+            }
+            // Includes descendants of a newly hidden element, which must stop being hit
+            self.layout_cache.remove(&element_id);
+            self.computed_styles.remove(&element_id);
+            self.cascaded_styles.remove(&element_id);
+            self.animations.remove_element(element_id);
+        }
+        
+        Ok(root_node)
+    }
+    
+    /// Lay out the whole tree in the current viewport
+    fn compute_from_root(&mut self, root_node: Node) -> Result<()> {
+        self.taffy.compute_layout(
+            root_node,
+            taffy::Size {
+                width: taffy::AvailableSpace::Definite(self.viewport.width),
+                height: taffy::AvailableSpace::Definite(self.viewport.height),
+            },
+        ).map_err(|e| EngineError::RenderingError(format!("Layout computation failed: {:?}", e)))
     }
     
-    /// Create a layout node for an element and its children
+    /// Create or update the layout node for an element and its children.
+    /// Existing nodes are only touched (and so marked dirty) when their style or children change.
     async fn create_layout_node(
         &mut self,
         element: &Element,
         document: &Document,
         stylesheets: &[std::sync::Arc<crate::css::Stylesheet>],
+        live_elements: &mut HashSet<ElementId>,
     ) -> Result<Node> {
+        live_elements.insert(element.id);
+        
         // Compute style for this element
-        let css_engine = crate::css::CSSEngine::new();
-        let computed_style = css_engine.compute_style(element, stylesheets);
+        let parent_style = element.parent.and_then(|parent| self.computed_styles.get(&parent));
+        let cascaded = self.css_engine.compute_style_in(element, document, stylesheets, parent_style);
+        let now = Instant::now();
+        let computed_style = match self.cascaded_styles.insert(element.id, cascaded.clone()) {
+            Some(previous) => self.animations.style_changed(element.id, &previous, &cascaded, now),
+            None => cascaded,
+        };
+        let computed_style = self.css_engine.animate_element(element.id, &computed_style, stylesheets, &mut self.animations, now);
+        
+        if self.validation_enabled {
+            self.style_issues.extend(style_issues(element.id, &computed_style));
+        }
         
         // Convert CSS style to Taffy style
-        let taffy_style = self.css_to_taffy_style(&computed_style);
+        let mut taffy_style = self.css_to_taffy_style(&computed_style);
+        
+        // Quirks mode: the root fills the viewport and the body fills the root
+        if document.compat_mode == CompatMode::Quirks && computed_style.height.is_none() && computed_style.height_percent.is_none() {
+            match element.tag_name.as_str() {
+                "html" => taffy_style.size.height = Dimension::Percent(1.0),
+                "body" => taffy_style.min_size.height = Dimension::Percent(1.0),
+                _ => {}
+            }
+        }
+        
+        // Replaced content: the natural size fills in auto dimensions, and its aspect ratio
+        // derives the other dimension when only one is given
+        if let Some(intrinsic) = self.intrinsic_size_for(document, element) {
+            taffy_style.aspect_ratio = Some(intrinsic.width / intrinsic.height);
+            let auto_width = computed_style.width.is_none() && computed_style.width_percent.is_none();
+            let auto_height = computed_style.height.is_none() && computed_style.height_percent.is_none();
+            if auto_width && auto_height {
+                taffy_style.size.width = Dimension::Length(intrinsic.width);
+                taffy_style.size.height = Dimension::Length(intrinsic.height);
+            }
+        }
+        
+        let (ascent, descent) = self.font_metrics_for(&computed_style.font_family).scaled(computed_style.font_size);
+        self.text_metrics.insert(element.id, (ascent, descent, computed_style.font_size));
+        
+        if matches!(computed_style.display, DisplayType::Inline) {
+            self.inline_content.insert(element.id, InlineContent {
+                text: element.text_content.clone(),
+                font_size: computed_style.font_size,
+            });
+        }
+        // A display: none subtree generates no boxes, so its descendants get no nodes at all
+        let hidden = computed_style.display == DisplayType::None;
+        self.computed_styles.insert(element.id, computed_style);
         
         // Create child nodes
         let mut child_nodes = Vec::new();
-        for child_id in &element.children {
+        for child_id in element.children.iter().filter(|_| !hidden) {
             if let Some(child_element) = document.elements.get(child_id) {
-                let child_node = self.create_layout_node(child_element, document, stylesheets).await?;
+                let child_node = self.create_layout_node(child_element, document, stylesheets, live_elements).await?;
                 child_nodes.push(child_node);
             }
         }
         
+        if let Some(&node) = self.element_to_node.get(&element.id) {
+            if self.taffy.style(node).map_or(true, |style| *style != taffy_style) {
+                self.taffy.set_style(node, taffy_style)
+                    .map_err(|e| EngineError::RenderingError(format!("Failed to update style: {:?}", e)))?;
+            }
+            if self.taffy.children(node).map_or(true, |children| children != child_nodes) {
+                self.taffy.set_children(node, &child_nodes)
+                    .map_err(|e| EngineError::RenderingError(format!("Failed to update children: {:?}", e)))?;
+            }
+            return Ok(node);
+        }
+        
         // Create Taffy node
         let node = self.taffy.new_with_children(taffy_style, &child_nodes)
             .map_err(|e| EngineError::RenderingError(format!("Failed to create layout node: {:?}", e)))?;
@@ -137,15 +441,21 @@ impl LayoutEngine {
         if let Some(height) = computed_style.height {
             style.size.height = Dimension::Length(height);
         }
+        if let Some(fraction) = computed_style.width_percent {
+            style.size.width = Dimension::Percent(fraction);
+        }
+        if let Some(fraction) = computed_style.height_percent {
+            style.size.height = Dimension::Percent(fraction);
+        }
         
         // Margin
-        style.margin = self.box_values_to_taffy_rect(&computed_style.margin);
+        style.margin = self.box_values_to_taffy_rect(&computed_style.margin, &computed_style.margin_percent);
         
         // Padding
-        style.padding = self.box_values_to_taffy_rect(&computed_style.padding);
+        style.padding = self.box_values_to_taffy_rect(&computed_style.padding, &computed_style.padding_percent);
         
         // Border
-        style.border = self.box_values_to_taffy_rect(&computed_style.border_width);
+        style.border = self.box_values_to_taffy_rect(&computed_style.border_width, &BoxPercentages::default());
         
         // Flex properties (if display is flex)
         if matches!(computed_style.display, DisplayType::Flex) {
@@ -158,27 +468,33 @@ impl LayoutEngine {
         style
     }
     
-    /// Convert BoxValues to Taffy Rect
-    fn box_values_to_taffy_rect(&self, box_values: &BoxValues) -> TaffyRect<LengthPercentageAuto> {
+    /// Convert BoxValues to Taffy Rect, with percentage sides left for Taffy to resolve
+    fn box_values_to_taffy_rect(&self, box_values: &BoxValues, percentages: &BoxPercentages) -> TaffyRect<LengthPercentageAuto> {
+        let side = |length: f32, percent: Option<f32>| match percent {
+            Some(fraction) => LengthPercentageAuto::Percent(fraction),
+            None => LengthPercentageAuto::Length(length),
+        };
         TaffyRect {
-            left: LengthPercentageAuto::Length(box_values.left),
-            right: LengthPercentageAuto::Length(box_values.right),
-            top: LengthPercentageAuto::Length(box_values.top),
-            bottom: LengthPercentageAuto::Length(box_values.bottom),
+            left: side(box_values.left, percentages.left),
+            right: side(box_values.right, percentages.right),
+            top: side(box_values.top, percentages.top),
+            bottom: side(box_values.bottom, percentages.bottom),
         }
     }
     
     /// Extract layout results into our layout tree structure
     fn extract_layout_tree(&mut self, root_node: Node, document: &Document) -> Result<LayoutTree> {
         let mut layout_boxes = HashMap::new();
+        let mut parents = HashMap::new();
         
-        self.extract_layout_recursive(root_node, &mut layout_boxes)?;
+        self.extract_layout_recursive(root_node, &mut layout_boxes, &mut parents)?;
         
         let root_element_id = self.node_to_element[&root_node];
         
         Ok(LayoutTree {
             root_element_id,
             layout_boxes,
+            parents,
         })
     }
     
@@ -187,15 +503,63 @@ impl LayoutEngine {
         &mut self,
         node: Node,
         layout_boxes: &mut HashMap<ElementId, LayoutBox>,
+        parents: &mut HashMap<ElementId, ElementId>,
     ) -> Result<()> {
-        let element_id = self.node_to_element[&node];
+        let Some(&element_id) = self.node_to_element.get(&node) else {
+            return Ok(());
+        };
+        
+        // Hidden elements keep a Taffy node (so toggling display is cheap) but get no box
+        if self.computed_styles.get(&element_id).is_some_and(|style| style.display == DisplayType::None) {
+            self.layout_cache.remove(&element_id);
+            return Ok(());
+        }
         
         // Get layout from Taffy
         let layout = self.taffy.layout(node)
             .map_err(|e| EngineError::RenderingError(format!("Failed to get layout: {:?}", e)))?;
         
-        // Convert to our layout box format
-        let layout_box = LayoutBox {
+        let layout_box = self.layout_box_from(element_id, layout);
+        
+        // Process children
+        let children = self.taffy.children(node)
+            .map_err(|e| EngineError::RenderingError(format!("Failed to get children: {:?}", e)))?;
+        let inline_fragments = self.flow_inline_children(layout, &children);
+        
+        self.layout_cache.insert(element_id, layout_box.clone());
+        layout_boxes.insert(element_id, layout_box);
+        
+        for child_node in children {
+            self.extract_layout_recursive(child_node, layout_boxes, parents)?;
+            let Some(&child_id) = self.node_to_element.get(&child_node) else { continue };
+            if layout_boxes.contains_key(&child_id) {
+                parents.insert(child_id, element_id);
+            }
+        }
+        
+        // Inline children take their geometry from line fragments rather than Taffy's block box
+        for (child_id, fragments) in inline_fragments {
+            if let Some(child_box) = layout_boxes.get_mut(&child_id) {
+                child_box.set_fragments(fragments);
+                self.layout_cache.insert(child_id, child_box.clone());
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Convert a Taffy layout into our layout box format.
+    /// The baseline sits one ascent below the top of the first line box, centered by half-leading.
+    fn layout_box_from(&self, element_id: ElementId, layout: &taffy::layout::Layout) -> LayoutBox {
+        let (ascent, descent, font_size) = self.text_metrics.get(&element_id)
+            .copied()
+            .unwrap_or_else(|| {
+                let (ascent, descent) = FontMetrics::default().scaled(DEFAULT_FONT_SIZE);
+                (ascent, descent, DEFAULT_FONT_SIZE)
+            });
+        let half_leading = (font_size * LINE_HEIGHT - ascent - descent) / 2.0;
+        
+        LayoutBox {
             element_id,
             content_rect: Rect::new(
                 layout.location.x,
@@ -221,29 +585,37 @@ impl LayoutEngine {
                 layout.size.width + layout.padding.left + layout.padding.right + layout.border.left + layout.border.right + layout.margin.left + layout.margin.right,
                 layout.size.height + layout.padding.top + layout.padding.bottom + layout.border.top + layout.border.bottom + layout.margin.top + layout.margin.bottom,
             ),
-            baseline: layout.location.y + layout.size.height, // Simplified baseline calculation
-        };
-        
-        layout_boxes.insert(element_id, layout_box);
-        self.layout_cache.insert(element_id, layout_box.clone());
-        
-        // Process children
-        let children = self.taffy.children(node)
-            .map_err(|e| EngineError::RenderingError(format!("Failed to get children: {:?}", e)))?;
-        
-        for child_node in children {
-            self.extract_layout_recursive(child_node, layout_boxes)?;
+            baseline: layout.location.y + half_leading + ascent,
+            ascent,
+            descent,
+            fragments: Vec::new(),
         }
-        
-        Ok(())
     }
     
-    /// Clear all layout data
+    /// Clear per-layout results, keeping the Taffy tree and its element mappings
     fn clear_layout(&mut self) {
-        self.taffy.clear();
-        self.element_to_node.clear();
-        self.node_to_element.clear();
         self.layout_cache.clear();
+        self.inline_content.clear();
+        self.text_metrics.clear();
+        self.computed_styles.clear();
+        self.style_issues.clear();
+        self.last_validation_error = None;
+    }
+    
+    /// Computed styles from the last layout pass, keyed by element
+    pub fn computed_styles(&self) -> &HashMap<ElementId, ComputedStyle> {
+        &self.computed_styles
+    }
+    
+    /// Advance animations to `now`, updating computed styles in place.
+    /// Returns the elements to repaint; geometry isn't recomputed.
+    pub fn tick_animations(&mut self, now: Instant) -> Vec<ElementId> {
+        self.animations.tick(now, &mut self.computed_styles)
+    }
+    
+    /// Whether any animation or transition still needs frames
+    pub fn has_running_animations(&self) -> bool {
+        self.animations.has_running_animations()
     }
     
     /// Get layout box for an element
@@ -251,32 +623,33 @@ impl LayoutEngine {
         self.layout_cache.get(&element_id)
     }
     
-    /// Update layout for a specific element (for dynamic changes)
+    /// Drop cached layout for elements changed by DOM mutations
+    pub fn invalidate_elements(&mut self, element_ids: &std::collections::HashSet<ElementId>) {
+        self.layout_cache.retain(|id, _| !element_ids.contains(id));
+        self.metrics.layout_invalidations += element_ids.len() as u32;
+    }
+    
+    /// Update layout for a specific element (for dynamic changes).
+    /// Only the element and its ancestors are marked dirty; Taffy reuses cached layout for the rest.
     pub async fn update_element_layout(
         &mut self,
         element_id: ElementId,
         new_style: &ComputedStyle,
     ) -> Result<()> {
-        if let Some(&node) = self.element_to_node.get(&element_id) {
-            let taffy_style = self.css_to_taffy_style(new_style);
-            self.taffy.set_style(node, taffy_style)
-                .map_err(|e| EngineError::RenderingError(format!("Failed to update style: {:?}", e)))?;
-            
-            // Recompute layout for this subtree
-            let available_space = Size::new(1920.0, 1080.0); // Should get from viewport
-            self.taffy.compute_layout(
-                node,
-                taffy::Size {
-                    width: taffy::AvailableSpace::Definite(available_space.width),
-                    height: taffy::AvailableSpace::Definite(available_space.height),
-                },
-            ).map_err(|e| EngineError::RenderingError(format!("Layout recomputation failed: {:?}", e)))?;
-            
-            // Update cache
-            self.update_layout_cache(node)?;
-        }
+        let (Some(&node), Some(root_node)) = (self.element_to_node.get(&element_id), self.root_node) else {
+            return Ok(());
+        };
+        self.metrics.layout_invalidations += 1;
         
-        Ok(())
+        let taffy_style = self.css_to_taffy_style(new_style);
+        self.taffy.set_style(node, taffy_style)
+            .map_err(|e| EngineError::RenderingError(format!("Failed to update style: {:?}", e)))?;
+        
+        // Lay out from the root so siblings and ancestors see the change in context
+        self.compute_from_root(root_node)?;
+        
+        // Update cache
+        self.update_layout_cache(root_node)
     }
     
     /// Update layout cache after recomputation
@@ -286,72 +659,237 @@ impl LayoutEngine {
         let layout = self.taffy.layout(node)
             .map_err(|e| EngineError::RenderingError(format!("Failed to get layout: {:?}", e)))?;
         
-        let layout_box = LayoutBox {
-            element_id,
-            content_rect: Rect::new(
-                layout.location.x,
-                layout.location.y,
-                layout.size.width,
-                layout.size.height,
-            ),
-            padding_rect: Rect::new(
-                layout.location.x - layout.padding.left,
-                layout.location.y - layout.padding.top,
-                layout.size.width + layout.padding.left + layout.padding.right,
-                layout.size.height + layout.padding.top + layout.padding.bottom,
-            ),
-            border_rect: Rect::new(
-                layout.location.x - layout.padding.left - layout.border.left,
-                layout.location.y - layout.padding.top - layout.border.top,
-                layout.size.width + layout.padding.left + layout.padding.right + layout.border.left + layout.border.right,
-                layout.size.height + layout.padding.top + layout.padding.bottom + layout.border.top + layout.border.bottom,
-            ),
-            margin_rect: Rect::new(
-                layout.location.x - layout.padding.left - layout.border.left - layout.margin.left,
-                layout.location.y - layout.padding.top - layout.border.top - layout.margin.top,
-                layout.size.width + layout.padding.left + layout.padding.right + layout.border.left + layout.border.right + layout.margin.left + layout.margin.right,
-                layout.size.height + layout.padding.top + layout.padding.bottom + layout.border.top + layout.border.bottom + layout.margin.top + layout.margin.bottom,
-            ),
-            baseline: layout.location.y + layout.size.height,
-        };
-        
-        self.layout_cache.insert(element_id, layout_box);
+        let layout_box = self.layout_box_from(element_id, layout);
         
         // Update children
         let children = self.taffy.children(node)
             .map_err(|e| EngineError::RenderingError(format!("Failed to get children: {:?}", e)))?;
+        let inline_fragments = self.flow_inline_children(layout, &children);
+        
+        self.layout_cache.insert(element_id, layout_box);
         
         for child_node in children {
             self.update_layout_cache(child_node)?;
         }
         
+        for (child_id, fragments) in inline_fragments {
+            if let Some(child_box) = self.layout_cache.get_mut(&child_id) {
+                child_box.set_fragments(fragments);
+            }
+        }
+        
         Ok(())
     }
     
-    /// Perform hit testing to find element at point
+    /// Break inline children into per-line fragments, wrapping words at the parent's content width.
+    /// A block sibling ends the current line and inline content after it continues below it.
+    /// Fragments share the coordinate space of the children's block boxes.
+    fn flow_inline_children(&self, layout: &taffy::layout::Layout, children: &[Node]) -> HashMap<ElementId, Vec<Rect>> {
+        let origin_x = layout.border.left + layout.padding.left;
+        let available_width = (layout.size.width
+            - layout.border.left - layout.border.right
+            - layout.padding.left - layout.padding.right).max(0.0);
+        
+        let mut fragments = HashMap::new();
+        let mut line_top = layout.border.top + layout.padding.top;
+        let mut line_height: f32 = 0.0;
+        let mut x = 0.0;
+        
+        for child in children {
+            let element_id = self.node_to_element.get(child).copied();
+            let hidden = element_id.and_then(|id| self.computed_styles.get(&id))
+                .is_some_and(|style| style.display == DisplayType::None);
+            if hidden {
+                continue;
+            }
+            let Some((element_id, content)) = element_id.and_then(|id| Some((id, self.inline_content.get(&id)?))) else {
+                // Block-level (or anonymous) box: start a fresh line below it
+                if let Ok(block) = self.taffy.layout(*child) {
+                    if x > 0.0 {
+                        line_top += line_height;
+                    }
+                    line_top = line_top.max(block.location.y + block.size.height);
+                    line_height = 0.0;
+                    x = 0.0;
+                }
+                continue;
+            };
+            
+            let advance = content.font_size * AVERAGE_ADVANCE;
+            let height = content.font_size * LINE_HEIGHT;
+            let mut rects = Vec::new();
+            let mut run_start: Option<f32> = None;
+            line_height = line_height.max(height);
+            
+            for word in content.text.split_whitespace() {
+                let width = word.chars().count() as f32 * advance;
+                let mut gap = if x > 0.0 { advance } else { 0.0 };
+                
+                // Wrap unless the word already starts the line
+                if x > 0.0 && x + gap + width > available_width {
+                    if let Some(start) = run_start.take() {
+                        rects.push(Rect::new(origin_x + start, line_top, x - start, height));
+                    }
+                    line_top += line_height;
+                    line_height = height;
+                    x = 0.0;
+                    gap = 0.0;
+                }
+                
+                run_start.get_or_insert(x + gap);
+                x += gap + width;
+            }
+            
+            if let Some(start) = run_start {
+                rects.push(Rect::new(origin_x + start, line_top, x - start, height));
+            }
+            fragments.insert(element_id, rects);
+        }
+        
+        fragments
+    }
+    
+    /// Perform hit testing to find the element at a point in viewport coordinates.
+    /// Boxes are moved by their scroll containers' offsets and only hit within the visible
+    /// part of ancestors that clip their overflow.
     pub fn hit_test(&self, point: Point) -> Option<ElementId> {
-        // Find the topmost element that contains the point
-        let mut hit_element = None;
-        let mut highest_z_index = i32::MIN;
+        // Descendants paint over their ancestors, so the deepest box under the point is on top.
+        // z-index and stacking contexts are not considered yet.
+        self.layout_cache.iter()
+            .filter(|(element_id, layout_box)| self.is_visible_at(**element_id, &layout_box.border_rect, point))
+            .max_by_key(|(element_id, _)| self.box_ancestors(**element_id).count())
+            .map(|(element_id, _)| *element_id)
+    }
+    
+    /// Whether `rect` of an element covers `point` once scrolled, and isn't clipped away there
+    fn is_visible_at(&self, element_id: ElementId, rect: &Rect, point: Point) -> bool {
+        let (scroll_x, scroll_y) = self.scroll_offset(element_id);
+        if !rect.translate(-scroll_x, -scroll_y).contains_point(point) {
+            return false;
+        }
         
-        for (element_id, layout_box) in &self.layout_cache {
-            if layout_box.border_rect.contains_point(point) {
-                // For now, just return the first hit
-                // In a real implementation, we'd consider z-index, stacking context, etc.
-                hit_element = Some(*element_id);
-                break;
+        self.box_ancestors(element_id)
+            .filter(|ancestor| self.computed_styles.get(ancestor).is_some_and(|style| style.overflow.clips()))
+            .all(|ancestor| match self.layout_cache.get(&ancestor) {
+                Some(ancestor_box) => {
+                    let (scroll_x, scroll_y) = self.scroll_offset(ancestor);
+                    ancestor_box.padding_rect.translate(-scroll_x, -scroll_y).contains_point(point)
+                }
+                None => true,
+            })
+    }
+    
+    fn box_ancestors(&self, element_id: ElementId) -> impl Iterator<Item = ElementId> + '_ {
+        std::iter::successors(self.box_parents.get(&element_id).copied(), move |id| self.box_parents.get(id).copied())
+    }
+    
+    /// How far an element's box has been moved up and left by the scroll containers around it
+    fn scroll_offset(&self, element_id: ElementId) -> (f32, f32) {
+        self.box_ancestors(element_id)
+            .filter_map(|ancestor| self.scroll_states.get(&ancestor))
+            .fold((0.0, 0.0), |(x, y), state| (x + state.scroll_x, y + state.scroll_y))
+    }
+    
+    /// Track the scrollable extent of the root and every scroll container in a new layout,
+    /// keeping existing offsets as far as the new content allows
+    fn update_scroll_states(&mut self, layout_tree: &LayoutTree) {
+        self.box_parents = layout_tree.parents.clone();
+        
+        // Far right and bottom edges of each element's descendants
+        let mut extents: HashMap<ElementId, (f32, f32)> = HashMap::new();
+        for (element_id, layout_box) in &layout_tree.layout_boxes {
+            let rect = &layout_box.margin_rect;
+            for ancestor in layout_tree.ancestors(*element_id) {
+                let extent = extents.entry(ancestor).or_insert((f32::MIN, f32::MIN));
+                extent.0 = extent.0.max(rect.origin.x + rect.size.width);
+                extent.1 = extent.1.max(rect.origin.y + rect.size.height);
             }
         }
         
-        hit_element
+        let mut scroll_states = HashMap::new();
+        for (element_id, layout_box) in &layout_tree.layout_boxes {
+            let frame = if *element_id == layout_tree.root_element_id {
+                Rect::new(0.0, 0.0, self.viewport.width, self.viewport.height)
+            } else if self.computed_styles.get(element_id).is_some_and(|style| style.overflow.scrolls()) {
+                layout_box.padding_rect
+            } else {
+                continue;
+            };
+            
+            let (right, bottom) = extents.get(element_id).copied().unwrap_or((f32::MIN, f32::MIN));
+            let mut state = ScrollState {
+                scroll_x: 0.0,
+                scroll_y: 0.0,
+                content_size: CoreSize::new(
+                    right.max(frame.origin.x + frame.size.width) - frame.origin.x,
+                    bottom.max(frame.origin.y + frame.size.height) - frame.origin.y,
+                ),
+                viewport_size: frame.size,
+            };
+            if let Some(previous) = self.scroll_states.get(element_id) {
+                state.set_offset(previous.scroll_x, previous.scroll_y);
+            }
+            scroll_states.insert(*element_id, state);
+        }
+        self.scroll_states = scroll_states;
+    }
+    
+    /// Scroll state of the root or a scroll container
+    pub fn scroll_state(&self, element_id: ElementId) -> Option<&ScrollState> {
+        self.scroll_states.get(&element_id)
+    }
+    
+    /// Scroll a container to an offset, clamped to its content, returning the new state
+    pub fn scroll_to(&mut self, element_id: ElementId, x: f32, y: f32) -> Result<ScrollState> {
+        let state = self.scroll_states.get_mut(&element_id)
+            .ok_or_else(|| EngineError::DomError(format!("Element {} is not a scroll container", element_id.0)))?;
+        state.set_offset(x, y);
+        Ok(state.clone())
+    }
+    
+    /// Scroll a container by a delta, clamped to its content, returning the new state
+    pub fn scroll_by(&mut self, element_id: ElementId, dx: f32, dy: f32) -> Result<ScrollState> {
+        let (x, y) = self.scroll_states.get(&element_id)
+            .map(|state| (state.scroll_x + dx, state.scroll_y + dy))
+            .ok_or_else(|| EngineError::DomError(format!("Element {} is not a scroll container", element_id.0)))?;
+        self.scroll_to(element_id, x, y)
+    }
+}
+
+/// Scroll position of a scroll container and the extent it can scroll over
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScrollState {
+    pub scroll_x: f32,
+    pub scroll_y: f32,
+    /// Size of everything inside the container, at least its viewport
+    pub content_size: CoreSize,
+    /// Visible area: the container's padding box, or the viewport for the root
+    pub viewport_size: CoreSize,
+}
+
+impl ScrollState {
+    pub fn max_scroll_x(&self) -> f32 {
+        (self.content_size.width - self.viewport_size.width).max(0.0)
+    }
+    
+    pub fn max_scroll_y(&self) -> f32 {
+        (self.content_size.height - self.viewport_size.height).max(0.0)
+    }
+    
+    /// Move to an offset, clamped so the viewport stays within the content
+    fn set_offset(&mut self, x: f32, y: f32) {
+        self.scroll_x = x.clamp(0.0, self.max_scroll_x());
+        self.scroll_y = y.clamp(0.0, self.max_scroll_y());
     }
 }
 
 /// Complete layout tree for a document
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct LayoutTree {
     pub root_element_id: ElementId,
     pub layout_boxes: HashMap<ElementId, LayoutBox>,
+    /// Parent of each box other than the root
+    pub parents: HashMap<ElementId, ElementId>,
 }
 
 impl LayoutTree {
@@ -360,11 +898,84 @@ impl LayoutTree {
         self.layout_boxes.get(&element_id)
     }
     
+    /// Get an element's client rects: one per line fragment for inline boxes, else its border box
+    pub fn get_client_rects(&self, element_id: ElementId) -> Vec<Rect> {
+        self.layout_boxes.get(&element_id)
+            .map(|layout_box| layout_box.client_rects())
+            .unwrap_or_default()
+    }
+    
+    /// Get the element whose box contains this one
+    pub fn parent_of(&self, element_id: ElementId) -> Option<ElementId> {
+        self.parents.get(&element_id).copied()
+    }
+    
+    /// Iterate from an element's parent up to the root
+    pub fn ancestors(&self, element_id: ElementId) -> impl Iterator<Item = ElementId> + '_ {
+        std::iter::successors(self.parent_of(element_id), move |id| self.parent_of(*id))
+    }
+    
+    /// Where an element's coordinate space sits in the page: boxes are positioned relative
+    /// to their parent, so this is the sum of its ancestors' positions
+    pub fn parent_offset(&self, element_id: ElementId) -> Point {
+        self.ancestors(element_id)
+            .filter_map(|ancestor| self.layout_boxes.get(&ancestor))
+            .fold(Point::zero(), |offset, ancestor_box| {
+                Point::new(offset.x + ancestor_box.content_rect.origin.x, offset.y + ancestor_box.content_rect.origin.y)
+            })
+    }
+    
     /// Get all layout boxes
     pub fn get_all_layout_boxes(&self) -> &HashMap<ElementId, LayoutBox> {
         &self.layout_boxes
     }
     
+    /// Serialize the boxes in tree order, with tag names, for debugging and snapshot tests.
+    /// With `stable_ids`, element ids are replaced by their tree-order index so output is deterministic.
+    pub fn to_json_value(&self, document: &Document, stable_ids: bool) -> serde_json::Value {
+        let mut next_index = 0;
+        document.elements.get(&self.root_element_id)
+            .map(|root| self.box_to_json(root, document, stable_ids, &mut next_index))
+            .unwrap_or(serde_json::Value::Null)
+    }
+    
+    /// Pretty-printed `to_json_value`
+    pub fn to_json_pretty(&self, document: &Document, stable_ids: bool) -> Result<String> {
+        serde_json::to_string_pretty(&self.to_json_value(document, stable_ids))
+            .map_err(|e| EngineError::InternalError(format!("Failed to serialize layout tree: {}", e)))
+    }
+    
+    fn box_to_json(&self, element: &Element, document: &Document, stable_ids: bool, next_index: &mut usize) -> serde_json::Value {
+        let id = if stable_ids {
+            serde_json::json!(*next_index)
+        } else {
+            serde_json::json!(element.id.0.to_string())
+        };
+        *next_index += 1;
+        
+        let rect = |rect: &Rect| serde_json::json!([rect.origin.x, rect.origin.y, rect.size.width, rect.size.height]);
+        let mut node = serde_json::json!({ "id": id, "tag": element.tag_name });
+        if let Some(layout_box) = self.layout_boxes.get(&element.id) {
+            node["content"] = rect(&layout_box.content_rect);
+            node["padding"] = rect(&layout_box.padding_rect);
+            node["border"] = rect(&layout_box.border_rect);
+            node["margin"] = rect(&layout_box.margin_rect);
+            node["baseline"] = serde_json::json!(layout_box.baseline);
+            if !layout_box.fragments.is_empty() {
+                node["fragments"] = layout_box.fragments.iter().map(rect).collect();
+            }
+        }
+        
+        let children: Vec<serde_json::Value> = element.children.iter()
+            .filter_map(|child_id| document.elements.get(child_id))
+            .map(|child| self.box_to_json(child, document, stable_ids, next_index))
+            .collect();
+        if !children.is_empty() {
+            node["children"] = serde_json::Value::Array(children);
+        }
+        node
+    }
+    
     /// Find element at point
     pub fn element_at_point(&self, point: Point) -> Option<ElementId> {
         // Find the topmost element that contains the point
@@ -378,7 +989,7 @@ impl LayoutTree {
 }
 
 /// Layout information for a single element
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct LayoutBox {
     pub element_id: ElementId,
     
@@ -394,11 +1005,49 @@ pub struct LayoutBox {
     /// Margin area (full element bounds)
     pub margin_rect: Rect,
     
-    /// Baseline for text alignment
+    /// Baseline of the first line, for `vertical-align: baseline`
     pub baseline: f32,
+    
+    /// Font ascent and descent at the element's font size
+    pub ascent: f32,
+    pub descent: f32,
+    
+    /// Per-line fragments of an inline box; empty for block-level boxes
+    pub fragments: Vec<Rect>,
 }
 
 impl LayoutBox {
+    /// Rects for `getClientRects()`: each line fragment, or the border box if there are none
+    pub fn client_rects(&self) -> Vec<Rect> {
+        if self.fragments.is_empty() {
+            vec![self.border_rect]
+        } else {
+            self.fragments.clone()
+        }
+    }
+    
+    /// Replace this box's geometry with line fragments; the other rects become their bounding box
+    pub fn set_fragments(&mut self, fragments: Vec<Rect>) {
+        if let Some(first) = fragments.first() {
+            let mut min = first.origin;
+            let mut max = Point::new(first.origin.x + first.size.width, first.origin.y + first.size.height);
+            for fragment in &fragments[1..] {
+                min.x = min.x.min(fragment.origin.x);
+                min.y = min.y.min(fragment.origin.y);
+                max.x = max.x.max(fragment.origin.x + fragment.size.width);
+                max.y = max.y.max(fragment.origin.y + fragment.size.height);
+            }
+            let bounds = Rect::new(min.x, min.y, max.x - min.x, max.y - min.y);
+            self.content_rect = bounds;
+            self.padding_rect = bounds;
+            self.border_rect = bounds;
+            self.margin_rect = bounds;
+            let half_leading = (first.size.height - self.ascent - self.descent) / 2.0;
+            self.baseline = first.origin.y + half_leading + self.ascent;
+        }
+        self.fragments = fragments;
+    }
+    
     /// Get the visible bounds of the element
     pub fn visible_rect(&self) -> Rect {
         self.border_rect
@@ -424,6 +1073,143 @@ impl LayoutBox {
     }
 }
 
+/// Approximate glyph advance as a fraction of the font size, used until text is shaped
+const AVERAGE_ADVANCE: f32 = 0.5;
+
+/// Line box height as a multiple of the font size (CSS `line-height: normal`)
+const LINE_HEIGHT: f32 = 1.2;
+
+/// Font size assumed for boxes laid out without a computed style
+const DEFAULT_FONT_SIZE: f32 = 16.0;
+
+/// Vertical font metrics as fractions of the em size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontMetrics {
+    pub ascent: f32,
+    pub descent: f32,
+}
+
+impl FontMetrics {
+    /// Ascent and descent in pixels at `font_size`
+    pub fn scaled(&self, font_size: f32) -> (f32, f32) {
+        (self.ascent * font_size, self.descent * font_size)
+    }
+}
+
+impl Default for FontMetrics {
+    /// Typical proportions of a sans-serif face, used until real metrics are registered
+    fn default() -> Self {
+        Self { ascent: 0.8, descent: 0.2 }
+    }
+}
+
+/// Text and font size of an inline-level element
+struct InlineContent {
+    text: String,
+    font_size: f32,
+}
+
+/// Tolerance for floating-point rounding in containment checks
+const GEOMETRY_EPSILON: f32 = 0.5;
+
+/// Style values that can never produce valid geometry
+fn style_issues(element_id: ElementId, style: &ComputedStyle) -> Vec<LayoutIssue> {
+    let mut lengths = vec![("width", style.width), ("height", style.height)];
+    for (name, values) in [("padding", &style.padding), ("border-width", &style.border_width)] {
+        for value in [values.top, values.right, values.bottom, values.left] {
+            lengths.push((name, Some(value)));
+        }
+    }
+    
+    let mut issues: Vec<LayoutIssue> = Vec::new();
+    for (property, value) in lengths {
+        let Some(value) = value else { continue };
+        let invalid = !value.is_finite() || value < 0.0;
+        let already_reported = issues.iter().any(|issue| {
+            matches!(issue, LayoutIssue::InvalidStyle { property: p, .. } if *p == property)
+        });
+        if invalid && !already_reported {
+            issues.push(LayoutIssue::InvalidStyle { element_id, property, value });
+        }
+    }
+    issues
+}
+
+/// A single problem found by layout validation
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutIssue {
+    /// A length that must be finite and non-negative was not
+    InvalidStyle { element_id: ElementId, property: &'static str, value: f32 },
+    /// A rect contains NaN or infinite coordinates
+    NonFiniteGeometry { element_id: ElementId, rect: &'static str },
+    /// A rect has negative width or height
+    NegativeSize { element_id: ElementId, rect: &'static str, width: f32, height: f32 },
+    /// An in-flow box starts before its parent's content box
+    OutsideParent { element_id: ElementId, parent_id: ElementId },
+}
+
+impl LayoutIssue {
+    /// The element the issue was found on
+    pub fn element_id(&self) -> ElementId {
+        match self {
+            LayoutIssue::InvalidStyle { element_id, .. }
+            | LayoutIssue::NonFiniteGeometry { element_id, .. }
+            | LayoutIssue::NegativeSize { element_id, .. }
+            | LayoutIssue::OutsideParent { element_id, .. } => *element_id,
+        }
+    }
+}
+
+impl fmt::Display for LayoutIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutIssue::InvalidStyle { element_id, property, value } => {
+                write!(f, "{:?}: invalid {} {}", element_id, property, value)
+            }
+            LayoutIssue::NonFiniteGeometry { element_id, rect } => {
+                write!(f, "{:?}: non-finite {} rect", element_id, rect)
+            }
+            LayoutIssue::NegativeSize { element_id, rect, width, height } => {
+                write!(f, "{:?}: negative {} size {}x{}", element_id, rect, width, height)
+            }
+            LayoutIssue::OutsideParent { element_id, parent_id } => {
+                write!(f, "{:?}: positioned outside parent {:?}", element_id, parent_id)
+            }
+        }
+    }
+}
+
+/// Layout validation failure listing every offending element
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutError {
+    pub issues: Vec<LayoutIssue>,
+}
+
+impl LayoutError {
+    /// Elements with at least one issue, without duplicates
+    pub fn offending_elements(&self) -> Vec<ElementId> {
+        let mut elements = Vec::new();
+        for issue in &self.issues {
+            if !elements.contains(&issue.element_id()) {
+                elements.push(issue.element_id());
+            }
+        }
+        elements
+    }
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Layout validation failed with {} issue(s)", self.issues.len())?;
+        for issue in &self.issues {
+            write!(f, "; {}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
 /// Layout constraints for responsive design
 #[derive(Debug, Clone)]
 pub struct LayoutConstraints {
@@ -456,6 +1242,24 @@ pub struct LayoutMetrics {
     pub cache_misses: u32,
 }
 
+#[async_trait::async_trait]
+impl MetricsReporter for LayoutEngine {
+    async fn get_metrics(&self) -> PerformanceMetrics {
+        PerformanceMetrics {
+            layout_time_ms: self.metrics.layout_time_ms,
+            ..PerformanceMetrics::default()
+        }
+    }
+}
+
+/// Layout state lives in memory and is dropped with the engine
+#[async_trait::async_trait]
+impl Shutdown for LayoutEngine {
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -492,6 +1296,9 @@ mod tests {
             border_rect: Rect::new(0.0, 0.0, 120.0, 70.0),
             margin_rect: Rect::new(-10.0, -10.0, 140.0, 90.0),
             baseline: 60.0,
+            ascent: 12.8,
+            descent: 3.2,
+            fragments: Vec::new(),
         };
         
         let center = layout_box.center();
@@ -502,6 +1309,109 @@ mod tests {
         assert_eq!(visible, layout_box.border_rect);
     }
     
+    #[tokio::test]
+    async fn test_layout_validation_reports_negative_width() {
+        let parser = crate::html::HTMLParser::new();
+        let document = parser.parse(
+            r#"<html><body><div id="bad" style="width: -50px">Broken</div></body></html>"#
+        ).unwrap();
+        let bad_id = document.get_element_by_id("bad").unwrap().id;
+        
+        let mut engine = LayoutEngine::new();
+        engine.set_validation_enabled(true);
+        
+        // The problem is recorded rather than failing the layout
+        let result = engine.compute_layout(&document, &[]).await;
+        assert!(result.is_ok());
+        
+        let error = engine.last_validation_error().unwrap();
+        assert!(error.offending_elements().contains(&bad_id));
+        assert!(error.issues.contains(&LayoutIssue::InvalidStyle {
+            element_id: bad_id,
+            property: "width",
+            value: -50.0,
+        }));
+    }
+    
+    #[tokio::test]
+    async fn test_layout_validation_allows_overflow_and_negative_margins() {
+        let parser = crate::html::HTMLParser::new();
+        let document = parser.parse(
+            r#"<html><body><div style="position: relative; width: 100px; height: 20px; padding: 5px">
+                <p style="width: 300px; height: 80px; margin: -10px -40px"></p>
+                <span style="position: absolute; left: -60px; top: -60px">Badge</span>
+            </div></body></html>"#
+        ).unwrap();
+        
+        let mut engine = LayoutEngine::new();
+        engine.set_validation_enabled(true);
+        assert!(engine.compute_layout(&document, &[]).await.is_ok());
+        assert!(engine.last_validation_error().is_none());
+    }
+    
+    #[test]
+    fn test_layout_validation_geometry() {
+        let engine = LayoutEngine::new();
+        let element_id = ElementId::new();
+        
+        let mut layout_boxes = HashMap::new();
+        layout_boxes.insert(element_id, LayoutBox {
+            element_id,
+            content_rect: Rect::new(0.0, 0.0, f32::NAN, 10.0),
+            padding_rect: Rect::new(0.0, 0.0, -5.0, 10.0),
+            border_rect: Rect::new(0.0, 0.0, 10.0, 10.0),
+            // Negative margins wider than the box are fine
+            margin_rect: Rect::new(20.0, 0.0, -10.0, 10.0),
+            baseline: 10.0,
+            ascent: 12.8,
+            descent: 3.2,
+            fragments: Vec::new(),
+        });
+        let layout_tree = LayoutTree { root_element_id: element_id, layout_boxes, parents: HashMap::new() };
+        
+        let error = engine.validate_layout(&layout_tree).unwrap_err();
+        assert_eq!(error.issues.len(), 2);
+        assert_eq!(error.issues[0], LayoutIssue::NonFiniteGeometry { element_id, rect: "content" });
+        assert!(matches!(error.issues[1], LayoutIssue::NegativeSize { rect: "padding", .. }));
+    }
+    
+    #[test]
+    fn test_layout_validation_containment() {
+        let engine = LayoutEngine::new();
+        let parent_id = ElementId::new();
+        let layout_box = |element_id, content: Rect, margin: Rect| LayoutBox {
+            element_id,
+            content_rect: content,
+            padding_rect: content,
+            border_rect: content,
+            margin_rect: margin,
+            baseline: 0.0,
+            ascent: 12.8,
+            descent: 3.2,
+            fragments: Vec::new(),
+        };
+        
+        // The parent's content box starts 10px in, past its padding
+        let mut parent = layout_box(parent_id, Rect::new(10.0, 10.0, 100.0, 50.0), Rect::new(10.0, 10.0, 100.0, 50.0));
+        parent.border_rect = Rect::new(0.0, 0.0, 120.0, 70.0);
+        let (inside, overflowing, pulled, outside) = (ElementId::new(), ElementId::new(), ElementId::new(), ElementId::new());
+        let boxes = [
+            parent,
+            layout_box(inside, Rect::new(10.0, 10.0, 50.0, 20.0), Rect::new(10.0, 10.0, 50.0, 20.0)),
+            // Far past the parent's right and bottom edges
+            layout_box(overflowing, Rect::new(10.0, 40.0, 500.0, 300.0), Rect::new(10.0, 40.0, 500.0, 300.0)),
+            // A -30px left margin accounts for starting left of the content box
+            layout_box(pulled, Rect::new(-20.0, 10.0, 50.0, 20.0), Rect::new(10.0, 10.0, 20.0, 20.0)),
+            layout_box(outside, Rect::new(-20.0, 10.0, 50.0, 20.0), Rect::new(-20.0, 10.0, 50.0, 20.0)),
+        ];
+        let parents = [inside, overflowing, pulled, outside].into_iter().map(|id| (id, parent_id)).collect();
+        let layout_boxes = boxes.into_iter().map(|b| (b.element_id, b)).collect();
+        let layout_tree = LayoutTree { root_element_id: parent_id, layout_boxes, parents };
+        
+        let error = engine.validate_layout(&layout_tree).unwrap_err();
+        assert_eq!(error.issues, vec![LayoutIssue::OutsideParent { element_id: outside, parent_id }]);
+    }
+    
     #[test]
     fn test_hit_testing() {
         let mut engine = LayoutEngine::new();
@@ -514,6 +1424,9 @@ mod tests {
             border_rect: Rect::new(0.0, 0.0, 120.0, 70.0),
             margin_rect: Rect::new(-10.0, -10.0, 140.0, 90.0),
             baseline: 60.0,
+            ascent: 12.8,
+            descent: 3.2,
+            fragments: Vec::new(),
         };
         
         engine.layout_cache.insert(element_id, layout_box);
@@ -526,4 +1439,266 @@ mod tests {
         let miss = engine.hit_test(Point::new(200.0, 200.0));
         assert_eq!(miss, None);
     }
-}
\ No newline at end of file
+    
+    #[tokio::test]
+    async fn test_inline_element_client_rects_per_line() {
+        let parser = crate::html::HTMLParser::new();
+        let document = parser.parse(
+            r#"<html><body><p style="width: 200px"><a id="link" style="display: inline; font-size: 20px">one two three four five</a></p></body></html>"#
+        ).unwrap();
+        let link_id = document.get_element_by_id("link").unwrap().id;
+        
+        let mut engine = LayoutEngine::new();
+        let layout_tree = engine.compute_layout(&document, &[]).await.unwrap();
+        
+        // At 20px each glyph advances 10px: "one two three four" fills 180px, "five" wraps
+        let rects = layout_tree.get_client_rects(link_id);
+        assert_eq!(rects, vec![
+            Rect::new(0.0, 0.0, 180.0, 24.0),
+            Rect::new(0.0, 24.0, 40.0, 24.0),
+        ]);
+        
+        // The bounding box spans both lines
+        let link_box = layout_tree.get_layout_box(link_id).unwrap();
+        assert_eq!(link_box.border_rect, Rect::new(0.0, 0.0, 180.0, 48.0));
+        assert_eq!(engine.get_layout_box(link_id).unwrap().client_rects(), rects);
+    }
+    
+    #[tokio::test]
+    async fn test_inline_content_after_block_sibling_starts_below_it() {
+        let parser = crate::html::HTMLParser::new();
+        let document = parser.parse(
+            r#"<html><body><div style="width: 200px"><a id="before" style="display: inline; font-size: 20px">one two</a><div id="block" style="height: 30px"></div><a id="after" style="display: inline; font-size: 20px">three</a></div></body></html>"#
+        ).unwrap();
+        let before_id = document.get_element_by_id("before").unwrap().id;
+        let block_id = document.get_element_by_id("block").unwrap().id;
+        let after_id = document.get_element_by_id("after").unwrap().id;
+        
+        let mut engine = LayoutEngine::new();
+        let layout_tree = engine.compute_layout(&document, &[]).await.unwrap();
+        
+        assert_eq!(layout_tree.get_client_rects(before_id), vec![Rect::new(0.0, 0.0, 70.0, 24.0)]);
+        let block = layout_tree.get_layout_box(block_id).unwrap().border_rect;
+        let after = layout_tree.get_client_rects(after_id);
+        assert_eq!(after.len(), 1);
+        assert_eq!(after[0].origin.x, 0.0);
+        assert!(after[0].origin.y >= block.origin.y + block.size.height);
+    }
+    
+    #[tokio::test]
+    async fn test_percentages_resolve_against_auto_width_parent() {
+        let parser = crate::html::HTMLParser::new();
+        let document = parser.parse(
+            r#"<html><body style="margin: 0"><div><p id="half" style="width: 50%; margin: 0 10%; height: 10px"></p></div></body></html>"#
+        ).unwrap();
+        let half_id = document.get_element_by_id("half").unwrap().id;
+        
+        let mut engine = LayoutEngine::new();
+        let layout_tree = engine.compute_layout_with_viewport(&document, &[], CoreSize::new(800.0, 600.0)).await.unwrap();
+        
+        let content = layout_tree.get_layout_box(half_id).unwrap().content_rect;
+        assert_eq!(content.size.width, 400.0);
+        assert_eq!(content.origin.x, 80.0);
+    }
+    
+    #[tokio::test]
+    async fn test_quirks_mode_body_fills_viewport() {
+        let parser = crate::html::HTMLParser::new();
+        let viewport = CoreSize::new(800.0, 600.0);
+        
+        let quirks = parser.parse("<html><body></body></html>").unwrap();
+        let body_id = quirks.body.as_ref().unwrap().id;
+        let mut engine = LayoutEngine::new();
+        let layout_tree = engine.compute_layout_with_viewport(&quirks, &[], viewport).await.unwrap();
+        assert_eq!(layout_tree.get_layout_box(body_id).unwrap().content_rect.size.height, 600.0);
+        
+        let standards = parser.parse("<!DOCTYPE html><html><body></body></html>").unwrap();
+        let body_id = standards.body.as_ref().unwrap().id;
+        let layout_tree = engine.compute_layout_with_viewport(&standards, &[], viewport).await.unwrap();
+        assert_eq!(layout_tree.get_layout_box(body_id).unwrap().content_rect.size.height, 0.0);
+    }
+    
+    #[tokio::test]
+    async fn test_update_element_layout_reuses_nodes() {
+        let parser = crate::html::HTMLParser::new();
+        let document = parser.parse(
+            r#"<html><body><div id="a" style="width: 100px; height: 10px"></div><div id="b" style="width: 50px; height: 10px"></div></body></html>"#
+        ).unwrap();
+        let a_id = document.get_element_by_id("a").unwrap().id;
+        let b_id = document.get_element_by_id("b").unwrap().id;
+        
+        let mut engine = LayoutEngine::new();
+        engine.compute_layout(&document, &[]).await.unwrap();
+        let nodes_before = engine.element_to_node.clone();
+        
+        let mut style = ComputedStyle::default();
+        style.width = Some(200.0);
+        style.height = Some(10.0);
+        engine.update_element_layout(a_id, &style).await.unwrap();
+        
+        assert_eq!(engine.get_layout_box(a_id).unwrap().content_rect.size.width, 200.0);
+        assert_eq!(engine.get_layout_box(b_id).unwrap().content_rect.size.width, 50.0);
+        assert_eq!(engine.element_to_node, nodes_before);
+        
+        // A full relayout of the same document reuses every node too
+        engine.compute_layout(&document, &[]).await.unwrap();
+        assert_eq!(engine.element_to_node, nodes_before);
+        assert_eq!(engine.taffy.total_node_count(), nodes_before.len());
+    }
+    
+    #[tokio::test]
+    async fn test_baseline_from_font_metrics() {
+        let parser = crate::html::HTMLParser::new();
+        let document = parser.parse(
+            r#"<html><body><p id="text" style="font-size: 20px; height: 24px">Hello</p></body></html>"#
+        ).unwrap();
+        let text_id = document.get_element_by_id("text").unwrap().id;
+        
+        let mut engine = LayoutEngine::new();
+        let layout_tree = engine.compute_layout(&document, &[]).await.unwrap();
+        let text_box = layout_tree.get_layout_box(text_id).unwrap();
+        
+        // 16px ascent + 4px descent in a 24px line: 2px of half-leading above the ascent
+        let top = text_box.content_rect.origin.y;
+        let bottom = top + text_box.content_rect.size.height;
+        assert_eq!((text_box.ascent, text_box.descent), (16.0, 4.0));
+        assert_eq!(text_box.baseline, top + 18.0);
+        assert!(text_box.baseline < bottom);
+        
+        // Real metrics take over once the font is registered
+        let mut engine = LayoutEngine::new();
+        engine.register_font_metrics("Serif", FontMetrics { ascent: 0.9, descent: 0.3 });
+        let document = parser.parse(
+            r#"<html><body><p id="text" style="font-size: 20px; font-family: 'Serif'">Hello</p></body></html>"#
+        ).unwrap();
+        let text_id = document.get_element_by_id("text").unwrap().id;
+        let layout_tree = engine.compute_layout(&document, &[]).await.unwrap();
+        assert_eq!(layout_tree.get_layout_box(text_id).unwrap().ascent, 18.0);
+    }
+    
+    #[tokio::test]
+    async fn test_layout_tree_json_snapshot() {
+        let parser = crate::html::HTMLParser::new();
+        let document = parser.parse(
+            r#"<!DOCTYPE html><html><body><div style="width: 100px; height: 50px"></div><span>Text</span></body></html>"#
+        ).unwrap();
+        
+        let mut engine = LayoutEngine::new();
+        let layout_tree = engine.compute_layout(&document, &[]).await.unwrap();
+        let json = layout_tree.to_json_value(&document, true);
+        
+        // Tree order with sequential ids
+        assert_eq!(json["tag"], "html");
+        assert_eq!(json["id"], 0);
+        assert_eq!(json["children"][0]["tag"], "head");
+        assert_eq!(json["children"][1]["tag"], "body");
+        let body = &json["children"][1];
+        assert_eq!(body["id"], 2);
+        assert_eq!(body["children"][0]["tag"], "div");
+        assert_eq!(body["children"][0]["id"], 3);
+        assert_eq!(body["children"][1]["tag"], "span");
+        assert_eq!(body["children"][1]["id"], 4);
+        
+        let div = &body["children"][0];
+        assert_eq!(div["content"][2], 100.0);
+        assert_eq!(div["content"][3], 50.0);
+        for key in ["padding", "border", "margin", "baseline"] {
+            assert!(div.get(key).is_some(), "missing {}", key);
+        }
+        
+        // Stable ids make repeated layouts of the same markup identical
+        let again = parser.parse(
+            r#"<!DOCTYPE html><html><body><div style="width: 100px; height: 50px"></div><span>Text</span></body></html>"#
+        ).unwrap();
+        let again_tree = LayoutEngine::new().compute_layout(&again, &[]).await.unwrap();
+        assert_eq!(
+            again_tree.to_json_pretty(&again, true).unwrap(),
+            layout_tree.to_json_pretty(&document, true).unwrap(),
+        );
+        assert_ne!(
+            again_tree.to_json_pretty(&again, false).unwrap(),
+            layout_tree.to_json_pretty(&document, false).unwrap(),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_intrinsic_size_aspect_ratio() {
+        let parser = crate::html::HTMLParser::new();
+        let mut document = parser.parse(
+            r#"<html><body><img id="sized" src="photo.png" style="width: 200px"><img id="natural" src="/images/photo.png"></body></html>"#
+        ).unwrap();
+        document.url = "https://example.com/images/gallery.html".to_string();
+        let sized_id = document.get_element_by_id("sized").unwrap().id;
+        let natural_id = document.get_element_by_id("natural").unwrap().id;
+        
+        let mut engine = LayoutEngine::new();
+        engine.register_intrinsic_size("https://example.com/images/photo.png", CoreSize::new(400.0, 300.0));
+        let layout_tree = engine.compute_layout(&document, &[]).await.unwrap();
+        
+        let sized = layout_tree.get_layout_box(sized_id).unwrap().content_rect.size;
+        assert_eq!((sized.width, sized.height), (200.0, 150.0));
+        let natural = layout_tree.get_layout_box(natural_id).unwrap().content_rect.size;
+        assert_eq!((natural.width, natural.height), (400.0, 300.0));
+    }
+
+    #[tokio::test]
+    async fn test_display_none_subtree_is_pruned() {
+        let parser = crate::html::HTMLParser::new();
+        let document = parser.parse(
+            r#"<html><body><div id="shown" style="height: 50px"></div><div id="hidden" style="display: none; height: 50px"><p id="inner" style="height: 20px">secret</p></div></body></html>"#
+        ).unwrap();
+        let shown_id = document.get_element_by_id("shown").unwrap().id;
+        let hidden_id = document.get_element_by_id("hidden").unwrap().id;
+        let inner_id = document.get_element_by_id("inner").unwrap().id;
+        
+        let mut engine = LayoutEngine::new();
+        let layout_tree = engine.compute_layout(&document, &[]).await.unwrap();
+        
+        assert!(layout_tree.get_layout_box(shown_id).is_some());
+        assert!(layout_tree.get_layout_box(hidden_id).is_none());
+        assert!(layout_tree.get_layout_box(inner_id).is_none());
+        assert!(engine.get_layout_box(inner_id).is_none());
+        // Only the visible div can be hit where the hidden one would have been
+        assert_ne!(engine.hit_test(Point::new(10.0, 60.0)), Some(hidden_id));
+        assert_ne!(engine.hit_test(Point::new(10.0, 60.0)), Some(inner_id));
+        
+        let mut rendering = crate::rendering::RenderingEngine::headless(800, 600).await.unwrap();
+        let render_tree = rendering.create_render_tree_with_styles(&layout_tree, engine.computed_styles()).await.unwrap();
+        assert!(render_tree.render_nodes.contains_key(&shown_id));
+        assert!(!render_tree.render_nodes.contains_key(&hidden_id));
+        assert!(!render_tree.render_nodes.contains_key(&inner_id));
+    }
+    
+    #[tokio::test]
+    async fn test_style_change_starts_transition() {
+        let parser = crate::html::HTMLParser::new();
+        let mut document = parser.parse(
+            r#"<html><body><div id="box" style="color: red; transition: color 10s linear"></div></body></html>"#
+        ).unwrap();
+        let box_id = document.get_element_by_id("box").unwrap().id;
+        
+        let mut engine = LayoutEngine::new();
+        engine.compute_layout(&document, &[]).await.unwrap();
+        assert_eq!(engine.computed_styles()[&box_id].color.r, 1.0);
+        assert!(!engine.has_running_animations());
+        
+        // The longhands describe the same transition as the shorthand
+        document.set_attribute(
+            box_id,
+            "style",
+            "color: blue; transition-property: color; transition-duration: 10s; transition-timing-function: linear",
+        ).unwrap();
+        engine.compute_layout(&document, &[]).await.unwrap();
+        assert_eq!(engine.computed_styles()[&box_id].color.r, 1.0, "starts from the old value");
+        assert!(engine.has_running_animations());
+        
+        let dirty = engine.tick_animations(Instant::now() + std::time::Duration::from_secs(5));
+        assert_eq!(dirty, vec![box_id]);
+        let color = engine.computed_styles()[&box_id].color;
+        assert!((color.r - 0.5).abs() < 0.01 && (color.b - 0.5).abs() < 0.01, "{:?}", color);
+        
+        engine.tick_animations(Instant::now() + std::time::Duration::from_secs(11));
+        assert_eq!(engine.computed_styles()[&box_id].color.b, 1.0);
+        assert!(!engine.has_running_animations());
+    }
+}
@@ -13,25 +13,35 @@ pub mod javascript;
 pub mod networking;
 pub mod media;
 pub mod storage;
+pub mod downloads;
+pub mod subresources;
+pub mod archive;
+pub mod navigation;
 pub mod security;
 pub mod ai;
 pub mod ffi;
 
 // Re-export main types for easier access
 pub use core::*;
-pub use html::{HTMLParser, Document, Element};
-pub use css::{CSSEngine, ComputedStyle};
-pub use layout::{LayoutEngine, LayoutTree};
-pub use rendering::{RenderingEngine, RenderTree};
-pub use javascript::{JSRuntime, JSValue};
-pub use networking::{NetworkStack, NetworkResponse};
+pub use html::{HTMLParser, Document, Element, ElementState, CompatMode, SanitizerConfig, MutationRecord, MutationKind, MutationObserverId};
+pub use css::{CSSEngine, ComputedStyle, TransformFunction};
+pub use layout::{LayoutEngine, LayoutTree, ScrollState};
+pub use rendering::{RenderingEngine, RenderTree, GlBackend};
+pub use javascript::{JSRuntime, JSValue, DomEvent};
+pub use networking::{NetworkStack, NetworkConfig, RateLimit, RobotsTxt, ResolverMode, NetworkResponse, InterceptAction, RequestParts, ResponseParts, WebSocketConnection, WebSocketMessage, CertificatePin, TlsInfo};
 pub use media::{MediaEngine, MediaElement};
-pub use storage::{StorageEngine, Bookmark, HistoryEntry};
+pub use storage::{StorageEngine, Bookmark, HistoryEntry, DownloadRecord, DownloadStatus};
+pub use downloads::DownloadManager;
+pub use archive::ArchiveOptions;
+pub use navigation::{NavigationController, NavigationEntry};
 pub use security::{SecurityEngine, SecurityEvent};
 pub use ai::{AIEngine, PageContext, AIInsight};
 
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, RwLock};
+use tokio_util::sync::CancellationToken;
+use futures::future::LocalBoxFuture;
 use anyhow::Result;
 
 /// Main engine instance that coordinates all subsystems
@@ -55,7 +65,7 @@ pub struct TitanEngine {
     pub network_stack: Arc<networking::NetworkStack>,
     
     /// Media engine for audio/video processing
-    pub media_engine: Arc<media::MediaEngine>,
+    pub media_engine: Arc<RwLock<media::MediaEngine>>,
     
     /// Storage system for persistent data
     pub storage: Arc<storage::StorageEngine>,
@@ -65,23 +75,201 @@ pub struct TitanEngine {
     
     /// AI integration for intelligent features
     pub ai_engine: Arc<ai::AIEngine>,
+    
+    /// Most recently loaded page and the response it came from
+    current_page: Arc<RwLock<Option<LoadedPage>>>,
+    
+    /// Active and finished downloads for this session
+    downloads: Arc<downloads::DownloadManager>,
+    
+    /// Limits applied to each navigation
+    navigation_budget: Arc<RwLock<NavigationBudget>>,
+    
+    /// Subsystems whose panics are converted into errors
+    panic_isolation: Arc<RwLock<core::PanicIsolation>>,
+    
+    /// Browser event broadcaster
+    event_sender: broadcast::Sender<core::BrowserEvent>,
+    
+    /// Handlers called with every event, in registration order
+    event_handlers: core::EventHandlers,
+    
+    /// Settings the engine was created with
+    settings: core::EngineSettings,
+}
+
+/// Recorded decision for a permission the user granted or denied
+fn permission_state(granted: bool) -> security::PermissionState {
+    if granted {
+        security::PermissionState::Granted
+    } else {
+        security::PermissionState::Denied
+    }
+}
+
+/// Fail with `EngineError::Cancelled` if the load has been abandoned
+fn check_cancelled(url: &str, cancel: &CancellationToken) -> core::Result<()> {
+    if cancel.is_cancelled() {
+        log::info!("Load of {} cancelled", url);
+        Err(core::EngineError::Cancelled(url.to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+/// How long a fetched favicon is reused before it is fetched again
+const FAVICON_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Favicon URLs to try for a page, best first: the largest icon declared with
+/// `<link rel="icon">` (later declarations win ties), then `/favicon.ico` on its origin
+fn favicon_candidates(document: &html::Document, page_url: &url::Url) -> Vec<url::Url> {
+    // Icons declared as `sizes="any"` are scalable and beat every fixed size
+    let declared_size = |sizes: Option<&str>| -> u64 {
+        sizes.unwrap_or_default()
+            .split_ascii_whitespace()
+            .map(|size| {
+                if size.eq_ignore_ascii_case("any") {
+                    return u64::MAX;
+                }
+                size.to_ascii_lowercase().split_once('x').map_or(0, |(width, height)| {
+                    width.parse::<u64>().unwrap_or(0).saturating_mul(height.parse::<u64>().unwrap_or(0))
+                })
+            })
+            .max()
+            .unwrap_or(0)
+    };
+    
+    let declared = std::iter::once(document.root.clone())
+        .chain(document.descendants(&document.root))
+        .filter(|element| element.tag_name.eq_ignore_ascii_case("link"))
+        .filter(|link| link.get_attribute("rel").is_some_and(|rel| {
+            rel.split_ascii_whitespace().any(|token| token.eq_ignore_ascii_case("icon"))
+        }))
+        .filter_map(|link| {
+            let href = link.get_attribute("href").filter(|href| !href.trim().is_empty())?;
+            let url = page_url.join(href.trim()).ok()?;
+            Some((declared_size(link.get_attribute("sizes")), url))
+        })
+        .max_by_key(|(size, _)| *size)
+        .map(|(_, url)| url);
+    
+    declared.into_iter()
+        .chain(page_url.join("/favicon.ico").ok())
+        .fold(Vec::new(), |mut candidates, url| {
+            if !candidates.contains(&url) {
+                candidates.push(url);
+            }
+            candidates
+        })
+}
+
+/// A loaded page kept for on-demand analysis
+struct LoadedPage {
+    handle: core::PageHandle,
+    response: networking::NetworkResponse,
+}
+
+/// What one navigation has fetched so far, checked against its budget
+struct NavigationTracker {
+    url: String,
+    budget: NavigationBudget,
+    started: Instant,
+    bytes: u64,
+    resources: usize,
+    exceeded: Option<core::BudgetLimit>,
+}
+
+impl NavigationTracker {
+    fn new(url: &str, budget: NavigationBudget) -> Self {
+        Self {
+            url: url.to_string(),
+            budget,
+            started: Instant::now(),
+            bytes: 0,
+            resources: 0,
+            exceeded: None,
+        }
+    }
+    
+    /// Count a fetched resource against the budget
+    fn record(&mut self, response: &networking::NetworkResponse) {
+        self.resources += 1;
+        self.bytes += response.body_bytes.len() as u64;
+    }
+    
+    /// The budget that rules out another fetch, if any
+    fn check(&self) -> Option<core::BudgetLimit> {
+        if self.exceeded.is_some() {
+            return self.exceeded;
+        }
+        
+        if self.budget.max_resources.is_some_and(|max| self.resources >= max) {
+            Some(core::BudgetLimit::Resources)
+        } else if self.budget.max_bytes.is_some_and(|max| self.bytes >= max) {
+            Some(core::BudgetLimit::Bytes)
+        } else if self.remaining_time() == Some(Duration::ZERO) {
+            Some(core::BudgetLimit::Time)
+        } else {
+            None
+        }
+    }
+    
+    fn remaining_time(&self) -> Option<Duration> {
+        self.budget.max_duration.map(|max| max.saturating_sub(self.started.elapsed()))
+    }
 }
 
 impl TitanEngine {
     /// Initialize the Titan Engine with default configuration
     pub async fn new() -> Result<Self> {
+        Self::with_settings(core::EngineSettings::default()).await
+    }
+    
+    /// Initialize the Titan Engine; requests carry the settings' user agent and languages
+    pub async fn with_settings(settings: core::EngineSettings) -> Result<Self> {
         log::info!("Initializing Titan Browser Engine");
         
         let html_parser = Arc::new(html::HTMLParser::new());
         let css_engine = Arc::new(css::CSSEngine::new());
         let layout_engine = Arc::new(RwLock::new(layout::LayoutEngine::new()));
         let rendering_engine = Arc::new(RwLock::new(rendering::RenderingEngine::new().await?));
-        let js_runtime = Arc::new(RwLock::new(javascript::JSRuntime::new().await?));
-        let network_stack = Arc::new(networking::NetworkStack::new().await?);
-        let media_engine = Arc::new(media::MediaEngine::new().await?);
+        let mut js_runtime = javascript::JSRuntime::new().await?;
+        let mut network_stack = networking::NetworkStack::new().await?;
+        network_stack.update_network_config(networking::NetworkConfig {
+            user_agent: settings.user_agent.clone(),
+            accept_language: settings.accept_language.clone(),
+            ..networking::NetworkConfig::default()
+        });
+        // The cookie jar and HSTS list each get their own connection to the browser database
+        network_stack.set_cookie_store(Arc::new(tokio::sync::Mutex::new(storage::StorageEngine::new().await?)));
+        network_stack.set_hsts_store(Arc::new(tokio::sync::Mutex::new(storage::StorageEngine::new().await?)));
+        let network_stack = Arc::new(network_stack);
+        let media_engine = Arc::new(RwLock::new(media::MediaEngine::new().await?));
         let storage = Arc::new(storage::StorageEngine::new().await?);
         let security = Arc::new(security::SecurityEngine::new().await?);
+        
+        // Restore the permission decisions made in earlier sessions
+        let permissions = security.permissions();
+        for (origin, permission, granted) in storage.list_permissions().await? {
+            match security::PermissionKind::parse(&permission) {
+                Some(kind) => permissions.set(&origin, kind, permission_state(granted)),
+                None => log::warn!("Ignoring unknown stored permission {}", permission),
+            }
+        }
+        
+        // Scripts consult the same permission decisions as the rest of the engine
+        js_runtime.set_permission_store(permissions);
+        js_runtime.apply_engine_settings(&settings);
+        js_runtime.set_network_stack(network_stack.clone());
+        let js_runtime = Arc::new(RwLock::new(js_runtime));
         let ai_engine = Arc::new(ai::AIEngine::new().await?);
+        let event_sender = broadcast::channel(64).0;
+        let event_handlers: core::EventHandlers = Arc::new(RwLock::new(Vec::new()));
+        let mut downloads = downloads::DownloadManager::new(network_stack.clone(), event_sender.clone());
+        // Download records get their own connection too, so transfers don't contend with the engine's
+        downloads.set_store(Arc::new(tokio::sync::Mutex::new(storage::StorageEngine::new().await?)));
+        downloads.set_event_handlers(event_handlers.clone());
+        let downloads = Arc::new(downloads);
         
         Ok(Self {
             html_parser,
@@ -94,66 +282,973 @@ impl TitanEngine {
             storage,
             security,
             ai_engine,
+            current_page: Arc::new(RwLock::new(None)),
+            downloads,
+            navigation_budget: Arc::new(RwLock::new(NavigationBudget::default())),
+            panic_isolation: Arc::new(RwLock::new(core::PanicIsolation::default())),
+            event_sender,
+            event_handlers,
+            settings,
         })
     }
     
     /// Load and render a web page
     pub async fn load_page(&self, url: &str) -> Result<core::PageHandle> {
+        let mut js_runtime = self.js_runtime.write().await;
+        self.load_page_in(url, &mut js_runtime).await
+    }
+    
+    /// Load and render a web page, abandoning it once `cancel` fires.
+    /// A cancelled load fails with `EngineError::Cancelled`.
+    pub async fn load_page_cancellable(&self, url: &str, cancel: &CancellationToken) -> Result<core::PageHandle> {
+        let mut js_runtime = self.js_runtime.write().await;
+        self.load_page_in_cancellable(url, &mut js_runtime, cancel).await
+    }
+    
+    /// Load and render a web page, running its scripts in the given runtime
+    pub async fn load_page_in(&self, url: &str, js_runtime: &mut javascript::JSRuntime) -> Result<core::PageHandle> {
+        self.load_page_in_cancellable(url, js_runtime, &CancellationToken::new()).await
+    }
+    
+    /// Like `load_page_in`, but abandoned once `cancel` fires
+    pub async fn load_page_in_cancellable(
+        &self,
+        url: &str,
+        js_runtime: &mut javascript::JSRuntime,
+        cancel: &CancellationToken,
+    ) -> Result<core::PageHandle> {
         log::info!("Loading page: {}", url);
         
-        // Security check
-        self.security.validate_url(url).await?;
+        self.track_load(url, async {
+            // Security check
+            self.security.validate_url(url).await?;
+            check_cancelled(url, cancel)?;
+            
+            // Fetch content; cancelling drops the in-flight request
+            let mut tracker = NavigationTracker::new(url, *self.navigation_budget.read().await);
+            let response = tokio::select! {
+                response = self.fetch_within_budget(url, None, &mut tracker) => response?,
+                _ = cancel.cancelled() => return Err(core::EngineError::Cancelled(url.to_string()).into()),
+            };
+            self.emit(core::BrowserEvent::LoadProgress { progress: 0.25 }).await;
+            check_cancelled(url, cancel)?;
+            
+            // Parse HTML
+            let mut document = self.isolate(core::Subsystem::Html, async { self.html_parser.parse_bytes(&response.body_bytes, response.content_type()) }).await?;
+            document.url = response.url.clone();
+            self.emit(core::BrowserEvent::LoadProgress { progress: 0.5 }).await;
+            
+            self.render_document(document, response, js_runtime, &mut tracker, cancel).await
+        }).await
+    }
+    
+    /// Style, lay out, script, render, and analyze an already-parsed document
+    pub async fn load_document(
+        &self,
+        document: html::Document,
+        response: networking::NetworkResponse,
+    ) -> Result<core::PageHandle> {
+        let mut js_runtime = self.js_runtime.write().await;
+        self.load_document_in(document, response, &mut js_runtime).await
+    }
+    
+    /// Like `load_document`, but scripts run in the given runtime instead of the shared one
+    pub async fn load_document_in(
+        &self,
+        document: html::Document,
+        response: networking::NetworkResponse,
+        js_runtime: &mut javascript::JSRuntime,
+    ) -> Result<core::PageHandle> {
+        let url = response.url.clone();
+        self.track_load(&url, async {
+            let mut tracker = NavigationTracker::new(&response.url, *self.navigation_budget.read().await);
+            tracker.record(&response);
+            self.render_document(document, response, js_runtime, &mut tracker, &CancellationToken::new()).await
+        }).await
+    }
+    
+    /// Bracket a load with `NavigationStarted` and `LoadCompleted`, which is sent even if the load fails
+    async fn track_load(
+        &self,
+        url: &str,
+        load: impl std::future::Future<Output = Result<core::PageHandle>>,
+    ) -> Result<core::PageHandle> {
+        let started = Instant::now();
+        self.emit(core::BrowserEvent::NavigationStarted { url: url.to_string() }).await;
+        
+        let result = load.await;
+        self.emit(core::BrowserEvent::LoadCompleted {
+            url: result.as_ref().map_or(url, |handle| handle.url.as_str()).to_string(),
+            load_time_ms: started.elapsed().as_millis() as u64,
+            success: result.is_ok(),
+        }).await;
         
-        // Fetch content
-        let response = self.network_stack.fetch(url).await?;
+        result
+    }
+    
+    /// Style, lay out, script, render, and analyze a document, fetching subresources within budget
+    async fn render_document(
+        &self,
+        document: html::Document,
+        response: networking::NetworkResponse,
+        js_runtime: &mut javascript::JSRuntime,
+        tracker: &mut NavigationTracker,
+        cancel: &CancellationToken,
+    ) -> Result<core::PageHandle> {
+        let url = response.url.as_str();
+        check_cancelled(url, cancel)?;
         
-        // Parse HTML
-        let document = self.html_parser.parse(&response.body)?;
+        // Fetch external stylesheets, scripts, and images together
+        let subresources = self.load_subresources(&document, tracker, cancel).await?;
         
         // Parse CSS
-        let stylesheets = self.css_engine.parse_stylesheets(&document).await?;
+        let stylesheets = self.isolate(core::Subsystem::Css, self.css_engine.parse_stylesheets_with(&document, &subresources.stylesheets)).await?;
+        check_cancelled(url, cancel)?;
+        
+        // Load web fonts before layout so font-family lists can use them
+        let web_fonts = self.load_web_fonts(&stylesheets, url, tracker, cancel).await?;
+        
+        // Decode images before layout so `<img>` elements get their natural size
+        let mut image_sizes = Vec::new();
+        {
+            let mut rendering_engine = self.rendering_engine.write().await;
+            for (image_url, image_data) in subresources.images {
+                match rendering_engine.load_image_from_url(&image_url, image_data, webrender::api::ImageFormat::BGRA8).await {
+                    Ok(_) => image_sizes.extend(rendering_engine.intrinsic_size(&image_url).map(|size| (image_url, size))),
+                    Err(e) => log::warn!("Failed to load image {}: {:?}", image_url, e),
+                }
+            }
+        }
+        
+        self.load_media_elements(&document).await;
         
         // Compute layout
         let mut layout_engine = self.layout_engine.write().await;
-        let layout_tree = layout_engine.compute_layout(&document, &stylesheets).await?;
+        layout_engine.set_web_fonts(&web_fonts);
+        for (image_url, size) in &image_sizes {
+            layout_engine.register_intrinsic_size(image_url, *size);
+        }
+        let layout_tree = self.isolate(core::Subsystem::Layout, layout_engine.compute_layout(&document, &stylesheets)).await?;
+        self.emit(core::BrowserEvent::LoadProgress { progress: 0.75 }).await;
+        check_cancelled(url, cancel)?;
         
         // Execute JavaScript
-        let mut js_runtime = self.js_runtime.write().await;
-        js_runtime.execute_page_scripts(&document).await?;
+        if let Ok(page_url) = url::Url::parse(url) {
+            js_runtime.set_origin(&page_url.origin().ascii_serialization());
+        }
+        js_runtime.set_content_security_policy(response.security_info.csp.as_deref());
+        self.isolate(core::Subsystem::JavaScript, js_runtime.execute_page_scripts_with(&document, &subresources.scripts)).await?;
+        check_cancelled(url, cancel)?;
         
         // Render page
         let mut rendering_engine = self.rendering_engine.write().await;
-        let render_tree = rendering_engine.create_render_tree(&layout_tree).await?;
+        let mut render_tree = self.isolate(core::Subsystem::Rendering, rendering_engine.create_render_tree_with_styles(&layout_tree, layout_engine.computed_styles())).await?;
+        
+        // Videos show their poster in place of frames until playback starts
+        {
+            let media_engine = self.media_engine.read().await;
+            for video in document.get_elements_by_tag_name("video") {
+                let poster_key = media_engine.visible_poster(video.id).and_then(|poster| rendering_engine.image_key(poster));
+                if let (Some(image_key), Some(layout_box)) = (poster_key, layout_tree.get_layout_box(video.id)) {
+                    render_tree.show_image(video.id, layout_box.content_rect, image_key);
+                }
+            }
+        }
+        self.emit(core::BrowserEvent::LoadProgress { progress: 1.0 }).await;
+        check_cancelled(url, cancel)?;
+        
+        // Load iframes into nested browsing contexts
+        let origin = url::Url::parse(url)
+            .map(|page_url| page_url.origin().ascii_serialization())
+            .unwrap_or_else(|_| "null".to_string());
+        let mut frames = Vec::new();
+        for iframe in document.get_elements_by_tag_name("iframe") {
+            match self.load_frame(&iframe, &document, &layout_tree, &origin, &mut rendering_engine, &mut render_tree, tracker).await {
+                Ok(frame) => frames.push(frame),
+                Err(e) => log::warn!("Failed to load iframe: {:?}", e),
+            }
+        }
+        
+        check_cancelled(url, cancel)?;
         
         // AI analysis
-        let ai_context = self.ai_engine.analyze_page(&document, &response).await?;
+        let ai_context = self.isolate(core::Subsystem::Ai, self.ai_engine.analyze_page(&document, &response)).await?;
+        
+        let mut handle = core::PageHandle::new(document, layout_tree, render_tree, ai_context);
+        handle.frames = frames;
+        *self.current_page.write().await = Some(LoadedPage {
+            handle: handle.clone(),
+            response,
+        });
         
-        Ok(core::PageHandle::new(document, layout_tree, render_tree, ai_context))
+        Ok(handle)
     }
     
-    /// Shutdown the engine gracefully
-    pub async fn shutdown(&self) -> Result<()> {
-        log::info!("Shutting down Titan Browser Engine");
+    /// Load an `<iframe>` into its own browsing context and composite it into the parent's render tree.
+    /// Frames nested inside the frame's document are not loaded.
+    async fn load_frame(
+        &self,
+        iframe: &html::Element,
+        parent_document: &html::Document,
+        parent_layout: &layout::LayoutTree,
+        parent_origin: &str,
+        rendering_engine: &mut rendering::RenderingEngine,
+        render_tree: &mut rendering::RenderTree,
+        tracker: &mut NavigationTracker,
+    ) -> Result<core::FrameHandle> {
+        let sandbox = iframe.get_attribute("sandbox").map(security::SandboxFlags::parse);
         
-        // Shutdown subsystems in reverse order
-        self.ai_engine.shutdown().await?;
-        self.security.shutdown().await?;
-        self.storage.shutdown().await?;
-        self.media_engine.shutdown().await?;
-        self.network_stack.shutdown().await?;
+        // srcdoc takes precedence over src and inherits the parent's origin
+        let (mut frame_document, frame_url, mut origin) = if let Some(srcdoc) = iframe.get_attribute("srcdoc") {
+            (self.html_parser.parse(srcdoc)?, "about:srcdoc".to_string(), parent_origin.to_string())
+        } else if let Some(src) = iframe.get_attribute("src").filter(|src| !src.trim().is_empty()) {
+            let frame_url = url::Url::parse(&parent_document.url)
+                .and_then(|base| base.join(src))
+                .or_else(|_| url::Url::parse(src))?;
+            self.security.validate_url(frame_url.as_str()).await?;
+            
+            let response = self.fetch_within_budget(frame_url.as_str(), Some(&parent_document.url), tracker).await?;
+            let origin = url::Url::parse(&response.url)
+                .map(|u| u.origin().ascii_serialization())
+                .unwrap_or_else(|_| "null".to_string());
+            (self.html_parser.parse_bytes(&response.body_bytes, response.content_type())?, response.url.clone(), origin)
+        } else {
+            (html::Document::new("about:blank".to_string()), "about:blank".to_string(), parent_origin.to_string())
+        };
+        frame_document.url = frame_url.clone();
         
-        {
-            let mut js_runtime = self.js_runtime.write().await;
-            js_runtime.shutdown().await?;
+        // Without allow-same-origin a sandboxed frame gets a unique opaque origin
+        if sandbox.is_some_and(|flags| !flags.allow_same_origin) {
+            origin = "null".to_string();
         }
         
-        {
-            let mut rendering_engine = self.rendering_engine.write().await;
-            rendering_engine.shutdown().await?;
+        // Lay out the frame's document inside the iframe's content box (300x150 by default)
+        let viewport = parent_layout.get_layout_box(iframe.id)
+            .map(|layout_box| layout_box.content_rect)
+            .unwrap_or_else(|| core::Rect::new(0.0, 0.0, 300.0, 150.0));
+        let stylesheets = self.css_engine.parse_stylesheets(&frame_document).await?;
+        let mut frame_layout_engine = layout::LayoutEngine::new();
+        let frame_layout = frame_layout_engine
+            .compute_layout_with_viewport(&frame_document, &stylesheets, viewport.size)
+            .await?;
+        
+        // Each frame gets its own runtime so scripts can't reach the parent's globals
+        let has_scripts = !frame_document.get_elements_by_tag_name("script").is_empty();
+        let scripts_allowed = sandbox.map_or(true, |flags| flags.allow_scripts);
+        let mut scripts_executed = false;
+        if has_scripts && scripts_allowed {
+            let mut frame_runtime = self.create_js_runtime().await?;
+            frame_runtime.set_origin(&origin);
+            if origin == "null" {
+                frame_runtime.update_security_settings(javascript::SecuritySettings {
+                    allow_storage_access: false,
+                    ..javascript::SecuritySettings::default()
+                });
+            }
+            frame_runtime.execute_page_scripts(&frame_document).await?;
+            scripts_executed = true;
+        } else if has_scripts {
+            log::info!("Blocked scripts in sandboxed iframe {}", frame_url);
         }
         
+        let frame_render_tree = rendering_engine.create_render_tree_with_styles(&frame_layout, frame_layout_engine.computed_styles()).await?;
+        let offset = parent_layout.parent_offset(iframe.id);
+        let viewport = viewport.translate(offset.x, offset.y);
+        render_tree.embed_frame(iframe.id, frame_render_tree, viewport);
+        
+        Ok(core::FrameHandle {
+            element_id: iframe.id,
+            url: frame_url,
+            origin,
+            sandbox,
+            document: Arc::new(frame_document),
+            layout_tree: Arc::new(frame_layout),
+            viewport,
+            scripts_executed,
+        })
+    }
+    
+    /// Hand a document's `<video>` and `<audio>` elements to the media engine, with `src` and
+    /// `poster` resolved against the page. Elements that fail to load are skipped.
+    async fn load_media_elements(&self, document: &html::Document) {
+        let base = url::Url::parse(&document.url).ok();
+        let mut media_engine = self.media_engine.write().await;
+        let media = document.get_elements_by_tag_name("video").into_iter()
+            .chain(document.get_elements_by_tag_name("audio"));
+        for element in media {
+            let mut element = (*element).clone();
+            for name in ["src", "poster"] {
+                let resolved = element.get_attribute(name)
+                    .and_then(|value| base.as_ref()?.join(value).ok());
+                if let Some(resolved) = resolved {
+                    element.set_attribute(name.to_string(), resolved.to_string());
+                }
+            }
+            if let Err(e) = media_engine.create_element_from_html(&element).await {
+                log::warn!("Failed to load <{}> element: {:?}", element.tag_name, e);
+            }
+        }
+    }
+    
+    /// Fetch a resource for a navigation, refusing once its budget is spent.
+    /// `site_for_cookies` is the top-level page for frames, `None` for the page itself.
+    async fn fetch_within_budget(
+        &self,
+        url: &str,
+        site_for_cookies: Option<&str>,
+        tracker: &mut NavigationTracker,
+    ) -> Result<networking::NetworkResponse> {
+        if let Some(limit) = tracker.check() {
+            self.report_budget_exceeded(tracker, limit).await;
+            return Err(core::EngineError::network(core::NetworkErrorKind::Other, Some(url), format!("Navigation budget exceeded, skipped {}", url)).into());
+        }
+        
+        let fetch = async {
+            match site_for_cookies {
+                Some(site) => self.network_stack.fetch_for_site(url, site).await,
+                None => self.network_stack.fetch(url).await,
+            }
+        };
+        let response = match tracker.remaining_time() {
+            Some(remaining) => match tokio::time::timeout(remaining, fetch).await {
+                Ok(response) => response?,
+                Err(_) => {
+                    self.report_budget_exceeded(tracker, core::BudgetLimit::Time).await;
+                    return Err(core::EngineError::network(core::NetworkErrorKind::Timeout, Some(url), format!("Navigation budget exceeded while fetching {}", url)).into());
+                }
+            },
+            None => fetch.await?,
+        };
+        
+        tracker.record(&response);
+        Ok(response)
+    }
+    
+    /// Fetch a document's subresources concurrently, as many as the navigation budget allows.
+    /// Resources that fail to load are left out rather than failing the page.
+    async fn load_subresources(
+        &self,
+        document: &html::Document,
+        tracker: &mut NavigationTracker,
+        cancel: &CancellationToken,
+    ) -> Result<subresources::Subresources> {
+        let mut resources = subresources::collect_subresources(document);
+        if resources.is_empty() {
+            return Ok(subresources::Subresources::default());
+        }
+        if let Some(limit) = tracker.check() {
+            self.report_budget_exceeded(tracker, limit).await;
+            return Ok(subresources::Subresources::default());
+        }
+        if let Some(max) = tracker.budget.max_resources {
+            let allowed = max.saturating_sub(tracker.resources);
+            if resources.len() > allowed {
+                resources.truncate(allowed);
+                self.report_budget_exceeded(tracker, core::BudgetLimit::Resources).await;
+            }
+        }
+        
+        let fetches = subresources::fetch_subresources(&self.network_stack, &document.url, resources, subresources::MAX_CONCURRENT_SUBRESOURCES);
+        let results = tokio::select! {
+            results = fetches => results,
+            _ = cancel.cancelled() => return Err(core::EngineError::Cancelled(document.url.clone()).into()),
+        };
+        for response in results.iter().filter_map(|(_, result)| result.as_ref().ok()) {
+            tracker.record(response);
+        }
+        
+        Ok(subresources::Subresources::from_responses(results))
+    }
+    
+    /// Load the fonts declared by `@font-face` rules and return the families that loaded.
+    /// Each rule's sources are tried in order until one loads; fonts that fail are skipped.
+    /// Relative sources resolve against the declaring stylesheet, or the page for `<style>`.
+    async fn load_web_fonts(
+        &self,
+        stylesheets: &[Arc<css::Stylesheet>],
+        page_url: &str,
+        tracker: &mut NavigationTracker,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>> {
+        let page_base = url::Url::parse(page_url).ok();
+        let mut loaded = Vec::new();
+        
+        for face in css::collect_font_faces(stylesheets) {
+            let base_url = face.base_url.as_deref()
+                .and_then(|stylesheet_url| url::Url::parse(stylesheet_url).ok())
+                .or_else(|| page_base.clone());
+            for source in face.sources.iter().filter(|source| source.is_supported()) {
+                let font_url = match source {
+                    css::FontFaceSource::Local(name) => {
+                        if self.rendering_engine.write().await.alias_font(&face.family, name).is_some() {
+                            loaded.push(face.family.clone());
+                            break;
+                        }
+                        continue;
+                    }
+                    css::FontFaceSource::Url { url, .. } => {
+                        let resolved = match &base_url {
+                            Some(base_url) => base_url.join(url),
+                            None => url::Url::parse(url),
+                        };
+                        match resolved {
+                            Ok(font_url) => font_url,
+                            Err(e) => {
+                                log::warn!("Invalid font URL {}: {}", url, e);
+                                continue;
+                            }
+                        }
+                    }
+                };
+                
+                if let Some(limit) = tracker.check() {
+                    self.report_budget_exceeded(tracker, limit).await;
+                    return Ok(loaded);
+                }
+                let response = tokio::select! {
+                    response = self.network_stack.fetch_for_site(font_url.as_str(), page_url) => response,
+                    _ = cancel.cancelled() => return Err(core::EngineError::Cancelled(page_url.to_string()).into()),
+                };
+                let response = match response {
+                    Ok(response) => response,
+                    Err(e) => {
+                        log::warn!("Failed to load font {}: {:?}", font_url, e);
+                        continue;
+                    }
+                };
+                tracker.record(&response);
+                if !(200..300).contains(&response.status) {
+                    log::warn!("Failed to load font {}: HTTP {}", font_url, response.status);
+                    continue;
+                }
+                
+                match self.rendering_engine.write().await.load_font(response.body_bytes, face.family.clone()).await {
+                    Ok(_) => {
+                        loaded.push(face.family.clone());
+                        break;
+                    }
+                    Err(e) => log::warn!("Failed to register font {}: {:?}", font_url, e),
+                }
+            }
+        }
+        
+        Ok(loaded)
+    }
+    
+    /// Emit a budget event the first time a navigation runs out
+    async fn report_budget_exceeded(&self, tracker: &mut NavigationTracker, limit: core::BudgetLimit) {
+        if tracker.exceeded.is_some() {
+            return;
+        }
+        tracker.exceeded = Some(limit);
+        
+        log::warn!("Navigation to {} exceeded its {:?} budget", tracker.url, limit);
+        self.emit(core::BrowserEvent::NavigationBudgetExceeded {
+            url: tracker.url.clone(),
+            limit,
+            resources_loaded: tracker.resources,
+            bytes_loaded: tracker.bytes,
+        }).await;
+    }
+    
+    /// Scroll the page (its root element) or a scroll container by a delta.
+    /// The offset is clamped to the content, and hit testing and the current page's render tree follow it.
+    pub async fn scroll_by(&self, element_id: core::ElementId, dx: f32, dy: f32) -> Result<layout::ScrollState> {
+        let (x, y) = self.layout_engine.read().await.scroll_state(element_id)
+            .map(|state| (state.scroll_x + dx, state.scroll_y + dy))
+            .ok_or_else(|| core::EngineError::DomError(format!("Element {} is not a scroll container", element_id.0)))?;
+        self.scroll_to(element_id, x, y).await
+    }
+    
+    /// Scroll the page (its root element) or a scroll container to an offset, clamped to its content
+    pub async fn scroll_to(&self, element_id: core::ElementId, x: f32, y: f32) -> Result<layout::ScrollState> {
+        let (previous, state) = {
+            let mut layout_engine = self.layout_engine.write().await;
+            let previous = layout_engine.scroll_state(element_id).cloned();
+            (previous, layout_engine.scroll_to(element_id, x, y)?)
+        };
+        let dx = state.scroll_x - previous.as_ref().map_or(0.0, |previous| previous.scroll_x);
+        let dy = state.scroll_y - previous.as_ref().map_or(0.0, |previous| previous.scroll_y);
+        if dx == 0.0 && dy == 0.0 {
+            return Ok(state);
+        }
+        
+        if let Some(page) = self.current_page.write().await.as_mut() {
+            let mut render_tree = (*page.handle.render_tree).clone();
+            render_tree.scroll_descendants(&page.handle.layout_tree, element_id, dx, dy);
+            page.handle.render_tree = Arc::new(render_tree);
+        }
+        
+        Ok(state)
+    }
+    
+    /// Set the limits applied to subsequent navigations
+    pub async fn set_navigation_budget(&self, budget: NavigationBudget) {
+        *self.navigation_budget.write().await = budget;
+    }
+    
+    /// Get the limits applied to navigations
+    pub async fn navigation_budget(&self) -> NavigationBudget {
+        *self.navigation_budget.read().await
+    }
+    
+    /// Choose which subsystems have their panics caught; all are isolated by default
+    pub async fn set_panic_isolation(&self, isolation: core::PanicIsolation) {
+        *self.panic_isolation.write().await = isolation;
+    }
+    
+    /// Run a subsystem call, catching its panics if that subsystem is isolated.
+    /// Tokio locks held by the call are released on unwind rather than poisoned.
+    async fn isolate<T>(
+        &self,
+        subsystem: core::Subsystem,
+        call: impl std::future::Future<Output = core::Result<T>>,
+    ) -> core::Result<T> {
+        if self.panic_isolation.read().await.is_isolated(subsystem) {
+            core::catch_panic(subsystem, call).await
+        } else {
+            call.await
+        }
+    }
+    
+    /// Subscribe to browser events
+    pub fn subscribe_events(&self) -> broadcast::Receiver<core::BrowserEvent> {
+        self.event_sender.subscribe()
+    }
+    
+    /// Register a handler to be called with every browser event
+    pub async fn add_event_handler(&self, handler: Arc<dyn core::EventHandler + Send + Sync>) {
+        self.event_handlers.write().await.push(handler);
+    }
+    
+    /// Broadcast an event to subscribers and registered handlers
+    async fn emit(&self, event: core::BrowserEvent) {
+        let _ = self.event_sender.send(event.clone());
+        
+        // Call handlers outside the lock so they can register others
+        let handlers = self.event_handlers.read().await.clone();
+        for handler in handlers {
+            if let Err(e) = handler.handle_event(event.clone()).await {
+                log::warn!("Event handler failed: {}", e);
+            }
+        }
+    }
+    
+    /// Download `url` to `path` in the background, returning the download's id
+    pub async fn start_download(&self, url: &str, path: impl AsRef<std::path::Path>) -> Result<String> {
+        Ok(self.downloads.start(url, path).await?)
+    }
+    
+    /// Download `url` into the downloads folder under `suggested_filename` or a name taken
+    /// from the URL, returning the download's id
+    pub async fn start_download_named(&self, url: &str, suggested_filename: Option<&str>) -> Result<String> {
+        Ok(self.downloads.start_named(url, suggested_filename).await?)
+    }
+    
+    /// List downloads, including those recorded by earlier sessions, newest first
+    pub async fn list_downloads(&self) -> Vec<storage::DownloadRecord> {
+        self.downloads.list().await
+    }
+    
+    /// Get the current state of a download
+    pub async fn download_status(&self, id: &str) -> Option<storage::DownloadRecord> {
+        self.downloads.status(id).await
+    }
+    
+    /// Pause a download, keeping the bytes received so far
+    pub async fn pause_download(&self, id: &str) -> Result<storage::DownloadRecord> {
+        Ok(self.downloads.pause(id).await?)
+    }
+    
+    /// Resume a paused download from the last byte written
+    pub async fn resume_download(&self, id: &str) -> Result<storage::DownloadRecord> {
+        Ok(self.downloads.resume(id).await?)
+    }
+    
+    /// Cancel a download and delete its partial file
+    pub async fn cancel_download(&self, id: &str) -> Result<storage::DownloadRecord> {
+        Ok(self.downloads.cancel(id).await?)
+    }
+    
+    /// Create a standalone JS runtime wired to the engine's permission decisions
+    pub async fn create_js_runtime(&self) -> Result<javascript::JSRuntime> {
+        let mut js_runtime = javascript::JSRuntime::new().await?;
+        js_runtime.set_permission_store(self.security.permissions());
+        js_runtime.apply_engine_settings(&self.settings);
+        js_runtime.set_network_stack(self.network_stack.clone());
+        Ok(js_runtime)
+    }
+    
+    /// Grant or deny a powerful feature such as geolocation to an origin
+    /// (e.g. "https://example.com"), remembering the decision across sessions
+    pub async fn set_permission(&self, origin: &str, permission: security::PermissionKind, granted: bool) -> Result<()> {
+        self.storage.set_permission(origin, permission.as_str(), granted).await?;
+        self.security.permissions().set(origin, permission, permission_state(granted));
         Ok(())
     }
+    
+    /// Get a page's favicon: its declared icon or `/favicon.ico`, cached per origin in storage.
+    /// Returns `None` if the page's origin has no icon.
+    pub async fn fetch_favicon(&self, page: &core::PageHandle) -> Result<Option<Vec<u8>>> {
+        let page_url = url::Url::parse(&page.url)?;
+        let origin = page_url.origin().ascii_serialization();
+        if let Some(data) = self.storage.get_favicon(&origin).await? {
+            return Ok(Some(data));
+        }
+        
+        for candidate in favicon_candidates(&page.document, &page_url) {
+            let response = match self.network_stack.fetch_for_site(candidate.as_str(), &page.url).await {
+                Ok(response) if (200..300).contains(&response.status) && !response.body_bytes.is_empty() => response,
+                Ok(response) => {
+                    log::debug!("No favicon at {} (status {})", candidate, response.status);
+                    continue;
+                }
+                Err(e) => {
+                    log::debug!("Failed to fetch favicon {}: {}", candidate, e);
+                    continue;
+                }
+            };
+            
+            let expires_at = chrono::Utc::now().timestamp() + FAVICON_MAX_AGE.as_secs() as i64;
+            if let Err(e) = self.storage.set_favicon(&origin, candidate.as_str(), &response.body_bytes, expires_at).await {
+                log::warn!("Failed to cache favicon for {}: {}", origin, e);
+            }
+            return Ok(Some(response.body_bytes));
+        }
+        
+        Ok(None)
+    }
+    
+    /// Save a page as one self-contained HTML file for offline reading, without scripts
+    pub async fn save_page_archive(&self, page: &core::PageHandle) -> Result<String> {
+        self.save_page_archive_with(page, &archive::ArchiveOptions::default()).await
+    }
+    
+    /// Save a page as one HTML file with its stylesheets, images, and optionally scripts inlined.
+    /// Subresources are fetched again; any that fail or would take the archive past
+    /// `options.max_bytes` are left as links.
+    pub async fn save_page_archive_with(&self, page: &core::PageHandle, options: &archive::ArchiveOptions) -> Result<String> {
+        let resources = subresources::collect_subresources(&page.document).into_iter()
+            .filter(|resource| options.include_scripts || resource.kind != subresources::SubresourceKind::Script)
+            .collect();
+        let fetched = subresources::fetch_subresources(&self.network_stack, &page.url, resources, subresources::MAX_CONCURRENT_SUBRESOURCES).await;
+        Ok(archive::build_archive(&page.document, fetched, options)?)
+    }
+    
+    /// Get the most recently loaded page
+    pub async fn current_page(&self) -> Option<core::PageHandle> {
+        self.current_page.read().await.as_ref().map(|page| page.handle.clone())
+    }
+    
+    /// Load `url` as a new entry in a tab's history, dropping its forward entries
+    pub async fn navigate(&self, navigation: &mut navigation::NavigationController, url: &str) -> Result<core::PageHandle> {
+        let handle = self.load_page(url).await?;
+        navigation.push(&handle.url, &handle.metadata.title);
+        
+        // The session stack is per tab; the visited list in storage is global
+        if let Err(e) = self.storage.add_history(&handle.url, &handle.metadata.title).await {
+            log::warn!("Failed to record history for {}: {}", handle.url, e);
+        }
+        
+        Ok(handle)
+    }
+    
+    /// Load the previous entry in a tab's history; the tab only moves if the load succeeds
+    pub async fn go_back(&self, navigation: &mut navigation::NavigationController) -> Result<core::PageHandle> {
+        let url = navigation.back_entry()
+            .map(|entry| entry.url.clone())
+            .ok_or_else(|| core::EngineError::InternalError("No previous page in history".to_string()))?;
+        
+        let handle = self.load_page(&url).await?;
+        navigation.go_back();
+        navigation.set_title(&handle.metadata.title);
+        Ok(handle)
+    }
+    
+    /// Load the next entry in a tab's history; the tab only moves if the load succeeds
+    pub async fn go_forward(&self, navigation: &mut navigation::NavigationController) -> Result<core::PageHandle> {
+        let url = navigation.forward_entry()
+            .map(|entry| entry.url.clone())
+            .ok_or_else(|| core::EngineError::InternalError("No next page in history".to_string()))?;
+        
+        let handle = self.load_page(&url).await?;
+        navigation.go_forward();
+        navigation.set_title(&handle.metadata.title);
+        Ok(handle)
+    }
+    
+    /// Load a tab's current entry again, keeping its place in history
+    pub async fn reload(&self, navigation: &mut navigation::NavigationController) -> Result<core::PageHandle> {
+        let url = navigation.reload()
+            .map(|entry| entry.url.clone())
+            .ok_or_else(|| core::EngineError::InternalError("No page to reload".to_string()))?;
+        
+        let handle = self.load_page(&url).await?;
+        navigation.set_title(&handle.metadata.title);
+        Ok(handle)
+    }
+    
+    /// Run `code` with the page's document bound, so DOM APIs see the page. Script mutations
+    /// are written back to the current page (when it is `page`), and the changed elements'
+    /// cached layout and render nodes are dropped so the next pass picks them up.
+    pub async fn evaluate_in_page(&self, page: &core::PageHandle, code: &str) -> Result<javascript::JSValue> {
+        let mut js_runtime = self.js_runtime.write().await;
+        if let Ok(page_url) = url::Url::parse(&page.url) {
+            js_runtime.set_origin(&page_url.origin().ascii_serialization());
+        }
+        js_runtime.bind_document((*page.document).clone());
+        let result = js_runtime.execute_script(code, &page.url).await;
+        let document = js_runtime.take_document();
+        drop(js_runtime);
+        
+        self.apply_script_mutations(page, document).await;
+        Ok(result?)
+    }
+    
+    /// Run one turn of the page's script event loop with its document bound: settle finished
+    /// `fetch` requests and dispatch WebSocket events arriving within `wait`. Call once per
+    /// frame, like `tick_animations`. Returns how many requests and events were handled.
+    pub async fn run_page_events(&self, page: &core::PageHandle, wait: Duration) -> Result<usize> {
+        let mut js_runtime = self.js_runtime.write().await;
+        if let Ok(page_url) = url::Url::parse(&page.url) {
+            js_runtime.set_origin(&page_url.origin().ascii_serialization());
+        }
+        js_runtime.bind_document((*page.document).clone());
+        let handled = js_runtime.run_event_loop(wait).await;
+        let document = js_runtime.take_document();
+        drop(js_runtime);
+        
+        self.apply_script_mutations(page, document).await;
+        Ok(handled?)
+    }
+    
+    /// Dispatch a user interaction to a page. Pointer input is hit-tested to find the target
+    /// element and key input goes to the body; the target's inline handlers and listeners run
+    /// with the page's document bound, bubbling to its ancestors. A `UserInteraction` event is
+    /// emitted either way. Pointer input also moves `:hover` to the target, clicks and touches
+    /// focus it, and a touch makes it `:active`. Returns false if a handler called `preventDefault()`.
+    pub async fn dispatch_event(
+        &self,
+        page: &core::PageHandle,
+        interaction_type: core::InteractionType,
+        input: core::InteractionInput,
+    ) -> Result<bool> {
+        let pointer = matches!(input, core::InteractionInput::Pointer(_));
+        let (target, position, key) = match input {
+            core::InteractionInput::Pointer(point) => (self.layout_engine.read().await.hit_test(point), point, None),
+            core::InteractionInput::Key(key) => (page.document.body.as_ref().map(|body| body.id), core::Point::zero(), Some(key)),
+        };
+        self.emit(core::BrowserEvent::UserInteraction {
+            interaction_type: interaction_type.clone(),
+            element_id: target,
+            position,
+        }).await;
+        let Some(target) = target else {
+            return Ok(true);
+        };
+        
+        let event = javascript::DomEvent {
+            event_type: interaction_type.dom_event_type().to_string(),
+            client_x: position.x,
+            client_y: position.y,
+            key,
+        };
+        let mut js_runtime = self.js_runtime.write().await;
+        if let Ok(page_url) = url::Url::parse(&page.url) {
+            js_runtime.set_origin(&page_url.origin().ascii_serialization());
+        }
+        let mut document = (*page.document).clone();
+        if pointer {
+            document.set_element_state(html::ElementState::Hover, Some(target));
+            let pressed = matches!(interaction_type, core::InteractionType::Touch);
+            document.set_element_state(html::ElementState::Active, pressed.then_some(target));
+        }
+        if matches!(interaction_type, core::InteractionType::Click | core::InteractionType::DoubleClick | core::InteractionType::Touch) {
+            document.set_element_state(html::ElementState::Focus, Some(target));
+        }
+        js_runtime.bind_document(document);
+        // Clicks and key presses are user gestures for the duration of their handlers
+        let gesture = matches!(interaction_type, core::InteractionType::Click | core::InteractionType::DoubleClick | core::InteractionType::KeyPress);
+        js_runtime.set_user_activation(gesture);
+        let result = js_runtime.dispatch_event(target, &event).await;
+        js_runtime.set_user_activation(false);
+        let document = js_runtime.take_document();
+        drop(js_runtime);
+        
+        self.apply_script_mutations(page, document).await;
+        Ok(result?)
+    }
+    
+    /// Write a document scripts ran against back to the current page (when it is `page`), and
+    /// drop the changed elements' cached layout and render nodes so the next pass picks them up
+    async fn apply_script_mutations(&self, page: &core::PageHandle, document: Option<html::Document>) {
+        if let Some(document) = document.filter(|document| !document.dirty_elements.is_empty()) {
+            self.layout_engine.write().await.invalidate_elements(&document.dirty_elements);
+            self.rendering_engine.write().await.invalidate_elements(&document.dirty_elements);
+            if let Some(loaded) = self.current_page.write().await.as_mut().filter(|loaded| loaded.handle.id == page.id) {
+                loaded.handle.document = Arc::new(document);
+            }
+        }
+    }
+    
+    /// Advance CSS animations and transitions on the current page to `now`; call once per frame.
+    /// Returns the elements whose style changed, which are marked for re-render.
+    pub async fn tick_animations(&self, now: Instant) -> Vec<core::ElementId> {
+        let dirty = self.layout_engine.write().await.tick_animations(now);
+        if !dirty.is_empty() {
+            self.rendering_engine.write().await.invalidate_elements(&dirty.iter().copied().collect());
+        }
+        dirty
+    }
+    
+    /// Run AI analysis on the most recently loaded page
+    pub async fn analyze_current_page(&self) -> Result<ai::PageContext> {
+        let current_page = self.current_page.read().await;
+        let page = current_page.as_ref()
+            .ok_or_else(|| core::EngineError::InternalError("No page loaded".to_string()))?;
+        
+        Ok(self.ai_engine.analyze_page(&page.handle.document, &page.response).await?)
+    }
+    
+    /// Aggregate each subsystem's metrics with the process's resident memory
+    pub async fn collect_metrics(&self) -> core::PerformanceMetrics {
+        use core::MetricsReporter;
+        
+        let mut metrics = core::PerformanceMetrics {
+            memory_usage_mb: memory_stats::memory_stats()
+                .map_or(0.0, |stats| stats.physical_mem as f64 / (1024.0 * 1024.0)),
+            ..core::PerformanceMetrics::default()
+        };
+        metrics.merge(&MetricsReporter::get_metrics(&*self.network_stack).await);
+        metrics.merge(&MetricsReporter::get_metrics(&*self.js_runtime.read().await).await);
+        metrics.merge(&MetricsReporter::get_metrics(&*self.layout_engine.read().await).await);
+        metrics.merge(&MetricsReporter::get_metrics(&*self.rendering_engine.read().await).await);
+        metrics
+    }
+    
+    /// Render engine metrics in the Prometheus text exposition format
+    pub async fn metrics(&self) -> String {
+        let network = self.network_stack.get_metrics().await;
+        let js = self.js_runtime.read().await.get_execution_stats();
+        let layout = self.layout_engine.read().await.get_metrics();
+        let rendering = self.rendering_engine.read().await.get_metrics();
+        let security_events = self.security.get_security_events(None).await;
+        
+        let mut writer = MetricsWriter::default();
+        
+        writer.counter("titan_network_requests_total", "Network requests issued", network.total_requests as f64);
+        writer.counter("titan_network_failed_requests_total", "Network requests that failed", network.failed_requests as f64);
+        writer.counter("titan_network_sent_bytes_total", "Bytes sent over the network", network.total_bytes_sent as f64);
+        writer.counter("titan_network_received_bytes_total", "Bytes received over the network", network.total_bytes_received as f64);
+        writer.counter("titan_network_cache_hits_total", "HTTP cache hits", network.cache_hits as f64);
+        writer.counter("titan_network_cache_misses_total", "HTTP cache misses", network.cache_misses as f64);
+        writer.gauge("titan_network_average_load_seconds", "Average page load time", network.average_load_time.as_secs_f64());
+        
+        writer.counter("titan_js_executions_total", "Scripts executed", js.total_executions as f64);
+        writer.counter("titan_js_execution_seconds_total", "Time spent executing scripts", js.total_execution_time.as_secs_f64());
+        writer.counter("titan_js_errors_total", "Script executions that failed", js.errors as f64);
+        writer.counter("titan_js_timeouts_total", "Script executions that timed out", js.timeouts as f64);
+        writer.counter("titan_js_security_violations_total", "Scripts blocked by security checks", js.security_violations as f64);
+        
+        writer.gauge("titan_layout_last_duration_seconds", "Duration of the last layout pass", layout.layout_time_ms as f64 / 1000.0);
+        writer.gauge("titan_layout_elements", "Elements laid out in the last layout pass", layout.elements_laid_out as f64);
+        writer.counter("titan_layout_invalidations_total", "Incremental layout invalidations", layout.layout_invalidations as f64);
+        
+        writer.counter("titan_render_frames_total", "Frames rendered", rendering.frames_rendered as f64);
+        writer.gauge("titan_render_frame_seconds", "Duration of the last frame", rendering.frame_time_ms / 1000.0);
+        writer.gauge("titan_render_draw_calls", "Draw calls in the last frame", rendering.draw_calls as f64);
+        
+        let mut event_counts: std::collections::BTreeMap<(String, bool), u64> = std::collections::BTreeMap::new();
+        for event in &security_events {
+            *event_counts.entry((format!("{:?}", event.event_type), event.blocked)).or_insert(0) += 1;
+        }
+        let samples: Vec<(Vec<(&str, String)>, f64)> = event_counts.into_iter()
+            .map(|((event_type, blocked), count)| {
+                (vec![("type", event_type), ("blocked", blocked.to_string())], count as f64)
+            })
+            .collect();
+        writer.labeled("titan_security_events_total", "counter", "Security events by type", &samples);
+        
+        writer.finish()
+    }
+    
+    /// Shutdown the engine gracefully, attempting every subsystem even if some fail
+    pub async fn shutdown(&self) -> Result<()> {
+        log::info!("Shutting down Titan Browser Engine");
+        
+        // Shutdown subsystems in reverse order of creation
+        let subsystems: Vec<(&'static str, LocalBoxFuture<'_, core::Result<()>>)> = vec![
+            ("ai", Box::pin(self.ai_engine.shutdown())),
+            ("security", Box::pin(self.security.shutdown())),
+            ("storage", Box::pin(self.storage.shutdown())),
+            ("media", Box::pin(async { self.media_engine.read().await.shutdown().await })),
+            ("network", Box::pin(self.network_stack.shutdown())),
+            ("javascript", Box::pin(async { self.js_runtime.write().await.shutdown().await })),
+            ("rendering", Box::pin(async { self.rendering_engine.write().await.shutdown().await })),
+            ("layout", Box::pin(async { self.layout_engine.read().await.shutdown().await })),
+            ("css", Box::pin(self.css_engine.shutdown())),
+            ("html", Box::pin(self.html_parser.shutdown())),
+        ];
+        
+        Ok(shutdown_all(subsystems).await?)
+    }
+}
+
+/// Run each shutdown in order, continuing past failures, and report every failure together
+async fn shutdown_all(subsystems: Vec<(&'static str, LocalBoxFuture<'_, core::Result<()>>)>) -> core::Result<()> {
+    let mut failures = Vec::new();
+    for (name, shutdown) in subsystems {
+        if let Err(e) = shutdown.await {
+            log::error!("Failed to shut down {}: {}", name, e);
+            failures.push(format!("{}: {}", name, e));
+        }
+    }
+    
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(core::EngineError::InternalError(format!("Shutdown failed for {}", failures.join("; "))))
+    }
+}
+
+/// Builds Prometheus text exposition output
+#[derive(Default)]
+struct MetricsWriter {
+    output: String,
+}
+
+impl MetricsWriter {
+    fn counter(&mut self, name: &str, help: &str, value: f64) {
+        self.labeled(name, "counter", help, &[(Vec::new(), value)]);
+    }
+    
+    fn gauge(&mut self, name: &str, help: &str, value: f64) {
+        self.labeled(name, "gauge", help, &[(Vec::new(), value)]);
+    }
+    
+    fn labeled(&mut self, name: &str, kind: &str, help: &str, samples: &[(Vec<(&str, String)>, f64)]) {
+        use std::fmt::Write;
+        
+        let _ = writeln!(self.output, "# HELP {} {}", name, help);
+        let _ = writeln!(self.output, "# TYPE {} {}", name, kind);
+        for (labels, value) in samples {
+            if labels.is_empty() {
+                let _ = writeln!(self.output, "{} {}", name, value);
+            } else {
+                let labels: Vec<String> = labels.iter()
+                    .map(|(key, value)| {
+                        let escaped = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+                        format!("{}=\"{}\"", key, escaped)
+                    })
+                    .collect();
+                let _ = writeln!(self.output, "{}{{{}}} {}", name, labels.join(","), value);
+            }
+        }
+    }
+    
+    fn finish(self) -> String {
+        self.output
+    }
 }
 
 /// Engine configuration options
@@ -199,6 +1294,29 @@ impl Default for EngineConfig {
     }
 }
 
+/// Limits on what a single navigation may fetch; `None` means unlimited
+#[derive(Debug, Clone, Copy)]
+pub struct NavigationBudget {
+    /// Total response bytes across the document and its subresources
+    pub max_bytes: Option<u64>,
+    
+    /// Wall-clock time from the start of the navigation
+    pub max_duration: Option<Duration>,
+    
+    /// Number of fetched resources, including the document
+    pub max_resources: Option<usize>,
+}
+
+impl Default for NavigationBudget {
+    fn default() -> Self {
+        Self {
+            max_bytes: Some(50 * 1024 * 1024),
+            max_duration: Some(Duration::from_secs(60)),
+            max_resources: Some(256),
+        }
+    }
+}
+
 /// Initialize logging for the engine
 pub fn init_logging() {
     env_logger::Builder::from_default_env()
@@ -210,6 +1328,26 @@ pub fn init_logging() {
 mod tests {
     use super::*;
     
+    fn test_response(url: &str, html: &str) -> networking::NetworkResponse {
+        networking::NetworkResponse {
+            url: url.to_string(),
+            status: 200,
+            headers: std::collections::HashMap::new(),
+            body: html.to_string(),
+            body_bytes: html.as_bytes().to_vec(),
+            load_time: Duration::from_millis(10),
+            from_cache: false,
+            security_info: networking::SecurityInfo {
+                https: true,
+                hsts: false,
+                csp: None,
+                x_frame_options: None,
+                x_content_type_options: None,
+                tls: None,
+            },
+        }
+    }
+    
     #[tokio::test]
     async fn test_engine_initialization() {
         init_logging();
@@ -217,6 +1355,139 @@ mod tests {
         engine.shutdown().await.unwrap();
     }
     
+    #[tokio::test]
+    async fn test_iframe_loading_and_sandbox() {
+        let engine = TitanEngine::new().await.unwrap();
+        
+        let html = r#"<html><body>
+            <iframe id="sandboxed" sandbox srcdoc="<script>globalThis.ran = true;</script><p>Sandboxed</p>"></iframe>
+            <iframe id="trusted" srcdoc="<p id='inner'>Hello from srcdoc</p>"></iframe>
+        </body></html>"#;
+        let mut document = engine.html_parser.parse(html).unwrap();
+        document.url = "https://example.com/".to_string();
+        let sandboxed_id = document.get_element_by_id("sandboxed").unwrap().id;
+        let trusted_id = document.get_element_by_id("trusted").unwrap().id;
+        
+        let page = engine.load_document(document, test_response("https://example.com/", html)).await.unwrap();
+        assert_eq!(page.frames.len(), 2);
+        
+        // Sandboxed without allow-scripts: opaque origin, scripts never run
+        let sandboxed = page.frames.iter().find(|f| f.element_id == sandboxed_id).unwrap();
+        assert!(!sandboxed.scripts_executed);
+        assert_eq!(sandboxed.origin, "null");
+        
+        // srcdoc content inherits the parent's origin and is drawn inside the iframe's box
+        let trusted = page.frames.iter().find(|f| f.element_id == trusted_id).unwrap();
+        assert_eq!(trusted.url, "about:srcdoc");
+        assert_eq!(trusted.origin, "https://example.com");
+        
+        let inner_id = trusted.document.get_element_by_id("inner").unwrap().id;
+        let inner_node = page.render_tree.get_render_node(inner_id).unwrap();
+        assert_eq!(inner_node.clip_rect, Some(trusted.viewport));
+        assert_eq!(page.render_tree.frames.get(&trusted_id), Some(&trusted.document.root.id));
+        
+        engine.shutdown().await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_navigation_resource_budget() {
+        let engine = TitanEngine::new().await.unwrap();
+        engine.set_navigation_budget(NavigationBudget {
+            max_resources: Some(1),
+            ..NavigationBudget::default()
+        }).await;
+        let mut events = engine.subscribe_events();
+        
+        let html = r#"<html><head><title>Budgeted</title></head><body>
+            <iframe id="inline" srcdoc="<p>Inline</p>"></iframe>
+            <iframe id="remote" src="/frame-a.html"></iframe>
+            <iframe id="other" src="/frame-b.html"></iframe>
+        </body></html>"#;
+        let mut document = engine.html_parser.parse(html).unwrap();
+        document.url = "https://example.com/".to_string();
+        let inline_id = document.get_element_by_id("inline").unwrap().id;
+        
+        // The document itself uses the only resource, so neither remote frame is fetched
+        let page = engine.load_document(document, test_response("https://example.com/", html)).await.unwrap();
+        assert_eq!(page.metadata.title, "Budgeted");
+        assert_eq!(page.frames.len(), 1);
+        assert_eq!(page.frames[0].element_id, inline_id);
+        assert_eq!(engine.network_stack.get_metrics().await.total_requests, 0);
+        
+        // The event fires once for the navigation, not once per skipped fetch
+        match events.try_recv().unwrap() {
+            core::BrowserEvent::NavigationBudgetExceeded { url, limit, resources_loaded, .. } => {
+                assert_eq!(url, "https://example.com/");
+                assert_eq!(limit, core::BudgetLimit::Resources);
+                assert_eq!(resources_loaded, 1);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(events.try_recv().is_err());
+        
+        engine.shutdown().await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_prometheus_metrics() {
+        let engine = TitanEngine::new().await.unwrap();
+        engine.js_runtime.write().await.execute_script("1 + 1", "test").await.unwrap();
+        
+        let metrics = engine.metrics().await;
+        assert!(metrics.contains("# TYPE titan_network_requests_total counter\ntitan_network_requests_total 0\n"));
+        assert!(metrics.contains("# TYPE titan_js_executions_total counter\ntitan_js_executions_total 1\n"));
+        
+        // Every sample line is `name[{labels}] value`
+        for line in metrics.lines().filter(|line| !line.starts_with('#')) {
+            let (name, value) = line.rsplit_once(' ').unwrap();
+            assert!(value.parse::<f64>().is_ok(), "bad value in {:?}", line);
+            let name = name.split('{').next().unwrap();
+            assert!(name.starts_with("titan_"), "bad name in {:?}", line);
+            assert!(name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'), "bad name in {:?}", line);
+        }
+        
+        engine.shutdown().await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_subsystem_panic_is_isolated() {
+        let engine = TitanEngine::new().await.unwrap();
+        
+        // Panic mid-layout while holding the layout engine's lock
+        let result: core::Result<()> = engine.isolate(core::Subsystem::Layout, async {
+            let _layout_engine = engine.layout_engine.write().await;
+            panic!("bad geometry");
+        }).await;
+        match result {
+            Err(core::EngineError::InternalError(message)) => {
+                assert_eq!(message, "layout subsystem panicked: bad geometry");
+            }
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+        
+        // The engine, including the layout lock, is still usable
+        let html = "<html><body><p>Still works</p></body></html>";
+        let document = engine.html_parser.parse(html).unwrap();
+        let page = engine.load_document(document, test_response("https://example.com/", html)).await;
+        assert!(page.is_ok());
+        
+        // Script panics would unwind through V8 frames, so JavaScript is never isolated
+        let mut isolation = core::PanicIsolation::all();
+        assert!(!isolation.is_isolated(core::Subsystem::JavaScript));
+        isolation.set(core::Subsystem::JavaScript, true);
+        assert!(!isolation.is_isolated(core::Subsystem::JavaScript));
+        
+        // Without isolation the panic propagates
+        isolation.set(core::Subsystem::Layout, false);
+        engine.set_panic_isolation(isolation).await;
+        let caught = futures::FutureExt::catch_unwind(std::panic::AssertUnwindSafe(
+            engine.isolate::<()>(core::Subsystem::Layout, async { panic!("bad geometry") })
+        )).await;
+        assert!(caught.is_err());
+        
+        engine.shutdown().await.unwrap();
+    }
+    
     #[tokio::test]
     async fn test_simple_page_load() {
         init_logging();
@@ -227,4 +1498,459 @@ mod tests {
         
         engine.shutdown().await.unwrap();
     }
-}
\ No newline at end of file
+    
+    #[tokio::test]
+    async fn test_load_events() {
+        struct Recorder(std::sync::Mutex<Vec<core::BrowserEvent>>);
+        
+        #[async_trait::async_trait]
+        impl core::EventHandler for Recorder {
+            async fn handle_event(&self, event: core::BrowserEvent) -> core::Result<()> {
+                self.0.lock().unwrap().push(event);
+                Ok(())
+            }
+        }
+        
+        let engine = TitanEngine::new().await.unwrap();
+        let recorder = Arc::new(Recorder(std::sync::Mutex::new(Vec::new())));
+        engine.add_event_handler(recorder.clone()).await;
+        let mut events = engine.subscribe_events();
+        
+        let html = "<html><body><p>Hello</p></body></html>";
+        let mut document = engine.html_parser.parse(html).unwrap();
+        document.url = "https://example.com/".to_string();
+        engine.load_document(document, test_response("https://example.com/", html)).await.unwrap();
+        
+        let mut received = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            received.push(event);
+        }
+        assert_eq!(received.len(), recorder.0.lock().unwrap().len());
+        
+        let summary: Vec<String> = received.iter().map(|event| match event {
+            core::BrowserEvent::NavigationStarted { url } => format!("started {}", url),
+            core::BrowserEvent::LoadProgress { progress } => format!("progress {}", progress),
+            core::BrowserEvent::LoadCompleted { url, success, .. } => format!("completed {} {}", url, success),
+            other => format!("{:?}", other),
+        }).collect();
+        assert_eq!(summary, [
+            "started https://example.com/",
+            "progress 0.75",
+            "progress 1",
+            "completed https://example.com/ true",
+        ]);
+        
+        // A failed load still reports completion
+        assert!(engine.load_page("not a url").await.is_err());
+        assert!(matches!(events.try_recv(), Ok(core::BrowserEvent::NavigationStarted { .. })));
+        assert!(matches!(events.try_recv(), Ok(core::BrowserEvent::LoadCompleted { success: false, .. })));
+    }
+    
+    #[tokio::test]
+    async fn test_cancel_in_flight_load() {
+        use hyper::service::{make_service_fn, service_fn};
+        use std::sync::atomic::{AtomicBool, Ordering};
+        
+        // The response never arrives in time, so only cancellation can end the load
+        let responded = Arc::new(AtomicBool::new(false));
+        let server_responded = responded.clone();
+        let make_service = make_service_fn(move |_| {
+            let responded = server_responded.clone();
+            async move {
+                Ok::<_, std::convert::Infallible>(service_fn(move |_request| {
+                    let responded = responded.clone();
+                    async move {
+                        tokio::time::sleep(Duration::from_secs(30)).await;
+                        responded.store(true, Ordering::SeqCst);
+                        Ok::<_, std::convert::Infallible>(hyper::Response::new(hyper::Body::from("<p>late</p>")))
+                    }
+                }))
+            }
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        
+        let mut engine = TitanEngine::new().await.unwrap();
+        let mut network = networking::NetworkStack::new().await.unwrap();
+        network.update_security_config(networking::SecurityConfig {
+            require_https: false,
+            ..networking::SecurityConfig::default()
+        });
+        engine.network_stack = Arc::new(network);
+        let mut events = engine.subscribe_events();
+        
+        let cancel = CancellationToken::new();
+        let canceller = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            canceller.cancel();
+        });
+        
+        let started = Instant::now();
+        let url = format!("http://{}/", addr);
+        let result = engine.load_page_cancellable(&url, &cancel).await;
+        
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<core::EngineError>(),
+            Some(core::EngineError::Cancelled(_))
+        ));
+        assert!(!responded.load(Ordering::SeqCst));
+        assert!(engine.current_page().await.is_none());
+        
+        // Nothing past the fetch ran, but completion is still reported
+        assert!(matches!(events.try_recv(), Ok(core::BrowserEvent::NavigationStarted { .. })));
+        assert!(matches!(events.try_recv(), Ok(core::BrowserEvent::LoadCompleted { success: false, .. })));
+    }
+
+    #[tokio::test]
+    async fn test_page_events_dispatch_websocket_messages() {
+        use futures::{SinkExt, StreamExt};
+        
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut server = tokio_tungstenite::accept_async(socket).await.unwrap();
+            server.send(tokio_tungstenite::tungstenite::Message::Text("hello".to_string())).await.unwrap();
+            while server.next().await.is_some() {}
+        });
+        
+        let engine = TitanEngine::new().await.unwrap();
+        let mut network = networking::NetworkStack::new().await.unwrap();
+        network.update_security_config(networking::SecurityConfig {
+            require_https: false,
+            ..networking::SecurityConfig::default()
+        });
+        {
+            let mut js_runtime = engine.js_runtime.write().await;
+            js_runtime.set_network_stack(Arc::new(network));
+            js_runtime.update_security_settings(javascript::SecuritySettings {
+                allow_network_access: true,
+                ..javascript::SecuritySettings::default()
+            });
+        }
+        
+        let url = format!("http://{}/", addr);
+        let html = format!(
+            "<html><body><script>let received; new WebSocket('ws://{}/').onmessage = (event) => {{ received = event.data; }};</script></body></html>",
+            addr,
+        );
+        let mut document = engine.html_parser.parse(&html).unwrap();
+        document.url = url.clone();
+        let page = engine.load_document(document, test_response(&url, &html)).await.unwrap();
+        
+        // The message arrives after loading finishes, so it is delivered by the event loop
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while engine.evaluate_in_page(&page, "typeof received").await.unwrap().to_string() != "string" {
+            assert!(Instant::now() < deadline, "message was never dispatched");
+            engine.run_page_events(&page, Duration::from_millis(100)).await.unwrap();
+        }
+        assert_eq!(engine.evaluate_in_page(&page, "received").await.unwrap().to_string(), "hello");
+    }
+
+    #[tokio::test]
+    async fn test_collect_metrics_reflects_recorded_work() {
+        let engine = TitanEngine::new().await.unwrap();
+        let html = "<html><body><p>Hello</p></body></html>";
+        let mut document = engine.html_parser.parse(html).unwrap();
+        document.url = "https://example.com/".to_string();
+        engine.load_document(document, test_response("https://example.com/", html)).await.unwrap();
+        engine.js_runtime.write().await
+            .execute_script("let total = 0; for (let i = 0; i < 5000000; i++) { total += i; }", "busy.js")
+            .await
+            .unwrap();
+        let mut render_tree = RenderTree {
+            root_element_id: core::ElementId::new(),
+            render_nodes: std::collections::HashMap::new(),
+            frames: std::collections::HashMap::new(),
+            dirty_nodes: std::collections::HashSet::new(),
+        };
+        engine.rendering_engine.write().await.render_frame(&mut render_tree).await.unwrap();
+        
+        let metrics = engine.collect_metrics().await;
+        let js = engine.js_runtime.read().await.get_execution_stats();
+        let layout = engine.layout_engine.read().await.get_metrics();
+        let rendering = engine.rendering_engine.read().await.get_metrics();
+        
+        assert!(js.total_execution_time.as_millis() > 0);
+        assert_eq!(metrics.javascript_execution_time_ms, js.total_execution_time.as_millis() as u64);
+        assert_eq!(metrics.layout_time_ms, layout.layout_time_ms);
+        assert_eq!(metrics.paint_time_ms, rendering.frame_time_ms.round() as u64);
+        assert!(metrics.render_fps > 0.0);
+        assert!(metrics.memory_usage_mb > 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_in_page() {
+        let engine = TitanEngine::new().await.unwrap();
+        let html = r#"<html><body><div id="x">Hello</div></body></html>"#;
+        let mut document = engine.html_parser.parse(html).unwrap();
+        document.url = "https://example.com/".to_string();
+        let page = engine.load_document(document, test_response("https://example.com/", html)).await.unwrap();
+        
+        match engine.evaluate_in_page(&page, "document.getElementById('x') != null").await.unwrap() {
+            javascript::JSValue::Boolean(found) => assert!(found),
+            other => panic!("Expected boolean, got {:?}", other),
+        }
+        
+        // Mutations land in the current page's document
+        engine.evaluate_in_page(&page, "document.getElementById('x').setAttribute('class', 'seen')").await.unwrap();
+        let current = engine.current_page().await.unwrap();
+        let element = current.document.get_element_by_id("x").unwrap();
+        assert_eq!(element.get_attribute("class"), Some("seen"));
+        assert!(current.document.dirty_elements.contains(&element.id));
+    }
+
+    #[tokio::test]
+    async fn test_scrolling_container_moves_hit_testing() {
+        let engine = TitanEngine::new().await.unwrap();
+        let html = r#"<html><body><div id="scroller" style="overflow: scroll; width: 100px; height: 100px"><div id="first" style="height: 100px"></div><div id="second" style="height: 100px"></div></div></body></html>"#;
+        let mut document = engine.html_parser.parse(html).unwrap();
+        document.url = "https://example.com/".to_string();
+        let scroller = document.get_element_by_id("scroller").unwrap().id;
+        let first = document.get_element_by_id("first").unwrap().id;
+        let second = document.get_element_by_id("second").unwrap().id;
+        engine.load_document(document, test_response("https://example.com/", html)).await.unwrap();
+        
+        let point = core::Point::new(50.0, 50.0);
+        assert_eq!(engine.layout_engine.read().await.hit_test(point), Some(first));
+        // The second child overflows the container and is clipped away
+        assert_ne!(engine.layout_engine.read().await.hit_test(core::Point::new(50.0, 150.0)), Some(second));
+        
+        // Scrolling past the end stops at the bottom of the content
+        let state = engine.scroll_by(scroller, 0.0, 500.0).await.unwrap();
+        assert_eq!((state.scroll_x, state.scroll_y), (0.0, 100.0));
+        assert_eq!(state.content_size.height, 200.0);
+        assert_eq!(engine.layout_engine.read().await.hit_test(point), Some(second));
+        
+        let page = engine.current_page().await.unwrap();
+        let (_, y) = page.render_tree.get_render_node(second).unwrap().transform.transform_point(0.0, 100.0);
+        assert_eq!(y, 0.0);
+        assert!(page.render_tree.is_dirty(second));
+        
+        let state = engine.scroll_to(scroller, 0.0, 0.0).await.unwrap();
+        assert_eq!(state.scroll_y, 0.0);
+        assert_eq!(engine.layout_engine.read().await.hit_test(point), Some(first));
+        assert!(engine.scroll_by(first, 0.0, 10.0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_click_runs_inline_handler() {
+        let engine = TitanEngine::new().await.unwrap();
+        let html = r#"<html><body><div id="button" style="width: 100px; height: 40px" onclick="clicked = event.type"></div></body></html>"#;
+        let mut document = engine.html_parser.parse(html).unwrap();
+        document.url = "https://example.com/".to_string();
+        let page = engine.load_document(document, test_response("https://example.com/", html)).await.unwrap();
+        let mut events = engine.subscribe_events();
+        
+        let default_allowed = engine.dispatch_event(
+            &page,
+            core::InteractionType::Click,
+            core::InteractionInput::Pointer(core::Point::new(10.0, 10.0)),
+        ).await.unwrap();
+        assert!(default_allowed);
+        
+        match engine.evaluate_in_page(&page, "clicked").await.unwrap() {
+            javascript::JSValue::String(event_type) => assert_eq!(event_type, "click"),
+            other => panic!("Expected the handler to set a string, got {:?}", other),
+        }
+        
+        let button = page.document.get_element_by_id("button").unwrap().id;
+        match events.recv().await.unwrap() {
+            core::BrowserEvent::UserInteraction { element_id, .. } => assert_eq!(element_id, Some(button)),
+            other => panic!("Expected a UserInteraction event, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_favicon_discovery_prefers_largest_declared_icon() {
+        let parser = html::HTMLParser::new();
+        let page_url = url::Url::parse("https://example.com/blog/post.html").unwrap();
+        
+        let document = parser.parse(r#"<html><head>
+            <link rel="shortcut icon" href="/small.ico" sizes="16x16">
+            <link rel="icon" href="icons/large.png" sizes="32x32 192x192">
+            <link rel="apple-touch-icon" href="/touch.png" sizes="512x512">
+            <link rel="stylesheet" href="/style.css">
+        </head><body></body></html>"#).unwrap();
+        let candidates: Vec<String> = favicon_candidates(&document, &page_url).iter().map(|url| url.to_string()).collect();
+        assert_eq!(candidates, vec![
+            "https://example.com/blog/icons/large.png".to_string(),
+            "https://example.com/favicon.ico".to_string(),
+        ]);
+        
+        // Without a declared icon only the origin's /favicon.ico is tried
+        let document = parser.parse("<html><head></head><body></body></html>").unwrap();
+        let candidates: Vec<String> = favicon_candidates(&document, &page_url).iter().map(|url| url.to_string()).collect();
+        assert_eq!(candidates, vec!["https://example.com/favicon.ico".to_string()]);
+    }
+
+    /// Subsystem stand-in that records its shutdown and optionally fails it
+    struct TestSubsystem {
+        name: &'static str,
+        fail: bool,
+        shut_down: Arc<std::sync::Mutex<Vec<&'static str>>>,
+    }
+    
+    #[async_trait::async_trait]
+    impl Shutdown for TestSubsystem {
+        async fn shutdown(&self) -> core::Result<()> {
+            self.shut_down.lock().unwrap().push(self.name);
+            if self.fail {
+                return Err(core::EngineError::InternalError("device lost".to_string()));
+            }
+            Ok(())
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_shutdown_continues_past_failing_subsystem() {
+        let shut_down = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let subsystem = |name, fail| TestSubsystem { name, fail, shut_down: shut_down.clone() };
+        let (first, failing, last) = (subsystem("ai", false), subsystem("rendering", true), subsystem("html", false));
+        let css = css::CSSEngine::new();
+        
+        let result = shutdown_all(vec![
+            ("ai", first.shutdown()),
+            ("rendering", failing.shutdown()),
+            ("css", css.shutdown()),
+            ("html", last.shutdown()),
+        ]).await;
+        
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("rendering: Internal error"), "{}", error);
+        assert!(!error.contains("html"), "{}", error);
+        assert_eq!(*shut_down.lock().unwrap(), vec!["ai", "rendering", "html"]);
+    }
+
+    #[tokio::test]
+    async fn test_set_permission_gates_geolocation() {
+        let engine = TitanEngine::with_settings(core::EngineSettings {
+            geolocation_enabled: true,
+            ..core::EngineSettings::default()
+        }).await.unwrap();
+        let origin = "https://maps.titan.test";
+        let mut js_runtime = engine.create_js_runtime().await.unwrap();
+        js_runtime.set_origin(origin);
+        js_runtime.set_geolocation(Some(javascript::GeoPosition { latitude: 51.5, longitude: -0.12, accuracy: 10.0 }));
+        let locate = "navigator.geolocation.getCurrentPosition(\
+            p => { globalThis.located = String(p.coords.latitude); }, \
+            e => { globalThis.located = 'error ' + e.code; });";
+        
+        engine.set_permission(origin, security::PermissionKind::Geolocation, true).await.unwrap();
+        js_runtime.execute_script(locate, "test").await.unwrap();
+        assert_eq!(js_runtime.execute_script("located", "test").await.unwrap().to_string(), "51.5");
+        
+        engine.set_permission(origin, security::PermissionKind::Geolocation, false).await.unwrap();
+        js_runtime.execute_script(locate, "test").await.unwrap();
+        assert_eq!(js_runtime.execute_script("located", "test").await.unwrap().to_string(), "error 1");
+        assert!(engine.storage.list_permissions().await.unwrap()
+            .contains(&(origin.to_string(), "geolocation".to_string(), false)));
+    }
+    
+    #[tokio::test]
+    async fn test_save_page_archive_inlines_subresources() {
+        use hyper::service::{make_service_fn, service_fn};
+        
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, std::convert::Infallible>(service_fn(|request: hyper::Request<hyper::Body>| async move {
+                let (content_type, body) = match request.uri().path() {
+                    "/style.css" => ("text/css", b"p { color: red; }".to_vec()),
+                    "/logo.png" => ("image/png", vec![0x89, b'P', b'N', b'G']),
+                    _ => ("text/javascript", b"console.log('hi')".to_vec()),
+                };
+                let response = hyper::Response::builder()
+                    .header("content-type", content_type)
+                    .body(hyper::Body::from(body))
+                    .unwrap();
+                Ok::<_, std::convert::Infallible>(response)
+            }))
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        
+        let mut engine = TitanEngine::new().await.unwrap();
+        let mut network = networking::NetworkStack::new().await.unwrap();
+        network.update_security_config(networking::SecurityConfig {
+            require_https: false,
+            ..networking::SecurityConfig::default()
+        });
+        engine.network_stack = Arc::new(network);
+        
+        let url = format!("http://{}/", addr);
+        let html = r#"<html><head><link rel="stylesheet" href="style.css" media="screen"><script src="/app.js"></script></head>
+            <body><p>Offline</p><img src="/logo.png"><a href="/next">next</a></body></html>"#;
+        let mut document = engine.html_parser.parse(html).unwrap();
+        document.url = url.clone();
+        let page = engine.load_document(document, test_response(&url, html)).await.unwrap();
+        
+        let archive = engine.save_page_archive(&page).await.unwrap();
+        assert!(archive.contains(r#"<style media="screen">p { color: red; }</style>"#), "{}", archive);
+        assert!(archive.contains(r#"src="data:image/png;base64,iVBORw==""#), "{}", archive);
+        assert!(archive.contains(&format!(r#"<base href="{}">"#, url)));
+        assert!(!archive.contains("<link") && !archive.contains("<script"));
+        
+        // Scripts on request, and resources that don't fit under the cap stay as links:
+        // room for the stylesheet and script but not the image
+        let unlimited = ArchiveOptions { include_scripts: true, max_bytes: usize::MAX };
+        let bare = archive::build_archive(&page.document, Vec::new(), &unlimited).unwrap().len();
+        let options = ArchiveOptions { include_scripts: true, max_bytes: bare + 40 };
+        let archive = engine.save_page_archive_with(&page, &options).await.unwrap();
+        assert!(archive.contains("<script>console.log('hi')</script>"), "{}", archive);
+        assert!(archive.contains(r#"src="/logo.png""#), "{}", archive);
+        
+        engine.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_video_poster_is_painted_until_playback() {
+        use hyper::service::{make_service_fn, service_fn};
+        
+        let mut poster = Vec::new();
+        image::RgbaImage::from_pixel(4, 3, image::Rgba([0, 0, 255, 255]))
+            .write_to(&mut std::io::Cursor::new(&mut poster), image::ImageOutputFormat::Png)
+            .unwrap();
+        let make_service = make_service_fn(move |_| {
+            let poster = poster.clone();
+            async move {
+                Ok::<_, std::convert::Infallible>(service_fn(move |request: hyper::Request<hyper::Body>| {
+                    let response = match request.uri().path() {
+                        "/poster.png" => hyper::Response::new(hyper::Body::from(poster.clone())),
+                        _ => hyper::Response::builder().status(404).body(hyper::Body::empty()).unwrap(),
+                    };
+                    async move { Ok::<_, std::convert::Infallible>(response) }
+                }))
+            }
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        
+        let mut engine = TitanEngine::new().await.unwrap();
+        let mut network = networking::NetworkStack::new().await.unwrap();
+        network.update_security_config(networking::SecurityConfig {
+            require_https: false,
+            ..networking::SecurityConfig::default()
+        });
+        engine.network_stack = Arc::new(network);
+        
+        // preload="none" registers the element without opening the stream
+        let url = format!("http://{}/", addr);
+        let html = r#"<html><body><video src="movie.webm" poster="poster.png" preload="none"></video></body></html>"#;
+        let mut document = engine.html_parser.parse(html).unwrap();
+        document.url = url.clone();
+        let video_id = document.get_elements_by_tag_name("video")[0].id;
+        let page = engine.load_document(document, test_response(&url, html)).await.unwrap();
+        
+        let poster_url = format!("http://{}/poster.png", addr);
+        assert_eq!(engine.media_engine.read().await.visible_poster(video_id), Some(poster_url.as_str()));
+        let image_key = engine.rendering_engine.read().await.image_key(&poster_url).unwrap();
+        let video_node = page.render_tree.get_render_node(video_id).unwrap();
+        assert!(video_node.display_items.iter().any(|item| matches!(item, rendering::DisplayItem::Image { image_key: key, .. } if *key == image_key)));
+        
+        engine.shutdown().await.unwrap();
+    }
+}
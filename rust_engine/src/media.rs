@@ -1,11 +1,13 @@
 //! Media engine for audio/video processing using GStreamer
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
+use std::time::Instant;
 use gstreamer as gst;
 use gstreamer_video as gst_video;
 use gstreamer_audio as gst_audio;
 use crate::core::{ElementId, Result, EngineError};
+use crate::html::Element;
 
 /// Media engine for handling audio and video content
 pub struct MediaEngine {
@@ -17,73 +19,273 @@ pub struct MediaEngine {
     
     /// Media capabilities
     capabilities: MediaCapabilities,
+    
+    /// Media configuration
+    config: MediaConfig,
+    
+    /// Elements waiting for a free pipeline slot before they can play
+    play_queue: VecDeque<ElementId>,
+    
+    /// Broadcast channel for media lifecycle events
+    event_sender: tokio::sync::broadcast::Sender<MediaEvent>,
 }
 
 impl MediaEngine {
     /// Create a new media engine
     pub async fn new() -> Result<Self> {
+        Self::with_config(MediaConfig::default()).await
+    }
+    
+    /// Create a new media engine with custom configuration
+    pub async fn with_config(config: MediaConfig) -> Result<Self> {
         // Initialize GStreamer
         gst::init().map_err(|e| EngineError::MediaError(format!("Failed to initialize GStreamer: {}", e)))?;
         
         let capabilities = MediaCapabilities::detect().await?;
+        let (event_sender, _) = tokio::sync::broadcast::channel(64);
         
         Ok(Self {
             pipeline_manager: Arc::new(tokio::sync::RwLock::new(PipelineManager::new())),
             media_elements: HashMap::new(),
             capabilities,
+            config,
+            play_queue: VecDeque::new(),
+            event_sender,
         })
     }
     
+    /// Subscribe to media lifecycle events
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<MediaEvent> {
+        self.event_sender.subscribe()
+    }
+    
+    /// Number of elements currently holding a live (playing or paused) pipeline
+    pub fn active_pipeline_count(&self) -> usize {
+        self.media_elements.values()
+            .filter(|e| e.pipeline.is_some() && matches!(e.state, MediaState::Playing | MediaState::Paused))
+            .count()
+    }
+    
+    /// Number of play requests waiting for a free pipeline slot
+    pub fn queued_play_count(&self) -> usize {
+        self.play_queue.len()
+    }
+    
+    fn emit(&self, event: MediaEvent) {
+        // No receivers is not an error
+        let _ = self.event_sender.send(event);
+    }
+    
     /// Create a media element for video playback
     pub async fn create_video_element(&mut self, element_id: ElementId, src: &str) -> Result<()> {
-        let pipeline = self.create_video_pipeline(src).await?;
-        
-        let media_element = MediaElement {
-            element_id,
-            media_type: MediaType::Video,
-            src: src.to_string(),
-            pipeline: Some(pipeline),
-            state: MediaState::Stopped,
-            volume: 1.0,
-            muted: false,
-            current_time: 0.0,
-            duration: 0.0,
+        self.create_media_element(element_id, MediaType::Video, src, MediaAttributes::default()).await
+    }
+    
+    /// Create a media element for audio playback
+    pub async fn create_audio_element(&mut self, element_id: ElementId, src: &str) -> Result<()> {
+        self.create_media_element(element_id, MediaType::Audio, src, MediaAttributes::default()).await
+    }
+    
+    /// Create a media element from a parsed `<video>` or `<audio>` element, honoring its attributes
+    pub async fn create_element_from_html(&mut self, element: &Element) -> Result<()> {
+        let media_type = match element.tag_name.to_ascii_lowercase().as_str() {
+            "video" => MediaType::Video,
+            "audio" => MediaType::Audio,
+            other => return Err(EngineError::MediaError(format!("Not a media element: <{}>", other))),
         };
         
-        self.media_elements.insert(element_id, media_element);
+        let src = element.get_attribute("src")
+            .ok_or_else(|| EngineError::MediaError("Media element has no src".to_string()))?;
         
-        Ok(())
+        self.create_media_element(element.id, media_type, src, MediaAttributes::from_element(element)).await
     }
     
-    /// Create a media element for audio playback
-    pub async fn create_audio_element(&mut self, element_id: ElementId, src: &str) -> Result<()> {
-        let pipeline = self.create_audio_pipeline(src).await?;
+    /// Create a media element, buffering according to `preload` and applying the autoplay policy
+    pub async fn create_media_element(
+        &mut self,
+        element_id: ElementId,
+        media_type: MediaType,
+        src: &str,
+        attributes: MediaAttributes,
+    ) -> Result<()> {
+        // preload="none" defers pipeline creation until the first play request
+        let pipeline = match attributes.preload {
+            PreloadHint::None => None,
+            PreloadHint::Metadata | PreloadHint::Auto => {
+                let pipeline = match media_type {
+                    MediaType::Video => self.create_video_pipeline(src).await?,
+                    MediaType::Audio => self.create_audio_pipeline(src).await?,
+                };
+                
+                // Ready opens the source; Paused prerolls and starts buffering data
+                let target_state = if attributes.preload == PreloadHint::Auto {
+                    gst::State::Paused
+                } else {
+                    gst::State::Ready
+                };
+                pipeline.set_state(target_state)
+                    .map_err(|e| EngineError::MediaError(format!("Failed to preload media: {:?}", e)))?;
+                
+                Some(pipeline)
+            }
+        };
         
         let media_element = MediaElement {
             element_id,
-            media_type: MediaType::Audio,
+            media_type,
             src: src.to_string(),
-            pipeline: Some(pipeline),
+            pipeline,
             state: MediaState::Stopped,
             volume: 1.0,
-            muted: false,
+            muted: attributes.muted,
             current_time: 0.0,
             duration: 0.0,
+            last_used: Instant::now(),
+            preload: attributes.preload.clone(),
+            poster: attributes.poster.clone(),
+            poster_visible: attributes.poster.is_some(),
         };
         
         self.media_elements.insert(element_id, media_element);
         
+        if attributes.muted {
+            self.set_muted(element_id, true).await?;
+        }
+        
+        // Autoplay policy: only muted media may start without a user gesture
+        if attributes.autoplay {
+            if attributes.muted {
+                self.play(element_id).await?;
+            } else {
+                log::info!("Blocked unmuted autoplay for element {:?}", element_id);
+            }
+        }
+        
         Ok(())
     }
     
+    /// Get the poster image URL while it should still be shown in place of video frames
+    pub fn visible_poster(&self, element_id: ElementId) -> Option<&str> {
+        self.media_elements.get(&element_id)
+            .filter(|e| e.poster_visible)
+            .and_then(|e| e.poster.as_deref())
+    }
+    
+    /// Get the playback state of a media element
+    pub fn get_state(&self, element_id: ElementId) -> Option<MediaState> {
+        self.media_elements.get(&element_id).map(|e| e.state.clone())
+    }
+    
+    /// Check whether a media element currently holds a pipeline (i.e. has started buffering)
+    pub fn has_pipeline(&self, element_id: ElementId) -> bool {
+        self.media_elements.get(&element_id)
+            .map(|e| e.pipeline.is_some())
+            .unwrap_or(false)
+    }
+    
     /// Play media element
     pub async fn play(&mut self, element_id: ElementId) -> Result<()> {
+        let already_active = match self.media_elements.get(&element_id) {
+            Some(media_element) => media_element.pipeline.is_some() && media_element.state == MediaState::Paused,
+            None => return Ok(()),
+        };
+        
+        if self.media_elements[&element_id].state == MediaState::Playing {
+            return Ok(());
+        }
+        
+        // A paused element already owns its slot; anything else needs a free one
+        if !already_active && self.active_pipeline_count() >= self.config.max_active_pipelines {
+            let evicted = match self.config.overflow_policy {
+                PipelineOverflowPolicy::EvictLeastRecentlyUsed => self.evict_least_recently_used_paused(),
+                PipelineOverflowPolicy::Queue => false,
+            };
+            
+            if !evicted {
+                if !self.play_queue.contains(&element_id) {
+                    self.play_queue.push_back(element_id);
+                }
+                self.emit(MediaEvent::PlayQueued { element_id });
+                return Ok(());
+            }
+        }
+        
+        self.start_playback(element_id).await
+    }
+    
+    /// Transition an element to playing, recreating its pipeline if it was torn down
+    async fn start_playback(&mut self, element_id: ElementId) -> Result<()> {
+        let needs_pipeline = match self.media_elements.get(&element_id) {
+            Some(media_element) => media_element.pipeline.is_none(),
+            None => return Ok(()),
+        };
+        
+        if needs_pipeline {
+            let (media_type, src) = {
+                let media_element = &self.media_elements[&element_id];
+                (media_element.media_type.clone(), media_element.src.clone())
+            };
+            let pipeline = match media_type {
+                MediaType::Video => self.create_video_pipeline(&src).await?,
+                MediaType::Audio => self.create_audio_pipeline(&src).await?,
+            };
+            if let Some(media_element) = self.media_elements.get_mut(&element_id) {
+                // Carry over volume and mute state set before the pipeline existed
+                if let Some(volume_element) = pipeline.by_name("volume") {
+                    volume_element.set_property("volume", &media_element.volume);
+                    volume_element.set_property("mute", &media_element.muted);
+                }
+                media_element.pipeline = Some(pipeline);
+            }
+        }
+        
         if let Some(media_element) = self.media_elements.get_mut(&element_id) {
             if let Some(pipeline) = &media_element.pipeline {
                 pipeline.set_state(gst::State::Playing)
                     .map_err(|e| EngineError::MediaError(format!("Failed to play media: {:?}", e)))?;
                 
                 media_element.state = MediaState::Playing;
+                media_element.last_used = Instant::now();
+                media_element.poster_visible = false;
+            }
+        }
+        
+        self.play_queue.retain(|id| *id != element_id);
+        
+        Ok(())
+    }
+    
+    /// Tear down the least-recently-used paused pipeline, returning whether one was freed
+    fn evict_least_recently_used_paused(&mut self) -> bool {
+        let candidate = self.media_elements.values()
+            .filter(|e| e.pipeline.is_some() && e.state == MediaState::Paused)
+            .min_by_key(|e| e.last_used)
+            .map(|e| e.element_id);
+        
+        let Some(element_id) = candidate else {
+            return false;
+        };
+        
+        if let Some(media_element) = self.media_elements.get_mut(&element_id) {
+            if let Some(pipeline) = media_element.pipeline.take() {
+                let _ = pipeline.set_state(gst::State::Null);
+            }
+            media_element.state = MediaState::Stopped;
+        }
+        
+        self.emit(MediaEvent::PipelineEvicted { element_id });
+        true
+    }
+    
+    /// Start the next queued element if a pipeline slot has become free
+    async fn drain_play_queue(&mut self) -> Result<()> {
+        while self.active_pipeline_count() < self.config.max_active_pipelines {
+            let Some(element_id) = self.play_queue.pop_front() else {
+                break;
+            };
+            if self.media_elements.contains_key(&element_id) {
+                self.start_playback(element_id).await?;
+                self.emit(MediaEvent::PlayDequeued { element_id });
             }
         }
         
@@ -92,12 +294,15 @@ impl MediaEngine {
     
     /// Pause media element
     pub async fn pause(&mut self, element_id: ElementId) -> Result<()> {
+        self.play_queue.retain(|id| *id != element_id);
+        
         if let Some(media_element) = self.media_elements.get_mut(&element_id) {
             if let Some(pipeline) = &media_element.pipeline {
                 pipeline.set_state(gst::State::Paused)
                     .map_err(|e| EngineError::MediaError(format!("Failed to pause media: {:?}", e)))?;
                 
                 media_element.state = MediaState::Paused;
+                media_element.last_used = Instant::now();
             }
         }
         
@@ -106,6 +311,8 @@ impl MediaEngine {
     
     /// Stop media element
     pub async fn stop(&mut self, element_id: ElementId) -> Result<()> {
+        self.play_queue.retain(|id| *id != element_id);
+        
         if let Some(media_element) = self.media_elements.get_mut(&element_id) {
             if let Some(pipeline) = &media_element.pipeline {
                 pipeline.set_state(gst::State::Null)
@@ -116,7 +323,7 @@ impl MediaEngine {
             }
         }
         
-        Ok(())
+        self.drain_play_queue().await
     }
     
     /// Set volume for media element
@@ -306,6 +513,8 @@ impl MediaEngine {
     
     /// Remove media element
     pub async fn remove_element(&mut self, element_id: ElementId) -> Result<()> {
+        self.play_queue.retain(|id| *id != element_id);
+        
         if let Some(media_element) = self.media_elements.remove(&element_id) {
             if let Some(pipeline) = media_element.pipeline {
                 pipeline.set_state(gst::State::Null)
@@ -313,7 +522,7 @@ impl MediaEngine {
             }
         }
         
-        Ok(())
+        self.drain_play_queue().await
     }
     
     /// Shutdown media engine
@@ -341,6 +550,56 @@ pub struct MediaElement {
     pub muted: bool,
     pub current_time: f64,
     pub duration: f64,
+    pub last_used: Instant,
+    pub preload: PreloadHint,
+    pub poster: Option<String>,
+    pub poster_visible: bool,
+}
+
+/// Media attributes read from the HTML element
+#[derive(Debug, Clone, Default)]
+pub struct MediaAttributes {
+    pub preload: PreloadHint,
+    pub poster: Option<String>,
+    pub autoplay: bool,
+    pub muted: bool,
+}
+
+impl MediaAttributes {
+    /// Read `preload`, `poster`, `autoplay`, and `muted` from an element
+    pub fn from_element(element: &Element) -> Self {
+        Self {
+            preload: element.get_attribute("preload")
+                .map(PreloadHint::parse)
+                .unwrap_or_default(),
+            poster: element.get_attribute("poster")
+                .filter(|p| !p.trim().is_empty())
+                .map(|p| p.to_string()),
+            autoplay: element.has_attribute("autoplay"),
+            muted: element.has_attribute("muted"),
+        }
+    }
+}
+
+/// How much media data to buffer before playback is requested
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum PreloadHint {
+    None,
+    #[default]
+    Metadata,
+    Auto,
+}
+
+impl PreloadHint {
+    /// Parse a `preload` attribute value; the empty string means "auto"
+    pub fn parse(value: &str) -> Self {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "none" => PreloadHint::None,
+            "metadata" => PreloadHint::Metadata,
+            "" | "auto" => PreloadHint::Auto,
+            _ => PreloadHint::Metadata,
+        }
+    }
 }
 
 /// Media type enumeration
@@ -360,6 +619,45 @@ pub enum MediaState {
     Error,
 }
 
+/// Media engine configuration
+#[derive(Debug, Clone)]
+pub struct MediaConfig {
+    /// Maximum number of elements holding a live (playing or paused) pipeline
+    pub max_active_pipelines: usize,
+    
+    /// What to do when a play request would exceed `max_active_pipelines`
+    pub overflow_policy: PipelineOverflowPolicy,
+}
+
+impl Default for MediaConfig {
+    fn default() -> Self {
+        Self {
+            max_active_pipelines: 8,
+            overflow_policy: PipelineOverflowPolicy::EvictLeastRecentlyUsed,
+        }
+    }
+}
+
+/// Behavior when the active pipeline limit is reached
+#[derive(Debug, Clone, PartialEq)]
+pub enum PipelineOverflowPolicy {
+    /// Hold the play request until a slot frees up
+    Queue,
+    /// Tear down the least-recently-used paused element, queueing if none is paused
+    EvictLeastRecentlyUsed,
+}
+
+/// Media lifecycle events
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaEvent {
+    /// A play request was queued because the pipeline limit was reached
+    PlayQueued { element_id: ElementId },
+    /// A queued play request started after a slot freed up
+    PlayDequeued { element_id: ElementId },
+    /// A paused element's pipeline was torn down to make room
+    PipelineEvicted { element_id: ElementId },
+}
+
 /// Media capabilities detection
 #[derive(Debug, Clone)]
 pub struct MediaCapabilities {
@@ -503,6 +801,10 @@ mod tests {
             muted: false,
             current_time: 0.0,
             duration: 0.0,
+            last_used: Instant::now(),
+            preload: PreloadHint::Metadata,
+            poster: None,
+            poster_visible: false,
         };
         
         assert_eq!(element.media_type, MediaType::Video);
@@ -511,6 +813,89 @@ mod tests {
         assert!(!element.muted);
     }
     
+    #[tokio::test]
+    async fn test_active_pipeline_limit() {
+        // Skip if GStreamer is not available
+        if gst::init().is_err() {
+            return;
+        }
+        
+        let config = MediaConfig {
+            max_active_pipelines: 2,
+            overflow_policy: PipelineOverflowPolicy::Queue,
+        };
+        let mut engine = MediaEngine::with_config(config).await.unwrap();
+        let mut events = engine.subscribe();
+        
+        let ids: Vec<ElementId> = (0..4).map(|_| ElementId::new()).collect();
+        for id in &ids {
+            engine.create_audio_element(*id, "file:///dev/null").await.unwrap();
+            engine.play(*id).await.unwrap();
+            assert!(engine.active_pipeline_count() <= 2);
+        }
+        
+        assert_eq!(engine.active_pipeline_count(), 2);
+        assert_eq!(engine.queued_play_count(), 2);
+        assert_eq!(events.try_recv().unwrap(), MediaEvent::PlayQueued { element_id: ids[2] });
+        
+        // Stopping a playing element lets the next queued one start
+        engine.stop(ids[0]).await.unwrap();
+        assert_eq!(engine.active_pipeline_count(), 2);
+        assert_eq!(engine.queued_play_count(), 1);
+    }
+    
+    #[test]
+    fn test_media_attributes_parsing() {
+        let mut element = Element::new("video".to_string(), ElementId::new());
+        element.set_attribute("preload".to_string(), "none".to_string());
+        element.set_attribute("poster".to_string(), "poster.jpg".to_string());
+        element.set_attribute("autoplay".to_string(), String::new());
+        
+        let attributes = MediaAttributes::from_element(&element);
+        assert_eq!(attributes.preload, PreloadHint::None);
+        assert_eq!(attributes.poster.as_deref(), Some("poster.jpg"));
+        assert!(attributes.autoplay);
+        assert!(!attributes.muted);
+        
+        assert_eq!(PreloadHint::parse(""), PreloadHint::Auto);
+        assert_eq!(PreloadHint::parse("bogus"), PreloadHint::Metadata);
+    }
+    
+    #[tokio::test]
+    async fn test_autoplay_policy_and_preload() {
+        // Skip if GStreamer is not available
+        if gst::init().is_err() {
+            return;
+        }
+        
+        let mut engine = MediaEngine::new().await.unwrap();
+        
+        let muted_id = ElementId::new();
+        let attributes = MediaAttributes { autoplay: true, muted: true, ..MediaAttributes::default() };
+        engine.create_media_element(muted_id, MediaType::Audio, "file:///dev/null", attributes).await.unwrap();
+        assert_eq!(engine.get_state(muted_id), Some(MediaState::Playing));
+        
+        let unmuted_id = ElementId::new();
+        let attributes = MediaAttributes { autoplay: true, ..MediaAttributes::default() };
+        engine.create_media_element(unmuted_id, MediaType::Audio, "file:///dev/null", attributes).await.unwrap();
+        assert_eq!(engine.get_state(unmuted_id), Some(MediaState::Stopped));
+        
+        let deferred_id = ElementId::new();
+        let attributes = MediaAttributes {
+            preload: PreloadHint::None,
+            poster: Some("poster.jpg".to_string()),
+            ..MediaAttributes::default()
+        };
+        engine.create_media_element(deferred_id, MediaType::Video, "file:///dev/null", attributes).await.unwrap();
+        assert!(!engine.has_pipeline(deferred_id));
+        assert_eq!(engine.visible_poster(deferred_id), Some("poster.jpg"));
+        
+        // Playing builds the pipeline and hides the poster
+        engine.play(deferred_id).await.unwrap();
+        assert!(engine.has_pipeline(deferred_id));
+        assert_eq!(engine.visible_poster(deferred_id), None);
+    }
+    
     #[test]
     fn test_pipeline_manager() {
         let mut manager = PipelineManager::new();
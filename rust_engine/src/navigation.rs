@@ -0,0 +1,159 @@
+//! Per-tab session history for back/forward navigation
+
+use serde::{Deserialize, Serialize};
+
+/// One entry in a tab's session history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NavigationEntry {
+    pub url: String,
+    pub title: String,
+    /// Scroll offset to restore when the entry is revisited
+    pub scroll_x: f32,
+    pub scroll_y: f32,
+}
+
+impl NavigationEntry {
+    pub fn new(url: &str, title: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            title: title.to_string(),
+            scroll_x: 0.0,
+            scroll_y: 0.0,
+        }
+    }
+}
+
+/// Back/forward stack for a single tab.
+/// This is the in-session history; the global visited list lives in storage.
+#[derive(Debug, Clone, Default)]
+pub struct NavigationController {
+    entries: Vec<NavigationEntry>,
+    /// Index of the current entry, if anything has been loaded
+    current: Option<usize>,
+}
+
+impl NavigationController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a fresh load, discarding any forward history
+    pub fn push(&mut self, url: &str, title: &str) -> &NavigationEntry {
+        let index = self.current.map_or(0, |current| current + 1);
+        self.entries.truncate(index);
+        self.entries.push(NavigationEntry::new(url, title));
+        self.current = Some(index);
+        &self.entries[index]
+    }
+
+    /// The entry currently shown
+    pub fn current(&self) -> Option<&NavigationEntry> {
+        self.current.map(|index| &self.entries[index])
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        self.current.is_some_and(|index| index > 0)
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        self.current.is_some_and(|index| index + 1 < self.entries.len())
+    }
+
+    /// The entry `go_back` would move to, without moving
+    pub fn back_entry(&self) -> Option<&NavigationEntry> {
+        self.current.filter(|&index| index > 0).map(|index| &self.entries[index - 1])
+    }
+
+    /// The entry `go_forward` would move to, without moving
+    pub fn forward_entry(&self) -> Option<&NavigationEntry> {
+        self.current.and_then(|index| self.entries.get(index + 1))
+    }
+
+    /// Move back one entry, returning the new current entry
+    pub fn go_back(&mut self) -> Option<&NavigationEntry> {
+        if !self.can_go_back() {
+            return None;
+        }
+        self.current = self.current.map(|index| index - 1);
+        self.current()
+    }
+
+    /// Move forward one entry, returning the new current entry
+    pub fn go_forward(&mut self) -> Option<&NavigationEntry> {
+        if !self.can_go_forward() {
+            return None;
+        }
+        self.current = self.current.map(|index| index + 1);
+        self.current()
+    }
+
+    /// The entry to load again; the stack is unchanged
+    pub fn reload(&self) -> Option<&NavigationEntry> {
+        self.current()
+    }
+
+    /// Remember the current entry's scroll offset
+    pub fn set_scroll_position(&mut self, x: f32, y: f32) {
+        if let Some(index) = self.current {
+            self.entries[index].scroll_x = x;
+            self.entries[index].scroll_y = y;
+        }
+    }
+
+    /// Update the current entry's title, e.g. after a reload
+    pub fn set_title(&mut self, title: &str) {
+        if let Some(index) = self.current {
+            self.entries[index].title = title.to_string();
+        }
+    }
+
+    /// All entries, oldest first
+    pub fn entries(&self) -> &[NavigationEntry] {
+        &self.entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_back_and_forward() {
+        let mut nav = NavigationController::new();
+        assert!(nav.current().is_none());
+        assert!(!nav.can_go_back());
+
+        nav.push("https://a.example/", "A");
+        nav.push("https://b.example/", "B");
+        nav.set_scroll_position(0.0, 480.0);
+        nav.push("https://c.example/", "C");
+
+        assert_eq!(nav.go_back().unwrap().url, "https://b.example/");
+        assert_eq!(nav.current().unwrap().scroll_y, 480.0);
+        assert_eq!(nav.go_back().unwrap().url, "https://a.example/");
+        assert!(!nav.can_go_back());
+        assert!(nav.go_back().is_none());
+
+        assert_eq!(nav.go_forward().unwrap().url, "https://b.example/");
+        assert!(nav.can_go_forward());
+        assert_eq!(nav.reload().unwrap().url, "https://b.example/");
+        assert_eq!(nav.entries().len(), 3);
+    }
+
+    #[test]
+    fn test_push_truncates_forward_history() {
+        let mut nav = NavigationController::new();
+        nav.push("https://a.example/", "A");
+        nav.push("https://b.example/", "B");
+        nav.push("https://c.example/", "C");
+        nav.go_back();
+        nav.go_back();
+
+        nav.push("https://d.example/", "D");
+
+        let urls: Vec<_> = nav.entries().iter().map(|entry| entry.url.as_str()).collect();
+        assert_eq!(urls, ["https://a.example/", "https://d.example/"]);
+        assert!(!nav.can_go_forward());
+        assert_eq!(nav.back_entry().unwrap().url, "https://a.example/");
+    }
+}
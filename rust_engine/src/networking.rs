@@ -1,18 +1,68 @@
 //! Networking stack with HTTP/3, WebSocket, and security features
 
 use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::Duration;
-use hyper::{Client, Request, Response, Body, Method, Uri};
-use hyper_rustls::HttpsConnectorBuilder;
+use std::future::Future;
+use std::io::Read;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use futures::stream::{SplitSink, SplitStream};
+use futures::{SinkExt, Stream, StreamExt};
+use hyper::body::HttpBody;
+use hyper::client::connect::dns::Name;
+use hyper::client::connect::{Connected, Connection};
+use hyper::client::HttpConnector;
+use hyper::{Client, Request, Response, Body, Uri};
+use hyper_rustls::{HttpsConnectorBuilder, MaybeHttpsStream};
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
 use rustls::{ClientConfig, RootCertStore};
+use serde::Deserialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tokio::time::timeout;
-use crate::core::{Result, EngineError};
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::{Connector, MaybeTlsStream, WebSocketStream};
+use crate::core::{Result, EngineError, MetricsReporter, NetworkErrorKind, PerformanceMetrics};
+use crate::storage::{SameSite, StorageEngine};
+
+pub use tokio_tungstenite::tungstenite::Message as WebSocketMessage;
+
+/// Default User-Agent, sent unless configured or overridden per request
+pub const USER_AGENT: &str = "TitanBrowser/1.0 (Rust Engine)";
+
+/// Default Accept-Language
+pub const ACCEPT_LANGUAGE: &str = "en-US,en;q=0.9";
+
+/// How long a fetched robots.txt is reused (RFC 9309 caps this at 24 hours)
+const ROBOTS_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long an unreachable robots.txt disallows the origin before it is retried
+const ROBOTS_FAILURE_TTL: Duration = Duration::from_secs(60);
+
+/// Redirects followed for robots.txt before treating it as unavailable
+const ROBOTS_MAX_REDIRECTS: usize = 5;
 
 /// High-performance networking stack
 pub struct NetworkStack {
     /// HTTP client with TLS support
-    http_client: Client<hyper_rustls::HttpsConnector<hyper::client::HttpConnector>>,
+    http_client: Client<TlsInfoConnector>,
+    
+    /// Certificate pins by lowercase host, shared with the TLS verifier
+    certificate_pins: Arc<RwLock<HashMap<String, Vec<CertificatePin>>>>,
+    
+    /// How the HTTP client resolves hostnames, shared with its connector
+    resolver_mode: Arc<RwLock<ResolverMode>>,
+    
+    /// How long the connector may spend resolving, connecting and handshaking
+    connect_timeout: Arc<RwLock<Option<Duration>>>,
+    
+    /// DNS-over-HTTPS client and answer cache
+    doh: Arc<DohResolver>,
+    
+    /// TLS configuration shared by HTTPS and secure WebSocket connections
+    tls_config: Arc<ClientConfig>,
     
     /// Request cache
     cache: Arc<tokio::sync::RwLock<RequestCache>>,
@@ -20,6 +70,42 @@ pub struct NetworkStack {
     /// Security settings
     security_config: SecurityConfig,
     
+    /// Connection settings
+    network_config: NetworkConfig,
+    
+    /// Per-host semaphores bounding concurrent connections, keyed by authority
+    host_limits: Mutex<HashMap<String, Arc<Semaphore>>>,
+    
+    /// Live and peak connection counts, shared with outstanding permits
+    connections: Arc<ConnectionCounters>,
+    
+    /// Hooks run on each outgoing request, in registration order
+    request_interceptors: RwLock<Vec<RequestInterceptor>>,
+    
+    /// Hooks run on each response before its body is read
+    response_interceptors: RwLock<Vec<ResponseInterceptor>>,
+    
+    /// Persistent response cache used by offline and record modes
+    persistent_cache: Option<Arc<tokio::sync::Mutex<StorageEngine>>>,
+    
+    /// Cookie jar; requests carry no cookies and `Set-Cookie` is ignored without one
+    cookie_store: Option<Arc<tokio::sync::Mutex<StorageEngine>>>,
+    
+    /// Hosts known to require HTTPS; `Strict-Transport-Security` is ignored without one
+    hsts_store: Option<Arc<tokio::sync::Mutex<StorageEngine>>>,
+    
+    /// Serve `fetch` only from the persistent cache, never the network
+    offline: AtomicBool,
+    
+    /// Save every successful `fetch` response to the persistent cache
+    recording: AtomicBool,
+    
+    /// Parsed robots.txt per origin with its expiry, fetched on first use in polite mode
+    robots: tokio::sync::RwLock<HashMap<String, (Arc<RobotsTxt>, Instant)>>,
+    
+    /// Per-host token buckets for `NetworkConfig::rate_limit`, keyed by authority
+    rate_limiters: Mutex<HashMap<String, TokenBucket>>,
+    
     /// Performance metrics
     metrics: Arc<tokio::sync::RwLock<NetworkMetrics>>,
 }
@@ -39,18 +125,37 @@ impl NetworkStack {
             })
         );
         
+        // Chains are checked against any pins before the usual web PKI validation
+        let certificate_pins = Arc::new(RwLock::new(HashMap::new()));
         let tls_config = ClientConfig::builder()
             .with_safe_defaults()
-            .with_root_certificates(root_store)
+            .with_custom_certificate_verifier(Arc::new(PinningVerifier {
+                roots: WebPkiVerifier::new(root_store, None),
+                pins: certificate_pins.clone(),
+            }))
             .with_no_client_auth();
         
+        // Resolve through the configured resolver; TLS still uses the URL's hostname for SNI
+        let resolver_mode = Arc::new(RwLock::new(ResolverMode::default()));
+        let doh = Arc::new(DohResolver::new(tls_config.clone()));
+        let mut http_connector = HttpConnector::new_with_resolver(StackResolver {
+            mode: resolver_mode.clone(),
+            doh: doh.clone(),
+        });
+        http_connector.enforce_http(false);
+        
         // Create HTTPS connector
         let https_connector = HttpsConnectorBuilder::new()
-            .with_tls_config(tls_config)
+            .with_tls_config(tls_config.clone())
             .https_or_http()
             .enable_http1()
             .enable_http2()
-            .build();
+            .wrap_connector(http_connector);
+        let connect_timeout = Arc::new(RwLock::new(NetworkConfig::default().connect_timeout));
+        let https_connector = TlsInfoConnector {
+            inner: https_connector,
+            connect_timeout: connect_timeout.clone(),
+        };
         
         let http_client = Client::builder()
             .http2_only(false)
@@ -60,23 +165,59 @@ impl NetworkStack {
         
         Ok(Self {
             http_client,
+            certificate_pins,
+            resolver_mode,
+            connect_timeout,
+            doh,
+            tls_config: Arc::new(tls_config),
             cache: Arc::new(tokio::sync::RwLock::new(RequestCache::new())),
             security_config: SecurityConfig::default(),
+            network_config: NetworkConfig::default(),
+            host_limits: Mutex::new(HashMap::new()),
+            connections: Arc::new(ConnectionCounters::default()),
+            request_interceptors: RwLock::new(Vec::new()),
+            response_interceptors: RwLock::new(Vec::new()),
+            persistent_cache: None,
+            cookie_store: None,
+            hsts_store: None,
+            offline: AtomicBool::new(false),
+            recording: AtomicBool::new(false),
+            robots: tokio::sync::RwLock::new(HashMap::new()),
+            rate_limiters: Mutex::new(HashMap::new()),
             metrics: Arc::new(tokio::sync::RwLock::new(NetworkMetrics::default())),
         })
     }
     
     /// Fetch a URL with full HTTP support
     pub async fn fetch(&self, url: &str) -> Result<NetworkResponse> {
+        self.fetch_from_site(url, None).await
+    }
+    
+    /// Fetch a resource on behalf of the page at `site_for_cookies`, so SameSite cookies
+    /// are withheld when the resource is cross-site
+    pub async fn fetch_for_site(&self, url: &str, site_for_cookies: &str) -> Result<NetworkResponse> {
+        self.fetch_from_site(url, Some(site_for_cookies)).await
+    }
+    
+    async fn fetch_from_site(&self, url: &str, site_for_cookies: Option<&str>) -> Result<NetworkResponse> {
         let start_time = std::time::Instant::now();
         
-        // Parse URL
-        let uri: Uri = url.parse()
-            .map_err(|e| EngineError::NetworkError(format!("Invalid URL: {}", e)))?;
+        // Security check and interceptors, which may rewrite the URL
+        let parts = RequestParts::new("GET", url, [
+            ("User-Agent", self.network_config.user_agent.as_str()),
+            ("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"),
+            ("Accept-Language", self.network_config.accept_language.as_str()),
+            ("Accept-Encoding", "gzip, deflate, br"),
+            ("DNT", "1"),
+            ("Connection", "keep-alive"),
+            ("Upgrade-Insecure-Requests", "1"),
+        ]);
+        let (mut parts, uri) = self.intercept_request(parts).await?;
+        self.attach_cookies(&mut parts, &uri, site_for_cookies).await?;
+        let url = parts.url.as_str();
         
-        // Security check
-        if !self.is_url_allowed(&uri).await {
-            return Err(EngineError::SecurityError(format!("URL blocked by security policy: {}", url)));
+        if self.offline.load(Ordering::Relaxed) {
+            return self.fetch_offline(url, start_time).await;
         }
         
         // Check cache first
@@ -86,33 +227,28 @@ impl NetworkStack {
             }
         }
         
+        // Honor robots.txt and the request rate, then wait for a free connection to this host
+        self.pace_request(url, &uri, parts.headers.get("User-Agent").map(String::as_str).unwrap_or(&self.network_config.user_agent)).await?;
+        let _slot = self.acquire_host_slot(&uri).await?;
+        
         // Build request
-        let request = Request::builder()
-            .method(Method::GET)
-            .uri(uri)
-            .header("User-Agent", "TitanBrowser/1.0 (Rust Engine)")
-            .header("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8")
-            .header("Accept-Language", "en-US,en;q=0.5")
-            .header("Accept-Encoding", "gzip, deflate, br")
-            .header("DNT", "1")
-            .header("Connection", "keep-alive")
-            .header("Upgrade-Insecure-Requests", "1")
-            .body(Body::empty())
-            .map_err(|e| EngineError::NetworkError(format!("Failed to build request: {}", e)))?;
+        let request = parts.build_request(uri.clone(), Body::empty())?;
         
-        // Execute request with timeout
-        let response = timeout(
-            Duration::from_secs(30),
-            self.http_client.request(request)
-        ).await
-        .map_err(|_| EngineError::NetworkError("Request timeout".to_string()))?
-        .map_err(|e| EngineError::NetworkError(format!("Request failed: {}", e)))?;
+        // The overall timeout caps the whole exchange, body included
+        let deadline = tokio::time::Instant::now() + self.security_config.timeout;
+        let response = tokio::time::timeout_at(deadline, self.http_client.request(request)).await
+            .map_err(|_| request_timeout(url))?
+            .map_err(|e| request_failed(url, e))?;
+        self.intercept_response(url, &response)?;
+        self.store_cookies(&uri, response.headers()).await;
+        self.store_hsts(&uri, response.headers()).await;
         
         // Convert response
         let status = response.status().as_u16();
         let headers = response.headers().clone();
-        let body_bytes = hyper::body::to_bytes(response.into_body()).await
-            .map_err(|e| EngineError::NetworkError(format!("Failed to read response body: {}", e)))?;
+        let tls = response.extensions().get::<TlsInfo>().cloned();
+        let body_bytes = read_body(url, response.into_body(), self.network_config.read_timeout, deadline).await?;
+        let body_bytes = decode_content(&headers, body_bytes, self.security_config.max_response_size)?;
         
         let body = String::from_utf8_lossy(&body_bytes).to_string();
         
@@ -123,16 +259,22 @@ impl NetworkStack {
                 .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
                 .collect(),
             body,
-            body_bytes: body_bytes.to_vec(),
+            body_bytes,
             load_time: start_time.elapsed(),
             from_cache: false,
-            security_info: self.extract_security_info(&headers),
+            security_info: self.extract_security_info(&headers, tls),
         };
         
         // Cache response if appropriate
         if self.should_cache_response(&network_response) {
             self.cache_response(url, &network_response).await;
         }
+        // A storage failure loses the recording but shouldn't fail the fetch
+        if self.recording.load(Ordering::Relaxed) && (200..300).contains(&status) {
+            if let Err(e) = self.record_response(&network_response).await {
+                log::warn!("Failed to record {}: {}", url, e);
+            }
+        }
         
         // Update metrics
         self.update_metrics(&network_response).await;
@@ -140,53 +282,242 @@ impl NetworkStack {
         Ok(network_response)
     }
     
-    /// Fetch with custom options
-    pub async fn fetch_with_options(&self, url: &str, options: RequestOptions) -> Result<NetworkResponse> {
-        let start_time = std::time::Instant::now();
+    /// Use `storage`'s response cache for offline and record modes
+    pub fn set_persistent_cache(&mut self, storage: Arc<tokio::sync::Mutex<StorageEngine>>) {
+        self.persistent_cache = Some(storage);
+    }
+    
+    /// Send and store cookies through `storage`
+    pub fn set_cookie_store(&mut self, storage: Arc<tokio::sync::Mutex<StorageEngine>>) {
+        self.cookie_store = Some(storage);
+    }
+    
+    /// Require connections to `host` to present a chain containing a certificate matching
+    /// one of its pins; connections that don't fail with `EngineError::CertificatePinMismatch`
+    pub fn pin_certificate(&self, host: &str, pin: CertificatePin) {
+        self.certificate_pins.write().unwrap_or_else(PoisonError::into_inner)
+            .entry(host.trim_end_matches('.').to_ascii_lowercase())
+            .or_default()
+            .push(pin);
+    }
+    
+    /// Remove every pin for `host`
+    pub fn clear_certificate_pins(&self, host: &str) {
+        self.certificate_pins.write().unwrap_or_else(PoisonError::into_inner)
+            .remove(&host.trim_end_matches('.').to_ascii_lowercase());
+    }
+    
+    /// Remember `Strict-Transport-Security` in `storage` and upgrade later http requests
+    /// to those hosts
+    pub fn set_hsts_store(&mut self, storage: Arc<tokio::sync::Mutex<StorageEngine>>) {
+        self.hsts_store = Some(storage);
+    }
+    
+    /// Rewrite an http URL to https when its host has a live HSTS entry
+    async fn upgrade_to_https(&self, url: &str) -> Result<Option<String>> {
+        let Some(store) = &self.hsts_store else {
+            return Ok(None);
+        };
+        let Ok(mut parsed) = url::Url::parse(url) else {
+            return Ok(None);
+        };
+        let Some(host) = parsed.host_str().filter(|_| parsed.scheme() == "http").map(str::to_string) else {
+            return Ok(None);
+        };
+        if !store.lock().await.is_hsts_host(&host).await? {
+            return Ok(None);
+        }
         
-        let uri: Uri = url.parse()
-            .map_err(|e| EngineError::NetworkError(format!("Invalid URL: {}", e)))?;
+        // An explicit port 80 becomes the https default; any other port is kept
+        if parsed.port() == Some(80) {
+            let _ = parsed.set_port(None);
+        }
+        let _ = parsed.set_scheme("https");
+        Ok(Some(parsed.to_string()))
+    }
+    
+    /// Save or clear the host's HSTS entry from a response received over https.
+    /// Per RFC 6797 the header is ignored over http and for IP address hosts.
+    async fn store_hsts(&self, uri: &Uri, headers: &hyper::HeaderMap) {
+        let (Some(store), Some(host)) = (&self.hsts_store, uri.host()) else {
+            return;
+        };
+        if uri.scheme_str() != Some("https") || host.parse::<IpAddr>().is_ok() || host.starts_with('[') {
+            return;
+        }
+        let Some(policy) = headers.get("strict-transport-security")
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_strict_transport_security) else {
+            return;
+        };
         
-        if !self.is_url_allowed(&uri).await {
-            return Err(EngineError::SecurityError(format!("URL blocked: {}", url)));
+        let store = store.lock().await;
+        let stored = if policy.max_age == 0 {
+            store.delete_hsts(host).await
+        } else {
+            let expires_at = chrono::Utc::now().timestamp().saturating_add(policy.max_age.min(i64::MAX as u64) as i64);
+            store.set_hsts(host, expires_at, policy.include_subdomains).await
+        };
+        // Like the cookie jar, a failure to remember the policy shouldn't fail the response
+        if let Err(e) = stored {
+            log::warn!("Failed to store HSTS policy for {}: {}", host, e);
+        }
+    }
+    
+    /// Add a `Cookie` header with the jar's cookies for this URI, unless the caller set one.
+    /// `site_for_cookies` is the URL of the page making the request; `None` is a top-level
+    /// navigation, which counts as same-site.
+    async fn attach_cookies(&self, parts: &mut RequestParts, uri: &Uri, site_for_cookies: Option<&str>) -> Result<()> {
+        let (Some(store), Some(host)) = (&self.cookie_store, uri.host()) else {
+            return Ok(());
+        };
+        if parts.headers.keys().any(|key| key.eq_ignore_ascii_case("cookie")) {
+            return Ok(());
         }
         
-        // Build request with options
-        let mut request_builder = Request::builder()
-            .method(options.method.as_str())
-            .uri(uri);
+        let secure = uri.scheme_str() == Some("https");
+        let cross_site = site_for_cookies.is_some_and(|site| !is_same_site(site, host));
+        let cookies = store.lock().await.get_cookies_for_request(host, uri.path(), secure, cross_site).await?;
+        if !cookies.is_empty() {
+            let header = cookies.iter()
+                .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+                .collect::<Vec<_>>()
+                .join("; ");
+            parts.headers.insert("Cookie".to_string(), header);
+        }
+        Ok(())
+    }
+    
+    /// Save each `Set-Cookie` in a response; cookies the server expires are deleted
+    async fn store_cookies(&self, uri: &Uri, headers: &hyper::HeaderMap) {
+        let (Some(store), Some(host)) = (&self.cookie_store, uri.host()) else {
+            return;
+        };
         
-        // Add headers
-        for (key, value) in &options.headers {
-            request_builder = request_builder.header(key, value);
+        let now = chrono::Utc::now().timestamp();
+        let mut store = store.lock().await;
+        for header in headers.get_all(hyper::header::SET_COOKIE) {
+            let Some(cookie) = header.to_str().ok().and_then(|value| parse_set_cookie(value, host, uri.path(), now)) else {
+                continue;
+            };
+            let stored = if cookie.expires.is_some_and(|expires| expires <= now) {
+                store.delete_cookie(&cookie.domain, &cookie.name, &cookie.path).await
+            } else {
+                store.set_cookie(cookie).await
+            };
+            // A cookie jar failure shouldn't fail the page load
+            if let Err(e) = stored {
+                log::warn!("Failed to store cookie from {}: {}", host, e);
+            }
         }
+    }
+    
+    /// Serve `fetch` exclusively from the persistent cache; uncached URLs fail instead of hitting the network
+    pub fn set_offline(&self, offline: bool) {
+        self.offline.store(offline, Ordering::Relaxed);
+    }
+    
+    pub fn is_offline(&self) -> bool {
+        self.offline.load(Ordering::Relaxed)
+    }
+    
+    /// Save every successful `fetch` response to the persistent cache, e.g. to snapshot a site
+    pub fn set_recording(&self, recording: bool) {
+        self.recording.store(recording, Ordering::Relaxed);
+    }
+    
+    /// Answer a fetch from the persistent cache
+    async fn fetch_offline(&self, url: &str, start_time: std::time::Instant) -> Result<NetworkResponse> {
+        let storage = self.persistent_cache.as_ref()
+            .ok_or_else(|| EngineError::network(NetworkErrorKind::Offline, Some(url), format!("Offline with no persistent cache, cannot fetch {}", url)))?;
+        let cached = storage.lock().await.get_cached_response(url).await?
+            .ok_or_else(|| EngineError::network(NetworkErrorKind::Offline, Some(url), format!("Offline and not cached: {}", url)))?;
+        
+        // Status is stored alongside the headers as an HTTP/2-style pseudo-header
+        let mut headers: HashMap<String, String> = serde_json::from_str(&cached.headers)
+            .map_err(|e| EngineError::StorageError(format!("Corrupt cached headers for {}: {}", url, e)))?;
+        let status = headers.remove(":status").and_then(|status| status.parse().ok()).unwrap_or(200);
+        
+        Ok(NetworkResponse {
+            url: url.to_string(),
+            status,
+            body: String::from_utf8_lossy(&cached.body).to_string(),
+            body_bytes: cached.body,
+            load_time: start_time.elapsed(),
+            from_cache: true,
+            security_info: SecurityInfo {
+                https: url.starts_with("https://"),
+                hsts: headers.contains_key("strict-transport-security"),
+                csp: headers.get("content-security-policy").cloned(),
+                x_frame_options: headers.get("x-frame-options").cloned(),
+                x_content_type_options: headers.get("x-content-type-options").cloned(),
+                // The session the response was recorded over isn't kept
+                tls: None,
+            },
+            headers,
+        })
+    }
+    
+    /// Persist a response for later offline use; recorded entries never expire
+    async fn record_response(&self, response: &NetworkResponse) -> Result<()> {
+        let Some(storage) = &self.persistent_cache else {
+            return Ok(());
+        };
+        
+        let mut headers = response.headers.clone();
+        headers.insert(":status".to_string(), response.status.to_string());
+        let headers = serde_json::to_string(&headers)
+            .map_err(|e| EngineError::StorageError(format!("Failed to serialize headers: {}", e)))?;
+        storage.lock().await.cache_response(&response.url, &headers, &response.body_bytes, i64::MAX).await
+    }
+    
+    /// Fetch with custom options
+    pub async fn fetch_with_options(&self, url: &str, options: RequestOptions) -> Result<NetworkResponse> {
+        let start_time = std::time::Instant::now();
         
         // Add default headers if not present
-        if !options.headers.contains_key("User-Agent") {
-            request_builder = request_builder.header("User-Agent", "TitanBrowser/1.0 (Rust Engine)");
+        let mut parts = RequestParts::new(&options.method, url, []);
+        parts.headers = options.headers.clone();
+        if let Some(user_agent) = &options.user_agent {
+            parts.headers.insert("User-Agent".to_string(), user_agent.clone());
+        }
+        if !parts.headers.keys().any(|key| key.eq_ignore_ascii_case("user-agent")) {
+            parts.headers.insert("User-Agent".to_string(), self.network_config.user_agent.clone());
+        }
+        if !parts.headers.keys().any(|key| key.eq_ignore_ascii_case("accept-language")) {
+            parts.headers.insert("Accept-Language".to_string(), self.network_config.accept_language.clone());
+        }
+        let (mut parts, uri) = self.intercept_request(parts).await?;
+        if options.credentials {
+            self.attach_cookies(&mut parts, &uri, options.site_for_cookies.as_deref()).await?;
         }
+        let url = parts.url.as_str();
+        
+        self.pace_request(url, &uri, parts.headers.get("User-Agent").map(String::as_str).unwrap_or(&self.network_config.user_agent)).await?;
+        let _slot = self.acquire_host_slot(&uri).await?;
         
         let body = match options.body {
             Some(body_data) => Body::from(body_data),
             None => Body::empty(),
         };
-        
-        let request = request_builder
-            .body(body)
-            .map_err(|e| EngineError::NetworkError(format!("Failed to build request: {}", e)))?;
+        let request = parts.build_request(uri.clone(), body)?;
         
         // Execute with custom timeout
-        let response = timeout(
-            options.timeout,
-            self.http_client.request(request)
-        ).await
-        .map_err(|_| EngineError::NetworkError("Request timeout".to_string()))?
-        .map_err(|e| EngineError::NetworkError(format!("Request failed: {}", e)))?;
+        let deadline = tokio::time::Instant::now() + options.timeout;
+        let response = tokio::time::timeout_at(deadline, self.http_client.request(request)).await
+            .map_err(|_| request_timeout(url))?
+            .map_err(|e| request_failed(url, e))?;
+        self.intercept_response(url, &response)?;
+        if options.credentials {
+            self.store_cookies(&uri, response.headers()).await;
+        }
+        self.store_hsts(&uri, response.headers()).await;
         
         let status = response.status().as_u16();
         let headers = response.headers().clone();
-        let body_bytes = hyper::body::to_bytes(response.into_body()).await
-            .map_err(|e| EngineError::NetworkError(format!("Failed to read response body: {}", e)))?;
+        let tls = response.extensions().get::<TlsInfo>().cloned();
+        let body_bytes = read_body(url, response.into_body(), self.network_config.read_timeout, deadline).await?;
+        let body_bytes = decode_content(&headers, body_bytes, self.security_config.max_response_size)?;
         
         let body = String::from_utf8_lossy(&body_bytes).to_string();
         
@@ -197,10 +528,89 @@ impl NetworkStack {
                 .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
                 .collect(),
             body,
-            body_bytes: body_bytes.to_vec(),
+            body_bytes,
             load_time: start_time.elapsed(),
             from_cache: false,
-            security_info: self.extract_security_info(&headers),
+            security_info: self.extract_security_info(&headers, tls),
+        })
+    }
+    
+    /// Start a GET whose body is streamed to the caller, resuming at `offset` via a Range request.
+    /// Bodies are requested without content coding so byte offsets stay meaningful.
+    pub async fn fetch_stream(&self, url: &str, offset: u64) -> Result<Response<Body>> {
+        let mut parts = RequestParts::new("GET", url, [
+            ("User-Agent", self.network_config.user_agent.as_str()),
+            ("Accept-Encoding", "identity"),
+        ]);
+        if offset > 0 {
+            parts.headers.insert("Range".to_string(), format!("bytes={}-", offset));
+        }
+        let (parts, uri) = self.intercept_request(parts).await?;
+        let slot = self.acquire_host_slot(&uri).await?;
+        let request = parts.build_request(uri, Body::empty())?;
+        
+        let response = timeout(
+            self.security_config.timeout,
+            self.http_client.request(request)
+        ).await
+        .map_err(|_| request_timeout(&parts.url))?
+        .map_err(|e| request_failed(&parts.url, e))?;
+        self.intercept_response(&parts.url, &response)?;
+        
+        if !response.status().is_success() {
+            return Err(EngineError::network(
+                NetworkErrorKind::Status(response.status().as_u16()),
+                Some(&parts.url),
+                format!("Request failed with status {}", response.status()),
+            ));
+        }
+        
+        // The connection stays busy until the caller finishes reading, so the slot goes with the body
+        let (head, body) = response.into_parts();
+        let body = Body::wrap_stream(body.map(move |chunk| {
+            let _ = &slot;
+            chunk
+        }));
+        Ok(Response::from_parts(head, body))
+    }
+    
+    /// Open a WebSocket connection, subject to the same URL policy as `fetch`
+    pub async fn connect_websocket(&self, url: &str) -> Result<WebSocketConnection> {
+        let uri: Uri = url.parse()
+            .map_err(|e| invalid_url(url, e))?;
+        
+        if !matches!(uri.scheme_str(), Some("ws") | Some("wss")) {
+            return Err(EngineError::network(NetworkErrorKind::InvalidUrl, Some(url), format!("Not a WebSocket URL: {}", url)));
+        }
+        
+        if !self.is_url_allowed(&uri).await {
+            return Err(EngineError::SecurityError(format!("URL blocked by security policy: {}", url)));
+        }
+        
+        let slot = self.acquire_host_slot(&uri).await?;
+        let config = WebSocketConfig {
+            max_message_size: Some(self.security_config.max_response_size as usize),
+            ..WebSocketConfig::default()
+        };
+        
+        let (stream, _response) = timeout(
+            self.security_config.timeout,
+            tokio_tungstenite::connect_async_tls_with_config(
+                url,
+                Some(config),
+                false,
+                Some(Connector::Rustls(self.tls_config.clone())),
+            ),
+        ).await
+        .map_err(|_| EngineError::network(NetworkErrorKind::Timeout, Some(url), "WebSocket connection timeout"))?
+        .map_err(|e| websocket_connect_failed(url, e))?;
+        
+        let (sender, receiver) = stream.split();
+        Ok(WebSocketConnection {
+            url: url.to_string(),
+            sender,
+            receiver,
+            _slot: slot,
         })
     }
     
@@ -209,8 +619,8 @@ impl NetworkStack {
         let scheme = uri.scheme_str().unwrap_or("");
         let host = uri.host().unwrap_or("");
         
-        // Block non-HTTPS in strict mode
-        if self.security_config.require_https && scheme != "https" {
+        // Block non-HTTPS (and non-WSS) in strict mode
+        if self.security_config.require_https && !matches!(scheme, "https" | "wss") {
             return false;
         }
         
@@ -260,10 +670,10 @@ impl NetworkStack {
         response.status >= 200 && response.status < 300
     }
     
-    /// Extract security information from headers
-    fn extract_security_info(&self, headers: &hyper::HeaderMap) -> SecurityInfo {
+    /// Extract security information from headers and the TLS session, if any
+    fn extract_security_info(&self, headers: &hyper::HeaderMap, tls: Option<TlsInfo>) -> SecurityInfo {
         SecurityInfo {
-            https: true, // We only support HTTPS
+            https: tls.is_some(),
             hsts: headers.get("strict-transport-security").is_some(),
             csp: headers.get("content-security-policy")
                 .map(|v| v.to_str().unwrap_or("").to_string()),
@@ -271,6 +681,7 @@ impl NetworkStack {
                 .map(|v| v.to_str().unwrap_or("").to_string()),
             x_content_type_options: headers.get("x-content-type-options")
                 .map(|v| v.to_str().unwrap_or("").to_string()),
+            tls,
         }
     }
     
@@ -292,9 +703,200 @@ impl NetworkStack {
         }
     }
     
+    /// Register a request interceptor; interceptors run in registration order before each request
+    pub fn add_request_interceptor(&self, interceptor: RequestInterceptor) {
+        self.request_interceptors.write().unwrap_or_else(PoisonError::into_inner).push(interceptor);
+    }
+    
+    /// Register a response interceptor, run on status and headers before the body is read
+    pub fn add_response_interceptor(&self, interceptor: ResponseInterceptor) {
+        self.response_interceptors.write().unwrap_or_else(PoisonError::into_inner).push(interceptor);
+    }
+    
+    /// Apply the URL policy and request interceptors; a rewritten URL must pass the policy too
+    async fn intercept_request(&self, mut parts: RequestParts) -> Result<(RequestParts, Uri)> {
+        if let Some(upgraded) = self.upgrade_to_https(&parts.url).await? {
+            log::debug!("Upgrading {} to HTTPS for HSTS", parts.url);
+            parts.url = upgraded;
+        }
+        let original_url = parts.url.clone();
+        self.check_url(&original_url).await?;
+        
+        for interceptor in self.request_interceptors.read().unwrap_or_else(PoisonError::into_inner).iter() {
+            if let InterceptAction::Block(reason) = interceptor(&mut parts) {
+                return Err(EngineError::SecurityError(format!("Request to {} blocked by interceptor: {}", original_url, reason)));
+            }
+        }
+        
+        let uri = if parts.url == original_url {
+            parts.url.parse().map_err(|e| invalid_url(&parts.url, e))?
+        } else {
+            self.check_url(&parts.url).await?
+        };
+        Ok((parts, uri))
+    }
+    
+    /// Parse a URL and check it against the security policy
+    async fn check_url(&self, url: &str) -> Result<Uri> {
+        let uri: Uri = url.parse()
+            .map_err(|e| invalid_url(url, e))?;
+        if !self.is_url_allowed(&uri).await {
+            return Err(EngineError::SecurityError(format!("URL blocked by security policy: {}", url)));
+        }
+        Ok(uri)
+    }
+    
+    /// Run response interceptors in registration order, stopping at the first block
+    fn intercept_response(&self, url: &str, response: &Response<Body>) -> Result<()> {
+        let parts = ResponseParts {
+            url: url.to_string(),
+            status: response.status().as_u16(),
+            headers: response.headers().iter()
+                .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
+                .collect(),
+        };
+        for interceptor in self.response_interceptors.read().unwrap_or_else(PoisonError::into_inner).iter() {
+            if let InterceptAction::Block(reason) = interceptor(&parts) {
+                return Err(EngineError::SecurityError(format!("Response from {} blocked by interceptor: {}", url, reason)));
+            }
+        }
+        Ok(())
+    }
+    
+    /// Wait until the URI's host has a free connection slot; the slot is released on drop
+    async fn acquire_host_slot(&self, uri: &Uri) -> Result<HostSlot> {
+        let host = uri.authority().map(|a| a.as_str().to_string()).unwrap_or_default();
+        let semaphore = self.host_limits.lock().unwrap_or_else(PoisonError::into_inner)
+            .entry(host)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.network_config.max_connections_per_host.max(1))))
+            .clone();
+        
+        let queued_at = std::time::Instant::now();
+        let permit = semaphore.acquire_owned().await
+            .map_err(|_| EngineError::network(NetworkErrorKind::Other, None, "Connection limiter closed"))?;
+        let wait = queued_at.elapsed();
+        
+        {
+            let mut metrics = self.metrics.write().await;
+            metrics.total_queue_wait += wait;
+            if wait > metrics.max_queue_wait {
+                metrics.max_queue_wait = wait;
+            }
+        }
+        
+        let active = self.connections.active.fetch_add(1, Ordering::SeqCst) + 1;
+        self.connections.peak.fetch_max(active, Ordering::SeqCst);
+        
+        Ok(HostSlot {
+            _permit: permit,
+            connections: self.connections.clone(),
+        })
+    }
+    
+    /// Whether robots.txt for the URL's origin lets `user_agent` fetch it; robots.txt is
+    /// fetched once per origin and cached
+    pub async fn is_fetch_allowed(&self, url: &str, user_agent: &str) -> Result<bool> {
+        let uri: Uri = url.parse()
+            .map_err(|e| invalid_url(url, e))?;
+        let robots = self.robots_for(&uri).await?;
+        let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+        Ok(robots.is_allowed(path, user_agent))
+    }
+    
+    /// Robots check in polite mode and the per-host rate limit, before a request goes out
+    async fn pace_request(&self, url: &str, uri: &Uri, user_agent: &str) -> Result<()> {
+        if self.network_config.respect_robots_txt && !self.is_fetch_allowed(url, user_agent).await? {
+            return Err(EngineError::RobotsDisallowed(url.to_string()));
+        }
+        
+        let Some(limit) = &self.network_config.rate_limit else {
+            return Ok(());
+        };
+        let host = uri.authority().map(|a| a.as_str().to_string()).unwrap_or_default();
+        let wait = self.rate_limiters.lock().unwrap_or_else(PoisonError::into_inner)
+            .entry(host)
+            .or_insert_with(|| TokenBucket::new(limit))
+            .reserve(limit, Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+        Ok(())
+    }
+    
+    /// Cached robots.txt for the URI's origin, fetching it on first use or once it expires
+    async fn robots_for(&self, uri: &Uri) -> Result<Arc<RobotsTxt>> {
+        let scheme = uri.scheme_str().unwrap_or("https");
+        let authority = uri.authority()
+            .ok_or_else(|| EngineError::network(NetworkErrorKind::InvalidUrl, Some(&uri.to_string()), format!("URL has no host: {}", uri)))?;
+        let origin = format!("{}://{}", scheme, authority);
+        
+        if let Some((robots, expires)) = self.robots.read().await.get(&origin) {
+            if Instant::now() < *expires {
+                return Ok(robots.clone());
+            }
+        }
+        
+        let (robots, ttl) = self.fetch_robots(&origin).await;
+        let robots = Arc::new(robots);
+        self.robots.write().await.insert(origin, (robots.clone(), Instant::now() + ttl));
+        Ok(robots)
+    }
+    
+    /// Fetch and parse an origin's robots.txt, bypassing interceptors and the response cache,
+    /// along with how long to keep it. Per RFC 9309 redirects are followed, a missing file
+    /// allows everything and an unreachable one allows nothing until it is retried.
+    async fn fetch_robots(&self, origin: &str) -> (RobotsTxt, Duration) {
+        let unreachable = (RobotsTxt::disallow_all(), ROBOTS_FAILURE_TTL);
+        let mut url = format!("{}/robots.txt", origin);
+        
+        for _ in 0..=ROBOTS_MAX_REDIRECTS {
+            let request = Request::get(url.as_str())
+                .header("User-Agent", self.network_config.user_agent.as_str())
+                .body(Body::empty());
+            let Ok(request) = request else {
+                return unreachable;
+            };
+            
+            let response = match timeout(Duration::from_secs(10), self.http_client.request(request)).await {
+                Ok(Ok(response)) => response,
+                _ => return unreachable,
+            };
+            let status = response.status();
+            if status.is_redirection() {
+                let location = response.headers().get(hyper::header::LOCATION)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|location| url::Url::parse(&url).ok()?.join(location).ok());
+                match location {
+                    Some(location) => {
+                        url = location.to_string();
+                        continue;
+                    }
+                    None => return (RobotsTxt::default(), ROBOTS_TTL),
+                }
+            }
+            if status.is_client_error() {
+                return (RobotsTxt::default(), ROBOTS_TTL);
+            }
+            if !status.is_success() {
+                return unreachable;
+            }
+            
+            return match hyper::body::to_bytes(response.into_body()).await {
+                Ok(body) => (RobotsTxt::parse(&String::from_utf8_lossy(&body)), ROBOTS_TTL),
+                Err(_) => unreachable,
+            };
+        }
+        
+        // Too many redirects: the file is unavailable, which allows everything
+        (RobotsTxt::default(), ROBOTS_TTL)
+    }
+    
     /// Get network metrics
     pub async fn get_metrics(&self) -> NetworkMetrics {
-        self.metrics.read().await.clone()
+        let mut metrics = self.metrics.read().await.clone();
+        metrics.active_connections = self.connections.active.load(Ordering::SeqCst);
+        metrics.peak_active_connections = self.connections.peak.load(Ordering::SeqCst);
+        metrics
     }
     
     /// Clear cache
@@ -308,6 +910,15 @@ impl NetworkStack {
         self.security_config = config;
     }
     
+    /// Update connection settings; new limits apply to requests started afterwards
+    pub fn update_network_config(&mut self, config: NetworkConfig) {
+        *self.resolver_mode.write().unwrap_or_else(PoisonError::into_inner) = config.resolver.clone();
+        *self.connect_timeout.write().unwrap_or_else(PoisonError::into_inner) = config.connect_timeout;
+        self.network_config = config;
+        self.host_limits.lock().unwrap_or_else(PoisonError::into_inner).clear();
+        self.rate_limiters.lock().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+    
     /// Shutdown the network stack
     pub async fn shutdown(&self) -> Result<()> {
         // Clean up resources
@@ -356,44 +967,434 @@ impl NetworkResponse {
     }
 }
 
-/// Request options
-#[derive(Debug, Clone)]
-pub struct RequestOptions {
-    pub method: String,
-    pub headers: HashMap<String, String>,
-    pub body: Option<Vec<u8>>,
-    pub timeout: Duration,
-    pub follow_redirects: bool,
-    pub max_redirects: u32,
+type WebSocketTransport = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// An open WebSocket connection
+pub struct WebSocketConnection {
+    url: String,
+    sender: SplitSink<WebSocketTransport, WebSocketMessage>,
+    receiver: SplitStream<WebSocketTransport>,
+    
+    /// Held for the life of the connection
+    _slot: HostSlot,
 }
 
-impl Default for RequestOptions {
-    fn default() -> Self {
-        Self {
-            method: "GET".to_string(),
-            headers: HashMap::new(),
-            body: None,
-            timeout: Duration::from_secs(30),
-            follow_redirects: true,
-            max_redirects: 10,
+impl WebSocketConnection {
+    /// URL the connection was opened to
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+    
+    /// Send a message to the server
+    pub async fn send(&mut self, message: WebSocketMessage) -> Result<()> {
+        self.sender.send(message).await
+            .map_err(|e| EngineError::network(NetworkErrorKind::Other, Some(&self.url), format!("WebSocket send failed: {}", e)))
+    }
+    
+    /// Incoming messages; the stream ends when the connection closes
+    pub fn incoming(&mut self) -> impl Stream<Item = Result<WebSocketMessage>> + '_ {
+        let url = self.url.as_str();
+        (&mut self.receiver).map(move |message| {
+            message.map_err(|e| EngineError::network(NetworkErrorKind::Other, Some(url), format!("WebSocket receive failed: {}", e)))
+        })
+    }
+    
+    /// Send a close frame and shut down the connection
+    pub async fn close(mut self) -> Result<()> {
+        self.sender.close().await
+            .map_err(|e| EngineError::network(NetworkErrorKind::Other, Some(&self.url), format!("WebSocket close failed: {}", e)))
+    }
+}
+
+/// Undo the response's `Content-Encoding`, capping output at `max_size` bytes.
+/// Unknown encodings leave the body as received.
+fn decode_content(headers: &hyper::HeaderMap, body: Vec<u8>, max_size: u64) -> Result<Vec<u8>> {
+    let encodings: Vec<String> = headers.get_all(hyper::header::CONTENT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|encoding| encoding.trim().to_ascii_lowercase())
+        .filter(|encoding| !encoding.is_empty() && encoding != "identity")
+        .collect();
+    
+    // Codings are listed in the order they were applied, so undo them last to first
+    let mut body = body;
+    for encoding in encodings.iter().rev() {
+        let decoder: Box<dyn Read + '_> = match encoding.as_str() {
+            "gzip" | "x-gzip" => Box::new(flate2::read::GzDecoder::new(body.as_slice())),
+            "deflate" => Box::new(flate2::read::ZlibDecoder::new(body.as_slice())),
+            "br" => Box::new(brotli::Decompressor::new(body.as_slice(), 4096)),
+            other => {
+                log::warn!("Unsupported content encoding {:?}, leaving body encoded", other);
+                return Ok(body);
+            }
+        };
+        
+        // Read one byte past the cap so oversized output is detected rather than truncated
+        let mut decoded = Vec::new();
+        decoder.take(max_size.saturating_add(1)).read_to_end(&mut decoded)
+            .map_err(|e| EngineError::network(NetworkErrorKind::Other, None, format!("Failed to decode {} body: {}", encoding, e)))?;
+        if decoded.len() as u64 > max_size {
+            return Err(EngineError::network(NetworkErrorKind::Other, None, format!(
+                "Decoded response exceeds maximum size of {} bytes", max_size
+            )));
         }
+        body = decoded;
     }
+    
+    Ok(body)
 }
 
-/// Security information from response
-#[derive(Debug, Clone)]
-pub struct SecurityInfo {
-    pub https: bool,
-    pub hsts: bool,
-    pub csp: Option<String>,
-    pub x_frame_options: Option<String>,
-    pub x_content_type_options: Option<String>,
+/// Hook that can edit an outgoing request or block it
+pub type RequestInterceptor = Box<dyn Fn(&mut RequestParts) -> InterceptAction + Send + Sync>;
+
+/// Hook that can inspect a response's status and headers and block it
+pub type ResponseInterceptor = Box<dyn Fn(&ResponseParts) -> InterceptAction + Send + Sync>;
+
+/// An interceptor's verdict; blocking stops later interceptors from running
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterceptAction {
+    Allow,
+    Block(String),
 }
 
-/// Network security configuration
+/// An outgoing request as seen by interceptors; edits to the URL or headers are sent
 #[derive(Debug, Clone)]
-pub struct SecurityConfig {
-    pub require_https: bool,
+pub struct RequestParts {
+    pub method: String,
+    pub url: String,
+    pub headers: HashMap<String, String>,
+}
+
+impl RequestParts {
+    fn new<const N: usize>(method: &str, url: &str, headers: [(&str, &str); N]) -> Self {
+        Self {
+            method: method.to_string(),
+            url: url.to_string(),
+            headers: headers.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        }
+    }
+    
+    fn build_request(&self, uri: Uri, body: Body) -> Result<Request<Body>> {
+        let mut request_builder = Request::builder()
+            .method(self.method.as_str())
+            .uri(uri);
+        for (key, value) in &self.headers {
+            request_builder = request_builder.header(key, value);
+        }
+        request_builder
+            .body(body)
+            .map_err(|e| EngineError::network(NetworkErrorKind::InvalidUrl, Some(&self.url), format!("Failed to build request: {}", e)))
+    }
+}
+
+/// A response's status and headers, seen by interceptors before the body is read
+#[derive(Debug, Clone)]
+pub struct ResponseParts {
+    pub url: String,
+    pub status: u16,
+    pub headers: HashMap<String, String>,
+}
+
+/// Request options
+#[derive(Debug, Clone)]
+pub struct RequestOptions {
+    pub method: String,
+    pub headers: HashMap<String, String>,
+    pub body: Option<Vec<u8>>,
+    pub timeout: Duration,
+    pub follow_redirects: bool,
+    pub max_redirects: u32,
+    /// URL of the page making the request; cookies are held back when it is on another site
+    pub site_for_cookies: Option<String>,
+    /// User-Agent for this request instead of the stack's configured one
+    pub user_agent: Option<String>,
+    /// Send stored cookies and save the ones the response sets
+    pub credentials: bool,
+}
+
+impl Default for RequestOptions {
+    fn default() -> Self {
+        Self {
+            method: "GET".to_string(),
+            headers: HashMap::new(),
+            body: None,
+            timeout: Duration::from_secs(30),
+            follow_redirects: true,
+            max_redirects: 10,
+            site_for_cookies: None,
+            user_agent: None,
+            credentials: true,
+        }
+    }
+}
+
+/// Security information from response
+#[derive(Debug, Clone)]
+pub struct SecurityInfo {
+    pub https: bool,
+    pub hsts: bool,
+    pub csp: Option<String>,
+    pub x_frame_options: Option<String>,
+    pub x_content_type_options: Option<String>,
+    /// Negotiated TLS session, for responses received over HTTPS
+    pub tls: Option<TlsInfo>,
+}
+
+/// Parameters of the TLS session a response arrived over
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsInfo {
+    /// e.g. "TLS 1.3"
+    pub protocol_version: String,
+    /// IANA name, e.g. "TLS13_AES_128_GCM_SHA256"
+    pub cipher_suite: String,
+    /// SHA-256 of the leaf certificate as colon-separated hex
+    pub certificate_fingerprint: Option<String>,
+}
+
+impl TlsInfo {
+    fn from_connection(connection: &rustls::ClientConnection) -> Option<Self> {
+        let protocol_version = match connection.protocol_version()? {
+            rustls::ProtocolVersion::TLSv1_2 => "TLS 1.2".to_string(),
+            rustls::ProtocolVersion::TLSv1_3 => "TLS 1.3".to_string(),
+            other => format!("{:?}", other),
+        };
+        Some(Self {
+            protocol_version,
+            cipher_suite: format!("{:?}", connection.negotiated_cipher_suite()?.suite()),
+            certificate_fingerprint: connection.peer_certificates()
+                .and_then(|chain| chain.first())
+                .map(|leaf| certificate_fingerprint(&leaf.0)),
+        })
+    }
+}
+
+/// SHA-256 of a DER certificate as colon-separated uppercase hex, as browsers display it
+pub fn certificate_fingerprint(certificate: &[u8]) -> String {
+    ring::digest::digest(&ring::digest::SHA256, certificate).as_ref().iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// An expected certificate for a pinned host, matched against the leaf and the issuers it chains to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CertificatePin {
+    /// SHA-256 of the whole DER certificate
+    CertificateSha256([u8; 32]),
+    /// SHA-256 of the DER SubjectPublicKeyInfo, which survives certificate renewal with the same key
+    PublicKeySha256([u8; 32]),
+}
+
+impl CertificatePin {
+    pub fn matches(&self, certificate: &[u8]) -> bool {
+        let (hash, data) = match self {
+            CertificatePin::CertificateSha256(hash) => (hash, Some(certificate)),
+            CertificatePin::PublicKeySha256(hash) => (hash, subject_public_key_info(certificate)),
+        };
+        data.is_some_and(|data| ring::digest::digest(&ring::digest::SHA256, data).as_ref() == hash)
+    }
+}
+
+/// The DER SubjectPublicKeyInfo of an X.509 certificate, or `None` if it doesn't parse
+fn subject_public_key_info(certificate: &[u8]) -> Option<&[u8]> {
+    let (_, certificate, _) = der_element(certificate)?;
+    let (_, tbs_certificate, _) = der_element(certificate)?;
+    
+    // Skip the optional [0] version, then serial number, signature, issuer, validity and subject
+    let mut rest = tbs_certificate;
+    if rest.first() == Some(&0xA0) {
+        rest = der_element(rest)?.2;
+    }
+    for _ in 0..5 {
+        rest = der_element(rest)?.2;
+    }
+    Some(der_element(rest)?.0)
+}
+
+/// Split the first DER element off `input` as (whole element, contents, rest)
+fn der_element(input: &[u8]) -> Option<(&[u8], &[u8], &[u8])> {
+    let length_byte = *input.get(1)?;
+    let (length, header) = if length_byte & 0x80 == 0 {
+        (length_byte as usize, 2)
+    } else {
+        let count = (length_byte & 0x7F) as usize;
+        if count == 0 || count > 4 {
+            return None;
+        }
+        let length = input.get(2..2 + count)?.iter().fold(0usize, |length, byte| (length << 8) | *byte as usize);
+        (length, 2 + count)
+    };
+    let element = input.get(..header.checked_add(length)?)?;
+    Some((element, &element[header..], &input[element.len()..]))
+}
+
+/// Why a pinned host's certificate chain was rejected
+#[derive(Debug)]
+struct PinMismatch {
+    host: String,
+}
+
+impl std::fmt::Display for PinMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no certificate in the chain for {} matches its pins", self.host)
+    }
+}
+
+impl std::error::Error for PinMismatch {}
+
+/// Web PKI validation, preceded for pinned hosts by a check that the leaf, or a presented
+/// certificate the leaf chains up to, matches one of the host's pins
+struct PinningVerifier {
+    roots: WebPkiVerifier,
+    pins: Arc<RwLock<HashMap<String, Vec<CertificatePin>>>>,
+}
+
+impl PinningVerifier {
+    fn check_pins(
+        &self,
+        host: &str,
+        end_entity: &rustls::Certificate,
+        intermediates: &[rustls::Certificate],
+        server_name: &rustls::ServerName,
+        now: std::time::SystemTime,
+    ) -> std::result::Result<(), rustls::Error> {
+        let pins = self.pins.read().unwrap_or_else(PoisonError::into_inner);
+        let Some(host_pins) = pins.get(host).filter(|host_pins| !host_pins.is_empty()) else {
+            return Ok(());
+        };
+        
+        // A presented certificate only counts if the leaf validates up to it; otherwise a
+        // server could append the pinned certificate to an unrelated chain
+        let pinned = |certificate: &rustls::Certificate| host_pins.iter().any(|pin| pin.matches(&certificate.0));
+        let matched = pinned(end_entity) || intermediates.iter().enumerate()
+            .filter(|(_, certificate)| pinned(certificate))
+            .any(|(anchor, _)| chains_to(end_entity, intermediates, anchor, server_name, now));
+        if matched {
+            Ok(())
+        } else {
+            Err(rustls::Error::InvalidCertificate(rustls::CertificateError::Other(Arc::new(PinMismatch {
+                host: host.to_string(),
+            }))))
+        }
+    }
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        intermediates: &[rustls::Certificate],
+        server_name: &rustls::ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: std::time::SystemTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        let host = match server_name {
+            rustls::ServerName::DnsName(name) => name.as_ref().trim_end_matches('.').to_ascii_lowercase(),
+            rustls::ServerName::IpAddress(address) => address.to_string(),
+            _ => String::new(),
+        };
+        self.check_pins(&host, end_entity, intermediates, server_name, now)?;
+        self.roots.verify_server_cert(end_entity, intermediates, server_name, scts, ocsp_response, now)
+    }
+}
+
+/// Whether `end_entity` validates up to `intermediates[anchor]` taken as the only trust anchor,
+/// through the other presented certificates
+fn chains_to(
+    end_entity: &rustls::Certificate,
+    intermediates: &[rustls::Certificate],
+    anchor: usize,
+    server_name: &rustls::ServerName,
+    now: std::time::SystemTime,
+) -> bool {
+    let mut roots = RootCertStore::empty();
+    if roots.add(&intermediates[anchor]).is_err() {
+        return false;
+    }
+    let path: Vec<rustls::Certificate> = intermediates.iter().enumerate()
+        .filter(|(index, _)| *index != anchor)
+        .map(|(_, certificate)| certificate.clone())
+        .collect();
+    WebPkiVerifier::new(roots, None)
+        .verify_server_cert(end_entity, &path, server_name, &mut std::iter::empty(), &[], now)
+        .is_ok()
+}
+
+/// HTTPS connector that attaches each connection's `TlsInfo` to the responses it carries
+#[derive(Clone)]
+struct TlsInfoConnector {
+    inner: hyper_rustls::HttpsConnector<HttpConnector<StackResolver>>,
+    connect_timeout: Arc<RwLock<Option<Duration>>>,
+}
+
+impl hyper::service::Service<Uri> for TlsInfoConnector {
+    type Response = TlsInfoStream;
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    type Future = Pin<Box<dyn Future<Output = std::result::Result<Self::Response, Self::Error>> + Send>>;
+    
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<std::result::Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+    
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let connecting = self.inner.call(uri);
+        let limit = *self.connect_timeout.read().unwrap_or_else(PoisonError::into_inner);
+        Box::pin(async move {
+            let stream = match limit {
+                Some(limit) => timeout(limit, connecting).await.map_err(|_| ConnectTimeout(limit))??,
+                None => connecting.await?,
+            };
+            let tls = match &stream {
+                MaybeHttpsStream::Https(tls_stream) => TlsInfo::from_connection(tls_stream.get_ref().1),
+                MaybeHttpsStream::Http(_) => None,
+            };
+            Ok(TlsInfoStream { stream, tls })
+        })
+    }
+}
+
+/// A connection and the TLS session it was established with
+struct TlsInfoStream {
+    stream: MaybeHttpsStream<tokio::net::TcpStream>,
+    tls: Option<TlsInfo>,
+}
+
+impl Connection for TlsInfoStream {
+    fn connected(&self) -> Connected {
+        let connected = self.stream.connected();
+        match &self.tls {
+            Some(tls) => connected.extra(tls.clone()),
+            None => connected,
+        }
+    }
+}
+
+impl tokio::io::AsyncRead for TlsInfoStream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut tokio::io::ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl tokio::io::AsyncWrite for TlsInfoStream {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+    
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+    
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+/// Network security configuration
+#[derive(Debug, Clone)]
+pub struct SecurityConfig {
+    pub require_https: bool,
     pub blocked_domains: Vec<String>,
     pub allowed_domains: Vec<String>,
     pub max_request_size: u64,
@@ -401,19 +1402,719 @@ pub struct SecurityConfig {
     pub timeout: Duration,
 }
 
-impl Default for SecurityConfig {
-    fn default() -> Self {
-        Self {
-            require_https: true,
-            blocked_domains: vec![
-                "malware.com".to_string(),
-                "phishing.net".to_string(),
-            ],
-            allowed_domains: Vec::new(),
-            max_request_size: 10 * 1024 * 1024, // 10MB
-            max_response_size: 100 * 1024 * 1024, // 100MB
-            timeout: Duration::from_secs(30),
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        Self {
+            require_https: true,
+            blocked_domains: vec![
+                "malware.com".to_string(),
+                "phishing.net".to_string(),
+            ],
+            allowed_domains: Vec::new(),
+            max_request_size: 10 * 1024 * 1024, // 10MB
+            max_response_size: 100 * 1024 * 1024, // 100MB
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Parse a `Set-Cookie` header received from `host` for a request to `request_path`.
+/// Host-only cookies are stored under the bare host and Domain cookies under ".domain";
+/// a Domain the host doesn't belong to rejects the cookie.
+fn parse_set_cookie(header: &str, host: &str, request_path: &str, now: i64) -> Option<crate::storage::Cookie> {
+    let mut segments = header.split(';');
+    let (name, value) = segments.next()?.split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+    
+    let host = host.to_ascii_lowercase();
+    let mut cookie = crate::storage::Cookie {
+        domain: host.clone(),
+        name: name.to_string(),
+        value: value.trim().trim_matches('"').to_string(),
+        // Default path is the request path's directory
+        path: match request_path.rfind('/') {
+            Some(0) | None => "/".to_string(),
+            Some(index) => request_path[..index].to_string(),
+        },
+        expires: None,
+        secure: false,
+        http_only: false,
+        same_site: SameSite::Lax,
+    };
+    let mut max_age = None;
+    
+    for attribute in segments {
+        let (key, value) = match attribute.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => (attribute.trim(), ""),
+        };
+        match key.to_ascii_lowercase().as_str() {
+            "domain" if !value.is_empty() => {
+                let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                if host != domain && !host.ends_with(&format!(".{}", domain)) {
+                    return None;
+                }
+                // A public suffix like `com` or `co.uk` would make a supercookie; it's only
+                // allowed when the host itself is that suffix, and then stays host-only
+                if is_ip_address(&host) || registrable_domain(&domain).is_none() {
+                    if host != domain {
+                        return None;
+                    }
+                    cookie.domain = host.clone();
+                } else {
+                    cookie.domain = format!(".{}", domain);
+                }
+            }
+            "path" if value.starts_with('/') => cookie.path = value.to_string(),
+            "max-age" => max_age = value.parse::<i64>().ok(),
+            "expires" => {
+                if let Some(expires) = parse_cookie_date(value) {
+                    cookie.expires = Some(expires);
+                }
+            }
+            "secure" => cookie.secure = true,
+            "httponly" => cookie.http_only = true,
+            "samesite" => cookie.same_site = SameSite::parse(value).unwrap_or_default(),
+            _ => {}
+        }
+    }
+    
+    // Browsers reject SameSite=None cookies that aren't also Secure
+    if cookie.same_site == SameSite::None && !cookie.secure {
+        return None;
+    }
+    
+    // Max-Age wins over Expires
+    if let Some(max_age) = max_age {
+        cookie.expires = Some(now.saturating_add(max_age.max(0)));
+    }
+    Some(cookie)
+}
+
+/// A parsed `Strict-Transport-Security` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct HstsPolicy {
+    max_age: u64,
+    include_subdomains: bool,
+}
+
+/// Parse `Strict-Transport-Security`; without a valid `max-age` the header is ignored
+fn parse_strict_transport_security(header: &str) -> Option<HstsPolicy> {
+    let mut max_age = None;
+    let mut include_subdomains = false;
+    for directive in header.split(';') {
+        let (name, value) = match directive.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+            None => (directive.trim(), None),
+        };
+        if name.eq_ignore_ascii_case("max-age") {
+            max_age = Some(value?.parse().ok()?);
+        } else if name.eq_ignore_ascii_case("includesubdomains") {
+            include_subdomains = true;
+        }
+    }
+    Some(HstsPolicy {
+        max_age: max_age?,
+        include_subdomains,
+    })
+}
+
+fn is_ip_address(host: &str) -> bool {
+    host.parse::<std::net::IpAddr>().is_ok() || host.starts_with('[')
+}
+
+/// The registrable domain (eTLD+1) of a host name, e.g. `example.co.uk` for `www.example.co.uk`;
+/// `None` when the host is itself a public suffix
+fn registrable_domain(host: &str) -> Option<&str> {
+    psl::domain_str(host)
+}
+
+/// Whether `host` is on the same site as the page at `site_url`: the same registrable
+/// domain, or the same host for IP addresses and hosts that are public suffixes
+fn is_same_site(site_url: &str, host: &str) -> bool {
+    fn site(host: &str) -> String {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        if is_ip_address(&host) {
+            return host;
+        }
+        registrable_domain(&host).map(str::to_string).unwrap_or(host)
+    }
+    
+    url::Url::parse(site_url).ok()
+        .and_then(|url| url.host_str().map(site))
+        .is_some_and(|page_site| page_site == site(host))
+}
+
+/// Unix timestamp of a cookie `Expires` date, in RFC 1123 or the older dashed form
+fn parse_cookie_date(value: &str) -> Option<i64> {
+    if let Ok(date) = chrono::DateTime::parse_from_rfc2822(value) {
+        return Some(date.timestamp());
+    }
+    chrono::NaiveDateTime::parse_from_str(value, "%a, %d-%b-%Y %H:%M:%S GMT")
+        .ok()
+        .map(|date| date.and_utc().timestamp())
+}
+
+/// Connection management settings
+#[derive(Debug, Clone)]
+pub struct NetworkConfig {
+    /// Requests to one host beyond this many wait for a free slot
+    pub max_connections_per_host: usize,
+    
+    /// How HTTP requests resolve hostnames
+    pub resolver: ResolverMode,
+    
+    /// Polite mode: refuse requests that the host's robots.txt disallows
+    pub respect_robots_txt: bool,
+    
+    /// Pace requests to each host; unlimited when `None`
+    pub rate_limit: Option<RateLimit>,
+    
+    /// User-Agent sent with every request that doesn't set its own
+    pub user_agent: String,
+    
+    /// Accept-Language sent with every request that doesn't set its own
+    pub accept_language: String,
+    
+    /// Limit on DNS lookup, TCP connect and TLS handshake together; `None` leaves only the overall timeout
+    pub connect_timeout: Option<Duration>,
+    
+    /// Longest the response body may go without new data; `None` leaves only the overall timeout
+    pub read_timeout: Option<Duration>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            max_connections_per_host: 6,
+            resolver: ResolverMode::default(),
+            respect_robots_txt: false,
+            rate_limit: None,
+            user_agent: USER_AGENT.to_string(),
+            accept_language: ACCEPT_LANGUAGE.to_string(),
+            connect_timeout: Some(Duration::from_secs(10)),
+            read_timeout: Some(Duration::from_secs(20)),
+        }
+    }
+}
+
+/// Token-bucket limit on requests to one host
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimit {
+    /// Sustained rate the bucket refills at
+    pub requests_per_second: f64,
+    
+    /// Requests allowed back to back before pacing starts
+    pub burst: u32,
+}
+
+/// Token bucket for one host; tokens may go negative to queue callers in order
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: &RateLimit) -> Self {
+        Self {
+            tokens: limit.burst.max(1) as f64,
+            last_refill: Instant::now(),
+        }
+    }
+    
+    /// Take a token and return how long the caller must wait before using it
+    fn reserve(&mut self, limit: &RateLimit, now: Instant) -> Duration {
+        let rate = limit.requests_per_second.max(f64::EPSILON);
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(limit.burst.max(1) as f64);
+        self.last_refill = now;
+        
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / rate)
+        }
+    }
+}
+
+/// Parsed robots.txt (RFC 9309)
+#[derive(Debug, Clone, Default)]
+pub struct RobotsTxt {
+    groups: Vec<RobotsGroup>,
+}
+
+/// Rules that apply to a set of user agents
+#[derive(Debug, Clone, Default)]
+struct RobotsGroup {
+    /// Lowercased product tokens, or "*"
+    user_agents: Vec<String>,
+    /// (allow, path pattern) pairs
+    rules: Vec<(bool, String)>,
+}
+
+impl RobotsTxt {
+    /// Parse robots.txt, ignoring lines it doesn't understand
+    pub fn parse(content: &str) -> Self {
+        let mut groups: Vec<RobotsGroup> = Vec::new();
+        let mut in_agent_lines = false;
+        
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((field, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            
+            match field.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    // Consecutive User-agent lines share one group
+                    if !in_agent_lines {
+                        groups.push(RobotsGroup::default());
+                    }
+                    in_agent_lines = true;
+                    if let Some(group) = groups.last_mut() {
+                        group.user_agents.push(value.to_ascii_lowercase());
+                    }
+                }
+                field @ ("allow" | "disallow") => {
+                    in_agent_lines = false;
+                    // An empty Disallow allows everything, so it adds no rule
+                    if let (Some(group), false) = (groups.last_mut(), value.is_empty()) {
+                        group.rules.push((field == "allow", value.to_string()));
+                    }
+                }
+                _ => in_agent_lines = false,
+            }
+        }
+        
+        Self { groups }
+    }
+    
+    /// Robots.txt that blocks every path, used when the file can't be retrieved
+    fn disallow_all() -> Self {
+        Self {
+            groups: vec![RobotsGroup {
+                user_agents: vec!["*".to_string()],
+                rules: vec![(false, "/".to_string())],
+            }],
+        }
+    }
+    
+    /// Whether `user_agent` may fetch `path`; the longest matching rule wins, and Allow
+    /// wins a tie
+    pub fn is_allowed(&self, path: &str, user_agent: &str) -> bool {
+        let user_agent = user_agent.to_ascii_lowercase();
+        let named: Vec<&RobotsGroup> = self.groups.iter()
+            .filter(|group| group.user_agents.iter().any(|token| token != "*" && user_agent.contains(token.as_str())))
+            .collect();
+        let groups = if named.is_empty() {
+            self.groups.iter().filter(|group| group.user_agents.iter().any(|token| token == "*")).collect()
+        } else {
+            named
+        };
+        
+        groups.iter()
+            .flat_map(|group| group.rules.iter())
+            .filter(|(_, pattern)| robots_pattern_matches(pattern, path))
+            .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+            .map_or(true, |(allow, _)| *allow)
+    }
+}
+
+/// Match a robots.txt path pattern, where `*` is any run of characters and a trailing `$`
+/// anchors the end
+fn robots_pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut pieces = pattern.split('*');
+    let first = pieces.next().unwrap_or("");
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+    
+    let pieces: Vec<&str> = pieces.collect();
+    for (index, piece) in pieces.iter().enumerate() {
+        // The last piece of an anchored pattern has to sit at the very end
+        if anchored && index + 1 == pieces.len() {
+            return rest.ends_with(piece);
+        }
+        match rest.find(piece) {
+            Some(position) => rest = &rest[position + piece.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+/// Hostname resolution strategy for HTTP requests
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum ResolverMode {
+    /// The operating system's resolver
+    #[default]
+    System,
+    
+    /// A DNS-over-HTTPS server speaking the JSON API (`?name=...&type=A`)
+    DnsOverHttps {
+        endpoint: String,
+        /// Use the system resolver when the DoH query fails
+        fallback_to_system: bool,
+    },
+}
+
+impl ResolverMode {
+    /// Cloudflare's DoH service, falling back to the system resolver
+    pub fn cloudflare() -> Self {
+        ResolverMode::DnsOverHttps {
+            endpoint: "https://cloudflare-dns.com/dns-query".to_string(),
+            fallback_to_system: true,
+        }
+    }
+    
+    /// Google's DoH service, falling back to the system resolver
+    pub fn google() -> Self {
+        ResolverMode::DnsOverHttps {
+            endpoint: "https://dns.google/resolve".to_string(),
+            fallback_to_system: true,
+        }
+    }
+}
+
+/// Resolves hostnames through a DNS-over-HTTPS JSON API, caching answers for their TTL
+pub struct DohResolver {
+    /// Client for DoH queries; the DoH server's own name goes through the system resolver
+    client: Client<hyper_rustls::HttpsConnector<HttpConnector>>,
+    cache: Mutex<HashMap<String, CachedAddresses>>,
+    queries: AtomicU64,
+}
+
+struct CachedAddresses {
+    addresses: Vec<IpAddr>,
+    expires: Instant,
+}
+
+#[derive(Deserialize)]
+struct DohResponse {
+    #[serde(rename = "Status")]
+    status: u32,
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DohAnswer>,
+}
+
+#[derive(Deserialize)]
+struct DohAnswer {
+    #[serde(rename = "type")]
+    record_type: u16,
+    #[serde(rename = "TTL")]
+    ttl: u32,
+    data: String,
+}
+
+impl DohResolver {
+    fn new(tls_config: ClientConfig) -> Self {
+        let connector = HttpsConnectorBuilder::new()
+            .with_tls_config(tls_config)
+            .https_or_http()
+            .enable_http1()
+            .enable_http2()
+            .build();
+        
+        Self {
+            client: Client::builder().build(connector),
+            cache: Mutex::new(HashMap::new()),
+            queries: AtomicU64::new(0),
+        }
+    }
+    
+    /// Resolve `host` to its A and AAAA addresses using the DoH server at `endpoint`
+    pub async fn resolve(&self, endpoint: &str, host: &str) -> Result<Vec<IpAddr>> {
+        if let Some(cached) = self.cache.lock().unwrap_or_else(PoisonError::into_inner).get(host) {
+            if cached.expires > Instant::now() {
+                return Ok(cached.addresses.clone());
+            }
+        }
+        
+        let mut addresses = Vec::new();
+        let mut ttl = u32::MAX;
+        for record_type in ["A", "AAAA"] {
+            for (address, record_ttl) in self.query(endpoint, host, record_type).await? {
+                addresses.push(address);
+                ttl = ttl.min(record_ttl);
+            }
+        }
+        
+        if addresses.is_empty() {
+            return Err(EngineError::network(NetworkErrorKind::Dns, Some(endpoint), format!("DoH returned no addresses for {}", host)));
+        }
+        
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner).insert(host.to_string(), CachedAddresses {
+            addresses: addresses.clone(),
+            expires: Instant::now() + Duration::from_secs(ttl as u64),
+        });
+        Ok(addresses)
+    }
+    
+    /// Number of DoH queries sent, one per record type per uncached lookup
+    pub fn query_count(&self) -> u64 {
+        self.queries.load(Ordering::SeqCst)
+    }
+    
+    async fn query(&self, endpoint: &str, host: &str, record_type: &str) -> Result<Vec<(IpAddr, u32)>> {
+        self.queries.fetch_add(1, Ordering::SeqCst);
+        
+        let uri: Uri = format!("{}?name={}&type={}", endpoint, host, record_type).parse()
+            .map_err(|e| EngineError::network(NetworkErrorKind::InvalidUrl, Some(endpoint), format!("Invalid DoH query: {}", e)))?;
+        let request = Request::builder()
+            .uri(uri)
+            .header("Accept", "application/dns-json")
+            .body(Body::empty())
+            .map_err(|e| EngineError::network(NetworkErrorKind::InvalidUrl, Some(endpoint), format!("Failed to build DoH request: {}", e)))?;
+        
+        let response = timeout(Duration::from_secs(5), self.client.request(request)).await
+            .map_err(|_| EngineError::network(NetworkErrorKind::Timeout, Some(endpoint), "DoH query timeout"))?
+            .map_err(|e| EngineError::network(request_error_kind(&e), Some(endpoint), format!("DoH query failed: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(EngineError::network(
+                NetworkErrorKind::Status(response.status().as_u16()),
+                Some(endpoint),
+                format!("DoH query failed with status {}", response.status()),
+            ));
+        }
+        
+        let body = hyper::body::to_bytes(response.into_body()).await
+            .map_err(|e| EngineError::network(NetworkErrorKind::Other, Some(endpoint), format!("Failed to read DoH response: {}", e)))?;
+        let response: DohResponse = serde_json::from_slice(&body)
+            .map_err(|e| EngineError::network(NetworkErrorKind::Dns, Some(endpoint), format!("Malformed DoH response: {}", e)))?;
+        if response.status != 0 {
+            return Err(EngineError::network(NetworkErrorKind::Dns, Some(endpoint), format!("DoH lookup for {} failed with rcode {}", host, response.status)));
+        }
+        
+        // Skip CNAMEs and anything else that isn't an address record
+        Ok(response.answer.into_iter()
+            .filter(|answer| answer.record_type == 1 || answer.record_type == 28)
+            .filter_map(|answer| answer.data.parse().ok().map(|address| (address, answer.ttl)))
+            .collect())
+    }
+}
+
+/// Resolver plugged into the HTTP connector, following the stack's current `ResolverMode`
+#[derive(Clone)]
+pub struct StackResolver {
+    mode: Arc<RwLock<ResolverMode>>,
+    doh: Arc<DohResolver>,
+}
+
+impl hyper::service::Service<Name> for StackResolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = std::io::Error;
+    type Future = Pin<Box<dyn Future<Output = std::io::Result<Self::Response>> + Send>>;
+    
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+    
+    fn call(&mut self, name: Name) -> Self::Future {
+        let mode = self.mode.read().unwrap_or_else(PoisonError::into_inner).clone();
+        let doh = self.doh.clone();
+        
+        Box::pin(async move {
+            let host = name.as_str();
+            let addresses = match mode {
+                ResolverMode::System => system_lookup(host).await?,
+                ResolverMode::DnsOverHttps { endpoint, fallback_to_system } => {
+                    match doh.resolve(&endpoint, host).await {
+                        // The connector fills in the port from the URL
+                        Ok(addresses) => addresses.into_iter().map(|ip| SocketAddr::new(ip, 0)).collect(),
+                        Err(e) if fallback_to_system => {
+                            log::warn!("DoH lookup for {} failed, using system resolver: {:?}", host, e);
+                            system_lookup(host).await?
+                        }
+                        Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::Other, DnsLookupError(e.to_string()))),
+                    }
+                }
+            };
+            Ok(addresses.into_iter())
+        })
+    }
+}
+
+async fn system_lookup(host: &str) -> std::io::Result<Vec<SocketAddr>> {
+    let addresses = tokio::net::lookup_host((host, 0)).await
+        .map_err(|e| std::io::Error::new(e.kind(), DnsLookupError(format!("{}: {}", host, e))))?;
+    Ok(addresses.collect())
+}
+
+/// A resolver failure, marked so request errors can be reported as DNS errors
+#[derive(Debug)]
+struct DnsLookupError(String);
+
+impl std::fmt::Display for DnsLookupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DNS lookup failed for {}", self.0)
+    }
+}
+
+impl std::error::Error for DnsLookupError {}
+
+/// A connection not established within `NetworkConfig::connect_timeout`
+#[derive(Debug)]
+struct ConnectTimeout(Duration);
+
+impl std::fmt::Display for ConnectTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "connect timeout after {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ConnectTimeout {}
+
+/// Classify a failed request by walking its chain of causes
+fn request_error_kind(error: &hyper::Error) -> NetworkErrorKind {
+    let mut cause: Option<&(dyn std::error::Error + 'static)> = Some(error);
+    while let Some(current) = cause {
+        if current.is::<DnsLookupError>() {
+            return NetworkErrorKind::Dns;
+        }
+        if current.is::<ConnectTimeout>() {
+            return NetworkErrorKind::Timeout;
+        }
+        if current.is::<rustls::Error>() {
+            return NetworkErrorKind::Tls;
+        }
+        // I/O errors hide what they wrap from `source()`, so look inside them directly
+        if let Some(io_error) = current.downcast_ref::<std::io::Error>() {
+            match io_error.get_ref() {
+                Some(inner) if inner.is::<DnsLookupError>() => return NetworkErrorKind::Dns,
+                Some(inner) if inner.is::<rustls::Error>() => return NetworkErrorKind::Tls,
+                _ => {}
+            }
+            if io_error.kind() == std::io::ErrorKind::TimedOut {
+                return NetworkErrorKind::Timeout;
+            }
+        }
+        cause = current.source();
+    }
+    
+    if error.is_timeout() {
+        NetworkErrorKind::Timeout
+    } else if error.is_connect() {
+        NetworkErrorKind::Connect
+    } else {
+        NetworkErrorKind::Other
+    }
+}
+
+/// Error for a request to `url` that failed before a response arrived
+fn request_failed(url: &str, error: hyper::Error) -> EngineError {
+    if let Some(host) = pin_mismatch_host(&error) {
+        return EngineError::CertificatePinMismatch {
+            host: host.to_string(),
+            url: url.to_string(),
+        };
+    }
+    if let Some(limit) = connect_timeout_cause(&error) {
+        return EngineError::network(NetworkErrorKind::Timeout, Some(url), format!("Connect timeout: no connection after {:?}", limit));
+    }
+    EngineError::network(request_error_kind(&error), Some(url), format!("Request failed: {}", error))
+}
+
+/// The connect timeout that expired, if that's why the request failed
+fn connect_timeout_cause(error: &(dyn std::error::Error + 'static)) -> Option<Duration> {
+    let mut cause = Some(error);
+    while let Some(current) = cause {
+        if let Some(ConnectTimeout(limit)) = current.downcast_ref::<ConnectTimeout>() {
+            return Some(*limit);
+        }
+        cause = current.source();
+    }
+    None
+}
+
+/// Read a response body, failing once it goes `read_timeout` without data or runs past `deadline`
+async fn read_body(url: &str, mut body: Body, read_timeout: Option<Duration>, deadline: tokio::time::Instant) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    loop {
+        let idle_deadline = read_timeout.map(|limit| tokio::time::Instant::now() + limit);
+        let wait_until = idle_deadline.map_or(deadline, |idle| idle.min(deadline));
+        match tokio::time::timeout_at(wait_until, body.data()).await {
+            Ok(Some(chunk)) => {
+                let chunk = chunk.map_err(|e| EngineError::network(NetworkErrorKind::Other, Some(url), format!("Failed to read response body: {}", e)))?;
+                bytes.extend_from_slice(&chunk);
+            }
+            Ok(None) => return Ok(bytes),
+            Err(_) => return Err(match read_timeout {
+                Some(limit) if wait_until < deadline => {
+                    EngineError::network(NetworkErrorKind::Timeout, Some(url), format!("Read timeout: no data for {:?}", limit))
+                }
+                _ => request_timeout(url),
+            }),
+        }
+    }
+}
+
+/// The host whose pins rejected the connection, if that's why the error happened
+fn pin_mismatch_host<'e>(error: &'e (dyn std::error::Error + 'static)) -> Option<&'e str> {
+    let mut cause = Some(error);
+    while let Some(current) = cause {
+        let tls_error = current.downcast_ref::<rustls::Error>().or_else(|| {
+            current.downcast_ref::<std::io::Error>()
+                .and_then(|io_error| io_error.get_ref())
+                .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+        });
+        if let Some(rustls::Error::InvalidCertificate(rustls::CertificateError::Other(reason))) = tls_error {
+            if let Some(mismatch) = reason.downcast_ref::<PinMismatch>() {
+                return Some(&mismatch.host);
+            }
         }
+        cause = current.source();
+    }
+    None
+}
+
+fn request_timeout(url: &str) -> EngineError {
+    EngineError::network(NetworkErrorKind::Timeout, Some(url), "Request timeout")
+}
+
+fn invalid_url(url: &str, error: impl std::fmt::Display) -> EngineError {
+    EngineError::network(NetworkErrorKind::InvalidUrl, Some(url), format!("Invalid URL: {}", error))
+}
+
+/// Error for a WebSocket handshake that failed, classified like HTTP request failures
+fn websocket_connect_failed(url: &str, error: tokio_tungstenite::tungstenite::Error) -> EngineError {
+    use tokio_tungstenite::tungstenite::Error as WsError;
+    let kind = match &error {
+        WsError::Tls(_) => NetworkErrorKind::Tls,
+        WsError::Http(response) => NetworkErrorKind::Status(response.status().as_u16()),
+        WsError::Url(_) => NetworkErrorKind::InvalidUrl,
+        WsError::Io(io_error) if io_error.kind() == std::io::ErrorKind::TimedOut => NetworkErrorKind::Timeout,
+        WsError::Io(_) => NetworkErrorKind::Connect,
+        _ => NetworkErrorKind::Other,
+    };
+    EngineError::network(kind, Some(url), format!("WebSocket connection failed: {}", error))
+}
+
+/// Connection counts updated as host slots are taken and released
+#[derive(Debug, Default)]
+struct ConnectionCounters {
+    active: AtomicU64,
+    peak: AtomicU64,
+}
+
+/// A held connection slot for one host
+struct HostSlot {
+    _permit: OwnedSemaphorePermit,
+    connections: Arc<ConnectionCounters>,
+}
+
+impl Drop for HostSlot {
+    fn drop(&mut self) {
+        self.connections.active.fetch_sub(1, Ordering::SeqCst);
     }
 }
 
@@ -461,6 +2162,22 @@ pub struct NetworkMetrics {
     pub cache_hits: u64,
     pub cache_misses: u64,
     pub average_load_time: Duration,
+    pub active_connections: u64,
+    pub peak_active_connections: u64,
+    pub total_queue_wait: Duration,
+    pub max_queue_wait: Duration,
+}
+
+#[async_trait::async_trait]
+impl MetricsReporter for NetworkStack {
+    async fn get_metrics(&self) -> PerformanceMetrics {
+        let network = NetworkStack::get_metrics(self).await;
+        PerformanceMetrics {
+            network_bytes_sent: network.total_bytes_sent,
+            network_bytes_received: network.total_bytes_received,
+            ..PerformanceMetrics::default()
+        }
+    }
 }
 
 impl NetworkMetrics {
@@ -492,6 +2209,78 @@ mod tests {
         // Basic test to ensure stack can be created
     }
     
+    #[tokio::test]
+    async fn test_websocket_echo() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut server = tokio_tungstenite::accept_async(socket).await.unwrap();
+            while let Some(Ok(message)) = server.next().await {
+                if message.is_close() {
+                    break;
+                }
+                server.send(message).await.unwrap();
+            }
+        });
+        
+        let mut stack = NetworkStack::new().await.unwrap();
+        let url = format!("ws://{}/echo", addr);
+        
+        // Plain ws:// is refused while HTTPS is required, as are non-WebSocket schemes
+        assert!(matches!(stack.connect_websocket(&url).await, Err(EngineError::SecurityError(_))));
+        assert!(stack.connect_websocket("https://example.com/").await.is_err());
+        
+        stack.update_security_config(SecurityConfig {
+            require_https: false,
+            ..SecurityConfig::default()
+        });
+        let mut connection = stack.connect_websocket(&url).await.unwrap();
+        
+        connection.send(WebSocketMessage::Text("ping".to_string())).await.unwrap();
+        let reply = connection.incoming().next().await.unwrap().unwrap();
+        assert_eq!(reply, WebSocketMessage::Text("ping".to_string()));
+        
+        connection.send(WebSocketMessage::Binary(vec![1, 2, 3])).await.unwrap();
+        let reply = connection.incoming().next().await.unwrap().unwrap();
+        assert_eq!(reply, WebSocketMessage::Binary(vec![1, 2, 3]));
+        
+        connection.close().await.unwrap();
+    }
+    
+    fn encoded_headers(encoding: &str) -> hyper::HeaderMap {
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert(hyper::header::CONTENT_ENCODING, encoding.parse().unwrap());
+        headers
+    }
+    
+    #[test]
+    fn test_gzip_body_decoding() {
+        use std::io::Write;
+        
+        let html = "<html><body>compressed</body></html>".repeat(10);
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(html.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+        
+        let decoded = decode_content(&encoded_headers("gzip"), compressed.clone(), 1024 * 1024).unwrap();
+        assert_eq!(decoded, html.as_bytes());
+        
+        // Output past the size cap is rejected rather than truncated
+        assert!(decode_content(&encoded_headers("gzip"), compressed, 64).is_err());
+    }
+    
+    #[test]
+    fn test_unsupported_encoding_is_left_as_is() {
+        let body = b"opaque bytes".to_vec();
+        
+        let decoded = decode_content(&encoded_headers("zstd"), body.clone(), 1024).unwrap();
+        assert_eq!(decoded, body);
+        
+        let decoded = decode_content(&encoded_headers("identity"), body.clone(), 1024).unwrap();
+        assert_eq!(decoded, body);
+    }
+    
     #[test]
     fn test_request_options() {
         let options = RequestOptions::default();
@@ -520,6 +2309,7 @@ mod tests {
                 csp: None,
                 x_frame_options: None,
                 x_content_type_options: None,
+                tls: None,
             },
         };
         
@@ -546,6 +2336,7 @@ mod tests {
                 csp: None,
                 x_frame_options: None,
                 x_content_type_options: None,
+                tls: None,
             },
         };
         
@@ -555,4 +2346,838 @@ mod tests {
         cache.clear();
         assert!(cache.get("https://example.com").is_none());
     }
-}
\ No newline at end of file
+    
+    #[tokio::test]
+    async fn test_per_host_connection_limit() {
+        use hyper::service::{make_service_fn, service_fn};
+        use std::convert::Infallible;
+        
+        // The server counts requests in flight and remembers the highest count it saw
+        let counters = Arc::new(ConnectionCounters::default());
+        let server_counters = counters.clone();
+        let make_service = make_service_fn(move |_| {
+            let counters = server_counters.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |_request: Request<Body>| {
+                    let counters = counters.clone();
+                    async move {
+                        let active = counters.active.fetch_add(1, Ordering::SeqCst) + 1;
+                        counters.peak.fetch_max(active, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        counters.active.fetch_sub(1, Ordering::SeqCst);
+                        Ok::<_, Infallible>(Response::new(Body::from("ok")))
+                    }
+                }))
+            }
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        
+        let mut stack = NetworkStack::new().await.unwrap();
+        stack.update_security_config(SecurityConfig {
+            require_https: false,
+            ..SecurityConfig::default()
+        });
+        stack.update_network_config(NetworkConfig {
+            max_connections_per_host: 3,
+            ..NetworkConfig::default()
+        });
+        
+        // Four times the limit; the extra requests queue instead of failing
+        let requests = (0..12).map(|i| {
+            let url = format!("http://{}/resource/{}", addr, i);
+            let stack = &stack;
+            async move { stack.fetch(&url).await }
+        });
+        let responses = futures::future::join_all(requests).await;
+        
+        assert!(responses.iter().all(|r| matches!(r, Ok(response) if response.status == 200)));
+        assert!(counters.peak.load(Ordering::SeqCst) <= 3);
+        
+        let metrics = stack.get_metrics().await;
+        assert_eq!(metrics.active_connections, 0);
+        assert!(metrics.peak_active_connections <= 3);
+        assert!(metrics.max_queue_wait >= Duration::from_millis(50));
+        
+        // A streamed body holds its slot until it is dropped
+        let response = stack.fetch_stream(&format!("http://{}/stream", addr), 0).await.unwrap();
+        assert_eq!(stack.get_metrics().await.active_connections, 1);
+        drop(response);
+        assert_eq!(stack.get_metrics().await.active_connections, 0);
+    }
+    
+    #[tokio::test]
+    async fn test_dns_over_https_resolution() {
+        use hyper::service::{make_service_fn, service_fn};
+        use std::convert::Infallible;
+        use std::str::FromStr;
+        
+        // One server answers DoH queries (always 127.0.0.1, TTL 1s) and serves pages
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|request: Request<Body>| async move {
+                if request.uri().path() != "/dns-query" {
+                    return Ok::<_, Infallible>(Response::new(Body::from("resolved via doh")));
+                }
+                let query = request.uri().query().unwrap_or_default();
+                let answer = if query.ends_with("type=A") {
+                    r#"[{"name": "titan-doh.test", "type": 1, "TTL": 1, "data": "127.0.0.1"}]"#
+                } else {
+                    "[]"
+                };
+                Ok(Response::new(Body::from(format!(r#"{{"Status": 0, "Answer": {}}}"#, answer))))
+            }))
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        
+        let endpoint = format!("http://{}/dns-query", addr);
+        let mut stack = NetworkStack::new().await.unwrap();
+        stack.update_security_config(SecurityConfig {
+            require_https: false,
+            ..SecurityConfig::default()
+        });
+        stack.update_network_config(NetworkConfig {
+            resolver: ResolverMode::DnsOverHttps { endpoint: endpoint.clone(), fallback_to_system: false },
+            ..NetworkConfig::default()
+        });
+        
+        // A name the system resolver can't know connects through the DoH answer
+        let response = stack.fetch(&format!("http://titan-doh.test:{}/page", addr.port())).await.unwrap();
+        assert_eq!(response.body, "resolved via doh");
+        assert_eq!(stack.doh.query_count(), 2); // A and AAAA
+        
+        // Cached until the TTL runs out
+        let addresses = stack.doh.resolve(&endpoint, "titan-doh.test").await.unwrap();
+        assert_eq!(addresses, vec![IpAddr::from([127, 0, 0, 1])]);
+        assert_eq!(stack.doh.query_count(), 2);
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        stack.doh.resolve(&endpoint, "titan-doh.test").await.unwrap();
+        assert_eq!(stack.doh.query_count(), 4);
+        
+        // An unreachable DoH server fails unless falling back to the system resolver is allowed
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let mut resolver = StackResolver {
+            mode: Arc::new(RwLock::new(ResolverMode::DnsOverHttps {
+                endpoint: format!("http://{}/dns-query", closed),
+                fallback_to_system: false,
+            })),
+            doh: Arc::new(DohResolver::new(stack.tls_config.as_ref().clone())),
+        };
+        let name = Name::from_str("localhost").unwrap();
+        assert!(hyper::service::Service::call(&mut resolver, name.clone()).await.is_err());
+        
+        *resolver.mode.write().unwrap() = ResolverMode::DnsOverHttps {
+            endpoint: format!("http://{}/dns-query", closed),
+            fallback_to_system: true,
+        };
+        let addresses: Vec<_> = hyper::service::Service::call(&mut resolver, name).await.unwrap().collect();
+        assert!(!addresses.is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_request_and_response_interceptors() {
+        use hyper::service::{make_service_fn, service_fn};
+        use std::convert::Infallible;
+        
+        // Echo the injected header; /tracked responses carry a tracking header
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|request: Request<Body>| async move {
+                let injected = request.headers().get("x-titan-extension")
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or("missing")
+                    .to_string();
+                let mut response = Response::new(Body::from(format!("{} {}", request.uri().path(), injected)));
+                if request.uri().path() == "/tracked" {
+                    response.headers_mut().insert("x-tracker", "1".parse().unwrap());
+                }
+                Ok::<_, Infallible>(response)
+            }))
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        
+        let mut stack = NetworkStack::new().await.unwrap();
+        stack.update_security_config(SecurityConfig {
+            require_https: false,
+            ..SecurityConfig::default()
+        });
+        
+        let later_calls = Arc::new(AtomicU64::new(0));
+        stack.add_request_interceptor(Box::new(|request| {
+            if request.url.contains("ads.example") {
+                InterceptAction::Block("ad server".to_string())
+            } else {
+                InterceptAction::Allow
+            }
+        }));
+        let server_url = format!("http://{}", addr);
+        stack.add_request_interceptor(Box::new(move |request| {
+            request.url = request.url.replace("http://rewrite.test", &server_url);
+            InterceptAction::Allow
+        }));
+        let calls = later_calls.clone();
+        stack.add_request_interceptor(Box::new(move |request| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            request.headers.insert("X-Titan-Extension".to_string(), "injected".to_string());
+            InterceptAction::Allow
+        }));
+        stack.add_response_interceptor(Box::new(|response| {
+            if response.headers.contains_key("x-tracker") {
+                InterceptAction::Block("tracking response".to_string())
+            } else {
+                InterceptAction::Allow
+            }
+        }));
+        
+        // Blocked before any later interceptor runs or a connection is made
+        let blocked = stack.fetch("http://ads.example/banner.js").await;
+        assert!(matches!(blocked, Err(EngineError::SecurityError(_))));
+        assert_eq!(later_calls.load(Ordering::SeqCst), 0);
+        
+        // Earlier rewrites are visible to later interceptors, and edits are sent
+        let response = stack.fetch("http://rewrite.test/page").await.unwrap();
+        assert_eq!(response.body, "/page injected");
+        assert_eq!(response.url, format!("http://{}/page", addr));
+        assert_eq!(later_calls.load(Ordering::SeqCst), 1);
+        
+        let options = RequestOptions::default();
+        let response = stack.fetch_with_options(&format!("http://{}/options", addr), options).await.unwrap();
+        assert_eq!(response.body, "/options injected");
+        
+        let tracked = stack.fetch(&format!("http://{}/tracked", addr)).await;
+        assert!(matches!(tracked, Err(EngineError::SecurityError(_))));
+    }
+    
+    #[tokio::test]
+    async fn test_record_then_replay_offline() {
+        use hyper::service::{make_service_fn, service_fn};
+        use std::convert::Infallible;
+        
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|_request: Request<Body>| async {
+                Ok::<_, Infallible>(Response::builder()
+                    .header("content-type", "text/html")
+                    .body(Body::from("<p>snapshot</p>"))
+                    .unwrap())
+            }))
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        let (shutdown, shutdown_signal) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async { shutdown_signal.await.ok(); }));
+        
+        let db_path = std::env::temp_dir().join(format!("titan-offline-{}.db", uuid::Uuid::new_v4()));
+        let storage = Arc::new(tokio::sync::Mutex::new(StorageEngine::open(&db_path).await.unwrap()));
+        let mut stack = NetworkStack::new().await.unwrap();
+        stack.update_security_config(SecurityConfig {
+            require_https: false,
+            ..SecurityConfig::default()
+        });
+        stack.set_persistent_cache(storage);
+        
+        // Record while the server is up
+        let url = format!("http://{}/page", addr);
+        stack.set_recording(true);
+        assert!(!stack.fetch(&url).await.unwrap().from_cache);
+        stack.set_recording(false);
+        shutdown.send(()).unwrap();
+        
+        // Replay with the server gone
+        stack.set_offline(true);
+        let replayed = stack.fetch(&url).await.unwrap();
+        assert!(replayed.from_cache);
+        assert_eq!(replayed.status, 200);
+        assert_eq!(replayed.body, "<p>snapshot</p>");
+        assert_eq!(replayed.headers.get("content-type").map(String::as_str), Some("text/html"));
+        
+        let _ = std::fs::remove_file(db_path);
+    }
+    
+    #[tokio::test]
+    async fn test_offline_miss_errors() {
+        let db_path = std::env::temp_dir().join(format!("titan-offline-{}.db", uuid::Uuid::new_v4()));
+        let mut stack = NetworkStack::new().await.unwrap();
+        stack.set_persistent_cache(Arc::new(tokio::sync::Mutex::new(StorageEngine::open(&db_path).await.unwrap())));
+        stack.set_offline(true);
+        
+        let error = stack.fetch("https://example.com/never-recorded").await.unwrap_err();
+        assert!(matches!(&error, EngineError::NetworkError { message, .. } if message.contains("not cached")));
+        assert_eq!(error.network_kind(), Some(NetworkErrorKind::Offline));
+        assert_eq!(error.url(), Some("https://example.com/never-recorded"));
+        
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn test_robots_txt_rules() {
+        let robots = RobotsTxt::parse("\
+            # Keep crawlers out of private areas\n\
+            User-agent: *\n\
+            Disallow: /private/\n\
+            Allow: /private/press/\n\
+            Disallow: /*.pdf$\n\
+            \n\
+            User-agent: BadBot\n\
+            User-agent: TitanBrowser\n\
+            Disallow: /search\n\
+            Disallow:\n");
+        
+        let crawler = "ExampleCrawler/2.0";
+        assert!(robots.is_allowed("/", crawler));
+        assert!(!robots.is_allowed("/private/notes.html", crawler));
+        assert!(robots.is_allowed("/private/press/launch.html", crawler));
+        assert!(!robots.is_allowed("/files/report.pdf", crawler));
+        assert!(robots.is_allowed("/files/report.pdf?download=1", crawler));
+        
+        // A named group replaces the wildcard group entirely
+        assert!(!robots.is_allowed("/search?q=rust", USER_AGENT));
+        assert!(robots.is_allowed("/private/notes.html", USER_AGENT));
+        
+        assert!(RobotsTxt::default().is_allowed("/anything", crawler));
+        assert!(!RobotsTxt::disallow_all().is_allowed("/", crawler));
+    }
+    
+    #[tokio::test]
+    async fn test_polite_fetch_honors_robots_and_rate_limit() {
+        use hyper::service::{make_service_fn, service_fn};
+        use std::convert::Infallible;
+        
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|request: Request<Body>| async move {
+                let body = if request.uri().path() == "/robots.txt" {
+                    "User-agent: *\nDisallow: /admin\n"
+                } else {
+                    "ok"
+                };
+                Ok::<_, Infallible>(Response::new(Body::from(body)))
+            }))
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        
+        let mut stack = NetworkStack::new().await.unwrap();
+        stack.update_security_config(SecurityConfig {
+            require_https: false,
+            ..SecurityConfig::default()
+        });
+        stack.update_network_config(NetworkConfig {
+            respect_robots_txt: true,
+            rate_limit: Some(RateLimit {
+                requests_per_second: 10.0,
+                burst: 1,
+            }),
+            ..NetworkConfig::default()
+        });
+        
+        let denied = stack.fetch(&format!("http://{}/admin/users", addr)).await;
+        assert!(matches!(denied, Err(EngineError::RobotsDisallowed(_))));
+        assert!(stack.is_fetch_allowed(&format!("http://{}/about", addr), USER_AGENT).await.unwrap());
+        
+        // One request per 100ms after the first, however many are issued at once
+        let started = Instant::now();
+        let requests = (0..5).map(|i| {
+            let url = format!("http://{}/page/{}", addr, i);
+            let stack = &stack;
+            async move { stack.fetch(&url).await }
+        });
+        let responses = futures::future::join_all(requests).await;
+        assert!(responses.iter().all(|r| matches!(r, Ok(response) if response.status == 200)));
+        assert!(started.elapsed() >= Duration::from_millis(350));
+    }
+
+    #[tokio::test]
+    async fn test_robots_txt_follows_redirects_and_retries_failures() {
+        use hyper::service::{make_service_fn, service_fn};
+        use std::convert::Infallible;
+        
+        let failures = Arc::new(AtomicU64::new(1));
+        let make_service = make_service_fn(move |_| {
+            let failures = failures.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let failures = failures.clone();
+                    async move {
+                        let response = match request.uri().path() {
+                            "/robots.txt" => Response::builder()
+                                .status(301)
+                                .header("Location", "/moved/robots.txt")
+                                .body(Body::empty()),
+                            "/moved/robots.txt" if failures.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)).is_ok() => {
+                                Response::builder().status(503).body(Body::empty())
+                            }
+                            "/moved/robots.txt" => Response::builder()
+                                .body(Body::from("User-agent: *\nDisallow: /admin\n")),
+                            _ => Response::builder().body(Body::from("ok")),
+                        };
+                        Ok::<_, Infallible>(response.unwrap())
+                    }
+                }))
+            }
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        
+        let stack = NetworkStack::new().await.unwrap();
+        let about = format!("http://{}/about", addr);
+        
+        // The redirect target is unavailable, so the origin is disallowed for a short while
+        assert!(!stack.is_fetch_allowed(&about, USER_AGENT).await.unwrap());
+        let origin = format!("http://{}", addr);
+        let expires = stack.robots.read().await[&origin].1;
+        assert!(expires <= Instant::now() + ROBOTS_FAILURE_TTL);
+        
+        // Once the failure expires robots.txt is fetched again through the redirect
+        stack.robots.write().await.get_mut(&origin).unwrap().1 = Instant::now();
+        assert!(stack.is_fetch_allowed(&about, USER_AGENT).await.unwrap());
+        assert!(!stack.is_fetch_allowed(&format!("http://{}/admin", addr), USER_AGENT).await.unwrap());
+    }
+
+    #[test]
+    fn test_parse_set_cookie_attributes() {
+        let now = 1_700_000_000;
+        let cookie = parse_set_cookie(
+            "id=a3fWa; Domain=.Example.com; Path=/app; Max-Age=3600; Expires=Wed, 21 Oct 2015 07:28:00 GMT; Secure; HttpOnly; SameSite=Strict",
+            "www.example.com",
+            "/login",
+            now,
+        ).unwrap();
+        assert_eq!(cookie.domain, ".example.com");
+        assert_eq!(cookie.path, "/app");
+        assert_eq!(cookie.expires, Some(now + 3600));
+        assert!(cookie.secure && cookie.http_only);
+        assert_eq!(cookie.same_site, SameSite::Strict);
+        
+        let cookie = parse_set_cookie("lang=en; Expires=Wed, 21-Oct-2015 07:28:00 GMT", "example.com", "/docs/intro", now).unwrap();
+        assert_eq!(cookie.domain, "example.com");
+        assert_eq!(cookie.path, "/docs");
+        assert_eq!(cookie.expires, Some(1_445_412_480));
+        
+        // A site can't set cookies for a domain it isn't part of
+        assert!(parse_set_cookie("id=1; Domain=other.com", "example.com", "/", now).is_none());
+        
+        // Nor for a public suffix, unless it is that host, and then only host-only
+        assert!(parse_set_cookie("id=1; Domain=com", "example.com", "/", now).is_none());
+        assert!(parse_set_cookie("id=1; Domain=.co.uk", "shop.example.co.uk", "/", now).is_none());
+        let cookie = parse_set_cookie("id=1; Domain=example.co.uk", "shop.example.co.uk", "/", now).unwrap();
+        assert_eq!(cookie.domain, ".example.co.uk");
+        let cookie = parse_set_cookie("id=1; Domain=github.io", "github.io", "/", now).unwrap();
+        assert_eq!(cookie.domain, "github.io");
+        let cookie = parse_set_cookie("id=1; Domain=127.0.0.1", "127.0.0.1", "/", now).unwrap();
+        assert_eq!(cookie.domain, "127.0.0.1");
+        
+        // SameSite=None is only accepted on Secure cookies
+        assert!(parse_set_cookie("id=1; SameSite=None", "example.com", "/", now).is_none());
+        let cookie = parse_set_cookie("id=1; SameSite=none; Secure", "example.com", "/", now).unwrap();
+        assert_eq!(cookie.same_site, SameSite::None);
+    }
+    
+    #[test]
+    fn test_is_same_site() {
+        assert!(is_same_site("https://www.example.com/page", "api.example.com"));
+        assert!(is_same_site("https://example.com/", "EXAMPLE.com"));
+        assert!(!is_same_site("https://example.com/", "example.org"));
+        assert!(!is_same_site("http://127.0.0.1:8080/", "127.0.0.2"));
+        
+        // Sites under multi-label public suffixes stay apart
+        assert!(!is_same_site("https://a.co.uk/", "b.co.uk"));
+        assert!(is_same_site("https://www.shop.co.uk/", "cdn.shop.co.uk"));
+        assert!(!is_same_site("https://alice.github.io/", "bob.github.io"));
+    }
+    
+    #[tokio::test]
+    async fn test_cookies_round_trip_through_fetch() {
+        use hyper::service::{make_service_fn, service_fn};
+        use std::convert::Infallible;
+        
+        // /login sets cookies; every other path echoes the Cookie header it received
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|request: Request<Body>| async move {
+                let response = if request.uri().path() == "/login" {
+                    Response::builder()
+                        .header("Set-Cookie", "session=abc123; Path=/; HttpOnly")
+                        .header("Set-Cookie", "admin=1; Path=/admin")
+                        .header("Set-Cookie", "stale=x; Max-Age=0")
+                        .body(Body::from("welcome"))
+                        .unwrap()
+                } else {
+                    let cookie = request.headers().get("cookie")
+                        .map(|value| value.to_str().unwrap().to_string())
+                        .unwrap_or_default();
+                    Response::new(Body::from(cookie))
+                };
+                Ok::<_, Infallible>(response)
+            }))
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        
+        let mut stack = NetworkStack::new().await.unwrap();
+        stack.update_security_config(SecurityConfig {
+            require_https: false,
+            ..SecurityConfig::default()
+        });
+        let jar = Arc::new(tokio::sync::Mutex::new(StorageEngine::open(":memory:").await.unwrap()));
+        stack.set_cookie_store(jar.clone());
+        
+        stack.fetch(&format!("http://{}/login", addr)).await.unwrap();
+        assert_eq!(jar.lock().await.get_cookies("127.0.0.1").await.unwrap().len(), 2);
+        
+        let account = stack.fetch(&format!("http://{}/account", addr)).await.unwrap();
+        assert_eq!(account.body, "session=abc123");
+        
+        let admin = stack.fetch(&format!("http://{}/admin/users", addr)).await.unwrap();
+        assert_eq!(admin.body, "admin=1; session=abc123");
+    }
+
+    #[tokio::test]
+    async fn test_configured_user_agent_is_sent() {
+        use hyper::service::{make_service_fn, service_fn};
+        use std::convert::Infallible;
+        
+        // Echo the identifying headers back in the body
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|request: Request<Body>| async move {
+                let header = |name: &str| request.headers().get(name)
+                    .map(|value| value.to_str().unwrap().to_string())
+                    .unwrap_or_default();
+                let body = format!("{} | {}", header("user-agent"), header("accept-language"));
+                Ok::<_, Infallible>(Response::new(Body::from(body)))
+            }))
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        
+        let mut stack = NetworkStack::new().await.unwrap();
+        stack.update_security_config(SecurityConfig {
+            require_https: false,
+            ..SecurityConfig::default()
+        });
+        stack.update_network_config(NetworkConfig {
+            user_agent: "TitanTest/2.0".to_string(),
+            accept_language: "fr-CA,fr;q=0.8".to_string(),
+            ..NetworkConfig::default()
+        });
+        
+        let response = stack.fetch(&format!("http://{}/page", addr)).await.unwrap();
+        assert_eq!(response.body, "TitanTest/2.0 | fr-CA,fr;q=0.8");
+        
+        let options = RequestOptions::default();
+        let response = stack.fetch_with_options(&format!("http://{}/default", addr), options).await.unwrap();
+        assert_eq!(response.body, "TitanTest/2.0 | fr-CA,fr;q=0.8");
+        
+        // A per-request override wins over the configured agent
+        let options = RequestOptions {
+            user_agent: Some("Override/1.0".to_string()),
+            ..RequestOptions::default()
+        };
+        let response = stack.fetch_with_options(&format!("http://{}/override", addr), options).await.unwrap();
+        assert_eq!(response.body, "Override/1.0 | fr-CA,fr;q=0.8");
+    }
+
+    #[tokio::test]
+    async fn test_request_failures_are_classified() {
+        use hyper::service::{make_service_fn, service_fn};
+        use std::convert::Infallible;
+        
+        // Plain HTTP server that answers slowly on /slow and 404s on /missing
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|request: Request<Body>| async move {
+                let response = match request.uri().path() {
+                    "/slow" => {
+                        tokio::time::sleep(Duration::from_secs(2)).await;
+                        Response::new(Body::from("late"))
+                    }
+                    "/missing" => Response::builder().status(404).body(Body::empty()).unwrap(),
+                    _ => Response::new(Body::from("ok")),
+                };
+                Ok::<_, Infallible>(response)
+            }))
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        
+        // A port that was just released refuses connections
+        let closed_port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        
+        let mut stack = NetworkStack::new().await.unwrap();
+        stack.update_security_config(SecurityConfig {
+            require_https: false,
+            ..SecurityConfig::default()
+        });
+        
+        let error = stack.fetch("http://titan-test.invalid/").await.unwrap_err();
+        assert_eq!(error.network_kind(), Some(NetworkErrorKind::Dns));
+        assert_eq!(error.url(), Some("http://titan-test.invalid/"));
+        assert!(error.recovery_hint().is_some());
+        
+        let url = format!("http://127.0.0.1:{}/", closed_port);
+        let error = stack.fetch(&url).await.unwrap_err();
+        assert_eq!(error.network_kind(), Some(NetworkErrorKind::Connect));
+        assert_eq!(error.url(), Some(url.as_str()));
+        
+        // TLS to a server that only speaks plain HTTP fails the handshake
+        let error = stack.fetch(&format!("https://{}/", addr)).await.unwrap_err();
+        assert_eq!(error.network_kind(), Some(NetworkErrorKind::Tls));
+        
+        let options = RequestOptions {
+            timeout: Duration::from_millis(200),
+            ..RequestOptions::default()
+        };
+        let error = stack.fetch_with_options(&format!("http://{}/slow", addr), options).await.unwrap_err();
+        assert_eq!(error.network_kind(), Some(NetworkErrorKind::Timeout));
+        
+        let url = format!("http://{}/missing", addr);
+        let error = stack.fetch_stream(&url, 0).await.unwrap_err();
+        assert_eq!(error.network_kind(), Some(NetworkErrorKind::Status(404)));
+        assert_eq!(error.url(), Some(url.as_str()));
+        assert_eq!(error.recovery_hint(), Some("The page may have moved or been removed"));
+        
+        let error = stack.fetch("not a url").await.unwrap_err();
+        assert_eq!(error.network_kind(), Some(NetworkErrorKind::InvalidUrl));
+        
+        // The display string is still the plain message
+        assert!(error.to_string().starts_with("Network error: Invalid URL"));
+    }
+
+    #[tokio::test]
+    async fn test_timeouts_report_their_phase() {
+        use hyper::service::{make_service_fn, service_fn};
+        use std::convert::Infallible;
+        
+        // Accepts connections but never answers, so a TLS handshake stalls
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stalled_addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
+            }
+        });
+        
+        // Sends headers and one chunk, then goes quiet
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|_request: Request<Body>| async move {
+                let (mut sender, body) = Body::channel();
+                tokio::spawn(async move {
+                    let _ = sender.send_data("partial".into()).await;
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    drop(sender);
+                });
+                Ok::<_, Infallible>(Response::new(body))
+            }))
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        
+        let mut stack = NetworkStack::new().await.unwrap();
+        stack.update_security_config(SecurityConfig {
+            require_https: false,
+            timeout: Duration::from_secs(5),
+            ..SecurityConfig::default()
+        });
+        stack.update_network_config(NetworkConfig {
+            connect_timeout: Some(Duration::from_millis(200)),
+            read_timeout: Some(Duration::from_millis(200)),
+            ..NetworkConfig::default()
+        });
+        
+        // The connect timeout fires well before the overall timeout
+        let started = Instant::now();
+        let error = stack.fetch(&format!("https://{}/", stalled_addr)).await.unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(error.network_kind(), Some(NetworkErrorKind::Timeout));
+        assert!(error.to_string().contains("Connect timeout"), "{}", error);
+        
+        let started = Instant::now();
+        let error = stack.fetch(&format!("http://{}/", addr)).await.unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(error.network_kind(), Some(NetworkErrorKind::Timeout));
+        assert!(error.to_string().contains("Read timeout"), "{}", error);
+        
+        // Without a read timeout the overall timeout still caps the body
+        stack.update_security_config(SecurityConfig {
+            require_https: false,
+            timeout: Duration::from_millis(300),
+            ..SecurityConfig::default()
+        });
+        stack.update_network_config(NetworkConfig {
+            read_timeout: None,
+            ..NetworkConfig::default()
+        });
+        let error = stack.fetch(&format!("http://{}/", addr)).await.unwrap_err();
+        assert_eq!(error.network_kind(), Some(NetworkErrorKind::Timeout));
+        assert!(error.to_string().contains("Request timeout"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_hsts_upgrades_known_hosts() {
+        use hyper::service::{make_service_fn, service_fn};
+        use std::convert::Infallible;
+        
+        assert_eq!(parse_strict_transport_security("max-age=31536000; includeSubDomains"), Some(HstsPolicy {
+            max_age: 31536000,
+            include_subdomains: true,
+        }));
+        assert_eq!(parse_strict_transport_security("max-age=\"60\""), Some(HstsPolicy { max_age: 60, include_subdomains: false }));
+        assert_eq!(parse_strict_transport_security("includeSubDomains"), None);
+        
+        // Plain HTTP server, so an upgraded request fails the TLS handshake
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|_request: Request<Body>| async {
+                Ok::<_, Infallible>(Response::new(Body::from("plain")))
+            }))
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let port = server.local_addr().port();
+        tokio::spawn(server);
+        
+        let store = Arc::new(tokio::sync::Mutex::new(StorageEngine::open(":memory:").await.unwrap()));
+        let mut stack = NetworkStack::new().await.unwrap();
+        stack.update_security_config(SecurityConfig {
+            require_https: false,
+            ..SecurityConfig::default()
+        });
+        stack.set_hsts_store(store.clone());
+        let url = format!("http://localhost:{}/page", port);
+        assert_eq!(stack.fetch(&url).await.unwrap().body, "plain");
+        
+        // The header only counts when it arrives over https
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert("strict-transport-security", "max-age=600; includeSubDomains".parse().unwrap());
+        stack.store_hsts(&format!("http://localhost:{}/", port).parse().unwrap(), &headers).await;
+        assert!(!store.lock().await.is_hsts_host("localhost").await.unwrap());
+        
+        stack.store_hsts(&"https://localhost/".parse().unwrap(), &headers).await;
+        assert!(store.lock().await.is_hsts_host("api.localhost").await.unwrap());
+        assert_eq!(
+            stack.upgrade_to_https("http://localhost:80/a?b=c").await.unwrap().as_deref(),
+            Some("https://localhost/a?b=c"),
+        );
+        let error = stack.fetch(&url).await.unwrap_err();
+        assert_eq!(error.network_kind(), Some(NetworkErrorKind::Tls));
+        assert_eq!(error.url(), Some(format!("https://localhost:{}/page", port).as_str()));
+        
+        // An expired entry no longer upgrades
+        store.lock().await.set_hsts("localhost", chrono::Utc::now().timestamp() - 1, true).await.unwrap();
+        assert_eq!(stack.upgrade_to_https(&url).await.unwrap(), None);
+        assert_eq!(stack.fetch(&format!("http://localhost:{}/other", port)).await.unwrap().body, "plain");
+        
+        // max-age=0 removes the entry
+        store.lock().await.set_hsts("localhost", chrono::Utc::now().timestamp() + 600, false).await.unwrap();
+        headers.insert("strict-transport-security", "max-age=0".parse().unwrap());
+        stack.store_hsts(&"https://localhost/".parse().unwrap(), &headers).await;
+        assert!(!store.lock().await.is_hsts_host("localhost").await.unwrap());
+    }
+
+    /// DER element with a short-form length
+    fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut element = vec![tag, contents.len() as u8];
+        element.extend_from_slice(contents);
+        element
+    }
+    
+    #[test]
+    fn test_certificate_pins_reject_mismatched_chain() {
+        // Skeleton certificate: only the structure leading up to the public key is real
+        let spki = der(0x30, &[der(0x30, &der(0x06, &[0x2A, 0x03])), der(0x03, &[0x00, 0x04, 0x01, 0x02])].concat());
+        let tbs = der(0x30, &[
+            der(0xA0, &der(0x02, &[0x02])),
+            der(0x02, &[0x01]),
+            der(0x30, &[]),
+            der(0x30, &[]),
+            der(0x30, &[]),
+            der(0x30, &[]),
+            spki.clone(),
+        ].concat());
+        let certificate = der(0x30, &[tbs, der(0x30, &[]), der(0x03, &[0x00])].concat());
+        assert_eq!(subject_public_key_info(&certificate), Some(spki.as_slice()));
+        
+        let sha256 = |data: &[u8]| -> [u8; 32] {
+            ring::digest::digest(&ring::digest::SHA256, data).as_ref().try_into().unwrap()
+        };
+        let key_pin = CertificatePin::PublicKeySha256(sha256(&spki));
+        assert!(key_pin.matches(&certificate));
+        assert!(CertificatePin::CertificateSha256(sha256(&certificate)).matches(&certificate));
+        assert!(!CertificatePin::PublicKeySha256([0; 32]).matches(&certificate));
+        
+        let pins = Arc::new(RwLock::new(HashMap::new()));
+        let verifier = PinningVerifier {
+            roots: WebPkiVerifier::new(RootCertStore::empty(), None),
+            pins: pins.clone(),
+        };
+        let verify = |host: &str| verifier.verify_server_cert(
+            &rustls::Certificate(certificate.clone()),
+            &[],
+            &rustls::ServerName::try_from(host).unwrap(),
+            &mut std::iter::empty(),
+            &[],
+            std::time::SystemTime::now(),
+        );
+        
+        // A wrong pin rejects the chain before it is validated
+        pins.write().unwrap().insert("pinned.example".to_string(), vec![CertificatePin::PublicKeySha256([7; 32])]);
+        let error = verify("pinned.example").unwrap_err();
+        let wrapped = std::io::Error::new(std::io::ErrorKind::InvalidData, error);
+        assert_eq!(pin_mismatch_host(&wrapped), Some("pinned.example"));
+        
+        // A matching pin, or no pin at all, leaves the decision to web PKI validation
+        pins.write().unwrap().insert("pinned.example".to_string(), vec![key_pin]);
+        for host in ["pinned.example", "unpinned.example"] {
+            let error = verify(host).unwrap_err();
+            let wrapped = std::io::Error::new(std::io::ErrorKind::InvalidData, error);
+            assert_eq!(pin_mismatch_host(&wrapped), None);
+        }
+        
+        // The pinned certificate appended to a chain it doesn't issue is not a match
+        let other_spki = der(0x30, &[der(0x30, &der(0x06, &[0x2A, 0x03])), der(0x03, &[0x00, 0x04, 0x09, 0x09])].concat());
+        let other_tbs = der(0x30, &[
+            der(0xA0, &der(0x02, &[0x02])),
+            der(0x02, &[0x02]),
+            der(0x30, &[]),
+            der(0x30, &[]),
+            der(0x30, &[]),
+            der(0x30, &[]),
+            other_spki,
+        ].concat());
+        let unrelated_leaf = der(0x30, &[other_tbs, der(0x30, &[]), der(0x03, &[0x00])].concat());
+        let error = verifier.verify_server_cert(
+            &rustls::Certificate(unrelated_leaf),
+            &[rustls::Certificate(certificate.clone())],
+            &rustls::ServerName::try_from("pinned.example").unwrap(),
+            &mut std::iter::empty(),
+            &[],
+            std::time::SystemTime::now(),
+        ).unwrap_err();
+        let wrapped = std::io::Error::new(std::io::ErrorKind::InvalidData, error);
+        assert_eq!(pin_mismatch_host(&wrapped), Some("pinned.example"));
+        
+        let error = EngineError::CertificatePinMismatch {
+            host: "pinned.example".to_string(),
+            url: "https://pinned.example/".to_string(),
+        };
+        assert_eq!(error.url(), Some("https://pinned.example/"));
+        assert!(error.recovery_hint().is_some());
+    }
+    
+    #[tokio::test]
+    async fn test_pin_certificate_normalizes_host() {
+        let stack = NetworkStack::new().await.unwrap();
+        stack.pin_certificate("Pinned.Example.", CertificatePin::CertificateSha256([1; 32]));
+        assert_eq!(
+            stack.certificate_pins.read().unwrap().get("pinned.example"),
+            Some(&vec![CertificatePin::CertificateSha256([1; 32])]),
+        );
+        
+        stack.clear_certificate_pins("pinned.example");
+        assert!(stack.certificate_pins.read().unwrap().is_empty());
+    }
+}
@@ -1,19 +1,27 @@
 //! Rendering engine using WebRender for GPU-accelerated display
 
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use gleam::gl;
+use tokio::sync::oneshot;
 use webrender::api::*;
-use webrender::{Renderer, RendererOptions, ShaderPrecacheFlags};
+use webrender::{Renderer, ShaderPrecacheFlags, WebRenderOptions};
 use winit::window::Window;
-use crate::core::{ElementId, Result, EngineError, Color, Rect};
+use crate::core::{ElementId, Result, EngineError, Color, Rect, Size, MetricsReporter, PerformanceMetrics};
 use crate::layout::{LayoutTree, LayoutBox};
-use crate::css::ComputedStyle;
+use crate::css::{ComputedStyle, DisplayType, TransformFunction};
 
 /// GPU-accelerated rendering engine
 pub struct RenderingEngine {
     /// WebRender API instance
     api: RenderApi,
     
+    /// Thread that owns the GL context and the WebRender renderer
+    render_thread: RenderThread,
+    
     /// Document ID for WebRender
     document_id: DocumentId,
     
@@ -26,35 +34,39 @@ pub struct RenderingEngine {
     /// Render tree cache
     render_tree_cache: HashMap<ElementId, RenderNode>,
     
+    /// Bounds of each node as of the last submitted frame, to find the area a change damages
+    painted_bounds: HashMap<ElementId, Rect>,
+    
+    /// Whether a frame has been submitted yet; until then everything is damaged
+    has_painted: bool,
+    
     /// Font keys
     font_keys: HashMap<String, FontKey>,
     
-    /// Image keys
+    /// Image keys and decoded sizes, keyed by source URL
     image_keys: HashMap<String, ImageKey>,
+    image_sizes: HashMap<String, Size>,
+    
+    /// Rendering performance metrics
+    metrics: RenderingMetrics,
 }
 
 impl RenderingEngine {
-    /// Create a new rendering engine
+    /// Create a new rendering engine drawing to a 1920x1080 offscreen surface,
+    /// on the GPU if one is available and in software otherwise
     pub async fn new() -> Result<Self> {
-        // This is a simplified setup - in reality we'd need proper window/surface setup
-        let device_size = DeviceIntSize::new(1920, 1080);
-        
-        // Create WebRender instance (simplified)
-        let opts = RendererOptions {
-            device_pixel_ratio: 1.0,
-            ..RendererOptions::default()
-        };
-        
-        // In a real implementation, we'd need proper GL context setup
-        // For now, we'll create a mock setup
-        let (mut renderer, sender) = Renderer::new(
-            // This would need proper GL context
-            // For compilation, we'll use a placeholder
-            unsafe { std::mem::zeroed() }, // This is not safe - just for compilation
-            opts,
-            None,
-            device_size,
-        ).map_err(|e| EngineError::RenderingError(format!("Failed to create renderer: {:?}", e)))?;
+        Self::with_backend(GlBackend::Auto, 1920, 1080).await
+    }
+    
+    /// Create a rendering engine that renders in software, for tests and CI machines without a GPU
+    pub async fn headless(width: u32, height: u32) -> Result<Self> {
+        Self::with_backend(GlBackend::Software, width, height).await
+    }
+    
+    /// Create a rendering engine on a specific GL backend
+    pub async fn with_backend(backend: GlBackend, width: u32, height: u32) -> Result<Self> {
+        let device_size = DeviceIntSize::new(width as i32, height as i32);
+        let (render_thread, sender) = RenderThread::spawn(backend, device_size).await?;
         
         let api = sender.create_api();
         let document_id = api.add_document(device_size);
@@ -62,36 +74,95 @@ impl RenderingEngine {
         
         Ok(Self {
             api,
+            render_thread,
             document_id,
             pipeline_id,
             epoch: Epoch(0),
             render_tree_cache: HashMap::new(),
+            painted_bounds: HashMap::new(),
+            has_painted: false,
             font_keys: HashMap::new(),
             image_keys: HashMap::new(),
+            image_sizes: HashMap::new(),
+            metrics: RenderingMetrics::default(),
         })
     }
     
-    /// Create render tree from layout tree
+    /// Create render tree from layout tree, painting every node with default styles
     pub async fn create_render_tree(&mut self, layout_tree: &LayoutTree) -> Result<RenderTree> {
+        self.create_render_tree_with_styles(layout_tree, &HashMap::new()).await
+    }
+    
+    /// Create render tree from layout tree using each element's computed style
+    pub async fn create_render_tree_with_styles(
+        &mut self,
+        layout_tree: &LayoutTree,
+        styles: &HashMap<ElementId, ComputedStyle>,
+    ) -> Result<RenderTree> {
         let mut render_nodes = HashMap::new();
+        let default_style = ComputedStyle::default();
+        let style_of = |element_id: &ElementId| styles.get(element_id).unwrap_or(&default_style);
+        
+        // Scrollable overflow of each scroll container: the extent of everything inside it
+        let mut scroll_content: HashMap<ElementId, Rect> = HashMap::new();
+        for (element_id, layout_box) in layout_tree.get_all_layout_boxes() {
+            for ancestor in layout_tree.ancestors(*element_id).filter(|id| style_of(id).overflow.scrolls()) {
+                scroll_content.entry(ancestor)
+                    .and_modify(|content| *content = union_rect(content, &layout_box.border_rect))
+                    .or_insert(layout_box.border_rect);
+            }
+        }
         
         // Process all layout boxes
         for (element_id, layout_box) in layout_tree.get_all_layout_boxes() {
-            let render_node = self.create_render_node(*element_id, layout_box).await?;
+            let computed_style = style_of(element_id);
+            if computed_style.display == DisplayType::None {
+                continue;
+            }
+            
+            // Content is clipped to the padding box of every ancestor that doesn't let it overflow
+            let mut clip_rect: Option<Rect> = None;
+            for ancestor in layout_tree.ancestors(*element_id).filter(|id| style_of(id).overflow.clips()) {
+                let Some(ancestor_box) = layout_tree.get_layout_box(ancestor) else { continue };
+                let padding_rect = ancestor_box.padding_rect;
+                clip_rect = Some(match clip_rect {
+                    Some(clip) => intersect_rect(&clip, &padding_rect)
+                        .unwrap_or(Rect::new(padding_rect.origin.x, padding_rect.origin.y, 0.0, 0.0)),
+                    None => padding_rect,
+                });
+            }
+            let overflow = OverflowClip {
+                clip_rect,
+                scroll_parent: layout_tree.ancestors(*element_id).find(|id| style_of(id).overflow.scrolls()),
+                scroll_frame: computed_style.overflow.scrolls().then(|| ScrollFrame {
+                    frame_rect: layout_box.padding_rect,
+                    content_rect: scroll_content.get(element_id)
+                        .map_or(layout_box.padding_rect, |content| union_rect(content, &layout_box.padding_rect)),
+                }),
+            };
+            
+            let parent = layout_tree.ancestors(*element_id).find(|id| style_of(id).display != DisplayType::None);
+            let render_node = self.create_render_node(*element_id, parent, layout_box, computed_style, overflow).await?;
             render_nodes.insert(*element_id, render_node);
         }
         
         Ok(RenderTree {
             root_element_id: layout_tree.root_element_id,
+            dirty_nodes: render_nodes.keys().copied().collect(),
             render_nodes,
+            frames: HashMap::new(),
         })
     }
     
     /// Create a render node for an element
-    async fn create_render_node(&mut self, element_id: ElementId, layout_box: &LayoutBox) -> Result<RenderNode> {
-        // Get computed style (this would come from CSS engine)
-        let computed_style = ComputedStyle::default(); // Placeholder
-        
+    async fn create_render_node(
+        &mut self,
+        element_id: ElementId,
+        parent: Option<ElementId>,
+        layout_box: &LayoutBox,
+        computed_style: &ComputedStyle,
+        overflow: OverflowClip,
+    ) -> Result<RenderNode> {
         let mut display_items = Vec::new();
         
         // Background
@@ -122,10 +193,18 @@ impl RenderingEngine {
         
         let render_node = RenderNode {
             element_id,
+            parent,
             display_items,
-            transform: Transform::identity(),
-            opacity: 1.0,
-            clip_rect: None,
+            // CSS transforms apply around the center of the border box
+            transform: Transform::from_functions(
+                &computed_style.transform,
+                layout_box.border_rect.origin.x + layout_box.border_rect.size.width / 2.0,
+                layout_box.border_rect.origin.y + layout_box.border_rect.size.height / 2.0,
+            ),
+            opacity: computed_style.opacity,
+            clip_rect: overflow.clip_rect,
+            scroll_frame: overflow.scroll_frame,
+            scroll_parent: overflow.scroll_parent,
         };
         
         self.render_tree_cache.insert(element_id, render_node.clone());
@@ -133,16 +212,48 @@ impl RenderingEngine {
         Ok(render_node)
     }
     
-    /// Render the current frame
-    pub async fn render_frame(&mut self, render_tree: &RenderTree) -> Result<()> {
-        // Build display list
-        let mut builder = DisplayListBuilder::new(self.pipeline_id);
+    /// Render the current frame, consuming the tree's dirty nodes.
+    /// Frames with no damage are skipped; otherwise the display list is resubmitted and
+    /// WebRender's picture caching re-rasterizes only the tiles that changed.
+    pub async fn render_frame(&mut self, render_tree: &mut RenderTree) -> Result<()> {
+        let start_time = Instant::now();
+        
+        let dirty_nodes = render_tree.take_dirty_nodes();
+        let removed: Vec<ElementId> = self.painted_bounds.keys()
+            .filter(|id| !render_tree.render_nodes.contains_key(id))
+            .copied()
+            .collect();
+        if self.has_painted && dirty_nodes.is_empty() && removed.is_empty() {
+            self.metrics.frames_skipped += 1;
+            self.metrics.dirty_nodes = 0;
+            self.metrics.repainted_area = 0.0;
+            return Ok(());
+        }
         
-        // Add items from render tree
-        for render_node in render_tree.render_nodes.values() {
-            self.add_render_node_to_display_list(&mut builder, render_node);
+        // Damage covers where each changed node was and where it is now
+        let mut repainted_area = 0.0;
+        for element_id in dirty_nodes.iter().chain(&removed) {
+            let old = self.painted_bounds.remove(element_id);
+            let new = render_tree.node_bounds(*element_id);
+            let damage = match (old, new) {
+                (Some(old), Some(new)) => Some(union_rect(&old, &new)),
+                (old, new) => old.or(new),
+            };
+            repainted_area += damage.map_or(0.0, |rect| (rect.size.width * rect.size.height) as f64);
+            if let Some(new) = new {
+                self.painted_bounds.insert(*element_id, new);
+            }
+        }
+        if !self.has_painted {
+            self.painted_bounds = render_tree.render_nodes.keys()
+                .filter_map(|id| render_tree.node_bounds(*id).map(|bounds| (*id, bounds)))
+                .collect();
         }
         
+        // Build display list
+        let mut builder = DisplayListBuilder::new(self.pipeline_id);
+        Self::build_display_list(&mut builder, self.pipeline_id, &render_tree.render_nodes);
+        
         let display_list = builder.end();
         
         // Send to WebRender
@@ -155,14 +266,95 @@ impl RenderingEngine {
             true,
         );
         
+        txn.set_root_pipeline(self.pipeline_id);
+        txn.generate_frame(0, RenderReasons::SCENE);
         self.api.send_transaction(self.document_id, txn);
         self.epoch.0 += 1;
         
+        // Wait for the render thread to draw the frame into the offscreen surface
+        self.render_thread.render().await?;
+        
+        // GPU time is not observable here; frame time covers building and drawing the frame
+        let frame_time_ms = start_time.elapsed().as_secs_f64() * 1000.0;
+        self.metrics.frame_time_ms = frame_time_ms;
+        self.metrics.cpu_time_ms = frame_time_ms;
+        self.metrics.draw_calls = render_tree.render_nodes.values()
+            .map(|node| node.display_items.len() as u32)
+            .sum();
+        self.metrics.frames_rendered += 1;
+        self.metrics.dirty_nodes = (dirty_nodes.len() + removed.len()) as u32;
+        self.metrics.repainted_area = repainted_area;
+        self.has_painted = true;
+        
         Ok(())
     }
     
-    /// Add render node items to display list
-    fn add_render_node_to_display_list(&self, builder: &mut DisplayListBuilder, render_node: &RenderNode) {
+    /// Add every node's items to the display list, starting from the nodes with no rendered parent
+    fn build_display_list(builder: &mut DisplayListBuilder, pipeline_id: PipelineId, render_nodes: &HashMap<ElementId, RenderNode>) {
+        let mut children: HashMap<ElementId, Vec<&RenderNode>> = HashMap::new();
+        let mut roots = Vec::new();
+        for render_node in render_nodes.values() {
+            match render_node.parent.filter(|parent| render_nodes.contains_key(parent)) {
+                Some(parent) => children.entry(parent).or_default().push(render_node),
+                None => roots.push(render_node),
+            }
+        }
+        
+        let root_space = SpaceAndClipInfo::root_scroll(pipeline_id).spatial_id;
+        for render_node in roots {
+            Self::add_render_node_to_display_list(builder, pipeline_id, &children, render_node, root_space, root_space);
+        }
+    }
+    
+    /// Add a render node and its descendants to the display list.
+    /// A non-identity transform gets its own reference frame and opacity below 1.0 an
+    /// opacity stacking context, both wrapped around the whole subtree so WebRender
+    /// transforms and composites it as a group. A scroll container's descendants are placed
+    /// in a scroll frame, and `clip_rect` is defined in `clip_space`, which doesn't scroll.
+    fn add_render_node_to_display_list(
+        builder: &mut DisplayListBuilder,
+        pipeline_id: PipelineId,
+        children: &HashMap<ElementId, Vec<&RenderNode>>,
+        render_node: &RenderNode,
+        parent_space: SpatialId,
+        clip_space: SpatialId,
+    ) {
+        let mut space_and_clip = SpaceAndClipInfo::root_scroll(pipeline_id);
+        space_and_clip.spatial_id = parent_space;
+        
+        if let Some(clip_rect) = &render_node.clip_rect {
+            let clip_id = builder.define_clip_rect(clip_space, to_layout_rect(clip_rect));
+            space_and_clip.clip_chain_id = builder.define_clip_chain(None, [clip_id]);
+        }
+        
+        let transformed = !render_node.transform.is_identity();
+        if transformed {
+            space_and_clip.spatial_id = builder.push_reference_frame(
+                LayoutPoint::zero(),
+                space_and_clip.spatial_id,
+                TransformStyle::Flat,
+                PropertyBinding::Value(render_node.transform.to_layout_transform()),
+                ReferenceFrameKind::Transform {
+                    is_2d_scale_translation: false,
+                    should_snap: false,
+                    paired_with_perspective: false,
+                },
+                SpatialTreeItemKey::new(render_node.element_id.0.as_u128() as u64, 0),
+            );
+        }
+        
+        let faded = render_node.opacity < 1.0;
+        if faded {
+            builder.push_simple_stacking_context_with_filters(
+                LayoutPoint::zero(),
+                space_and_clip.spatial_id,
+                PrimitiveFlags::IS_BACKFACE_VISIBLE,
+                &[FilterOp::Opacity(PropertyBinding::Value(render_node.opacity), render_node.opacity)],
+                &[],
+                &[],
+            );
+        }
+        
         for display_item in &render_node.display_items {
             match display_item {
                 DisplayItem::Rectangle { rect, color } => {
@@ -174,7 +366,7 @@ impl RenderingEngine {
                     builder.push_rect(
                         &CommonItemProperties::new(
                             layout_rect,
-                            SpaceAndClipInfo::root_scroll(self.pipeline_id),
+                            space_and_clip,
                         ),
                         layout_rect,
                         ColorF::new(color.r, color.g, color.b, color.a),
@@ -217,7 +409,7 @@ impl RenderingEngine {
                     builder.push_border(
                         &CommonItemProperties::new(
                             layout_rect,
-                            SpaceAndClipInfo::root_scroll(self.pipeline_id),
+                            space_and_clip,
                         ),
                         layout_rect,
                         border_widths,
@@ -244,7 +436,7 @@ impl RenderingEngine {
                     builder.push_image(
                         &CommonItemProperties::new(
                             layout_rect,
-                            SpaceAndClipInfo::root_scroll(self.pipeline_id),
+                            space_and_clip,
                         ),
                         layout_rect,
                         ImageRendering::Auto,
@@ -255,6 +447,45 @@ impl RenderingEngine {
                 }
             }
         }
+        
+        // Content scrolls within the container's frame, under a clip that stays put
+        let (child_space, child_clip_space) = match &render_node.scroll_frame {
+            Some(scroll_frame) => {
+                let key = render_node.element_id.0.as_u128() as u64;
+                let scroll_space = builder.define_scroll_frame(
+                    space_and_clip.spatial_id,
+                    ExternalScrollId(key, pipeline_id),
+                    to_layout_rect(&scroll_frame.content_rect),
+                    to_layout_rect(&scroll_frame.frame_rect),
+                    LayoutVector2D::zero(),
+                    APZScrollGeneration::default(),
+                    HasScrollLinkedEffect::No,
+                    SpatialTreeItemKey::new(key, 1),
+                );
+                (scroll_space, space_and_clip.spatial_id)
+            }
+            None => (space_and_clip.spatial_id, clip_space),
+        };
+        for child in children.get(&render_node.element_id).into_iter().flatten() {
+            Self::add_render_node_to_display_list(builder, pipeline_id, children, child, child_space, child_clip_space);
+        }
+        
+        if faded {
+            builder.pop_stacking_context();
+        }
+        if transformed {
+            builder.pop_reference_frame();
+        }
+    }
+    
+    /// Get rendering performance metrics
+    pub fn get_metrics(&self) -> RenderingMetrics {
+        self.metrics.clone()
+    }
+    
+    /// Drop cached render nodes for elements changed by DOM mutations
+    pub fn invalidate_elements(&mut self, element_ids: &std::collections::HashSet<ElementId>) {
+        self.render_tree_cache.retain(|id, _| !element_ids.contains(id));
     }
     
     /// Load and register a font
@@ -265,36 +496,64 @@ impl RenderingEngine {
         txn.add_raw_font(font_key, font_data, 0);
         self.api.send_transaction(self.document_id, txn);
         
-        self.font_keys.insert(font_family, font_key);
+        self.font_keys.insert(font_family.trim().to_lowercase(), font_key);
         
         Ok(font_key)
     }
     
-    /// Load and register an image
+    /// Make `font_family` another name for the already loaded `existing` font,
+    /// as `@font-face` does with `local()` sources
+    pub fn alias_font(&mut self, font_family: &str, existing: &str) -> Option<FontKey> {
+        let font_key = *self.font_keys.get(&existing.trim().to_lowercase())?;
+        self.font_keys.insert(font_family.trim().to_lowercase(), font_key);
+        Some(font_key)
+    }
+    
+    /// Key of the first loaded font in a `font-family` list
+    pub fn font_key(&self, font_family: &str) -> Option<FontKey> {
+        font_family.split(',')
+            .map(|family| family.trim().trim_matches(|c| c == '"' || c == '\'').to_lowercase())
+            .find_map(|family| self.font_keys.get(&family).copied())
+    }
+    
+    /// Decode and register an image; `format` picks the pixel layout uploaded to WebRender
     pub async fn load_image(&mut self, image_data: Vec<u8>, format: ImageFormat) -> Result<ImageKey> {
-        let image_key = self.api.generate_image_key();
-        
-        // Determine image dimensions (this would need proper image decoding)
-        let dimensions = match format {
-            ImageFormat::RGBA8 => (100, 100), // Placeholder
-            ImageFormat::BGRA8 => (100, 100), // Placeholder
-            _ => (100, 100),
-        };
+        let (descriptor, pixels) = decode_image(&image_data, format)?;
+        Ok(self.add_image(descriptor, pixels))
+    }
+    
+    /// Decode and register the image fetched from `url`, remembering its size for layout
+    pub async fn load_image_from_url(&mut self, url: &str, image_data: Vec<u8>, format: ImageFormat) -> Result<ImageKey> {
+        let (descriptor, pixels) = decode_image(&image_data, format)?;
+        let image_key = self.add_image(descriptor, pixels);
         
-        let descriptor = ImageDescriptor::new(
-            dimensions.0,
-            dimensions.1,
-            format,
-            ImageDescriptorFlags::IS_OPAQUE,
+        self.image_keys.insert(url.to_string(), image_key);
+        self.image_sizes.insert(
+            url.to_string(),
+            Size::new(descriptor.size.width as f32, descriptor.size.height as f32),
         );
         
-        let data = ImageData::new(image_data);
+        Ok(image_key)
+    }
+    
+    fn add_image(&mut self, descriptor: ImageDescriptor, pixels: Vec<u8>) -> ImageKey {
+        let image_key = self.api.generate_image_key();
         
         let mut txn = Transaction::new();
-        txn.add_image(image_key, descriptor, data, None);
+        txn.add_image(image_key, descriptor, ImageData::new(pixels), None);
         self.api.send_transaction(self.document_id, txn);
         
-        Ok(image_key)
+        image_key
+    }
+    
+    /// Natural size of an image loaded with `load_image_from_url`
+    pub fn intrinsic_size(&self, url: &str) -> Option<Size> {
+        self.image_sizes.get(url).copied()
+    }
+    
+    /// Key of an image loaded with `load_image_from_url`
+    pub fn image_key(&self, url: &str) -> Option<ImageKey> {
+        self.image_keys.get(url).copied()
     }
     
     /// Update viewport size
@@ -313,17 +572,220 @@ impl RenderingEngine {
     
     /// Shutdown the rendering engine
     pub async fn shutdown(&mut self) -> Result<()> {
-        // Clean up WebRender resources
+        // Clean up WebRender resources, then the renderer and its GL context
         self.api.shut_down(true);
+        self.render_thread.stop();
         Ok(())
     }
 }
 
+/// Which GL implementation backs the offscreen rendering surface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlBackend {
+    /// The system's GPU, falling back to software rendering if there isn't one
+    Auto,
+    /// The system's GPU only
+    Hardware,
+    /// Software rendering only (e.g. Mesa llvmpipe), for headless tests and CI
+    Software,
+}
+
+/// How long the render thread waits for WebRender to finish building a frame
+const FRAME_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Requests to the render thread
+enum RenderCommand {
+    /// Draw the most recently built frame, replying once it is on the surface
+    Render(oneshot::Sender<Result<()>>),
+    Shutdown,
+}
+
+/// Thread owning the GL context and WebRender `Renderer`, which must stay on the thread that made them
+struct RenderThread {
+    commands: mpsc::Sender<RenderCommand>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl RenderThread {
+    /// Create the GL context and renderer on a new thread, returning the API sender once both are ready
+    async fn spawn(backend: GlBackend, device_size: DeviceIntSize) -> Result<(Self, RenderApiSender)> {
+        let (commands, command_receiver) = mpsc::channel();
+        let (ready_sender, ready) = oneshot::channel();
+        
+        let handle = std::thread::Builder::new()
+            .name("titan-render".to_string())
+            .spawn(move || Self::run(backend, device_size, command_receiver, ready_sender))
+            .map_err(|e| EngineError::RenderingError(format!("Failed to start render thread: {}", e)))?;
+        
+        let sender = ready.await
+            .map_err(|_| EngineError::RenderingError("Render thread exited during setup".to_string()))??;
+        
+        Ok((Self { commands, handle: Some(handle) }, sender))
+    }
+    
+    fn run(
+        backend: GlBackend,
+        device_size: DeviceIntSize,
+        commands: mpsc::Receiver<RenderCommand>,
+        ready: oneshot::Sender<Result<RenderApiSender>>,
+    ) {
+        let (frame_sender, frames) = mpsc::channel();
+        let setup = OffscreenGl::new(backend, device_size).and_then(|gl_context| {
+            let options = WebRenderOptions {
+                clear_color: ColorF::WHITE,
+                precache_flags: ShaderPrecacheFlags::empty(),
+                ..WebRenderOptions::default()
+            };
+            let notifier = Box::new(FrameNotifier { frames: frame_sender });
+            let (renderer, sender) = webrender::create_webrender_instance(gl_context.gl.clone(), notifier, options, None)
+                .map_err(|e| EngineError::RenderingError(format!("Failed to create renderer: {:?}", e)))?;
+            Ok((gl_context, renderer, sender))
+        });
+        
+        let (gl_context, mut renderer, sender): (OffscreenGl, Renderer, RenderApiSender) = match setup {
+            Ok(setup) => setup,
+            Err(e) => {
+                let _ = ready.send(Err(e));
+                return;
+            }
+        };
+        let _ = ready.send(Ok(sender));
+        
+        while let Ok(command) = commands.recv() {
+            match command {
+                RenderCommand::Render(reply) => {
+                    let result = match frames.recv_timeout(FRAME_TIMEOUT) {
+                        Ok(()) => {
+                            // Coalesce frames that finished while we waited
+                            while frames.try_recv().is_ok() {}
+                            renderer.update();
+                            renderer.render(device_size, 0)
+                                .map(|_| ())
+                                .map_err(|errors| EngineError::RenderingError(format!("Failed to render frame: {:?}", errors)))
+                        }
+                        Err(_) => Err(EngineError::RenderingError("Timed out waiting for a frame".to_string())),
+                    };
+                    let _ = reply.send(result);
+                }
+                RenderCommand::Shutdown => break,
+            }
+        }
+        
+        // The renderer's GL objects must be released while its context is still alive
+        renderer.deinit();
+        drop(gl_context);
+    }
+    
+    /// Draw the latest frame
+    async fn render(&self) -> Result<()> {
+        let (reply, result) = oneshot::channel();
+        self.commands.send(RenderCommand::Render(reply))
+            .map_err(|_| EngineError::RenderingError("Render thread has stopped".to_string()))?;
+        result.await
+            .map_err(|_| EngineError::RenderingError("Render thread has stopped".to_string()))?
+    }
+    
+    /// Stop the thread, releasing the renderer and GL context
+    fn stop(&mut self) {
+        let _ = self.commands.send(RenderCommand::Shutdown);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for RenderThread {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Wakes the render thread when WebRender has a frame ready to draw
+struct FrameNotifier {
+    frames: mpsc::Sender<()>,
+}
+
+impl RenderNotifier for FrameNotifier {
+    fn clone(&self) -> Box<dyn RenderNotifier> {
+        Box::new(FrameNotifier { frames: self.frames.clone() })
+    }
+    
+    fn wake_up(&self, _composite_needed: bool) {}
+    
+    fn new_frame_ready(&self, _document_id: DocumentId, _scrolled: bool, _composite_needed: bool) {
+        let _ = self.frames.send(());
+    }
+}
+
+/// A GL context current on this thread, drawing into an offscreen surface
+struct OffscreenGl {
+    device: surfman::Device,
+    context: surfman::Context,
+    gl: Rc<dyn gl::Gl>,
+}
+
+impl OffscreenGl {
+    fn new(backend: GlBackend, size: DeviceIntSize) -> Result<Self> {
+        let gl_error = |e: surfman::Error| EngineError::RenderingError(format!("GL context setup failed: {:?}", e));
+        
+        let connection = surfman::Connection::new().map_err(gl_error)?;
+        let adapter = match backend {
+            GlBackend::Auto => connection.create_adapter().or_else(|_| connection.create_software_adapter()),
+            GlBackend::Hardware => connection.create_adapter(),
+            GlBackend::Software => connection.create_software_adapter(),
+        }.map_err(gl_error)?;
+        let mut device = connection.create_device(&adapter).map_err(gl_error)?;
+        
+        let attributes = surfman::ContextAttributes {
+            version: surfman::GLVersion::new(3, 2),
+            flags: surfman::ContextAttributeFlags::ALPHA
+                | surfman::ContextAttributeFlags::DEPTH
+                | surfman::ContextAttributeFlags::STENCIL,
+        };
+        let descriptor = device.create_context_descriptor(&attributes).map_err(gl_error)?;
+        let mut context = device.create_context(&descriptor, None).map_err(gl_error)?;
+        
+        let surface_type = surfman::SurfaceType::Generic {
+            size: euclid::default::Size2D::new(size.width, size.height),
+        };
+        let bound = device.create_surface(&context, surfman::SurfaceAccess::GPUOnly, surface_type)
+            .and_then(|surface| device.bind_surface_to_context(&mut context, surface).map_err(|(e, _)| e))
+            .and_then(|()| device.make_context_current(&context));
+        if let Err(e) = bound {
+            let _ = device.destroy_context(&mut context);
+            return Err(gl_error(e));
+        }
+        
+        let gl = unsafe { gl::GlFns::load_with(|symbol| device.get_proc_address(&context, symbol)) };
+        
+        // WebRender draws into whatever framebuffer is bound when it starts: the surface's
+        if let Ok(Some(info)) = device.context_surface_info(&context) {
+            gl.bind_framebuffer(gl::FRAMEBUFFER, info.framebuffer_object);
+        }
+        
+        Ok(Self { device, context, gl })
+    }
+}
+
+impl Drop for OffscreenGl {
+    fn drop(&mut self) {
+        // surfman panics if a context is dropped without being destroyed
+        if let Ok(Some(mut surface)) = self.device.unbind_surface_from_context(&mut self.context) {
+            let _ = self.device.destroy_surface(&mut self.context, &mut surface);
+        }
+        let _ = self.device.destroy_context(&mut self.context);
+    }
+}
+
 /// Complete render tree for a document
 #[derive(Debug, Clone)]
 pub struct RenderTree {
     pub root_element_id: ElementId,
     pub render_nodes: HashMap<ElementId, RenderNode>,
+    /// Root element of each embedded frame's document, keyed by its `<iframe>` element
+    pub frames: HashMap<ElementId, ElementId>,
+    /// Nodes changed since the last rendered frame
+    pub dirty_nodes: HashSet<ElementId>,
 }
 
 impl RenderTree {
@@ -332,9 +794,82 @@ impl RenderTree {
         self.render_nodes.get(&element_id)
     }
     
-    /// Update render node
+    /// Update render node, marking it dirty
     pub fn update_render_node(&mut self, element_id: ElementId, render_node: RenderNode) {
         self.render_nodes.insert(element_id, render_node);
+        self.mark_dirty(element_id);
+    }
+    
+    /// Mark a node as needing repaint in the next frame
+    pub fn mark_dirty(&mut self, element_id: ElementId) {
+        self.dirty_nodes.insert(element_id);
+    }
+    
+    pub fn is_dirty(&self, element_id: ElementId) -> bool {
+        self.dirty_nodes.contains(&element_id)
+    }
+    
+    /// Take the nodes changed since the last call
+    pub fn take_dirty_nodes(&mut self) -> HashSet<ElementId> {
+        std::mem::take(&mut self.dirty_nodes)
+    }
+    
+    /// Move everything inside a scroll container by a change in its scroll offset,
+    /// marking the moved nodes dirty so the area they cover is repainted.
+    /// Only the container's children are offset; their descendants move with them.
+    pub fn scroll_descendants(&mut self, layout_tree: &LayoutTree, container_id: ElementId, dx: f32, dy: f32) {
+        let offset = Transform::translate(-dx, -dy);
+        for (element_id, render_node) in self.render_nodes.iter_mut() {
+            if layout_tree.ancestors(*element_id).any(|ancestor| ancestor == container_id) {
+                if render_node.parent == Some(container_id) {
+                    render_node.transform = offset.multiply(&render_node.transform);
+                }
+                self.dirty_nodes.insert(*element_id);
+            }
+        }
+    }
+    
+    /// Paint an image over a node's content box in place of its text, e.g. a video's poster
+    pub fn show_image(&mut self, element_id: ElementId, rect: Rect, image_key: ImageKey) {
+        if let Some(render_node) = self.render_nodes.get_mut(&element_id) {
+            render_node.display_items.retain(|item| !matches!(item, DisplayItem::Text { .. }));
+            render_node.display_items.push(DisplayItem::Image { rect, image_key });
+            self.dirty_nodes.insert(element_id);
+        }
+    }
+    
+    /// Area a node covers on the page, after its own and its ancestors' transforms
+    pub fn node_bounds(&self, element_id: ElementId) -> Option<Rect> {
+        let render_node = self.render_nodes.get(&element_id)?;
+        let transform = std::iter::successors(render_node.parent, |id| self.render_nodes.get(id).and_then(|node| node.parent))
+            .filter_map(|id| self.render_nodes.get(&id))
+            .fold(render_node.transform.clone(), |transform, ancestor| ancestor.transform.multiply(&transform));
+        render_node.bounds_in(&transform)
+    }
+    
+    /// Composite a nested frame's render tree into this one, offset to and clipped by `viewport`,
+    /// which is in page coordinates. The frame's own transforms and clips are kept, moved
+    /// into the parent's space.
+    pub fn embed_frame(&mut self, frame_element_id: ElementId, frame_tree: RenderTree, viewport: Rect) {
+        let offset = Transform::translate(viewport.origin.x, viewport.origin.y);
+        
+        for (element_id, mut render_node) in frame_tree.render_nodes {
+            // Descendants are placed inside their frame root, which carries the offset
+            if render_node.parent.is_none() {
+                render_node.parent = Some(frame_element_id);
+                render_node.transform = offset.multiply(&render_node.transform);
+            }
+            render_node.clip_rect = match render_node.clip_rect {
+                Some(clip) => Some(intersect_rect(&clip.translate(viewport.origin.x, viewport.origin.y), &viewport)
+                    .unwrap_or_else(|| Rect::new(viewport.origin.x, viewport.origin.y, 0.0, 0.0))),
+                None => Some(viewport),
+            };
+            self.render_nodes.insert(element_id, render_node);
+            self.dirty_nodes.insert(element_id);
+        }
+        
+        self.frames.extend(frame_tree.frames);
+        self.frames.insert(frame_element_id, frame_tree.root_element_id);
     }
 }
 
@@ -342,10 +877,63 @@ impl RenderTree {
 #[derive(Debug, Clone)]
 pub struct RenderNode {
     pub element_id: ElementId,
+    /// Nearest rendered ancestor; its transform and opacity apply to this node too
+    pub parent: Option<ElementId>,
     pub display_items: Vec<DisplayItem>,
+    /// Transform within the parent's space
     pub transform: Transform,
     pub opacity: f32,
+    /// Clip applied to this node's items, from `overflow` on its ancestors or an embedding frame
     pub clip_rect: Option<Rect>,
+    /// Set on `overflow: scroll`/`auto` elements, whose descendants scroll within it
+    pub scroll_frame: Option<ScrollFrame>,
+    /// Nearest ancestor scroll container
+    pub scroll_parent: Option<ElementId>,
+}
+
+/// Viewport and scrollable extent of a scroll container
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScrollFrame {
+    /// The container's padding box, through which the content is seen
+    pub frame_rect: Rect,
+    /// Everything inside the container, which can be scrolled into view
+    pub content_rect: Rect,
+}
+
+/// How ancestors' `overflow` affects a node
+struct OverflowClip {
+    clip_rect: Option<Rect>,
+    scroll_parent: Option<ElementId>,
+    scroll_frame: Option<ScrollFrame>,
+}
+
+impl RenderNode {
+    /// Area covered by the node's display items after its own transform and clip
+    pub fn bounds(&self) -> Option<Rect> {
+        self.bounds_in(&self.transform)
+    }
+    
+    /// Area covered by the node's display items under `transform`, then clipped
+    fn bounds_in(&self, transform: &Transform) -> Option<Rect> {
+        let rect = self.display_items.iter()
+            .map(DisplayItem::rect)
+            .reduce(|a, b| union_rect(&a, &b))?;
+        let corners = [
+            (rect.origin.x, rect.origin.y),
+            (rect.origin.x + rect.size.width, rect.origin.y),
+            (rect.origin.x, rect.origin.y + rect.size.height),
+            (rect.origin.x + rect.size.width, rect.origin.y + rect.size.height),
+        ].map(|(x, y)| transform.transform_point(x, y));
+        let min_x = corners.iter().map(|c| c.0).fold(f32::INFINITY, f32::min);
+        let min_y = corners.iter().map(|c| c.1).fold(f32::INFINITY, f32::min);
+        let max_x = corners.iter().map(|c| c.0).fold(f32::NEG_INFINITY, f32::max);
+        let max_y = corners.iter().map(|c| c.1).fold(f32::NEG_INFINITY, f32::max);
+        let bounds = Rect::new(min_x, min_y, max_x - min_x, max_y - min_y);
+        match &self.clip_rect {
+            Some(clip) => intersect_rect(&bounds, clip),
+            None => Some(bounds),
+        }
+    }
 }
 
 /// Display items that can be rendered
@@ -372,6 +960,73 @@ pub enum DisplayItem {
     },
 }
 
+impl DisplayItem {
+    pub fn rect(&self) -> Rect {
+        match self {
+            DisplayItem::Rectangle { rect, .. }
+            | DisplayItem::Border { rect, .. }
+            | DisplayItem::Text { rect, .. }
+            | DisplayItem::Image { rect, .. } => *rect,
+        }
+    }
+}
+
+fn to_layout_rect(rect: &Rect) -> LayoutRect {
+    LayoutRect::new(
+        LayoutPoint::new(rect.origin.x, rect.origin.y),
+        LayoutSize::new(rect.size.width, rect.size.height),
+    )
+}
+
+/// Smallest rect containing both
+fn union_rect(a: &Rect, b: &Rect) -> Rect {
+    let x = a.origin.x.min(b.origin.x);
+    let y = a.origin.y.min(b.origin.y);
+    let right = (a.origin.x + a.size.width).max(b.origin.x + b.size.width);
+    let bottom = (a.origin.y + a.size.height).max(b.origin.y + b.size.height);
+    Rect::new(x, y, right - x, bottom - y)
+}
+
+/// Overlap of two rects, if any
+fn intersect_rect(a: &Rect, b: &Rect) -> Option<Rect> {
+    let x = a.origin.x.max(b.origin.x);
+    let y = a.origin.y.max(b.origin.y);
+    let right = (a.origin.x + a.size.width).min(b.origin.x + b.size.width);
+    let bottom = (a.origin.y + a.size.height).min(b.origin.y + b.size.height);
+    (right > x && bottom > y).then(|| Rect::new(x, y, right - x, bottom - y))
+}
+
+/// Decode PNG, JPEG or GIF (first frame) bytes into premultiplied pixels in `format`,
+/// which must be `RGBA8` or `BGRA8`
+fn decode_image(bytes: &[u8], format: ImageFormat) -> Result<(ImageDescriptor, Vec<u8>)> {
+    let decoded = image::load_from_memory(bytes)
+        .map_err(|e| EngineError::RenderingError(format!("Failed to decode image: {}", e)))?
+        .into_rgba8();
+    let (width, height) = decoded.dimensions();
+    
+    let mut opaque = true;
+    let mut pixels = decoded.into_raw();
+    for pixel in pixels.chunks_exact_mut(4) {
+        let alpha = pixel[3];
+        opaque &= alpha == 255;
+        // WebRender draws images as premultiplied alpha
+        for channel in &mut pixel[..3] {
+            *channel = ((*channel as u16 * alpha as u16 + 127) / 255) as u8;
+        }
+        match format {
+            ImageFormat::RGBA8 => {}
+            ImageFormat::BGRA8 => pixel.swap(0, 2),
+            other => {
+                return Err(EngineError::RenderingError(format!("Unsupported image upload format: {:?}", other)));
+            }
+        }
+    }
+    
+    let flags = if opaque { ImageDescriptorFlags::IS_OPAQUE } else { ImageDescriptorFlags::empty() };
+    let descriptor = ImageDescriptor::new(width as i32, height as i32, format, flags);
+    Ok((descriptor, pixels))
+}
+
 /// 2D transformation matrix
 #[derive(Debug, Clone)]
 pub struct Transform {
@@ -411,6 +1066,71 @@ impl Transform {
             ],
         }
     }
+    
+    /// Clockwise rotation in degrees (y points down)
+    pub fn rotate(degrees: f32) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Self {
+            matrix: [
+                cos, -sin, 0.0, 0.0,
+                sin,  cos, 0.0, 0.0,
+                0.0,  0.0, 1.0, 0.0,
+                0.0,  0.0, 0.0, 1.0,
+            ],
+        }
+    }
+    
+    /// `self` applied after `other`
+    pub fn multiply(&self, other: &Transform) -> Self {
+        let mut matrix = [0.0; 16];
+        for row in 0..4 {
+            for col in 0..4 {
+                matrix[row * 4 + col] = (0..4)
+                    .map(|k| self.matrix[row * 4 + k] * other.matrix[k * 4 + col])
+                    .sum();
+            }
+        }
+        Self { matrix }
+    }
+    
+    pub fn is_identity(&self) -> bool {
+        self.matrix == Self::identity().matrix
+    }
+    
+    /// Compose a CSS transform list around the given origin
+    pub fn from_functions(functions: &[TransformFunction], origin_x: f32, origin_y: f32) -> Self {
+        if functions.is_empty() {
+            return Self::identity();
+        }
+        let composed = functions.iter().fold(Self::identity(), |transform, function| {
+            let next = match *function {
+                TransformFunction::Translate(x, y) => Self::translate(x, y),
+                TransformFunction::Scale(sx, sy) => Self::scale(sx, sy),
+                TransformFunction::Rotate(degrees) => Self::rotate(degrees),
+            };
+            transform.multiply(&next)
+        });
+        Self::translate(origin_x, origin_y)
+            .multiply(&composed)
+            .multiply(&Self::translate(-origin_x, -origin_y))
+    }
+    
+    /// Map a point through the transform
+    pub fn transform_point(&self, x: f32, y: f32) -> (f32, f32) {
+        let m = &self.matrix;
+        (m[0] * x + m[1] * y + m[3], m[4] * x + m[5] * y + m[7])
+    }
+    
+    /// WebRender's row-vector layout of the same matrix
+    pub fn to_layout_transform(&self) -> LayoutTransform {
+        let m = &self.matrix;
+        LayoutTransform::new(
+            m[0], m[4], m[8], m[12],
+            m[1], m[5], m[9], m[13],
+            m[2], m[6], m[10], m[14],
+            m[3], m[7], m[11], m[15],
+        )
+    }
 }
 
 /// Rendering performance metrics
@@ -423,11 +1143,31 @@ pub struct RenderingMetrics {
     pub triangles: u32,
     pub texture_memory_mb: f64,
     pub vertex_memory_mb: f64,
+    pub frames_rendered: u64,
+    /// Frames skipped because nothing changed
+    pub frames_skipped: u64,
+    /// Nodes repainted by the last frame
+    pub dirty_nodes: u32,
+    /// Area, in CSS pixels, of the last frame's damage rects
+    pub repainted_area: f64,
+}
+
+#[async_trait::async_trait]
+impl MetricsReporter for RenderingEngine {
+    async fn get_metrics(&self) -> PerformanceMetrics {
+        let frame_time_ms = self.metrics.frame_time_ms;
+        PerformanceMetrics {
+            render_fps: if frame_time_ms > 0.0 { (1000.0 / frame_time_ms) as f32 } else { 0.0 },
+            paint_time_ms: frame_time_ms.round() as u64,
+            ..PerformanceMetrics::default()
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::css::OverflowType;
     
     #[test]
     fn test_transform_operations() {
@@ -451,11 +1191,14 @@ mod tests {
         let mut render_tree = RenderTree {
             root_element_id: ElementId::new(),
             render_nodes: HashMap::new(),
+            frames: HashMap::new(),
+            dirty_nodes: HashSet::new(),
         };
         
         let element_id = ElementId::new();
         let render_node = RenderNode {
             element_id,
+            parent: None,
             display_items: vec![DisplayItem::Rectangle {
                 rect: Rect::new(0.0, 0.0, 100.0, 100.0),
                 color: Color::red(),
@@ -463,9 +1206,278 @@ mod tests {
             transform: Transform::identity(),
             opacity: 1.0,
             clip_rect: None,
+            scroll_frame: None,
+            scroll_parent: None,
         };
         
         render_tree.update_render_node(element_id, render_node);
         assert!(render_tree.get_render_node(element_id).is_some());
     }
-}
\ No newline at end of file
+    
+    #[tokio::test]
+    async fn test_headless_renders_empty_frame() {
+        let mut engine = RenderingEngine::headless(64, 64).await.unwrap();
+        let mut render_tree = RenderTree {
+            root_element_id: ElementId::new(),
+            render_nodes: HashMap::new(),
+            frames: HashMap::new(),
+            dirty_nodes: HashSet::new(),
+        };
+        
+        engine.render_frame(&mut render_tree).await.unwrap();
+        assert_eq!(engine.get_metrics().frames_rendered, 1);
+        
+        // Nothing changed, so the next frame is skipped
+        engine.render_frame(&mut render_tree).await.unwrap();
+        assert_eq!(engine.get_metrics().frames_rendered, 1);
+        assert_eq!(engine.get_metrics().frames_skipped, 1);
+        
+        engine.shutdown().await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_font_family_maps_to_loaded_font_key() {
+        let mut engine = RenderingEngine::headless(64, 64).await.unwrap();
+        let font_key = engine.load_font(vec![0; 64], "Brand Sans".to_string()).await.unwrap();
+        
+        assert_eq!(engine.font_key("Missing, \"Brand Sans\", serif"), Some(font_key));
+        assert_eq!(engine.font_key("serif"), None);
+        
+        // local() names an already loaded font
+        assert_eq!(engine.alias_font("Brand", "brand sans"), Some(font_key));
+        assert_eq!(engine.font_key("Brand"), Some(font_key));
+        assert_eq!(engine.alias_font("Other", "Not Loaded"), None);
+        
+        engine.shutdown().await.unwrap();
+    }
+    
+    #[test]
+    fn test_update_marks_only_changed_node_dirty() {
+        let node = |element_id, x| RenderNode {
+            element_id,
+            parent: None,
+            display_items: vec![DisplayItem::Rectangle {
+                rect: Rect::new(x, 0.0, 10.0, 10.0),
+                color: Color::red(),
+            }],
+            transform: Transform::identity(),
+            opacity: 1.0,
+            clip_rect: None,
+            scroll_frame: None,
+            scroll_parent: None,
+        };
+        let (first, second) = (ElementId::new(), ElementId::new());
+        let mut render_tree = RenderTree {
+            root_element_id: first,
+            render_nodes: HashMap::from([(first, node(first, 0.0)), (second, node(second, 20.0))]),
+            frames: HashMap::new(),
+            dirty_nodes: HashSet::new(),
+        };
+        
+        render_tree.update_render_node(second, node(second, 30.0));
+        assert!(render_tree.is_dirty(second));
+        assert!(!render_tree.is_dirty(first));
+        assert_eq!(render_tree.take_dirty_nodes(), HashSet::from([second]));
+        assert!(render_tree.dirty_nodes.is_empty());
+        
+        let moved = render_tree.get_render_node(second).unwrap();
+        assert_eq!(moved.bounds(), Some(Rect::new(30.0, 0.0, 10.0, 10.0)));
+        
+        // Clipped to a frame viewport
+        let mut clipped = node(first, 0.0);
+        clipped.clip_rect = Some(Rect::new(5.0, 5.0, 100.0, 100.0));
+        assert_eq!(clipped.bounds(), Some(Rect::new(5.0, 5.0, 5.0, 5.0)));
+    }
+
+    #[test]
+    fn test_opacity_and_transform_in_display_list() {
+        let pipeline_id = PipelineId(0, 0);
+        let mut render_node = RenderNode {
+            element_id: ElementId::new(),
+            parent: None,
+            display_items: vec![DisplayItem::Rectangle {
+                rect: Rect::new(0.0, 0.0, 20.0, 10.0),
+                color: Color::red(),
+            }],
+            transform: Transform::identity(),
+            opacity: 0.5,
+            clip_rect: None,
+            scroll_frame: None,
+            scroll_parent: None,
+        };
+        let child = RenderNode {
+            element_id: ElementId::new(),
+            parent: Some(render_node.element_id),
+            opacity: 1.0,
+            ..render_node.clone()
+        };
+        let render_nodes = HashMap::from([
+            (render_node.element_id, render_node.clone()),
+            (child.element_id, child),
+        ]);
+        
+        let mut builder = DisplayListBuilder::new(pipeline_id);
+        RenderingEngine::build_display_list(&mut builder, pipeline_id, &render_nodes);
+        let (_, display_list) = builder.end();
+        let mut iter = display_list.iter();
+        let mut opacities = Vec::new();
+        let mut depth = 0;
+        let mut rect_depths = Vec::new();
+        while let Some(item) = iter.next() {
+            match item.item() {
+                webrender::api::DisplayItem::PushStackingContext(_) => {
+                    depth += 1;
+                    opacities.extend(item.filters().iter().filter_map(|filter| match filter {
+                        FilterOp::Opacity(_, opacity) => Some(*opacity),
+                        _ => None,
+                    }));
+                }
+                webrender::api::DisplayItem::PopStackingContext => depth -= 1,
+                webrender::api::DisplayItem::Rectangle(_) => rect_depths.push(depth),
+                _ => {}
+            }
+        }
+        // One group fades the parent and child together
+        assert_eq!(opacities, vec![0.5]);
+        assert_eq!(rect_depths, vec![1, 1]);
+        
+        // A quarter turn about the center swaps the box's extent
+        render_node.transform = Transform::from_functions(&[TransformFunction::Rotate(90.0)], 10.0, 5.0);
+        let bounds = render_node.bounds().unwrap();
+        assert!((bounds.origin.x - 5.0).abs() < 1e-4 && (bounds.origin.y + 5.0).abs() < 1e-4);
+        assert!((bounds.size.width - 10.0).abs() < 1e-4 && (bounds.size.height - 20.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_embed_frame_keeps_frame_transforms() {
+        let frame_root = ElementId::new();
+        let rotated = ElementId::new();
+        let node = |element_id, parent, transform, clip_rect| RenderNode {
+            element_id,
+            parent,
+            display_items: vec![DisplayItem::Rectangle {
+                rect: Rect::new(0.0, 0.0, 10.0, 10.0),
+                color: Color::red(),
+            }],
+            transform,
+            opacity: 1.0,
+            clip_rect,
+            scroll_frame: None,
+            scroll_parent: None,
+        };
+        let frame_tree = RenderTree {
+            root_element_id: frame_root,
+            render_nodes: HashMap::from([
+                (frame_root, node(frame_root, None, Transform::identity(), None)),
+                (rotated, node(rotated, Some(frame_root), Transform::translate(5.0, 0.0), Some(Rect::new(0.0, 0.0, 50.0, 500.0)))),
+            ]),
+            frames: HashMap::new(),
+            dirty_nodes: HashSet::new(),
+        };
+        let mut page = RenderTree {
+            root_element_id: ElementId::new(),
+            render_nodes: HashMap::new(),
+            frames: HashMap::new(),
+            dirty_nodes: HashSet::new(),
+        };
+        let iframe = ElementId::new();
+        let viewport = Rect::new(100.0, 40.0, 300.0, 150.0);
+        page.embed_frame(iframe, frame_tree, viewport);
+        
+        let root = page.get_render_node(frame_root).unwrap();
+        assert_eq!(root.transform.transform_point(0.0, 0.0), (100.0, 40.0));
+        assert_eq!(root.clip_rect, Some(viewport));
+        assert_eq!(root.parent, Some(iframe));
+        
+        // The frame's own offset stays relative to the frame root, and its clip moves into
+        // the page, inside the viewport
+        let moved = page.get_render_node(rotated).unwrap();
+        assert_eq!(moved.transform.transform_point(0.0, 0.0), (5.0, 0.0));
+        assert_eq!(moved.clip_rect, Some(Rect::new(100.0, 40.0, 50.0, 150.0)));
+        assert_eq!(page.node_bounds(rotated), Some(Rect::new(105.0, 40.0, 10.0, 10.0)));
+        assert_eq!(page.frames.get(&iframe), Some(&frame_root));
+    }
+
+    #[test]
+    fn test_decode_png_dimensions() {
+        let mut png = Vec::new();
+        let source = image::RgbaImage::from_pixel(3, 2, image::Rgba([255, 0, 0, 128]));
+        source.write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png).unwrap();
+        
+        let (descriptor, pixels) = decode_image(&png, ImageFormat::BGRA8).unwrap();
+        assert_eq!((descriptor.size.width, descriptor.size.height), (3, 2));
+        assert_eq!(descriptor.format, ImageFormat::BGRA8);
+        assert!(!descriptor.is_opaque());
+        assert_eq!(pixels.len(), 3 * 2 * 4);
+        // Premultiplied red, swizzled to BGRA
+        assert_eq!(&pixels[..4], &[0, 0, 128, 128]);
+        
+        assert!(matches!(
+            decode_image(b"not an image", ImageFormat::RGBA8),
+            Err(EngineError::RenderingError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_overflow_hidden_clips_children_in_display_list() {
+        let layout_box = |element_id, rect: Rect| LayoutBox {
+            element_id,
+            content_rect: rect,
+            padding_rect: rect,
+            border_rect: rect,
+            margin_rect: rect,
+            baseline: 0.0,
+            ascent: 0.0,
+            descent: 0.0,
+            fragments: Vec::new(),
+        };
+        let (parent, child) = (ElementId::new(), ElementId::new());
+        let parent_rect = Rect::new(10.0, 10.0, 100.0, 50.0);
+        let layout_tree = LayoutTree {
+            root_element_id: parent,
+            layout_boxes: HashMap::from([
+                (parent, layout_box(parent, parent_rect)),
+                (child, layout_box(child, Rect::new(10.0, 10.0, 300.0, 200.0))),
+            ]),
+            parents: HashMap::from([(child, parent)]),
+        };
+        let mut parent_style = ComputedStyle::default();
+        parent_style.overflow = OverflowType::Hidden;
+        let mut child_style = ComputedStyle::default();
+        child_style.background_color = Color::red();
+        let styles = HashMap::from([(parent, parent_style), (child, child_style)]);
+        
+        let mut engine = RenderingEngine::headless(200, 200).await.unwrap();
+        let render_tree = engine.create_render_tree_with_styles(&layout_tree, &styles).await.unwrap();
+        let child_node = render_tree.get_render_node(child).unwrap();
+        assert_eq!(child_node.clip_rect, Some(parent_rect));
+        assert_eq!(child_node.bounds(), Some(parent_rect));
+        assert_eq!(render_tree.get_render_node(parent).unwrap().clip_rect, None);
+        
+        let pipeline_id = PipelineId(0, 0);
+        let mut builder = DisplayListBuilder::new(pipeline_id);
+        RenderingEngine::build_display_list(&mut builder, pipeline_id, &render_tree.render_nodes);
+        let (_, display_list) = builder.end();
+        let mut iter = display_list.iter();
+        let mut clips = Vec::new();
+        let mut clipped_rects = 0;
+        while let Some(item) = iter.next() {
+            match item.item() {
+                webrender::api::DisplayItem::RectClip(clip) => clips.push(clip.clip_rect),
+                webrender::api::DisplayItem::Rectangle(rect) if rect.common.clip_chain_id != ClipChainId::INVALID => clipped_rects += 1,
+                _ => {}
+            }
+        }
+        assert_eq!(clips, vec![to_layout_rect(&parent_rect)]);
+        assert_eq!(clipped_rects, 1);
+        
+        // A scroll container gets a scroll frame sized to its overflowing content
+        let mut styles = styles;
+        styles.get_mut(&parent).unwrap().overflow = OverflowType::Scroll;
+        let render_tree = engine.create_render_tree_with_styles(&layout_tree, &styles).await.unwrap();
+        let scroll_frame = render_tree.get_render_node(parent).unwrap().scroll_frame.clone().unwrap();
+        assert_eq!(scroll_frame.frame_rect, parent_rect);
+        assert_eq!(scroll_frame.content_rect, Rect::new(10.0, 10.0, 300.0, 200.0));
+        assert_eq!(render_tree.get_render_node(child).unwrap().scroll_parent, Some(parent));
+    }
+}
@@ -1,7 +1,7 @@
 //! Security engine for threat detection and protection
 
 use std::collections::{HashMap, HashSet};
-use std::sync::Arc;
+use std::sync::{Arc, PoisonError};
 use std::time::{Duration, SystemTime};
 use tokio::sync::RwLock;
 use url::Url;
@@ -24,8 +24,11 @@ pub struct SecurityEngine {
     /// Malware detection engine
     malware_detector: MalwareDetector,
     
-    /// Configuration
-    config: SecurityConfig,
+    /// Per-origin permission decisions
+    permissions: PermissionStore,
+    
+    /// Configuration, read at the start of each scan so updates apply to the shared engine
+    config: RwLock<SecurityConfig>,
 }
 
 impl SecurityEngine {
@@ -41,7 +44,8 @@ impl SecurityEngine {
             event_log,
             csp_engine: CSPEngine::new(),
             malware_detector: MalwareDetector::new(),
-            config: SecurityConfig::default(),
+            permissions: PermissionStore::new(),
+            config: RwLock::new(SecurityConfig::default()),
         })
     }
     
@@ -84,23 +88,30 @@ impl SecurityEngine {
     
     /// Scan content for threats
     pub async fn scan_content(&self, content: &str, url: &str) -> Result<ScanResult> {
+        let config = self.config.read().await.clone();
         let mut threats = Vec::new();
         let mut risk_score = 0.0;
         
         // JavaScript threat detection
-        let js_threats = self.detect_javascript_threats(content).await;
-        threats.extend(js_threats.iter().cloned());
-        risk_score += js_threats.len() as f64 * 0.3;
+        if config.enable_javascript_scanning {
+            let js_threats = self.detect_javascript_threats(content).await;
+            threats.extend(js_threats.iter().cloned());
+            risk_score += js_threats.len() as f64 * 0.3;
+        }
         
         // Malware detection
-        let malware_threats = self.malware_detector.scan_content(content).await;
-        threats.extend(malware_threats.iter().cloned());
-        risk_score += malware_threats.len() as f64 * 0.5;
+        if config.enable_malware_detection {
+            let malware_threats = self.malware_detector.scan_content(content).await;
+            threats.extend(malware_threats.iter().cloned());
+            risk_score += malware_threats.len() as f64 * 0.5;
+        }
         
         // Phishing detection
-        let phishing_threats = self.detect_phishing_content(content, url).await;
-        threats.extend(phishing_threats.iter().cloned());
-        risk_score += phishing_threats.len() as f64 * 0.4;
+        if config.enable_phishing_protection {
+            let phishing_threats = self.detect_phishing_content(content, url).await;
+            threats.extend(phishing_threats.iter().cloned());
+            risk_score += phishing_threats.len() as f64 * 0.4;
+        }
         
         // Data exfiltration detection
         let exfiltration_threats = self.detect_data_exfiltration(content).await;
@@ -108,9 +119,11 @@ impl SecurityEngine {
         risk_score += exfiltration_threats.len() as f64 * 0.6;
         
         // Cryptojacking detection
-        let crypto_threats = self.detect_cryptojacking(content).await;
-        threats.extend(crypto_threats.iter().cloned());
-        risk_score += crypto_threats.len() as f64 * 0.7;
+        if config.enable_cryptojacking_protection {
+            let crypto_threats = self.detect_cryptojacking(content).await;
+            threats.extend(crypto_threats.iter().cloned());
+            risk_score += crypto_threats.len() as f64 * 0.7;
+        }
         
         // Log significant threats
         for threat in &threats {
@@ -376,9 +389,19 @@ impl SecurityEngine {
         }
     }
     
-    /// Update security configuration
-    pub fn update_config(&mut self, config: SecurityConfig) {
-        self.config = config;
+    /// Update security configuration; it applies from the next scan
+    pub async fn update_config(&self, config: SecurityConfig) {
+        *self.config.write().await = config;
+    }
+    
+    /// The current security configuration
+    pub async fn config(&self) -> SecurityConfig {
+        self.config.read().await.clone()
+    }
+    
+    /// Get a handle to the permission store shared with the runtimes
+    pub fn permissions(&self) -> PermissionStore {
+        self.permissions.clone()
     }
     
     /// Shutdown security engine
@@ -388,6 +411,122 @@ impl SecurityEngine {
     }
 }
 
+/// Powerful features that require an explicit per-origin grant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PermissionKind {
+    ClipboardRead,
+    ClipboardWrite,
+    Geolocation,
+    Notifications,
+    Camera,
+    Microphone,
+}
+
+impl PermissionKind {
+    /// Permission name as used by the Permissions API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ClipboardRead => "clipboard-read",
+            Self::ClipboardWrite => "clipboard-write",
+            Self::Geolocation => "geolocation",
+            Self::Notifications => "notifications",
+            Self::Camera => "camera",
+            Self::Microphone => "microphone",
+        }
+    }
+    
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "clipboard-read" => Some(Self::ClipboardRead),
+            "clipboard-write" => Some(Self::ClipboardWrite),
+            "geolocation" => Some(Self::Geolocation),
+            "notifications" => Some(Self::Notifications),
+            "camera" => Some(Self::Camera),
+            "microphone" => Some(Self::Microphone),
+            _ => None,
+        }
+    }
+}
+
+/// Decision recorded for a permission
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionState {
+    Granted,
+    Denied,
+    Prompt,
+}
+
+/// Per-origin permission decisions; clones share the same store
+#[derive(Debug, Clone, Default)]
+pub struct PermissionStore {
+    // std lock so synchronous JS bindings can query it
+    decisions: Arc<std::sync::RwLock<HashMap<(String, PermissionKind), PermissionState>>>,
+}
+
+impl PermissionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Get the decision for an origin, `Prompt` if none has been made
+    pub fn query(&self, origin: &str, kind: PermissionKind) -> PermissionState {
+        self.decisions.read().unwrap_or_else(PoisonError::into_inner)
+            .get(&(origin.to_string(), kind))
+            .copied()
+            .unwrap_or(PermissionState::Prompt)
+    }
+    
+    /// Check whether a permission has been granted to an origin
+    pub fn is_granted(&self, origin: &str, kind: PermissionKind) -> bool {
+        self.query(origin, kind) == PermissionState::Granted
+    }
+    
+    /// Record a decision for an origin
+    pub fn set(&self, origin: &str, kind: PermissionKind, state: PermissionState) {
+        self.decisions.write().unwrap_or_else(PoisonError::into_inner).insert((origin.to_string(), kind), state);
+    }
+    
+    /// Grant a permission to an origin
+    pub fn grant(&self, origin: &str, kind: PermissionKind) {
+        self.set(origin, kind, PermissionState::Granted);
+    }
+    
+    /// Forget every decision made for an origin
+    pub fn reset_origin(&self, origin: &str) {
+        self.decisions.write().unwrap_or_else(PoisonError::into_inner).retain(|(o, _), _| o != origin);
+    }
+}
+
+/// Restrictions from an iframe's `sandbox` attribute; each flag lifts one restriction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SandboxFlags {
+    pub allow_scripts: bool,
+    pub allow_same_origin: bool,
+    pub allow_forms: bool,
+    pub allow_popups: bool,
+    pub allow_modals: bool,
+    pub allow_top_navigation: bool,
+}
+
+impl SandboxFlags {
+    /// Parse a `sandbox` attribute value; an empty value applies every restriction
+    pub fn parse(value: &str) -> Self {
+        let mut flags = Self::default();
+        for token in value.split_whitespace() {
+            match token.to_ascii_lowercase().as_str() {
+                "allow-scripts" => flags.allow_scripts = true,
+                "allow-same-origin" => flags.allow_same_origin = true,
+                "allow-forms" => flags.allow_forms = true,
+                "allow-popups" => flags.allow_popups = true,
+                "allow-modals" => flags.allow_modals = true,
+                "allow-top-navigation" => flags.allow_top_navigation = true,
+                other => log::debug!("Ignoring unknown sandbox token: {}", other),
+            }
+        }
+        flags
+    }
+}
+
 /// Security event representation
 #[derive(Debug, Clone)]
 pub struct SecurityEvent {
@@ -564,6 +703,32 @@ impl Default for SecurityConfig {
 mod tests {
     use super::*;
     
+    #[test]
+    fn test_sandbox_flags_parsing() {
+        assert_eq!(SandboxFlags::parse(""), SandboxFlags::default());
+        
+        let flags = SandboxFlags::parse("allow-scripts  ALLOW-FORMS bogus");
+        assert!(flags.allow_scripts);
+        assert!(flags.allow_forms);
+        assert!(!flags.allow_same_origin);
+    }
+    
+    #[test]
+    fn test_permission_store() {
+        let store = PermissionStore::new();
+        let origin = "https://example.com";
+        
+        assert_eq!(store.query(origin, PermissionKind::ClipboardRead), PermissionState::Prompt);
+        
+        // Clones share decisions
+        store.clone().grant(origin, PermissionKind::ClipboardRead);
+        assert!(store.is_granted(origin, PermissionKind::ClipboardRead));
+        assert!(!store.is_granted("https://other.example", PermissionKind::ClipboardRead));
+        
+        store.reset_origin(origin);
+        assert_eq!(store.query(origin, PermissionKind::ClipboardRead), PermissionState::Prompt);
+    }
+    
     #[tokio::test]
     async fn test_security_engine_creation() {
         let engine = SecurityEngine::new().await.unwrap();
@@ -606,4 +771,26 @@ mod tests {
         assert!(engine.has_suspicious_url_patterns("https://bit.ly/suspicious"));
         assert!(!engine.has_suspicious_url_patterns("https://google.com"));
     }
-}
\ No newline at end of file
+    
+    #[tokio::test]
+    async fn test_config_update_applies_to_next_scan() {
+        let engine = Arc::new(SecurityEngine::new().await.unwrap());
+        engine.update_config(SecurityConfig {
+            enable_cryptojacking_protection: false,
+            ..SecurityConfig::default()
+        }).await;
+        
+        let page = r#"<script src="https://cdn.example/coinhive.min.js"></script>"#;
+        let result = engine.scan_content(page, "https://example.com/").await.unwrap();
+        assert!(result.threats.is_empty());
+        assert!(result.safe);
+        
+        // Tightened through another handle to the same engine
+        let handle = engine.clone();
+        handle.update_config(SecurityConfig::default()).await;
+        let result = engine.scan_content(page, "https://example.com/").await.unwrap();
+        assert!(result.threats.iter().any(|t| matches!(t.threat_type, SecurityEventType::CryptojackingAttempt)));
+        assert!(!result.safe);
+        assert!(engine.config().await.enable_cryptojacking_protection);
+    }
+}
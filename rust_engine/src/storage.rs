@@ -1,18 +1,34 @@
 //! Storage engine using SQLite for persistent data
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
 use std::path::Path;
-use rusqlite::{Connection, params, Result as SqliteResult};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use rusqlite::{Connection, OptionalExtension, params, Result as SqliteResult};
 use serde::{Serialize, Deserialize};
 use crate::core::{Result, EngineError};
+use crate::html::{escape_html, Document, Element, HTMLParser};
+
+/// Cached bodies at least this large are stored Brotli-compressed
+const CACHE_COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Brotli quality for cached bodies: well compressed without slowing page loads
+const CACHE_BROTLI_QUALITY: u32 = 5;
+
+/// How long a write waits for another connection's write to the same file to finish
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Prepared statements kept per connection, enough for every hot query
+const STATEMENT_CACHE_CAPACITY: usize = 64;
 
 /// Storage engine for persistent data management
 pub struct StorageEngine {
-    /// SQLite connection
-    connection: Connection,
+    /// SQLite connection, only touched from tokio's blocking pool
+    connection: Arc<Mutex<Connection>>,
     
     /// In-memory cache for frequently accessed data
-    cache: HashMap<String, CachedValue>,
+    cache: Mutex<HashMap<String, CachedValue>>,
     
     /// Storage configuration
     config: StorageConfig,
@@ -21,127 +37,63 @@ pub struct StorageEngine {
 impl StorageEngine {
     /// Create a new storage engine
     pub async fn new() -> Result<Self> {
-        let db_path = "titan_browser.db";
-        let connection = Connection::open(db_path)
-            .map_err(|e| EngineError::StorageError(format!("Failed to open database: {}", e)))?;
-        
-        let mut engine = Self {
-            connection,
-            cache: HashMap::new(),
-            config: StorageConfig::default(),
-        };
-        
-        // Initialize database schema
-        engine.initialize_schema().await?;
-        
-        Ok(engine)
+        Self::open("titan_browser.db").await
     }
     
-    /// Initialize database schema
-    async fn initialize_schema(&mut self) -> Result<()> {
-        // Create tables
-        self.connection.execute_batch(r#"
-            CREATE TABLE IF NOT EXISTS settings (
-                key TEXT PRIMARY KEY,
-                value TEXT NOT NULL,
-                created_at INTEGER NOT NULL,
-                updated_at INTEGER NOT NULL
-            );
+    /// Open a storage engine backed by the database at `db_path`, creating it if needed
+    pub async fn open(db_path: impl AsRef<Path>) -> Result<Self> {
+        let db_path = db_path.as_ref().to_path_buf();
+        let connection = tokio::task::spawn_blocking(move || {
+            let connection = Connection::open(&db_path)
+                .map_err(|e| EngineError::StorageError(format!("Failed to open database: {}", e)))?;
             
-            CREATE TABLE IF NOT EXISTS cookies (
-                domain TEXT NOT NULL,
-                name TEXT NOT NULL,
-                value TEXT NOT NULL,
-                path TEXT NOT NULL DEFAULT '/',
-                expires INTEGER,
-                secure BOOLEAN NOT NULL DEFAULT 0,
-                http_only BOOLEAN NOT NULL DEFAULT 0,
-                same_site TEXT DEFAULT 'Lax',
-                created_at INTEGER NOT NULL,
-                PRIMARY KEY (domain, name, path)
-            );
-            
-            CREATE TABLE IF NOT EXISTS local_storage (
-                origin TEXT NOT NULL,
-                key TEXT NOT NULL,
-                value TEXT NOT NULL,
-                created_at INTEGER NOT NULL,
-                updated_at INTEGER NOT NULL,
-                PRIMARY KEY (origin, key)
-            );
+            // WAL lets the engine's other connections to this file read while one writes
+            connection.query_row("PRAGMA journal_mode = WAL", [], |row| row.get::<_, String>(0))
+                .map_err(|e| EngineError::StorageError(format!("Failed to enable WAL: {}", e)))?;
+            connection.busy_timeout(BUSY_TIMEOUT)
+                .map_err(|e| EngineError::StorageError(format!("Failed to set busy timeout: {}", e)))?;
+            connection.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
             
-            CREATE TABLE IF NOT EXISTS session_storage (
-                session_id TEXT NOT NULL,
-                origin TEXT NOT NULL,
-                key TEXT NOT NULL,
-                value TEXT NOT NULL,
-                created_at INTEGER NOT NULL,
-                PRIMARY KEY (session_id, origin, key)
-            );
-            
-            CREATE TABLE IF NOT EXISTS cache_entries (
-                url TEXT PRIMARY KEY,
-                headers TEXT NOT NULL,
-                body BLOB NOT NULL,
-                expires_at INTEGER NOT NULL,
-                created_at INTEGER NOT NULL
-            );
-            
-            CREATE TABLE IF NOT EXISTS downloads (
-                id TEXT PRIMARY KEY,
-                url TEXT NOT NULL,
-                filename TEXT NOT NULL,
-                path TEXT NOT NULL,
-                status TEXT NOT NULL,
-                progress REAL NOT NULL DEFAULT 0.0,
-                total_bytes INTEGER NOT NULL DEFAULT 0,
-                downloaded_bytes INTEGER NOT NULL DEFAULT 0,
-                created_at INTEGER NOT NULL,
-                completed_at INTEGER
-            );
-            
-            CREATE TABLE IF NOT EXISTS bookmarks (
-                id TEXT PRIMARY KEY,
-                title TEXT NOT NULL,
-                url TEXT NOT NULL,
-                description TEXT,
-                favicon TEXT,
-                tags TEXT,
-                folder_id TEXT,
-                created_at INTEGER NOT NULL,
-                updated_at INTEGER NOT NULL
-            );
-            
-            CREATE TABLE IF NOT EXISTS history (
-                id TEXT PRIMARY KEY,
-                url TEXT NOT NULL,
-                title TEXT NOT NULL,
-                visit_count INTEGER NOT NULL DEFAULT 1,
-                last_visit INTEGER NOT NULL,
-                created_at INTEGER NOT NULL
-            );
-            
-            CREATE INDEX IF NOT EXISTS idx_cookies_domain ON cookies(domain);
-            CREATE INDEX IF NOT EXISTS idx_local_storage_origin ON local_storage(origin);
-            CREATE INDEX IF NOT EXISTS idx_cache_expires ON cache_entries(expires_at);
-            CREATE INDEX IF NOT EXISTS idx_history_url ON history(url);
-            CREATE INDEX IF NOT EXISTS idx_history_last_visit ON history(last_visit);
-        "#).map_err(|e| EngineError::StorageError(format!("Failed to initialize schema: {}", e)))?;
+            // Initialize database schema
+            initialize_schema(&connection)?;
+            Ok::<_, EngineError>(connection)
+        }).await.map_err(|e| EngineError::StorageError(format!("Storage task failed: {}", e)))??;
         
-        Ok(())
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            cache: Mutex::new(HashMap::new()),
+            config: StorageConfig::default(),
+        })
+    }
+    
+    /// Run `f` with the connection on tokio's blocking pool, so disk I/O never stalls the
+    /// async runtime. Calls on one engine take turns on its connection.
+    async fn with_connection<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || f(&mut connection.lock().unwrap_or_else(PoisonError::into_inner)))
+            .await
+            .map_err(|e| EngineError::StorageError(format!("Storage task failed: {}", e)))?
     }
     
     /// Store a setting
-    pub async fn set_setting(&mut self, key: &str, value: &str) -> Result<()> {
+    pub async fn set_setting(&self, key: &str, value: &str) -> Result<()> {
         let now = chrono::Utc::now().timestamp();
         
-        self.connection.execute(
-            "INSERT OR REPLACE INTO settings (key, value, created_at, updated_at) VALUES (?1, ?2, ?3, ?4)",
-            params![key, value, now, now],
-        ).map_err(|e| EngineError::StorageError(format!("Failed to set setting: {}", e)))?;
+        let (owned_key, owned_value) = (key.to_string(), value.to_string());
+        self.with_connection(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO settings (key, value, created_at, updated_at) VALUES (?1, ?2, ?3, ?4)",
+                params![owned_key, owned_value, now, now],
+            ).map_err(|e| EngineError::StorageError(format!("Failed to set setting: {}", e)))?;
+            Ok(())
+        }).await?;
         
         // Update cache
-        self.cache.insert(key.to_string(), CachedValue {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner).insert(key.to_string(), CachedValue {
             value: value.to_string(),
             expires_at: None,
         });
@@ -152,139 +104,211 @@ impl StorageEngine {
     /// Get a setting
     pub async fn get_setting(&self, key: &str) -> Result<Option<String>> {
         // Check cache first
-        if let Some(cached) = self.cache.get(key) {
+        if let Some(cached) = self.cache.lock().unwrap_or_else(PoisonError::into_inner).get(key) {
             if !cached.is_expired() {
                 return Ok(Some(cached.value.clone()));
             }
         }
         
-        let mut stmt = self.connection.prepare("SELECT value FROM settings WHERE key = ?1")
-            .map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
-        
-        let result = stmt.query_row(params![key], |row| {
-            Ok(row.get::<_, String>(0)?)
-        });
-        
-        match result {
-            Ok(value) => Ok(Some(value)),
-            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-            Err(e) => Err(EngineError::StorageError(format!("Failed to get setting: {}", e))),
-        }
+        let key = key.to_string();
+        self.with_connection(move |connection| {
+            let mut stmt = connection.prepare_cached("SELECT value FROM settings WHERE key = ?1")
+                .map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
+            
+            let result = stmt.query_row(params![key], |row| {
+                Ok(row.get::<_, String>(0)?)
+            });
+            
+            match result {
+                Ok(value) => Ok(Some(value)),
+                Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+                Err(e) => Err(EngineError::StorageError(format!("Failed to get setting: {}", e))),
+            }
+        }).await
     }
     
-    /// Store a cookie
+    /// Store a cookie; `SameSite=None` cookies must be Secure
     pub async fn set_cookie(&mut self, cookie: Cookie) -> Result<()> {
+        if cookie.same_site == SameSite::None && !cookie.secure {
+            return Err(EngineError::SecurityError(format!("Cookie {} has SameSite=None without Secure", cookie.name)));
+        }
         let now = chrono::Utc::now().timestamp();
         
-        self.connection.execute(
-            r#"INSERT OR REPLACE INTO cookies 
-               (domain, name, value, path, expires, secure, http_only, same_site, created_at) 
-               VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"#,
-            params![
-                cookie.domain,
-                cookie.name,
-                cookie.value,
-                cookie.path,
-                cookie.expires,
-                cookie.secure,
-                cookie.http_only,
-                cookie.same_site,
-                now
-            ],
-        ).map_err(|e| EngineError::StorageError(format!("Failed to set cookie: {}", e)))?;
-        
-        Ok(())
+        self.with_connection(move |connection| {
+            connection.execute(
+                r#"INSERT OR REPLACE INTO cookies
+                   (domain, name, value, path, expires, secure, http_only, same_site, created_at)
+                   VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"#,
+                params![
+                    cookie.domain,
+                    cookie.name,
+                    cookie.value,
+                    cookie.path,
+                    cookie.expires,
+                    cookie.secure,
+                    cookie.http_only,
+                    cookie.same_site.as_str(),
+                    now
+                ],
+            ).map_err(|e| EngineError::StorageError(format!("Failed to set cookie: {}", e)))?;
+            Ok(())
+        }).await
     }
     
     /// Get cookies for a domain
     pub async fn get_cookies(&self, domain: &str) -> Result<Vec<Cookie>> {
-        let mut stmt = self.connection.prepare(
-            "SELECT domain, name, value, path, expires, secure, http_only, same_site FROM cookies WHERE domain = ?1 OR domain = ?2"
-        ).map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
-        
-        let domain_wildcard = format!(".{}", domain);
-        let rows = stmt.query_map(params![domain, domain_wildcard], |row| {
-            Ok(Cookie {
-                domain: row.get(0)?,
-                name: row.get(1)?,
-                value: row.get(2)?,
-                path: row.get(3)?,
-                expires: row.get(4)?,
-                secure: row.get(5)?,
-                http_only: row.get(6)?,
-                same_site: row.get(7)?,
-            })
-        }).map_err(|e| EngineError::StorageError(format!("Failed to query cookies: {}", e)))?;
-        
-        let mut cookies = Vec::new();
-        for row in rows {
-            cookies.push(row.map_err(|e| EngineError::StorageError(format!("Failed to parse cookie: {}", e)))?);
+        let domain = domain.to_string();
+        self.with_connection(move |connection| {
+            let mut stmt = connection.prepare(
+                "SELECT domain, name, value, path, expires, secure, http_only, same_site FROM cookies WHERE domain = ?1 OR domain = ?2"
+            ).map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
+            
+            let domain_wildcard = format!(".{}", domain);
+            let rows = stmt.query_map(params![domain, domain_wildcard], |row| {
+                Ok(Cookie {
+                    domain: row.get(0)?,
+                    name: row.get(1)?,
+                    value: row.get(2)?,
+                    path: row.get(3)?,
+                    expires: row.get(4)?,
+                    secure: row.get(5)?,
+                    http_only: row.get(6)?,
+                    same_site: SameSite::parse(&row.get::<_, String>(7)?).unwrap_or_default(),
+                })
+            }).map_err(|e| EngineError::StorageError(format!("Failed to query cookies: {}", e)))?;
+            
+            let mut cookies = Vec::new();
+            for row in rows {
+                cookies.push(row.map_err(|e| EngineError::StorageError(format!("Failed to parse cookie: {}", e)))?);
+            }
+            
+            Ok(cookies)
+        }).await
+    }
+    
+    /// Cookies to send with a request to `host` and `path`: host-only cookies for the exact
+    /// host, domain cookies for it and its parent domains, skipping expired cookies, Secure
+    /// ones on insecure requests, and all but `SameSite=None` ones on cross-site requests.
+    /// Longer paths come first, as RFC 6265 orders them.
+    pub async fn get_cookies_for_request(&self, host: &str, path: &str, secure: bool, cross_site: bool) -> Result<Vec<Cookie>> {
+        let host = host.to_ascii_lowercase();
+        let mut domains = vec![host.clone()];
+        let mut suffix = host.as_str();
+        loop {
+            domains.push(format!(".{}", suffix));
+            match suffix.split_once('.') {
+                Some((_, parent)) if !parent.is_empty() => suffix = parent,
+                _ => break,
+            }
         }
         
-        Ok(cookies)
+        let now = chrono::Utc::now().timestamp();
+        let path = path.to_string();
+        self.with_connection(move |connection| {
+            let mut cookies = Vec::new();
+            for domain in &domains {
+                for cookie in cookies_with_domain(connection, domain)? {
+                    let expired = cookie.expires.is_some_and(|expires| expires <= now);
+                    let same_site_allowed = !cross_site || cookie.same_site == SameSite::None;
+                    if !expired && (secure || !cookie.secure) && same_site_allowed && cookie_path_matches(&cookie.path, &path) {
+                        cookies.push(cookie);
+                    }
+                }
+            }
+            cookies.sort_by(|a, b| b.path.len().cmp(&a.path.len()));
+            
+            Ok(cookies)
+        }).await
+    }
+    
+    /// Remove a cookie, e.g. when a server expires it
+    pub async fn delete_cookie(&mut self, domain: &str, name: &str, path: &str) -> Result<()> {
+        let (domain, name, path) = (domain.to_string(), name.to_string(), path.to_string());
+        self.with_connection(move |connection| {
+            connection.execute(
+                "DELETE FROM cookies WHERE domain = ?1 AND name = ?2 AND path = ?3",
+                params![domain, name, path],
+            ).map_err(|e| EngineError::StorageError(format!("Failed to delete cookie: {}", e)))?;
+            Ok(())
+        }).await
     }
     
     /// Store local storage item
     pub async fn set_local_storage(&mut self, origin: &str, key: &str, value: &str) -> Result<()> {
         let now = chrono::Utc::now().timestamp();
         
-        self.connection.execute(
-            "INSERT OR REPLACE INTO local_storage (origin, key, value, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5)",
-            params![origin, key, value, now, now],
-        ).map_err(|e| EngineError::StorageError(format!("Failed to set local storage: {}", e)))?;
-        
-        Ok(())
+        let (origin, key, value) = (origin.to_string(), key.to_string(), value.to_string());
+        self.with_connection(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO local_storage (origin, key, value, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![origin, key, value, now, now],
+            ).map_err(|e| EngineError::StorageError(format!("Failed to set local storage: {}", e)))?;
+            Ok(())
+        }).await
     }
     
     /// Get local storage item
     pub async fn get_local_storage(&self, origin: &str, key: &str) -> Result<Option<String>> {
-        let mut stmt = self.connection.prepare("SELECT value FROM local_storage WHERE origin = ?1 AND key = ?2")
-            .map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
-        
-        let result = stmt.query_row(params![origin, key], |row| {
-            Ok(row.get::<_, String>(0)?)
-        });
-        
-        match result {
-            Ok(value) => Ok(Some(value)),
-            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-            Err(e) => Err(EngineError::StorageError(format!("Failed to get local storage: {}", e))),
-        }
+        let (origin, key) = (origin.to_string(), key.to_string());
+        self.with_connection(move |connection| {
+            let mut stmt = connection.prepare_cached("SELECT value FROM local_storage WHERE origin = ?1 AND key = ?2")
+                .map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
+            
+            let result = stmt.query_row(params![origin, key], |row| {
+                Ok(row.get::<_, String>(0)?)
+            });
+            
+            match result {
+                Ok(value) => Ok(Some(value)),
+                Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+                Err(e) => Err(EngineError::StorageError(format!("Failed to get local storage: {}", e))),
+            }
+        }).await
     }
     
-    /// Cache HTTP response
+    /// Cache HTTP response. Large bodies are stored compressed.
     pub async fn cache_response(&mut self, url: &str, headers: &str, body: &[u8], expires_at: i64) -> Result<()> {
         let now = chrono::Utc::now().timestamp();
         
-        self.connection.execute(
-            "INSERT OR REPLACE INTO cache_entries (url, headers, body, expires_at, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
-            params![url, headers, body, expires_at, now],
-        ).map_err(|e| EngineError::StorageError(format!("Failed to cache response: {}", e)))?;
-        
-        Ok(())
+        let (url, headers, body) = (url.to_string(), headers.to_string(), body.to_vec());
+        self.with_connection(move |connection| {
+            let (encoding, stored) = encode_cached_body(&body)?;
+            connection.execute(
+                "INSERT OR REPLACE INTO cache_entries (url, headers, body, expires_at, created_at, body_encoding) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![url, headers, stored, expires_at, now, encoding],
+            ).map_err(|e| EngineError::StorageError(format!("Failed to cache response: {}", e)))?;
+            Ok(())
+        }).await
     }
     
     /// Get cached response
     pub async fn get_cached_response(&self, url: &str) -> Result<Option<CachedResponse>> {
         let now = chrono::Utc::now().timestamp();
         
-        let mut stmt = self.connection.prepare(
-            "SELECT headers, body, expires_at FROM cache_entries WHERE url = ?1 AND expires_at > ?2"
-        ).map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
-        
-        let result = stmt.query_row(params![url, now], |row| {
-            Ok(CachedResponse {
-                headers: row.get(0)?,
-                body: row.get(1)?,
-                expires_at: row.get(2)?,
-            })
-        });
-        
-        match result {
-            Ok(response) => Ok(Some(response)),
-            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-            Err(e) => Err(EngineError::StorageError(format!("Failed to get cached response: {}", e))),
-        }
+        let url = url.to_string();
+        self.with_connection(move |connection| {
+            let mut stmt = connection.prepare_cached(
+                "SELECT headers, body, expires_at, body_encoding FROM cache_entries WHERE url = ?1 AND expires_at > ?2"
+            ).map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
+            
+            let result = stmt.query_row(params![url, now], |row| {
+                let encoding: String = row.get(3)?;
+                Ok((CachedResponse {
+                    headers: row.get(0)?,
+                    body: row.get(1)?,
+                    expires_at: row.get(2)?,
+                }, encoding))
+            });
+            
+            match result {
+                Ok((mut response, encoding)) => {
+                    response.body = decode_cached_body(&encoding, response.body)?;
+                    Ok(Some(response))
+                }
+                Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+                Err(e) => Err(EngineError::StorageError(format!("Failed to get cached response: {}", e))),
+            }
+        }).await
     }
     
     /// Add bookmark
@@ -293,87 +317,164 @@ impl StorageEngine {
         let tags_json = serde_json::to_string(&bookmark.tags)
             .map_err(|e| EngineError::StorageError(format!("Failed to serialize tags: {}", e)))?;
         
-        self.connection.execute(
-            r#"INSERT INTO bookmarks 
-               (id, title, url, description, favicon, tags, folder_id, created_at, updated_at) 
-               VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"#,
-            params![
-                bookmark.id,
-                bookmark.title,
-                bookmark.url,
-                bookmark.description,
-                bookmark.favicon,
-                tags_json,
-                bookmark.folder_id,
-                now,
-                now
-            ],
-        ).map_err(|e| EngineError::StorageError(format!("Failed to add bookmark: {}", e)))?;
-        
-        Ok(())
+        self.with_connection(move |connection| {
+            connection.execute(
+                r#"INSERT INTO bookmarks
+                   (id, title, url, description, favicon, tags, folder_id, created_at, updated_at)
+                   VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"#,
+                params![
+                    bookmark.id,
+                    bookmark.title,
+                    bookmark.url,
+                    bookmark.description,
+                    bookmark.favicon,
+                    tags_json,
+                    bookmark.folder_id,
+                    now,
+                    now
+                ],
+            ).map_err(|e| EngineError::StorageError(format!("Failed to add bookmark: {}", e)))?;
+            Ok(())
+        }).await
     }
     
-    /// Get all bookmarks
-    pub async fn get_bookmarks(&self) -> Result<Vec<Bookmark>> {
-        let mut stmt = self.connection.prepare(
-            "SELECT id, title, url, description, favicon, tags, folder_id FROM bookmarks ORDER BY created_at DESC"
-        ).map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
-        
-        let rows = stmt.query_map([], |row| {
-            let tags_json: String = row.get(5)?;
-            let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_default();
-            
-            Ok(Bookmark {
-                id: row.get(0)?,
-                title: row.get(1)?,
-                url: row.get(2)?,
-                description: row.get(3)?,
-                favicon: row.get(4)?,
-                tags,
-                folder_id: row.get(6)?,
-            })
-        }).map_err(|e| EngineError::StorageError(format!("Failed to query bookmarks: {}", e)))?;
-        
-        let mut bookmarks = Vec::new();
-        for row in rows {
-            bookmarks.push(row.map_err(|e| EngineError::StorageError(format!("Failed to parse bookmark: {}", e)))?);
-        }
+    /// Import bookmarks, e.g. from another browser, in one transaction. Bookmarks whose id
+    /// is already stored are skipped. Returns how many were added.
+    pub async fn import_bookmarks(&self, bookmarks: Vec<Bookmark>) -> Result<usize> {
+        let now = chrono::Utc::now().timestamp();
         
-        Ok(bookmarks)
+        self.with_connection(move |connection| {
+            let transaction = connection.transaction()
+                .map_err(|e| EngineError::StorageError(format!("Failed to begin transaction: {}", e)))?;
+            let added = insert_bookmarks(&transaction, &bookmarks, now)?;
+            transaction.commit()
+                .map_err(|e| EngineError::StorageError(format!("Failed to import bookmarks: {}", e)))?;
+            
+            Ok(added)
+        }).await
     }
     
-    /// Add history entry
-    pub async fn add_history(&mut self, url: &str, title: &str) -> Result<()> {
+    /// Add a bookmark folder; `parent_id` nests it inside another folder
+    pub async fn add_bookmark_folder(&self, folder: BookmarkFolder) -> Result<()> {
         let now = chrono::Utc::now().timestamp();
         
-        // Check if URL already exists
-        let mut stmt = self.connection.prepare("SELECT visit_count FROM history WHERE url = ?1")
-            .map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
+        self.with_connection(move |connection| {
+            insert_bookmark_folder(connection, &folder, now)
+        }).await
+    }
+    
+    /// Get all bookmark folders, oldest first
+    pub async fn get_bookmark_folders(&self) -> Result<Vec<BookmarkFolder>> {
+        self.with_connection(|connection| {
+            let mut stmt = connection.prepare(
+                "SELECT id, title, parent_id FROM bookmark_folders ORDER BY created_at, rowid"
+            ).map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
+            
+            let rows = stmt.query_map([], |row| {
+                Ok(BookmarkFolder {
+                    id: row.get(0)?,
+                    title: row.get(1)?,
+                    parent_id: row.get(2)?,
+                })
+            }).map_err(|e| EngineError::StorageError(format!("Failed to query bookmark folders: {}", e)))?;
+            
+            let mut folders = Vec::new();
+            for row in rows {
+                folders.push(row.map_err(|e| EngineError::StorageError(format!("Failed to parse bookmark folder: {}", e)))?);
+            }
+            
+            Ok(folders)
+        }).await
+    }
+    
+    /// Export bookmarks and their folders as a Netscape bookmark file, the format every
+    /// major browser imports
+    pub async fn export_bookmarks_html(&self) -> Result<String> {
+        let folders = self.get_bookmark_folders().await?;
+        let mut bookmarks = self.get_bookmarks().await?;
+        bookmarks.reverse();
         
-        let existing_count = stmt.query_row(params![url], |row| {
-            Ok(row.get::<_, i32>(0)?)
-        });
+        Ok(render_netscape_bookmarks(&folders, &bookmarks))
+    }
+    
+    /// Import a Netscape bookmark file, recreating its folders under fresh ids. Entries that
+    /// can't be read, such as links without an address, are skipped. Returns how many
+    /// bookmarks were added.
+    pub async fn import_bookmarks_html(&self, html: &str) -> Result<usize> {
+        let document = HTMLParser::new().parse(html)?;
+        let mut parsed = NetscapeBookmarks::default();
+        parsed.read_list(&document, &document.root, None);
+        let now = chrono::Utc::now().timestamp();
         
-        match existing_count {
-            Ok(count) => {
-                // Update existing entry
-                self.connection.execute(
-                    "UPDATE history SET title = ?1, visit_count = ?2, last_visit = ?3 WHERE url = ?4",
-                    params![title, count + 1, now, url],
-                ).map_err(|e| EngineError::StorageError(format!("Failed to update history: {}", e)))?;
+        self.with_connection(move |connection| {
+            let transaction = connection.transaction()
+                .map_err(|e| EngineError::StorageError(format!("Failed to begin transaction: {}", e)))?;
+            for folder in &parsed.folders {
+                insert_bookmark_folder(&transaction, folder, now)?;
             }
-            Err(rusqlite::Error::QueryReturnedNoRows) => {
-                // Insert new entry
-                let id = uuid::Uuid::new_v4().to_string();
-                self.connection.execute(
-                    "INSERT INTO history (id, url, title, visit_count, last_visit, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-                    params![id, url, title, 1, now, now],
-                ).map_err(|e| EngineError::StorageError(format!("Failed to insert history: {}", e)))?;
+            let added = insert_bookmarks(&transaction, &parsed.bookmarks, now)?;
+            transaction.commit()
+                .map_err(|e| EngineError::StorageError(format!("Failed to import bookmarks: {}", e)))?;
+            
+            Ok(added)
+        }).await
+    }
+    
+    /// Get all bookmarks
+    pub async fn get_bookmarks(&self) -> Result<Vec<Bookmark>> {
+        self.with_connection(|connection| {
+            let mut stmt = connection.prepare(
+                "SELECT id, title, url, description, favicon, tags, folder_id FROM bookmarks ORDER BY created_at DESC"
+            ).map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
+            
+            let rows = stmt.query_map([], |row| {
+                let tags_json: String = row.get(5)?;
+                let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_default();
+                
+                Ok(Bookmark {
+                    id: row.get(0)?,
+                    title: row.get(1)?,
+                    url: row.get(2)?,
+                    description: row.get(3)?,
+                    favicon: row.get(4)?,
+                    tags,
+                    folder_id: row.get(6)?,
+                })
+            }).map_err(|e| EngineError::StorageError(format!("Failed to query bookmarks: {}", e)))?;
+            
+            let mut bookmarks = Vec::new();
+            for row in rows {
+                bookmarks.push(row.map_err(|e| EngineError::StorageError(format!("Failed to parse bookmark: {}", e)))?);
             }
-            Err(e) => return Err(EngineError::StorageError(format!("Failed to query history: {}", e))),
-        }
+            
+            Ok(bookmarks)
+        }).await
+    }
+    
+    /// Add history entry
+    pub async fn add_history(&self, url: &str, title: &str) -> Result<()> {
+        let now = chrono::Utc::now().timestamp();
         
-        Ok(())
+        let (url, title) = (url.to_string(), title.to_string());
+        self.with_connection(move |connection| {
+            record_visits(connection, None, &url, &title, 1, now)
+        }).await
+    }
+    
+    /// Import history, e.g. from another browser, in one transaction. Entries for URLs
+    /// already in history add their visits to the existing entry. Returns how many were imported.
+    pub async fn import_history(&self, entries: Vec<HistoryEntry>) -> Result<usize> {
+        self.with_connection(move |connection| {
+            let transaction = connection.transaction()
+                .map_err(|e| EngineError::StorageError(format!("Failed to begin transaction: {}", e)))?;
+            for entry in &entries {
+                record_visits(&transaction, Some(&entry.id), &entry.url, &entry.title, entry.visit_count, entry.last_visit)?;
+            }
+            transaction.commit()
+                .map_err(|e| EngineError::StorageError(format!("Failed to import history: {}", e)))?;
+            
+            Ok(entries.len())
+        }).await
     }
     
     /// Get history entries
@@ -384,81 +485,345 @@ impl StorageEngine {
             "SELECT id, url, title, visit_count, last_visit FROM history ORDER BY last_visit DESC".to_string()
         };
         
-        let mut stmt = self.connection.prepare(&query)
-            .map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
-        
-        let rows = stmt.query_map([], |row| {
-            Ok(HistoryEntry {
-                id: row.get(0)?,
-                url: row.get(1)?,
-                title: row.get(2)?,
-                visit_count: row.get(3)?,
-                last_visit: row.get(4)?,
-            })
-        }).map_err(|e| EngineError::StorageError(format!("Failed to query history: {}", e)))?;
+        self.with_connection(move |connection| {
+            let mut stmt = connection.prepare(&query)
+                .map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
+            
+            let rows = stmt.query_map([], |row| {
+                Ok(HistoryEntry {
+                    id: row.get(0)?,
+                    url: row.get(1)?,
+                    title: row.get(2)?,
+                    visit_count: row.get(3)?,
+                    last_visit: row.get(4)?,
+                })
+            }).map_err(|e| EngineError::StorageError(format!("Failed to query history: {}", e)))?;
+            
+            let mut history = Vec::new();
+            for row in rows {
+                history.push(row.map_err(|e| EngineError::StorageError(format!("Failed to parse history: {}", e)))?);
+            }
+            
+            Ok(history)
+        }).await
+    }
+    
+    /// Insert or update a download record
+    pub async fn save_download(&self, download: &DownloadRecord) -> Result<()> {
+        let download = download.clone();
+        self.with_connection(move |connection| {
+            connection.execute(
+                r#"INSERT OR REPLACE INTO downloads
+                   (id, url, filename, path, status, progress, total_bytes, downloaded_bytes, created_at, completed_at)
+                   VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)"#,
+                params![
+                    download.id,
+                    download.url,
+                    download.filename,
+                    download.path,
+                    download.status.as_str(),
+                    download.progress(),
+                    download.total_bytes.unwrap_or(0) as i64,
+                    download.downloaded_bytes as i64,
+                    download.created_at,
+                    download.completed_at
+                ],
+            ).map_err(|e| EngineError::StorageError(format!("Failed to save download: {}", e)))?;
+            Ok(())
+        }).await
+    }
+    
+    /// Record a download's progress and status; finishing it as completed stamps `completed_at`
+    pub async fn update_download_progress(
+        &self,
+        id: &str,
+        downloaded_bytes: u64,
+        total_bytes: Option<u64>,
+        status: DownloadStatus,
+    ) -> Result<()> {
+        let progress = match total_bytes {
+            Some(total) if total > 0 => (downloaded_bytes as f64 / total as f64).min(1.0),
+            _ => 0.0,
+        };
+        let completed_at = (status == DownloadStatus::Completed).then(|| chrono::Utc::now().timestamp());
         
-        let mut history = Vec::new();
-        for row in rows {
-            history.push(row.map_err(|e| EngineError::StorageError(format!("Failed to parse history: {}", e)))?);
-        }
+        let id = id.to_string();
+        self.with_connection(move |connection| {
+            let updated = connection.execute(
+                r#"UPDATE downloads
+                   SET downloaded_bytes = ?2, total_bytes = ?3, progress = ?4, status = ?5,
+                       completed_at = COALESCE(completed_at, ?6)
+                   WHERE id = ?1"#,
+                params![id, downloaded_bytes as i64, total_bytes.unwrap_or(0) as i64, progress, status.as_str(), completed_at],
+            ).map_err(|e| EngineError::StorageError(format!("Failed to update download: {}", e)))?;
+            
+            if updated == 0 {
+                return Err(EngineError::StorageError(format!("Unknown download {}", id)));
+            }
+            Ok(())
+        }).await
+    }
+    
+    /// Get all downloads, newest first
+    pub async fn list_downloads(&self) -> Result<Vec<DownloadRecord>> {
+        self.with_connection(|connection| {
+            let mut stmt = connection.prepare(
+                r#"SELECT id, url, filename, path, status, total_bytes, downloaded_bytes, created_at, completed_at
+                   FROM downloads ORDER BY created_at DESC"#
+            ).map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
+            
+            let rows = stmt.query_map([], download_from_row)
+                .map_err(|e| EngineError::StorageError(format!("Failed to query downloads: {}", e)))?;
+            
+            let mut downloads = Vec::new();
+            for row in rows {
+                downloads.push(row.map_err(|e| EngineError::StorageError(format!("Failed to parse download: {}", e)))?);
+            }
+            
+            Ok(downloads)
+        }).await
+    }
+    
+    /// Get a single download
+    pub async fn download_status(&self, id: &str) -> Result<Option<DownloadRecord>> {
+        let id = id.to_string();
+        self.with_connection(move |connection| {
+            let mut stmt = connection.prepare(
+                r#"SELECT id, url, filename, path, status, total_bytes, downloaded_bytes, created_at, completed_at
+                   FROM downloads WHERE id = ?1"#
+            ).map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
+            
+            match stmt.query_row(params![id], download_from_row) {
+                Ok(download) => Ok(Some(download)),
+                Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+                Err(e) => Err(EngineError::StorageError(format!("Failed to get download: {}", e))),
+            }
+        }).await
+    }
+    
+    /// Cache the favicon for an origin until `expires_at`, replacing any previous one
+    pub async fn set_favicon(&self, origin: &str, url: &str, data: &[u8], expires_at: i64) -> Result<()> {
+        let now = chrono::Utc::now().timestamp();
         
-        Ok(history)
+        let (origin, url, data) = (origin.to_string(), url.to_string(), data.to_vec());
+        self.with_connection(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO favicons (origin, url, data, expires_at, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![origin, url, data, expires_at, now],
+            ).map_err(|e| EngineError::StorageError(format!("Failed to cache favicon: {}", e)))?;
+            Ok(())
+        }).await
     }
     
-    /// Clear expired cache entries
-    pub async fn cleanup_cache(&mut self) -> Result<()> {
+    /// The cached favicon for an origin, if it hasn't expired
+    pub async fn get_favicon(&self, origin: &str) -> Result<Option<Vec<u8>>> {
         let now = chrono::Utc::now().timestamp();
         
-        self.connection.execute(
-            "DELETE FROM cache_entries WHERE expires_at <= ?1",
-            params![now],
-        ).map_err(|e| EngineError::StorageError(format!("Failed to cleanup cache: {}", e)))?;
+        let origin = origin.to_string();
+        self.with_connection(move |connection| {
+            let result = connection.query_row(
+                "SELECT data FROM favicons WHERE origin = ?1 AND expires_at > ?2",
+                params![origin, now],
+                |row| row.get::<_, Vec<u8>>(0),
+            );
+            
+            match result {
+                Ok(data) => Ok(Some(data)),
+                Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+                Err(e) => Err(EngineError::StorageError(format!("Failed to get favicon: {}", e))),
+            }
+        }).await
+    }
+    
+    /// Remember whether `origin` was granted or denied a permission such as "geolocation"
+    pub async fn set_permission(&self, origin: &str, permission: &str, granted: bool) -> Result<()> {
+        let now = chrono::Utc::now().timestamp();
         
-        Ok(())
+        let (origin, permission) = (origin.to_string(), permission.to_string());
+        self.with_connection(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO permissions (origin, permission, granted, updated_at) VALUES (?1, ?2, ?3, ?4)",
+                params![origin, permission, granted, now],
+            ).map_err(|e| EngineError::StorageError(format!("Failed to set permission: {}", e)))?;
+            Ok(())
+        }).await
     }
     
-    /// Get storage statistics
-    pub async fn get_storage_stats(&self) -> Result<StorageStats> {
-        let mut stats = StorageStats::default();
+    /// Every recorded permission decision as (origin, permission, granted)
+    pub async fn list_permissions(&self) -> Result<Vec<(String, String, bool)>> {
+        self.with_connection(|connection| {
+            let mut stmt = connection.prepare(
+                "SELECT origin, permission, granted FROM permissions ORDER BY origin, permission"
+            ).map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
+            
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .map_err(|e| EngineError::StorageError(format!("Failed to query permissions: {}", e)))?;
+            
+            let mut permissions = Vec::new();
+            for row in rows {
+                permissions.push(row.map_err(|e| EngineError::StorageError(format!("Failed to parse permission: {}", e)))?);
+            }
+            
+            Ok(permissions)
+        }).await
+    }
+    
+    /// Remember that `host` requires HTTPS until the Unix time `expires_at`
+    pub async fn set_hsts(&self, host: &str, expires_at: i64, include_subdomains: bool) -> Result<()> {
+        let now = chrono::Utc::now().timestamp();
         
-        // Get table sizes
-        let tables = vec![
-            "settings", "cookies", "local_storage", "session_storage",
-            "cache_entries", "downloads", "bookmarks", "history"
-        ];
+        let host = host.to_ascii_lowercase();
+        self.with_connection(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO hsts (host, expires_at, include_subdomains, updated_at) VALUES (?1, ?2, ?3, ?4)",
+                params![host, expires_at, include_subdomains, now],
+            ).map_err(|e| EngineError::StorageError(format!("Failed to set HSTS entry: {}", e)))?;
+            Ok(())
+        }).await
+    }
+    
+    /// Forget a host's HSTS entry, as a `max-age=0` header asks
+    pub async fn delete_hsts(&self, host: &str) -> Result<()> {
+        let host = host.to_ascii_lowercase();
+        self.with_connection(move |connection| {
+            connection.execute(
+                "DELETE FROM hsts WHERE host = ?1",
+                params![host],
+            ).map_err(|e| EngineError::StorageError(format!("Failed to delete HSTS entry: {}", e)))?;
+            Ok(())
+        }).await
+    }
+    
+    /// Whether requests to `host` must use HTTPS: it has a live entry of its own, or a
+    /// parent domain has one with includeSubDomains
+    pub async fn is_hsts_host(&self, host: &str) -> Result<bool> {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        let now = chrono::Utc::now().timestamp();
+        self.with_connection(move |connection| {
+            let mut stmt = connection.prepare_cached(
+                "SELECT include_subdomains FROM hsts WHERE host = ?1 AND expires_at > ?2"
+            ).map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
+            
+            let mut candidate = host.as_str();
+            let mut is_superdomain = false;
+            loop {
+                let include_subdomains: Option<bool> = stmt.query_row(params![candidate, now], |row| row.get(0))
+                    .optional()
+                    .map_err(|e| EngineError::StorageError(format!("Failed to query HSTS entry: {}", e)))?;
+                match include_subdomains {
+                    Some(include_subdomains) if include_subdomains || !is_superdomain => return Ok(true),
+                    _ => {}
+                }
+                match candidate.split_once('.') {
+                    Some((_, parent)) if !parent.is_empty() => {
+                        candidate = parent;
+                        is_superdomain = true;
+                    }
+                    _ => return Ok(false),
+                }
+            }
+        }).await
+    }
+    
+    /// Store the user's autofill value for a field category such as "email" or "city"
+    pub async fn set_autofill_value(&mut self, category: &str, value: &str) -> Result<()> {
+        let now = chrono::Utc::now().timestamp();
         
-        for table in tables {
-            let mut stmt = self.connection.prepare(&format!("SELECT COUNT(*) FROM {}", table))
+        let (category, value) = (category.to_string(), value.to_string());
+        self.with_connection(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO autofill_profile (category, value, updated_at) VALUES (?1, ?2, ?3)",
+                params![category, value, now],
+            ).map_err(|e| EngineError::StorageError(format!("Failed to set autofill value: {}", e)))?;
+            Ok(())
+        }).await
+    }
+    
+    /// The stored autofill profile, keyed by field category; empty if none was saved
+    pub async fn get_autofill_profile(&self) -> Result<HashMap<String, String>> {
+        self.with_connection(|connection| {
+            let mut stmt = connection.prepare("SELECT category, value FROM autofill_profile")
                 .map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
             
-            let count: i64 = stmt.query_row([], |row| Ok(row.get(0)?))
-                .map_err(|e| EngineError::StorageError(format!("Failed to get count: {}", e)))?;
-            
-            match table {
-                "settings" => stats.settings_count = count as u64,
-                "cookies" => stats.cookies_count = count as u64,
-                "local_storage" => stats.local_storage_count = count as u64,
-                "cache_entries" => stats.cache_entries_count = count as u64,
-                "bookmarks" => stats.bookmarks_count = count as u64,
-                "history" => stats.history_count = count as u64,
-                _ => {}
+            let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+                .map_err(|e| EngineError::StorageError(format!("Failed to query autofill profile: {}", e)))?;
+            
+            let mut profile = HashMap::new();
+            for row in rows {
+                let (category, value) = row.map_err(|e| EngineError::StorageError(format!("Failed to parse autofill value: {}", e)))?;
+                profile.insert(category, value);
             }
-        }
-        
-        // Get database size
-        let mut stmt = self.connection.prepare("PRAGMA page_count")
-            .map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
-        let page_count: i64 = stmt.query_row([], |row| Ok(row.get(0)?))
-            .map_err(|e| EngineError::StorageError(format!("Failed to get page count: {}", e)))?;
-        
-        let mut stmt = self.connection.prepare("PRAGMA page_size")
-            .map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
-        let page_size: i64 = stmt.query_row([], |row| Ok(row.get(0)?))
-            .map_err(|e| EngineError::StorageError(format!("Failed to get page size: {}", e)))?;
-        
-        stats.database_size_bytes = (page_count * page_size) as u64;
+            
+            Ok(profile)
+        }).await
+    }
+    
+    /// Delete the stored autofill profile
+    pub async fn clear_autofill_profile(&mut self) -> Result<()> {
+        self.with_connection(|connection| {
+            connection.execute("DELETE FROM autofill_profile", [])
+                .map_err(|e| EngineError::StorageError(format!("Failed to clear autofill profile: {}", e)))?;
+            Ok(())
+        }).await
+    }
+    
+    /// Clear expired cache entries
+    pub async fn cleanup_cache(&mut self) -> Result<()> {
+        let now = chrono::Utc::now().timestamp();
         
-        Ok(stats)
+        self.with_connection(move |connection| {
+            connection.execute(
+                "DELETE FROM cache_entries WHERE expires_at <= ?1",
+                params![now],
+            ).map_err(|e| EngineError::StorageError(format!("Failed to cleanup cache: {}", e)))?;
+            Ok(())
+        }).await
+    }
+    
+    /// Get storage statistics
+    pub async fn get_storage_stats(&self) -> Result<StorageStats> {
+        self.with_connection(|connection| {
+            let mut stats = StorageStats::default();
+            
+            // Get table sizes
+            let tables = vec![
+                "settings", "cookies", "local_storage", "session_storage",
+                "cache_entries", "downloads", "bookmarks", "history"
+            ];
+            
+            for table in tables {
+                let mut stmt = connection.prepare(&format!("SELECT COUNT(*) FROM {}", table))
+                    .map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
+                
+                let count: i64 = stmt.query_row([], |row| Ok(row.get(0)?))
+                    .map_err(|e| EngineError::StorageError(format!("Failed to get count: {}", e)))?;
+                
+                match table {
+                    "settings" => stats.settings_count = count as u64,
+                    "cookies" => stats.cookies_count = count as u64,
+                    "local_storage" => stats.local_storage_count = count as u64,
+                    "cache_entries" => stats.cache_entries_count = count as u64,
+                    "bookmarks" => stats.bookmarks_count = count as u64,
+                    "history" => stats.history_count = count as u64,
+                    _ => {}
+                }
+            }
+            
+            // Get database size
+            let mut stmt = connection.prepare("PRAGMA page_count")
+                .map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
+            let page_count: i64 = stmt.query_row([], |row| Ok(row.get(0)?))
+                .map_err(|e| EngineError::StorageError(format!("Failed to get page count: {}", e)))?;
+            
+            let mut stmt = connection.prepare("PRAGMA page_size")
+                .map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
+            let page_size: i64 = stmt.query_row([], |row| Ok(row.get(0)?))
+                .map_err(|e| EngineError::StorageError(format!("Failed to get page size: {}", e)))?;
+            
+            stats.database_size_bytes = (page_count * page_size) as u64;
+            
+            Ok(stats)
+        }).await
     }
     
     /// Shutdown storage engine
@@ -468,6 +833,407 @@ impl StorageEngine {
     }
 }
 
+/// Initialize database schema
+fn initialize_schema(connection: &Connection) -> Result<()> {
+    // Create tables
+    connection.execute_batch(r#"
+        CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+        
+        CREATE TABLE IF NOT EXISTS cookies (
+            domain TEXT NOT NULL,
+            name TEXT NOT NULL,
+            value TEXT NOT NULL,
+            path TEXT NOT NULL DEFAULT '/',
+            expires INTEGER,
+            secure BOOLEAN NOT NULL DEFAULT 0,
+            http_only BOOLEAN NOT NULL DEFAULT 0,
+            same_site TEXT DEFAULT 'Lax',
+            created_at INTEGER NOT NULL,
+            PRIMARY KEY (domain, name, path)
+        );
+        
+        CREATE TABLE IF NOT EXISTS local_storage (
+            origin TEXT NOT NULL,
+            key TEXT NOT NULL,
+            value TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL,
+            PRIMARY KEY (origin, key)
+        );
+        
+        CREATE TABLE IF NOT EXISTS session_storage (
+            session_id TEXT NOT NULL,
+            origin TEXT NOT NULL,
+            key TEXT NOT NULL,
+            value TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            PRIMARY KEY (session_id, origin, key)
+        );
+        
+        CREATE TABLE IF NOT EXISTS cache_entries (
+            url TEXT PRIMARY KEY,
+            headers TEXT NOT NULL,
+            body BLOB NOT NULL,
+            expires_at INTEGER NOT NULL,
+            created_at INTEGER NOT NULL,
+            body_encoding TEXT NOT NULL DEFAULT 'identity'
+        );
+        
+        CREATE TABLE IF NOT EXISTS downloads (
+            id TEXT PRIMARY KEY,
+            url TEXT NOT NULL,
+            filename TEXT NOT NULL,
+            path TEXT NOT NULL,
+            status TEXT NOT NULL,
+            progress REAL NOT NULL DEFAULT 0.0,
+            total_bytes INTEGER NOT NULL DEFAULT 0,
+            downloaded_bytes INTEGER NOT NULL DEFAULT 0,
+            created_at INTEGER NOT NULL,
+            completed_at INTEGER
+        );
+        
+        CREATE TABLE IF NOT EXISTS bookmarks (
+            id TEXT PRIMARY KEY,
+            title TEXT NOT NULL,
+            url TEXT NOT NULL,
+            description TEXT,
+            favicon TEXT,
+            tags TEXT,
+            folder_id TEXT,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+        
+        CREATE TABLE IF NOT EXISTS bookmark_folders (
+            id TEXT PRIMARY KEY,
+            title TEXT NOT NULL,
+            parent_id TEXT,
+            created_at INTEGER NOT NULL
+        );
+        
+        CREATE TABLE IF NOT EXISTS history (
+            id TEXT PRIMARY KEY,
+            url TEXT NOT NULL,
+            title TEXT NOT NULL,
+            visit_count INTEGER NOT NULL DEFAULT 1,
+            last_visit INTEGER NOT NULL,
+            created_at INTEGER NOT NULL
+        );
+        
+        CREATE TABLE IF NOT EXISTS favicons (
+            origin TEXT PRIMARY KEY,
+            url TEXT NOT NULL,
+            data BLOB NOT NULL,
+            expires_at INTEGER NOT NULL,
+            created_at INTEGER NOT NULL
+        );
+        
+        CREATE TABLE IF NOT EXISTS permissions (
+            origin TEXT NOT NULL,
+            permission TEXT NOT NULL,
+            granted INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL,
+            PRIMARY KEY (origin, permission)
+        );
+        
+        CREATE TABLE IF NOT EXISTS hsts (
+            host TEXT PRIMARY KEY,
+            expires_at INTEGER NOT NULL,
+            include_subdomains INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+        
+        CREATE TABLE IF NOT EXISTS autofill_profile (
+            category TEXT PRIMARY KEY,
+            value TEXT NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+        
+        CREATE INDEX IF NOT EXISTS idx_cookies_domain ON cookies(domain);
+        CREATE INDEX IF NOT EXISTS idx_local_storage_origin ON local_storage(origin);
+        CREATE INDEX IF NOT EXISTS idx_cache_expires ON cache_entries(expires_at);
+        CREATE INDEX IF NOT EXISTS idx_history_url ON history(url);
+        CREATE INDEX IF NOT EXISTS idx_history_last_visit ON history(last_visit);
+    "#).map_err(|e| EngineError::StorageError(format!("Failed to initialize schema: {}", e)))?;
+    
+    // Databases created before cached bodies were compressed; their rows stay uncompressed
+    add_column_if_missing(connection, "cache_entries", "body_encoding", "TEXT NOT NULL DEFAULT 'identity'")?;
+    
+    Ok(())
+}
+
+/// Add a column to a table created by an older schema
+fn add_column_if_missing(connection: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+    let exists: bool = connection.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+        params![table, column],
+        |row| row.get(0),
+    ).map_err(|e| EngineError::StorageError(format!("Failed to inspect schema: {}", e)))?;
+    
+    if !exists {
+        connection.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+            .map_err(|e| EngineError::StorageError(format!("Failed to migrate schema: {}", e)))?;
+    }
+    
+    Ok(())
+}
+
+/// Add `visit_count` visits to the history entry for `url`, creating it (with `id` if given)
+/// when there is none. The title follows the most recent visit.
+fn record_visits(connection: &Connection, id: Option<&str>, url: &str, title: &str, visit_count: i32, last_visit: i64) -> Result<()> {
+    let updated = connection.prepare_cached(
+        r#"UPDATE history
+           SET title = CASE WHEN ?3 >= last_visit THEN ?2 ELSE title END,
+               visit_count = visit_count + ?4, last_visit = MAX(last_visit, ?3)
+           WHERE url = ?1"#
+    ).and_then(|mut stmt| stmt.execute(params![url, title, last_visit, visit_count]))
+        .map_err(|e| EngineError::StorageError(format!("Failed to update history: {}", e)))?;
+    
+    if updated == 0 {
+        let id = id.map_or_else(|| uuid::Uuid::new_v4().to_string(), str::to_string);
+        let now = chrono::Utc::now().timestamp();
+        connection.prepare_cached(
+            "INSERT INTO history (id, url, title, visit_count, last_visit, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)"
+        ).and_then(|mut stmt| stmt.execute(params![id, url, title, visit_count, last_visit, now]))
+            .map_err(|e| EngineError::StorageError(format!("Failed to insert history: {}", e)))?;
+    }
+    
+    Ok(())
+}
+
+/// Insert bookmarks with a reused statement, skipping ids already stored
+fn insert_bookmarks(connection: &Connection, bookmarks: &[Bookmark], now: i64) -> Result<usize> {
+    let mut stmt = connection.prepare_cached(
+        r#"INSERT OR IGNORE INTO bookmarks
+           (id, title, url, description, favicon, tags, folder_id, created_at, updated_at)
+           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"#
+    ).map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
+    
+    let mut added = 0;
+    for bookmark in bookmarks {
+        let tags_json = serde_json::to_string(&bookmark.tags)
+            .map_err(|e| EngineError::StorageError(format!("Failed to serialize tags: {}", e)))?;
+        added += stmt.execute(params![
+            bookmark.id,
+            bookmark.title,
+            bookmark.url,
+            bookmark.description,
+            bookmark.favicon,
+            tags_json,
+            bookmark.folder_id,
+            now,
+            now
+        ]).map_err(|e| EngineError::StorageError(format!("Failed to add bookmark: {}", e)))?;
+    }
+    
+    Ok(added)
+}
+
+fn insert_bookmark_folder(connection: &Connection, folder: &BookmarkFolder, now: i64) -> Result<()> {
+    connection.prepare_cached(
+        "INSERT OR REPLACE INTO bookmark_folders (id, title, parent_id, created_at) VALUES (?1, ?2, ?3, ?4)"
+    ).and_then(|mut stmt| stmt.execute(params![folder.id, folder.title, folder.parent_id, now]))
+        .map_err(|e| EngineError::StorageError(format!("Failed to add bookmark folder: {}", e)))?;
+    
+    Ok(())
+}
+
+/// A Netscape bookmark file: nested `<DL>` lists whose `<DT>` entries hold either an
+/// `<H3>` folder title followed by the folder's own list, or an `<A>` link, optionally
+/// followed by a `<DD>` description
+fn render_netscape_bookmarks(folders: &[BookmarkFolder], bookmarks: &[Bookmark]) -> String {
+    let mut output = String::from(
+        "<!DOCTYPE NETSCAPE-Bookmark-file-1>\n\
+         <!-- This is an automatically generated file.\n     It will be read and overwritten.\n     DO NOT EDIT! -->\n\
+         <META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">\n\
+         <TITLE>Bookmarks</TITLE>\n\
+         <H1>Bookmarks</H1>\n"
+    );
+    
+    let known: HashSet<&str> = folders.iter().map(|folder| folder.id.as_str()).collect();
+    render_netscape_list(None, folders, bookmarks, &known, 0, &mut output);
+    output
+}
+
+/// The folder an entry is listed under: folders whose parent is missing go at the top
+/// level, as do bookmarks filed in unknown folders
+fn listed_under<'a>(parent: &'a Option<String>, known: &HashSet<&str>) -> Option<&'a str> {
+    parent.as_deref().filter(|id| known.contains(id))
+}
+
+fn render_netscape_list(
+    folder_id: Option<&str>,
+    folders: &[BookmarkFolder],
+    bookmarks: &[Bookmark],
+    known: &HashSet<&str>,
+    depth: usize,
+    output: &mut String,
+) {
+    let indent = "    ".repeat(depth);
+    output.push_str(&indent);
+    output.push_str("<DL><p>\n");
+    
+    for folder in folders.iter().filter(|folder| listed_under(&folder.parent_id, known) == folder_id) {
+        output.push_str(&indent);
+        output.push_str("    <DT><H3>");
+        escape_html(&folder.title, false, output);
+        output.push_str("</H3>\n");
+        render_netscape_list(Some(&folder.id), folders, bookmarks, known, depth + 1, output);
+    }
+    
+    for bookmark in bookmarks.iter().filter(|bookmark| listed_under(&bookmark.folder_id, known) == folder_id) {
+        output.push_str(&indent);
+        output.push_str("    <DT><A HREF=\"");
+        escape_html(&bookmark.url, true, output);
+        output.push('"');
+        if !bookmark.tags.is_empty() {
+            output.push_str(" TAGS=\"");
+            escape_html(&bookmark.tags.join(","), true, output);
+            output.push('"');
+        }
+        if let Some(favicon) = &bookmark.favicon {
+            output.push_str(" ICON=\"");
+            escape_html(favicon, true, output);
+            output.push('"');
+        }
+        output.push('>');
+        escape_html(&bookmark.title, false, output);
+        output.push_str("</A>\n");
+        if let Some(description) = bookmark.description.as_deref().filter(|description| !description.is_empty()) {
+            output.push_str(&indent);
+            output.push_str("    <DD>");
+            escape_html(description, false, output);
+            output.push('\n');
+        }
+    }
+    
+    output.push_str(&indent);
+    output.push_str("</DL><p>\n");
+}
+
+/// Folders and bookmarks read from a Netscape bookmark file, under fresh ids
+#[derive(Default)]
+struct NetscapeBookmarks {
+    folders: Vec<BookmarkFolder>,
+    bookmarks: Vec<Bookmark>,
+}
+
+impl NetscapeBookmarks {
+    /// Read the entries among `list`'s children into `folder_id`. The HTML parser nests a
+    /// folder's `<DL>` inside its `<DT>`, but a list right after the `<DT>` counts too.
+    fn read_list(&mut self, document: &Document, list: &Element, folder_id: Option<&str>) {
+        let mut open_folder: Option<String> = None;
+        let mut last_bookmark: Option<usize> = None;
+        
+        for child in list.children.iter().filter_map(|id| document.elements.get(id)) {
+            match child.tag_name.as_str() {
+                "dt" => {
+                    open_folder = None;
+                    last_bookmark = None;
+                    let children: Vec<&Arc<Element>> = child.children.iter()
+                        .filter_map(|id| document.elements.get(id))
+                        .collect();
+                    
+                    if let Some(heading) = children.iter().find(|element| element.tag_name == "h3") {
+                        let id = uuid::Uuid::new_v4().to_string();
+                        self.folders.push(BookmarkFolder {
+                            id: id.clone(),
+                            title: heading.text_content.trim().to_string(),
+                            parent_id: folder_id.map(str::to_string),
+                        });
+                        let mut has_list = false;
+                        for nested in children.iter().filter(|element| element.tag_name == "dl") {
+                            self.read_list(document, nested, Some(&id));
+                            has_list = true;
+                        }
+                        if !has_list {
+                            open_folder = Some(id);
+                        }
+                    } else {
+                        let link = children.iter().find(|element| element.tag_name == "a");
+                        if let Some(bookmark) = link.and_then(|link| read_link(link, folder_id)) {
+                            self.bookmarks.push(bookmark);
+                            last_bookmark = Some(self.bookmarks.len() - 1);
+                        }
+                        // A stray list inside a link entry stays in the current folder
+                        for nested in children.iter().filter(|element| element.tag_name == "dl") {
+                            self.read_list(document, nested, folder_id);
+                        }
+                    }
+                }
+                "dd" => {
+                    let description = child.text_content.trim();
+                    if let Some(index) = last_bookmark.take().filter(|_| !description.is_empty()) {
+                        self.bookmarks[index].description = Some(description.to_string());
+                    }
+                }
+                "dl" => {
+                    let nested_folder = open_folder.take();
+                    self.read_list(document, child, nested_folder.as_deref().or(folder_id));
+                }
+                _ => self.read_list(document, child, folder_id),
+            }
+        }
+    }
+}
+
+/// The bookmark an `<A>` entry links to; `None` without an address
+fn read_link(link: &Element, folder_id: Option<&str>) -> Option<Bookmark> {
+    let url = link.get_attribute("href").map(str::trim).filter(|url| !url.is_empty())?;
+    let tags = link.get_attribute("tags")
+        .map(|tags| tags.split(',').map(str::trim).filter(|tag| !tag.is_empty()).map(str::to_string).collect())
+        .unwrap_or_default();
+    
+    Some(Bookmark {
+        id: uuid::Uuid::new_v4().to_string(),
+        title: link.text_content.trim().to_string(),
+        url: url.to_string(),
+        description: None,
+        favicon: link.get_attribute("icon").map(str::to_string),
+        tags,
+        folder_id: folder_id.map(str::to_string),
+    })
+}
+
+/// Cookies stored under exactly `domain`
+fn cookies_with_domain(connection: &Connection, domain: &str) -> Result<Vec<Cookie>> {
+    let mut stmt = connection.prepare_cached(
+        "SELECT domain, name, value, path, expires, secure, http_only, same_site FROM cookies WHERE domain = ?1"
+    ).map_err(|e| EngineError::StorageError(format!("Failed to prepare statement: {}", e)))?;
+    
+    let rows = stmt.query_map(params![domain], |row| {
+        Ok(Cookie {
+            domain: row.get(0)?,
+            name: row.get(1)?,
+            value: row.get(2)?,
+            path: row.get(3)?,
+            expires: row.get(4)?,
+            secure: row.get(5)?,
+            http_only: row.get(6)?,
+            same_site: SameSite::parse(&row.get::<_, String>(7)?).unwrap_or_default(),
+        })
+    }).map_err(|e| EngineError::StorageError(format!("Failed to query cookies: {}", e)))?;
+    
+    let mut cookies = Vec::new();
+    for row in rows {
+        cookies.push(row.map_err(|e| EngineError::StorageError(format!("Failed to parse cookie: {}", e)))?);
+    }
+    
+    Ok(cookies)
+}
+
+/// RFC 6265 path-match: `request_path` is `cookie_path` or below it
+fn cookie_path_matches(cookie_path: &str, request_path: &str) -> bool {
+    request_path == cookie_path
+        || (request_path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || request_path[cookie_path.len()..].starts_with('/')))
+}
+
 /// Cookie representation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Cookie {
@@ -478,7 +1244,39 @@ pub struct Cookie {
     pub expires: Option<i64>,
     pub secure: bool,
     pub http_only: bool,
-    pub same_site: String,
+    pub same_site: SameSite,
+}
+
+/// Whether a cookie is sent on cross-site requests
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SameSite {
+    /// Only sent on same-site requests
+    Strict,
+    /// Sent on same-site requests and top-level navigations
+    #[default]
+    Lax,
+    /// Sent on every request; the cookie must be Secure
+    None,
+}
+
+impl SameSite {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Strict => "Strict",
+            Self::Lax => "Lax",
+            Self::None => "None",
+        }
+    }
+    
+    /// Parse an attribute value, ignoring case as `Set-Cookie` does
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "strict" => Some(Self::Strict),
+            "lax" => Some(Self::Lax),
+            "none" => Some(Self::None),
+            _ => None,
+        }
+    }
 }
 
 /// Bookmark representation
@@ -493,6 +1291,94 @@ pub struct Bookmark {
     pub folder_id: Option<String>,
 }
 
+/// Download as recorded in the `downloads` table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadRecord {
+    pub id: String,
+    pub url: String,
+    pub filename: String,
+    pub path: String,
+    pub status: DownloadStatus,
+    pub total_bytes: Option<u64>,
+    pub downloaded_bytes: u64,
+    pub created_at: i64,
+    pub completed_at: Option<i64>,
+}
+
+impl DownloadRecord {
+    /// Fraction complete, or 0 while the total size is unknown
+    pub fn progress(&self) -> f64 {
+        match self.total_bytes {
+            Some(total) if total > 0 => (self.downloaded_bytes as f64 / total as f64).min(1.0),
+            _ => 0.0,
+        }
+    }
+}
+
+/// Lifecycle state of a download
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DownloadStatus {
+    InProgress,
+    Paused,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+impl DownloadStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::InProgress => "in_progress",
+            Self::Paused => "paused",
+            Self::Completed => "completed",
+            Self::Cancelled => "cancelled",
+            Self::Failed => "failed",
+        }
+    }
+    
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "in_progress" => Some(Self::InProgress),
+            "paused" => Some(Self::Paused),
+            "completed" => Some(Self::Completed),
+            "cancelled" => Some(Self::Cancelled),
+            "failed" => Some(Self::Failed),
+            _ => None,
+        }
+    }
+    
+    /// Whether the download can no longer be resumed
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Completed | Self::Cancelled)
+    }
+}
+
+fn download_from_row(row: &rusqlite::Row) -> SqliteResult<DownloadRecord> {
+    let status: String = row.get(4)?;
+    let total_bytes: i64 = row.get(5)?;
+    let downloaded_bytes: i64 = row.get(6)?;
+    
+    Ok(DownloadRecord {
+        id: row.get(0)?,
+        url: row.get(1)?,
+        filename: row.get(2)?,
+        path: row.get(3)?,
+        status: DownloadStatus::parse(&status).unwrap_or(DownloadStatus::Failed),
+        total_bytes: (total_bytes > 0).then_some(total_bytes as u64),
+        downloaded_bytes: downloaded_bytes as u64,
+        created_at: row.get(7)?,
+        completed_at: row.get(8)?,
+    })
+}
+
+/// Folder that bookmarks can be filed under
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookmarkFolder {
+    pub id: String,
+    pub title: String,
+    pub parent_id: Option<String>,
+}
+
 /// History entry representation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryEntry {
@@ -511,6 +1397,39 @@ pub struct CachedResponse {
     pub expires_at: i64,
 }
 
+/// The encoding to store `body` with and the stored bytes: Brotli when the body is large
+/// enough and actually shrinks, otherwise the body unchanged
+fn encode_cached_body(body: &[u8]) -> Result<(&'static str, Vec<u8>)> {
+    if body.len() < CACHE_COMPRESSION_THRESHOLD {
+        return Ok(("identity", body.to_vec()));
+    }
+    
+    let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, CACHE_BROTLI_QUALITY, 22);
+    encoder.write_all(body)
+        .map_err(|e| EngineError::StorageError(format!("Failed to compress cached response: {}", e)))?;
+    let compressed = encoder.into_inner();
+    
+    if compressed.len() < body.len() {
+        Ok(("br", compressed))
+    } else {
+        Ok(("identity", body.to_vec()))
+    }
+}
+
+/// Undo `encode_cached_body`
+fn decode_cached_body(encoding: &str, stored: Vec<u8>) -> Result<Vec<u8>> {
+    match encoding {
+        "identity" => Ok(stored),
+        "br" => {
+            let mut body = Vec::new();
+            brotli::Decompressor::new(stored.as_slice(), 4096).read_to_end(&mut body)
+                .map_err(|e| EngineError::StorageError(format!("Failed to decompress cached response: {}", e)))?;
+            Ok(body)
+        }
+        other => Err(EngineError::StorageError(format!("Unknown cached body encoding: {}", other))),
+    }
+}
+
 /// Cached value with expiration
 #[derive(Debug, Clone)]
 struct CachedValue {
@@ -598,7 +1517,7 @@ mod tests {
             expires: None,
             secure: false,
             http_only: false,
-            same_site: "Lax".to_string(),
+            same_site: SameSite::Lax,
         };
         
         engine.set_cookie(cookie).await.unwrap();
@@ -630,4 +1549,302 @@ mod tests {
         assert_eq!(bookmarks[0].title, "Test Bookmark");
         assert_eq!(bookmarks[0].tags.len(), 2);
     }
-}
\ No newline at end of file
+    
+    #[tokio::test]
+    async fn test_download_records() {
+        let mut engine = StorageEngine::new().await.unwrap();
+        
+        let mut download = DownloadRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            url: "https://example.com/file.zip".to_string(),
+            filename: "file.zip".to_string(),
+            path: "/tmp/file.zip".to_string(),
+            status: DownloadStatus::InProgress,
+            total_bytes: None,
+            downloaded_bytes: 0,
+            created_at: chrono::Utc::now().timestamp(),
+            completed_at: None,
+        };
+        engine.save_download(&download).await.unwrap();
+        
+        download.status = DownloadStatus::Paused;
+        download.total_bytes = Some(1000);
+        download.downloaded_bytes = 250;
+        engine.save_download(&download).await.unwrap();
+        
+        let stored = engine.download_status(&download.id).await.unwrap().unwrap();
+        assert_eq!(stored.status, DownloadStatus::Paused);
+        assert_eq!(stored.total_bytes, Some(1000));
+        assert_eq!(stored.progress(), 0.25);
+        assert!(engine.list_downloads().await.unwrap().iter().any(|d| d.id == download.id));
+        assert!(engine.download_status("missing").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_download_record_lifecycle() {
+        let storage = StorageEngine::open(":memory:").await.unwrap();
+        let record = DownloadRecord {
+            id: "dl-1".to_string(),
+            url: "https://example.com/file.zip".to_string(),
+            filename: "file.zip".to_string(),
+            path: "/tmp/file.zip".to_string(),
+            status: DownloadStatus::InProgress,
+            total_bytes: None,
+            downloaded_bytes: 0,
+            created_at: 1,
+            completed_at: None,
+        };
+        storage.save_download(&record).await.unwrap();
+        
+        storage.update_download_progress("dl-1", 512, Some(2048), DownloadStatus::Paused).await.unwrap();
+        let paused = storage.download_status("dl-1").await.unwrap().unwrap();
+        assert_eq!(paused.status, DownloadStatus::Paused);
+        assert_eq!((paused.downloaded_bytes, paused.total_bytes), (512, Some(2048)));
+        assert_eq!(paused.completed_at, None);
+        
+        storage.update_download_progress("dl-1", 2048, Some(2048), DownloadStatus::Completed).await.unwrap();
+        let downloads = storage.list_downloads().await.unwrap();
+        assert_eq!(downloads.len(), 1);
+        assert_eq!(downloads[0].status, DownloadStatus::Completed);
+        assert!(downloads[0].completed_at.is_some());
+        
+        assert!(storage.update_download_progress("missing", 0, None, DownloadStatus::Failed).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_same_site_cookies() {
+        let mut engine = StorageEngine::open(":memory:").await.unwrap();
+        let cookie = |name: &str, secure, same_site| Cookie {
+            domain: "example.com".to_string(),
+            name: name.to_string(),
+            value: "1".to_string(),
+            path: "/".to_string(),
+            expires: None,
+            secure,
+            http_only: false,
+            same_site,
+        };
+        
+        // Browsers reject SameSite=None unless the cookie is also Secure
+        assert!(engine.set_cookie(cookie("insecure", false, SameSite::None)).await.is_err());
+        assert!(engine.get_cookies("example.com").await.unwrap().is_empty());
+        
+        engine.set_cookie(cookie("strict", true, SameSite::Strict)).await.unwrap();
+        engine.set_cookie(cookie("lax", true, SameSite::Lax)).await.unwrap();
+        engine.set_cookie(cookie("none", true, SameSite::None)).await.unwrap();
+        
+        let names = |cookies: Vec<Cookie>| {
+            let mut names: Vec<_> = cookies.into_iter().map(|c| c.name).collect();
+            names.sort();
+            names
+        };
+        let same_site = engine.get_cookies_for_request("example.com", "/", true, false).await.unwrap();
+        assert_eq!(names(same_site), vec!["lax", "none", "strict"]);
+        let cross_site = engine.get_cookies_for_request("example.com", "/", true, true).await.unwrap();
+        assert_eq!(names(cross_site), vec!["none"]);
+        assert_eq!(engine.get_cookies("example.com").await.unwrap().iter().find(|c| c.name == "none").unwrap().same_site, SameSite::None);
+    }
+    
+    #[tokio::test]
+    async fn test_cached_bodies_are_compressed() {
+        let mut engine = StorageEngine::open(":memory:").await.unwrap();
+        let body = "<p>Lorem ipsum dolor sit amet</p>\n".repeat(10_000).into_bytes();
+        engine.cache_response("https://example.com/big", "content-type: text/html", &body, i64::MAX).await.unwrap();
+        engine.cache_response("https://example.com/small", "", b"tiny", i64::MAX).await.unwrap();
+        
+        let (stored_len, encoding): (usize, String) = engine.connection.lock().unwrap().query_row(
+            "SELECT length(body), body_encoding FROM cache_entries WHERE url = ?1",
+            params!["https://example.com/big"],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).unwrap();
+        assert_eq!(encoding, "br");
+        assert!(stored_len < body.len() / 10);
+        
+        let cached = engine.get_cached_response("https://example.com/big").await.unwrap().unwrap();
+        assert_eq!(cached.body, body);
+        assert_eq!(cached.headers, "content-type: text/html");
+        let cached = engine.get_cached_response("https://example.com/small").await.unwrap().unwrap();
+        assert_eq!(cached.body, b"tiny");
+    }
+    
+    #[tokio::test]
+    async fn test_cache_rows_from_older_schema_read_uncompressed() {
+        let db_path = std::env::temp_dir().join(format!("titan-cache-{}.db", uuid::Uuid::new_v4()));
+        {
+            let connection = Connection::open(&db_path).unwrap();
+            connection.execute_batch(
+                "CREATE TABLE cache_entries (url TEXT PRIMARY KEY, headers TEXT NOT NULL, body BLOB NOT NULL, expires_at INTEGER NOT NULL, created_at INTEGER NOT NULL);"
+            ).unwrap();
+            connection.execute(
+                "INSERT INTO cache_entries (url, headers, body, expires_at, created_at) VALUES (?1, '', ?2, ?3, 0)",
+                params!["https://example.com/", b"old body".to_vec(), i64::MAX],
+            ).unwrap();
+        }
+        
+        let engine = StorageEngine::open(&db_path).await.unwrap();
+        let cached = engine.get_cached_response("https://example.com/").await.unwrap().unwrap();
+        assert_eq!(cached.body, b"old body");
+        
+        drop(engine);
+        let _ = std::fs::remove_file(db_path);
+    }
+    
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_access_in_wal_mode() {
+        let db_path = std::env::temp_dir().join(format!("titan-wal-{}.db", uuid::Uuid::new_v4()));
+        // Separate engines on one file, as the engine's stores are
+        let first = Arc::new(StorageEngine::open(&db_path).await.unwrap());
+        let second = Arc::new(StorageEngine::open(&db_path).await.unwrap());
+        
+        let journal_mode = second.with_connection(|connection| {
+            connection.query_row("PRAGMA journal_mode", [], |row| row.get::<_, String>(0))
+                .map_err(|e| EngineError::StorageError(e.to_string()))
+        }).await.unwrap();
+        assert_eq!(journal_mode, "wal");
+        
+        let tasks = (0..50).map(|i| {
+            let engine = if i % 2 == 0 { first.clone() } else { second.clone() };
+            tokio::spawn(async move {
+                let origin = format!("https://site{}.example", i);
+                engine.set_permission(&origin, "geolocation", i % 3 == 0).await.unwrap();
+                engine.add_history(&origin, "Visited").await.unwrap();
+                engine.list_permissions().await.unwrap();
+                engine.get_history(Some(10)).await.unwrap();
+            })
+        });
+        let results = tokio::time::timeout(Duration::from_secs(30), futures::future::join_all(tasks))
+            .await
+            .expect("storage calls deadlocked");
+        assert!(results.iter().all(|result| result.is_ok()));
+        
+        assert_eq!(first.list_permissions().await.unwrap().len(), 50);
+        assert_eq!(second.get_history(None).await.unwrap().len(), 50);
+        
+        drop((first, second));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", db_path.display(), suffix));
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_bulk_import() {
+        let engine = StorageEngine::open(":memory:").await.unwrap();
+        engine.add_history("https://example.com/0", "Old title").await.unwrap();
+        
+        let history: Vec<_> = (0..400).map(|i| HistoryEntry {
+            id: format!("imported-{}", i),
+            url: format!("https://example.com/{}", i),
+            title: format!("Page {}", i),
+            visit_count: 3,
+            last_visit: 1_600_000_000 + i,
+        }).collect();
+        assert_eq!(engine.import_history(history).await.unwrap(), 400);
+        
+        let stored = engine.get_history(None).await.unwrap();
+        assert_eq!(stored.len(), 400);
+        // The existing entry gains the imported visits but keeps its newer title
+        let merged = stored.iter().find(|entry| entry.url == "https://example.com/0").unwrap();
+        assert_eq!(merged.visit_count, 4);
+        assert_eq!(merged.title, "Old title");
+        
+        let bookmarks: Vec<_> = (0..300).map(|i| Bookmark {
+            id: format!("bookmark-{}", i % 250),
+            title: format!("Bookmark {}", i),
+            url: format!("https://example.org/{}", i),
+            description: None,
+            favicon: None,
+            tags: vec!["imported".to_string()],
+            folder_id: None,
+        }).collect();
+        // Ids repeated within the import are only added once
+        assert_eq!(engine.import_bookmarks(bookmarks).await.unwrap(), 250);
+        let stored = engine.get_bookmarks().await.unwrap();
+        assert_eq!(stored.len(), 250);
+        assert!(stored.iter().all(|bookmark| bookmark.tags == ["imported"]));
+    }
+    
+    #[tokio::test]
+    async fn test_bookmarks_html_round_trip() {
+        let engine = StorageEngine::open(":memory:").await.unwrap();
+        let folder = |id: &str, title: &str, parent_id: Option<&str>| BookmarkFolder {
+            id: id.to_string(),
+            title: title.to_string(),
+            parent_id: parent_id.map(str::to_string),
+        };
+        engine.add_bookmark_folder(folder("work", "Work & Co", None)).await.unwrap();
+        engine.add_bookmark_folder(folder("projects", "Projects", Some("work"))).await.unwrap();
+        engine.add_bookmark_folder(folder("empty", "Empty", None)).await.unwrap();
+        
+        let bookmark = |url: &str, title: &str, folder_id: Option<&str>| Bookmark {
+            id: uuid::Uuid::new_v4().to_string(),
+            title: title.to_string(),
+            url: url.to_string(),
+            description: None,
+            favicon: None,
+            tags: Vec::new(),
+            folder_id: folder_id.map(str::to_string),
+        };
+        engine.import_bookmarks(vec![
+            Bookmark {
+                description: Some("Search <fast>".to_string()),
+                tags: vec!["search".to_string(), "daily".to_string()],
+                ..bookmark("https://example.com/?q=a&b=\"c\"", "Example", None)
+            },
+            bookmark("https://work.example/", "Intranet", Some("work")),
+            bookmark("https://git.example/titan", "Titan repo", Some("projects")),
+            bookmark("https://lost.example/", "Unfiled", Some("deleted-folder")),
+        ]).await.unwrap();
+        
+        let html = engine.export_bookmarks_html().await.unwrap();
+        assert!(html.starts_with("<!DOCTYPE NETSCAPE-Bookmark-file-1>"));
+        
+        let copy = StorageEngine::open(":memory:").await.unwrap();
+        assert_eq!(copy.import_bookmarks_html(&html).await.unwrap(), 4);
+        
+        // Compare by content and folder path, since ids are fresh
+        async fn summary(engine: &StorageEngine) -> Vec<(String, String, Vec<String>, Option<String>, String)> {
+            let folders = engine.get_bookmark_folders().await.unwrap();
+            let path = |mut id: Option<String>| {
+                let mut names = Vec::new();
+                while let Some(folder) = id.and_then(|id| folders.iter().find(|folder| folder.id == id)) {
+                    names.insert(0, folder.title.clone());
+                    id = folder.parent_id.clone();
+                }
+                names.join("/")
+            };
+            let mut bookmarks: Vec<_> = engine.get_bookmarks().await.unwrap().into_iter()
+                .map(|b| (b.url, b.title, b.tags, b.description, path(b.folder_id)))
+                .collect();
+            bookmarks.sort();
+            bookmarks
+        }
+        assert_eq!(summary(&copy).await, summary(&engine).await);
+        assert!(summary(&copy).await.contains(&(
+            "https://git.example/titan".to_string(),
+            "Titan repo".to_string(),
+            Vec::new(),
+            None,
+            "Work & Co/Projects".to_string(),
+        )));
+        let mut titles: Vec<_> = copy.get_bookmark_folders().await.unwrap().into_iter().map(|f| f.title).collect();
+        titles.sort();
+        assert_eq!(titles, ["Empty", "Projects", "Work & Co"]);
+    }
+    
+    #[tokio::test]
+    async fn test_import_malformed_bookmarks_html() {
+        let engine = StorageEngine::open(":memory:").await.unwrap();
+        assert_eq!(engine.import_bookmarks_html("not a bookmark file").await.unwrap(), 0);
+        
+        // Unclosed lists, a link without an address, and a folder list after its heading
+        let html = r#"<DL><p><DT><A>No address</A>
+            <DT><H3>Reading</H3><DD>stray
+            <DT><A HREF=" https://ok.example/ " TAGS=" a, ,b">Ok</A>
+            <DL><DT><A HREF="https://nested.example/">Nested</A>"#;
+        assert_eq!(engine.import_bookmarks_html(html).await.unwrap(), 2);
+        let bookmarks = engine.get_bookmarks().await.unwrap();
+        let ok = bookmarks.iter().find(|b| b.url == "https://ok.example/").unwrap();
+        assert_eq!(ok.tags, ["a", "b"]);
+        assert_eq!(ok.title, "Ok");
+    }
+}
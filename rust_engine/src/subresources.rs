@@ -0,0 +1,276 @@
+//! Concurrent fetching of a page's stylesheets, scripts, and images
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use futures::future::join_all;
+use tokio::sync::Semaphore;
+use url::Url;
+use crate::core::{ElementId, Result};
+use crate::css::is_stylesheet_link;
+use crate::html::Document;
+use crate::networking::{NetworkResponse, NetworkStack};
+
+/// Most subresource requests in flight at once for one page, as browsers allow per host
+pub const MAX_CONCURRENT_SUBRESOURCES: usize = 6;
+
+/// What a subresource is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubresourceKind {
+    Stylesheet,
+    Script,
+    Image,
+    /// A `<video>` element's `poster` image
+    Poster,
+}
+
+/// An external resource referenced by an element
+#[derive(Debug, Clone)]
+pub struct Subresource {
+    pub kind: SubresourceKind,
+    pub element_id: ElementId,
+    pub url: Url,
+}
+
+/// Fetched subresource bodies, ready to hand to the engines that use them
+#[derive(Debug, Default)]
+pub struct Subresources {
+    /// Stylesheet text, keyed by its `<link>` element
+    pub stylesheets: HashMap<ElementId, String>,
+
+    /// Script source, keyed by its `<script>` element
+    pub scripts: HashMap<ElementId, String>,
+
+    /// Encoded image bytes, keyed by absolute URL
+    pub images: HashMap<String, Vec<u8>>,
+}
+
+impl Subresources {
+    /// Keep the successful responses; a failed resource only loses that resource
+    pub fn from_responses(results: Vec<(Subresource, Result<NetworkResponse>)>) -> Self {
+        let mut subresources = Self::default();
+        for (resource, result) in results {
+            let response = match result {
+                Ok(response) if (200..300).contains(&response.status) => response,
+                Ok(response) => {
+                    log::warn!("Failed to load {}: HTTP {}", resource.url, response.status);
+                    continue;
+                }
+                Err(e) => {
+                    log::warn!("Failed to load {}: {:?}", resource.url, e);
+                    continue;
+                }
+            };
+
+            match resource.kind {
+                SubresourceKind::Stylesheet => {
+                    subresources.stylesheets.insert(resource.element_id, response.body);
+                }
+                SubresourceKind::Script => {
+                    subresources.scripts.insert(resource.element_id, response.body);
+                }
+                SubresourceKind::Image | SubresourceKind::Poster => {
+                    subresources.images.insert(resource.url.to_string(), response.body_bytes);
+                }
+            }
+        }
+        subresources
+    }
+}
+
+/// External stylesheets, scripts, and images (including video posters) in document order,
+/// resolved against the document URL
+pub fn collect_subresources(document: &Document) -> Vec<Subresource> {
+    let base = Url::parse(&document.url).ok();
+    let mut resources = Vec::new();
+
+    for element in document.descendants(&document.root) {
+        let (kind, reference) = match element.tag_name.as_str() {
+            "link" if is_stylesheet_link(&element) => (SubresourceKind::Stylesheet, element.get_attribute("href")),
+            "script" => (SubresourceKind::Script, element.get_attribute("src")),
+            "img" => (SubresourceKind::Image, element.get_attribute("src")),
+            "video" => (SubresourceKind::Poster, element.get_attribute("poster")),
+            _ => continue,
+        };
+
+        let Some(reference) = reference.map(str::trim).filter(|reference| !reference.is_empty()) else {
+            continue;
+        };
+        let url = match &base {
+            Some(base) => base.join(reference),
+            None => Url::parse(reference),
+        };
+        match url {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => resources.push(Subresource {
+                kind,
+                element_id: element.id,
+                url,
+            }),
+            Ok(_) => {}
+            Err(e) => log::warn!("Skipping subresource {}: {}", reference, e),
+        }
+    }
+
+    resources
+}
+
+/// Fetch every resource at once, with at most `limit` requests in flight.
+/// `page_url` is the top-level page, which decides whether SameSite cookies are sent.
+/// Results come back in the order the resources were given.
+pub async fn fetch_subresources(
+    network: &NetworkStack,
+    page_url: &str,
+    resources: Vec<Subresource>,
+    limit: usize,
+) -> Vec<(Subresource, Result<NetworkResponse>)> {
+    let permits = Arc::new(Semaphore::new(limit.max(1)));
+
+    join_all(resources.into_iter().map(|resource| {
+        let permits = permits.clone();
+        async move {
+            // The semaphore is never closed, so acquiring only waits
+            let _permit = permits.acquire().await.expect("subresource semaphore closed");
+            let response = network.fetch_for_site(resource.url.as_str(), page_url).await;
+            (resource, response)
+        }
+    })).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server};
+    use crate::html::HTMLParser;
+    use crate::networking::SecurityConfig;
+    use crate::storage::StorageEngine;
+
+    #[tokio::test]
+    async fn test_subresources_are_fetched_concurrently() {
+        // Every response is slow, so only overlapping requests finish in time
+        let requested = Arc::new(Mutex::new(Vec::new()));
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let (server_requested, server_in_flight, server_peak) = (requested.clone(), in_flight.clone(), peak.clone());
+        let make_service = make_service_fn(move |_| {
+            let (requested, in_flight, peak) = (server_requested.clone(), server_in_flight.clone(), server_peak.clone());
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let (requested, in_flight, peak) = (requested.clone(), in_flight.clone(), peak.clone());
+                    async move {
+                        let path = request.uri().path().to_string();
+                        requested.lock().unwrap().push(path.clone());
+                        let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(current, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(200)).await;
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+
+                        let response = match path.as_str() {
+                            "/missing.js" => Response::builder().status(404).body(Body::empty()).unwrap(),
+                            _ => Response::new(Body::from(format!("/* {} */", path))),
+                        };
+                        Ok::<_, Infallible>(response)
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let mut document = HTMLParser::new().parse(r#"<html><head>
+            <link rel="stylesheet" href="/a.css"><link rel="icon" href="/icon.png">
+            <link rel="alternate stylesheet" href="b.css">
+            <script src="/app.js"></script><script src="/missing.js"></script><script>inline()</script>
+        </head><body><img src="/logo.png"><img src="data:image/png;base64,AAAA"><img></body></html>"#).unwrap();
+        document.url = format!("http://{}/page/", addr);
+
+        let resources = collect_subresources(&document);
+        let kinds: Vec<_> = resources.iter().map(|r| (r.kind, r.url.path().to_string())).collect();
+        assert_eq!(kinds, vec![
+            (SubresourceKind::Stylesheet, "/a.css".to_string()),
+            (SubresourceKind::Stylesheet, "/page/b.css".to_string()),
+            (SubresourceKind::Script, "/app.js".to_string()),
+            (SubresourceKind::Script, "/missing.js".to_string()),
+            (SubresourceKind::Image, "/logo.png".to_string()),
+        ]);
+
+        let mut network = NetworkStack::new().await.unwrap();
+        network.update_security_config(SecurityConfig {
+            require_https: false,
+            ..SecurityConfig::default()
+        });
+        let started = std::time::Instant::now();
+        let results = fetch_subresources(&network, &document.url, resources, 3).await;
+        assert!(started.elapsed() < Duration::from_millis(900), "fetches ran serially");
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+
+        let mut paths = requested.lock().unwrap().clone();
+        paths.sort();
+        assert_eq!(paths, vec!["/a.css", "/app.js", "/logo.png", "/missing.js", "/page/b.css"]);
+
+        // The 404 drops only its own script
+        let subresources = Subresources::from_responses(results);
+        assert_eq!(subresources.stylesheets.len(), 2);
+        assert_eq!(subresources.scripts.len(), 1);
+        assert!(subresources.scripts.values().any(|source| source == "/* /app.js */"));
+        assert_eq!(subresources.images.keys().cloned().collect::<Vec<_>>(), vec![format!("http://{}/logo.png", addr)]);
+    }
+
+    #[tokio::test]
+    async fn test_cross_site_subresources_omit_strict_cookies() {
+        // /login sets a Strict cookie; every other path echoes, uncached, the Cookie header it received
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|request: Request<Body>| async move {
+                let response = if request.uri().path() == "/login" {
+                    Response::builder()
+                        .header("Set-Cookie", "session=abc123; Path=/; SameSite=Strict")
+                        .body(Body::empty())
+                        .unwrap()
+                } else {
+                    let cookie = request.headers().get("cookie")
+                        .map(|value| value.to_str().unwrap().to_string())
+                        .unwrap_or_default();
+                    Response::builder()
+                        .header("Cache-Control", "no-store")
+                        .body(Body::from(cookie))
+                        .unwrap()
+                };
+                Ok::<_, Infallible>(response)
+            }))
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let mut network = NetworkStack::new().await.unwrap();
+        network.update_security_config(SecurityConfig {
+            require_https: false,
+            ..SecurityConfig::default()
+        });
+        let jar = Arc::new(tokio::sync::Mutex::new(StorageEngine::open(":memory:").await.unwrap()));
+        network.set_cookie_store(jar);
+        network.fetch(&format!("http://{}/login", addr)).await.unwrap();
+
+        let html = format!(r#"<html><head><link rel="stylesheet" href="http://{0}/style.css"></head>
+            <body><img src="http://{0}/pixel.png"></body></html>"#, addr);
+        let cookies = |results: Vec<(Subresource, Result<NetworkResponse>)>| results.into_iter()
+            .map(|(_, response)| response.unwrap().body)
+            .collect::<Vec<_>>();
+
+        // Embedded in a page on another site, neither request carries the cookie
+        let mut document = HTMLParser::new().parse(&html).unwrap();
+        document.url = "https://tracker.example/".to_string();
+        let results = fetch_subresources(&network, &document.url, collect_subresources(&document), 2).await;
+        assert_eq!(cookies(results), vec!["", ""]);
+
+        // The same markup on the cookie's own site sends it
+        document.url = format!("http://{}/", addr);
+        let results = fetch_subresources(&network, &document.url, collect_subresources(&document), 2).await;
+        assert_eq!(cookies(results), vec!["session=abc123", "session=abc123"]);
+    }
+}